@@ -0,0 +1,11 @@
+-- Track per-user read state for channels so the channel list can report unread counts
+-- last_read_at uses NANOSECONDS to match message.created_at
+
+CREATE TABLE IF NOT EXISTS channel_read (
+    channel_id VARCHAR(255) NOT NULL,
+    user_id VARCHAR(255) NOT NULL,
+    last_read_at BIGINT NOT NULL,
+    PRIMARY KEY (channel_id, user_id),
+    FOREIGN KEY (channel_id) REFERENCES channel(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES "user"(id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- Reactions are unique per user and emoji on a message
+-- Remove duplicates created before the constraint existed, keeping the earliest one
+
+DELETE FROM message_reaction a
+USING message_reaction b
+WHERE a.message_id = b.message_id
+  AND a.user_id = b.user_id
+  AND a.name = b.name
+  AND (a.created_at, a.id) > (b.created_at, b.id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_message_reaction_unique ON message_reaction(message_id, user_id, name);
@@ -0,0 +1,14 @@
+-- Token usage per user, model and period (YYYY-MM-DD for days, YYYY-MM for months)
+
+CREATE TABLE IF NOT EXISTS token_usage (
+    user_id VARCHAR(255) NOT NULL,
+    model_id VARCHAR(255) NOT NULL,
+    period VARCHAR(16) NOT NULL,
+    prompt_tokens BIGINT NOT NULL DEFAULT 0,
+    completion_tokens BIGINT NOT NULL DEFAULT 0,
+    updated_at BIGINT NOT NULL,
+    PRIMARY KEY (user_id, model_id, period),
+    FOREIGN KEY (user_id) REFERENCES "user"(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_token_usage_user_period ON token_usage(user_id, period);
@@ -0,0 +1,15 @@
+-- Audit trail of mutating API requests
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    id VARCHAR(255) PRIMARY KEY NOT NULL,
+    user_id VARCHAR(255),
+    method VARCHAR(16) NOT NULL,
+    path TEXT NOT NULL,
+    status BIGINT NOT NULL,
+    source_ip VARCHAR(255),
+    request_body TEXT,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_user_id ON audit_log(user_id, created_at);
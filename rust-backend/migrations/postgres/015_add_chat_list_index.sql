@@ -0,0 +1,3 @@
+-- Chat lists filter by user and sort by last update
+
+CREATE INDEX IF NOT EXISTS idx_chat_user_id_updated_at ON chat(user_id, updated_at DESC);
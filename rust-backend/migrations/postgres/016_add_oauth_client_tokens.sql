@@ -0,0 +1,11 @@
+-- Tokens users granted to OAuth clients, encrypted
+
+CREATE TABLE IF NOT EXISTS oauth_client_token (
+    user_id VARCHAR(255) NOT NULL,
+    client_id VARCHAR(255) NOT NULL,
+    data TEXT NOT NULL,
+    expires_at BIGINT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    PRIMARY KEY (user_id, client_id)
+);
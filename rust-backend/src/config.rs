@@ -1,7 +1,9 @@
+use arc_swap::ArcSwap;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::path::PathBuf;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, MutexGuard};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -9,6 +11,13 @@ pub struct Config {
     pub host: String,
     pub port: u16,
     pub enable_random_port: bool,
+    /// Seconds streaming completions get to finish on shutdown before they are cancelled
+    pub shutdown_drain_timeout: u64,
+    /// PEM certificate chain and private key; HTTPS is served when both are set
+    pub ssl_cert_file: Option<String>,
+    pub ssl_key_file: Option<String>,
+    /// Listen on this Unix domain socket instead of HOST/PORT
+    pub unix_socket_path: Option<String>,
     pub env: String,
     pub webui_secret_key: String,
 
@@ -19,7 +28,11 @@ pub struct Config {
     pub database_url: String,
     pub database_pool_size: u32,
     pub database_pool_max_overflow: u32,
+    /// Connections kept open while idle
+    pub database_pool_min_connections: u32,
     pub database_pool_timeout: u64,
+    /// Seconds before idle connections above the minimum are closed; 0 keeps them
+    pub database_pool_idle_timeout: u64,
     pub database_pool_recycle: u64,
 
     // Redis
@@ -34,11 +47,16 @@ pub struct Config {
     pub enable_api_key_endpoint_restrictions: bool,
     pub api_key_allowed_endpoints: String,
     pub default_user_role: String,
+    pub default_group_id: Option<String>,
     pub show_admin_details: bool,
     pub webui_url: String,
     pub pending_user_overlay_title: Option<String>,
     pub pending_user_overlay_content: Option<String>,
     pub response_watermark: Option<String>,
+    pub response_watermark_mode: String,
+    pub response_watermark_in_stream: bool,
+    /// Content deltas sent per Socket.IO event; requests can override it in their `params`
+    pub chat_response_stream_delta_chunk_size: usize,
 
     // LDAP Authentication
     pub enable_ldap: bool,
@@ -62,14 +80,60 @@ pub struct Config {
 
     // CORS
     pub cors_allow_origin: String,
+    pub cors_allow_credentials: bool,
+
+    /// Reverse proxies whose X-Forwarded-For/Forwarded headers are believed
+    pub trusted_proxies: Vec<ipnet::IpNet>,
+
+    // Outbound proxy for upstream requests
+    pub http_proxy: Option<String>,
+    pub https_proxy: Option<String>,
+    /// Hosts, domains and CIDR ranges reached directly rather than through the proxy
+    pub no_proxy: Vec<String>,
+
+    // TLS for upstream requests
+    /// PEM bundle of extra CAs trusted for upstream servers, on top of the system roots
+    pub upstream_ca_bundle: Option<String>,
+    /// PEM client certificate and PKCS#8 key presented to upstreams asking for one
+    pub upstream_client_cert: Option<String>,
+    pub upstream_client_key: Option<String>,
+    /// Accept any upstream certificate. Insecure; for testing only.
+    pub upstream_tls_insecure_skip_verify: bool,
+
+    // Security headers
+    pub security_headers_mode: String,
+    pub enable_csp: bool,
+    pub content_security_policy: Option<String>,
+    pub csp_connect_src: Vec<String>,
+    pub csp_img_src: Vec<String>,
+    pub enable_hsts: bool,
+    pub hsts_max_age: u64,
+    pub x_frame_options: String,
+    pub referrer_policy: String,
+    pub permissions_policy: String,
 
     // WebSocket
     pub enable_websocket_support: bool,
     pub websocket_manager: String,
     pub websocket_redis_url: Option<String>,
+    /// Engine.IO heartbeat timing advertised to clients, in seconds
+    pub websocket_server_ping_interval: u64,
+    pub websocket_server_ping_timeout: u64,
+    /// Seconds a disconnected client can resume its session and be replayed the
+    /// events it missed; 0 disables connection state recovery
+    pub websocket_recovery_window: u64,
+    /// Bytes of missed events kept per session for replay
+    pub websocket_recovery_max_bytes: usize,
+    /// Events a session may send per minute, for events without a limit of their own
+    pub websocket_event_rate_limit: usize,
+    /// Per-minute limits for particular events, on top of the built-in ones
+    pub websocket_event_rate_limits: HashMap<String, usize>,
+    /// Completions one user may stream over `/api/ws/chat` at once
+    pub ws_chat_max_streams_per_user: usize,
 
     // Features
     pub enable_openai_api: bool,
+    pub enable_openai_legacy_completions: bool,
     pub enable_channels: bool,
     pub enable_image_generation: bool,
     pub enable_code_execution: bool,
@@ -82,12 +146,60 @@ pub struct Config {
     pub bypass_admin_access_control: Option<bool>,
 
     // Storage
+    /// Root of the state kept on disk; the directories below default to inside it
+    pub data_dir: String,
     pub upload_dir: String,
     pub cache_dir: String,
     pub static_dir: String,
+    /// Seconds browsers may cache static files with a content hash in their name
+    pub static_cache_max_age: u64,
+
+    // File uploads
+    /// Largest upload in bytes; also the body limit of upload and transcription routes
+    pub file_max_size: u64,
+    pub file_max_count: usize,
+    /// Largest request body for chat completions and saved chats, in bytes
+    pub chat_body_max_size: u64,
+    /// Largest request body for all other routes, in bytes
+    pub body_max_size: u64,
+    /// Content types accepted on upload; `type/*` matches a whole family
+    pub file_allowed_content_types: Vec<String>,
+    /// Accept executables and scripts, which are rejected by default
+    pub enable_executable_uploads: bool,
+    /// Total upload bytes allowed per user; unlimited when unset
+    pub file_user_quota: Option<u64>,
+    /// Uploaded images and avatars are downscaled to fit these bounds; unset disables
+    pub file_image_compression_width: Option<u32>,
+    pub file_image_compression_height: Option<u32>,
+    /// Require HMAC-signed, expiring URLs for cached files and file downloads
+    pub enable_signed_urls: bool,
+    /// Seconds a signed URL stays valid
+    pub signed_url_expiry: u64,
 
     // Logging
     pub global_log_level: String,
+    pub enable_json_access_log: bool,
+    /// Bearer token accepted by `/metrics` in addition to admin sessions
+    pub metrics_bearer_token: Option<String>,
+    /// Bearer token other services use to emit through `/api/socketio/emit`
+    /// without a user session or its target checks
+    pub socketio_emit_service_key: Option<String>,
+
+    // Audit log
+    pub enable_audit_log: bool,
+    pub audit_log_methods: Vec<String>,
+    pub audit_log_paths: Vec<String>,
+    pub audit_log_excluded_paths: Vec<String>,
+    pub audit_log_retention_days: i64,
+
+    // Request rate limits, as "<requests>/<seconds>" per route group
+    pub enable_rate_limit: bool,
+    pub rate_limit_auth: String,
+    pub rate_limit_chat: String,
+    pub rate_limit_upload: String,
+    pub rate_limit_tasks: String,
+    pub rate_limit_exempt_roles: Vec<String>,
+    pub rate_limit_role_rules: Vec<String>,
 
     // OpenAI
     pub openai_api_base_url: String,
@@ -95,6 +207,8 @@ pub struct Config {
     pub openai_api_base_urls: Vec<String>,
     pub openai_api_keys: Vec<String>,
     pub openai_api_configs: serde_json::Value,
+    pub upstream_circuit_breaker_threshold: usize,
+    pub upstream_circuit_breaker_timeout: u64,
 
     // Audio - TTS
     pub tts_openai_api_base_url: String,
@@ -156,17 +270,31 @@ pub struct Config {
     pub rag_openai_api_key: String,
     pub rag_openai_api_base_url: String,
     pub rag_template: String,
+    /// How answers cite sources: `inline`, `footnotes` or `none`
+    pub rag_citation_style: String,
+    /// Name cited sources by their file name or URL
+    pub rag_citation_include_source: bool,
     pub rag_full_context: bool,
     pub bypass_embedding_and_retrieval: bool,
     pub enable_rag_hybrid_search: bool,
     pub top_k_reranker: i32,
     pub relevance_threshold: f64,
+    /// Most documents injected as RAG context; 0 for no limit
+    pub rag_max_sources: usize,
     pub hybrid_bm25_weight: f64,
     pub content_extraction_engine: String,
     pub pdf_extract_images: bool,
     pub rag_embedding_model_trust_remote_code: bool,
     pub rag_reranking_model_trust_remote_code: bool,
 
+    // Web Search
+    pub web_search_engine: String,
+    pub web_search_result_count: usize,
+    pub searxng_query_url: String,
+    pub brave_search_api_key: String,
+    pub google_pse_api_key: String,
+    pub google_pse_engine_id: String,
+
     // Sentence Transformers
     pub sentence_transformers_home: Option<String>,
     pub sentence_transformers_backend: String,
@@ -214,6 +342,11 @@ pub struct Config {
     pub model_order_list: Vec<String>,
     pub default_prompt_suggestions: serde_json::Value,
     pub banners: serde_json::Value,
+    /// Uploaded logo/favicon/splash images replacing the embedded ones
+    pub custom_branding: std::collections::BTreeMap<
+        crate::utils::branding::BrandingAsset,
+        crate::utils::branding::BrandingImage,
+    >,
     pub user_permissions: serde_json::Value,
 
     // Version and Updates
@@ -222,10 +355,15 @@ pub struct Config {
     // Task Configuration
     pub task_model: Option<String>,
     pub task_model_external: Option<String>,
+    /// Model for chat titles; falls back to the task model, then the chat model
+    pub title_generation_model: Option<String>,
     pub enable_search_query_generation: bool,
     pub enable_retrieval_query_generation: bool,
     pub enable_autocomplete_generation: bool,
     pub autocomplete_generation_input_max_length: i32,
+    pub autocomplete_generation_prompt_template: String,
+    pub autocomplete_generation_rate_limit: usize,
+    pub autocomplete_generation_timeout: u64,
     pub enable_tags_generation: bool,
     pub tags_generation_prompt_template: String,
     pub enable_title_generation: bool,
@@ -233,6 +371,8 @@ pub struct Config {
     pub enable_follow_up_generation: bool,
     pub follow_up_generation_prompt_template: String,
     pub image_prompt_generation_prompt_template: String,
+    pub enable_emoji_generation: bool,
+    pub emoji_generation_prompt_template: String,
     pub query_generation_prompt_template: String,
     pub tools_function_calling_prompt_template: String,
 
@@ -241,10 +381,24 @@ pub struct Config {
 
     // Direct connections
     pub enable_direct_connections: bool,
+    /// Instance-wide direct connections offered to every user after their own
+    pub default_direct_connections: serde_json::Value,
     pub enable_base_models_cache: bool,
 
     // Tool Servers
     pub tool_server_connections: serde_json::Value,
+    /// Seconds a tool call may run before it is aborted, unless its definition sets
+    /// its own `timeout_seconds`
+    pub tool_execution_timeout: u64,
+    /// Secrets tools may receive as environment variables, when their definition
+    /// declares them in `environment.required`/`optional`
+    pub tool_secrets: HashMap<String, String>,
+    /// Names of the `tool_secrets` each tool may receive, by tool id. Anyone who can
+    /// edit a tool can declare any name, so a secret only reaches the tools listed here
+    pub tool_secret_grants: HashMap<String, Vec<String>>,
+    /// OAuth clients tools authorize with on behalf of users, by the id used in their
+    /// `/oauth/clients/{id}/...` routes
+    pub oauth_clients: HashMap<String, crate::services::oauth_client::OAuthClientInfo>,
 
     // Integrations
     pub enable_google_drive_integration: bool,
@@ -253,10 +407,102 @@ pub struct Config {
     // Evaluations
     pub enable_evaluation_arena_models: bool,
     pub evaluation_arena_models: serde_json::Value,
+
+    // Token budgets (0 = unlimited)
+    pub token_budget_period: String,
+    pub user_token_limit: i64,
+    pub model_token_limits: serde_json::Value,
+
+    // Chat completion request checks
+    /// "clamp" brings out-of-range sampling parameters into range, "reject" answers 422
+    pub chat_param_policy: String,
+    pub chat_max_messages: usize,
+    /// Characters of text allowed in a single message
+    pub chat_max_message_chars: usize,
+    /// Cap on `max_tokens`/`max_completion_tokens`; 0 for none
+    pub chat_max_tokens: i64,
+    /// Save streamed reasoning in the message content as a collapsible block
+    pub keep_reasoning_in_content: bool,
 }
 
 /// Mutable config wrapper for runtime updates
-pub type MutableConfig = Arc<RwLock<Config>>;
+pub type MutableConfig = Arc<SharedConfig>;
+
+/// Config that can be updated at runtime
+///
+/// Readers take a snapshot, which never blocks and stays valid across awaits. Writers
+/// edit a copy that replaces the current config when their guard is dropped, so
+/// readers never see a half-applied update.
+pub struct SharedConfig {
+    current: ArcSwap<Config>,
+    /// Serializes writers so concurrent updates don't overwrite each other
+    write_lock: Mutex<()>,
+}
+
+impl SharedConfig {
+    pub fn new(config: Config) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(config),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// The current config
+    pub fn snapshot(&self) -> Arc<Config> {
+        self.current.load_full()
+    }
+
+    /// Start an update; it is applied when the guard is dropped
+    ///
+    /// Don't hold the guard across awaits, other writers wait for it.
+    pub fn write(&self) -> ConfigWriteGuard<'_> {
+        // A writer that panicked never applied its copy, so the config is intact
+        let lock = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        ConfigWriteGuard {
+            _lock: lock,
+            shared: self,
+            config: Some(Config::clone(&self.current.load())),
+        }
+    }
+}
+
+/// Pending config update, applied on drop unless the writer panicked
+pub struct ConfigWriteGuard<'a> {
+    _lock: MutexGuard<'a, ()>,
+    shared: &'a SharedConfig,
+    config: Option<Config>,
+}
+
+impl std::ops::Deref for ConfigWriteGuard<'_> {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        self.config.as_ref().expect("config taken before drop")
+    }
+}
+
+impl std::ops::DerefMut for ConfigWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Config {
+        self.config.as_mut().expect("config taken before drop")
+    }
+}
+
+impl Drop for ConfigWriteGuard<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            return;
+        }
+        if let Some(config) = self.config.take() {
+            self.shared.current.store(Arc::new(config));
+        }
+    }
+}
+
+pub const DEFAULT_X_FRAME_OPTIONS: &str = "DENY";
+pub const DEFAULT_REFERRER_POLICY: &str = "strict-origin-when-cross-origin";
+/// Microphone and camera stay available to the page itself for voice input and calls
+pub const DEFAULT_PERMISSIONS_POLICY: &str = "geolocation=(), microphone=(self), camera=(self)";
+pub const DEFAULT_HSTS_MAX_AGE: u64 = 31536000;
 
 impl Config {
     /// Expand tilde (~) to home directory in path
@@ -285,6 +531,21 @@ impl Config {
         let config_dir =
             env::var("CONFIG_DIR").unwrap_or_else(|_| "~/.config/open-coreui".to_string());
 
+        // Uploads and caches live under the data directory unless moved individually
+        let data_dir = match env::var("DATA_DIR") {
+            Ok(dir) => Self::expand_home_dir(&dir),
+            Err(_) => PathBuf::from(Self::expand_home_dir(&config_dir))
+                .join("data")
+                .to_string_lossy()
+                .to_string(),
+        };
+        let data_path = |name: &str| {
+            PathBuf::from(&data_dir)
+                .join(name)
+                .to_string_lossy()
+                .to_string()
+        };
+
         // Check if random port is enabled
         let enable_random_port = env::var("ENABLE_RANDOM_PORT")
             .unwrap_or_else(|_| "false".to_string())
@@ -302,11 +563,28 @@ impl Config {
                 .unwrap_or(8168)
         };
 
+        let cors_allow_origin = env::var("CORS_ALLOW_ORIGIN").unwrap_or_else(|_| "*".to_string());
+
+        let ssl_cert_file = env::var("SSL_CERT_FILE").ok().filter(|s| !s.is_empty());
+        let ssl_key_file = env::var("SSL_KEY_FILE").ok().filter(|s| !s.is_empty());
+        let scheme = if ssl_cert_file.is_some() && ssl_key_file.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+
         Ok(Config {
             // Server
             host: env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
             port,
             enable_random_port,
+            shutdown_drain_timeout: env::var("SHUTDOWN_DRAIN_TIMEOUT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+            ssl_cert_file,
+            ssl_key_file,
+            unix_socket_path: env::var("UNIX_SOCKET_PATH").ok().filter(|s| !s.is_empty()),
             env: env::var("ENV").unwrap_or_else(|_| "production".to_string()),
             webui_secret_key: env::var("WEBUI_SECRET_KEY").unwrap_or_else(|_| {
                 let key = uuid::Uuid::new_v4().to_string();
@@ -328,13 +606,21 @@ impl Config {
                 .parse()
                 .unwrap_or(10),
             database_pool_max_overflow: env::var("DATABASE_POOL_MAX_OVERFLOW")
-                .unwrap_or_else(|_| "10".to_string())
+                .unwrap_or_else(|_| "0".to_string())
                 .parse()
-                .unwrap_or(10),
+                .unwrap_or(0),
+            database_pool_min_connections: env::var("DATABASE_POOL_MIN_CONNECTIONS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
             database_pool_timeout: env::var("DATABASE_POOL_TIMEOUT")
                 .unwrap_or_else(|_| "30".to_string())
                 .parse()
                 .unwrap_or(30),
+            database_pool_idle_timeout: env::var("DATABASE_POOL_IDLE_TIMEOUT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(600),
             database_pool_recycle: env::var("DATABASE_POOL_RECYCLE")
                 .unwrap_or_else(|_| "3600".to_string())
                 .parse()
@@ -369,15 +655,32 @@ impl Config {
             api_key_allowed_endpoints: env::var("API_KEY_ALLOWED_ENDPOINTS").unwrap_or_default(),
             default_user_role: env::var("DEFAULT_USER_ROLE")
                 .unwrap_or_else(|_| "pending".to_string()),
+            default_group_id: env::var("DEFAULT_GROUP_ID")
+                .ok()
+                .filter(|id| !id.trim().is_empty()),
             show_admin_details: env::var("SHOW_ADMIN_DETAILS")
                 .unwrap_or_else(|_| "true".to_string())
                 .parse()
                 .unwrap_or(true),
             webui_url: env::var("WEBUI_URL")
-                .unwrap_or_else(|_| "http://localhost:8168".to_string()),
+                .unwrap_or_else(|_| format!("{}://localhost:8168", scheme)),
             pending_user_overlay_title: env::var("PENDING_USER_OVERLAY_TITLE").ok(),
             pending_user_overlay_content: env::var("PENDING_USER_OVERLAY_CONTENT").ok(),
             response_watermark: env::var("RESPONSE_WATERMARK").ok(),
+            // "visible" appends the text, "invisible" appends a zero-width encoding of it
+            response_watermark_mode: env::var("RESPONSE_WATERMARK_MODE")
+                .unwrap_or_else(|_| "visible".to_string()),
+            response_watermark_in_stream: env::var("RESPONSE_WATERMARK_IN_STREAM")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .unwrap_or(true),
+            chat_response_stream_delta_chunk_size: env::var(
+                "CHAT_RESPONSE_STREAM_DELTA_CHUNK_SIZE",
+            )
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|size| *size > 0)
+            .unwrap_or(1),
 
             // LDAP Authentication
             enable_ldap: env::var("ENABLE_LDAP")
@@ -418,7 +721,61 @@ impl Config {
             scim_token: env::var("SCIM_TOKEN").unwrap_or_default(),
 
             // CORS
-            cors_allow_origin: env::var("CORS_ALLOW_ORIGIN").unwrap_or_else(|_| "*".to_string()),
+            cors_allow_credentials: env::var("CORS_ALLOW_CREDENTIALS")
+                .map(|v| v.to_lowercase() == "true")
+                // Credentials default to on only for an explicit origin allowlist
+                .unwrap_or_else(|_| parse_cors_origins(&cors_allow_origin).is_some()),
+            cors_allow_origin,
+            trusted_proxies: parse_trusted_proxies(
+                &env::var("TRUSTED_PROXIES").unwrap_or_default(),
+            )?,
+
+            // Outbound proxy
+            http_proxy: parse_proxy_url("HTTP_PROXY")?,
+            https_proxy: parse_proxy_url("HTTPS_PROXY")?,
+            no_proxy: parse_list(&proxy_env("NO_PROXY").unwrap_or_default()),
+
+            // TLS for upstream requests
+            upstream_ca_bundle: env::var("UPSTREAM_CA_BUNDLE")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            upstream_client_cert: env::var("UPSTREAM_CLIENT_CERT")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            upstream_client_key: env::var("UPSTREAM_CLIENT_KEY")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            upstream_tls_insecure_skip_verify: env::var("UPSTREAM_TLS_INSECURE_SKIP_VERIFY")
+                .map(|v| v.to_lowercase() == "true")
+                .unwrap_or(false),
+
+            // Security headers
+            security_headers_mode: env::var("SECURITY_HEADERS_MODE")
+                .map(|v| v.to_lowercase())
+                .unwrap_or_else(|_| "relaxed".to_string()),
+            enable_csp: env::var("ENABLE_CSP")
+                .map(|v| v.to_lowercase() == "true")
+                .unwrap_or(true),
+            content_security_policy: env::var("CONTENT_SECURITY_POLICY")
+                .ok()
+                .filter(|v| !v.trim().is_empty()),
+            csp_connect_src: parse_list(&env::var("CSP_CONNECT_SRC").unwrap_or_default()),
+            csp_img_src: parse_list(&env::var("CSP_IMG_SRC").unwrap_or_default()),
+            // HTTPS_ENABLED is the older name of this setting
+            enable_hsts: env::var("ENABLE_HSTS")
+                .or_else(|_| env::var("HTTPS_ENABLED"))
+                .map(|v| v.to_lowercase() == "true")
+                .unwrap_or(false),
+            hsts_max_age: env::var("HSTS_MAX_AGE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_HSTS_MAX_AGE),
+            x_frame_options: env::var("X_FRAME_OPTIONS")
+                .unwrap_or_else(|_| DEFAULT_X_FRAME_OPTIONS.to_string()),
+            referrer_policy: env::var("REFERRER_POLICY")
+                .unwrap_or_else(|_| DEFAULT_REFERRER_POLICY.to_string()),
+            permissions_policy: env::var("PERMISSIONS_POLICY")
+                .unwrap_or_else(|_| DEFAULT_PERMISSIONS_POLICY.to_string()),
 
             // WebSocket
             enable_websocket_support: env::var("ENABLE_WEBSOCKET_SUPPORT")
@@ -428,12 +785,47 @@ impl Config {
             websocket_manager: env::var("WEBSOCKET_MANAGER")
                 .unwrap_or_else(|_| "local".to_string()),
             websocket_redis_url: env::var("WEBSOCKET_REDIS_URL").ok(),
+            websocket_server_ping_interval: env::var("WEBSOCKET_SERVER_PING_INTERVAL")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(25),
+            websocket_server_ping_timeout: env::var("WEBSOCKET_SERVER_PING_TIMEOUT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(20),
+            websocket_recovery_window: env::var("WEBSOCKET_RECOVERY_WINDOW")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(120),
+            websocket_recovery_max_bytes: env::var("WEBSOCKET_RECOVERY_MAX_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1024 * 1024),
+            websocket_event_rate_limit: env::var("WEBSOCKET_EVENT_RATE_LIMIT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(100),
+            websocket_event_rate_limits: parse_event_limits(
+                &env::var("WEBSOCKET_EVENT_RATE_LIMITS").unwrap_or_default(),
+            )?,
+            ws_chat_max_streams_per_user: env::var("WS_CHAT_MAX_STREAMS_PER_USER")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(3),
 
             // Features
             enable_openai_api: env::var("ENABLE_OPENAI_API")
                 .unwrap_or_else(|_| "true".to_string())
                 .parse()
                 .unwrap_or(true),
+            // Legacy /completions; connections with `legacy_completions` in their config are
+            // proxied, others are served through chat completions
+            enable_openai_legacy_completions: env::var("ENABLE_OPENAI_LEGACY_COMPLETIONS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
             enable_channels: env::var("ENABLE_CHANNELS")
                 .unwrap_or_else(|_| "false".to_string())
                 .parse()
@@ -475,8 +867,9 @@ impl Config {
                 .and_then(|s| s.parse().ok()),
 
             // Storage
-            upload_dir: env::var("UPLOAD_DIR").unwrap_or_else(|_| "/app/data/uploads".to_string()),
-            cache_dir: env::var("CACHE_DIR").unwrap_or_else(|_| "/app/data/cache".to_string()),
+            upload_dir: env::var("UPLOAD_DIR").unwrap_or_else(|_| data_path("uploads")),
+            cache_dir: env::var("CACHE_DIR").unwrap_or_else(|_| data_path("cache")),
+            data_dir: data_dir.clone(),
             static_dir: env::var("STATIC_DIR").unwrap_or_else(|_| {
                 // Default to config_dir/build
                 let expanded_config_dir = Self::expand_home_dir(&config_dir);
@@ -485,9 +878,116 @@ impl Config {
                     .to_string_lossy()
                     .to_string()
             }),
+            static_cache_max_age: env::var("STATIC_CACHE_MAX_AGE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(31_536_000),
+
+            // File uploads
+            file_max_size: env::var("FILE_MAX_SIZE_MB")
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(10)
+                * 1024
+                * 1024,
+            file_max_count: env::var("FILE_MAX_COUNT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10),
+            chat_body_max_size: env::var("CHAT_BODY_MAX_SIZE_MB")
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(25)
+                * 1024
+                * 1024,
+            body_max_size: env::var("BODY_MAX_SIZE_MB")
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(2)
+                * 1024
+                * 1024,
+            file_allowed_content_types: match env::var("FILE_ALLOWED_CONTENT_TYPES") {
+                Ok(types) if !types.trim().is_empty() => parse_list(&types),
+                _ => DEFAULT_ALLOWED_CONTENT_TYPES
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect(),
+            },
+            enable_executable_uploads: env::var("ENABLE_EXECUTABLE_UPLOADS")
+                .unwrap_or_else(|_| "false".to_string())
+                .to_lowercase()
+                .parse()
+                .unwrap_or(false),
+            file_user_quota: env::var("FILE_USER_QUOTA_MB")
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .filter(|mb| *mb > 0)
+                .map(|mb| mb * 1024 * 1024),
+            file_image_compression_width: parse_image_bound("FILE_IMAGE_COMPRESSION_WIDTH"),
+            file_image_compression_height: parse_image_bound("FILE_IMAGE_COMPRESSION_HEIGHT"),
+            enable_signed_urls: env::var("ENABLE_SIGNED_URLS")
+                .map(|v| v.to_lowercase() == "true")
+                .unwrap_or(true),
+            signed_url_expiry: env::var("SIGNED_URL_EXPIRY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(3600),
 
             // Logging
             global_log_level: env::var("GLOBAL_LOG_LEVEL").unwrap_or_else(|_| "INFO".to_string()),
+            enable_json_access_log: env::var("ENABLE_JSON_ACCESS_LOG")
+                .unwrap_or_else(|_| "false".to_string())
+                .to_lowercase()
+                .parse()
+                .unwrap_or(false),
+            metrics_bearer_token: env::var("METRICS_BEARER_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            socketio_emit_service_key: env::var("SOCKETIO_EMIT_SERVICE_KEY")
+                .ok()
+                .filter(|key| !key.is_empty()),
+
+            // Audit log
+            enable_audit_log: env::var("ENABLE_AUDIT_LOG")
+                .unwrap_or_else(|_| "true".to_string())
+                .to_lowercase()
+                .parse()
+                .unwrap_or(true),
+            audit_log_methods: parse_list(
+                &env::var("AUDIT_LOG_METHODS")
+                    .unwrap_or_else(|_| "POST,PUT,PATCH,DELETE".to_string()),
+            )
+            .into_iter()
+            .map(|method| method.to_uppercase())
+            .collect(),
+            audit_log_paths: parse_list(
+                &env::var("AUDIT_LOG_PATHS").unwrap_or_else(|_| "/api".to_string()),
+            ),
+            audit_log_excluded_paths: parse_list(
+                &env::var("AUDIT_LOG_EXCLUDED_PATHS").unwrap_or_default(),
+            ),
+            audit_log_retention_days: env::var("AUDIT_LOG_RETENTION_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(90),
+
+            // Rate limits
+            enable_rate_limit: env::var("ENABLE_RATE_LIMIT")
+                .unwrap_or_else(|_| "true".to_string())
+                .to_lowercase()
+                .parse()
+                .unwrap_or(true),
+            rate_limit_auth: env::var("RATE_LIMIT_AUTH").unwrap_or_else(|_| "20/60".to_string()),
+            rate_limit_chat: env::var("RATE_LIMIT_CHAT").unwrap_or_else(|_| "60/60".to_string()),
+            rate_limit_upload: env::var("RATE_LIMIT_UPLOAD")
+                .unwrap_or_else(|_| "30/60".to_string()),
+            rate_limit_tasks: env::var("RATE_LIMIT_TASKS").unwrap_or_else(|_| "120/60".to_string()),
+            rate_limit_exempt_roles: parse_list(
+                &env::var("RATE_LIMIT_EXEMPT_ROLES").unwrap_or_else(|_| "admin".to_string()),
+            ),
+            rate_limit_role_rules: parse_list(
+                &env::var("RATE_LIMIT_ROLE_RULES").unwrap_or_default(),
+            ),
 
             // OpenAI
             openai_api_base_url: env::var("OPENAI_API_BASE_URL")
@@ -528,6 +1028,14 @@ impl Config {
                 }
             },
             openai_api_configs: serde_json::json!({}),
+            upstream_circuit_breaker_threshold: env::var("UPSTREAM_CIRCUIT_BREAKER_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5),
+            upstream_circuit_breaker_timeout: env::var("UPSTREAM_CIRCUIT_BREAKER_TIMEOUT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
 
             // Audio - TTS
             tts_openai_api_base_url: env::var("TTS_OPENAI_API_BASE_URL")
@@ -621,6 +1129,12 @@ impl Config {
                 .unwrap_or_else(|_| "https://api.openai.com/v1".to_string()),
             rag_template: env::var("RAG_TEMPLATE")
                 .unwrap_or_else(|_| crate::utils::retrieval::DEFAULT_RAG_TEMPLATE.to_string()),
+            rag_citation_style: env::var("RAG_CITATION_STYLE")
+                .unwrap_or_else(|_| "inline".to_string()),
+            rag_citation_include_source: env::var("RAG_CITATION_INCLUDE_SOURCE")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .unwrap_or(true),
             rag_full_context: env::var("RAG_FULL_CONTEXT")
                 .unwrap_or_else(|_| "false".to_string())
                 .parse()
@@ -641,6 +1155,10 @@ impl Config {
                 .unwrap_or_else(|_| "0.0".to_string())
                 .parse()
                 .unwrap_or(0.0),
+            rag_max_sources: env::var("RAG_MAX_SOURCES")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .unwrap_or(0),
             hybrid_bm25_weight: env::var("HYBRID_BM25_WEIGHT")
                 .unwrap_or_else(|_| "0.5".to_string())
                 .parse()
@@ -664,6 +1182,17 @@ impl Config {
             .parse()
             .unwrap_or(true),
 
+            // Web Search
+            web_search_engine: env::var("WEB_SEARCH_ENGINE").unwrap_or_default(),
+            web_search_result_count: env::var("WEB_SEARCH_RESULT_COUNT")
+                .unwrap_or_else(|_| "3".to_string())
+                .parse()
+                .unwrap_or(3),
+            searxng_query_url: env::var("SEARXNG_QUERY_URL").unwrap_or_default(),
+            brave_search_api_key: env::var("BRAVE_SEARCH_API_KEY").unwrap_or_default(),
+            google_pse_api_key: env::var("GOOGLE_PSE_API_KEY").unwrap_or_default(),
+            google_pse_engine_id: env::var("GOOGLE_PSE_ENGINE_ID").unwrap_or_default(),
+
             // Sentence Transformers
             sentence_transformers_home: env::var("SENTENCE_TRANSFORMERS_HOME").ok(),
             sentence_transformers_backend: env::var("SENTENCE_TRANSFORMERS_BACKEND")
@@ -770,6 +1299,7 @@ impl Config {
                 .collect(),
             default_prompt_suggestions: serde_json::json!([]),
             banners: serde_json::json!([]),
+            custom_branding: Default::default(),
             user_permissions: serde_json::json!({}),
 
             // Version and Updates
@@ -781,6 +1311,7 @@ impl Config {
             // Task Configuration
             task_model: env::var("TASK_MODEL").ok(),
             task_model_external: env::var("TASK_MODEL_EXTERNAL").ok(),
+            title_generation_model: env::var("TITLE_GENERATION_MODEL").ok(),
             enable_search_query_generation: env::var("ENABLE_SEARCH_QUERY_GENERATION")
                 .unwrap_or_else(|_| "true".to_string())
                 .parse()
@@ -799,6 +1330,18 @@ impl Config {
             .unwrap_or_else(|_| "200".to_string())
             .parse()
             .unwrap_or(200),
+            autocomplete_generation_prompt_template: env::var(
+                "AUTOCOMPLETE_GENERATION_PROMPT_TEMPLATE",
+            )
+            .unwrap_or_else(|_| String::new()),
+            autocomplete_generation_rate_limit: env::var("AUTOCOMPLETE_GENERATION_RATE_LIMIT")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            autocomplete_generation_timeout: env::var("AUTOCOMPLETE_GENERATION_TIMEOUT")
+                .unwrap_or_else(|_| "3".to_string())
+                .parse()
+                .unwrap_or(3),
             enable_tags_generation: env::var("ENABLE_TAGS_GENERATION")
                 .unwrap_or_else(|_| "true".to_string())
                 .parse()
@@ -821,6 +1364,12 @@ impl Config {
                 "IMAGE_PROMPT_GENERATION_PROMPT_TEMPLATE",
             )
             .unwrap_or_else(|_| String::new()),
+            enable_emoji_generation: env::var("ENABLE_EMOJI_GENERATION")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .unwrap_or(true),
+            emoji_generation_prompt_template: env::var("EMOJI_GENERATION_PROMPT_TEMPLATE")
+                .unwrap_or_else(|_| String::new()),
             query_generation_prompt_template: env::var("QUERY_GENERATION_PROMPT_TEMPLATE")
                 .unwrap_or_else(|_| String::new()),
             tools_function_calling_prompt_template: env::var(
@@ -839,6 +1388,7 @@ impl Config {
                 .unwrap_or_else(|_| "false".to_string())
                 .parse()
                 .unwrap_or(false),
+            default_direct_connections: serde_json::json!({}),
             enable_base_models_cache: env::var("ENABLE_BASE_MODELS_CACHE")
                 .unwrap_or_else(|_| "true".to_string())
                 .parse()
@@ -846,13 +1396,32 @@ impl Config {
 
             // Tool Servers
             tool_server_connections: serde_json::json!([]),
+            tool_execution_timeout: env::var("TOOL_EXECUTION_TIMEOUT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+            tool_secrets: parse_json_map(
+                "TOOL_SECRETS",
+                &env::var("TOOL_SECRETS").unwrap_or_default(),
+            )?,
+            tool_secret_grants: parse_json_map(
+                "TOOL_SECRET_GRANTS",
+                &env::var("TOOL_SECRET_GRANTS").unwrap_or_default(),
+            )?,
+            oauth_clients: parse_json_map(
+                "OAUTH_CLIENTS",
+                &env::var("OAUTH_CLIENTS").unwrap_or_default(),
+            )?,
 
             // Evaluations
             enable_evaluation_arena_models: env::var("ENABLE_EVALUATION_ARENA_MODELS")
                 .unwrap_or_else(|_| "false".to_string())
                 .parse()
                 .unwrap_or(false),
-            evaluation_arena_models: serde_json::json!([]),
+            evaluation_arena_models: env::var("EVALUATION_ARENA_MODELS")
+                .ok()
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or_else(|| serde_json::json!([])),
 
             // Integrations
             enable_google_drive_integration: env::var("ENABLE_GOOGLE_DRIVE_INTEGRATION")
@@ -863,6 +1432,435 @@ impl Config {
                 .unwrap_or_else(|_| "false".to_string())
                 .parse()
                 .unwrap_or(false),
+
+            // Token budgets
+            token_budget_period: env::var("TOKEN_BUDGET_PERIOD")
+                .unwrap_or_else(|_| "month".to_string()),
+            user_token_limit: env::var("USER_TOKEN_LIMIT")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .unwrap_or(0),
+            model_token_limits: env::var("MODEL_TOKEN_LIMITS")
+                .ok()
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or_else(|| serde_json::json!({})),
+
+            // Chat completion request checks
+            chat_param_policy: env::var("CHAT_PARAM_POLICY")
+                .unwrap_or_else(|_| "clamp".to_string()),
+            chat_max_messages: env::var("CHAT_MAX_MESSAGES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1000),
+            chat_max_message_chars: env::var("CHAT_MAX_MESSAGE_CHARS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1_000_000),
+            chat_max_tokens: env::var("CHAT_MAX_TOKENS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+            keep_reasoning_in_content: env::var("KEEP_REASONING_IN_CONTENT")
+                .map(|v| v.to_lowercase() == "true")
+                .unwrap_or(false),
         })
     }
 }
+
+impl Config {
+    /// Explicit CORS origin allowlist, or `None` when any origin is allowed
+    pub fn cors_allowed_origins(&self) -> Option<Vec<String>> {
+        parse_cors_origins(&self.cors_allow_origin)
+    }
+
+    /// Validate the CORS policy before the server starts
+    pub fn validate_cors(&self) -> anyhow::Result<()> {
+        validate_cors_policy(&self.cors_allow_origin, self.cors_allow_credentials)
+    }
+
+    /// Directory of cached text-to-speech audio, served under `/cache/audio/speech`
+    pub fn speech_cache_dir(&self) -> PathBuf {
+        PathBuf::from(&self.cache_dir).join("audio").join("speech")
+    }
+
+    /// Create the data directory and the directories derived from it
+    pub fn ensure_data_dirs(&self) -> std::io::Result<()> {
+        for dir in [
+            PathBuf::from(&self.data_dir),
+            PathBuf::from(&self.upload_dir),
+            PathBuf::from(&self.cache_dir),
+            self.speech_cache_dir(),
+        ] {
+            std::fs::create_dir_all(&dir)?;
+        }
+        Ok(())
+    }
+
+    /// Which listener the server binds, rejecting conflicting settings
+    pub fn listener(&self) -> anyhow::Result<Listener> {
+        let tls = match (&self.ssl_cert_file, &self.ssl_key_file) {
+            (Some(cert_file), Some(key_file)) => Some(Listener::Tls {
+                cert_file: cert_file.clone(),
+                key_file: key_file.clone(),
+            }),
+            (None, None) => None,
+            _ => anyhow::bail!("SSL_CERT_FILE and SSL_KEY_FILE must be set together"),
+        };
+
+        match (&self.unix_socket_path, tls) {
+            (Some(_), _) if self.enable_random_port => {
+                anyhow::bail!("UNIX_SOCKET_PATH cannot be combined with ENABLE_RANDOM_PORT=true")
+            }
+            (Some(_), Some(_)) => anyhow::bail!(
+                "UNIX_SOCKET_PATH cannot be combined with SSL_CERT_FILE/SSL_KEY_FILE; \
+                 terminate TLS in the proxy in front of the socket"
+            ),
+            (Some(path), None) => Ok(Listener::Unix(path.clone())),
+            (None, Some(tls)) => Ok(tls),
+            (None, None) => Ok(Listener::Tcp),
+        }
+    }
+}
+
+/// Where the HTTP server listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listener {
+    /// Plain HTTP on HOST/PORT
+    Tcp,
+    /// HTTPS on HOST/PORT
+    Tls { cert_file: String, key_file: String },
+    /// Plain HTTP on a Unix domain socket
+    Unix(String),
+}
+
+/// Upload types accepted when FILE_ALLOWED_CONTENT_TYPES is unset: documents, media
+/// and text (including source code)
+const DEFAULT_ALLOWED_CONTENT_TYPES: &[&str] = &[
+    "text/*",
+    "image/*",
+    "audio/*",
+    "video/*",
+    "application/pdf",
+    "application/json",
+    "application/xml",
+    "application/rtf",
+    "application/epub+zip",
+    "application/msword",
+    "application/vnd.ms-excel",
+    "application/vnd.ms-powerpoint",
+    "application/vnd.openxmlformats-officedocument.*",
+    "application/vnd.oasis.opendocument.*",
+];
+
+/// Image compression bound in pixels: 1024 by default, `0` turns it off
+fn parse_image_bound(name: &str) -> Option<u32> {
+    let px = env::var(name)
+        .ok()
+        .and_then(|s| s.parse::<u32>().ok())
+        .unwrap_or(1024);
+    (px > 0).then_some(px)
+}
+
+/// Parse a comma-separated list, dropping empty entries
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parse a comma-separated list of CIDR ranges; a bare address is a single host
+fn parse_trusted_proxies(value: &str) -> anyhow::Result<Vec<ipnet::IpNet>> {
+    parse_list(value)
+        .iter()
+        .map(|entry| {
+            entry
+                .parse::<ipnet::IpNet>()
+                .or_else(|_| entry.parse::<std::net::IpAddr>().map(ipnet::IpNet::from))
+                .map_err(|_| anyhow::anyhow!("Invalid TRUSTED_PROXIES entry: {:?}", entry))
+        })
+        .collect()
+}
+
+/// Parse `event=limit` pairs, e.g. `typing=20,chat-events=300`
+fn parse_event_limits(value: &str) -> anyhow::Result<HashMap<String, usize>> {
+    parse_list(value)
+        .iter()
+        .map(|entry| {
+            entry
+                .rsplit_once('=')
+                .and_then(|(event, limit)| Some((event.trim(), limit.trim().parse().ok()?)))
+                .filter(|(event, _)| !event.is_empty())
+                .map(|(event, limit)| (event.to_string(), limit))
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Invalid WEBSOCKET_EVENT_RATE_LIMITS entry: {:?}", entry)
+                })
+        })
+        .collect()
+}
+
+/// Parse a JSON object from the variable `name`; unset or empty is an empty map
+fn parse_json_map<T: serde::de::DeserializeOwned>(
+    name: &str,
+    value: &str,
+) -> anyhow::Result<HashMap<String, T>> {
+    if value.trim().is_empty() {
+        return Ok(HashMap::new());
+    }
+    serde_json::from_str(value).map_err(|e| anyhow::anyhow!("Invalid {}: {}", name, e))
+}
+
+/// A proxy variable, in the upper or lower case spelling curl and most tools accept
+fn proxy_env(name: &str) -> Option<String> {
+    env::var(name)
+        .or_else(|_| env::var(name.to_lowercase()))
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Proxy URL from the environment, checked so a typo fails at startup rather than on the
+/// first upstream request
+fn parse_proxy_url(name: &str) -> anyhow::Result<Option<String>> {
+    match proxy_env(name) {
+        Some(url) => match reqwest::Proxy::all(url.as_str()) {
+            Ok(_) => Ok(Some(url)),
+            Err(e) => Err(anyhow::anyhow!("Invalid {}: {}", name, e)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Parse a comma-separated origin list. `"*"` anywhere in the list means any origin.
+fn parse_cors_origins(value: &str) -> Option<Vec<String>> {
+    let origins: Vec<String> = value
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.trim_end_matches('/').to_string())
+        .collect();
+
+    if origins.iter().any(|o| o == "*") {
+        None
+    } else {
+        Some(origins)
+    }
+}
+
+fn validate_cors_policy(cors_allow_origin: &str, allow_credentials: bool) -> anyhow::Result<()> {
+    match parse_cors_origins(cors_allow_origin) {
+        None if allow_credentials => anyhow::bail!(
+            "CORS_ALLOW_CREDENTIALS=true cannot be combined with CORS_ALLOW_ORIGIN=\"*\"; \
+             list the allowed origins explicitly (comma-separated) or disable credentials"
+        ),
+        Some(origins) if origins.is_empty() => {
+            anyhow::bail!("CORS_ALLOW_ORIGIN is empty; use \"*\" or a comma-separated origin list")
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cors_origins() {
+        assert_eq!(parse_cors_origins("*"), None);
+        assert_eq!(parse_cors_origins("https://a.com, *"), None);
+        assert_eq!(
+            parse_cors_origins("https://a.com, https://b.com/"),
+            Some(vec![
+                "https://a.com".to_string(),
+                "https://b.com".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_listener() {
+        let mut config = Config::from_env().unwrap();
+        config.enable_random_port = false;
+        config.ssl_cert_file = None;
+        config.ssl_key_file = None;
+        config.unix_socket_path = None;
+        assert_eq!(config.listener().unwrap(), Listener::Tcp);
+
+        config.ssl_cert_file = Some("cert.pem".to_string());
+        assert!(config.listener().is_err());
+        config.ssl_key_file = Some("key.pem".to_string());
+        assert_eq!(
+            config.listener().unwrap(),
+            Listener::Tls {
+                cert_file: "cert.pem".to_string(),
+                key_file: "key.pem".to_string()
+            }
+        );
+
+        config.unix_socket_path = Some("/run/webui.sock".to_string());
+        assert!(config.listener().is_err());
+        config.ssl_cert_file = None;
+        config.ssl_key_file = None;
+        assert_eq!(
+            config.listener().unwrap(),
+            Listener::Unix("/run/webui.sock".to_string())
+        );
+
+        config.enable_random_port = true;
+        assert!(config.listener().is_err());
+    }
+
+    #[test]
+    fn test_ensure_data_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::from_env().unwrap();
+        config.data_dir = dir.path().join("data").to_string_lossy().to_string();
+        config.upload_dir = dir
+            .path()
+            .join("data/uploads")
+            .to_string_lossy()
+            .to_string();
+        // Moved out of the data directory
+        config.cache_dir = dir.path().join("cache").to_string_lossy().to_string();
+
+        config.ensure_data_dirs().unwrap();
+        assert!(dir.path().join("data/uploads").is_dir());
+        assert!(dir.path().join("cache/audio/speech").is_dir());
+        assert_eq!(
+            config.speech_cache_dir(),
+            dir.path().join("cache/audio/speech")
+        );
+    }
+
+    #[test]
+    fn test_parse_event_limits() {
+        let limits = parse_event_limits("typing=20, ydoc:document:update = 600").unwrap();
+        assert_eq!(limits.get("typing"), Some(&20));
+        assert_eq!(limits.get("ydoc:document:update"), Some(&600));
+        assert!(parse_event_limits("").unwrap().is_empty());
+        assert!(parse_event_limits("typing").is_err());
+        assert!(parse_event_limits("typing=lots").is_err());
+        assert!(parse_event_limits("=5").is_err());
+    }
+
+    #[test]
+    fn test_parse_json_map() {
+        let secrets: HashMap<String, String> =
+            parse_json_map("TOOL_SECRETS", r#"{"WEATHER_API_KEY": "abc"}"#).unwrap();
+        assert_eq!(secrets["WEATHER_API_KEY"], "abc");
+        assert!(parse_json_map::<String>("TOOL_SECRETS", " ")
+            .unwrap()
+            .is_empty());
+
+        let error = parse_json_map::<String>("TOOL_SECRETS", r#"{"WEATHER_API_KEY": abc}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Invalid TOOL_SECRETS:"), "{}", error);
+
+        // Clients without a client_id are rejected too
+        assert!(
+            parse_json_map::<crate::services::oauth_client::OAuthClientInfo>(
+                "OAUTH_CLIENTS",
+                r#"{"github": {"scope": "repo"}}"#,
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_trusted_proxies() {
+        let proxies = parse_trusted_proxies("10.0.0.0/8, 127.0.0.1, ::1").unwrap();
+        assert_eq!(
+            proxies,
+            vec![
+                "10.0.0.0/8".parse::<ipnet::IpNet>().unwrap(),
+                "127.0.0.1/32".parse().unwrap(),
+                "::1/128".parse().unwrap(),
+            ]
+        );
+        assert!(parse_trusted_proxies("").unwrap().is_empty());
+        assert!(parse_trusted_proxies("10.0.0.0/33").is_err());
+        assert!(parse_trusted_proxies("proxy.local").is_err());
+    }
+
+    #[test]
+    fn test_validate_cors_policy() {
+        assert!(validate_cors_policy("*", false).is_ok());
+        assert!(validate_cors_policy("*", true).is_err());
+        assert!(validate_cors_policy("https://a.com", true).is_ok());
+        assert!(validate_cors_policy(" , ", false).is_err());
+    }
+
+    #[test]
+    fn test_shared_config_applies_updates_on_drop() {
+        let shared = SharedConfig::new(Config::from_env().unwrap());
+        let before = shared.snapshot();
+        {
+            let mut config = shared.write();
+            config.webui_name = "Updated WebUI".to_string();
+            // Readers keep seeing the old config until the update is applied
+            assert_eq!(shared.snapshot().webui_name, before.webui_name);
+        }
+        assert_eq!(shared.snapshot().webui_name, "Updated WebUI");
+        assert_ne!(before.webui_name, "Updated WebUI");
+    }
+
+    #[test]
+    fn test_shared_config_survives_panicking_writer() {
+        let shared = SharedConfig::new(Config::from_env().unwrap());
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut config = shared.write();
+            config.webui_name = "Half applied".to_string();
+            panic!("writer failed");
+        }));
+        assert!(result.is_err());
+        assert_ne!(shared.snapshot().webui_name, "Half applied");
+
+        // Later writers aren't poisoned
+        shared.write().webui_name = "Recovered".to_string();
+        assert_eq!(shared.snapshot().webui_name, "Recovered");
+    }
+
+    #[tokio::test]
+    async fn test_update_during_long_streams_does_not_block() {
+        use std::time::Duration;
+
+        let shared = Arc::new(SharedConfig::new(Config::from_env().unwrap()));
+
+        // Streaming requests hold on to their config across awaits
+        let mut streams = Vec::new();
+        for _ in 0..50 {
+            let shared = shared.clone();
+            streams.push(tokio::spawn(async move {
+                let config = shared.snapshot();
+                let name = config.webui_name.clone();
+                for _ in 0..20 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    assert_eq!(config.webui_name, name);
+                }
+            }));
+        }
+        tokio::task::yield_now().await;
+
+        // Updates go through while the streams are still running
+        let writer = shared.clone();
+        let updates = tokio::task::spawn_blocking(move || {
+            for i in 0..100 {
+                writer.write().webui_name = format!("Instance {}", i);
+            }
+        });
+        tokio::time::timeout(Duration::from_millis(100), updates)
+            .await
+            .expect("config updates blocked by readers")
+            .unwrap();
+        assert_eq!(shared.snapshot().webui_name, "Instance 99");
+        assert!(streams.iter().any(|s| !s.is_finished()));
+
+        for stream in streams {
+            stream.await.unwrap();
+        }
+    }
+}
@@ -1,43 +1,227 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use sqlx::{
-    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
     SqlitePool,
 };
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::config::Config;
 
 // Load SQLite schema from external file
 const SQLITE_SCHEMA: &str = include_str!("schema.sql");
 
+/// Bookkeeping of the schema revisions applied to the database
+const MIGRATIONS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS schema_migrations (
+    version TEXT PRIMARY KEY,
+    applied_at BIGINT NOT NULL,
+    failed_statements INTEGER NOT NULL DEFAULT 0
+)"#;
+
+/// Version of the embedded schema, derived from its contents
+///
+/// The schema is applied as a whole and is safe to re-run, so every revision of
+/// `schema.sql` shipped in a build is one migration.
+pub fn schema_version() -> String {
+    let digest = Sha256::digest(SQLITE_SCHEMA.as_bytes());
+    let hex: String = digest[..6].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("schema-{}", hex)
+}
+
+/// How long a SQLite connection waits on a lock held by another writer before failing
+const SQLITE_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connection options for a `sqlite:` URL
+///
+/// WAL lets readers carry on while a chat is being saved, and with it `NORMAL`
+/// synchronous is still crash-safe. Writers queue on the busy timeout instead of
+/// failing with "database is locked".
+fn sqlite_options(database_url: &str) -> anyhow::Result<SqliteConnectOptions> {
+    Ok(SqliteConnectOptions::from_str(database_url)?
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(SqliteSynchronous::Normal)
+        .busy_timeout(SQLITE_BUSY_TIMEOUT)
+        .foreign_keys(true))
+}
+
+/// Sizing and timeouts of the connection pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolSettings {
+    pub max_connections: u32,
+    /// Connections kept open even when idle
+    pub min_connections: u32,
+    /// How long a query waits for a free connection before failing
+    pub acquire_timeout: Duration,
+    /// Idle connections above `min_connections` are closed after this long
+    pub idle_timeout: Option<Duration>,
+    /// Connections are replaced after this long
+    pub max_lifetime: Option<Duration>,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        PoolSettings {
+            max_connections: 10,
+            min_connections: 1,
+            acquire_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(600)),
+            max_lifetime: Some(Duration::from_secs(3600)),
+        }
+    }
+}
+
+impl PoolSettings {
+    /// Settings from the `DATABASE_POOL_*` variables
+    ///
+    /// The pool holds up to `DATABASE_POOL_SIZE + DATABASE_POOL_MAX_OVERFLOW`
+    /// connections. A zero idle timeout or recycle time disables it.
+    pub fn from_config(config: &Config) -> Self {
+        let max_connections = config
+            .database_pool_size
+            .saturating_add(config.database_pool_max_overflow)
+            .max(1);
+        let min_connections = config.database_pool_min_connections.min(max_connections);
+        let nonzero = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+        PoolSettings {
+            max_connections,
+            min_connections,
+            acquire_timeout: Duration::from_secs(config.database_pool_timeout.max(1)),
+            idle_timeout: nonzero(config.database_pool_idle_timeout),
+            max_lifetime: nonzero(config.database_pool_recycle),
+        }
+    }
+}
+
+/// Connection counts reported by `/health/ready`
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStats {
+    /// Open connections, busy or idle
+    pub size: u32,
+    pub idle: usize,
+    pub active: usize,
+    pub max: u32,
+}
+
+/// A schema revision recorded in `schema_migrations`
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AppliedMigration {
+    pub version: String,
+    pub applied_at: i64,
+    /// Statements that failed while applying it. Always 0 now that a schema with failed
+    /// statements is left pending; older releases recorded it anyway.
+    pub failed_statements: i64,
+}
+
+/// Applied and pending migrations, as reported by `GET /api/v1/db/migrations`
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub current_version: String,
+    pub applied: Vec<AppliedMigration>,
+    pub pending: Vec<String>,
+}
+
 #[derive(Clone)]
 pub struct Database {
     pub pool: SqlitePool,
+    /// Held while migrations run, so two runs never interleave
+    migration_lock: Arc<Mutex<()>>,
 }
 
 impl Database {
     pub async fn new(database_url: &str) -> anyhow::Result<Self> {
-        let connect_options = SqliteConnectOptions::from_str(database_url)?.create_if_missing(true);
+        Self::connect(database_url, &PoolSettings::default()).await
+    }
+
+    pub async fn connect(database_url: &str, settings: &PoolSettings) -> anyhow::Result<Self> {
+        let connect_options = sqlite_options(database_url)?;
 
         let pool = SqlitePoolOptions::new()
-            .max_connections(10)
-            .min_connections(1)
-            .acquire_timeout(Duration::from_secs(30))
-            .idle_timeout(Duration::from_secs(600))
-            .max_lifetime(Duration::from_secs(3600))
+            .max_connections(settings.max_connections)
+            .min_connections(settings.min_connections)
+            .acquire_timeout(settings.acquire_timeout)
+            .idle_timeout(settings.idle_timeout)
+            .max_lifetime(settings.max_lifetime)
             .connect_with(connect_options)
             .await?;
 
-        Ok(Database { pool })
+        Ok(Database {
+            pool,
+            migration_lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        let size = self.pool.size();
+        let idle = self.pool.num_idle();
+        PoolStats {
+            size,
+            idle,
+            active: (size as usize).saturating_sub(idle),
+            max: self.pool.options().get_max_connections(),
+        }
+    }
+
+    /// Apply pending migrations, waiting for a run already in progress. Returns the
+    /// versions applied, none when the database is up to date.
+    pub async fn run_migrations(&self) -> anyhow::Result<Vec<String>> {
+        let _guard = self.migration_lock.lock().await;
+        self.apply_pending_migrations().await
+    }
+
+    /// Like [`Self::run_migrations`], but returns `None` instead of waiting when
+    /// another run is in progress
+    pub async fn try_run_migrations(&self) -> anyhow::Result<Option<Vec<String>>> {
+        let _guard = match self.migration_lock.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => return Ok(None),
+        };
+        self.apply_pending_migrations().await.map(Some)
+    }
+
+    pub async fn migration_status(&self) -> anyhow::Result<MigrationStatus> {
+        sqlx::query(MIGRATIONS_TABLE).execute(&self.pool).await?;
+        let applied: Vec<AppliedMigration> = sqlx::query_as(
+            "SELECT version, applied_at, failed_statements FROM schema_migrations
+             ORDER BY applied_at, version",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let current_version = schema_version();
+        let pending = if applied.iter().any(|m| m.version == current_version) {
+            Vec::new()
+        } else {
+            vec![current_version.clone()]
+        };
+        Ok(MigrationStatus {
+            current_version,
+            applied,
+            pending,
+        })
     }
 
-    pub async fn run_migrations(&self) -> anyhow::Result<()> {
-        tracing::info!("Initializing database schema");
+    async fn apply_pending_migrations(&self) -> anyhow::Result<Vec<String>> {
+        let status = self.migration_status().await?;
+        if status.pending.is_empty() {
+            tracing::info!("Database schema {} is up to date", status.current_version);
+            return Ok(Vec::new());
+        }
+
+        tracing::info!("Initializing database schema {}", status.current_version);
 
         // Parse and execute all SQL statements
         let statements = Self::parse_sql_statements(SQLITE_SCHEMA);
+        let mut failed_statements = 0;
         for (idx, statement) in statements.iter().enumerate() {
             let trimmed = statement.trim();
             if !trimmed.is_empty() && !trimmed.starts_with("--") {
                 if let Err(e) = sqlx::query(trimmed).execute(&self.pool).await {
+                    failed_statements += 1;
                     tracing::warn!(
                         "Error executing statement {}: {} - Error: {}",
                         idx + 1,
@@ -48,8 +232,34 @@ impl Database {
             }
         }
 
+        // A partly applied schema stays pending, so the next run retries it
+        if failed_statements > 0 {
+            tracing::error!(
+                "Database schema {} not recorded as applied: {} statement(s) failed",
+                status.current_version,
+                failed_statements
+            );
+            anyhow::bail!(
+                "{} statement(s) of database schema {} failed",
+                failed_statements,
+                status.current_version
+            );
+        }
+
+        sqlx::query(
+            "INSERT INTO schema_migrations (version, applied_at, failed_statements)
+             VALUES ($1, $2, 0)
+             ON CONFLICT(version) DO UPDATE SET
+                applied_at = excluded.applied_at,
+                failed_statements = 0",
+        )
+        .bind(&status.current_version)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+
         tracing::info!("Database schema initialization completed");
-        Ok(())
+        Ok(status.pending)
     }
 
     /// Parse SQL statements from schema
@@ -194,3 +404,127 @@ impl Database {
         Ok(models)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_settings_from_config() {
+        let mut config = Config::from_env().unwrap();
+        config.database_pool_size = 4;
+        config.database_pool_max_overflow = 2;
+        config.database_pool_min_connections = 10;
+        config.database_pool_timeout = 5;
+        config.database_pool_idle_timeout = 0;
+        config.database_pool_recycle = 1800;
+
+        let settings = PoolSettings::from_config(&config);
+        assert_eq!(settings.max_connections, 6);
+        // The minimum can't exceed the maximum
+        assert_eq!(settings.min_connections, 6);
+        assert_eq!(settings.acquire_timeout, Duration::from_secs(5));
+        assert_eq!(settings.idle_timeout, None);
+        assert_eq!(settings.max_lifetime, Some(Duration::from_secs(1800)));
+    }
+
+    #[tokio::test]
+    async fn test_pool_stats() {
+        let settings = PoolSettings {
+            max_connections: 3,
+            ..PoolSettings::default()
+        };
+        let db = Database::connect("sqlite::memory:", &settings)
+            .await
+            .unwrap();
+        let conn = db.pool.acquire().await.unwrap();
+        let stats = db.pool_stats();
+        assert_eq!(stats.max, 3);
+        assert!(stats.active >= 1);
+        assert_eq!(stats.active + stats.idle, stats.size as usize);
+        drop(conn);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_pragmas() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("webui.db").display());
+        let db = Database::new(&url).await.unwrap();
+
+        let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(journal_mode.to_lowercase(), "wal");
+        // NORMAL
+        let synchronous: i64 = sqlx::query_scalar("PRAGMA synchronous")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(synchronous, 1);
+        let foreign_keys: i64 = sqlx::query_scalar("PRAGMA foreign_keys")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(foreign_keys, 1);
+        let busy_timeout: i64 = sqlx::query_scalar("PRAGMA busy_timeout")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(busy_timeout, 5000);
+    }
+
+    #[tokio::test]
+    async fn test_migrations_are_recorded_and_idempotent() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("webui.db").display());
+        let db = Database::new(&url).await.unwrap();
+
+        let status = db.migration_status().await.unwrap();
+        assert!(status.applied.is_empty());
+        assert_eq!(status.pending, vec![schema_version()]);
+
+        assert_eq!(db.run_migrations().await.unwrap(), vec![schema_version()]);
+        let status = db.migration_status().await.unwrap();
+        assert!(status.pending.is_empty());
+        assert_eq!(status.applied.len(), 1);
+        assert_eq!(status.applied[0].version, schema_version());
+        assert_eq!(status.applied[0].failed_statements, 0);
+
+        // Nothing left to do
+        assert_eq!(db.try_run_migrations().await.unwrap(), Some(Vec::new()));
+        assert_eq!(db.migration_status().await.unwrap().applied.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_migration_is_not_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("webui.db").display());
+        let db = Database::new(&url).await.unwrap();
+
+        // A stray table without the indexed columns makes the schema's index statements fail
+        sqlx::query(r#"CREATE TABLE "user" (id TEXT PRIMARY KEY)"#)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert!(db.run_migrations().await.is_err());
+        let status = db.migration_status().await.unwrap();
+        assert!(status.applied.is_empty());
+        assert_eq!(status.pending, vec![schema_version()]);
+
+        // Retried once the cause is fixed
+        sqlx::query(r#"DROP TABLE "user""#)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(db.run_migrations().await.unwrap(), vec![schema_version()]);
+        assert!(db.migration_status().await.unwrap().pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_migration_run_is_refused() {
+        let db = Database::new("sqlite::memory:").await.unwrap();
+        let _running = db.migration_lock.lock().await;
+        assert_eq!(db.try_run_migrations().await.unwrap(), None);
+    }
+}
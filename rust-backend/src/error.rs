@@ -6,6 +6,8 @@ use actix_web::{
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::middleware::request_id::current_request_id;
+
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("Database error: {0}")]
@@ -20,6 +22,17 @@ pub enum AppError {
     #[error("Validation error: {0}")]
     Validation(String),
 
+    /// Field errors from `validator`, reported like FastAPI's request validation errors
+    #[error("Validation error: {0}")]
+    InvalidFields(#[from] validator::ValidationErrors),
+
+    /// Request body, query or path that couldn't be parsed, with where it failed
+    #[error("Invalid request {location}: {message}")]
+    InvalidRequest {
+        location: &'static str,
+        message: String,
+    },
+
     #[error("Not found: {0}")]
     NotFound(String),
 
@@ -70,16 +83,182 @@ pub enum AppError {
 
     #[error("Too many requests: {0}")]
     TooManyRequests(String),
+
+    #[error("Upstream unavailable: {0}")]
+    UpstreamUnavailable(String),
+
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    #[error("Unsupported media type: {0}")]
+    UnsupportedMediaType(String),
+
+    /// The server is shutting down and not taking new work
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
-    pub detail: String,
+    pub detail: ErrorDetail,
+    /// Id of the failed request, for quoting in bug reports; left out when there is none
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub request_id: Option<String>,
 }
 
-impl ResponseError for AppError {
-    fn error_response(&self) -> HttpResponse {
-        let (status, error_message) = match self {
+/// The `detail` of an error body: a message, or FastAPI-style field errors
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ErrorDetail {
+    Message(String),
+    Fields(Vec<FieldError>),
+}
+
+/// One entry of a FastAPI validation error list
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct FieldError {
+    /// Where the error is, e.g. `["body", "email"]`
+    pub loc: Vec<String>,
+    pub msg: String,
+    #[serde(rename = "type")]
+    pub error_type: String,
+}
+
+/// Flatten `validator` errors (including nested structs and lists) into field errors
+fn field_errors(errors: &validator::ValidationErrors, loc: &[String]) -> Vec<FieldError> {
+    use validator::ValidationErrorsKind;
+
+    let mut fields: Vec<_> = errors.errors().iter().collect();
+    fields.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut result = Vec::new();
+    for (field, kind) in fields {
+        let mut field_loc = loc.to_vec();
+        field_loc.push(field.to_string());
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                result.extend(errors.iter().map(|error| FieldError {
+                    loc: field_loc.clone(),
+                    msg: validation_message(error),
+                    error_type: format!("value_error.{}", error.code),
+                }));
+            }
+            ValidationErrorsKind::Struct(errors) => {
+                result.extend(field_errors(errors, &field_loc));
+            }
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    let mut item_loc = field_loc.clone();
+                    item_loc.push(index.to_string());
+                    result.extend(field_errors(errors, &item_loc));
+                }
+            }
+        }
+    }
+    result
+}
+
+fn validation_message(error: &validator::ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+    let param = |name: &str| error.params.get(name).map(|v| v.to_string());
+    match error.code.as_ref() {
+        "email" => "value is not a valid email address".to_string(),
+        "url" => "invalid or missing URL scheme".to_string(),
+        "required" => "field required".to_string(),
+        "length" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => {
+                format!(
+                    "ensure this value has between {} and {} characters",
+                    min, max
+                )
+            }
+            (Some(min), None) => format!("ensure this value has at least {} characters", min),
+            (None, Some(max)) => format!("ensure this value has at most {} characters", max),
+            (None, None) => "invalid length".to_string(),
+        },
+        "range" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("ensure this value is between {} and {}", min, max),
+            (Some(min), None) => format!("ensure this value is at least {}", min),
+            (None, Some(max)) => format!("ensure this value is at most {}", max),
+            (None, None) => "value out of range".to_string(),
+        },
+        code => format!("invalid value ({})", code),
+    }
+}
+
+/// Turn extractor failures (malformed JSON, bad query strings or path segments) into
+/// the same 422 body FastAPI sends, instead of actix's plain-text errors
+pub fn json_error_handler(
+    err: actix_web::error::JsonPayloadError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    use actix_web::error::{JsonPayloadError, PayloadError};
+
+    // Oversized bodies are a 413 (BodyLimit replaces the body with the group's limit)
+    if matches!(
+        err,
+        JsonPayloadError::Overflow { .. }
+            | JsonPayloadError::OverflowKnownLength { .. }
+            | JsonPayloadError::Payload(PayloadError::Overflow)
+    ) {
+        return AppError::PayloadTooLarge(err.to_string()).into();
+    }
+    AppError::InvalidRequest {
+        location: "body",
+        message: err.to_string(),
+    }
+    .into()
+}
+
+pub fn query_error_handler(
+    err: actix_web::error::QueryPayloadError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    AppError::InvalidRequest {
+        location: "query",
+        message: err.to_string(),
+    }
+    .into()
+}
+
+pub fn path_error_handler(
+    err: actix_web::error::PathError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    AppError::InvalidRequest {
+        location: "path",
+        message: err.to_string(),
+    }
+    .into()
+}
+
+impl AppError {
+    /// Status and `detail` of the error body
+    fn detail(&self) -> (StatusCode, ErrorDetail) {
+        match self {
+            AppError::InvalidFields(errors) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorDetail::Fields(field_errors(errors, &["body".to_string()])),
+            ),
+            AppError::InvalidRequest { location, message } => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorDetail::Fields(vec![FieldError {
+                    loc: vec![location.to_string()],
+                    msg: message.clone(),
+                    error_type: format!("value_error.{}", location),
+                }]),
+            ),
+            _ => {
+                let (status, message) = self.message();
+                (status, ErrorDetail::Message(message))
+            }
+        }
+    }
+
+    fn message(&self) -> (StatusCode, String) {
+        match self {
             AppError::Database(ref e) => {
                 tracing::error!("Database error: {:?}", e);
                 (
@@ -141,27 +320,30 @@ impl ResponseError for AppError {
                 (StatusCode::GATEWAY_TIMEOUT, e.clone())
             }
             AppError::TooManyRequests(ref e) => (StatusCode::TOO_MANY_REQUESTS, e.clone()),
-        };
+            AppError::UpstreamUnavailable(ref e) => (StatusCode::SERVICE_UNAVAILABLE, e.clone()),
+            AppError::PayloadTooLarge(ref e) => (StatusCode::PAYLOAD_TOO_LARGE, e.clone()),
+            AppError::UnsupportedMediaType(ref e) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, e.clone())
+            }
+            AppError::ServiceUnavailable(ref e) => (StatusCode::SERVICE_UNAVAILABLE, e.clone()),
+            AppError::InvalidFields(_) | AppError::InvalidRequest { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, self.to_string())
+            }
+        }
+    }
+}
 
+impl ResponseError for AppError {
+    fn error_response(&self) -> HttpResponse {
+        let (status, detail) = self.detail();
         let body = ErrorResponse {
-            detail: error_message,
+            detail,
+            request_id: current_request_id(),
         };
 
-        // Build response with CORS headers to ensure they're always present
-        // even when errors occur in middleware before CORS middleware processes the response
+        // CORS headers come from the Cors middleware, which also adds them to errors
+        // raised by inner middleware before a handler runs
         let mut response_builder = HttpResponse::build(status);
-        response_builder
-            .insert_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"))
-            .insert_header((header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true"))
-            .insert_header((
-                header::ACCESS_CONTROL_ALLOW_METHODS,
-                "GET, POST, PUT, DELETE, PATCH, OPTIONS",
-            ))
-            .insert_header((
-                header::ACCESS_CONTROL_ALLOW_HEADERS,
-                "Content-Type, Authorization, Accept, Cookie",
-            ))
-            .insert_header((header::ACCESS_CONTROL_EXPOSE_HEADERS, "Set-Cookie"));
 
         // Clear auth cookies on authentication errors (matching Python backend behavior)
         if matches!(
@@ -171,10 +353,11 @@ impl ResponseError for AppError {
                 | AppError::Jwt(_)
                 | AppError::InvalidCredentials
         ) {
+            // Not `Secure`: browsers ignore `Secure` cookies sent over plain HTTP, and
+            // an expired cookie without it still clears the one set over HTTPS
             let mut token_cookie = Cookie::new("token", "");
             token_cookie.set_http_only(true);
-            token_cookie.set_same_site(SameSite::None);
-            token_cookie.set_secure(true);
+            token_cookie.set_same_site(SameSite::Lax);
             token_cookie.set_path("/");
             token_cookie.set_max_age(time::Duration::seconds(-1));
 
@@ -207,6 +390,12 @@ impl ResponseError for AppError {
             AppError::RedisPool(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
             AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::UpstreamUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::InvalidFields(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::InvalidRequest { .. } => StatusCode::UNPROCESSABLE_ENTITY,
         }
     }
 }
@@ -219,3 +408,277 @@ impl From<deadpool_redis::PoolError> for AppError {
         AppError::RedisPool(err.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use validator::Validate;
+
+    async fn render(error: AppError) -> (StatusCode, serde_json::Value) {
+        let resp = error.error_response();
+        let status = resp.status();
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[derive(Validate)]
+    struct SignupForm {
+        #[validate(email)]
+        email: String,
+        #[validate(length(min = 1, max = 8))]
+        name: String,
+        #[validate(nested)]
+        profile: Profile,
+    }
+
+    #[derive(Validate)]
+    struct Profile {
+        #[validate(length(min = 3))]
+        username: String,
+    }
+
+    #[actix_web::test]
+    async fn test_message_variants() {
+        let cases = vec![
+            (
+                AppError::Database(sqlx::Error::RowNotFound),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ),
+            (
+                AppError::Redis("down".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Redis error",
+            ),
+            (
+                AppError::Auth("Not authenticated".to_string()),
+                StatusCode::UNAUTHORIZED,
+                "Not authenticated",
+            ),
+            (
+                AppError::Validation("Invalid email".to_string()),
+                StatusCode::BAD_REQUEST,
+                "Invalid email",
+            ),
+            (
+                AppError::NotFound("Chat not found".to_string()),
+                StatusCode::NOT_FOUND,
+                "Chat not found",
+            ),
+            (
+                AppError::Unauthorized("Invalid token".to_string()),
+                StatusCode::UNAUTHORIZED,
+                "Invalid token",
+            ),
+            (
+                AppError::Forbidden("Admin access required".to_string()),
+                StatusCode::FORBIDDEN,
+                "Admin access required",
+            ),
+            (
+                AppError::BadRequest("Missing model".to_string()),
+                StatusCode::BAD_REQUEST,
+                "Missing model",
+            ),
+            (
+                AppError::InternalServerError("boom".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "boom",
+            ),
+            (
+                AppError::Jwt(jsonwebtoken::errors::ErrorKind::InvalidToken.into()),
+                StatusCode::UNAUTHORIZED,
+                "Invalid token",
+            ),
+            (
+                AppError::InvalidCredentials,
+                StatusCode::UNAUTHORIZED,
+                "Invalid credentials",
+            ),
+            (
+                AppError::UserAlreadyExists,
+                StatusCode::BAD_REQUEST,
+                "User already exists",
+            ),
+            (
+                AppError::Conflict("Name taken".to_string()),
+                StatusCode::CONFLICT,
+                "Name taken",
+            ),
+            (
+                AppError::Io(std::io::Error::other("disk full")),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "IO error",
+            ),
+            (
+                AppError::NotImplemented("Not implemented".to_string()),
+                StatusCode::NOT_IMPLEMENTED,
+                "Not implemented",
+            ),
+            (
+                AppError::ExternalServiceError("Upstream failed".to_string()),
+                StatusCode::BAD_GATEWAY,
+                "Upstream failed",
+            ),
+            (
+                AppError::Internal("boom".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "boom",
+            ),
+            (
+                AppError::RedisPool("timeout".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Redis pool error",
+            ),
+            (
+                AppError::Timeout("Upstream timed out".to_string()),
+                StatusCode::GATEWAY_TIMEOUT,
+                "Upstream timed out",
+            ),
+            (
+                AppError::TooManyRequests("Slow down".to_string()),
+                StatusCode::TOO_MANY_REQUESTS,
+                "Slow down",
+            ),
+            (
+                AppError::UpstreamUnavailable("Circuit open".to_string()),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Circuit open",
+            ),
+            (
+                AppError::PayloadTooLarge("File too large".to_string()),
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "File too large",
+            ),
+            (
+                AppError::UnsupportedMediaType("File type not allowed".to_string()),
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "File type not allowed",
+            ),
+            (
+                AppError::ServiceUnavailable("Server is shutting down".to_string()),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Server is shutting down",
+            ),
+        ];
+
+        for (error, status, detail) in cases {
+            assert_eq!(error.status_code(), status, "{:?}", error);
+            let (rendered_status, body) = render(error).await;
+            assert_eq!(rendered_status, status);
+            assert_eq!(body, json!({ "detail": detail }));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_validator_errors_use_fastapi_shape() {
+        let form = SignupForm {
+            email: "not-an-email".to_string(),
+            name: String::new(),
+            profile: Profile {
+                username: "ab".to_string(),
+            },
+        };
+        let error: AppError = form.validate().unwrap_err().into();
+        assert_eq!(error.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, body) = render(error).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body,
+            json!({
+                "detail": [
+                    {
+                        "loc": ["body", "email"],
+                        "msg": "value is not a valid email address",
+                        "type": "value_error.email"
+                    },
+                    {
+                        "loc": ["body", "name"],
+                        "msg": "ensure this value has between 1 and 8 characters",
+                        "type": "value_error.length"
+                    },
+                    {
+                        "loc": ["body", "profile", "username"],
+                        "msg": "ensure this value has at least 3 characters",
+                        "type": "value_error.length"
+                    }
+                ]
+            })
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_extractor_errors_use_fastapi_shape() {
+        let (status, body) = render(AppError::InvalidRequest {
+            location: "query",
+            message: "missing field `page`".to_string(),
+        })
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body,
+            json!({
+                "detail": [{
+                    "loc": ["query"],
+                    "msg": "missing field `page`",
+                    "type": "value_error.query"
+                }]
+            })
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_auth_errors_clear_token_cookie() {
+        let resp = AppError::Unauthorized("Invalid token".to_string()).error_response();
+        assert!(resp.headers().get(header::SET_COOKIE).is_some());
+
+        let resp = AppError::Forbidden("Admin access required".to_string()).error_response();
+        assert!(resp.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_errors_follow_the_cors_policy() {
+        use actix_web::dev::Service;
+        use actix_web::middleware::{from_fn, Next};
+        use actix_web::{body::BoxBody, dev::ServiceRequest, dev::ServiceResponse, test, web};
+
+        let app = test::init_service(
+            actix_web::App::new()
+                .wrap(from_fn(
+                    |_req: ServiceRequest, _next: Next<BoxBody>| async {
+                        Err::<ServiceResponse<BoxBody>, actix_web::Error>(
+                            AppError::Unauthorized("Not authenticated".to_string()).into(),
+                        )
+                    },
+                ))
+                .wrap(
+                    actix_cors::Cors::default()
+                        .allowed_origin("https://app.example.com")
+                        .supports_credentials(),
+                )
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::ORIGIN, "https://app.example.com"))
+            .to_request();
+        let resp = app.call(req).await.err().unwrap().error_response();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            resp.headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap(),
+            "https://app.example.com"
+        );
+        assert_eq!(
+            resp.headers()
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+    }
+}
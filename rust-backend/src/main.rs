@@ -10,21 +10,23 @@ mod services;
 mod socket;
 mod socketio;
 mod static_files;
+#[cfg(test)]
+mod test_utils;
 mod utils;
 mod websocket_chat;
 
 use actix_cors::Cors;
-use actix_files::Files;
 use actix_web::{
     http::header,
-    middleware::{Compress, Logger, NormalizePath},
+    middleware::{Compress, Condition, Logger, NormalizePath},
     web, App, HttpRequest, HttpResponse, HttpServer,
 };
 use std::net::SocketAddr;
 use tracing::{info, warn, Level};
-use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
-use crate::config::{Config, MutableConfig};
+use crate::config::{Config, Listener, MutableConfig, SharedConfig};
 use crate::db::Database;
 use crate::routes::create_routes;
 use crate::services::sandbox_executor::SandboxExecutorClient;
@@ -34,7 +36,7 @@ use std::sync::{Arc, RwLock};
 pub struct AppState {
     pub db: Database,
     pub config: MutableConfig,
-    pub redis: Option<deadpool_redis::Pool>,
+    pub redis: Option<utils::redis_health::RedisPool>,
     // Model cache: model_id -> model info (with urlIdx, etc.)
     pub models_cache: Arc<RwLock<std::collections::HashMap<String, serde_json::Value>>>,
     // Socket state for tracking sessions and users (Socket.IO-like functionality)
@@ -49,6 +51,14 @@ pub struct AppState {
     pub embedding_provider: Option<Arc<dyn retrieval::EmbeddingProvider>>,
     // Sandbox executor client for secure code execution
     pub sandbox_executor_client: Option<Arc<SandboxExecutorClient>>,
+    // Per-user rate limiter for type-ahead autocomplete requests
+    pub autocomplete_rate_limiter: Arc<socketio::RateLimiter>,
+    // Circuit breakers for the configured OpenAI endpoints, keyed by urlIdx
+    pub upstream_breakers: Arc<utils::upstream::UpstreamBreakers>,
+    // Request counters for route rate limits when Redis isn't used
+    pub request_rate_limits: Arc<middleware::rate_limit::MemoryRateLimitStore>,
+    // Running streaming completions, drained on shutdown
+    pub tasks: Arc<utils::tasks::TaskManager>,
 }
 
 #[actix_web::main]
@@ -61,15 +71,18 @@ async fn main() -> anyhow::Result<()> {
         .parse()
         .unwrap_or(Level::INFO);
 
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(log_level)
-        .with_target(false)
-        .with_thread_ids(true)
-        .with_file(true)
-        .with_line_number(true)
-        .finish();
-
-    tracing::subscriber::set_global_default(subscriber)?;
+    // Spans are also exported over OTLP when OTEL_EXPORTER_OTLP_ENDPOINT is set
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::filter::LevelFilter::from_level(log_level))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_thread_ids(true)
+                .with_file(true)
+                .with_line_number(true),
+        )
+        .with(utils::telemetry::otlp_layer()?)
+        .try_init()?;
 
     info!("Starting Open WebUI Rust Backend");
 
@@ -78,8 +91,12 @@ async fn main() -> anyhow::Result<()> {
     info!("Configuration loaded from environment");
 
     // Initialize database
-    let db = Database::new(&config.database_url).await?;
-    info!("Database connected");
+    let pool_settings = db::PoolSettings::from_config(&config);
+    let db = Database::connect(&config.database_url, &pool_settings).await?;
+    info!(
+        "Database connected (pool: {}-{} connections)",
+        pool_settings.min_connections, pool_settings.max_connections
+    );
 
     // Run migrations
     db.run_migrations().await?;
@@ -89,11 +106,42 @@ async fn main() -> anyhow::Result<()> {
     let config = services::ConfigService::load_from_db(&db, config).await?;
     info!("Configuration loaded and merged from database");
 
+    config.ensure_data_dirs().map_err(|e| {
+        anyhow::anyhow!("Failed to create data directory {}: {}", config.data_dir, e)
+    })?;
+    info!("📁 Data directory: {}", config.data_dir);
+
+    // Refuse to start with a CORS policy that would expose credentials to any site
+    config.validate_cors()?;
+    // ...or with conflicting listener settings (TLS/Unix socket/random port)
+    let listener = config.listener()?;
+    let cors_allowed_origins = config.cors_allowed_origins();
+    let cors_allow_credentials = config.cors_allow_credentials;
+    match &cors_allowed_origins {
+        Some(origins) => info!(
+            "🌐 CORS policy: origins [{}], credentials {}",
+            origins.join(", "),
+            if cors_allow_credentials {
+                "allowed"
+            } else {
+                "disabled"
+            }
+        ),
+        None => info!("🌐 CORS policy: any origin (*), credentials disabled"),
+    }
+
     // Initialize Redis if enabled
     let redis = if config.enable_redis {
         let redis_config = deadpool_redis::Config::from_url(&config.redis_url);
-        let pool = redis_config.create_pool(Some(deadpool_redis::Runtime::Tokio1))?;
-        info!("Redis connected");
+        let pool = utils::redis_health::RedisPool::new(
+            redis_config.create_pool(Some(deadpool_redis::Runtime::Tokio1))?,
+        );
+        // Redis-backed features fall back to local-only behaviour while this reports
+        // Redis as down, and resume once it answers again
+        if pool.check().await {
+            info!("Redis connected");
+        }
+        pool.spawn_health_check(std::time::Duration::from_secs(10));
         Some(pool)
     } else {
         None
@@ -113,18 +161,71 @@ async fn main() -> anyhow::Result<()> {
     let socketio_handler = if socketio_enabled {
         use crate::socketio::redis_adapter::RedisAdapter;
         use crate::socketio::{
-            EventHandler, PresenceConfig, PresenceManager, RateLimitConfig, RateLimiter,
-            RecoveryConfig, RecoveryManager, SocketIOManager, SocketIOMetrics, YDocManager,
+            EventHandler, HealthConfig, HealthMonitor, PresenceConfig, PresenceManager,
+            RateLimitConfig, RateLimiter, RecoveryConfig, RecoveryManager, SocketIOManager,
+            SocketIOMetrics, YDocManager,
+        };
+
+        // Bridge emits and room membership across replicas through Redis pub/sub.
+        // On with ENABLE_REDIS or WEBSOCKET_MANAGER=redis; SOCKETIO_REDIS_URL and
+        // WEBSOCKET_REDIS_URL override REDIS_URL.
+        let redis_adapter_url = std::env::var("SOCKETIO_REDIS_URL").ok().or_else(|| {
+            (config.enable_redis || config.websocket_manager == "redis").then(|| {
+                config
+                    .websocket_redis_url
+                    .clone()
+                    .unwrap_or_else(|| config.redis_url.clone())
+            })
+        });
+        let redis_adapter = match redis_adapter_url {
+            Some(redis_url) => {
+                let server_id = uuid::Uuid::new_v4().to_string();
+                match RedisAdapter::new(&redis_url, server_id.clone()) {
+                    Ok(adapter) => {
+                        let adapter = Arc::new(adapter);
+                        adapter.spawn_heartbeat();
+                        info!(
+                            "✅ Redis adapter initialized for horizontal scaling (server: {})",
+                            server_id
+                        );
+                        Some(adapter)
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to initialize Redis adapter: {}", e);
+                        None
+                    }
+                }
+            }
+            None => None,
         };
 
-        let manager = SocketIOManager::new();
+        let mut manager = SocketIOManager::new().with_ping_config(
+            config.websocket_server_ping_interval * 1000,
+            config.websocket_server_ping_timeout * 1000,
+        );
+        if let Some(adapter) = &redis_adapter {
+            manager = manager.with_redis_adapter(adapter.clone());
+        }
         let auth_endpoint = format!("http://{}:{}", config.host, config.port);
 
         // Initialize metrics
         let metrics = SocketIOMetrics::new();
 
-        // Initialize rate limiter
-        let rate_limit_config = RateLimitConfig::default();
+        // Track heartbeats with the same timing the Engine.IO handshake advertises
+        let health_monitor = HealthMonitor::new(HealthConfig {
+            heartbeat_interval: std::time::Duration::from_millis(manager.ping_interval()),
+            ping_timeout: std::time::Duration::from_millis(manager.ping_timeout()),
+            ..HealthConfig::default()
+        });
+
+        // Initialize rate limiter for incoming events
+        let mut rate_limit_config = RateLimitConfig {
+            max_events: config.websocket_event_rate_limit,
+            ..RateLimitConfig::default()
+        };
+        rate_limit_config
+            .event_limits
+            .extend(config.websocket_event_rate_limits.clone());
         let rate_limiter = Arc::new(RateLimiter::new(rate_limit_config));
 
         // Initialize presence manager
@@ -132,51 +233,18 @@ async fn main() -> anyhow::Result<()> {
         let presence_manager = Arc::new(PresenceManager::new(presence_config));
 
         // Initialize recovery manager with Redis if available
-        let recovery_config = RecoveryConfig::default();
+        let recovery_config = RecoveryConfig {
+            recovery_window: std::time::Duration::from_secs(config.websocket_recovery_window),
+            max_replay_bytes: config.websocket_recovery_max_bytes,
+            ..RecoveryConfig::default()
+        };
         let recovery_manager = Arc::new(RecoveryManager::new(redis.clone(), recovery_config));
 
         // Initialize YDoc manager with Redis if available
         let ydoc_manager = YDocManager::new(redis.clone());
 
-        // Initialize Redis adapter if SOCKETIO_REDIS_URL is set
-        let redis_adapter = if let Ok(redis_url) = std::env::var("SOCKETIO_REDIS_URL") {
-            let server_id = uuid::Uuid::new_v4().to_string();
-            match RedisAdapter::new(&redis_url, server_id.clone()) {
-                Ok(adapter) => {
-                    let adapter_arc = Arc::new(adapter);
-
-                    // Spawn Redis subscription handler
-                    let adapter_sub = adapter_arc.clone();
-                    tokio::spawn(async move {
-                        // TODO: Handle incoming Redis messages and route to local sessions
-                        if let Err(e) = adapter_sub
-                            .subscribe(move |msg| {
-                                tracing::debug!("Received Redis message: {:?}", msg);
-                                // Route message to local connections
-                            })
-                            .await
-                        {
-                            tracing::error!("Redis subscription error: {:?}", e);
-                        }
-                    });
-
-                    info!(
-                        "✅ Redis adapter initialized for horizontal scaling (server: {})",
-                        server_id
-                    );
-                    Some(adapter_arc)
-                }
-                Err(e) => {
-                    tracing::error!("Failed to initialize Redis adapter: {}", e);
-                    None
-                }
-            }
-        } else {
-            None
-        };
-
         let handler = EventHandler::new(
-            manager.clone(),
+            manager,
             auth_endpoint,
             ydoc_manager,
             redis_adapter,
@@ -184,16 +252,32 @@ async fn main() -> anyhow::Result<()> {
             rate_limiter.clone(),
             presence_manager.clone(),
             recovery_manager.clone(),
+            health_monitor.clone(),
             db.clone(),
         );
+        handler.start_redis_subscription();
 
         // Spawn background cleanup tasks
-        let manager_cleanup = manager.clone();
+        let session_cleanup = handler.clone();
+        let stale_session_timeout = handler.manager().stale_session_timeout();
         tokio::spawn(async move {
             loop {
-                tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
-                // Clean up sessions that haven't pinged in 60 seconds (3x ping interval + timeout)
-                manager_cleanup.cleanup_stale_sessions(60).await;
+                tokio::time::sleep(tokio::time::Duration::from_secs(
+                    stale_session_timeout as u64,
+                ))
+                .await;
+                // Clean up sessions that missed two pings plus the answer timeout
+                session_cleanup.cleanup_stale_sessions(stale_session_timeout).await;
+            }
+        });
+
+        // Disconnect sessions that stop answering pings before the stale cleanup would
+        let heartbeat_check = handler.clone();
+        let heartbeat_interval = health_monitor.config().heartbeat_interval;
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(heartbeat_interval).await;
+                heartbeat_check.disconnect_unresponsive_sessions().await;
             }
         });
 
@@ -212,7 +296,35 @@ async fn main() -> anyhow::Result<()> {
             loop {
                 tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
                 presence_cleanup.update_away_statuses().await;
-                presence_cleanup.cleanup_typing_indicators().await;
+            }
+        });
+
+        // Expire typing indicators shortly after clients stop sending updates
+        let typing_cleanup = handler.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                typing_cleanup.expire_typing_indicators().await;
+            }
+        });
+
+        // Persist collaborative documents and evict ones nobody has edited for a minute
+        let ydoc_maintenance = handler.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                ydoc_maintenance
+                    .evict_idle_ydoc_documents(std::time::Duration::from_secs(60))
+                    .await;
+            }
+        });
+
+        // Save users' last-seen times in one batch rather than on every ping
+        let last_seen_flush = handler.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
+                last_seen_flush.flush_last_seen().await;
             }
         });
 
@@ -256,7 +368,8 @@ async fn main() -> anyhow::Result<()> {
 
     // Create app state
     // Create shared HTTP client with connection pooling and optimized settings
-    let http_client = reqwest::Client::builder()
+    utils::http_client::check_tls(&config)?;
+    let http_client = utils::http_client::builder(&config)?
         .pool_max_idle_per_host(10) // Reuse connections
         .tcp_nodelay(true) // Disable Nagle's algorithm for real-time streaming
         .timeout(std::time::Duration::from_secs(300)) // 5 min default timeout
@@ -265,6 +378,9 @@ async fn main() -> anyhow::Result<()> {
         .build()?;
 
     tracing::info!("🌐 HTTP client initialized with connection pooling");
+    if utils::http_client::proxy_enabled(&config) {
+        tracing::info!("🌐 Upstream requests go through the outbound proxy");
+    }
 
     // Initialize vector database if enabled
     let vector_db_enabled = std::env::var("ENABLE_RAG")
@@ -293,6 +409,9 @@ async fn main() -> anyhow::Result<()> {
     };
 
     // Initialize embedding provider if enabled
+    let embedding_client = utils::http_client::builder(&config)?
+        .timeout(std::time::Duration::from_secs(120))
+        .build()?;
     let embedding_provider = if vector_db_enabled {
         let engine = config.rag_embedding_engine.to_lowercase();
 
@@ -309,7 +428,9 @@ async fn main() -> anyhow::Result<()> {
                     None
                 };
 
-                match retrieval::embeddings::OpenAIEmbeddings::new(api_key, model) {
+                match retrieval::embeddings::OpenAIEmbeddings::new(api_key, model)
+                    .map(|provider| provider.with_http_client(embedding_client.clone()))
+                {
                     Ok(provider) => {
                         info!("✅ Embedding provider initialized (OpenAI)");
                         Some(Arc::new(provider) as Arc<dyn retrieval::EmbeddingProvider>)
@@ -331,6 +452,7 @@ async fn main() -> anyhow::Result<()> {
                 };
 
                 match retrieval::embeddings::KnoxChatEmbeddings::new(api_key, base_url, model_name)
+                    .map(|provider| provider.with_http_client(embedding_client.clone()))
                 {
                     Ok(provider) => {
                         info!("✅ Embedding provider initialized (Knox Chat)");
@@ -376,7 +498,9 @@ async fn main() -> anyhow::Result<()> {
                                     Some(knox_api_key),
                                     None,
                                     Some("voyage-3.5".to_string()),
-                                ) {
+                                )
+                                .map(|provider| provider.with_http_client(embedding_client.clone()))
+                                {
                                     Ok(provider) => {
                                         info!("✅ Knox Chat fallback initialized");
                                         Some(Arc::new(provider)
@@ -394,7 +518,9 @@ async fn main() -> anyhow::Result<()> {
                                 match retrieval::embeddings::OpenAIEmbeddings::new(
                                     Some(config.rag_openai_api_key.clone()),
                                     Some("text-embedding-3-small".to_string()),
-                                ) {
+                                )
+                                .map(|provider| provider.with_http_client(embedding_client.clone()))
+                                {
                                     Ok(provider) => {
                                         info!("✅ OpenAI fallback initialized");
                                         Some(Arc::new(provider)
@@ -427,7 +553,9 @@ async fn main() -> anyhow::Result<()> {
                             Some(knox_api_key),
                             None,
                             Some("voyage-3.5".to_string()),
-                        ) {
+                        )
+                        .map(|provider| provider.with_http_client(embedding_client.clone()))
+                        {
                             Ok(provider) => {
                                 info!("✅ Knox Chat fallback initialized");
                                 Some(Arc::new(provider) as Arc<dyn retrieval::EmbeddingProvider>)
@@ -444,7 +572,9 @@ async fn main() -> anyhow::Result<()> {
                         match retrieval::embeddings::OpenAIEmbeddings::new(
                             Some(config.rag_openai_api_key.clone()),
                             Some("text-embedding-3-small".to_string()),
-                        ) {
+                        )
+                        .map(|provider| provider.with_http_client(embedding_client.clone()))
+                        {
                             Ok(provider) => {
                                 info!("✅ OpenAI fallback initialized");
                                 Some(Arc::new(provider) as Arc<dyn retrieval::EmbeddingProvider>)
@@ -484,9 +614,78 @@ async fn main() -> anyhow::Result<()> {
         None
     };
 
+    // Initialize autocomplete rate limiter (requests per user per minute). The limit
+    // itself is read from the config on each request, so it can change at runtime.
+    let autocomplete_rate_limiter =
+        Arc::new(socketio::RateLimiter::new(socketio::RateLimitConfig {
+            max_events: config.autocomplete_generation_rate_limit,
+            window_duration: std::time::Duration::from_secs(60),
+            max_queue_size: 0,
+            burst_allowance: 0,
+            ..socketio::RateLimitConfig::default()
+        }));
+    let autocomplete_rate_limiter_cleanup = autocomplete_rate_limiter.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(600)).await; // Every 10 minutes
+            autocomplete_rate_limiter_cleanup
+                .cleanup_old_buckets()
+                .await;
+        }
+    });
+
+    // Audit log writer, plus a daily sweep of entries past the retention window
+    let enable_audit_log = config.enable_audit_log;
+    let audit = middleware::Audit::start(
+        db.clone(),
+        middleware::audit::AuditFilter::from_config(&config),
+    );
+    if enable_audit_log && config.audit_log_retention_days > 0 {
+        let retention_days = config.audit_log_retention_days;
+        let audit_db = db.clone();
+        tokio::spawn(async move {
+            loop {
+                let cutoff = chrono::Utc::now().timestamp() - retention_days * 24 * 60 * 60;
+                match services::audit::AuditService::new(&audit_db)
+                    .delete_entries_before(cutoff)
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(deleted) => info!("Deleted {} expired audit log entries", deleted),
+                    Err(e) => warn!("Failed to delete expired audit log entries: {}", e),
+                }
+                tokio::time::sleep(tokio::time::Duration::from_secs(24 * 60 * 60)).await;
+            }
+        });
+    }
+
+    let upstream_breakers = Arc::new(utils::upstream::UpstreamBreakers::new(
+        socketio::CircuitBreakerConfig {
+            failure_threshold: config.upstream_circuit_breaker_threshold,
+            timeout: std::time::Duration::from_secs(config.upstream_circuit_breaker_timeout),
+            ..Default::default()
+        },
+    ));
+
+    let request_rate_limits = Arc::new(middleware::rate_limit::MemoryRateLimitStore::new());
+    let request_rate_limits_cleanup = request_rate_limits.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(600)).await;
+            let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+            request_rate_limits_cleanup.cleanup(now_ms);
+        }
+    });
+
+    let tasks = Arc::new(utils::tasks::TaskManager::new(
+        redis.clone(),
+        config.enable_redis.then(|| config.redis_url.clone()),
+        "open-webui".to_string(),
+    ));
+
     let state = web::Data::new(AppState {
         db: db.clone(),
-        config: Arc::new(RwLock::new(config.clone())),
+        config: Arc::new(SharedConfig::new(config.clone())),
         redis: redis.clone(),
         models_cache: Arc::new(RwLock::new(std::collections::HashMap::new())),
         socket_state,
@@ -495,16 +694,23 @@ async fn main() -> anyhow::Result<()> {
         vector_db,
         embedding_provider,
         sandbox_executor_client,
+        autocomplete_rate_limiter,
+        upstream_breakers,
+        request_rate_limits,
+        tasks,
     });
 
     // Start server
     let addr = SocketAddr::from((config.host.parse::<std::net::IpAddr>()?, config.port));
-    let cors_allow_origin = config.cors_allow_origin.clone();
     let enable_random_port = config.enable_random_port;
+    let enable_json_access_log = config.enable_json_access_log;
+    let shutdown_state = state.clone();
+    let shutdown_drain_timeout = std::time::Duration::from_secs(config.shutdown_drain_timeout);
     
     // Check if static directory exists
     let static_dir = config.static_dir.clone();
     let static_dir_exists = std::path::Path::new(&static_dir).exists();
+    let static_cache_max_age = config.static_cache_max_age;
     
     if static_dir_exists {
         info!("📁 Using external static files directory: {}", static_dir);
@@ -512,56 +718,90 @@ async fn main() -> anyhow::Result<()> {
         info!("📁 External static directory not found ({}), using embedded static files", static_dir);
     }
 
-    if enable_random_port {
-        info!("🚀 Starting server with random port on host: {}", config.host);
-    } else {
-        info!("🚀 Server running at http://{}", addr);
+    let scheme = match listener {
+        Listener::Tls { .. } => "https",
+        _ => "http",
+    };
+    match &listener {
+        Listener::Unix(path) => info!("🚀 Server running at unix:{}", path),
+        _ if enable_random_port => {
+            info!("🚀 Starting server with random port on host: {}", config.host)
+        }
+        _ => info!("🚀 Server running at {}://{}", scheme, addr),
     }
 
     let server = HttpServer::new(move || {
         // Create CORS middleware
-        // NOTE: When credentials are needed (cookies/auth), we cannot use allow_any_origin()
-        // Instead, we need to allow specific origins or use allowed_origin_fn to dynamically allow
-        let cors = if cors_allow_origin == "*" {
-            // When "*" is specified, allow any origin dynamically while supporting credentials
-            Cors::default()
-                .allowed_origin_fn(|_origin, _req_head| {
-                    // Allow all origins when * is configured
-                    true
-                })
+        // NOTE: Credentials are never combined with a wildcard origin (checked at startup)
+        let cors = match &cors_allowed_origins {
+            None => Cors::default()
+                .allow_any_origin()
+                .send_wildcard()
                 .allow_any_method()
                 .allow_any_header()
-                .expose_headers(vec![header::SET_COOKIE])
-                .supports_credentials()
-                .max_age(3600)
-        } else {
-            let origins: Vec<&str> = cors_allow_origin.split(',').map(|s| s.trim()).collect();
-            let mut cors = Cors::default();
-            for origin in origins {
-                cors = cors.allowed_origin(origin);
+                .max_age(3600),
+            Some(origins) => {
+                let mut cors = Cors::default();
+                for origin in origins {
+                    cors = cors.allowed_origin(origin);
+                }
+                cors = cors
+                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "PATCH", "OPTIONS"])
+                    .allowed_headers(vec![
+                        header::CONTENT_TYPE,
+                        header::AUTHORIZATION,
+                        header::ACCEPT,
+                        header::COOKIE,
+                    ])
+                    .expose_headers(vec![header::SET_COOKIE])
+                    .max_age(3600);
+                if cors_allow_credentials {
+                    cors = cors.supports_credentials();
+                }
+                cors
             }
-            cors.allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "PATCH", "OPTIONS"])
-                .allowed_headers(vec![
-                    header::CONTENT_TYPE,
-                    header::AUTHORIZATION,
-                    header::ACCEPT,
-                    header::COOKIE,
-                ])
-                .expose_headers(vec![header::SET_COOKIE])
-                .supports_credentials()
-                .max_age(3600)
         };
 
         App::new()
             .app_data(state.clone())
+            // Extractor failures answer with FastAPI-style 422 bodies. Body sizes are
+            // capped per route group by BodyLimit, from the runtime config, so the
+            // extractors' own (much lower) default limits are lifted.
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(usize::MAX)
+                    .error_handler(error::json_error_handler),
+            )
+            .app_data(web::PayloadConfig::new(usize::MAX))
+            .app_data(web::QueryConfig::default().error_handler(error::query_error_handler))
+            .app_data(web::PathConfig::default().error_handler(error::path_error_handler))
             .wrap(cors)
             .wrap(Compress::default())
-            .wrap(Logger::default())
+            // Structured JSON access logs replace the default text logger when enabled
+            .wrap(Condition::new(
+                !enable_json_access_log,
+                // Logger::default()'s format, with the client address from RealIp
+                // rather than forwarding headers taken on trust
+                Logger::new(r#"%{client_ip}xi "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+                    .custom_request_replace("client_ip", |req| {
+                        middleware::RealIp::of(req.request()).to_string()
+                    }),
+            ))
+            .wrap(Condition::new(enable_json_access_log, middleware::AccessLog))
+            .wrap(Condition::new(enable_audit_log, audit.clone()))
+            .wrap(middleware::BodyLimit)
+            // Inside NormalizePath, so trailing slashes don't split route series
+            .wrap(middleware::HttpMetrics)
             .wrap(NormalizePath::trim())
             .wrap(middleware::SecurityHeaders) // Security headers middleware
+            // Outermost, so every log line and error carries the request id
+            .wrap(middleware::RequestIdMiddleware)
             // Health checks
             .route("/health", web::get().to(health_check))
             .route("/health/db", web::get().to(health_check_db))
+            .route("/health/ready", web::get().to(health_ready))
+            // Prometheus metrics
+            .route("/metrics", web::get().to(get_metrics))
             // Config and version
             .route("/api/config", web::get().to(get_app_config))
             .route("/api/version", web::get().to(get_app_version))
@@ -575,16 +815,40 @@ async fn main() -> anyhow::Result<()> {
             // API routes (nested after specific routes to avoid conflicts)
             .service(web::scope("/api/v1").configure(create_routes))
             // OpenAI compatible API
-            .service(web::scope("/openai").configure(routes::openai::create_routes))
+            .service(
+                web::scope("/openai")
+                    .wrap(middleware::OpenAIErrors)
+                    .configure(routes::openai::create_routes),
+            )
+            // OpenAI-compatible API for external clients, authenticated with API keys
+            .service(
+                web::scope("/v1")
+                    .wrap(middleware::ApiKeyMiddleware)
+                    .wrap(middleware::OpenAIErrors)
+                    .route("/models", web::get().to(routes::openai::list_models_v1))
+                    .service(
+                        web::resource("/chat/completions")
+                            .wrap(middleware::rate_limit::RateLimit::new(
+                                middleware::rate_limit::RouteGroup::Chat,
+                            ))
+                            .route(web::post().to(routes::openai::chat_completions_v1)),
+                    )
+                    .route("/embeddings", web::post().to(embeddings)),
+            )
             // Chat endpoints (legacy routes without /v1 prefix)
             .service(
                 web::resource("/api/chat/completions")
+                    .wrap(middleware::rate_limit::RateLimit::new(
+                        middleware::rate_limit::RouteGroup::Chat,
+                    ))
                     .wrap(middleware::AuthMiddleware)
+                    .wrap(middleware::OpenAIErrors)
                     .route(web::post().to(chat_completions)),
             )
             .service(
                 web::resource("/api/chat/completed")
                     .wrap(middleware::AuthMiddleware)
+                    .wrap(middleware::OpenAIErrors)
                     .route(web::post().to(chat_completed)),
             )
             // WebSocket endpoint for real-time chat streaming
@@ -600,10 +864,18 @@ async fn main() -> anyhow::Result<()> {
                     .route(web::post().to(chat_action)),
             )
             // Embeddings endpoint (legacy route without /v1 prefix)
-            .route("/api/embeddings", web::post().to(embeddings))
+            .service(
+                web::resource("/api/embeddings")
+                    .wrap(middleware::OpenAIErrors)
+                    .route(web::post().to(embeddings)),
+            )
             // Task management
             .route("/api/tasks", web::get().to(list_tasks))
-            .route("/api/tasks/stop/{task_id}", web::post().to(stop_task))
+            .service(
+                web::resource("/api/tasks/stop/{task_id}")
+                    .wrap(middleware::AuthMiddleware)
+                    .route(web::post().to(stop_task)),
+            )
             .route(
                 "/api/tasks/chat/{chat_id}",
                 web::get().to(list_tasks_by_chat),
@@ -613,19 +885,23 @@ async fn main() -> anyhow::Result<()> {
             .route("/api/webhook", web::get().to(get_webhook))
             .route("/api/webhook", web::post().to(update_webhook))
             // OAuth integration endpoints (for MCP and other tools)
-            .route(
-                "/oauth/clients/{client_id}/authorize",
-                web::get().to(oauth_client_authorize),
+            .service(
+                web::resource("/oauth/clients/{client_id}/authorize")
+                    .wrap(middleware::AuthMiddleware)
+                    .route(web::get().to(oauth_client_authorize)),
             )
-            .route(
-                "/oauth/clients/{client_id}/callback",
-                web::get().to(oauth_client_callback),
+            .service(
+                web::resource("/oauth/clients/{client_id}/callback")
+                    .wrap(middleware::AuthMiddleware)
+                    .route(web::get().to(oauth_client_callback)),
             )
             // PWA manifest and opensearch
             .route("/manifest.json", web::get().to(get_manifest))
             .route("/opensearch.xml", web::get().to(get_opensearch))
             // Favicon
             .route("/favicon.png", web::get().to(serve_favicon))
+            // Branding images uploaded by admins (before the /static directory service)
+            .route("/static/custom/{asset}", web::get().to(serve_branding_image))
             // Cache file serving
             .route("/cache/{path:.*}", web::get().to(serve_cache_file))
             // Serve default user avatar at root (for backward compatibility)
@@ -636,7 +912,11 @@ async fn main() -> anyhow::Result<()> {
                 move |cfg| {
                     if static_dir_exists {
                         // Use external static directory if it exists
-                        cfg.service(Files::new("/static", static_dir.clone()));
+                        cfg.service(static_files::files_service(
+                            "/static",
+                            &static_dir,
+                            static_cache_max_age,
+                        ));
                     }
                 }
             })
@@ -649,26 +929,55 @@ async fn main() -> anyhow::Result<()> {
         std::time::Duration::from_secs(75),
     ))
     // CRITICAL: Set client timeout high for long-running streams
-    .client_request_timeout(std::time::Duration::from_secs(300));
-
-    let server = server.bind(addr)?;
+    .client_request_timeout(std::time::Duration::from_secs(300))
+    // Signals are handled by graceful_shutdown, which drains streams first; by the
+    // time the server stops, remaining connections only get a short grace period
+    .disable_signals()
+    .shutdown_timeout(5);
+
+    let server = match &listener {
+        Listener::Tcp => server.bind(addr)?,
+        Listener::Tls { cert_file, key_file } => {
+            // SIGHUP re-reads both files, so certificate renewals need no restart
+            let cert = Arc::new(utils::tls::ReloadableCert::load(cert_file, key_file)?);
+            utils::tls::reload_on_sighup(cert.clone());
+            server.bind_rustls_0_23(addr, utils::tls::server_config(cert)?)?
+        }
+        #[cfg(unix)]
+        Listener::Unix(path) => {
+            // A socket left behind by an unclean exit would make the bind fail
+            let _ = std::fs::remove_file(path);
+            server.bind_uds(path)?
+        }
+        #[cfg(not(unix))]
+        Listener::Unix(_) => {
+            anyhow::bail!("UNIX_SOCKET_PATH is only supported on Unix")
+        }
+    };
     
     // If random port is enabled, get the actual assigned port
     if enable_random_port {
         let addrs = server.addrs();
         let actual_addr = addrs.first()
             .ok_or_else(|| anyhow::anyhow!("Failed to get server address"))?;
-        info!("🚀 Server running at http://{}", actual_addr);
+        info!("🚀 Server running at {}://{}", scheme, actual_addr);
     }
     
-    server.run().await?;
+    let server = server.run();
+    tokio::spawn(utils::shutdown::graceful_shutdown(
+        shutdown_state,
+        server.handle(),
+        shutdown_drain_timeout,
+    ));
+    server.await?;
+    utils::telemetry::shutdown();
 
     Ok(())
 }
 
 // Serve default user avatar
 async fn serve_user_avatar(state: web::Data<AppState>) -> Result<HttpResponse, crate::error::AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let static_dir = &config.static_dir;
     let user_avatar_path = std::path::Path::new(static_dir).join("user.png");
     
@@ -694,7 +1003,18 @@ async fn serve_user_avatar(state: web::Data<AppState>) -> Result<HttpResponse, c
 
 // Serve favicon
 async fn serve_favicon(state: web::Data<AppState>) -> Result<HttpResponse, crate::error::AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
+
+    // Favicon uploaded by an admin takes precedence
+    if let Some((image_data, content_type)) =
+        utils::branding::load_custom_image(&config, utils::branding::BrandingAsset::Favicon)
+    {
+        return Ok(HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header((actix_web::http::header::CACHE_CONTROL, "no-cache"))
+            .body(image_data));
+    }
+
     let static_dir = &config.static_dir;
     let favicon_path = std::path::Path::new(static_dir).join("favicon.png");
     
@@ -718,6 +1038,25 @@ async fn serve_favicon(state: web::Data<AppState>) -> Result<HttpResponse, crate
     Err(crate::error::AppError::NotFound("Favicon not found".to_string()))
 }
 
+// Uploaded branding image, or the embedded default
+async fn serve_branding_image(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, crate::error::AppError> {
+    let asset = utils::branding::BrandingAsset::parse(&path).ok_or_else(|| {
+        crate::error::AppError::NotFound("Branding image not found".to_string())
+    })?;
+    let config = state.config.snapshot();
+    match utils::branding::load_image(&config, asset) {
+        Some((image_data, content_type)) => Ok(HttpResponse::Ok()
+            .content_type(content_type)
+            // Revalidate so a new upload shows up without a hard refresh
+            .insert_header((actix_web::http::header::CACHE_CONTROL, "no-cache"))
+            .body(image_data)),
+        None => Err(crate::error::AppError::NotFound("Branding image not found".to_string())),
+    }
+}
+
 // Health check endpoints
 async fn health_check() -> HttpResponse {
     HttpResponse::Ok().json(serde_json::json!({ "status": true }))
@@ -734,11 +1073,107 @@ async fn health_check_db(
     Ok(HttpResponse::Ok().json(serde_json::json!({ "status": true })))
 }
 
+// Readiness: ready while the database answers; the other components are reported so
+// degraded dependencies (e.g. an OpenAI endpoint with an open circuit) are visible
+async fn health_ready(state: web::Data<AppState>) -> HttpResponse {
+    let database_ok = sqlx::query("SELECT 1")
+        .execute(state.db.pool())
+        .await
+        .is_ok();
+
+    let endpoints = state.upstream_breakers.stats().await;
+    let openai_status = if endpoints
+        .values()
+        .any(|s| s.state == socketio::circuit_breaker::CircuitState::Open)
+    {
+        "degraded"
+    } else {
+        "ok"
+    };
+
+    // Redis is optional: an outage degrades to local-only behaviour, so it is
+    // reported but doesn't make the instance unready
+    let redis = match &state.redis {
+        Some(pool) => serde_json::to_value(pool.health().status()).unwrap_or_default(),
+        None => serde_json::json!({ "status": "disabled" }),
+    };
+    let redis_adapter = match state
+        .socketio_handler
+        .as_ref()
+        .and_then(|handler| handler.redis_adapter())
+    {
+        Some(adapter) => serde_json::to_value(adapter.health().status()).unwrap_or_default(),
+        None => serde_json::json!({ "status": "disabled" }),
+    };
+
+    let body = serde_json::json!({
+        "status": database_ok,
+        "components": {
+            "database": {
+                "status": if database_ok { "ok" } else { "unavailable" },
+                "pool": state.db.pool_stats(),
+            },
+            "openai": { "status": openai_status, "endpoints": endpoints },
+            "redis": redis,
+            "socketio_redis_adapter": redis_adapter,
+        }
+    });
+
+    if database_ok {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+// Prometheus scrape endpoint, readable with METRICS_BEARER_TOKEN or an admin session
+async fn get_metrics(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, crate::error::AppError> {
+    use sha2::{Digest, Sha256};
+
+    let expected_token = state.config.snapshot().metrics_bearer_token.clone();
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer "));
+    // Compare digests so the check doesn't leak the token's prefix through timing
+    let token_ok = match (&expected_token, bearer) {
+        (Some(expected), Some(given)) => {
+            Sha256::digest(expected.as_bytes()) == Sha256::digest(given.as_bytes())
+        }
+        _ => false,
+    };
+    if !token_ok {
+        let user = middleware::authenticate(&state, &req).await?;
+        if user.role != "admin" {
+            return Err(crate::error::AppError::Forbidden(
+                "Admin access required".to_string(),
+            ));
+        }
+    }
+
+    let mut body = utils::metrics::metrics().render();
+    body.push_str(&utils::metrics::render_pool_stats(state.db.pool()));
+    if let Some(handler) = &state.socketio_handler {
+        let exporter = socketio::PrometheusExporter::new(handler.metrics().clone());
+        if let Ok(socketio_metrics) = exporter.export().await {
+            body.push_str(&socketio_metrics);
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(body))
+}
+
 async fn get_app_config(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
     use serde_json::json;
 
-    // Get read lock on config
-    let config = state.config.read().unwrap();
+    // Snapshot of the current config
+    let config = state.config.snapshot();
 
     // Try to get user from token (in Authorization header or cookie)
     let token = req
@@ -810,6 +1245,8 @@ async fn get_app_config(state: web::Data<AppState>, req: HttpRequest) -> HttpRes
             json!(config.enable_autocomplete_generation);
         response["features"]["enable_community_sharing"] = json!(config.enable_community_sharing);
         response["features"]["enable_message_rating"] = json!(config.enable_message_rating);
+        response["features"]["enable_evaluation_arena_models"] =
+            json!(config.enable_evaluation_arena_models);
         response["features"]["enable_user_webhooks"] = json!(config.enable_user_webhooks);
         response["features"]["enable_admin_export"] = json!(config.enable_admin_export);
         response["features"]["enable_admin_chat_access"] = json!(config.enable_admin_chat_access);
@@ -825,6 +1262,7 @@ async fn get_app_config(state: web::Data<AppState>, req: HttpRequest) -> HttpRes
             json!(&config.default_models)
         };
         response["default_prompt_suggestions"] = config.default_prompt_suggestions.clone();
+        response["banners"] = config.banners.clone();
         response["user_count"] = json!(user_count);
 
         response["code"] = json!({
@@ -843,19 +1281,34 @@ async fn get_app_config(state: web::Data<AppState>, req: HttpRequest) -> HttpRes
         });
 
         response["file"] = json!({
-            "max_size": 10485760, // 10MB default
-            "max_count": 10,
+            "max_size": config.file_max_size,
+            "max_count": config.file_max_count,
             "image_compression": {
-                "width": 1024,
-                "height": 1024
+                "width": config.file_image_compression_width,
+                "height": config.file_image_compression_height
             }
         });
 
+        // Groups can be limited to temporary chats; admins never are
+        let (temporary, temporary_enforced) = match &user {
+            Some(user) => {
+                let permissions = utils::permissions::get_user_permissions(
+                    &state.db,
+                    &config.user_permissions,
+                    &user.id,
+                )
+                .await
+                .unwrap_or_default();
+                utils::permissions::temporary_chat_access(&permissions, &user.role)
+            }
+            None => (true, false),
+        };
         response["permissions"] = json!({
             "chat": {
                 "deletion": true,
                 "edit": true,
-                "temporary": true
+                "temporary": temporary,
+                "temporary_enforced": temporary_enforced
             }
         });
 
@@ -914,7 +1367,7 @@ async fn get_models(
     use serde_json::json;
 
     // Get config for model service
-    let config = state.config.read().unwrap().clone();
+    let config = state.config.snapshot().as_ref().clone();
     let model_service = crate::services::models::ModelService::new(config.clone());
 
     // Fetch all models
@@ -922,11 +1375,26 @@ async fn get_models(
         Ok(mut models) => {
             // Apply user-based filtering if authenticated
             if let Some(user) = auth_user {
-                models =
-                    model_service.filter_models_by_access(models, &user.user.id, &user.user.role);
+                let user_group_ids: std::collections::HashSet<String> =
+                    crate::services::group::GroupService::new(&state.db)
+                        .get_groups_by_member_id(&user.user.id)
+                        .await
+                        .map(|groups| groups.into_iter().map(|g| g.id).collect())
+                        .unwrap_or_default();
+                models = model_service.filter_models_by_access(
+                    models,
+                    &user.user.id,
+                    &user.user.role,
+                    &user_group_ids,
+                );
             } else {
                 // For unauthenticated users, only show public models
-                models = model_service.filter_models_by_access(models, "", "guest");
+                models = model_service.filter_models_by_access(
+                    models,
+                    "",
+                    "guest",
+                    &std::collections::HashSet::new(),
+                );
             }
 
             // Apply model ordering if configured
@@ -993,7 +1461,7 @@ async fn get_base_models(state: web::Data<AppState>) -> HttpResponse {
     use serde_json::json;
 
     // Get config for model service
-    let config = state.config.read().unwrap().clone();
+    let config = state.config.snapshot().as_ref().clone();
     let model_service = crate::services::models::ModelService::new(config);
 
     // Fetch base models (no filtering or customization)
@@ -1037,8 +1505,39 @@ fn configure_socketio_routes(cfg: &mut web::ServiceConfig) {
         .route("/ws/socket.io", web::get().to(socketio_connection_get))
         .route("/ws/socket.io", web::post().to(socketio_connection_post))
         // Other endpoints
-        .route("/api/socketio/emit", web::post().to(socketio_native_emit))
-        .route("/api/socketio/health", web::get().to(socketio_health))
+        .service(
+            web::resource("/api/socketio/emit").route(web::post().to(socketio_native_emit)),
+        )
+        .service(
+            web::resource("/api/socketio/health")
+                .wrap(middleware::AdminMiddleware)
+                .wrap(middleware::AuthMiddleware)
+                .route(web::get().to(socketio_health)),
+        )
+        .service(
+            web::resource("/api/socketio/stats")
+                .wrap(middleware::AdminMiddleware)
+                .wrap(middleware::AuthMiddleware)
+                .route(web::get().to(socketio_stats)),
+        )
+        .service(
+            web::resource("/api/socketio/sessions/{user_id}")
+                .wrap(middleware::AdminMiddleware)
+                .wrap(middleware::AuthMiddleware)
+                .route(web::get().to(socketio_user_sessions)),
+        )
+        .service(
+            web::resource("/api/socketio/sessions/{sid}/disconnect")
+                .wrap(middleware::AdminMiddleware)
+                .wrap(middleware::AuthMiddleware)
+                .route(web::post().to(socketio_disconnect_session)),
+        )
+        .service(
+            web::resource("/api/socketio/health/sessions")
+                .wrap(middleware::AdminMiddleware)
+                .wrap(middleware::AuthMiddleware)
+                .route(web::get().to(socketio_heartbeat_sessions)),
+        )
         .route("/api/socketio/auth", web::post().to(socketio_auth));
 }
 
@@ -1120,14 +1619,37 @@ async fn socketio_connection_post(
     socketio::transport::polling_handler(req, body, manager_data, handler_data).await
 }
 
-// Native Socket.IO emit endpoint (for Rust backend to emit events)
+// Native Socket.IO emit endpoint, for users and for services holding
+// SOCKETIO_EMIT_SERVICE_KEY
 async fn socketio_native_emit(
     state: web::Data<AppState>,
+    req: HttpRequest,
     payload: web::Json<socketio::events::EmitRequest>,
 ) -> Result<web::Json<socketio::events::EmitResponse>, actix_web::Error> {
+    use sha2::{Digest, Sha256};
+
+    let service_key = state.config.snapshot().socketio_emit_service_key.clone();
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer "));
+    // Compare digests so the check doesn't leak the key's prefix through timing
+    let is_service = match (&service_key, bearer) {
+        (Some(expected), Some(given)) => {
+            Sha256::digest(expected.as_bytes()) == Sha256::digest(given.as_bytes())
+        }
+        _ => false,
+    };
+    let user = if is_service {
+        None
+    } else {
+        Some(middleware::authenticate(&state, &req).await?)
+    };
+
     if let Some(ref handler) = state.socketio_handler {
         let handler_data = web::Data::new(handler.as_ref().clone());
-        socketio::events::handle_emit_request(handler_data, payload).await
+        socketio::events::handle_emit_request(handler_data, user.as_ref(), payload).await
     } else {
         Err(actix_web::error::ErrorServiceUnavailable(
             "Socket.IO not enabled",
@@ -1135,7 +1657,7 @@ async fn socketio_native_emit(
     }
 }
 
-// Native Socket.IO health check
+// Native Socket.IO health check (admin only)
 async fn socketio_health(
     state: web::Data<AppState>,
 ) -> Result<web::Json<socketio::events::HealthResponse>, actix_web::Error> {
@@ -1149,6 +1671,64 @@ async fn socketio_health(
     }
 }
 
+// Per-session heartbeat health (admin only)
+async fn socketio_heartbeat_sessions(
+    state: web::Data<AppState>,
+) -> Result<web::Json<Vec<socketio::health::ConnectionHealth>>, actix_web::Error> {
+    if let Some(ref handler) = state.socketio_handler {
+        let handler_data = web::Data::new(handler.as_ref().clone());
+        socketio::events::handle_heartbeat_sessions(handler_data).await
+    } else {
+        Err(actix_web::error::ErrorServiceUnavailable(
+            "Socket.IO not enabled",
+        ))
+    }
+}
+
+// Sessions, users, rooms, uptime and event rates (admin only)
+async fn socketio_stats(
+    state: web::Data<AppState>,
+) -> Result<web::Json<socketio::events::StatsResponse>, actix_web::Error> {
+    if let Some(ref handler) = state.socketio_handler {
+        let handler_data = web::Data::new(handler.as_ref().clone());
+        socketio::events::handle_stats(handler_data).await
+    } else {
+        Err(actix_web::error::ErrorServiceUnavailable(
+            "Socket.IO not enabled",
+        ))
+    }
+}
+
+// A user's sessions with transport, connect time and last ping (admin only)
+async fn socketio_user_sessions(
+    state: web::Data<AppState>,
+    user_id: web::Path<String>,
+) -> Result<web::Json<Vec<socketio::events::SessionInfo>>, actix_web::Error> {
+    if let Some(ref handler) = state.socketio_handler {
+        let handler_data = web::Data::new(handler.as_ref().clone());
+        socketio::events::handle_user_sessions(handler_data, &user_id).await
+    } else {
+        Err(actix_web::error::ErrorServiceUnavailable(
+            "Socket.IO not enabled",
+        ))
+    }
+}
+
+// Forcibly drop a session (admin only)
+async fn socketio_disconnect_session(
+    state: web::Data<AppState>,
+    sid: web::Path<String>,
+) -> Result<web::Json<serde_json::Value>, actix_web::Error> {
+    if let Some(ref handler) = state.socketio_handler {
+        let handler_data = web::Data::new(handler.as_ref().clone());
+        socketio::events::handle_disconnect_session(handler_data, &sid).await
+    } else {
+        Err(actix_web::error::ErrorServiceUnavailable(
+            "Socket.IO not enabled",
+        ))
+    }
+}
+
 // Socket.IO authentication endpoint
 async fn socketio_auth(
     state: web::Data<AppState>,
@@ -1163,7 +1743,7 @@ async fn socketio_auth(
         .ok_or_else(|| actix_web::error::ErrorBadRequest("Missing token"))?;
 
     // Verify JWT token
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let claims = match crate::utils::auth::verify_jwt(token, &config.webui_secret_key) {
         Ok(claims) => claims,
         Err(_) => {
@@ -1237,7 +1817,7 @@ async fn embeddings(
         .to_string();
 
     // Get config and fetch models
-    let config = state.config.read().unwrap().clone();
+    let config = state.config.snapshot().as_ref().clone();
     let model_service = crate::services::models::ModelService::new(config.clone());
 
     let all_models = model_service.get_all_models(&state.db).await?;
@@ -1251,7 +1831,19 @@ async fn embeddings(
     // Check access control
     if let Some(ref user) = auth_user {
         if user.user.role != "admin" {
-            if !model_service.check_model_access(model, &user.user.id, &user.user.role) {
+            let user_group_ids: std::collections::HashSet<String> =
+                crate::services::group::GroupService::new(&state.db)
+                    .get_groups_by_member_id(&user.user.id)
+                    .await?
+                    .into_iter()
+                    .map(|g| g.id)
+                    .collect();
+            if !model_service.check_model_access(
+                model,
+                &user.user.id,
+                &user.user.role,
+                &user_group_ids,
+            ) {
                 return Err(crate::error::AppError::Forbidden(
                     "Access denied to this model".to_string(),
                 ));
@@ -1299,9 +1891,8 @@ async fn embeddings(
         }
 
         // Send request
-        let response = request
-            .json(&form_data)
-            .send()
+        let span = utils::telemetry::upstream_span("embeddings", base_url, Some(&model.id));
+        let response = utils::telemetry::send_traced(request.json(&form_data), span)
             .await
             .map_err(|e| crate::error::AppError::ExternalServiceError(e.to_string()))?;
 
@@ -1360,37 +1951,70 @@ async fn list_tasks(_state: web::Data<AppState>) -> HttpResponse {
     }))
 }
 
+/// How long `stop_task` waits for clients to acknowledge the cancel event
+const TASK_CANCEL_ACK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
 async fn stop_task(
     state: web::Data<AppState>,
     task_id: web::Path<String>,
+    auth_user: middleware::AuthUser,
 ) -> Result<HttpResponse, crate::error::AppError> {
     use serde_json::json;
 
+    // Stop the task, which saves what it streamed so far; an id that isn't a running
+    // task is taken as a chat whose tasks are stopped. Only the user who started a
+    // task, or an admin, may stop it.
+    let task_id = task_id.into_inner();
+    let is_admin = auth_user.user.role == "admin";
+    let chat_id = if state.tasks.list_tasks().await?.contains(&task_id) {
+        let chat_id = state.tasks.task_item_id(&task_id).await;
+        if !state
+            .tasks
+            .stop_task_as(&task_id, &auth_user.user.id, is_admin)
+            .await?
+        {
+            return Err(crate::error::AppError::NotFound("Task not found".to_string()));
+        }
+        chat_id.unwrap_or_else(|| task_id.clone())
+    } else if state
+        .tasks
+        .stop_item_tasks_as(&task_id, &auth_user.user.id, is_admin)
+        .await?
+    {
+        task_id.clone()
+    } else {
+        return Err(crate::error::AppError::NotFound("Task not found".to_string()));
+    };
+
     // Emit cancel event through Socket.IO
-    // The task_id is the chat_id in our Socket.IO streaming implementation
+    let mut acknowledged = 0;
     if let Some(ref socket_state) = state.socket_state {
-        // Send cancel event to all connected clients for this chat
-        // This will trigger the frontend to stop listening for streaming events
-        tracing::info!("Sending stop signal for chat: {}", task_id.as_str());
-
-        // Emit a chat:tasks:cancel event that the frontend listens for
-        let event_emitter = crate::socket::get_event_emitter(
-            socket_state.clone(),
-            "system".to_string(), // system user for admin actions
-            Some(task_id.to_string()),
-            None,
-            None,
-        );
-
-        event_emitter(json!({
-            "type": "chat:tasks:cancel"
-        }))
-        .await;
+        // Send cancel event to the user's connected clients, which stop listening for
+        // streaming events and acknowledge it
+        tracing::info!("Sending stop signal for chat: {}", chat_id);
+
+        let payload = json!({
+            "chat_id": chat_id,
+            "message_id": null,
+            "data": {"type": "chat:tasks:cancel"},
+        });
+        acknowledged = socket_state
+            .native_handler
+            .emit_to_user_with_ack(
+                &auth_user.user.id,
+                "chat-events",
+                payload,
+                TASK_CANCEL_ACK_TIMEOUT,
+            )
+            .await
+            .len();
     }
 
     Ok(HttpResponse::Ok().json(json!({
         "status": true,
-        "message": format!("Stop signal sent for {}", task_id.as_str())
+        "message": format!("Stop signal sent for {}", task_id.as_str()),
+        "acknowledged": acknowledged > 0,
+        "acknowledged_sessions": acknowledged,
     })))
 }
 
@@ -1407,20 +2031,26 @@ async fn list_tasks_by_chat(
 }
 
 // Usage and webhook
-async fn get_usage(_state: web::Data<AppState>) -> HttpResponse {
+async fn get_usage(state: web::Data<AppState>) -> HttpResponse {
     use serde_json::json;
 
-    // TODO: Implement usage tracking (models in use, active users)
+    let (model_ids, user_ids) = match &state.socketio_handler {
+        Some(handler) => (
+            handler.manager().get_models_in_use().await,
+            handler.active_user_ids().await,
+        ),
+        None => (Vec::new(), Vec::new()),
+    };
     HttpResponse::Ok().json(json!({
-        "model_ids": [],
-        "user_ids": []
+        "model_ids": model_ids,
+        "user_ids": user_ids
     }))
 }
 
 async fn get_webhook(state: web::Data<AppState>) -> HttpResponse {
     use serde_json::json;
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     HttpResponse::Ok().json(json!({
         "url": config.webhook_url.as_deref().unwrap_or("")
     }))
@@ -1440,32 +2070,91 @@ async fn update_webhook(
 
 // OAuth integration endpoints
 async fn oauth_client_authorize(
-    _state: web::Data<AppState>,
-    _client_id: web::Path<String>,
+    state: web::Data<AppState>,
+    client_id: web::Path<String>,
+    auth_user: middleware::AuthUser,
 ) -> Result<HttpResponse, crate::error::AppError> {
-    // TODO: Implement OAuth client authorization
-    Err(crate::error::AppError::NotImplemented(
-        "OAuth client authorization not implemented yet".to_string(),
-    ))
+    let config = state.config.snapshot();
+    let manager = services::oauth_client::OAuthClientManager::from_config(&state.db, &config);
+    let url = manager
+        .start_authorization(&client_id, &auth_user.user.id)
+        .await?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((actix_web::http::header::LOCATION, url))
+        .finish())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct OAuthClientCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
 }
 
 async fn oauth_client_callback(
-    _state: web::Data<AppState>,
-    _client_id: web::Path<String>,
+    state: web::Data<AppState>,
+    client_id: web::Path<String>,
+    query: web::Query<OAuthClientCallbackQuery>,
+    auth_user: middleware::AuthUser,
 ) -> Result<HttpResponse, crate::error::AppError> {
-    use serde_json::json;
+    let config = state.config.snapshot();
+    let manager = services::oauth_client::OAuthClientManager::from_config(&state.db, &config);
+    if manager.get_client(&client_id).await.is_none() {
+        return Err(crate::error::AppError::NotFound(format!(
+            "OAuth client not found: {}",
+            client_id
+        )));
+    }
 
-    // TODO: Implement OAuth client callback
-    Ok(HttpResponse::Ok().json(json!({
-        "status": "ok"
-    })))
+    if let Some(error) = &query.error {
+        return Err(crate::error::AppError::BadRequest(format!(
+            "Authorization was not granted: {}",
+            query.error_description.as_deref().unwrap_or(error)
+        )));
+    }
+    let (code, oauth_state) = match (&query.code, &query.state) {
+        (Some(code), Some(oauth_state)) => (code, oauth_state),
+        _ => {
+            return Err(crate::error::AppError::BadRequest(
+                "Missing code or state".to_string(),
+            ))
+        }
+    };
+
+    manager
+        .complete_authorization(&client_id, &auth_user.user.id, code, oauth_state)
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(services::oauth_client::authorization_complete_page(&client_id)))
 }
 
 // PWA manifest
-async fn get_manifest(_state: web::Data<AppState>) -> HttpResponse {
+async fn get_manifest(state: web::Data<AppState>) -> HttpResponse {
     use serde_json::json;
 
-    let webui_name = "Open WebUI";
+    let config = state.config.snapshot();
+    let webui_name = config.webui_name.as_str();
+
+    // Prefer an uploaded logo as the app icon
+    let (icon_src, icon_type, icon_sizes) = match config
+        .custom_branding
+        .get(&utils::branding::BrandingAsset::Logo)
+    {
+        Some(image) => (
+            utils::branding::asset_url(utils::branding::BrandingAsset::Logo, Some(image)),
+            image.content_type.clone(),
+            format!("{}x{}", image.width, image.height),
+        ),
+        None => (
+            "/static/logo.png".to_string(),
+            "image/png".to_string(),
+            "500x500".to_string(),
+        ),
+    };
 
     HttpResponse::Ok().json(json!({
         "name": webui_name,
@@ -1476,15 +2165,15 @@ async fn get_manifest(_state: web::Data<AppState>) -> HttpResponse {
         "background_color": "#343541",
         "icons": [
             {
-                "src": "/static/logo.png",
-                "type": "image/png",
-                "sizes": "500x500",
+                "src": icon_src,
+                "type": icon_type,
+                "sizes": icon_sizes,
                 "purpose": "any",
             },
             {
-                "src": "/static/logo.png",
-                "type": "image/png",
-                "sizes": "500x500",
+                "src": icon_src,
+                "type": icon_type,
+                "sizes": icon_sizes,
                 "purpose": "maskable",
             },
         ],
@@ -1492,9 +2181,11 @@ async fn get_manifest(_state: web::Data<AppState>) -> HttpResponse {
 }
 
 // OpenSearch XML
-async fn get_opensearch() -> HttpResponse {
-    let webui_name = "Open WebUI";
-    let webui_url = "http://localhost:8168"; // TODO: Get from config
+async fn get_opensearch(state: web::Data<AppState>) -> HttpResponse {
+    let config = state.config.snapshot();
+    let webui_name = config.webui_name.as_str();
+    // Defaults to https://localhost:8168 when TLS is configured
+    let webui_url = config.webui_url.trim_end_matches('/');
 
     let xml = format!(
         r#"<?xml version="1.0" encoding="UTF-8"?>
@@ -1512,13 +2203,37 @@ async fn get_opensearch() -> HttpResponse {
     HttpResponse::Ok().content_type("application/xml").body(xml)
 }
 
-// Cache file serving
+// Cache file serving (generated images, TTS audio), by signed URL
 async fn serve_cache_file(
-    _state: web::Data<AppState>,
-    _path: web::Path<String>,
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<utils::signed_url::SignedQuery>,
 ) -> Result<HttpResponse, crate::error::AppError> {
-    // TODO: Implement cache file serving with path traversal protection
-    Err(crate::error::AppError::NotFound(
-        "File not found".to_string(),
-    ))
+    let cache_dir = {
+        let config = state.config.snapshot();
+        utils::signed_url::verify_path(&config, req.path(), &query)?;
+        config.cache_dir.clone()
+    };
+
+    // Only plain path segments, so the request can't leave the cache directory
+    let relative = std::path::Path::new(path.as_str());
+    if relative
+        .components()
+        .any(|c| !matches!(c, std::path::Component::Normal(_)))
+    {
+        return Err(crate::error::AppError::NotFound(
+            "File not found".to_string(),
+        ));
+    }
+
+    let file_path = std::path::Path::new(&cache_dir).join(relative);
+    let bytes = tokio::fs::read(&file_path)
+        .await
+        .map_err(|_| crate::error::AppError::NotFound("File not found".to_string()))?;
+    let content_type = mime_guess::from_path(&file_path).first_or_octet_stream();
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type.as_ref())
+        .body(bytes))
 }
@@ -0,0 +1,263 @@
+use actix_web::{
+    body::{BodySize, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::HeaderName,
+    Error, HttpMessage,
+};
+use futures_util::future::LocalBoxFuture;
+use serde::Serialize;
+use std::future::{ready, Ready};
+use std::io::Write;
+use std::time::Instant;
+
+use crate::middleware::request_id::{RequestId, REQUEST_ID_HEADER};
+use crate::middleware::{AuthUser, RealIp};
+
+/// Middleware that writes one JSON object per request to stdout
+///
+/// Fields: timestamp, method, path, query, status, duration_ms, request_id,
+/// user_id (when authenticated), bytes, remote_addr, user_agent.
+///
+/// Credentials in the query, such as websocket tokens and OAuth codes, are redacted.
+///
+/// The entry is written once the response headers are ready, so streaming
+/// responses (SSE) are logged with their real status; `bytes` is null when the
+/// body size is not known up front.
+///
+/// Enabled with ENABLE_JSON_ACCESS_LOG=true (replaces actix's default Logger).
+pub struct AccessLog;
+
+impl<S, B> Transform<S, ServiceRequest> for AccessLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AccessLogMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AccessLogMiddleware { service }))
+    }
+}
+
+pub struct AccessLogMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for AccessLogMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+
+        let method = req.method().to_string();
+        let path = req.path().to_string();
+        let query = Some(req.query_string())
+            .filter(|q| !q.is_empty())
+            .map(redact_query);
+        let remote_addr = RealIp::of(req.request()).0.map(|ip| ip.to_string());
+        let user_agent = header_value(&req, actix_web::http::header::USER_AGENT);
+        // Assigned by RequestIdMiddleware; fall back to the header when it isn't installed
+        let request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.0.clone())
+            .or_else(|| header_value(&req, HeaderName::from_static(REQUEST_ID_HEADER)))
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await?;
+
+            // AuthMiddleware stores the user in the shared request extensions
+            let user_id = res
+                .request()
+                .extensions()
+                .get::<AuthUser>()
+                .map(|auth_user| auth_user.user.id.clone());
+
+            let bytes = match res.response().body().size() {
+                BodySize::Sized(n) => Some(n),
+                BodySize::None => Some(0),
+                BodySize::Stream => None,
+            };
+
+            let entry = AccessLogEntry {
+                timestamp: chrono::Utc::now().to_rfc3339(),
+                method,
+                path,
+                query,
+                status: res.status().as_u16(),
+                duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+                request_id,
+                user_id,
+                bytes,
+                remote_addr,
+                user_agent,
+            };
+            entry.write();
+
+            Ok(res)
+        })
+    }
+}
+
+/// A single access log line
+#[derive(Debug, Serialize)]
+pub struct AccessLogEntry {
+    pub timestamp: String,
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub status: u16,
+    pub duration_ms: f64,
+    pub request_id: String,
+    pub user_id: Option<String>,
+    pub bytes: Option<u64>,
+    pub remote_addr: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl AccessLogEntry {
+    fn write(&self) {
+        if let Ok(line) = serde_json::to_string(self) {
+            #[cfg(test)]
+            tests::WRITTEN.with(|written| written.borrow_mut().push(line.clone()));
+            let mut stdout = std::io::stdout().lock();
+            let _ = writeln!(stdout, "{}", line);
+        }
+    }
+}
+
+/// Query parameters carrying credentials, whose values are left out of the log
+const SENSITIVE_QUERY_PARAMS: &[&str] = &[
+    "token",
+    "access_token",
+    "code",
+    "state",
+    "key",
+    "api_key",
+    "signature",
+];
+
+/// `query` with the values of credential parameters replaced
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _))
+                if SENSITIVE_QUERY_PARAMS
+                    .iter()
+                    .any(|param| name.eq_ignore_ascii_case(param)) =>
+            {
+                format!("{}=[REDACTED]", name)
+            }
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn header_value(req: &ServiceRequest, name: HeaderName) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test as actix_test;
+    use actix_web::{web, App, HttpResponse};
+    use std::cell::RefCell;
+
+    thread_local! {
+        /// Lines written on this thread, which runs the whole actix test
+        pub(super) static WRITTEN: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[actix_web::test]
+    async fn test_access_log_passes_response_through() {
+        let app = actix_test::init_service(App::new().wrap(AccessLog).route(
+            "/items",
+            web::get().to(|| async {
+                actix_web::rt::time::sleep(std::time::Duration::from_millis(20)).await;
+                HttpResponse::Created().body("test")
+            }),
+        ))
+        .await;
+
+        let req = actix_test::TestRequest::get()
+            .uri("/items?page=2&token=secret")
+            .insert_header(("x-request-id", "abc"))
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status().as_u16(), 201);
+
+        let lines = WRITTEN.with(|written| written.take());
+        assert_eq!(lines.len(), 1);
+        let entry: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(entry["method"], "GET");
+        assert_eq!(entry["path"], "/items");
+        assert_eq!(entry["query"], "page=2&token=[REDACTED]");
+        assert_eq!(entry["status"], 201);
+        assert_eq!(entry["request_id"], "abc");
+        assert_eq!(entry["bytes"], 4);
+        assert!(entry["user_id"].is_null());
+        assert!(entry["duration_ms"].as_f64().unwrap() >= 20.0);
+    }
+
+    #[test]
+    fn test_credentials_are_redacted_from_the_query() {
+        assert_eq!(
+            redact_query("EIO=4&transport=websocket&token=eyJhbGciOi"),
+            "EIO=4&transport=websocket&token=[REDACTED]"
+        );
+        assert_eq!(
+            redact_query("code=4%2F0AbC&State=xyz&scope=repo"),
+            "code=[REDACTED]&State=[REDACTED]&scope=repo"
+        );
+        assert_eq!(
+            redact_query("api_key=sk-1&key=k&expires=1700000000&signature=abc"),
+            "api_key=[REDACTED]&key=[REDACTED]&expires=1700000000&signature=[REDACTED]"
+        );
+        assert_eq!(redact_query("page=2&tokens=5&flag"), "page=2&tokens=5&flag");
+    }
+
+    #[test]
+    fn test_access_log_entry_serialization() {
+        let entry = AccessLogEntry {
+            timestamp: "2024-01-01T00:00:00Z".to_string(),
+            method: "GET".to_string(),
+            path: "/api/v1/chats".to_string(),
+            query: None,
+            status: 200,
+            duration_ms: 1.5,
+            request_id: "abc".to_string(),
+            user_id: Some("user-1".to_string()),
+            bytes: None,
+            remote_addr: None,
+            user_agent: None,
+        };
+
+        let value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(value["status"], 200);
+        assert_eq!(value["user_id"], "user-1");
+        assert!(value["bytes"].is_null());
+    }
+}
@@ -1,2 +1,352 @@
-// TODO: Implement audit middleware for actix-web
-// This middleware will log important actions for audit purposes
+use actix_web::{
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    web::{Bytes, BytesMut},
+    Error, HttpMessage,
+};
+use futures_util::{future::LocalBoxFuture, StreamExt};
+use serde_json::{json, Value};
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::middleware::{AuthUser, RealIp};
+use crate::models::audit::AuditLog;
+use crate::services::audit::AuditService;
+use crate::services::config::REDACTED_VALUE;
+use crate::utils::time::current_timestamp_seconds;
+
+/// Entries waiting to be written; requests never wait on the database, so entries
+/// are dropped (with a warning) if the writer falls this far behind
+const AUDIT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Request bodies larger than this are not stored
+const MAX_AUDIT_BODY_BYTES: usize = 64 * 1024;
+
+/// Paths whose request bodies are stored (after scrubbing): authentication and
+/// configuration changes
+const BODY_CAPTURE_PREFIXES: &[&str] = &["/api/v1/auths", "/api/v1/configs"];
+
+/// Substrings marking a JSON key whose value must never be stored
+const SENSITIVE_KEY_PARTS: &[&str] = &[
+    "password",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "private_key",
+    "authorization",
+    "credential",
+];
+
+/// Which requests are audited
+#[derive(Debug, Clone)]
+pub struct AuditFilter {
+    /// Upper-case HTTP methods
+    pub methods: Vec<String>,
+    /// Path prefixes to audit
+    pub paths: Vec<String>,
+    /// Path prefixes never audited, even when they match `paths`
+    pub excluded_paths: Vec<String>,
+}
+
+impl AuditFilter {
+    pub fn from_config(config: &Config) -> Self {
+        AuditFilter {
+            methods: config.audit_log_methods.clone(),
+            paths: config.audit_log_paths.clone(),
+            excluded_paths: config.audit_log_excluded_paths.clone(),
+        }
+    }
+
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        self.methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+            && self
+                .paths
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_str()))
+            && !self
+                .excluded_paths
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_str()))
+    }
+}
+
+/// Whether the request body of `path` is kept in the audit entry
+fn captures_body(path: &str) -> bool {
+    BODY_CAPTURE_PREFIXES
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+        || path.split('/').any(|segment| segment == "config")
+}
+
+fn is_sensitive_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    SENSITIVE_KEY_PARTS.iter().any(|part| key.contains(part))
+}
+
+/// Replace credentials anywhere in a JSON document with [`REDACTED_VALUE`]
+pub fn scrub_sensitive(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if !is_sensitive_key(key) {
+                    scrub_sensitive(child);
+                    continue;
+                }
+                match child {
+                    Value::String(s) if s.is_empty() => {}
+                    Value::Array(items) => {
+                        for item in items.iter_mut() {
+                            if matches!(item, Value::String(s) if !s.is_empty()) {
+                                *item = json!(REDACTED_VALUE);
+                            }
+                        }
+                    }
+                    // Flags and numeric limits (e.g. `max_tokens`) are not secrets
+                    Value::Null | Value::Bool(_) | Value::Number(_) => {}
+                    _ => *child = json!(REDACTED_VALUE),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(scrub_sensitive),
+        _ => {}
+    }
+}
+
+/// Scrubbed copy of a JSON request body; other bodies are not stored
+fn scrub_body(body: &[u8]) -> Option<String> {
+    if body.is_empty() || body.len() > MAX_AUDIT_BODY_BYTES {
+        return None;
+    }
+    let mut value: Value = serde_json::from_slice(body).ok()?;
+    scrub_sensitive(&mut value);
+    serde_json::to_string(&value).ok()
+}
+
+fn bytes_to_payload(bytes: Bytes) -> Payload {
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(bytes);
+    Payload::from(payload)
+}
+
+/// Middleware recording mutating requests in the `audit_log` table
+///
+/// Entries are handed to a background writer over a bounded channel, so auditing
+/// never adds database latency to the request.
+///
+/// Enabled with ENABLE_AUDIT_LOG=true; see AUDIT_LOG_METHODS, AUDIT_LOG_PATHS and
+/// AUDIT_LOG_EXCLUDED_PATHS for which requests are recorded.
+#[derive(Clone)]
+pub struct Audit {
+    sender: mpsc::Sender<AuditLog>,
+    filter: Arc<AuditFilter>,
+}
+
+impl Audit {
+    /// Spawn the background writer and return the middleware feeding it
+    pub fn start(db: Database, filter: AuditFilter) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<AuditLog>(AUDIT_CHANNEL_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(entry) = receiver.recv().await {
+                if let Err(e) = AuditService::new(&db).insert_entry(&entry).await {
+                    tracing::warn!("Failed to write audit log entry: {}", e);
+                }
+            }
+        });
+
+        Audit {
+            sender,
+            filter: Arc::new(filter),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Audit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuditMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuditMiddleware {
+            service: Rc::new(service),
+            sender: self.sender.clone(),
+            filter: self.filter.clone(),
+        }))
+    }
+}
+
+pub struct AuditMiddleware<S> {
+    service: Rc<S>,
+    sender: mpsc::Sender<AuditLog>,
+    filter: Arc<AuditFilter>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuditMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let method = req.method().to_string();
+        let path = req.path().to_string();
+
+        if !self.filter.matches(&method, &path) {
+            return Box::pin(self.service.call(req));
+        }
+
+        let service = self.service.clone();
+        let sender = self.sender.clone();
+        let source_ip = RealIp::of(req.request()).0.map(|ip| ip.to_string());
+        let is_json = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|ct| ct.starts_with("application/json"))
+            .unwrap_or(false);
+
+        Box::pin(async move {
+            let request_body = if is_json && captures_body(&path) {
+                // Buffer the body so it can be both recorded and handed on to the handler
+                let mut payload = req.take_payload();
+                let mut body = BytesMut::new();
+                while let Some(chunk) = payload.next().await {
+                    body.extend_from_slice(&chunk?);
+                }
+                let body = body.freeze();
+                let scrubbed = scrub_body(&body);
+                req.set_payload(bytes_to_payload(body));
+                scrubbed
+            } else {
+                None
+            };
+
+            let res = service.call(req).await?;
+
+            // AuthMiddleware stores the user in the shared request extensions
+            let user_id = res
+                .request()
+                .extensions()
+                .get::<AuthUser>()
+                .map(|auth_user| auth_user.user.id.clone());
+
+            let entry = AuditLog {
+                id: uuid::Uuid::new_v4().to_string(),
+                user_id,
+                method,
+                path,
+                status: res.status().as_u16() as i64,
+                source_ip,
+                request_body,
+                created_at: current_timestamp_seconds(),
+            };
+            if let Err(mpsc::error::TrySendError::Full(entry)) = sender.try_send(entry) {
+                tracing::warn!(
+                    "Audit log queue is full, dropping entry for {} {}",
+                    entry.method,
+                    entry.path
+                );
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test as actix_test;
+    use actix_web::{web, App, HttpResponse};
+
+    fn filter() -> AuditFilter {
+        AuditFilter {
+            methods: vec!["POST".to_string(), "DELETE".to_string()],
+            paths: vec!["/api".to_string()],
+            excluded_paths: vec!["/api/v1/chats".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let filter = filter();
+        assert!(filter.matches("POST", "/api/v1/configs/export"));
+        assert!(filter.matches("delete", "/api/v1/users/1"));
+        assert!(!filter.matches("GET", "/api/v1/configs/export"));
+        assert!(!filter.matches("POST", "/api/v1/chats/new"));
+        assert!(!filter.matches("POST", "/health"));
+    }
+
+    #[test]
+    fn test_scrub_sensitive() {
+        let mut body = json!({
+            "email": "a@b.com",
+            "password": "hunter2",
+            "OPENAI_API_KEYS": ["sk-1", ""],
+            "ENABLE_API_KEY": true,
+            "nested": [{"client_secret": "s", "name": "x"}]
+        });
+        scrub_sensitive(&mut body);
+
+        assert_eq!(body["email"], "a@b.com");
+        assert_eq!(body["password"], REDACTED_VALUE);
+        assert_eq!(body["OPENAI_API_KEYS"], json!([REDACTED_VALUE, ""]));
+        assert_eq!(body["ENABLE_API_KEY"], true);
+        assert_eq!(body["nested"][0]["client_secret"], REDACTED_VALUE);
+        assert_eq!(body["nested"][0]["name"], "x");
+    }
+
+    #[test]
+    fn test_captures_body() {
+        assert!(captures_body("/api/v1/auths/signin"));
+        assert!(captures_body("/api/v1/configs/connections"));
+        assert!(captures_body("/api/v1/retrieval/config/update"));
+        assert!(!captures_body("/api/v1/chats/new"));
+    }
+
+    #[actix_web::test]
+    async fn test_audit_records_scrubbed_body_and_forwards_it() {
+        let (sender, mut receiver) = mpsc::channel(8);
+        let audit = Audit {
+            sender,
+            filter: Arc::new(filter()),
+        };
+        let app = actix_test::init_service(App::new().wrap(audit).route(
+            "/api/v1/auths/signin",
+            web::post().to(|body: web::Bytes| async move { HttpResponse::Ok().body(body) }),
+        ))
+        .await;
+
+        let req = actix_test::TestRequest::post()
+            .uri("/api/v1/auths/signin")
+            .set_json(json!({"email": "a@b.com", "password": "hunter2"}))
+            .to_request();
+        let body = actix_test::call_and_read_body(&app, req).await;
+        assert!(String::from_utf8_lossy(&body).contains("hunter2"));
+
+        let entry = receiver.try_recv().unwrap();
+        assert_eq!(entry.method, "POST");
+        assert_eq!(entry.status, 200);
+        assert!(entry.user_id.is_none());
+        let stored: Value = serde_json::from_str(&entry.request_body.unwrap()).unwrap();
+        assert_eq!(stored["password"], REDACTED_VALUE);
+        assert_eq!(stored["email"], "a@b.com");
+    }
+}
@@ -7,7 +7,7 @@ use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     error::Error as ActixError,
     http::header,
-    web, HttpMessage,
+    web, HttpMessage, HttpRequest,
 };
 use futures::future::{ready, LocalBoxFuture, Ready};
 use std::rc::Rc;
@@ -96,64 +96,144 @@ where
                 .app_data::<web::Data<AppState>>()
                 .ok_or_else(|| AppError::InternalServerError("App state not found".to_string()))?;
 
-            // Try to extract token from Authorization header first
-            let token = if let Some(auth_header) = req.headers().get(header::AUTHORIZATION) {
-                if let Ok(auth_str) = auth_header.to_str() {
-                    auth_str.strip_prefix("Bearer ").map(|s| s.to_string())
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
-
-            // If no Authorization header, try to get token from cookie
-            let token = token
-                .or_else(|| req.cookie("token").map(|c| c.value().to_string()))
-                .ok_or_else(|| AppError::Unauthorized("Missing authorization token".to_string()))?;
-
-            // Check if it's an API key (starts with sk-)
-            let user = if token.starts_with("sk-") {
-                let config = state.config.read().unwrap();
-                if !config.enable_api_key {
-                    return Err(AppError::Forbidden("API keys are disabled".to_string()).into());
-                }
-                drop(config); // Release the lock
-
-                let user_service = UserService::new(&state.db);
-                user_service
-                    .get_user_by_api_key(&token)
-                    .await
-                    .map_err(|e| AppError::from(e))?
-                    .ok_or_else(|| AppError::Unauthorized("Invalid API key".to_string()))?
-            } else {
-                // Otherwise, verify JWT token
-                let config = state.config.read().unwrap();
-                let webui_secret_key = config.webui_secret_key.clone();
-                drop(config); // Release the lock
-
-                let claims = verify_jwt(&token, &webui_secret_key).map_err(|e| {
-                    // Token verification failed (expired or invalid)
-                    tracing::debug!("JWT verification failed: {:?}", e);
-                    AppError::Unauthorized("Invalid or expired token".to_string())
-                })?;
-
-                // Check token expiration explicitly
-                if let Some(exp) = claims.exp {
-                    let now = chrono::Utc::now().timestamp();
-                    if now > exp {
-                        tracing::debug!("Token expired at {}, current time {}", exp, now);
-                        return Err(AppError::Unauthorized("Token expired".to_string()).into());
-                    }
-                }
-
-                let user_service = UserService::new(&state.db);
-                user_service
-                    .get_user_by_id(&claims.sub)
-                    .await
-                    .map_err(|e| AppError::from(e))?
-                    .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?
-            };
+            let user = authenticate(state, req.request()).await?;
+
+            // Insert user into request extensions
+            req.extensions_mut().insert(AuthUser { user });
+
+            let res = service.call(req).await?;
+            Ok(res)
+        })
+    }
+}
+
+/// Token of a request's `Authorization: Bearer` header
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    if let Some(auth_header) = req.headers().get(header::AUTHORIZATION) {
+        if let Ok(auth_str) = auth_header.to_str() {
+            auth_str.strip_prefix("Bearer ").map(|s| s.to_string())
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+/// Resolve the user behind a request's bearer token, API key or `token` cookie
+pub async fn authenticate(state: &AppState, req: &HttpRequest) -> Result<User, AppError> {
+    // Try to extract token from Authorization header first
+    let token = bearer_token(req);
+
+    // If no Authorization header, try to get token from cookie
+    let token = token
+        .or_else(|| req.cookie("token").map(|c| c.value().to_string()))
+        .ok_or_else(|| AppError::Unauthorized("Missing authorization token".to_string()))?;
+
+    authenticate_token(state, &token).await
+}
+
+/// Resolve the user a JWT or API key belongs to
+pub async fn authenticate_token(state: &AppState, token: &str) -> Result<User, AppError> {
+    // Check if it's an API key (starts with sk-)
+    let user = if token.starts_with("sk-") {
+        let config = state.config.snapshot();
+        if !config.enable_api_key {
+            return Err(AppError::Forbidden("API keys are disabled".to_string()));
+        }
+
+        let user_service = UserService::new(&state.db);
+        user_service
+            .get_user_by_api_key(token)
+            .await?
+            .ok_or_else(|| AppError::Unauthorized("Invalid API key".to_string()))?
+    } else {
+        // Otherwise, verify JWT token
+        let config = state.config.snapshot();
+        let webui_secret_key = config.webui_secret_key.clone();
+
+        let claims = verify_jwt(token, &webui_secret_key).map_err(|e| {
+            // Token verification failed (expired or invalid)
+            tracing::debug!("JWT verification failed: {:?}", e);
+            AppError::Unauthorized("Invalid or expired token".to_string())
+        })?;
+
+        // Check token expiration explicitly
+        if let Some(exp) = claims.exp {
+            let now = chrono::Utc::now().timestamp();
+            if now > exp {
+                tracing::debug!("Token expired at {}, current time {}", exp, now);
+                return Err(AppError::Unauthorized("Token expired".to_string()));
+            }
+        }
+
+        let user_service = UserService::new(&state.db);
+        user_service
+            .get_user_by_id(&claims.sub)
+            .await?
+            .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?
+    };
+
+    Ok(user)
+}
+
+/// Resolve the user behind a request's `Authorization: Bearer sk-...` API key
+pub async fn authenticate_api_key(state: &AppState, req: &HttpRequest) -> Result<User, AppError> {
+    match bearer_token(req) {
+        Some(token) if token.starts_with("sk-") => authenticate_token(state, &token).await,
+        Some(_) => Err(AppError::Unauthorized("An API key is required".to_string())),
+        None => Err(AppError::Unauthorized("Missing API key".to_string())),
+    }
+}
+
+// API key middleware factory, for the OpenAI-compatible API used by external clients
+pub struct ApiKeyMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for ApiKeyMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = ApiKeyMiddlewareService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiKeyMiddlewareService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct ApiKeyMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiKeyMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            // Extract state
+            let state = req
+                .app_data::<web::Data<AppState>>()
+                .ok_or_else(|| AppError::InternalServerError("App state not found".to_string()))?;
+
+            let user = authenticate_api_key(state, req.request()).await?;
 
             // Insert user into request extensions
             req.extensions_mut().insert(AuthUser { user });
@@ -211,64 +291,7 @@ where
                 .app_data::<web::Data<AppState>>()
                 .ok_or_else(|| AppError::InternalServerError("App state not found".to_string()))?;
 
-            // Try to extract token from Authorization header first
-            let token = if let Some(auth_header) = req.headers().get(header::AUTHORIZATION) {
-                if let Ok(auth_str) = auth_header.to_str() {
-                    auth_str.strip_prefix("Bearer ").map(|s| s.to_string())
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
-
-            // If no Authorization header, try to get token from cookie
-            let token = token
-                .or_else(|| req.cookie("token").map(|c| c.value().to_string()))
-                .ok_or_else(|| AppError::Unauthorized("Missing authorization token".to_string()))?;
-
-            // Check if it's an API key (starts with sk-)
-            let user = if token.starts_with("sk-") {
-                let config = state.config.read().unwrap();
-                if !config.enable_api_key {
-                    return Err(AppError::Forbidden("API keys are disabled".to_string()).into());
-                }
-                drop(config); // Release the lock
-
-                let user_service = UserService::new(&state.db);
-                user_service
-                    .get_user_by_api_key(&token)
-                    .await
-                    .map_err(|e| AppError::from(e))?
-                    .ok_or_else(|| AppError::Unauthorized("Invalid API key".to_string()))?
-            } else {
-                // Otherwise, verify JWT token
-                let config = state.config.read().unwrap();
-                let webui_secret_key = config.webui_secret_key.clone();
-                drop(config); // Release the lock
-
-                let claims = verify_jwt(&token, &webui_secret_key).map_err(|e| {
-                    // Token verification failed (expired or invalid)
-                    tracing::debug!("JWT verification failed: {:?}", e);
-                    AppError::Unauthorized("Invalid or expired token".to_string())
-                })?;
-
-                // Check token expiration explicitly
-                if let Some(exp) = claims.exp {
-                    let now = chrono::Utc::now().timestamp();
-                    if now > exp {
-                        tracing::debug!("Token expired at {}, current time {}", exp, now);
-                        return Err(AppError::Unauthorized("Token expired".to_string()).into());
-                    }
-                }
-
-                let user_service = UserService::new(&state.db);
-                user_service
-                    .get_user_by_id(&claims.sub)
-                    .await
-                    .map_err(|e| AppError::from(e))?
-                    .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?
-            };
+            let user = authenticate(state, req.request()).await?;
 
             // Check if user is admin
             if user.role != "admin" {
@@ -0,0 +1,344 @@
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::PayloadError,
+    http::header::CONTENT_LENGTH,
+    web, Error, HttpMessage, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use futures_util::StreamExt;
+use serde::Serialize;
+use serde_json::json;
+use std::cell::Cell;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use crate::config::Config;
+use crate::middleware::request_id::current_request_id;
+use crate::AppState;
+
+/// Room left in upload bodies for the multipart framing and form fields around the file
+const MULTIPART_OVERHEAD: u64 = 1024 * 1024;
+
+/// Routes sharing a request body limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyLimitGroup {
+    /// File uploads and audio transcription, limited by `FILE_MAX_SIZE_MB`
+    Upload,
+    /// Chat completions and saved chats, which carry base64 images inline
+    Chat,
+    /// Everything else
+    Default,
+}
+
+const UPLOAD_PREFIXES: &[&str] = &[
+    "/api/v1/files",
+    "/api/v1/audio/transcriptions",
+    "/api/v1/notes/transcribe",
+    "/api/v1/pipelines/upload",
+    "/api/v1/configs/branding",
+    "/api/v1/configs/import",
+];
+
+const CHAT_PREFIXES: &[&str] = &[
+    "/api/chat/",
+    "/api/v1/chats",
+    "/api/v1/tasks/",
+    "/openai/",
+    "/v1/chat/",
+    "/socket.io",
+    "/ws/socket.io",
+];
+
+impl BodyLimitGroup {
+    pub fn for_path(path: &str) -> Self {
+        if UPLOAD_PREFIXES.iter().any(|p| path.starts_with(p)) {
+            BodyLimitGroup::Upload
+        } else if CHAT_PREFIXES.iter().any(|p| path.starts_with(p)) {
+            BodyLimitGroup::Chat
+        } else {
+            BodyLimitGroup::Default
+        }
+    }
+
+    /// The configured limit in bytes, as reported to clients
+    pub fn limit(&self, config: &Config) -> u64 {
+        match self {
+            BodyLimitGroup::Upload => config.file_max_size,
+            BodyLimitGroup::Chat => config.chat_body_max_size,
+            BodyLimitGroup::Default => config.body_max_size,
+        }
+    }
+
+    /// Bytes actually accepted; uploads get room for the multipart envelope, since the
+    /// upload handlers check the file itself against `FILE_MAX_SIZE_MB`
+    fn enforced_limit(&self, config: &Config) -> u64 {
+        match self {
+            BodyLimitGroup::Upload => config.file_max_size.saturating_add(MULTIPART_OVERHEAD),
+            _ => self.limit(config),
+        }
+    }
+}
+
+/// Middleware enforcing the request body limit of each [`BodyLimitGroup`]
+///
+/// Limits come from the runtime config, so admin changes apply to the next request.
+/// A `Content-Length` over the limit is refused before the handler runs; streamed
+/// bodies are cut off once they pass it. Either way the client gets a 413 naming the
+/// limit. The extractor limits (`JsonConfig`, `PayloadConfig`) are lifted in `main`,
+/// so this is the only cap.
+pub struct BodyLimit;
+
+impl<S, B> Transform<S, ServiceRequest> for BodyLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = BodyLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(BodyLimitMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct BodyLimitMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for BodyLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let group = BodyLimitGroup::for_path(req.path());
+            let (limit, enforced) = match req.app_data::<web::Data<AppState>>() {
+                Some(state) => {
+                    let config = state.config.snapshot();
+                    (group.limit(&config), group.enforced_limit(&config))
+                }
+                None => return service.call(req).await.map(|res| res.map_into_left_body()),
+            };
+
+            let content_length = req
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
+            if content_length.is_some_and(|length| length > enforced) {
+                let response = payload_too_large(group, limit);
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+
+            // Count what is actually read, for chunked bodies and lying Content-Lengths
+            let exceeded = Rc::new(Cell::new(false));
+            let payload = req.take_payload();
+            let flag = exceeded.clone();
+            let mut received = 0u64;
+            let limited = payload.map(move |chunk| {
+                let chunk = chunk?;
+                received += chunk.len() as u64;
+                if received > enforced {
+                    flag.set(true);
+                    return Err(PayloadError::Overflow);
+                }
+                Ok(chunk)
+            });
+            req.set_payload(Payload::from(limited.boxed_local()));
+
+            let res = service.call(req).await?;
+            if exceeded.get() {
+                let (req, _) = res.into_parts();
+                let response = payload_too_large(group, limit);
+                return Ok(ServiceResponse::new(req, response).map_into_right_body());
+            }
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+fn payload_too_large(group: BodyLimitGroup, limit: u64) -> HttpResponse {
+    tracing::warn!(
+        "Request body over the {} byte limit of the {:?} group",
+        limit,
+        group
+    );
+    HttpResponse::PayloadTooLarge().json(json!({
+        "detail": format!("Request body is larger than the {} limit", format_size(limit)),
+        "request_id": current_request_id(),
+        "body_limit": {
+            "group": group,
+            "limit": limit,
+        },
+    }))
+}
+
+/// Human-readable size, e.g. `10 MB` or `512 KB`
+fn format_size(bytes: u64) -> String {
+    const MB: u64 = 1024 * 1024;
+    if bytes >= MB && bytes.is_multiple_of(MB) {
+        format!("{} MB", bytes / MB)
+    } else if bytes >= MB {
+        format!("{:.1} MB", bytes as f64 / MB as f64)
+    } else {
+        format!("{} KB", bytes.div_ceil(1024))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_state;
+
+    #[test]
+    fn test_groups() {
+        assert_eq!(
+            BodyLimitGroup::for_path("/api/v1/files/"),
+            BodyLimitGroup::Upload
+        );
+        assert_eq!(
+            BodyLimitGroup::for_path("/api/v1/audio/transcriptions"),
+            BodyLimitGroup::Upload
+        );
+        assert_eq!(
+            BodyLimitGroup::for_path("/api/chat/completions"),
+            BodyLimitGroup::Chat
+        );
+        assert_eq!(
+            BodyLimitGroup::for_path("/openai/chat/completions"),
+            BodyLimitGroup::Chat
+        );
+        assert_eq!(
+            BodyLimitGroup::for_path("/api/v1/chats/abc"),
+            BodyLimitGroup::Chat
+        );
+        assert_eq!(
+            BodyLimitGroup::for_path("/v1/chat/completions"),
+            BodyLimitGroup::Chat
+        );
+        assert_eq!(
+            BodyLimitGroup::for_path("/api/v1/tasks/title/completions"),
+            BodyLimitGroup::Chat
+        );
+        assert_eq!(
+            BodyLimitGroup::for_path("/api/v1/auths/signin"),
+            BodyLimitGroup::Default
+        );
+    }
+
+    #[test]
+    fn test_limits_follow_config() {
+        let mut config = Config::from_env().unwrap();
+        config.file_max_size = 10 * 1024 * 1024;
+        config.chat_body_max_size = 25 * 1024 * 1024;
+        config.body_max_size = 2 * 1024 * 1024;
+
+        assert_eq!(BodyLimitGroup::Upload.limit(&config), 10 * 1024 * 1024);
+        assert_eq!(
+            BodyLimitGroup::Upload.enforced_limit(&config),
+            11 * 1024 * 1024
+        );
+        assert_eq!(BodyLimitGroup::Chat.limit(&config), 25 * 1024 * 1024);
+        assert_eq!(
+            BodyLimitGroup::Default.enforced_limit(&config),
+            2 * 1024 * 1024
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_large_v1_chat_body_gets_the_chat_limit() {
+        use actix_web::{test, App};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::from_env().unwrap();
+        config.body_max_size = 1024 * 1024;
+        config.chat_body_max_size = 8 * 1024 * 1024;
+        let state = test_state(&dir, config).await;
+
+        let read_body = || web::post().to(|body: web::Bytes| async move { body.len().to_string() });
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .app_data(web::PayloadConfig::new(usize::MAX))
+                .wrap(BodyLimit)
+                .route("/v1/chat/completions", read_body())
+                .route("/api/v1/auths/signin", read_body()),
+        )
+        .await;
+
+        // An inline image easily passes the default limit
+        let body = vec![b'a'; 4 * 1024 * 1024];
+        let req = test::TestRequest::post()
+            .uri("/v1/chat/completions")
+            .set_payload(body.clone())
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), 200);
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/auths/signin")
+            .set_payload(body)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), 413);
+    }
+
+    #[actix_web::test]
+    async fn test_body_over_limit_with_understated_content_length() {
+        use actix_web::{test, App};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::from_env().unwrap();
+        config.body_max_size = 1024 * 1024;
+        let state = test_state(&dir, config).await;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .app_data(web::PayloadConfig::new(usize::MAX))
+                .wrap(BodyLimit)
+                .route(
+                    "/api/v1/auths/signin",
+                    web::post().to(|body: web::Bytes| async move { body.len().to_string() }),
+                ),
+        )
+        .await;
+
+        // The header claims a tiny body, so only counting what is read catches it
+        let req = test::TestRequest::post()
+            .uri("/api/v1/auths/signin")
+            .set_payload(vec![b'a'; 2 * 1024 * 1024])
+            .insert_header((CONTENT_LENGTH, "16"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), 413);
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["body_limit"]["group"], "default");
+        assert_eq!(body["body_limit"]["limit"], 1024 * 1024);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(2 * 1024 * 1024), "2 MB");
+        assert_eq!(format_size(1536 * 1024), "1.5 MB");
+        assert_eq!(format_size(512 * 1024), "512 KB");
+    }
+}
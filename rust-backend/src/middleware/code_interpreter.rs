@@ -1,11 +1,24 @@
 /// Code Interpreter Middleware for Automatic Code Execution
 /// This module detects code blocks in streaming chat responses and executes them automatically
 /// Similar to Python backend's middleware.py code interpreter functionality
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
-use tracing::{debug, info};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
 
 use crate::{
-    services::sandbox_executor::{SandboxExecuteResponse, SandboxExecutorClient},
+    error::{AppError, AppResult},
+    services::{
+        file::FileService,
+        sandbox_executor::{
+            SandboxArtifact, SandboxExecuteRequest, SandboxExecuteResponse, SandboxExecutorClient,
+        },
+    },
+    utils::upload,
     AppState,
 };
 
@@ -155,6 +168,7 @@ impl CodeBlockDetector {
                 | "py"
                 | "javascript"
                 | "js"
+                | "node"
                 | "bash"
                 | "sh"
                 | "shell"
@@ -171,7 +185,7 @@ impl CodeBlockDetector {
     pub fn normalize_language(language: &str) -> String {
         match language {
             "py" => "python".to_string(),
-            "js" => "javascript".to_string(),
+            "js" | "node" => "javascript".to_string(),
             "sh" | "shell" => "bash".to_string(),
             "rb" => "ruby".to_string(),
             "rs" => "rust".to_string(),
@@ -180,13 +194,128 @@ impl CodeBlockDetector {
     }
 }
 
-/// Execute a code block using the sandbox executor
-pub async fn execute_code_block(
+/// Prefix of the stdout lines running code writes to report its progress
+///
+/// The rest of the line is either a message or a JSON object like
+/// `{"message": "Fetching page 2 of 5", "current": 2, "total": 5}`.
+pub const PROGRESS_PREFIX: &str = "::progress::";
+
+/// Progress reported by a running tool
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolProgress {
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+}
+
+/// Progress reported by a line of stdout, if it's a progress line
+pub fn parse_progress_line(line: &str) -> Option<ToolProgress> {
+    let rest = line
+        .trim_end_matches('\r')
+        .strip_prefix(PROGRESS_PREFIX)?
+        .trim();
+    if rest.starts_with('{') {
+        if let Ok(progress) = serde_json::from_str::<ToolProgress>(rest) {
+            return Some(progress);
+        }
+    }
+    Some(ToolProgress {
+        message: rest.to_string(),
+        current: None,
+        total: None,
+    })
+}
+
+/// Stdout without its progress lines
+pub fn strip_progress_lines(stdout: &str) -> String {
+    stdout
+        .lines()
+        .filter(|line| parse_progress_line(line).is_none())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Finds progress lines in stdout as it streams in, when lines are split across chunks
+#[derive(Debug, Default)]
+pub struct ProgressParser {
+    /// Start of a line whose end hasn't arrived yet
+    pending: String,
+}
+
+impl ProgressParser {
+    /// Progress reported by the lines a chunk completes
+    pub fn push(&mut self, chunk: &str) -> Vec<ToolProgress> {
+        self.pending.push_str(chunk);
+        let end = match self.pending.rfind('\n') {
+            Some(end) => end,
+            None => return Vec::new(),
+        };
+        let lines: String = self.pending.drain(..=end).collect();
+        lines.lines().filter_map(parse_progress_line).collect()
+    }
+
+    /// Progress reported by a last line without a newline, once stdout ended
+    pub fn finish(&mut self) -> Option<ToolProgress> {
+        parse_progress_line(&std::mem::take(&mut self.pending))
+    }
+}
+
+/// `chat:tool:progress` event for progress reported by a running tool
+pub fn progress_event(tool_name: &str, progress: &ToolProgress) -> Value {
+    let mut data = json!({"name": tool_name});
+    if let (Some(data), Ok(Value::Object(progress))) =
+        (data.as_object_mut(), serde_json::to_value(progress))
+    {
+        data.extend(progress);
+    }
+    json!({"type": "chat:tool:progress", "data": data})
+}
+
+/// Packages listed in the code's `# requirements:` comments, such as
+/// `# requirements: pandas, numpy==1.26.4`
+pub fn parse_requirements(code: &str) -> Vec<String> {
+    let mut packages: Vec<String> = Vec::new();
+    for line in code.lines() {
+        let comment = match line.trim().strip_prefix('#') {
+            Some(comment) => comment.trim(),
+            None => continue,
+        };
+        let (key, value) = match comment.split_once(':') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        if !key.trim().eq_ignore_ascii_case("requirements") {
+            continue;
+        }
+
+        for package in value.split(|c: char| c == ',' || c.is_whitespace()) {
+            if !package.is_empty() && !packages.iter().any(|p| p == package) {
+                packages.push(package.to_string());
+            }
+        }
+    }
+    packages
+}
+
+/// Execute a code block using the sandbox executor, emitting a `chat:tool:progress` event
+/// for each progress line it writes while running
+///
+/// Code blocks with the same `session_key`, such as a chat id, run in the same sandbox
+/// session and share its state. Packages in the block's `# requirements:` comments are
+/// installed before it runs.
+pub async fn execute_code_block<E>(
     code_block: &CodeBlock,
     sandbox_client: &Arc<SandboxExecutorClient>,
     user_id: &str,
+    session_key: Option<&str>,
     timeout: Option<i32>,
-) -> Result<SandboxExecuteResponse, String> {
+    event_emitter: &E,
+) -> Result<SandboxExecuteResponse, String>
+where
+    E: Fn(Value) -> Pin<Box<dyn Future<Output = ()> + Send>>,
+{
     let language = CodeBlockDetector::normalize_language(&code_block.language);
 
     info!(
@@ -195,19 +324,143 @@ pub async fn execute_code_block(
         code_block.code.len()
     );
 
-    sandbox_client
-        .execute_code(
-            code_block.code.clone(),
-            language,
-            timeout.map(|t| t as u64),
-            Some(user_id.to_string()),
-            None,
+    let (output_tx, mut output_rx) = mpsc::unbounded_channel();
+    let request = SandboxExecuteRequest {
+        code: code_block.code.clone(),
+        language,
+        timeout: timeout.map(|t| t as u64),
+        user_id: Some(user_id.to_string()),
+        request_id: None,
+        packages: parse_requirements(&code_block.code),
+    };
+    let execution = sandbox_client.execute_code_streaming(request, session_key, output_tx);
+    tokio::pin!(execution);
+
+    let mut parser = ProgressParser::default();
+    let result = loop {
+        tokio::select! {
+            result = &mut execution => break result,
+            Some(chunk) = output_rx.recv() => {
+                for progress in parser.push(&chunk) {
+                    event_emitter(progress_event("code_interpreter", &progress)).await;
+                }
+            }
+        }
+    };
+
+    // Output that arrived with the end of the execution
+    let mut progress = Vec::new();
+    while let Ok(chunk) = output_rx.try_recv() {
+        progress.extend(parser.push(&chunk));
+    }
+    progress.extend(parser.finish());
+    for progress in progress {
+        event_emitter(progress_event("code_interpreter", &progress)).await;
+    }
+
+    result
+}
+
+/// A file an execution wrote, saved as the user's file
+#[derive(Debug, Clone)]
+pub struct StoredArtifact {
+    pub name: String,
+    pub content_type: String,
+    /// Link to the file's content
+    pub url: String,
+}
+
+/// Save the files an execution wrote as the user's files, so the chat can show and link
+/// them. Artifacts the upload rules or the user's quota don't allow are left out.
+pub async fn store_artifacts(
+    state: &actix_web::web::Data<AppState>,
+    user_id: &str,
+    result: &SandboxExecuteResponse,
+) -> Vec<StoredArtifact> {
+    let mut stored = Vec::new();
+    for artifact in &result.artifacts {
+        match store_artifact(state, user_id, artifact).await {
+            Ok(artifact) => stored.push(artifact),
+            Err(e) => warn!("Not keeping code interpreter file {}: {}", artifact.name, e),
+        }
+    }
+    stored
+}
+
+async fn store_artifact(
+    state: &actix_web::web::Data<AppState>,
+    user_id: &str,
+    artifact: &SandboxArtifact,
+) -> AppResult<StoredArtifact> {
+    let config = state.config.snapshot();
+    let service = FileService::new(&state.db);
+
+    let bytes = STANDARD
+        .decode(&artifact.content)
+        .map_err(|e| AppError::BadRequest(format!("Invalid artifact content: {}", e)))?;
+    let filename = artifact
+        .name
+        .rsplit('/')
+        .next()
+        .unwrap_or(&artifact.name)
+        .to_string();
+    let content_type =
+        upload::validate_upload(&config, &filename, Some(&artifact.mime_type), &bytes)?;
+    if config.file_user_quota.is_some() {
+        let used = service.get_storage_used_by_user(user_id).await?;
+        upload::check_user_quota(&config, used, bytes.len() as u64)?;
+    }
+
+    let file_id = uuid::Uuid::new_v4().to_string();
+    let upload_dir = std::path::Path::new(&config.upload_dir);
+    tokio::fs::create_dir_all(upload_dir).await?;
+    let file_path = upload_dir.join(&file_id);
+    tokio::fs::write(&file_path, &bytes).await?;
+    let hash = format!("{:x}", md5::compute(&bytes));
+
+    let meta = json!({
+        "source": "code_interpreter",
+        "size": bytes.len(),
+        "content_type": content_type,
+    });
+    service
+        .create_file(
+            &file_id,
+            user_id,
+            &filename,
+            &file_path.to_string_lossy(),
+            Some(&hash),
+            Some(meta),
         )
-        .await
+        .await?;
+
+    Ok(StoredArtifact {
+        name: filename,
+        content_type,
+        // Saved into the chat, so it must not be a signed URL that expires
+        url: format!("/api/v1/files/{}/content", file_id),
+    })
+}
+
+/// Markdown showing image artifacts and linking the other files
+fn format_artifacts(artifacts: &[StoredArtifact]) -> String {
+    let mut output = String::new();
+    for artifact in artifacts {
+        let name = artifact.name.replace(['[', ']'], "");
+        if artifact.content_type.starts_with("image/") {
+            output.push_str(&format!("![{}]({})\n\n", name, artifact.url));
+        } else {
+            output.push_str(&format!("📎 [{}]({})\n\n", name, artifact.url));
+        }
+    }
+    output
 }
 
-/// Format execution result for display in chat
-pub fn format_execution_result(result: &SandboxExecuteResponse) -> String {
+/// Format execution result for display in chat, with the files it wrote
+pub fn format_execution_result(
+    result: &SandboxExecuteResponse,
+    artifacts: &[StoredArtifact],
+) -> String {
     let mut output = String::new();
 
     // Add execution metadata
@@ -216,10 +469,11 @@ pub fn format_execution_result(result: &SandboxExecuteResponse) -> String {
         result.execution_time_ms
     ));
 
-    // Add stdout if present
-    if !result.stdout.is_empty() {
+    // Add stdout if present, leaving out the progress it reported while running
+    let stdout = strip_progress_lines(&result.stdout);
+    if !stdout.trim().is_empty() {
         output.push_str("**Output:**\n```\n");
-        output.push_str(&result.stdout);
+        output.push_str(&stdout);
         output.push_str("\n```\n\n");
     }
 
@@ -251,12 +505,18 @@ pub fn format_execution_result(result: &SandboxExecuteResponse) -> String {
         output.push_str(&format!("**Error:** {}\n\n", error));
     }
 
+    // Add the files the code wrote
+    if !artifacts.is_empty() {
+        output.push_str("**Files:**\n\n");
+        output.push_str(&format_artifacts(artifacts));
+    }
+
     output
 }
 
 /// Check if code interpreter is enabled in config
 pub fn is_code_interpreter_enabled(state: &actix_web::web::Data<AppState>) -> bool {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     config.enable_code_interpreter && config.code_interpreter_engine == "sandbox"
 }
 
@@ -266,9 +526,8 @@ pub fn is_code_interpreter_enabled(state: &actix_web::web::Data<AppState>) -> bo
 pub fn get_sandbox_client(
     state: &actix_web::web::Data<AppState>,
 ) -> Option<Arc<SandboxExecutorClient>> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let sandbox_url = config.code_interpreter_sandbox_url.clone()?;
-    drop(config);
 
     // Check if existing client has the same URL, otherwise create a new one
     if let Some(existing_client) = state.sandbox_executor_client.as_ref() {
@@ -283,7 +542,7 @@ pub fn get_sandbox_client(
 
 /// Get code interpreter timeout from config
 pub fn get_code_interpreter_timeout(state: &actix_web::web::Data<AppState>) -> Option<i32> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     config.code_interpreter_sandbox_timeout
 }
 
@@ -339,6 +598,8 @@ mod tests {
     fn test_language_normalization() {
         assert_eq!(CodeBlockDetector::normalize_language("py"), "python");
         assert_eq!(CodeBlockDetector::normalize_language("js"), "javascript");
+        assert_eq!(CodeBlockDetector::normalize_language("node"), "javascript");
+        assert_eq!(CodeBlockDetector::normalize_language("bash"), "bash");
         assert_eq!(CodeBlockDetector::normalize_language("sh"), "bash");
         assert_eq!(CodeBlockDetector::normalize_language("rb"), "ruby");
     }
@@ -351,4 +612,112 @@ mod tests {
         assert!(!CodeBlockDetector::is_executable_language("markdown"));
         assert!(!CodeBlockDetector::is_executable_language("text"));
     }
+
+    #[test]
+    fn test_progress_lines_are_parsed_across_chunks() {
+        let mut parser = ProgressParser::default();
+        assert!(parser.push("starting\n::progr").is_empty());
+        assert_eq!(
+            parser.push("ess:: Fetching page 1 of 5\r\n::progress:: {\"message\": "),
+            vec![ToolProgress {
+                message: "Fetching page 1 of 5".to_string(),
+                current: None,
+                total: None,
+            }]
+        );
+        assert!(parser
+            .push("\"Fetching page 2 of 5\", \"current\": 2")
+            .is_empty());
+        assert_eq!(
+            parser.push(", \"total\": 5}\ndone\n::progress:: Saving"),
+            vec![ToolProgress {
+                message: "Fetching page 2 of 5".to_string(),
+                current: Some(2),
+                total: Some(5),
+            }]
+        );
+        assert_eq!(parser.finish().unwrap().message, "Saving");
+        assert!(parser.finish().is_none());
+
+        // Only lines starting with the prefix are progress
+        assert!(parse_progress_line("print('::progress:: no')").is_none());
+    }
+
+    #[test]
+    fn test_progress_events_and_output() {
+        let progress = parse_progress_line(r#"::progress:: {"message": "Page 2", "current": 2}"#);
+        assert_eq!(
+            progress_event("code_interpreter", &progress.unwrap()),
+            json!({
+                "type": "chat:tool:progress",
+                "data": {"name": "code_interpreter", "message": "Page 2", "current": 2}
+            })
+        );
+
+        let result = SandboxExecuteResponse {
+            execution_id: "e1".to_string(),
+            status: "success".to_string(),
+            stdout: "::progress:: Loading\nanswer: 42\n::progress:: Done".to_string(),
+            stderr: String::new(),
+            result: None,
+            execution_time_ms: 3,
+            memory_used_mb: None,
+            exit_code: Some(0),
+            error: None,
+            artifacts: Vec::new(),
+        };
+        let formatted = format_execution_result(&result, &[]);
+        assert!(formatted.contains("```\nanswer: 42\n```"));
+        assert!(!formatted.contains("Loading"));
+        assert!(!formatted.contains("**Files:**"));
+    }
+
+    #[test]
+    fn test_artifacts_are_shown_and_linked() {
+        let result: SandboxExecuteResponse = serde_json::from_value(json!({
+            "execution_id": "e1",
+            "status": "success",
+            "stdout": "",
+            "stderr": "",
+            "result": null,
+            "execution_time_ms": 12,
+            "memory_used_mb": null,
+            "exit_code": 0,
+            "error": null,
+        }))
+        .unwrap();
+        assert!(result.artifacts.is_empty());
+
+        let artifacts = vec![
+            StoredArtifact {
+                name: "plot.png".to_string(),
+                content_type: "image/png".to_string(),
+                url: "/api/v1/files/f1/content".to_string(),
+            },
+            StoredArtifact {
+                name: "data[1].csv".to_string(),
+                content_type: "text/csv".to_string(),
+                url: "/api/v1/files/f2/content".to_string(),
+            },
+        ];
+        let formatted = format_execution_result(&result, &artifacts);
+        assert!(formatted.ends_with(
+            "**Files:**\n\n![plot.png](/api/v1/files/f1/content)\n\n\
+             📎 [data1.csv](/api/v1/files/f2/content)\n\n"
+        ));
+    }
+
+    #[test]
+    fn test_parse_requirements() {
+        let code = "# Requirements: pandas, numpy==1.26.4\n\
+                    #requirements: matplotlib seaborn,pandas\n\
+                    # requirements are listed above\n\
+                    print('# requirements: requests')\n\
+                    import pandas as pd";
+        assert_eq!(
+            parse_requirements(code),
+            vec!["pandas", "numpy==1.26.4", "matplotlib", "seaborn"]
+        );
+        assert!(parse_requirements("print('hi')").is_empty());
+    }
 }
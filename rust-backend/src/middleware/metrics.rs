@@ -0,0 +1,116 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+use std::future::{ready, Ready};
+use std::time::Instant;
+
+use crate::utils::metrics::metrics;
+
+/// Route label for requests that match no registered route (404s, SPA assets)
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Middleware recording request counts, latency and in-flight requests
+///
+/// Requests are labeled with the route template they matched (`/api/v1/chats/{id}`)
+/// rather than the raw path, so IDs in URLs never create new series. Latency is
+/// measured until the response headers are ready; streamed completions are timed
+/// separately by `chat_stream_duration_seconds`.
+pub struct HttpMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for HttpMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = HttpMetricsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(HttpMetricsMiddleware { service }))
+    }
+}
+
+pub struct HttpMetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for HttpMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let method = req.method().to_string();
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+        metrics().request_started(&route);
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let result = fut.await;
+            let status = match &result {
+                Ok(res) => res.status(),
+                Err(err) => err.as_response_error().status_code(),
+            };
+            metrics().request_finished(
+                &method,
+                &route,
+                status.as_u16(),
+                start.elapsed().as_secs_f64(),
+            );
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App, HttpResponse};
+
+    #[actix_web::test]
+    async fn test_requests_labeled_by_route_template() {
+        let app = test::init_service(App::new().wrap(HttpMetrics).service(
+            web::scope("/api/v1/metrics-test").route(
+                "/{id}",
+                web::get().to(|| async { HttpResponse::Ok().finish() }),
+            ),
+        ))
+        .await;
+
+        for id in ["a", "b", "c"] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/api/v1/metrics-test/{}", id))
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get()
+            .uri("/metrics-test-missing/1")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let out = metrics().render();
+        assert!(out.contains(
+            "http_requests_total{method=\"GET\",route=\"/api/v1/metrics-test/{id}\",status=\"2xx\"} 3"
+        ));
+        assert!(out.contains("route=\"unmatched\",status=\"4xx\""));
+        assert!(!out.contains("/api/v1/metrics-test/a"));
+    }
+}
@@ -1,9 +1,21 @@
+pub mod access_log;
 pub mod audit;
 pub mod auth;
+pub mod body_limit;
 pub mod code_interpreter;
+pub mod metrics;
+pub mod openai_errors;
 pub mod rate_limit;
+pub mod real_ip;
 pub mod request_id;
 pub mod security_headers;
 
+pub use access_log::AccessLog;
+pub use audit::Audit;
 pub use auth::*;
+pub use body_limit::BodyLimit;
+pub use metrics::HttpMetrics;
+pub use openai_errors::OpenAIErrors;
+pub use real_ip::RealIp;
+pub use request_id::RequestIdMiddleware;
 pub use security_headers::SecurityHeaders;
@@ -0,0 +1,318 @@
+use actix_web::{
+    body::{self, BoxBody, EitherBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    http::{
+        header::{self, HeaderValue},
+        StatusCode,
+    },
+    Error, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use serde_json::{json, Map, Value};
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+/// Middleware that renders error responses in OpenAI's error envelope
+///
+/// OpenAI SDKs read failures from `{"error": {"message", "type", "param", "code"}}`,
+/// while the rest of the API answers with FastAPI's `{"detail": ...}`. Error
+/// responses passing through here get the `error` object added next to their existing
+/// fields, so SDK clients and the web UI can both read them. Bodies that already carry
+/// an `error` object (such as upstream provider errors) are passed through unchanged.
+///
+/// Used on the `/openai` scope and the legacy chat endpoints.
+pub struct OpenAIErrors;
+
+impl<S, B> Transform<S, ServiceRequest> for OpenAIErrors
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = OpenAIErrorsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(OpenAIErrorsMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct OpenAIErrorsMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for OpenAIErrorsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = match fut.await {
+                Ok(res) => res,
+                // Errors from inner middleware (auth, rate limits) haven't been rendered yet.
+                // They stay errors, carrying the rendered response: holding a clone of the
+                // request for a `ServiceResponse` would break routing, which must own it.
+                Err(err) => {
+                    let response = into_openai_error(err.error_response()).await;
+                    return Err(InternalError::from_response(err, response).into());
+                }
+            };
+
+            if !is_error_response(res.response()) {
+                return Ok(res.map_into_left_body());
+            }
+
+            let (req, response) = res.into_parts();
+            let response = into_openai_error(response.map_into_boxed_body()).await;
+            Ok(ServiceResponse::new(req, response).map_into_right_body())
+        })
+    }
+}
+
+fn is_error_response<B>(response: &HttpResponse<B>) -> bool {
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return false;
+    }
+    // Streams are never buffered
+    !response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|ct| ct.starts_with("text/event-stream"))
+        .unwrap_or(false)
+}
+
+async fn into_openai_error(response: HttpResponse<BoxBody>) -> HttpResponse<BoxBody> {
+    let status = response.status();
+    let (head, body) = response.into_parts();
+    let bytes = body::to_bytes(body).await.unwrap_or_default();
+
+    let mut fields = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(Value::Object(fields)) => fields,
+        _ => {
+            let text = String::from_utf8_lossy(&bytes).trim().to_string();
+            let mut fields = Map::new();
+            fields.insert("detail".to_string(), Value::String(text));
+            fields
+        }
+    };
+    if fields.get("error").map(Value::is_object).unwrap_or(false) {
+        return head.set_body(BoxBody::new(bytes));
+    }
+    fields.insert(
+        "error".to_string(),
+        openai_error(status, fields.get("detail")),
+    );
+
+    let mut response = head.set_body(BoxBody::new(Value::Object(fields).to_string()));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+/// The OpenAI `error` object for a status and FastAPI-style `detail`
+pub fn openai_error(status: StatusCode, detail: Option<&Value>) -> Value {
+    let (message, param) = match detail {
+        Some(Value::String(message)) if !message.is_empty() => (message.clone(), None),
+        // Field errors: `[{"loc": ["body", "model"], "msg": "...", "type": "..."}]`
+        Some(Value::Array(errors)) if !errors.is_empty() => {
+            let messages: Vec<String> = errors
+                .iter()
+                .map(|e| match field_name(e) {
+                    Some(field) => format!("{}: {}", field, e["msg"].as_str().unwrap_or("")),
+                    None => e["msg"].as_str().unwrap_or("").to_string(),
+                })
+                .collect();
+            (messages.join("; "), field_name(&errors[0]))
+        }
+        _ => (
+            status
+                .canonical_reason()
+                .unwrap_or("Request failed")
+                .to_string(),
+            None,
+        ),
+    };
+
+    let (error_type, code) = match status {
+        StatusCode::UNAUTHORIZED => ("invalid_request_error", Some("invalid_api_key")),
+        StatusCode::FORBIDDEN => ("invalid_request_error", Some("insufficient_permissions")),
+        StatusCode::NOT_FOUND => ("invalid_request_error", Some("not_found")),
+        StatusCode::TOO_MANY_REQUESTS => ("rate_limit_error", Some("rate_limit_exceeded")),
+        s if s.is_server_error() => ("server_error", None),
+        _ => ("invalid_request_error", None),
+    };
+
+    json!({
+        "message": message,
+        "type": error_type,
+        "param": param,
+        "code": code,
+    })
+}
+
+/// Last path segment of a field error's `loc`, skipping the `body`/`query` prefix
+fn field_name(error: &Value) -> Option<String> {
+    let loc = error["loc"].as_array()?;
+    if loc.len() < 2 {
+        return None;
+    }
+    loc.last().and_then(|l| match l {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use actix_web::middleware::{from_fn, Next};
+    use actix_web::test as actix_test;
+    use actix_web::{web, App};
+    use serde::Deserialize;
+
+    /// What OpenAI SDKs deserialize from an error body
+    #[derive(Deserialize)]
+    struct SdkErrorBody {
+        error: SdkError,
+    }
+
+    #[derive(Deserialize)]
+    struct SdkError {
+        message: String,
+        #[serde(rename = "type")]
+        error_type: String,
+        param: Option<String>,
+        code: Option<String>,
+    }
+
+    async fn read_sdk_error(resp: ServiceResponse<impl MessageBody>) -> SdkError {
+        let body = actix_test::read_body(resp).await;
+        serde_json::from_slice::<SdkErrorBody>(&body).unwrap().error
+    }
+
+    #[actix_web::test]
+    async fn test_sdk_client_reads_error_message() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(OpenAIErrors)
+                .route(
+                    "/unauthorized",
+                    web::post().to(|| async {
+                        Err::<HttpResponse, _>(AppError::Unauthorized(
+                            "Invalid API key".to_string(),
+                        ))
+                    }),
+                )
+                .route(
+                    "/limited",
+                    web::post().to(|| async {
+                        HttpResponse::TooManyRequests()
+                            .insert_header((header::RETRY_AFTER, "30"))
+                            .json(json!({ "detail": "Too many requests" }))
+                    }),
+                )
+                .route(
+                    "/upstream",
+                    web::post().to(|| async {
+                        HttpResponse::BadRequest().json(json!({
+                            "error": { "message": "Unknown model", "type": "invalid_request_error" }
+                        }))
+                    }),
+                )
+                .service(
+                    web::resource("/guarded")
+                        .wrap(from_fn(
+                            |_req: ServiceRequest, _next: Next<BoxBody>| async {
+                                Err::<ServiceResponse<BoxBody>, Error>(
+                                    AppError::Forbidden("Admin access required".to_string()).into(),
+                                )
+                            },
+                        ))
+                        .route(web::post().to(HttpResponse::Ok)),
+                )
+                .route(
+                    "/ok",
+                    web::post()
+                        .to(|| async { HttpResponse::Ok().json(json!({ "id": "chatcmpl" })) }),
+                ),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::post()
+            .uri("/unauthorized")
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let error = read_sdk_error(resp).await;
+        assert_eq!(error.message, "Invalid API key");
+        assert_eq!(error.error_type, "invalid_request_error");
+        assert_eq!(error.code.as_deref(), Some("invalid_api_key"));
+        assert_eq!(error.param, None);
+
+        // Errors raised by inner middleware, like the auth check
+        let req = actix_test::TestRequest::post().uri("/guarded").to_request();
+        let resp = app.call(req).await.err().unwrap().error_response();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let body = body::to_bytes(resp.into_body()).await.unwrap();
+        let error = serde_json::from_slice::<SdkErrorBody>(&body).unwrap().error;
+        assert_eq!(error.message, "Admin access required");
+        assert_eq!(error.code.as_deref(), Some("insufficient_permissions"));
+
+        let req = actix_test::TestRequest::post().uri("/limited").to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "30");
+        let error = read_sdk_error(resp).await;
+        assert_eq!(error.message, "Too many requests");
+        assert_eq!(error.error_type, "rate_limit_error");
+
+        let req = actix_test::TestRequest::post()
+            .uri("/upstream")
+            .to_request();
+        let error = read_sdk_error(actix_test::call_service(&app, req).await).await;
+        assert_eq!(error.message, "Unknown model");
+
+        let req = actix_test::TestRequest::post().uri("/ok").to_request();
+        let body: Value = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body, json!({ "id": "chatcmpl" }));
+    }
+
+    #[test]
+    fn test_field_errors_name_the_param() {
+        let error = openai_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Some(&json!([
+                { "loc": ["body", "model"], "msg": "field required", "type": "value_error.required" }
+            ])),
+        );
+        assert_eq!(error["message"], "model: field required");
+        assert_eq!(error["param"], "model");
+        assert_eq!(error["type"], "invalid_request_error");
+
+        let error = openai_error(StatusCode::BAD_GATEWAY, None);
+        assert_eq!(error["message"], "Bad Gateway");
+        assert_eq!(error["type"], "server_error");
+    }
+}
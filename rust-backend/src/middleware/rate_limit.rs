@@ -1,2 +1,562 @@
-// TODO: Implement rate limiting middleware for actix-web
-// This middleware will limit the number of requests per time window
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue, RETRY_AFTER},
+    web, Error, HttpMessage, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::middleware::request_id::current_request_id;
+use crate::middleware::{AuthUser, RealIp};
+use crate::utils::redis_health::RedisPool;
+use crate::AppState;
+
+const REDIS_KEY_PREFIX: &str = "open-webui:ratelimit";
+
+/// Groups of routes sharing a limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteGroup {
+    /// Sign-in and sign-up
+    Auth,
+    /// Chat completions
+    Chat,
+    /// File uploads
+    Upload,
+    /// Task generations (titles, tags, follow-ups, ...)
+    Tasks,
+}
+
+impl RouteGroup {
+    pub const ALL: [RouteGroup; 4] = [
+        RouteGroup::Auth,
+        RouteGroup::Chat,
+        RouteGroup::Upload,
+        RouteGroup::Tasks,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RouteGroup::Auth => "auth",
+            RouteGroup::Chat => "chat",
+            RouteGroup::Upload => "upload",
+            RouteGroup::Tasks => "tasks",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        RouteGroup::ALL
+            .into_iter()
+            .find(|group| group.as_str().eq_ignore_ascii_case(value.trim()))
+    }
+}
+
+/// At most `requests` requests per `window_secs` seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RateLimitRule {
+    pub requests: u64,
+    pub window_secs: u64,
+}
+
+impl RateLimitRule {
+    /// Parse `"<requests>/<seconds>"`; `Ok(None)` means unlimited (empty or zero)
+    pub fn parse(value: &str) -> Result<Option<Self>, String> {
+        let value = value.trim();
+        if value.is_empty() || value == "0" {
+            return Ok(None);
+        }
+        let parsed = value.split_once('/').and_then(|(requests, window)| {
+            Some((
+                requests.trim().parse::<u64>().ok()?,
+                window.trim().parse::<u64>().ok()?,
+            ))
+        });
+        match parsed {
+            Some((0, _)) => Ok(None),
+            Some((requests, window_secs)) if window_secs > 0 => Ok(Some(RateLimitRule {
+                requests,
+                window_secs,
+            })),
+            _ => Err(format!(
+                "invalid rate limit '{}', expected <requests>/<seconds>",
+                value
+            )),
+        }
+    }
+}
+
+/// The limits in force, resolved from the config
+#[derive(Debug, Clone, Serialize)]
+pub struct RateLimitPolicy {
+    pub enabled: bool,
+    pub exempt_roles: Vec<String>,
+    /// Default rule per group; groups without a rule are unlimited
+    pub groups: BTreeMap<RouteGroup, RateLimitRule>,
+    /// role -> group -> rule overriding the group default; `None` lifts the limit
+    pub roles: BTreeMap<String, BTreeMap<RouteGroup, Option<RateLimitRule>>>,
+}
+
+impl RateLimitPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        let mut groups = BTreeMap::new();
+        for (group, value) in [
+            (RouteGroup::Auth, &config.rate_limit_auth),
+            (RouteGroup::Chat, &config.rate_limit_chat),
+            (RouteGroup::Upload, &config.rate_limit_upload),
+            (RouteGroup::Tasks, &config.rate_limit_tasks),
+        ] {
+            match RateLimitRule::parse(value) {
+                Ok(Some(rule)) => {
+                    groups.insert(group, rule);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Ignoring {} rate limit: {}", group.as_str(), e),
+            }
+        }
+
+        // Entries look like "pending:chat=5/60"
+        let mut roles: BTreeMap<String, BTreeMap<RouteGroup, Option<RateLimitRule>>> =
+            BTreeMap::new();
+        for entry in &config.rate_limit_role_rules {
+            let parsed = entry.split_once('=').and_then(|(target, rule)| {
+                let (role, group) = target.split_once(':')?;
+                let rule = RateLimitRule::parse(rule).ok()?;
+                Some((role.trim().to_string(), RouteGroup::parse(group)?, rule))
+            });
+            match parsed {
+                Some((role, group, rule)) => {
+                    roles.entry(role).or_default().insert(group, rule);
+                }
+                None => tracing::warn!("Ignoring invalid rate limit role rule '{}'", entry),
+            }
+        }
+
+        RateLimitPolicy {
+            enabled: config.enable_rate_limit,
+            exempt_roles: config.rate_limit_exempt_roles.clone(),
+            groups,
+            roles,
+        }
+    }
+
+    /// The rule applying to a request of `group` made with `role` (`None` when signed out)
+    pub fn rule_for(&self, group: RouteGroup, role: Option<&str>) -> Option<RateLimitRule> {
+        if !self.enabled {
+            return None;
+        }
+        if let Some(role) = role {
+            if self.exempt_roles.iter().any(|r| r == role) {
+                return None;
+            }
+            if let Some(rule) = self.roles.get(role).and_then(|rules| rules.get(&group)) {
+                return *rule;
+            }
+        }
+        self.groups.get(&group).copied()
+    }
+}
+
+/// Result of counting one request against a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allowed { remaining: u64 },
+    Limited { retry_after_secs: u64 },
+}
+
+/// Sliding-window estimate: the previous window's count weighted by how much of it
+/// still overlaps the sliding window, plus the current window's count
+fn decide(rule: RateLimitRule, previous: u64, current: u64, now_ms: u64) -> Decision {
+    let window_ms = rule.window_secs * 1000;
+    let elapsed_ms = now_ms % window_ms;
+    let overlap = 1.0 - elapsed_ms as f64 / window_ms as f64;
+    let estimate = previous as f64 * overlap + current as f64;
+    let limit = rule.requests as f64;
+
+    if estimate <= limit {
+        return Decision::Allowed {
+            remaining: (limit - estimate).floor() as u64,
+        };
+    }
+
+    // Time until the previous window's share has decayed enough for one more request,
+    // or until the window rolls over when the current window alone is full
+    let wait_ms = if current < rule.requests && previous > 0 {
+        let needed_overlap = (limit - current as f64) / previous as f64;
+        (((1.0 - needed_overlap) * window_ms as f64) as u64).saturating_sub(elapsed_ms)
+    } else {
+        window_ms - elapsed_ms
+    };
+    Decision::Limited {
+        retry_after_secs: wait_ms.div_ceil(1000).max(1),
+    }
+}
+
+struct WindowCounter {
+    window_secs: u64,
+    window: u64,
+    previous: u64,
+    current: u64,
+}
+
+/// Process-local counters, used when Redis is disabled or unreachable
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    counters: Mutex<HashMap<String, WindowCounter>>,
+}
+
+impl MemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hit(&self, key: &str, rule: RateLimitRule, now_ms: u64) -> Decision {
+        let window = now_ms / 1000 / rule.window_secs;
+        let mut counters = self.counters.lock().unwrap();
+        let counter = counters
+            .entry(key.to_string())
+            .or_insert_with(|| WindowCounter {
+                window_secs: rule.window_secs,
+                window,
+                previous: 0,
+                current: 0,
+            });
+
+        if counter.window_secs != rule.window_secs || counter.window + 1 < window {
+            *counter = WindowCounter {
+                window_secs: rule.window_secs,
+                window,
+                previous: 0,
+                current: 0,
+            };
+        } else if counter.window + 1 == window {
+            counter.previous = counter.current;
+            counter.current = 0;
+            counter.window = window;
+        }
+
+        let decision = decide(rule, counter.previous, counter.current + 1, now_ms);
+        if matches!(decision, Decision::Allowed { .. }) {
+            counter.current += 1;
+        }
+        decision
+    }
+
+    /// Drop counters that no longer affect any decision
+    pub fn cleanup(&self, now_ms: u64) {
+        self.counters
+            .lock()
+            .unwrap()
+            .retain(|_, counter| now_ms / 1000 / counter.window_secs <= counter.window + 1);
+    }
+}
+
+async fn redis_hit(
+    pool: &RedisPool,
+    key: &str,
+    rule: RateLimitRule,
+    now_ms: u64,
+) -> Result<Decision, String> {
+    let window = now_ms / 1000 / rule.window_secs;
+    let current_key = format!("{}:{}:{}", REDIS_KEY_PREFIX, key, window);
+    let previous_key = format!("{}:{}:{}", REDIS_KEY_PREFIX, key, window.saturating_sub(1));
+
+    let mut conn = pool.get().await.ok_or("Redis unavailable")?;
+    let (current, previous): (u64, Option<u64>) = redis::pipe()
+        .atomic()
+        .incr(&current_key, 1)
+        .expire(&current_key, (rule.window_secs * 2) as i64)
+        .ignore()
+        .get(&previous_key)
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| {
+            pool.health().mark_failed(&e);
+            e.to_string()
+        })?;
+
+    let decision = decide(rule, previous.unwrap_or(0), current, now_ms);
+    if matches!(decision, Decision::Limited { .. }) {
+        // Rejected requests don't count against the window
+        let _ = redis::cmd("DECR")
+            .arg(&current_key)
+            .query_async::<i64>(&mut conn)
+            .await;
+    }
+    Ok(decision)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Middleware limiting how often a user (or, when signed out, an IP address) may
+/// call the routes of a [`RouteGroup`]
+///
+/// Counters are kept in Redis when `ENABLE_REDIS` is on, so every replica shares
+/// them, and in memory otherwise (or while Redis is unreachable). Register it inside
+/// `AuthMiddleware` so the user's id and role are known.
+///
+/// Requests over the limit get a 429 with `Retry-After`.
+pub struct RateLimit {
+    group: RouteGroup,
+}
+
+impl RateLimit {
+    pub fn new(group: RouteGroup) -> Self {
+        RateLimit { group }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            group: self.group,
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    group: RouteGroup,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let group = self.group;
+
+        Box::pin(async move {
+            let state = match req.app_data::<web::Data<AppState>>() {
+                Some(state) => state.clone(),
+                None => return service.call(req).await.map(|res| res.map_into_left_body()),
+            };
+
+            let auth_user = req.extensions().get::<AuthUser>().cloned();
+            let (identity, role) = match auth_user {
+                Some(auth_user) => (
+                    format!("user:{}", auth_user.user.id),
+                    Some(auth_user.user.role),
+                ),
+                None => (format!("ip:{}", RealIp::of(req.request())), None),
+            };
+
+            let (rule, use_redis) = {
+                let config = state.config.snapshot();
+                let policy = RateLimitPolicy::from_config(&config);
+                (policy.rule_for(group, role.as_deref()), config.enable_redis)
+            };
+            let rule = match rule {
+                Some(rule) => rule,
+                None => return service.call(req).await.map(|res| res.map_into_left_body()),
+            };
+
+            let key = format!("{}:{}", group.as_str(), identity);
+            let now = now_ms();
+            let redis_decision = match (&state.redis, use_redis) {
+                (Some(pool), true) => match redis_hit(pool, &key, rule, now).await {
+                    Ok(decision) => Some(decision),
+                    Err(e) => {
+                        // Outages are logged once by the pool; count locally meanwhile
+                        tracing::debug!("Rate limit store unavailable, counting locally: {}", e);
+                        None
+                    }
+                },
+                _ => None,
+            };
+            let decision =
+                redis_decision.unwrap_or_else(|| state.request_rate_limits.hit(&key, rule, now));
+
+            match decision {
+                Decision::Allowed { remaining } => {
+                    let mut res = service.call(req).await?;
+                    let headers = res.headers_mut();
+                    headers.insert(
+                        HeaderName::from_static("x-ratelimit-limit"),
+                        HeaderValue::from(rule.requests),
+                    );
+                    headers.insert(
+                        HeaderName::from_static("x-ratelimit-remaining"),
+                        HeaderValue::from(remaining),
+                    );
+                    Ok(res.map_into_left_body())
+                }
+                Decision::Limited { retry_after_secs } => {
+                    tracing::warn!(
+                        "Rate limit exceeded for {} on {} ({} requests per {}s)",
+                        identity,
+                        group.as_str(),
+                        rule.requests,
+                        rule.window_secs
+                    );
+                    let response = too_many_requests(group, rule, retry_after_secs);
+                    Ok(req.into_response(response).map_into_right_body())
+                }
+            }
+        })
+    }
+}
+
+fn too_many_requests(
+    group: RouteGroup,
+    rule: RateLimitRule,
+    retry_after_secs: u64,
+) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, retry_after_secs.to_string()))
+        .insert_header(("x-ratelimit-limit", rule.requests.to_string()))
+        .insert_header(("x-ratelimit-remaining", "0"))
+        .json(json!({
+            "detail": format!(
+                "Too many requests, please retry in {} seconds",
+                retry_after_secs
+            ),
+            "request_id": current_request_id(),
+            "rate_limit": {
+                "group": group,
+                "limit": rule.requests,
+                "window": rule.window_secs,
+                "retry_after": retry_after_secs,
+            },
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULE: RateLimitRule = RateLimitRule {
+        requests: 2,
+        window_secs: 60,
+    };
+
+    #[test]
+    fn test_parse_rule() {
+        assert_eq!(
+            RateLimitRule::parse("20/60").unwrap(),
+            Some(RateLimitRule {
+                requests: 20,
+                window_secs: 60
+            })
+        );
+        assert_eq!(RateLimitRule::parse("").unwrap(), None);
+        assert_eq!(RateLimitRule::parse("0/60").unwrap(), None);
+        assert!(RateLimitRule::parse("20").is_err());
+        assert!(RateLimitRule::parse("20/0").is_err());
+    }
+
+    #[test]
+    fn test_memory_store_limits_and_recovers() {
+        let store = MemoryRateLimitStore::new();
+        let start = 60_000 * 1000;
+
+        assert_eq!(
+            store.hit("chat:user:a", RULE, start),
+            Decision::Allowed { remaining: 1 }
+        );
+        assert_eq!(
+            store.hit("chat:user:a", RULE, start + 1),
+            Decision::Allowed { remaining: 0 }
+        );
+        assert_eq!(
+            store.hit("chat:user:a", RULE, start + 1000),
+            Decision::Limited {
+                retry_after_secs: 59
+            }
+        );
+        // Other identities have their own counters
+        assert!(matches!(
+            store.hit("chat:user:b", RULE, start + 1000),
+            Decision::Allowed { .. }
+        ));
+
+        // Halfway through the next window the previous requests only count for half
+        assert!(matches!(
+            store.hit("chat:user:a", RULE, start + 90_000),
+            Decision::Allowed { .. }
+        ));
+        assert!(matches!(
+            store.hit("chat:user:a", RULE, start + 90_001),
+            Decision::Limited { .. }
+        ));
+
+        store.cleanup(start + 300_000);
+        assert!(store.counters.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_policy_roles() {
+        let policy = RateLimitPolicy {
+            enabled: true,
+            exempt_roles: vec!["admin".to_string()],
+            groups: BTreeMap::from([(RouteGroup::Chat, RULE)]),
+            roles: BTreeMap::from([(
+                "pending".to_string(),
+                BTreeMap::from([(
+                    RouteGroup::Chat,
+                    Some(RateLimitRule {
+                        requests: 1,
+                        window_secs: 60,
+                    }),
+                )]),
+            )]),
+        };
+
+        assert_eq!(policy.rule_for(RouteGroup::Chat, Some("admin")), None);
+        assert_eq!(policy.rule_for(RouteGroup::Chat, Some("user")), Some(RULE));
+        assert_eq!(policy.rule_for(RouteGroup::Chat, None), Some(RULE));
+        assert_eq!(
+            policy
+                .rule_for(RouteGroup::Chat, Some("pending"))
+                .map(|r| r.requests),
+            Some(1)
+        );
+        assert_eq!(policy.rule_for(RouteGroup::Upload, Some("user")), None);
+    }
+
+    #[actix_web::test]
+    async fn test_too_many_requests_response() {
+        let resp = too_many_requests(RouteGroup::Auth, RULE, 12);
+        assert_eq!(resp.status().as_u16(), 429);
+        assert_eq!(resp.headers().get(RETRY_AFTER).unwrap(), "12");
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["rate_limit"]["group"], "auth");
+        assert_eq!(body["rate_limit"]["retry_after"], 12);
+        assert!(body["detail"].as_str().unwrap().contains("12 seconds"));
+    }
+}
@@ -0,0 +1,245 @@
+use actix_web::{
+    dev::{Payload, ServiceRequest},
+    http::header::{HeaderMap, FORWARDED},
+    web, FromRequest, HttpMessage, HttpRequest,
+};
+use ipnet::IpNet;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::{ready, Ready};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::AppState;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// The client's IP address, with forwarding headers honoured only from trusted proxies
+///
+/// Reverse proxies listed in `TRUSTED_PROXIES` may pass the client address on in
+/// `X-Forwarded-For` (or `Forwarded` when there is none). The address is the right-most
+/// entry not itself a trusted proxy, since everything to its left was written by the
+/// client. From any other peer the headers are ignored and the peer address is used.
+/// Connections over a Unix socket have no peer address and count as trusted.
+///
+/// Resolved once per request by `RequestIdMiddleware`; `None` when there is no peer
+/// address to fall back on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RealIp(pub Option<IpAddr>);
+
+impl RealIp {
+    /// The resolved address of `req`, computing it if no middleware has yet
+    pub fn of(req: &HttpRequest) -> RealIp {
+        if let Some(ip) = req.extensions().get::<RealIp>() {
+            return *ip;
+        }
+        let ip = match req.app_data::<web::Data<AppState>>() {
+            Some(state) => {
+                let config = state.config.snapshot();
+                resolve(peer_ip(req), req.headers(), &config.trusted_proxies)
+            }
+            None => peer_ip(req),
+        };
+        RealIp(ip)
+    }
+
+    /// Resolve the address of a request in middleware and remember it for the handler
+    pub fn resolve_for(req: &ServiceRequest) -> RealIp {
+        let ip = RealIp::of(req.request());
+        req.extensions_mut().insert(ip);
+        ip
+    }
+}
+
+impl fmt::Display for RealIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(ip) => write!(f, "{}", ip),
+            None => write!(f, "unknown"),
+        }
+    }
+}
+
+impl FromRequest for RealIp {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(RealIp::of(req)))
+    }
+}
+
+fn peer_ip(req: &HttpRequest) -> Option<IpAddr> {
+    req.peer_addr().map(|addr| addr.ip())
+}
+
+fn is_trusted(ip: IpAddr, trusted: &[IpNet]) -> bool {
+    let ip = ip.to_canonical();
+    trusted.iter().any(|net| net.contains(&ip))
+}
+
+/// The client address for a request from `peer` carrying `headers`
+pub fn resolve(peer: Option<IpAddr>, headers: &HeaderMap, trusted: &[IpNet]) -> Option<IpAddr> {
+    let peer_trusted = match peer {
+        Some(peer) => is_trusted(peer, trusted),
+        None => !trusted.is_empty(),
+    };
+    if !peer_trusted {
+        return peer;
+    }
+
+    let mut hops = forwarded_for(headers);
+    if hops.is_empty() {
+        hops = forwarded(headers);
+    }
+
+    let mut client = peer;
+    for hop in hops.iter().rev() {
+        // An entry that isn't an address can only have come from the client
+        let ip = match parse_hop(hop) {
+            Some(ip) => ip,
+            None => break,
+        };
+        client = Some(ip);
+        if !is_trusted(ip, trusted) {
+            break;
+        }
+    }
+    client
+}
+
+/// `X-Forwarded-For` entries, from the client side to the nearest proxy
+fn forwarded_for(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(X_FORWARDED_FOR)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|hop| hop.trim().to_string())
+        .filter(|hop| !hop.is_empty())
+        .collect()
+}
+
+/// The `for=` parameters of RFC 7239 `Forwarded` headers, in order
+fn forwarded(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(FORWARDED)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| value.trim().trim_matches('"').to_string())
+            })
+        })
+        .collect()
+}
+
+/// An address as proxies write it: bare, with a port, or bracketed IPv6 with a port
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    if let Ok(ip) = hop.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(addr) = hop.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    hop.strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .and_then(|ip| ip.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        headers
+    }
+
+    fn trusted() -> Vec<IpNet> {
+        vec!["10.0.0.0/8".parse().unwrap(), "::1/128".parse().unwrap()]
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_forwarding_headers() {
+        let spoofed = headers(&[("x-forwarded-for", "1.2.3.4"), ("forwarded", "for=5.6.7.8")]);
+        assert_eq!(
+            resolve(ip("203.0.113.9"), &spoofed, &trusted()),
+            ip("203.0.113.9")
+        );
+        // Nothing is trusted unless configured
+        assert_eq!(resolve(ip("10.0.0.2"), &spoofed, &[]), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn test_trusted_proxy_uses_rightmost_untrusted_hop() {
+        // The client prepended a fake address; the proxies appended the real one
+        let h = headers(&[("x-forwarded-for", "1.2.3.4, 198.51.100.7, 10.0.0.5")]);
+        assert_eq!(resolve(ip("10.0.0.2"), &h, &trusted()), ip("198.51.100.7"));
+
+        // Split across several headers, with ports
+        let h = headers(&[
+            ("x-forwarded-for", "198.51.100.7:5123"),
+            ("x-forwarded-for", "10.0.0.5"),
+        ]);
+        assert_eq!(resolve(ip("10.0.0.2"), &h, &trusted()), ip("198.51.100.7"));
+
+        // Only trusted hops: the furthest one is the best we know
+        let h = headers(&[("x-forwarded-for", "10.0.0.9, 10.0.0.5")]);
+        assert_eq!(resolve(ip("10.0.0.2"), &h, &trusted()), ip("10.0.0.9"));
+
+        // Garbage stops the walk
+        let h = headers(&[("x-forwarded-for", "198.51.100.7, nonsense, 10.0.0.5")]);
+        assert_eq!(resolve(ip("10.0.0.2"), &h, &trusted()), ip("10.0.0.5"));
+
+        // No headers at all
+        assert_eq!(
+            resolve(ip("10.0.0.2"), &HeaderMap::new(), &trusted()),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn test_forwarded_header() {
+        let h = headers(&[(
+            "forwarded",
+            "for=1.2.3.4, for=\"[2001:db8::7]:4711\";proto=https, for=10.0.0.5",
+        )]);
+        assert_eq!(resolve(ip("::1"), &h, &trusted()), ip("2001:db8::7"));
+
+        // X-Forwarded-For wins when both are present
+        let h = headers(&[
+            ("x-forwarded-for", "198.51.100.7"),
+            ("forwarded", "for=1.2.3.4"),
+        ]);
+        assert_eq!(resolve(ip("10.0.0.2"), &h, &trusted()), ip("198.51.100.7"));
+    }
+
+    #[test]
+    fn test_ipv4_mapped_peer_is_trusted() {
+        let h = headers(&[("x-forwarded-for", "198.51.100.7")]);
+        assert_eq!(
+            resolve(ip("::ffff:10.0.0.2"), &h, &trusted()),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn test_unix_socket_peer() {
+        let h = headers(&[("x-forwarded-for", "198.51.100.7")]);
+        assert_eq!(resolve(None, &h, &trusted()), ip("198.51.100.7"));
+        assert_eq!(resolve(None, &h, &[]), None);
+    }
+}
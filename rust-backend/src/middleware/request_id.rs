@@ -1,2 +1,294 @@
-// TODO: Implement request ID middleware for actix-web
-// This middleware will assign a unique ID to each request for tracing
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    http::header::{HeaderName, HeaderValue},
+    Error, HttpMessage,
+};
+use futures_util::future::LocalBoxFuture;
+use std::future::{ready, Future, Ready};
+use tracing::Instrument;
+
+use crate::middleware::RealIp;
+use crate::utils::telemetry;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-supplied request id that is reused rather than replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: Option<String>;
+}
+
+/// The id of the request being handled, stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Id of the request the current task is serving, if any
+pub fn current_request_id() -> Option<String> {
+    CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok().flatten()
+}
+
+/// Carry the current request id and tracing span into `fut`, for work handed to
+/// `tokio::spawn` (e.g. streaming completions) that outlives the request
+pub fn propagate<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+    CURRENT_REQUEST_ID.scope(
+        current_request_id(),
+        fut.instrument(tracing::Span::current()),
+    )
+}
+
+/// Forward the current request id and trace context on outgoing upstream calls
+pub trait WithRequestId {
+    fn with_request_id(self) -> Self;
+}
+
+impl WithRequestId for reqwest::RequestBuilder {
+    fn with_request_id(self) -> Self {
+        let mut builder = match current_request_id() {
+            Some(id) => self.header(REQUEST_ID_HEADER, id),
+            None => self,
+        };
+        for (name, value) in telemetry::trace_headers() {
+            builder = builder.header(name, value);
+        }
+        builder
+    }
+}
+
+/// Reuse a client-supplied id only when it is short and free of unusual characters
+fn accepted_request_id(value: &str) -> Option<String> {
+    let valid = !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LEN
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+    valid.then(|| value.to_string())
+}
+
+/// Middleware assigning every request an id
+///
+/// The id comes from the `X-Request-Id` header when present (otherwise a new UUID),
+/// is echoed back in the response header, attached to the tracing span of every log
+/// line written while handling the request, and included in error bodies. The span
+/// also records the client address ([`RealIp`]), resolved here once per request.
+///
+/// Registered outermost so errors raised by inner middleware (e.g. authentication)
+/// are rendered while the id is still known.
+pub struct RequestIdMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdService { service }))
+    }
+}
+
+pub struct RequestIdService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(accepted_request_id)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        req.extensions_mut().insert(RequestId(request_id.clone()));
+        let client_ip = RealIp::resolve_for(&req);
+
+        // Route templates keep span names low-cardinality; unmatched paths use the method
+        let route = req.match_pattern().unwrap_or_default();
+        let span_name = format!("{} {}", req.method(), route);
+        let span = tracing::info_span!(
+            "request",
+            request_id = %request_id,
+            client.address = %client_ip,
+            otel.name = %span_name.trim_end(),
+            otel.kind = "server",
+            otel.status_code = tracing::field::Empty,
+            http.request.method = %req.method(),
+            http.route = %route,
+            http.response.status_code = tracing::field::Empty,
+        );
+        telemetry::set_remote_parent(&span, req.headers());
+        let header_value = HeaderValue::from_str(&request_id).ok();
+
+        let fut = CURRENT_REQUEST_ID.scope(
+            Some(request_id.clone()),
+            self.service.call(req).instrument(span.clone()),
+        );
+
+        Box::pin(async move {
+            let result = fut.await;
+            let record_status = |status: actix_web::http::StatusCode| {
+                span.record("http.response.status_code", status.as_u16());
+                if status.is_server_error() {
+                    span.record("otel.status_code", "ERROR");
+                }
+            };
+
+            match result {
+                Ok(mut res) => {
+                    record_status(res.status());
+                    if let Some(value) = header_value {
+                        res.headers_mut()
+                            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                    }
+                    Ok(res)
+                }
+                // Render errors here, inside the span and with the id available to AppError.
+                // The request itself isn't kept: routing needs to be its only owner.
+                Err(err) => {
+                    let _entered = span.enter();
+                    let mut response =
+                        CURRENT_REQUEST_ID.sync_scope(Some(request_id), || err.error_response());
+                    record_status(response.status());
+                    if let Some(value) = header_value {
+                        response
+                            .headers_mut()
+                            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                    }
+                    Err(InternalError::from_response(err, response).into())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{AppError, AppResult};
+    use actix_web::body::BoxBody;
+    use actix_web::middleware::{from_fn, Next};
+    use actix_web::test as actix_test;
+    use actix_web::{web, App, HttpResponse};
+
+    async fn not_found() -> AppResult<HttpResponse> {
+        Err(AppError::NotFound("Chat not found".to_string()))
+    }
+
+    #[test]
+    fn test_accepted_request_id() {
+        assert_eq!(accepted_request_id("abc-123").as_deref(), Some("abc-123"));
+        assert!(accepted_request_id("").is_none());
+        assert!(accepted_request_id("bad id\n").is_none());
+        assert!(accepted_request_id(&"a".repeat(MAX_REQUEST_ID_LEN + 1)).is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_round_trips_into_error_body() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(RequestIdMiddleware)
+                .route("/missing", web::get().to(not_found)),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::get()
+            .uri("/missing")
+            .insert_header((REQUEST_ID_HEADER, "req-42"))
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status().as_u16(), 404);
+        assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "req-42");
+
+        let body: serde_json::Value = actix_test::read_body_json(resp).await;
+        assert_eq!(body["detail"], "Chat not found");
+        assert_eq!(body["request_id"], "req-42");
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_on_errors_from_inner_middleware() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(from_fn(
+                    |_req: ServiceRequest, _next: Next<BoxBody>| async {
+                        Err::<ServiceResponse<BoxBody>, Error>(
+                            AppError::Unauthorized("Not authenticated".to_string()).into(),
+                        )
+                    },
+                ))
+                .wrap(RequestIdMiddleware)
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::get()
+            .uri("/")
+            .insert_header((REQUEST_ID_HEADER, "req-9"))
+            .to_request();
+        let err = app.call(req).await.err().unwrap();
+        let resp = err.error_response();
+        assert_eq!(resp.status().as_u16(), 401);
+        assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "req-9");
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["detail"], "Not authenticated");
+        assert_eq!(body["request_id"], "req-9");
+    }
+
+    #[actix_web::test]
+    async fn test_spawned_tasks_keep_request_id() {
+        let app = actix_test::init_service(App::new().wrap(RequestIdMiddleware).route(
+            "/",
+            web::get().to(|| async {
+                let correlation_id = tokio::spawn(propagate(async {
+                    crate::socketio::CorrelationId::current().to_string()
+                }))
+                .await
+                .unwrap();
+                HttpResponse::Ok().body(correlation_id)
+            }),
+        ))
+        .await;
+
+        let req = actix_test::TestRequest::get()
+            .uri("/")
+            .insert_header((REQUEST_ID_HEADER, "req-7"))
+            .to_request();
+        let body = actix_test::call_and_read_body(&app, req).await;
+        assert_eq!(body, "req-7");
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_generated_when_missing() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(RequestIdMiddleware)
+                .route("/", web::get().to(|| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+
+        let resp =
+            actix_test::call_service(&app, actix_test::TestRequest::get().uri("/").to_request())
+                .await;
+        let id = resp.headers().get(REQUEST_ID_HEADER).unwrap();
+        assert!(uuid::Uuid::parse_str(id.to_str().unwrap()).is_ok());
+    }
+}
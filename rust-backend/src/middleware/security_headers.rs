@@ -1,19 +1,31 @@
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error,
+    http::header::{self, HeaderMap, HeaderName, HeaderValue},
+    web, Error,
 };
 use futures_util::future::LocalBoxFuture;
 use std::future::{ready, Ready};
 
+use crate::config::{
+    Config, DEFAULT_HSTS_MAX_AGE, DEFAULT_PERMISSIONS_POLICY, DEFAULT_REFERRER_POLICY,
+    DEFAULT_X_FRAME_OPTIONS,
+};
+use crate::AppState;
+
 /// Middleware that adds security headers to all responses
 ///
 /// Security headers added:
 /// - X-Content-Type-Options: nosniff (prevent MIME sniffing)
-/// - X-Frame-Options: DENY (prevent clickjacking)
+/// - X-Frame-Options (prevent clickjacking)
 /// - X-XSS-Protection: 1; mode=block (XSS protection)
-/// - Referrer-Policy: strict-origin-when-cross-origin (control referrer info)
+/// - Referrer-Policy (control referrer info)
 /// - Permissions-Policy: Restrict browser features
-/// - Content-Security-Policy: Restrict resource loading (if enabled)
+/// - Content-Security-Policy: Restrict resource loading (unless ENABLE_CSP=false)
+/// - Strict-Transport-Security: on HTTPS requests when ENABLE_HSTS=true
+///
+/// Values come from the runtime config, so admin changes apply to the next request.
+/// Headers already set by a handler are left alone, and event streams only get the
+/// headers that matter for non-document responses.
 ///
 /// Based on Python backend: backend/open_webui/utils/security_headers.py
 pub struct SecurityHeaders;
@@ -52,121 +64,217 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
+        let policy = match req.app_data::<web::Data<AppState>>() {
+            Some(state) => SecurityPolicy::from_config(&state.config.snapshot()),
+            None => SecurityPolicy::default(),
+        };
+        let is_https = req.connection_info().scheme() == "https";
         let fut = self.service.call(req);
 
         Box::pin(async move {
             let mut res = fut.await?;
 
             // Add security headers to the response
-            let headers = res.headers_mut();
-            add_security_headers(headers);
+            policy.apply(res.headers_mut(), is_https);
 
             Ok(res)
         })
     }
 }
 
-/// Add security headers to response headers
-pub fn add_security_headers(headers: &mut actix_web::http::header::HeaderMap) {
-    use actix_web::http::header::{HeaderName, HeaderValue};
-
-    // Prevent MIME type sniffing
-    headers.insert(
-        HeaderName::from_static("x-content-type-options"),
-        HeaderValue::from_static("nosniff"),
-    );
-
-    // Prevent clickjacking by not allowing the page to be framed
-    headers.insert(
-        HeaderName::from_static("x-frame-options"),
-        HeaderValue::from_static("DENY"),
-    );
-
-    // Enable XSS protection in legacy browsers
-    headers.insert(
-        HeaderName::from_static("x-xss-protection"),
-        HeaderValue::from_static("1; mode=block"),
-    );
-
-    // Control referrer information
-    headers.insert(
-        HeaderName::from_static("referrer-policy"),
-        HeaderValue::from_static("strict-origin-when-cross-origin"),
-    );
-
-    // Restrict browser features
-    // This helps prevent unauthorized access to camera, microphone, geolocation, etc.
-    headers.insert(
-        HeaderName::from_static("permissions-policy"),
-        HeaderValue::from_static("geolocation=(), microphone=(), camera=()"),
-    );
-
-    // Content Security Policy (CSP)
-    // Note: This is a basic CSP. For production, you may want to customize this based on your needs.
-    // The Python backend has environment-specific CSP configuration.
-    //
-    // For now, we use a permissive policy that allows:
-    // - Same-origin and inline scripts/styles (required for SPA)
-    // - Images from any source (for user-uploaded content)
-    // - Connections to any source (for API calls to various LLM providers)
-    //
-    // TODO: Make this configurable via environment variables or admin settings
-    let csp_enabled = std::env::var("ENABLE_CSP")
-        .unwrap_or_else(|_| "false".to_string())
-        .to_lowercase()
-        == "true";
-
-    if csp_enabled {
-        // Strict CSP
-        headers.insert(
-            HeaderName::from_static("content-security-policy"),
-            HeaderValue::from_static(
-                "default-src 'self'; \
-                 script-src 'self' 'unsafe-inline' 'unsafe-eval'; \
-                 style-src 'self' 'unsafe-inline'; \
-                 img-src * data: blob:; \
-                 font-src 'self' data:; \
-                 connect-src *; \
-                 media-src 'self' blob:; \
-                 object-src 'none'; \
-                 frame-ancestors 'none'; \
-                 base-uri 'self'; \
-                 form-action 'self'",
-            ),
-        );
+/// The security headers to send, resolved from the config
+#[derive(Debug, Clone)]
+pub struct SecurityPolicy {
+    content_security_policy: Option<String>,
+    strict_transport_security: Option<String>,
+    x_frame_options: Option<String>,
+    referrer_policy: Option<String>,
+    permissions_policy: Option<String>,
+}
+
+impl Default for SecurityPolicy {
+    fn default() -> Self {
+        SecurityPolicy {
+            content_security_policy: Some(build_csp(false, DEFAULT_X_FRAME_OPTIONS, &[], &[])),
+            strict_transport_security: None,
+            x_frame_options: Some(DEFAULT_X_FRAME_OPTIONS.to_string()),
+            referrer_policy: Some(DEFAULT_REFERRER_POLICY.to_string()),
+            permissions_policy: Some(DEFAULT_PERMISSIONS_POLICY.to_string()),
+        }
+    }
+}
+
+impl SecurityPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        let content_security_policy = if !config.enable_csp {
+            None
+        } else if let Some(csp) = &config.content_security_policy {
+            Some(csp.clone())
+        } else {
+            Some(build_csp(
+                config.security_headers_mode == "strict",
+                &config.x_frame_options,
+                &config.csp_connect_src,
+                &config.csp_img_src,
+            ))
+        };
+
+        let strict_transport_security = config.enable_hsts.then(|| {
+            let max_age = if config.hsts_max_age > 0 {
+                config.hsts_max_age
+            } else {
+                DEFAULT_HSTS_MAX_AGE
+            };
+            format!("max-age={}; includeSubDomains", max_age)
+        });
+
+        let non_empty = |value: &str| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+
+        SecurityPolicy {
+            content_security_policy,
+            strict_transport_security,
+            x_frame_options: non_empty(&config.x_frame_options),
+            referrer_policy: non_empty(&config.referrer_policy),
+            permissions_policy: non_empty(&config.permissions_policy),
+        }
+    }
+
+    /// Add the headers to a response, keeping any the handler already set
+    pub fn apply(&self, headers: &mut HeaderMap, is_https: bool) {
+        // Prevent MIME type sniffing
+        set_default(headers, "x-content-type-options", Some("nosniff"));
 
-        // HTTP Strict Transport Security (HSTS)
-        // Only add if HTTPS is enabled
-        let https_enabled = std::env::var("HTTPS_ENABLED")
-            .unwrap_or_else(|_| "false".to_string())
-            .to_lowercase()
-            == "true";
-
-        if https_enabled {
-            // Enforce HTTPS for 1 year, including subdomains
-            headers.insert(
-                HeaderName::from_static("strict-transport-security"),
-                HeaderValue::from_static("max-age=31536000; includeSubDomains; preload"),
+        // HSTS is ignored by browsers over plain HTTP, so only send it on HTTPS
+        if is_https {
+            set_default(
+                headers,
+                "strict-transport-security",
+                self.strict_transport_security.as_deref(),
             );
         }
+
+        // The remaining headers govern documents; event streams are consumed by
+        // script and must reach the client untouched
+        let is_event_stream = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|ct| ct.starts_with("text/event-stream"))
+            .unwrap_or(false);
+        if is_event_stream {
+            return;
+        }
+
+        set_default(headers, "x-frame-options", self.x_frame_options.as_deref());
+        // Enable XSS protection in legacy browsers
+        set_default(headers, "x-xss-protection", Some("1; mode=block"));
+        set_default(headers, "referrer-policy", self.referrer_policy.as_deref());
+        set_default(
+            headers,
+            "permissions-policy",
+            self.permissions_policy.as_deref(),
+        );
+        set_default(
+            headers,
+            "content-security-policy",
+            self.content_security_policy.as_deref(),
+        );
     }
 }
 
+fn set_default(headers: &mut HeaderMap, name: &'static str, value: Option<&str>) {
+    let name = HeaderName::from_static(name);
+    if headers.contains_key(&name) {
+        return;
+    }
+    if let Some(value) = value.and_then(|v| HeaderValue::from_str(v).ok()) {
+        headers.insert(name, value);
+    }
+}
+
+/// Frame sources the UI embeds under the strict policy
+const FRAME_EMBEDS: &str = "'self' blob: https://www.youtube.com https://www.youtube-nocookie.com";
+
+/// Extra CSP sources from the config; entries that could end the directive are dropped
+fn extra_sources(origins: &[String]) -> String {
+    origins
+        .iter()
+        .map(|o| o.trim())
+        .filter(|o| {
+            !o.is_empty() && !o.contains(|c: char| c == ';' || c == ',' || c.is_whitespace())
+        })
+        .map(|o| format!(" {}", o))
+        .collect()
+}
+
+/// Content-Security-Policy for the bundled frontend
+///
+/// The relaxed policy allows connections and images from anywhere, as the UI talks to
+/// arbitrary model endpoints and shows remote images. The strict policy limits both to
+/// this origin plus the configured extra origins, and disallows `eval`. Both allow the
+/// frames the UI embeds: artifacts and previews from this origin or `blob:`, and YouTube.
+pub fn build_csp(
+    strict: bool,
+    x_frame_options: &str,
+    connect_src: &[String],
+    img_src: &[String],
+) -> String {
+    let (script_src, connect, img, frame) = if strict {
+        (
+            "'self' 'unsafe-inline' 'wasm-unsafe-eval'",
+            "'self'",
+            "'self' data: blob:",
+            FRAME_EMBEDS,
+        )
+    } else {
+        (
+            "'self' 'unsafe-inline' 'unsafe-eval'",
+            "* ws: wss: data: blob:",
+            "* data: blob:",
+            "* data: blob:",
+        )
+    };
+
+    let mut directives = vec![
+        "default-src 'self'".to_string(),
+        format!("script-src {}", script_src),
+        "style-src 'self' 'unsafe-inline'".to_string(),
+        format!("img-src {}{}", img, extra_sources(img_src)),
+        "font-src 'self' data:".to_string(),
+        format!("connect-src {}{}", connect, extra_sources(connect_src)),
+        "media-src 'self' data: blob:".to_string(),
+        "worker-src 'self' blob:".to_string(),
+        format!("frame-src {}", frame),
+        format!("child-src {}", frame),
+        "object-src 'none'".to_string(),
+        "base-uri 'self'".to_string(),
+        "form-action 'self'".to_string(),
+    ];
+    // Keep frame-ancestors in line with X-Frame-Options
+    match x_frame_options.trim().to_uppercase().as_str() {
+        "DENY" => directives.push("frame-ancestors 'none'".to_string()),
+        "SAMEORIGIN" => directives.push("frame-ancestors 'self'".to_string()),
+        _ => {}
+    }
+
+    directives.join("; ")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use actix_web::{test, web, App, HttpResponse};
+    use actix_web::test as actix_test;
+    use actix_web::{web, App, HttpResponse};
 
     #[actix_web::test]
     async fn test_security_headers() {
-        let app = test::init_service(App::new().wrap(SecurityHeaders).route(
+        let app = actix_test::init_service(App::new().wrap(SecurityHeaders).route(
             "/",
             web::get().to(|| async { HttpResponse::Ok().body("test") }),
         ))
         .await;
 
-        let req = test::TestRequest::get().uri("/").to_request();
-        let resp = test::call_service(&app, req).await;
+        let req = actix_test::TestRequest::get().uri("/").to_request();
+        let resp = actix_test::call_service(&app, req).await;
 
         // Check that security headers are present
         let headers = resp.headers();
@@ -189,7 +297,80 @@ mod tests {
         assert!(headers.contains_key("permissions-policy"));
         assert_eq!(
             headers.get("permissions-policy").unwrap(),
-            "geolocation=(), microphone=(), camera=()"
+            "geolocation=(), microphone=(self), camera=(self)"
+        );
+
+        // Relaxed CSP by default, and no HSTS over plain HTTP
+        let csp = headers
+            .get("content-security-policy")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(csp.contains("connect-src *"));
+        assert!(csp.contains("frame-src * data: blob:;"));
+        assert!(csp.contains("frame-ancestors 'none'"));
+        assert!(!headers.contains_key("strict-transport-security"));
+    }
+
+    #[actix_web::test]
+    async fn test_event_streams_keep_their_headers() {
+        let app = actix_test::init_service(App::new().wrap(SecurityHeaders).route(
+            "/stream",
+            web::get().to(|| async {
+                HttpResponse::Ok()
+                    .content_type("text/event-stream")
+                    .body("data: {}\n\n")
+            }),
+        ))
+        .await;
+
+        let req = actix_test::TestRequest::get().uri("/stream").to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        let headers = resp.headers();
+
+        assert_eq!(headers.get("content-type").unwrap(), "text/event-stream");
+        assert_eq!(headers.get("x-content-type-options").unwrap(), "nosniff");
+        assert!(!headers.contains_key("content-security-policy"));
+    }
+
+    #[test]
+    fn test_strict_csp_with_extra_origins() {
+        let csp = build_csp(
+            true,
+            "SAMEORIGIN",
+            &[
+                "https://api.openai.com".to_string(),
+                "bad; script-src *".to_string(),
+            ],
+            &["https://images.example.com".to_string()],
+        );
+
+        assert!(csp.contains("connect-src 'self' https://api.openai.com;"));
+        assert!(csp.contains("img-src 'self' data: blob: https://images.example.com;"));
+        assert!(!csp.contains("'unsafe-eval'"));
+        assert!(!csp.contains("bad"));
+        assert!(csp.contains(
+            "frame-src 'self' blob: https://www.youtube.com https://www.youtube-nocookie.com;"
+        ));
+        assert!(csp.contains("child-src 'self' blob: https://www.youtube.com"));
+        assert!(csp.ends_with("frame-ancestors 'self'"));
+    }
+
+    #[test]
+    fn test_hsts_only_on_https() {
+        let policy = SecurityPolicy {
+            strict_transport_security: Some("max-age=60; includeSubDomains".to_string()),
+            ..SecurityPolicy::default()
+        };
+
+        let mut headers = HeaderMap::new();
+        policy.apply(&mut headers, false);
+        assert!(!headers.contains_key("strict-transport-security"));
+
+        policy.apply(&mut headers, true);
+        assert_eq!(
+            headers.get("strict-transport-security").unwrap(),
+            "max-age=60; includeSubDomains"
         );
     }
 }
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// One audited request. `request_body` is only kept for auth and config changes, with
+/// credentials scrubbed before it is stored.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuditLog {
+    pub id: String,
+    pub user_id: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub status: i64,
+    pub source_ip: Option<String>,
+    pub request_body: Option<String>,
+    pub created_at: i64,
+}
+
+/// Filters for querying the audit log; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogFilter {
+    pub user_id: Option<String>,
+    /// Matches entries whose path starts with this prefix
+    pub path: Option<String>,
+    /// Only entries created at or after this unix timestamp
+    pub since: Option<i64>,
+}
@@ -19,6 +19,28 @@ pub struct Chat {
     pub updated_at: i64,
 }
 
+/// Sidebar entry for a chat, without the chat JSON
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ChatListItem {
+    pub id: String,
+    pub title: String,
+    pub folder_id: Option<String>,
+    pub archived: bool,
+    pub pinned: Option<bool>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// Which of a user's chats a list query returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatListFilter {
+    /// Chats that aren't archived
+    Active,
+    /// Pinned chats that aren't archived
+    Pinned,
+    Archived,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateChatRequest {
     pub id: String,
@@ -91,3 +113,96 @@ impl From<Chat> for ChatListResponse {
         }
     }
 }
+
+/// A bookmarked message, with enough of its chat to link back to it
+#[derive(Debug, Clone, Serialize)]
+pub struct BookmarkedMessageResponse {
+    pub chat_id: String,
+    pub chat_title: String,
+    pub message_id: String,
+    pub role: Option<String>,
+    pub content: String,
+    pub model: Option<String>,
+    pub timestamp: Option<i64>,
+}
+
+impl BookmarkedMessageResponse {
+    /// Collect the messages flagged `bookmarked: true` in `chat.history.messages`
+    pub fn from_chat(chat: &Chat) -> Vec<Self> {
+        let messages = match chat
+            .chat
+            .get("history")
+            .and_then(|h| h.get("messages"))
+            .and_then(|m| m.as_object())
+        {
+            Some(messages) => messages,
+            None => return Vec::new(),
+        };
+
+        let mut bookmarks: Vec<Self> = messages
+            .iter()
+            .filter(|(_, message)| {
+                message.get("bookmarked").and_then(|b| b.as_bool()) == Some(true)
+            })
+            .map(|(message_id, message)| BookmarkedMessageResponse {
+                chat_id: chat.id.clone(),
+                chat_title: chat.title.clone(),
+                message_id: message_id.clone(),
+                role: message
+                    .get("role")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+                content: message
+                    .get("content")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                model: message
+                    .get("model")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+                timestamp: message.get("timestamp").and_then(|v| v.as_i64()),
+            })
+            .collect();
+
+        bookmarks.sort_by_key(|b| b.timestamp);
+        bookmarks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bookmarked_messages_from_chat() {
+        let chat = Chat {
+            id: "c1".to_string(),
+            user_id: "u1".to_string(),
+            title: "Trip".to_string(),
+            chat: json!({
+                "history": {
+                    "messages": {
+                        "m2": {"role": "assistant", "content": "Take the train", "model": "gpt", "timestamp": 20, "bookmarked": true},
+                        "m1": {"role": "user", "content": "How do I get there?", "timestamp": 10, "bookmarked": true},
+                        "m3": {"role": "user", "content": "Thanks", "timestamp": 30, "bookmarked": false}
+                    }
+                }
+            }),
+            folder_id: None,
+            archived: false,
+            pinned: None,
+            share_id: None,
+            meta: None,
+            created_at: 0,
+            updated_at: 0,
+        };
+
+        let bookmarks = BookmarkedMessageResponse::from_chat(&chat);
+        let ids: Vec<&str> = bookmarks.iter().map(|b| b.message_id.as_str()).collect();
+        assert_eq!(ids, vec!["m1", "m2"]);
+        assert_eq!(bookmarks[1].model.as_deref(), Some("gpt"));
+        assert_eq!(bookmarks[1].chat_title, "Trip");
+    }
+}
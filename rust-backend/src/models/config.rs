@@ -15,3 +15,315 @@ pub struct ConfigData {
     #[serde(flatten)]
     pub data: serde_json::Value,
 }
+
+/// Banner types the frontend knows how to style
+pub const BANNER_TYPES: &[&str] = &["info", "success", "warning", "error"];
+
+/// Admin-configured banner shown at the top of the chat UI
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Banner {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub banner_type: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    pub content: String,
+    pub dismissible: bool,
+    pub timestamp: i64,
+}
+
+/// Banner as submitted by an admin; `id` and `timestamp` are filled in when missing
+#[derive(Debug, Clone, Deserialize)]
+pub struct BannerForm {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(rename = "type")]
+    pub banner_type: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    pub content: String,
+    #[serde(default = "default_dismissible")]
+    pub dismissible: bool,
+    #[serde(default)]
+    pub timestamp: Option<i64>,
+}
+
+fn default_dismissible() -> bool {
+    true
+}
+
+impl BannerForm {
+    /// Validate the banner and assign an id and timestamp if it doesn't have them yet
+    pub fn into_banner(self, now: i64) -> Result<Banner, String> {
+        if !BANNER_TYPES.contains(&self.banner_type.as_str()) {
+            return Err(format!(
+                "Invalid banner type '{}', expected one of: {}",
+                self.banner_type,
+                BANNER_TYPES.join(", ")
+            ));
+        }
+        if self.content.trim().is_empty() {
+            return Err("Banner content must not be empty".to_string());
+        }
+
+        Ok(Banner {
+            id: self
+                .id
+                .filter(|id| !id.trim().is_empty())
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            banner_type: self.banner_type,
+            title: self.title.filter(|t| !t.trim().is_empty()),
+            content: self.content,
+            dismissible: self.dismissible,
+            timestamp: self.timestamp.unwrap_or(now),
+        })
+    }
+}
+
+/// Most prompt suggestions an admin can configure
+pub const MAX_PROMPT_SUGGESTIONS: usize = 50;
+
+/// Longest prompt suggestion content, in characters
+pub const MAX_PROMPT_SUGGESTION_LENGTH: usize = 4000;
+
+/// Prompt suggestion shown on the new chat screen; `title` is a heading and a subheading
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PromptSuggestion {
+    pub title: Vec<String>,
+    pub content: String,
+}
+
+/// Check the suggestion count and that each has a two-part title and non-empty content
+pub fn validate_prompt_suggestions(suggestions: &[PromptSuggestion]) -> Result<(), String> {
+    if suggestions.len() > MAX_PROMPT_SUGGESTIONS {
+        return Err(format!(
+            "At most {} prompt suggestions are allowed",
+            MAX_PROMPT_SUGGESTIONS
+        ));
+    }
+
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        if suggestion.title.len() != 2 {
+            return Err(format!(
+                "Suggestion {}: title must be an array of two strings",
+                i + 1
+            ));
+        }
+        if suggestion.content.trim().is_empty() {
+            return Err(format!("Suggestion {}: content must not be empty", i + 1));
+        }
+        if suggestion.content.chars().count() > MAX_PROMPT_SUGGESTION_LENGTH {
+            return Err(format!(
+                "Suggestion {}: content exceeds {} characters",
+                i + 1,
+                MAX_PROMPT_SUGGESTION_LENGTH
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// OpenAI-compatible connections in the shape of a user's `directConnections` settings;
+/// `configs` is keyed by connection index (or, in older settings, by URL)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct DirectConnections {
+    #[serde(rename = "OPENAI_API_BASE_URLS", default)]
+    pub base_urls: Vec<String>,
+    #[serde(rename = "OPENAI_API_KEYS", default)]
+    pub keys: Vec<String>,
+    #[serde(rename = "OPENAI_API_CONFIGS", default)]
+    pub configs: serde_json::Map<String, serde_json::Value>,
+}
+
+impl DirectConnections {
+    /// Parse stored connections, treating anything malformed as no connections
+    pub fn from_value(value: &serde_json::Value) -> Self {
+        serde_json::from_value(value.clone()).unwrap_or_default()
+    }
+
+    /// The user's own connections, from `settings.directConnections` or
+    /// `settings.ui.directConnections`
+    pub fn from_user_settings(settings: Option<&serde_json::Value>) -> Self {
+        settings
+            .and_then(|s| {
+                s.get("directConnections")
+                    .or_else(|| s.get("ui").and_then(|ui| ui.get("directConnections")))
+            })
+            .map(Self::from_value)
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.base_urls.is_empty()
+    }
+
+    /// Check that URLs, keys and configs line up and every URL is http(s)
+    pub fn validate(&self) -> Result<(), String> {
+        if self.keys.len() != self.base_urls.len() {
+            return Err(format!(
+                "OPENAI_API_KEYS has {} entries but OPENAI_API_BASE_URLS has {}",
+                self.keys.len(),
+                self.base_urls.len()
+            ));
+        }
+        for url in &self.base_urls {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(format!("Invalid connection URL: {}", url));
+            }
+        }
+        for key in self.configs.keys() {
+            let in_range = key
+                .parse::<usize>()
+                .map(|idx| idx < self.base_urls.len())
+                .unwrap_or_else(|_| self.base_urls.contains(key));
+            if !in_range {
+                return Err(format!(
+                    "OPENAI_API_CONFIGS entry '{}' does not match a connection",
+                    key
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Append `defaults` after these connections so existing indices stay stable; default
+    /// config entries are re-indexed and URL-keyed ones never override the user's
+    pub fn merged_with(mut self, defaults: &DirectConnections) -> Self {
+        let offset = self.base_urls.len();
+        for (key, value) in &defaults.configs {
+            let key = match key.parse::<usize>() {
+                Ok(idx) => (idx + offset).to_string(),
+                Err(_) => key.clone(),
+            };
+            self.configs.entry(key).or_insert_with(|| value.clone());
+        }
+
+        // Pad keys so default keys line up with their URLs
+        self.keys.resize(offset, String::new());
+        self.base_urls.extend(defaults.base_urls.iter().cloned());
+        self.keys.extend(defaults.keys.iter().cloned());
+        self
+    }
+
+    /// URL, key and config of the connection at `idx`
+    pub fn get(&self, idx: usize) -> Option<(String, String, serde_json::Value)> {
+        let url = self.base_urls.get(idx)?;
+        let key = self.keys.get(idx).cloned().unwrap_or_default();
+        let config = self
+            .configs
+            .get(&idx.to_string())
+            .or_else(|| self.configs.get(url))
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!({}));
+        Some((url.clone(), key, config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_banner_form_fills_id_and_timestamp() {
+        let form: BannerForm =
+            serde_json::from_value(json!({"type": "warning", "content": "Maintenance at 5pm"}))
+                .unwrap();
+        let banner = form.into_banner(1700000000).unwrap();
+
+        assert!(!banner.id.is_empty());
+        assert_eq!(banner.timestamp, 1700000000);
+        assert!(banner.dismissible);
+
+        let value = serde_json::to_value(&banner).unwrap();
+        assert_eq!(value["type"], "warning");
+
+        let kept: BannerForm = serde_json::from_value(value).unwrap();
+        assert_eq!(kept.into_banner(1).unwrap(), banner);
+    }
+
+    #[test]
+    fn test_banner_form_rejects_bad_type_and_empty_content() {
+        let form: BannerForm =
+            serde_json::from_value(json!({"type": "danger", "content": "x"})).unwrap();
+        assert!(form.into_banner(0).is_err());
+
+        let form: BannerForm =
+            serde_json::from_value(json!({"type": "info", "content": "  "})).unwrap();
+        assert!(form.into_banner(0).is_err());
+    }
+
+    #[test]
+    fn test_validate_prompt_suggestions() {
+        let valid = PromptSuggestion {
+            title: vec!["Help me study".to_string(), "vocabulary".to_string()],
+            content: "Help me study vocabulary".to_string(),
+        };
+        assert!(validate_prompt_suggestions(std::slice::from_ref(&valid)).is_ok());
+
+        let one_part_title = PromptSuggestion {
+            title: vec!["Help me study".to_string()],
+            ..valid.clone()
+        };
+        assert!(validate_prompt_suggestions(&[one_part_title]).is_err());
+
+        let oversized = PromptSuggestion {
+            content: "x".repeat(MAX_PROMPT_SUGGESTION_LENGTH + 1),
+            ..valid.clone()
+        };
+        assert!(validate_prompt_suggestions(&[oversized]).is_err());
+
+        let too_many = vec![valid; MAX_PROMPT_SUGGESTIONS + 1];
+        assert!(validate_prompt_suggestions(&too_many).is_err());
+    }
+
+    #[test]
+    fn test_direct_connections_validation() {
+        let connections: DirectConnections = serde_json::from_value(json!({
+            "OPENAI_API_BASE_URLS": ["https://api.example.com/v1"],
+            "OPENAI_API_KEYS": ["sk-1"],
+            "OPENAI_API_CONFIGS": {"0": {"enable": true}}
+        }))
+        .unwrap();
+        assert!(connections.validate().is_ok());
+
+        let mut missing_key = connections.clone();
+        missing_key.keys.clear();
+        assert!(missing_key.validate().is_err());
+
+        let mut stray_config = connections.clone();
+        stray_config.configs.insert("1".to_string(), json!({}));
+        assert!(stray_config.validate().is_err());
+    }
+
+    #[test]
+    fn test_user_direct_connections_come_before_defaults() {
+        let settings = json!({"ui": {"directConnections": {
+            "OPENAI_API_BASE_URLS": ["https://mine.example.com/v1"],
+            "OPENAI_API_KEYS": ["sk-mine"],
+            "OPENAI_API_CONFIGS": {"0": {"prefix_id": "mine"}}
+        }}});
+        let defaults = DirectConnections {
+            base_urls: vec!["https://shared.example.com/v1".to_string()],
+            keys: vec!["sk-shared".to_string()],
+            configs: json!({"0": {"prefix_id": "shared"}})
+                .as_object()
+                .unwrap()
+                .clone(),
+        };
+
+        let merged = DirectConnections::from_user_settings(Some(&settings)).merged_with(&defaults);
+        let (url, key, config) = merged.get(0).unwrap();
+        assert_eq!(url, "https://mine.example.com/v1");
+        assert_eq!(key, "sk-mine");
+        assert_eq!(config["prefix_id"], "mine");
+
+        let (url, key, config) = merged.get(1).unwrap();
+        assert_eq!(url, "https://shared.example.com/v1");
+        assert_eq!(key, "sk-shared");
+        assert_eq!(config["prefix_id"], "shared");
+
+        let only_defaults = DirectConnections::from_user_settings(None).merged_with(&defaults);
+        assert_eq!(only_defaults.get(0).unwrap().1, "sk-shared");
+    }
+}
@@ -61,6 +61,40 @@ pub struct UserIdsForm {
     pub user_ids: Option<Vec<String>>,
 }
 
+/// Emails as a list or as pasted text separated by commas, semicolons or whitespace
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum EmailList {
+    List(Vec<String>),
+    Text(String),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserEmailsForm {
+    pub emails: EmailList,
+}
+
+impl UserEmailsForm {
+    /// Normalized, de-duplicated emails in the order given
+    pub fn emails(&self) -> Vec<String> {
+        let raw: Vec<&str> = match &self.emails {
+            EmailList::List(emails) => emails.iter().map(String::as_str).collect(),
+            EmailList::Text(text) => text
+                .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                .collect(),
+        };
+
+        let mut emails: Vec<String> = Vec::new();
+        for email in raw {
+            let email = email.trim().to_lowercase();
+            if !email.is_empty() && !emails.contains(&email) {
+                emails.push(email);
+            }
+        }
+        emails
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct GroupUpdateForm {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -107,3 +141,32 @@ impl From<Group> for GroupResponse {
         }
     }
 }
+
+#[derive(Debug, Serialize)]
+pub struct GroupEmailsResponse {
+    #[serde(flatten)]
+    pub group: GroupResponse,
+    /// Emails that did not match any user
+    pub not_found: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pasted_emails_are_split_and_normalized() {
+        let form: UserEmailsForm = serde_json::from_str(
+            r#"{"emails": "Alice@example.com, bob@example.com;\ncarol@example.com  alice@example.com"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            form.emails(),
+            vec!["alice@example.com", "bob@example.com", "carol@example.com"]
+        );
+
+        let form: UserEmailsForm =
+            serde_json::from_str(r#"{"emails": [" Dave@example.com ", ""]}"#).unwrap();
+        assert_eq!(form.emails(), vec!["dave@example.com"]);
+    }
+}
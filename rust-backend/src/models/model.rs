@@ -10,9 +10,10 @@ pub struct Model {
     pub name: String,
     #[sqlx(json)]
     pub params: JsonValue,
-    #[sqlx(json)]
+    #[sqlx(json(nullable))]
     pub meta: Option<JsonValue>,
-    #[sqlx(json)]
+    /// `None` (public) when the column is NULL, as for models created without one
+    #[sqlx(json(nullable))]
     pub access_control: Option<JsonValue>,
     pub is_active: bool,
     pub created_at: i64,
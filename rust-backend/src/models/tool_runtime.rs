@@ -36,6 +36,12 @@ pub struct ToolSpec {
     pub error_handling: Option<ErrorHandlingStrategy>,
     #[serde(default)]
     pub cache_enabled: bool,
+    /// Overrides the configured execution timeout for this tool
+    #[serde(default)]
+    pub timeout_seconds: Option<u64>,
+    /// JSON Schema the tool's result must match
+    #[serde(default)]
+    pub output_schema: Option<serde_json::Value>,
 }
 
 /// Tool types supported
@@ -169,6 +175,17 @@ pub struct UserContext {
     pub role: Option<String>,
 }
 
+impl From<&super::User> for UserContext {
+    fn from(user: &super::User) -> Self {
+        Self {
+            id: user.id.clone(),
+            name: user.name.clone(),
+            email: user.email.clone(),
+            role: Some(user.role.clone()),
+        }
+    }
+}
+
 /// Tool execution response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolExecutionResponse {
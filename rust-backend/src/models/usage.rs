@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Tokens consumed by a user on one model during one period
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TokenUsage {
+    pub user_id: String,
+    pub model_id: String,
+    pub period: String,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub updated_at: i64,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> i64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
@@ -184,6 +184,12 @@ impl OpenAIEmbeddings {
         })
     }
 
+    /// Send requests with `http_client`, e.g. one going through the outbound proxy
+    pub fn with_http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.client = self.client.with_http_client(http_client);
+        self
+    }
+
     /// Create from environment variables
     pub fn from_env() -> Result<Self, EmbeddingError> {
         let api_key = std::env::var("RAG_OPENAI_API_KEY")
@@ -331,6 +337,12 @@ impl KnoxChatEmbeddings {
         })
     }
 
+    /// Send requests with `http_client`, e.g. one going through the outbound proxy
+    pub fn with_http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.client = http_client;
+        self
+    }
+
     /// Create from environment variables
     pub fn from_env() -> Result<Self, EmbeddingError> {
         let api_key = std::env::var("KNOXCHAT_API_KEY").ok();
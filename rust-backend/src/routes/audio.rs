@@ -1,10 +1,12 @@
 use actix_web::{web, HttpResponse};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sha2::{Digest, Sha256};
 
 use crate::{
     error::AppError,
     middleware::{AuthMiddleware, AuthUser},
+    utils::{http_client, signed_url},
     AppState,
 };
 
@@ -109,7 +111,7 @@ async fn get_audio_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(AudioConfigResponse {
         tts: TTSConfigForm {
@@ -151,7 +153,7 @@ async fn update_audio_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let mut config = state.config.write().unwrap();
+    let mut config = state.config.write();
 
     // Update TTS config
     config.tts_openai_api_base_url = form_data.tts.openai_api_base_url.clone();
@@ -214,7 +216,7 @@ async fn update_audio_config(
     let _ =
         crate::services::ConfigService::update_section(&state.db, "audio", audio_config_json).await;
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(AudioConfigResponse {
         tts: TTSConfigForm {
@@ -252,19 +254,36 @@ async fn speech(
     _auth_user: AuthUser,
     payload: web::Json<SpeechRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     // Forward to configured TTS engine (OpenAI-compatible endpoint)
     if config.tts_engine == "openai" {
-        let client = reqwest::Client::new();
+        let client = http_client::client(&config);
 
+        let response_format = payload.response_format.as_deref().unwrap_or("mp3");
         let tts_payload = json!({
             "model": payload.model.as_ref().unwrap_or(&config.tts_model),
             "input": payload.input,
             "voice": payload.voice.as_ref().unwrap_or(&config.tts_voice),
-            "response_format": payload.response_format.as_deref().unwrap_or("mp3"),
+            "response_format": response_format,
         });
 
+        // Speech is cached by request, and also servable from a signed cache URL
+        let file_name = format!(
+            "{:x}.{}",
+            Sha256::digest(tts_payload.to_string().as_bytes()),
+            response_format
+        );
+        let cache_path = config.speech_cache_dir().join(&file_name);
+        let cache_url =
+            signed_url::sign_path(&config, &format!("/cache/audio/speech/{}", file_name));
+        if let Ok(audio_bytes) = tokio::fs::read(&cache_path).await {
+            return Ok(HttpResponse::Ok()
+                .content_type("audio/mpeg")
+                .insert_header(("Content-Location", cache_url))
+                .body(audio_bytes));
+        }
+
         let response = client
             .post(format!("{}/audio/speech", config.tts_openai_api_base_url))
             .header(
@@ -288,8 +307,16 @@ async fn speech(
             AppError::ExternalServiceError(format!("Failed to read TTS response: {}", e))
         })?;
 
+        if let Some(dir) = cache_path.parent() {
+            let _ = tokio::fs::create_dir_all(dir).await;
+        }
+        if let Err(e) = tokio::fs::write(&cache_path, &audio_bytes).await {
+            tracing::warn!("Failed to cache TTS audio: {}", e);
+        }
+
         return Ok(HttpResponse::Ok()
             .content_type("audio/mpeg")
+            .insert_header(("Content-Location", cache_url))
             .body(audio_bytes));
     }
 
@@ -306,7 +333,7 @@ async fn transcriptions(
     _auth_user: AuthUser,
     _payload: actix_multipart::Multipart,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     // TODO: Implement file upload handling with multipart
     // TODO: Forward to configured STT engine
@@ -331,7 +358,7 @@ async fn get_models(
     state: web::Data<AppState>,
     _auth_user: AuthUser,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     let mut models = Vec::new();
 
@@ -342,7 +369,7 @@ async fn get_models(
                 .tts_openai_api_base_url
                 .starts_with("https://api.openai.com")
             {
-                let client = reqwest::Client::new();
+                let client = http_client::client(&config);
                 if let Ok(response) = client
                     .get(format!("{}/audio/models", config.tts_openai_api_base_url))
                     .send()
@@ -380,7 +407,7 @@ async fn get_models(
         }
         "elevenlabs" => {
             // Fetch from Elevenlabs API
-            let client = reqwest::Client::new();
+            let client = http_client::client(&config);
             if let Ok(response) = client
                 .get("https://api.elevenlabs.io/v1/models")
                 .header("xi-api-key", &config.tts_api_key)
@@ -416,7 +443,7 @@ async fn get_voices(
     state: web::Data<AppState>,
     _auth_user: AuthUser,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     let mut voices = Vec::new();
 
@@ -427,7 +454,7 @@ async fn get_voices(
                 .tts_openai_api_base_url
                 .starts_with("https://api.openai.com")
             {
-                let client = reqwest::Client::new();
+                let client = http_client::client(&config);
                 if let Ok(response) = client
                     .get(format!("{}/audio/voices", config.tts_openai_api_base_url))
                     .send()
@@ -481,7 +508,7 @@ async fn get_voices(
         }
         "elevenlabs" => {
             // Fetch from Elevenlabs API
-            let client = reqwest::Client::new();
+            let client = http_client::client(&config);
             if let Ok(response) = client
                 .get("https://api.elevenlabs.io/v1/voices")
                 .header("xi-api-key", &config.tts_api_key)
@@ -517,7 +544,7 @@ async fn get_voices(
                 )
             };
 
-            let client = reqwest::Client::new();
+            let client = http_client::client(&config);
             if let Ok(response) = client
                 .get(&url)
                 .header("Ocp-Apim-Subscription-Key", &config.tts_api_key)
@@ -8,27 +8,50 @@ use serde_json::json;
 use validator::Validate;
 
 use crate::error::AppResult;
-use crate::middleware::{AuthMiddleware, AuthUser};
+use crate::middleware::rate_limit::{RateLimit, RouteGroup};
+use crate::middleware::{AuthMiddleware, AuthUser, RealIp};
 use crate::models::{SessionResponse, SigninRequest, SignupRequest};
+use crate::services::group::GroupService;
 use crate::services::{AuthService, UserService};
 use crate::utils::auth::create_jwt;
+use crate::utils::image_compression::{compress_blocking, compress_data_url, ImageBounds};
 use crate::AppState;
 
+/// The `token` auth cookie. It is `Secure` (and sent cross-site) only when the request
+/// came over HTTPS, directly or via a proxy's `X-Forwarded-Proto`, since browsers drop
+/// `Secure` cookies set over plain HTTP.
+fn token_cookie(req: &HttpRequest, value: String) -> Cookie<'static> {
+    let mut cookie = Cookie::new("token", value);
+    cookie.set_http_only(true);
+    if req.connection_info().scheme() == "https" {
+        cookie.set_same_site(SameSite::None);
+        cookie.set_secure(true);
+    } else {
+        cookie.set_same_site(SameSite::Lax);
+    }
+    cookie.set_path("/");
+    cookie
+}
+
 // Helper function to create a cookie for clearing auth cookies
-fn create_clear_cookie() -> Cookie<'static> {
-    let mut token_cookie = Cookie::new("token", "");
-    token_cookie.set_http_only(true);
-    token_cookie.set_same_site(SameSite::None);
-    token_cookie.set_secure(true);
-    token_cookie.set_path("/");
+fn create_clear_cookie(req: &HttpRequest) -> Cookie<'static> {
+    let mut token_cookie = token_cookie(req, String::new());
     token_cookie.set_max_age(time::Duration::seconds(-1));
     token_cookie
 }
 
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
-    cfg.route("/signin", web::post().to(signin))
-        .route("/signup", web::post().to(signup))
-        .route("/signout", web::get().to(signout))
+    cfg.service(
+        web::resource("/signin")
+            .wrap(RateLimit::new(RouteGroup::Auth))
+            .route(web::post().to(signin)),
+    )
+    .service(
+        web::resource("/signup")
+            .wrap(RateLimit::new(RouteGroup::Auth))
+            .route(web::post().to(signup)),
+    );
+    cfg.route("/signout", web::get().to(signout))
         .route("/ldap", web::post().to(ldap_auth))
         .service(
             web::resource("")
@@ -92,7 +115,7 @@ async fn get_session_user(
     auth_user: AuthUser,
     req: HttpRequest,
 ) -> AppResult<HttpResponse> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     // Get token from Authorization header or cookie
     let token = if let Some(auth_header) = req.headers().get(header::AUTHORIZATION) {
@@ -190,11 +213,7 @@ async fn get_session_user(
     });
 
     // Create/refresh cookie with token
-    let mut cookie = Cookie::new("token", token.clone());
-    cookie.set_http_only(true);
-    cookie.set_same_site(SameSite::None);
-    cookie.set_secure(true);
-    cookie.set_path("/");
+    let mut cookie = token_cookie(&req, token.clone());
 
     // Set expiration if available
     if let Some(exp) = expires_at {
@@ -212,18 +231,26 @@ async fn get_session_user(
 
 async fn signin(
     state: web::Data<AppState>,
+    http_req: HttpRequest,
+    client_ip: RealIp,
     req: web::Json<SigninRequest>,
 ) -> AppResult<HttpResponse> {
-    req.validate()
-        .map_err(|e| crate::error::AppError::Validation(e.to_string()))?;
+    req.validate()?;
 
     let auth_service = AuthService::new(&state.db);
     let user_service = UserService::new(&state.db);
 
-    let user_id = auth_service
+    let user_id = match auth_service
         .authenticate(&req.email.to_lowercase(), &req.password)
         .await?
-        .ok_or(crate::error::AppError::InvalidCredentials)?;
+    {
+        Some(user_id) => user_id,
+        None => {
+            // With the client address, for fail2ban-style blocking in front of the app
+            tracing::warn!("Failed sign-in from {}", client_ip);
+            return Err(crate::error::AppError::InvalidCredentials);
+        }
+    };
 
     let user =
         user_service
@@ -233,7 +260,7 @@ async fn signin(
                 "User not found".to_string(),
             ))?;
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let token = create_jwt(&user.id, &config.webui_secret_key, &config.jwt_expires_in)?;
 
     let expires_at = chrono::Utc::now()
@@ -253,11 +280,7 @@ async fn signin(
     };
 
     // Create cookie with token
-    let mut cookie = Cookie::new("token", token);
-    cookie.set_http_only(true);
-    cookie.set_same_site(SameSite::None);
-    cookie.set_secure(true);
-    cookie.set_path("/");
+    let mut cookie = token_cookie(&http_req, token);
 
     // Set expiration if available
     if let Some(exp) = expires_at {
@@ -272,9 +295,10 @@ async fn signin(
 
 async fn signup(
     state: web::Data<AppState>,
+    http_req: HttpRequest,
     req: web::Json<SignupRequest>,
 ) -> AppResult<HttpResponse> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_signup {
         return Err(crate::error::AppError::Forbidden(
@@ -282,8 +306,7 @@ async fn signup(
         ));
     }
 
-    req.validate()
-        .map_err(|e| crate::error::AppError::Validation(e.to_string()))?;
+    req.validate()?;
 
     if let Some(confirmation) = &req.password_confirmation {
         if &req.password != confirmation {
@@ -332,6 +355,8 @@ async fn signup(
         .create_auth(&user_id, &req.email.to_lowercase(), &req.password)
         .await?;
 
+    add_to_default_group(&state.db, config.default_group_id.as_deref(), &user.id).await?;
+
     let token = create_jwt(&user.id, &config.webui_secret_key, &config.jwt_expires_in)?;
 
     let expires_at = chrono::Utc::now()
@@ -351,11 +376,7 @@ async fn signup(
     };
 
     // Create cookie with token
-    let mut cookie = Cookie::new("token", token);
-    cookie.set_http_only(true);
-    cookie.set_same_site(SameSite::None);
-    cookie.set_secure(true);
-    cookie.set_path("/");
+    let mut cookie = token_cookie(&http_req, token);
 
     // Set expiration if available
     if let Some(exp) = expires_at {
@@ -368,14 +389,31 @@ async fn signup(
         .json(session_response))
 }
 
-async fn signout(_state: web::Data<AppState>) -> HttpResponse {
+/// Add a newly registered user to the admin-configured default group, if it still exists
+async fn add_to_default_group(
+    db: &crate::db::Database,
+    group_id: Option<&str>,
+    user_id: &str,
+) -> AppResult<()> {
+    let group_id = match group_id {
+        Some(group_id) => group_id,
+        None => return Ok(()),
+    };
+
+    let group_service = GroupService::new(db);
+    if group_service.get_group_by_id(group_id).await?.is_some() {
+        group_service
+            .add_users_to_group(group_id, &[user_id.to_string()])
+            .await?;
+    } else {
+        tracing::warn!("Default group {} no longer exists, skipping", group_id);
+    }
+    Ok(())
+}
+
+async fn signout(_state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
     // Clear the token cookie by setting an expired cookie
-    let mut cookie = Cookie::new("token", "");
-    cookie.set_http_only(true);
-    cookie.set_same_site(SameSite::None);
-    cookie.set_secure(true);
-    cookie.set_path("/");
-    cookie.set_max_age(time::Duration::seconds(-1));
+    let cookie = create_clear_cookie(&req);
 
     HttpResponse::Ok()
         .append_header((header::SET_COOKIE, cookie.to_string()))
@@ -391,14 +429,23 @@ async fn update_profile(
 
     // Extract update fields from request
     let name = req.get("name").and_then(|v| v.as_str());
-    let profile_image_url = req.get("profile_image_url").and_then(|v| v.as_str());
+    let mut profile_image_url = req
+        .get("profile_image_url")
+        .and_then(|v| v.as_str())
+        .map(|url| url.to_string());
+
+    // Avatars arrive as data URLs; keep them within the configured image bounds
+    let bounds = ImageBounds::from_config(&state.config.snapshot());
+    if let (Some(url), Some(bounds)) = (profile_image_url.clone(), bounds) {
+        profile_image_url = Some(compress_blocking(move || compress_data_url(&url, bounds)).await?);
+    }
 
     // Update user profile in the database
     user_service
         .update_user_profile(
             &auth_user.user.id,
             name,
-            profile_image_url,
+            profile_image_url.as_deref(),
             None, // bio
             None, // gender
             None, // date_of_birth
@@ -479,8 +526,7 @@ async fn add_user(
         ));
     }
 
-    req.validate()
-        .map_err(|e| crate::error::AppError::Validation(e.to_string()))?;
+    req.validate()?;
 
     let user_service = UserService::new(&state.db);
     let auth_service = AuthService::new(&state.db);
@@ -516,7 +562,7 @@ async fn add_user(
         .create_auth(&user_id, &req.email.to_lowercase(), &req.password)
         .await?;
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let token = create_jwt(&user.id, &config.webui_secret_key, &config.jwt_expires_in)?;
 
     Ok(HttpResponse::Ok().json(json!({
@@ -534,7 +580,7 @@ async fn get_admin_details(
     state: web::Data<AppState>,
     _auth_user: AuthUser,
 ) -> AppResult<HttpResponse> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.show_admin_details {
         return Err(crate::error::AppError::Forbidden(
@@ -580,7 +626,7 @@ async fn create_api_key(
     state: web::Data<AppState>,
     auth_user: AuthUser,
 ) -> AppResult<HttpResponse> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_api_key {
         return Err(crate::error::AppError::Forbidden(
@@ -650,6 +696,8 @@ struct AdminConfigResponse {
     api_key_allowed_endpoints: String,
     #[serde(rename = "DEFAULT_USER_ROLE")]
     default_user_role: String,
+    #[serde(rename = "DEFAULT_GROUP_ID", default)]
+    default_group_id: Option<String>,
     #[serde(rename = "JWT_EXPIRES_IN")]
     jwt_expires_in: String,
     #[serde(rename = "ENABLE_COMMUNITY_SHARING")]
@@ -668,6 +716,12 @@ struct AdminConfigResponse {
     pending_user_overlay_content: Option<String>,
     #[serde(rename = "RESPONSE_WATERMARK")]
     response_watermark: Option<String>,
+    #[serde(rename = "RESPONSE_WATERMARK_MODE", default)]
+    response_watermark_mode: Option<String>,
+    #[serde(rename = "RESPONSE_WATERMARK_IN_STREAM", default)]
+    response_watermark_in_stream: Option<bool>,
+    #[serde(rename = "CHAT_RESPONSE_STREAM_DELTA_CHUNK_SIZE", default)]
+    chat_response_stream_delta_chunk_size: Option<usize>,
 }
 
 async fn get_admin_config(
@@ -681,7 +735,7 @@ async fn get_admin_config(
         ));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(AdminConfigResponse {
         show_admin_details: config.show_admin_details,
@@ -691,6 +745,7 @@ async fn get_admin_config(
         enable_api_key_endpoint_restrictions: config.enable_api_key_endpoint_restrictions,
         api_key_allowed_endpoints: config.api_key_allowed_endpoints.clone(),
         default_user_role: config.default_user_role.clone(),
+        default_group_id: config.default_group_id.clone(),
         jwt_expires_in: config.jwt_expires_in.clone(),
         enable_community_sharing: config.enable_community_sharing,
         enable_message_rating: config.enable_message_rating,
@@ -700,6 +755,9 @@ async fn get_admin_config(
         pending_user_overlay_title: config.pending_user_overlay_title.clone(),
         pending_user_overlay_content: config.pending_user_overlay_content.clone(),
         response_watermark: config.response_watermark.clone(),
+        response_watermark_mode: Some(config.response_watermark_mode.clone()),
+        response_watermark_in_stream: Some(config.response_watermark_in_stream),
+        chat_response_stream_delta_chunk_size: Some(config.chat_response_stream_delta_chunk_size),
     }))
 }
 
@@ -715,8 +773,8 @@ async fn update_admin_config(
         ));
     }
 
-    // Update config with write lock
-    let mut config = state.config.write().unwrap();
+    // Update config (applied when the guard drops)
+    let mut config = state.config.write();
 
     config.show_admin_details = form_data.show_admin_details;
     config.webui_url = form_data.webui_url.clone();
@@ -729,6 +787,10 @@ async fn update_admin_config(
     if ["pending", "user", "admin"].contains(&form_data.default_user_role.as_str()) {
         config.default_user_role = form_data.default_user_role.clone();
     }
+    config.default_group_id = form_data
+        .default_group_id
+        .clone()
+        .filter(|id| !id.trim().is_empty());
 
     // Validate JWT_EXPIRES_IN format (basic validation)
     let pattern = regex::Regex::new(r"^(-1|0|(-?\d+(\.\d+)?)(ms|s|m|h|d|w))$").unwrap();
@@ -744,6 +806,20 @@ async fn update_admin_config(
     config.pending_user_overlay_title = form_data.pending_user_overlay_title.clone();
     config.pending_user_overlay_content = form_data.pending_user_overlay_content.clone();
     config.response_watermark = form_data.response_watermark.clone();
+    if let Some(mode) = form_data.response_watermark_mode.as_deref() {
+        if ["visible", "invisible"].contains(&mode) {
+            config.response_watermark_mode = mode.to_string();
+        }
+    }
+    if let Some(in_stream) = form_data.response_watermark_in_stream {
+        config.response_watermark_in_stream = in_stream;
+    }
+    if let Some(size) = form_data
+        .chat_response_stream_delta_chunk_size
+        .filter(|size| *size > 0)
+    {
+        config.chat_response_stream_delta_chunk_size = size;
+    }
 
     // Persist admin config to database
     let admin_config_json = serde_json::json!({
@@ -754,6 +830,7 @@ async fn update_admin_config(
         "enable_api_key_endpoint_restrictions": config.enable_api_key_endpoint_restrictions,
         "api_key_allowed_endpoints": config.api_key_allowed_endpoints,
         "default_user_role": config.default_user_role,
+        "default_group_id": config.default_group_id,
         "jwt_expires_in": config.jwt_expires_in,
         "enable_community_sharing": config.enable_community_sharing,
         "enable_message_rating": config.enable_message_rating,
@@ -763,9 +840,12 @@ async fn update_admin_config(
         "pending_user_overlay_title": config.pending_user_overlay_title,
         "pending_user_overlay_content": config.pending_user_overlay_content,
         "response_watermark": config.response_watermark,
+        "response_watermark_mode": config.response_watermark_mode,
+        "response_watermark_in_stream": config.response_watermark_in_stream,
+        "chat_response_stream_delta_chunk_size": config.chat_response_stream_delta_chunk_size,
     });
 
-    // Drop the write lock before async operations
+    // Apply the update before async operations
     drop(config);
 
     if let Err(e) =
@@ -774,8 +854,8 @@ async fn update_admin_config(
         tracing::warn!("Failed to persist admin config to database: {}", e);
     }
 
-    // Re-acquire read lock for response
-    let config = state.config.read().unwrap();
+    // Read the updated config for the response
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(AdminConfigResponse {
         show_admin_details: config.show_admin_details,
@@ -785,6 +865,7 @@ async fn update_admin_config(
         enable_api_key_endpoint_restrictions: config.enable_api_key_endpoint_restrictions,
         api_key_allowed_endpoints: config.api_key_allowed_endpoints.clone(),
         default_user_role: config.default_user_role.clone(),
+        default_group_id: config.default_group_id.clone(),
         jwt_expires_in: config.jwt_expires_in.clone(),
         enable_community_sharing: config.enable_community_sharing,
         enable_message_rating: config.enable_message_rating,
@@ -794,6 +875,9 @@ async fn update_admin_config(
         pending_user_overlay_title: config.pending_user_overlay_title.clone(),
         pending_user_overlay_content: config.pending_user_overlay_content.clone(),
         response_watermark: config.response_watermark.clone(),
+        response_watermark_mode: Some(config.response_watermark_mode.clone()),
+        response_watermark_in_stream: Some(config.response_watermark_in_stream),
+        chat_response_stream_delta_chunk_size: Some(config.chat_response_stream_delta_chunk_size),
     }))
 }
 
@@ -838,7 +922,7 @@ async fn get_ldap_config(
         ));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(LdapConfigResponse {
         enable_ldap: config.enable_ldap,
@@ -857,8 +941,8 @@ async fn update_ldap_config(
         ));
     }
 
-    // Update config with write lock
-    let mut config = state.config.write().unwrap();
+    // Update config (applied when the guard drops)
+    let mut config = state.config.write();
     config.enable_ldap = form_data.enable_ldap;
 
     // TODO: Persist to database
@@ -879,7 +963,7 @@ async fn get_ldap_server(
         ));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(LdapServerConfig {
         label: config.ldap_server_label.clone(),
@@ -924,8 +1008,8 @@ async fn update_ldap_server(
         ));
     }
 
-    // Update config with write lock
-    let mut config = state.config.write().unwrap();
+    // Update config (applied when the guard drops)
+    let mut config = state.config.write();
 
     config.ldap_server_label = form_data.label.clone();
     config.ldap_server_host = form_data.host.clone();
@@ -971,12 +1055,12 @@ struct LdapAuthRequest {
 
 async fn ldap_auth(
     state: web::Data<AppState>,
+    http_req: HttpRequest,
     req: web::Json<LdapAuthRequest>,
 ) -> AppResult<HttpResponse> {
-    req.validate()
-        .map_err(|e| crate::error::AppError::Validation(e.to_string()))?;
+    req.validate()?;
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     // Check if LDAP is enabled
     if !config.enable_ldap {
@@ -1048,6 +1132,9 @@ async fn ldap_auth(
                 )
                 .await?,
         );
+        if let Some(user) = &user {
+            add_to_default_group(&state.db, config.default_group_id.as_deref(), &user.id).await?;
+        }
     }
 
     let user = user.ok_or(crate::error::AppError::NotFound(
@@ -1074,10 +1161,7 @@ async fn ldap_auth(
     };
 
     // Create cookie with token
-    let mut cookie = Cookie::new("token", token);
-    cookie.set_http_only(true);
-    cookie.set_same_site(SameSite::Lax);
-    cookie.set_path("/");
+    let mut cookie = token_cookie(&http_req, token);
 
     // Set expiration if available
     if let Some(exp) = expires_at {
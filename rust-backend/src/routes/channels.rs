@@ -32,6 +32,8 @@ struct ChannelResponse {
     updated_at: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
     write_access: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unread_count: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -84,6 +86,11 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AuthMiddleware)
             .route(web::delete().to(delete_channel_by_id)),
     )
+    .service(
+        web::resource("/{id}/presence")
+            .wrap(AuthMiddleware)
+            .route(web::get().to(get_channel_presence)),
+    )
     // Message routes
     .service(
         web::resource("/{id}/messages")
@@ -115,6 +122,11 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AuthMiddleware)
             .route(web::delete().to(delete_message_by_id)),
     )
+    .service(
+        web::resource("/{id}/messages/{message_id}/reactions")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(toggle_reaction_on_message)),
+    )
     .service(
         web::resource("/{id}/messages/{message_id}/reactions/add")
             .wrap(AuthMiddleware)
@@ -133,9 +145,19 @@ async fn get_channels(state: web::Data<AppState>, auth_user: AuthUser) -> AppRes
         .get_channels_by_user_id(&auth_user.user.id)
         .await?;
 
-    let response: Vec<ChannelResponse> = channels
-        .iter()
-        .map(|channel| ChannelResponse {
+    let channel_ids: Vec<String> = channels.iter().map(|c| c.id.clone()).collect();
+    let unread_counts = channel_service
+        .get_unread_counts(&channel_ids, &auth_user.user.id)
+        .await
+        .ok();
+
+    let mut response: Vec<ChannelResponse> = Vec::with_capacity(channels.len());
+    for channel in &channels {
+        let unread_count = unread_counts
+            .as_ref()
+            .map(|counts| counts.get(&channel.id).copied().unwrap_or(0));
+
+        response.push(ChannelResponse {
             id: channel.id.clone(),
             user_id: channel.user_id.clone(),
             channel_type: channel.channel_type.clone(),
@@ -147,8 +169,9 @@ async fn get_channels(state: web::Data<AppState>, auth_user: AuthUser) -> AppRes
             created_at: channel.created_at,
             updated_at: channel.updated_at,
             write_access: None,
-        })
-        .collect();
+            unread_count,
+        });
+    }
 
     Ok(HttpResponse::Ok().json(response))
 }
@@ -180,6 +203,7 @@ async fn get_all_channels(
             created_at: channel.created_at,
             updated_at: channel.updated_at,
             write_access: None,
+            unread_count: None,
         })
         .collect();
 
@@ -196,8 +220,7 @@ async fn create_new_channel(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    form.validate()
-        .map_err(|e| AppError::Validation(e.to_string()))?;
+    form.validate()?;
 
     let channel_service = ChannelService::new(&state.db);
 
@@ -229,6 +252,7 @@ async fn create_new_channel(
         created_at: channel.created_at,
         updated_at: channel.updated_at,
         write_access: None,
+        unread_count: None,
     };
 
     Ok(HttpResponse::Ok().json(response))
@@ -272,6 +296,7 @@ async fn get_channel_by_id(
         created_at: channel.created_at,
         updated_at: channel.updated_at,
         write_access: Some(write_access),
+        unread_count: None,
     };
 
     Ok(HttpResponse::Ok().json(response))
@@ -293,8 +318,7 @@ async fn update_channel_by_id(
         .await?
         .ok_or_else(|| AppError::NotFound("Channel not found".to_string()))?;
 
-    form.validate()
-        .map_err(|e| AppError::Validation(e.to_string()))?;
+    form.validate()?;
 
     let updated_channel = channel_service
         .update_channel(
@@ -320,6 +344,7 @@ async fn update_channel_by_id(
         created_at: updated_channel.created_at,
         updated_at: updated_channel.updated_at,
         write_access: None,
+        unread_count: None,
     };
 
     Ok(HttpResponse::Ok().json(response))
@@ -345,6 +370,51 @@ async fn delete_channel_by_id(
     Ok(HttpResponse::Ok().json(true))
 }
 
+/// Returns the ids of channel members that currently have a live Socket.IO session in the
+/// channel room. Sessions only join rooms of channels they can read.
+async fn get_channel_presence(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    id: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    let channel_service = ChannelService::new(&state.db);
+    let channel = channel_service
+        .get_channel_by_id(&id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Channel not found".to_string()))?;
+
+    // Check read access
+    if auth_user.user.role != "admin" && channel.user_id != auth_user.user.id {
+        let has_read_access = crate::utils::access_control::has_access(
+            &state.db,
+            &auth_user.user.id,
+            "read",
+            channel.access_control.as_ref(),
+            false,
+        )
+        .await?;
+
+        if !has_read_access {
+            return Err(AppError::Forbidden("Access denied".to_string()));
+        }
+    }
+
+    let user_ids = match state.socketio_handler {
+        Some(ref socketio_handler) => {
+            socketio_handler
+                .manager()
+                .get_room_user_ids(&format!("channel:{}", channel.id))
+                .await
+        }
+        None => Vec::new(),
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "channel_id": channel.id,
+        "user_ids": user_ids,
+    })))
+}
+
 // Message response with user information
 #[derive(Debug, Serialize)]
 struct MessageUserResponse {
@@ -395,6 +465,9 @@ fn default_limit() -> i64 {
     50
 }
 
+/// Upper bound for a single page of channel messages
+const MAX_MESSAGES_PAGE_LIMIT: i64 = 200;
+
 async fn get_channel_messages(
     state: web::Data<AppState>,
     auth_user: AuthUser,
@@ -423,32 +496,47 @@ async fn get_channel_messages(
         }
     }
 
+    let skip = query.skip.max(0);
+    let limit = query.limit.clamp(1, MAX_MESSAGES_PAGE_LIMIT);
+
     let message_service = MessageService::new(&state.db);
     let messages = message_service
-        .get_messages_by_channel_id(&id, query.skip, query.limit)
+        .get_messages_by_channel_id(&id, skip, limit)
+        .await?;
+
+    // Loading the newest page means the user has caught up on the channel
+    if skip == 0 {
+        if let Err(e) = channel_service
+            .mark_channel_read(&id, &auth_user.user.id)
+            .await
+        {
+            tracing::warn!("Failed to mark channel {} as read: {}", id, e);
+        }
+    }
+
+    // Fetch thread and reaction summaries for the whole page at once
+    let message_ids: Vec<String> = messages.iter().map(|m| m.id.clone()).collect();
+    let thread_summaries = message_service.get_thread_summaries(&message_ids).await?;
+    let mut reactions_by_message = message_service
+        .get_reactions_by_message_ids(&message_ids)
         .await?;
 
     let mut response = Vec::new();
     for message in messages {
-        let message_response = message_service.to_message_response(message.clone()).await?;
-        let reply_count = message_service
-            .get_thread_replies_count(&message.id)
-            .await
-            .ok();
-        let latest_reply_at = message_service
-            .get_latest_thread_reply_at(&message.id)
-            .await
-            .ok()
-            .flatten();
-        let reactions = message_service.get_reactions(&message.id).await.ok();
+        let (reply_count, latest_reply_at) = thread_summaries
+            .get(&message.id)
+            .copied()
+            .unwrap_or((0, None));
+        let reactions = reactions_by_message.remove(&message.id).unwrap_or_default();
+        let message_response = message_service.to_message_response(message).await?;
 
         response.push(MessageUserResponse {
             message: message_response,
             user: None, // User is already in message_response
             reply_to_message: None,
-            reply_count,
+            reply_count: Some(reply_count),
             latest_reply_at,
-            reactions,
+            reactions: Some(reactions),
         });
     }
 
@@ -531,12 +619,26 @@ async fn post_new_message(
                 {
                     let parent_message_response =
                         message_service.to_message_response(parent_message).await?;
+                    let (reply_count, latest_reply_at) = message_service
+                        .get_thread_summaries(std::slice::from_ref(parent_id))
+                        .await
+                        .ok()
+                        .and_then(|summaries| summaries.get(parent_id).copied())
+                        .unwrap_or((0, None));
                     let parent_event_data = json!({
                         "channel_id": &channel_id,
                         "message_id": parent_id,
                         "data": {
                             "type": "message:reply",
-                            "data": parent_message_response,
+                            "data": MessageUserResponse {
+                                message: parent_message_response,
+                                user: None,
+                                reply_to_message: None,
+                                reply_count: Some(reply_count),
+                                latest_reply_at,
+                                reactions: None,
+                            },
+                            "reply": &message_response,
                         },
                         "user": UserNameResponse::from(user),
                         "channel": {
@@ -638,15 +740,23 @@ async fn get_channel_thread_messages(
         }
     }
 
+    let skip = query.skip.max(0);
+    let limit = query.limit.clamp(1, MAX_MESSAGES_PAGE_LIMIT);
+
     let message_service = MessageService::new(&state.db);
     let messages = message_service
-        .get_thread_messages(&id, &message_id, query.skip, query.limit)
+        .get_thread_messages(&id, &message_id, skip, limit)
+        .await?;
+
+    let message_ids: Vec<String> = messages.iter().map(|m| m.id.clone()).collect();
+    let mut reactions_by_message = message_service
+        .get_reactions_by_message_ids(&message_ids)
         .await?;
 
     let mut response = Vec::new();
     for message in messages {
-        let message_response = message_service.to_message_response(message.clone()).await?;
-        let reactions = message_service.get_reactions(&message.id).await.ok();
+        let reactions = reactions_by_message.remove(&message.id).unwrap_or_default();
+        let message_response = message_service.to_message_response(message).await?;
 
         response.push(MessageUserResponse {
             message: message_response,
@@ -654,7 +764,7 @@ async fn get_channel_thread_messages(
             reply_to_message: None,
             reply_count: Some(0),
             latest_reply_at: None,
-            reactions,
+            reactions: Some(reactions),
         });
     }
 
@@ -789,6 +899,7 @@ async fn delete_message_by_id(
                     "type": "message:delete",
                     "data": {
                         "id": &message_id,
+                        "parent_id": &message.parent_id,
                         "user": UserNameResponse::from(user.clone()),
                     },
                 },
@@ -954,3 +1065,115 @@ async fn remove_reaction_from_message(
 
     Ok(HttpResponse::Ok().json(true))
 }
+
+/// Add the reaction if the user hasn't used this emoji on the message yet, otherwise remove it
+async fn toggle_reaction_on_message(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    path: web::Path<(String, String)>,
+    form: web::Json<ReactionForm>,
+) -> AppResult<HttpResponse> {
+    let (id, message_id) = path.into_inner();
+
+    if form.name.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "Reaction name is required".to_string(),
+        ));
+    }
+
+    let channel_service = ChannelService::new(&state.db);
+    let channel = channel_service
+        .get_channel_by_id(&id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Channel not found".to_string()))?;
+
+    // Check read access
+    if auth_user.user.role != "admin" && channel.user_id != auth_user.user.id {
+        let has_read_access = crate::utils::access_control::has_access(
+            &state.db,
+            &auth_user.user.id,
+            "read",
+            channel.access_control.as_ref(),
+            false,
+        )
+        .await?;
+
+        if !has_read_access {
+            return Err(AppError::Forbidden("Access denied".to_string()));
+        }
+    }
+
+    let message_service = MessageService::new(&state.db);
+    let message = message_service
+        .get_message_by_id(&message_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Message not found".to_string()))?;
+
+    if message.channel_id.as_ref() != Some(&id) {
+        return Err(AppError::BadRequest(
+            "Message does not belong to this channel".to_string(),
+        ));
+    }
+
+    let removed = message_service
+        .has_reaction(&message_id, &auth_user.user.id, &form.name)
+        .await?;
+    if removed {
+        message_service
+            .remove_reaction(&message_id, &auth_user.user.id, &form.name)
+            .await?;
+    } else {
+        message_service
+            .add_reaction(&message_id, &auth_user.user.id, &form.name)
+            .await?;
+    }
+
+    let reactions = message_service.get_reactions(&message_id).await?;
+    let event_type = if removed {
+        "message:reaction:remove"
+    } else {
+        "message:reaction:add"
+    };
+
+    // Emit Socket.IO event for real-time updates
+    if let Some(ref socketio_handler) = state.socketio_handler {
+        let user_service = UserService::new(&state.db);
+        if let Some(user) = user_service
+            .get_user_by_id(&auth_user.user.id)
+            .await
+            .ok()
+            .flatten()
+        {
+            let event_data = json!({
+                "channel_id": &id,
+                "message_id": &message_id,
+                "data": {
+                    "type": event_type,
+                    "data": {
+                        "id": &message_id,
+                        "parent_id": &message.parent_id,
+                        "name": &form.name,
+                        "user_id": &auth_user.user.id,
+                        "reactions": &reactions,
+                    },
+                },
+                "user": UserNameResponse::from(user),
+                "channel": {
+                    "id": channel.id,
+                    "name": channel.name,
+                }
+            });
+
+            // Broadcast to all users in the channel room
+            let room = format!("channel:{}", id);
+            let _ = socketio_handler
+                .broadcast_to_room(&room, "channel-events", event_data, None)
+                .await;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "added": !removed,
+        "reactions": reactions,
+    })))
+}
@@ -4,7 +4,7 @@ use serde_json::json;
 
 use crate::error::AppResult;
 use crate::middleware::{AuthMiddleware, AuthUser};
-use crate::models::chat::{ChatResponse, CreateChatRequest, UpdateChatRequest};
+use crate::models::chat::{ChatListFilter, ChatResponse, CreateChatRequest, UpdateChatRequest};
 use crate::services::chat::ChatService;
 use crate::AppState;
 
@@ -66,6 +66,11 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AuthMiddleware)
             .route(web::get().to(get_pinned_chats)),
     )
+    .service(
+        web::resource("/bookmarks")
+            .wrap(AuthMiddleware)
+            .route(web::get().to(get_bookmarked_messages)),
+    )
     .service(
         web::resource("/folder/{folder_id}")
             .wrap(AuthMiddleware)
@@ -154,6 +159,11 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AuthMiddleware)
             .route(web::post().to(update_chat_message_by_id)),
     )
+    .service(
+        web::resource("/{id}/message/{message_id}/bookmark")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(toggle_message_bookmark)),
+    )
     .service(
         web::resource("/{id}/messages/{message_id}/event")
             .wrap(AuthMiddleware)
@@ -192,24 +202,36 @@ pub struct ImportChatRequest {
 #[derive(Debug, Deserialize)]
 pub struct ChatListQuery {
     pub page: Option<i64>,
+    pub limit: Option<i64>,
 }
 
+impl ChatListQuery {
+    /// `(skip, limit)` for the requested page
+    fn pagination(&self) -> (i64, i64) {
+        let page = self.page.unwrap_or(1).max(1);
+        let limit = self
+            .limit
+            .unwrap_or(DEFAULT_CHAT_LIST_LIMIT)
+            .clamp(1, MAX_CHAT_LIST_LIMIT);
+        ((page - 1) * limit, limit)
+    }
+}
+
+const DEFAULT_CHAT_LIST_LIMIT: i64 = 50;
+const MAX_CHAT_LIST_LIMIT: i64 = 200;
+
 async fn get_chats(
     state: web::Data<AppState>,
     auth_user: AuthUser,
     query: web::Query<ChatListQuery>,
 ) -> AppResult<HttpResponse> {
     let service = ChatService::new(&state.db);
-    let page = query.page.unwrap_or(1);
-    let limit = 50;
-    let skip = (page - 1) * limit;
+    let (skip, limit) = query.pagination();
 
     let chats = service
-        .get_chats_by_user_id(&auth_user.id, false, skip, limit)
+        .get_chat_list_items_by_user_id(&auth_user.id, ChatListFilter::Active, skip, limit)
         .await?;
-
-    let responses: Vec<ChatResponse> = chats.into_iter().map(|c| c.into()).collect();
-    Ok(HttpResponse::Ok().json(responses))
+    Ok(HttpResponse::Ok().json(chats))
 }
 
 async fn get_all_chats(state: web::Data<AppState>, auth_user: AuthUser) -> AppResult<HttpResponse> {
@@ -247,12 +269,15 @@ async fn get_all_tags(
 async fn get_pinned_chats(
     state: web::Data<AppState>,
     auth_user: AuthUser,
+    query: web::Query<ChatListQuery>,
 ) -> AppResult<HttpResponse> {
     let service = ChatService::new(&state.db);
-    let chats = service.get_pinned_chats_by_user_id(&auth_user.id).await?;
+    let (skip, limit) = query.pagination();
 
-    let responses: Vec<ChatResponse> = chats.into_iter().map(|c| c.into()).collect();
-    Ok(HttpResponse::Ok().json(responses))
+    let chats = service
+        .get_chat_list_items_by_user_id(&auth_user.id, ChatListFilter::Pinned, skip, limit)
+        .await?;
+    Ok(HttpResponse::Ok().json(chats))
 }
 
 async fn create_new_chat(
@@ -544,6 +569,7 @@ async fn get_user_archived_chats(
 #[derive(Debug, Deserialize)]
 pub struct ArchivedChatListQuery {
     pub page: Option<i64>,
+    pub limit: Option<i64>,
     pub query: Option<String>,
     pub order_by: Option<String>,
     pub direction: Option<String>,
@@ -555,12 +581,14 @@ async fn get_archived_session_user_chat_list(
     query: web::Query<ArchivedChatListQuery>,
 ) -> AppResult<HttpResponse> {
     let service = ChatService::new(&state.db);
-    let page = query.page.unwrap_or(1);
-    let limit = 60;
-    let skip = (page - 1) * limit;
+    let (skip, limit) = ChatListQuery {
+        page: query.page,
+        limit: query.limit,
+    }
+    .pagination();
 
     let chats = service
-        .get_archived_chat_list_by_user_id(&auth_user.id, skip, limit)
+        .get_chat_list_items_by_user_id(&auth_user.id, ChatListFilter::Archived, skip, limit)
         .await?;
     Ok(HttpResponse::Ok().json(chats))
 }
@@ -741,6 +769,45 @@ async fn update_chat_message_by_id(
     Ok(HttpResponse::Ok().json(response))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct BookmarkForm {
+    pub bookmarked: Option<bool>,
+}
+
+async fn toggle_message_bookmark(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    path: web::Path<(String, String)>,
+    form_data: Option<web::Json<BookmarkForm>>,
+) -> AppResult<HttpResponse> {
+    let (id, message_id) = path.into_inner();
+    let service = ChatService::new(&state.db);
+
+    // No body toggles the current state
+    let bookmarked = form_data.and_then(|f| f.bookmarked);
+    let bookmarked = service
+        .set_message_bookmark(&id, &message_id, &auth_user.id, bookmarked)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "chat_id": id,
+        "message_id": message_id,
+        "bookmarked": bookmarked,
+    })))
+}
+
+async fn get_bookmarked_messages(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> AppResult<HttpResponse> {
+    let service = ChatService::new(&state.db);
+    let bookmarks = service
+        .get_bookmarked_messages_by_user_id(&auth_user.id)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(bookmarks))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct EventForm {
     pub r#type: String,
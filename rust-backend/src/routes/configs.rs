@@ -1,10 +1,21 @@
-use actix_web::{web, HttpResponse};
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::StreamExt as _;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::{
     error::AppError,
-    middleware::{AuthMiddleware, AuthUser},
+    middleware::{
+        audit::AuditFilter, rate_limit::RateLimitPolicy, AuthMiddleware, AuthUser, RealIp,
+    },
+    models::audit::AuditLog,
+    models::config::{
+        validate_prompt_suggestions, Banner, BannerForm, DirectConnections, PromptSuggestion,
+    },
+    services::audit::AuditService,
+    utils::branding::{self, BrandingAsset, MAX_BRANDING_IMAGE_BYTES},
+    utils::time::current_timestamp_seconds,
     AppState,
 };
 
@@ -93,13 +104,36 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .route("/banners", web::post().to(set_banners))
             .route("/connections", web::get().to(get_connections_config))
             .route("/connections", web::post().to(set_connections_config))
+            .route(
+                "/direct_connections",
+                web::get().to(get_direct_connections_config),
+            )
+            .route(
+                "/direct_connections",
+                web::post().to(set_direct_connections_config),
+            )
             .route("/code_execution", web::get().to(get_code_execution_config))
             .route("/code_execution", web::post().to(set_code_execution_config))
             .route("/models", web::get().to(get_models_config))
             .route("/models", web::post().to(set_models_config))
+            .route("/suggestions", web::get().to(get_default_suggestions))
             .route("/suggestions", web::post().to(set_default_suggestions))
             .route("/tool_servers", web::get().to(get_tool_servers_config))
-            .route("/tool_servers", web::post().to(set_tool_servers_config)),
+            .route("/tool_servers", web::post().to(set_tool_servers_config))
+            .route(
+                "/security_headers",
+                web::get().to(get_security_headers_config),
+            )
+            .route(
+                "/security_headers",
+                web::post().to(set_security_headers_config),
+            )
+            .route("/rate_limits", web::get().to(get_rate_limits_config))
+            .route("/body_limits", web::get().to(get_body_limits_config))
+            .route("/body_limits", web::post().to(set_body_limits_config))
+            .route("/branding", web::get().to(get_branding_config))
+            .route("/branding/reset", web::post().to(reset_branding))
+            .route("/branding/{asset}", web::post().to(upload_branding_image)),
     );
 }
 
@@ -107,7 +141,7 @@ async fn get_configs(
     state: web::Data<AppState>,
     _user: AuthUser,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(json!({
         "status": true,
@@ -136,19 +170,78 @@ async fn update_configs(
     ))
 }
 
+#[derive(Debug, Deserialize)]
+struct ExportConfigQuery {
+    #[serde(default = "default_redact_secrets")]
+    redact_secrets: bool,
+}
+
+fn default_redact_secrets() -> bool {
+    true
+}
+
+/// Store an audit entry for a config export or import, which are audited even when
+/// ENABLE_AUDIT_LOG is off. Requests the audit middleware records are left to it.
+async fn record_config_audit(
+    state: &AppState,
+    req: &HttpRequest,
+    auth_user: &AuthUser,
+    details: serde_json::Value,
+) {
+    let config = state.config.snapshot();
+    let method = req.method().as_str();
+    let path = req.path();
+    if config.enable_audit_log && AuditFilter::from_config(&config).matches(method, path) {
+        return;
+    }
+
+    let entry = AuditLog {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: Some(auth_user.user.id.clone()),
+        method: method.to_string(),
+        path: path.to_string(),
+        status: 200,
+        source_ip: RealIp::of(req).0.map(|ip| ip.to_string()),
+        request_body: Some(details.to_string()),
+        created_at: current_timestamp_seconds(),
+    };
+    if let Err(e) = AuditService::new(&state.db).insert_entry(&entry).await {
+        tracing::warn!("Failed to write config audit entry: {}", e);
+    }
+}
+
 async fn export_config(
+    req: HttpRequest,
     state: web::Data<AppState>,
     auth_user: AuthUser,
+    query: web::Query<ExportConfigQuery>,
 ) -> Result<HttpResponse, AppError> {
     // Only admins can export config
     if auth_user.user.role != "admin" {
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
+    let data =
+        crate::services::ConfigService::export_config(&state.db, &config, query.redact_secrets)
+            .await?;
 
-    // Export all config as JSON
-    Ok(HttpResponse::Ok().json(serde_json::to_value(&*config).unwrap()))
+    tracing::info!(
+        target: "audit",
+        user_id = %auth_user.user.id,
+        email = %auth_user.user.email,
+        redact_secrets = query.redact_secrets,
+        "Config exported"
+    );
+    record_config_audit(
+        &state,
+        &req,
+        &auth_user,
+        json!({ "redact_secrets": query.redact_secrets }),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(data))
 }
 
 #[derive(Debug, Deserialize)]
@@ -157,26 +250,50 @@ struct ImportConfigForm {
 }
 
 async fn import_config(
+    req: HttpRequest,
     state: web::Data<AppState>,
     auth_user: AuthUser,
-    _form_data: web::Json<ImportConfigForm>,
+    form_data: web::Json<ImportConfigForm>,
 ) -> Result<HttpResponse, AppError> {
     // Only admins can import config
     if auth_user.user.role != "admin" {
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    // TODO: Implement config import from JSON
-    // For now, just return the current config
-    let config = state.config.read().unwrap();
-    Ok(HttpResponse::Ok().json(serde_json::to_value(&*config).unwrap()))
+    let current = state.config.snapshot().as_ref().clone();
+    let (config, changed_sections) =
+        crate::services::ConfigService::import_config(&state.db, current, &form_data.config)
+            .await?;
+
+    *state.config.write() = config.clone();
+
+    tracing::info!(
+        target: "audit",
+        user_id = %auth_user.user.id,
+        email = %auth_user.user.email,
+        changed_sections = ?changed_sections,
+        "Config imported"
+    );
+    record_config_audit(
+        &state,
+        &req,
+        &auth_user,
+        json!({ "changed_sections": changed_sections }),
+    )
+    .await;
+
+    let data = crate::services::ConfigService::export_config(&state.db, &config, true).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "config": data,
+        "changed_sections": changed_sections,
+    })))
 }
 
 async fn get_features(
     state: web::Data<AppState>,
     _user: AuthUser,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(json!({
         "enable_signup": config.enable_signup,
@@ -198,13 +315,13 @@ async fn get_banners(
     state: web::Data<AppState>,
     _user: AuthUser,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     Ok(HttpResponse::Ok().json(&config.banners))
 }
 
 #[derive(Debug, Deserialize)]
 struct SetBannersForm {
-    banners: serde_json::Value,
+    banners: Vec<BannerForm>,
 }
 
 async fn set_banners(
@@ -217,27 +334,34 @@ async fn set_banners(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    // Update in-memory config
-    {
-        let mut config = state.config.write().unwrap();
-        config.banners = form_data.banners.clone();
-    }
+    let now = current_timestamp_seconds();
+    let banners = form_data
+        .into_inner()
+        .banners
+        .into_iter()
+        .map(|banner| banner.into_banner(now))
+        .collect::<Result<Vec<Banner>, String>>()
+        .map_err(AppError::BadRequest)?;
+    let banners = json!(banners);
 
-    // Persist to database (best-effort)
-    let config = state.config.read().unwrap();
-    let ui_json = serde_json::json!({
-        "banners": config.banners
+    // Save before updating the in-memory config, so a failed save changes nothing; the
+    // "ui" section also holds the prompt suggestions
+    let ui_json = json!({
+        "banners": banners,
+        "default_prompt_suggestions": state.config.snapshot().default_prompt_suggestions
     });
-    let _ = crate::services::ConfigService::update_section(&state.db, "ui", ui_json).await;
+    crate::services::ConfigService::update_section(&state.db, "ui", ui_json).await?;
 
-    Ok(HttpResponse::Ok().json(&config.banners))
+    state.config.write().banners = banners.clone();
+
+    Ok(HttpResponse::Ok().json(banners))
 }
 
 async fn get_connections_config(
     state: web::Data<AppState>,
     _user: AuthUser,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(ConnectionsConfigResponse {
         enable_direct_connections: config.enable_direct_connections,
@@ -257,7 +381,7 @@ async fn set_connections_config(
 
     // Update in-memory config (matching Python's behavior exactly)
     {
-        let mut config = state.config.write().unwrap();
+        let mut config = state.config.write();
         config.enable_direct_connections = payload.enable_direct_connections;
         config.enable_base_models_cache = payload.enable_base_models_cache;
     }
@@ -276,9 +400,13 @@ async fn set_connections_config(
     }
 
     // Also update the "direct" section for backward compatibility
-    let direct_json = serde_json::json!({
-        "enable": payload.enable_direct_connections
-    });
+    let direct_json = {
+        let config = state.config.snapshot();
+        serde_json::json!({
+            "enable": payload.enable_direct_connections,
+            "default_connections": config.default_direct_connections
+        })
+    };
     if let Err(e) =
         crate::services::ConfigService::update_section(&state.db, "direct", direct_json).await
     {
@@ -286,13 +414,86 @@ async fn set_connections_config(
     }
 
     // Return success even if DB save failed (config is updated in memory)
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     Ok(HttpResponse::Ok().json(ConnectionsConfigResponse {
         enable_direct_connections: config.enable_direct_connections,
         enable_base_models_cache: config.enable_base_models_cache,
     }))
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct DirectConnectionsConfigForm {
+    #[serde(rename = "ENABLE_DIRECT_CONNECTIONS")]
+    enable_direct_connections: bool,
+    #[serde(rename = "DEFAULT_DIRECT_CONNECTIONS", default)]
+    default_direct_connections: Option<DirectConnections>,
+}
+
+async fn get_direct_connections_config(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> Result<HttpResponse, AppError> {
+    if auth_user.user.role != "admin" {
+        return Err(AppError::Forbidden("Admin access required".to_string()));
+    }
+
+    let config = state.config.snapshot();
+    Ok(HttpResponse::Ok().json(DirectConnectionsConfigForm {
+        enable_direct_connections: config.enable_direct_connections,
+        default_direct_connections: Some(DirectConnections::from_value(
+            &config.default_direct_connections,
+        )),
+    }))
+}
+
+/// Toggle direct connections and optionally replace the instance-wide default
+/// connections that are offered to every user after their own
+async fn set_direct_connections_config(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    payload: web::Json<DirectConnectionsConfigForm>,
+) -> Result<HttpResponse, AppError> {
+    if auth_user.user.role != "admin" {
+        return Err(AppError::Forbidden("Admin access required".to_string()));
+    }
+
+    let payload = payload.into_inner();
+    if let Some(defaults) = &payload.default_direct_connections {
+        defaults.validate().map_err(AppError::BadRequest)?;
+    }
+
+    let (direct_json, connections_json) = {
+        let mut config = state.config.write();
+        config.enable_direct_connections = payload.enable_direct_connections;
+        if let Some(defaults) = &payload.default_direct_connections {
+            config.default_direct_connections = json!(defaults);
+        }
+        (
+            json!({
+                "enable": config.enable_direct_connections,
+                "default_connections": config.default_direct_connections
+            }),
+            json!({
+                "enable_direct_connections": config.enable_direct_connections,
+                "enable_base_models_cache": config.enable_base_models_cache
+            }),
+        )
+    };
+
+    // The flag lives in both sections; "connections" wins when the config is loaded
+    crate::services::ConfigService::update_section(&state.db, "direct", direct_json).await?;
+    crate::services::ConfigService::update_section(&state.db, "connections", connections_json)
+        .await?;
+
+    let config = state.config.snapshot();
+    Ok(HttpResponse::Ok().json(DirectConnectionsConfigForm {
+        enable_direct_connections: config.enable_direct_connections,
+        default_direct_connections: Some(DirectConnections::from_value(
+            &config.default_direct_connections,
+        )),
+    }))
+}
+
 async fn get_code_execution_config(
     state: web::Data<AppState>,
     auth_user: AuthUser,
@@ -302,7 +503,7 @@ async fn get_code_execution_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(CodeExecutionConfigForm {
         enable_code_execution: config.enable_code_execution,
@@ -345,7 +546,7 @@ async fn set_code_execution_config(
 
     // Update in-memory config
     {
-        let mut config = state.config.write().unwrap();
+        let mut config = state.config.write();
 
         config.enable_code_execution = form_data.enable_code_execution;
         config.code_execution_engine = form_data.code_execution_engine.clone();
@@ -380,7 +581,7 @@ async fn set_code_execution_config(
     }
 
     // Persist to database (best-effort, like Python)
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let code_execution_json = serde_json::json!({
         "engine": config.code_execution_engine,
         "jupyter_url": config.code_execution_jupyter_url,
@@ -465,7 +666,7 @@ async fn get_models_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(ModelsConfigForm {
         default_models: Some(config.default_models.clone()),
@@ -485,7 +686,7 @@ async fn set_models_config(
 
     // Update in-memory config
     {
-        let mut config = state.config.write().unwrap();
+        let mut config = state.config.write();
 
         if let Some(default_models) = &form_data.default_models {
             config.default_models = default_models.clone();
@@ -496,7 +697,7 @@ async fn set_models_config(
     }
 
     // Persist to database (best-effort)
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let models_json = serde_json::json!({
         "default_models": config.default_models,
         "model_order_list": config.model_order_list
@@ -509,9 +710,21 @@ async fn set_models_config(
     }))
 }
 
+async fn get_default_suggestions(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> Result<HttpResponse, AppError> {
+    if auth_user.user.role != "admin" {
+        return Err(AppError::Forbidden("Admin access required".to_string()));
+    }
+
+    let config = state.config.snapshot();
+    Ok(HttpResponse::Ok().json(&config.default_prompt_suggestions))
+}
+
 #[derive(Debug, Deserialize)]
 struct SetDefaultSuggestionsForm {
-    suggestions: serde_json::Value,
+    suggestions: Vec<PromptSuggestion>,
 }
 
 async fn set_default_suggestions(
@@ -524,20 +737,21 @@ async fn set_default_suggestions(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    // Update in-memory config
-    {
-        let mut config = state.config.write().unwrap();
-        config.default_prompt_suggestions = form_data.suggestions.clone();
-    }
+    let suggestions = form_data.into_inner().suggestions;
+    validate_prompt_suggestions(&suggestions).map_err(AppError::BadRequest)?;
+    let suggestions = json!(suggestions);
 
-    // Persist to database (best-effort)
-    let config = state.config.read().unwrap();
-    let ui_json = serde_json::json!({
-        "default_prompt_suggestions": config.default_prompt_suggestions
+    // Save before updating the in-memory config, so a failed save changes nothing; the
+    // "ui" section also holds the banners
+    let ui_json = json!({
+        "banners": state.config.snapshot().banners,
+        "default_prompt_suggestions": suggestions
     });
-    let _ = crate::services::ConfigService::update_section(&state.db, "ui", ui_json).await;
+    crate::services::ConfigService::update_section(&state.db, "ui", ui_json).await?;
 
-    Ok(HttpResponse::Ok().json(&config.default_prompt_suggestions))
+    state.config.write().default_prompt_suggestions = suggestions.clone();
+
+    Ok(HttpResponse::Ok().json(suggestions))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -555,7 +769,7 @@ async fn get_tool_servers_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(ToolServersConfigForm {
         tool_server_connections: config.tool_server_connections.clone(),
@@ -574,12 +788,12 @@ async fn set_tool_servers_config(
 
     // Update in-memory config
     {
-        let mut config = state.config.write().unwrap();
+        let mut config = state.config.write();
         config.tool_server_connections = form_data.tool_server_connections.clone();
     }
 
     // Persist to database (best-effort)
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let tool_servers_json = serde_json::json!({
         "connections": config.tool_server_connections
     });
@@ -594,3 +808,345 @@ async fn set_tool_servers_config(
         tool_server_connections: config.tool_server_connections.clone(),
     }))
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SecurityHeadersConfigForm {
+    #[serde(rename = "SECURITY_HEADERS_MODE")]
+    security_headers_mode: String,
+    #[serde(rename = "ENABLE_CSP")]
+    enable_csp: bool,
+    #[serde(rename = "CONTENT_SECURITY_POLICY")]
+    content_security_policy: Option<String>,
+    #[serde(rename = "CSP_CONNECT_SRC")]
+    csp_connect_src: Vec<String>,
+    #[serde(rename = "CSP_IMG_SRC")]
+    csp_img_src: Vec<String>,
+    #[serde(rename = "ENABLE_HSTS")]
+    enable_hsts: bool,
+    #[serde(rename = "HSTS_MAX_AGE")]
+    hsts_max_age: u64,
+    #[serde(rename = "X_FRAME_OPTIONS")]
+    x_frame_options: String,
+    #[serde(rename = "REFERRER_POLICY")]
+    referrer_policy: String,
+    #[serde(rename = "PERMISSIONS_POLICY")]
+    permissions_policy: String,
+}
+
+impl SecurityHeadersConfigForm {
+    fn from_config(config: &crate::config::Config) -> Self {
+        SecurityHeadersConfigForm {
+            security_headers_mode: config.security_headers_mode.clone(),
+            enable_csp: config.enable_csp,
+            content_security_policy: config.content_security_policy.clone(),
+            csp_connect_src: config.csp_connect_src.clone(),
+            csp_img_src: config.csp_img_src.clone(),
+            enable_hsts: config.enable_hsts,
+            hsts_max_age: config.hsts_max_age,
+            x_frame_options: config.x_frame_options.clone(),
+            referrer_policy: config.referrer_policy.clone(),
+            permissions_policy: config.permissions_policy.clone(),
+        }
+    }
+
+    /// Reject values that would be dropped or make an invalid header
+    fn validate(&self) -> Result<(), String> {
+        if !matches!(self.security_headers_mode.as_str(), "relaxed" | "strict") {
+            return Err("SECURITY_HEADERS_MODE must be \"relaxed\" or \"strict\"".to_string());
+        }
+        if !matches!(
+            self.x_frame_options.to_uppercase().as_str(),
+            "" | "DENY" | "SAMEORIGIN"
+        ) {
+            return Err("X_FRAME_OPTIONS must be DENY, SAMEORIGIN or empty".to_string());
+        }
+
+        let header_values = [
+            self.content_security_policy.as_deref().unwrap_or_default(),
+            self.referrer_policy.as_str(),
+            self.permissions_policy.as_str(),
+        ];
+        if header_values
+            .iter()
+            .any(|v| actix_web::http::header::HeaderValue::from_str(v).is_err())
+        {
+            return Err("Security header values must not contain control characters".to_string());
+        }
+
+        let invalid_origin = self
+            .csp_connect_src
+            .iter()
+            .chain(&self.csp_img_src)
+            .find(|o| {
+                o.is_empty() || o.contains(|c: char| c == ';' || c == ',' || c.is_whitespace())
+            });
+        if let Some(origin) = invalid_origin {
+            return Err(format!("Invalid CSP source: {:?}", origin));
+        }
+        Ok(())
+    }
+}
+
+async fn get_security_headers_config(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> Result<HttpResponse, AppError> {
+    if auth_user.user.role != "admin" {
+        return Err(AppError::Forbidden("Admin access required".to_string()));
+    }
+
+    let config = state.config.snapshot();
+    Ok(HttpResponse::Ok().json(SecurityHeadersConfigForm::from_config(&config)))
+}
+
+async fn get_rate_limits_config(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> Result<HttpResponse, AppError> {
+    if auth_user.user.role != "admin" {
+        return Err(AppError::Forbidden("Admin access required".to_string()));
+    }
+
+    let config = state.config.snapshot();
+    let redis_available = state
+        .redis
+        .as_ref()
+        .is_some_and(|redis| redis.health().is_available());
+    let backend = if config.enable_redis && redis_available {
+        "redis"
+    } else {
+        "memory"
+    };
+    Ok(HttpResponse::Ok().json(json!({
+        "backend": backend,
+        "policy": RateLimitPolicy::from_config(&config),
+    })))
+}
+
+async fn set_security_headers_config(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    form_data: web::Json<SecurityHeadersConfigForm>,
+) -> Result<HttpResponse, AppError> {
+    // Only admins can update this
+    if auth_user.user.role != "admin" {
+        return Err(AppError::Forbidden("Admin access required".to_string()));
+    }
+
+    let mut form_data = form_data.into_inner();
+    form_data.security_headers_mode = form_data.security_headers_mode.to_lowercase();
+    form_data.x_frame_options = form_data.x_frame_options.to_uppercase();
+    form_data.validate().map_err(AppError::BadRequest)?;
+
+    // Update in-memory config; the middleware picks it up on the next request
+    let (response, security_headers_json) = {
+        let mut config = state.config.write();
+        config.security_headers_mode = form_data.security_headers_mode;
+        config.enable_csp = form_data.enable_csp;
+        config.content_security_policy = form_data
+            .content_security_policy
+            .filter(|csp| !csp.trim().is_empty());
+        config.csp_connect_src = form_data.csp_connect_src;
+        config.csp_img_src = form_data.csp_img_src;
+        config.enable_hsts = form_data.enable_hsts;
+        config.hsts_max_age = form_data.hsts_max_age;
+        config.x_frame_options = form_data.x_frame_options;
+        config.referrer_policy = form_data.referrer_policy;
+        config.permissions_policy = form_data.permissions_policy;
+        (
+            SecurityHeadersConfigForm::from_config(&config),
+            crate::services::config::security_headers_json(&config),
+        )
+    };
+
+    crate::services::ConfigService::update_section(
+        &state.db,
+        "security_headers",
+        security_headers_json,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Request body limits in MB; uploads share `FILE_MAX_SIZE` with `/api/config`
+#[derive(Debug, Serialize, Deserialize)]
+struct BodyLimitsConfigForm {
+    #[serde(rename = "FILE_MAX_SIZE")]
+    file_max_size: u64,
+    #[serde(rename = "CHAT_BODY_MAX_SIZE")]
+    chat_body_max_size: u64,
+    #[serde(rename = "BODY_MAX_SIZE")]
+    body_max_size: u64,
+}
+
+const MB: u64 = 1024 * 1024;
+
+impl BodyLimitsConfigForm {
+    fn from_config(config: &crate::config::Config) -> Self {
+        BodyLimitsConfigForm {
+            file_max_size: config.file_max_size / MB,
+            chat_body_max_size: config.chat_body_max_size / MB,
+            body_max_size: config.body_max_size / MB,
+        }
+    }
+}
+
+async fn get_body_limits_config(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> Result<HttpResponse, AppError> {
+    if auth_user.user.role != "admin" {
+        return Err(AppError::Forbidden("Admin access required".to_string()));
+    }
+
+    let config = state.config.snapshot();
+    Ok(HttpResponse::Ok().json(BodyLimitsConfigForm::from_config(&config)))
+}
+
+async fn set_body_limits_config(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    form_data: web::Json<BodyLimitsConfigForm>,
+) -> Result<HttpResponse, AppError> {
+    if auth_user.user.role != "admin" {
+        return Err(AppError::Forbidden("Admin access required".to_string()));
+    }
+
+    // A zero limit would refuse every request of the group, including this one
+    if form_data.file_max_size == 0
+        || form_data.chat_body_max_size == 0
+        || form_data.body_max_size == 0
+    {
+        return Err(AppError::BadRequest(
+            "Body limits must be at least 1 MB".to_string(),
+        ));
+    }
+
+    // The BodyLimit middleware reads these on the next request
+    let (response, body_limits_json) = {
+        let mut config = state.config.write();
+        config.file_max_size = form_data.file_max_size.saturating_mul(MB);
+        config.chat_body_max_size = form_data.chat_body_max_size.saturating_mul(MB);
+        config.body_max_size = form_data.body_max_size.saturating_mul(MB);
+        (
+            BodyLimitsConfigForm::from_config(&config),
+            crate::services::config::body_limits_json(&config),
+        )
+    };
+
+    crate::services::ConfigService::update_section(&state.db, "body_limits", body_limits_json)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+fn branding_response(config: &crate::config::Config) -> serde_json::Value {
+    let assets: serde_json::Map<String, serde_json::Value> = BrandingAsset::ALL
+        .into_iter()
+        .map(|asset| {
+            let image = config.custom_branding.get(&asset);
+            (
+                asset.as_str().to_string(),
+                json!({
+                    "url": branding::asset_url(asset, image),
+                    "custom": image,
+                }),
+            )
+        })
+        .collect();
+    json!({
+        "webui_name": config.webui_name,
+        "assets": assets,
+    })
+}
+
+/// Record the branding images in the persistent config and describe them
+async fn save_branding(state: &web::Data<AppState>) -> Result<serde_json::Value, AppError> {
+    let (response, branding_json) = {
+        let config = state.config.snapshot();
+        (
+            branding_response(&config),
+            crate::services::config::branding_json(&config),
+        )
+    };
+    crate::services::ConfigService::update_section(&state.db, "branding", branding_json).await?;
+    Ok(response)
+}
+
+async fn get_branding_config(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> Result<HttpResponse, AppError> {
+    if auth_user.user.role != "admin" {
+        return Err(AppError::Forbidden("Admin access required".to_string()));
+    }
+
+    let config = state.config.snapshot();
+    Ok(HttpResponse::Ok().json(branding_response(&config)))
+}
+
+async fn upload_branding_image(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    path: web::Path<String>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, AppError> {
+    if auth_user.user.role != "admin" {
+        return Err(AppError::Forbidden("Admin access required".to_string()));
+    }
+
+    let asset = BrandingAsset::parse(&path)
+        .ok_or_else(|| AppError::NotFound(format!("Unknown branding asset: {}", path.as_str())))?;
+
+    let mut file_data = Vec::new();
+    while let Some(field) = payload.next().await {
+        let mut field =
+            field.map_err(|e| AppError::BadRequest(format!("Multipart error: {}", e)))?;
+        let field_name = field
+            .content_disposition()
+            .and_then(|cd| cd.get_name())
+            .unwrap_or("");
+        if field_name != "file" {
+            continue;
+        }
+        while let Some(chunk) = field.next().await {
+            let chunk =
+                chunk.map_err(|e| AppError::BadRequest(format!("Chunk read error: {}", e)))?;
+            // Stop reading early; validation reports the size error
+            if file_data.len() > MAX_BRANDING_IMAGE_BYTES {
+                break;
+            }
+            file_data.extend_from_slice(&chunk);
+        }
+    }
+    if file_data.is_empty() {
+        return Err(AppError::BadRequest("No file uploaded".to_string()));
+    }
+
+    let static_dir = state.config.snapshot().static_dir.clone();
+    let image = branding::store_image(&static_dir, asset, &file_data)?;
+    state.config.write().custom_branding.insert(asset, image);
+
+    Ok(HttpResponse::Ok().json(save_branding(&state).await?))
+}
+
+/// Remove all uploaded branding images, going back to the embedded defaults
+async fn reset_branding(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> Result<HttpResponse, AppError> {
+    if auth_user.user.role != "admin" {
+        return Err(AppError::Forbidden("Admin access required".to_string()));
+    }
+
+    {
+        let mut config = state.config.write();
+        for asset in BrandingAsset::ALL {
+            branding::delete_image(&config.static_dir, asset);
+        }
+        config.custom_branding.clear();
+    }
+
+    Ok(HttpResponse::Ok().json(save_branding(&state).await?))
+}
@@ -5,7 +5,7 @@ use crate::{
     error::{AppError, AppResult},
     middleware::{AdminMiddleware, AuthMiddleware, AuthUser},
     models::feedback::{FeedbackForm, FeedbackModel},
-    services::{FeedbackService, UserService},
+    services::{feedback::compute_leaderboard, FeedbackService, UserService},
     AppState,
 };
 
@@ -15,6 +15,7 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AuthMiddleware)
             .route("/config", web::get().to(get_config))
             .route("/config", web::post().to(update_config))
+            .route("/leaderboard", web::get().to(get_leaderboard))
             .service(
                 web::scope("/feedbacks")
                     .service(
@@ -29,6 +30,7 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
                             .route(web::get().to(export_all_feedbacks)),
                     )
                     .route("/user", web::get().to(get_user_feedbacks))
+                    .route("", web::get().to(get_feedbacks))
                     .route("", web::delete().to(delete_user_feedbacks)),
             )
             .route("/feedback", web::post().to(create_feedback))
@@ -52,7 +54,7 @@ async fn get_config(state: web::Data<AppState>, auth_user: AuthUser) -> AppResul
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(EvaluationConfig {
         enable_evaluation_arena_models: config.enable_evaluation_arena_models,
@@ -78,22 +80,67 @@ async fn update_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    // Update in-memory config
+    if let Some(models) = form_data
+        .evaluation_arena_models
+        .as_ref()
+        .filter(|m| !m.is_array())
     {
-        let mut config = state.config.write().unwrap();
+        return Err(AppError::BadRequest(format!(
+            "EVALUATION_ARENA_MODELS must be a list, got {}",
+            models
+        )));
+    }
+
+    // Update in-memory config
+    let response = {
+        let mut config = state.config.write();
         if let Some(enable) = form_data.enable_evaluation_arena_models {
             config.enable_evaluation_arena_models = enable;
         }
         if let Some(ref models) = form_data.evaluation_arena_models {
             config.evaluation_arena_models = models.clone();
         }
+        EvaluationConfig {
+            enable_evaluation_arena_models: config.enable_evaluation_arena_models,
+            evaluation_arena_models: config.evaluation_arena_models.clone(),
+        }
+    };
+
+    // Persist to database
+    crate::services::ConfigService::update_section(
+        &state.db,
+        "evaluation",
+        serde_json::json!({
+            "enable_arena_models": response.enable_evaluation_arena_models,
+            "arena_models": response.evaluation_arena_models,
+        }),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// GET /leaderboard - Elo ratings per model computed from rating feedback
+async fn get_leaderboard(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser,
+) -> AppResult<HttpResponse> {
+    let enabled = state.config.snapshot().enable_evaluation_arena_models;
+    if !enabled {
+        return Err(AppError::Forbidden(
+            "Evaluation arena models are disabled".to_string(),
+        ));
     }
 
-    let config = state.config.read().unwrap();
-    Ok(HttpResponse::Ok().json(EvaluationConfig {
-        enable_evaluation_arena_models: config.enable_evaluation_arena_models,
-        evaluation_arena_models: config.evaluation_arena_models.clone(),
-    }))
+    let feedback_service = FeedbackService::new(&state.db);
+    let feedbacks: Vec<FeedbackModel> = feedback_service
+        .get_all_feedbacks()
+        .await?
+        .into_iter()
+        .map(FeedbackModel::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(compute_leaderboard(&feedbacks)))
 }
 
 #[derive(Debug, Serialize)]
@@ -157,6 +204,21 @@ async fn export_all_feedbacks(
     Ok(HttpResponse::Ok().json(feedback_models))
 }
 
+/// GET /feedbacks - All feedbacks for admins, the user's own feedbacks otherwise
+async fn get_feedbacks(state: web::Data<AppState>, auth_user: AuthUser) -> AppResult<HttpResponse> {
+    let feedback_service = FeedbackService::new(&state.db);
+    let feedbacks = if auth_user.user.role == "admin" {
+        feedback_service.get_all_feedbacks().await?
+    } else {
+        feedback_service
+            .get_feedbacks_by_user_id(&auth_user.user.id)
+            .await?
+    };
+    let feedback_models: Vec<FeedbackModel> =
+        feedbacks.into_iter().map(FeedbackModel::from).collect();
+    Ok(HttpResponse::Ok().json(feedback_models))
+}
+
 /// GET /feedbacks/user - Get current user's feedbacks
 async fn get_user_feedbacks(
     state: web::Data<AppState>,
@@ -1,14 +1,20 @@
 use actix_multipart::Multipart;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use futures_util::StreamExt as _;
 use serde::Deserialize;
 use std::io::Write;
 
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
-use crate::middleware::auth::{AdminMiddleware, AuthUser};
+use crate::middleware::auth::{authenticate, AdminMiddleware, AuthMiddleware, AuthUser};
+use crate::middleware::rate_limit::{RateLimit, RouteGroup};
 use crate::models::file::FileResponse;
 use crate::services::file::FileService;
+use crate::services::file_cleanup::{self, CleanupOptions};
+use crate::utils::image_compression::{compress_blocking, compress_image, ImageBounds};
+use crate::utils::signed_url::{self, SignedQuery};
+use crate::utils::upload;
+use crate::AppState;
 
 #[derive(Debug, Deserialize)]
 pub struct FileContentForm {
@@ -101,13 +107,15 @@ async fn search_files(
 
 // POST / - Upload file
 async fn upload_file(
-    db: web::Data<Database>,
+    state: web::Data<AppState>,
     user: AuthUser,
     mut payload: Multipart,
 ) -> AppResult<HttpResponse> {
-    let service = FileService::new(&db);
+    let service = FileService::new(&state.db);
+    let config = state.config.snapshot();
 
     let mut filename = String::new();
+    let mut declared_type = None;
     let mut file_data = Vec::new();
 
     // Process multipart fields
@@ -125,11 +133,13 @@ async fn upload_file(
                 .and_then(|cd| cd.get_filename())
                 .unwrap_or("unnamed")
                 .to_string();
+            declared_type = field.content_type().map(|mime| mime.to_string());
 
-            // Read file data
+            // Read file data, giving up as soon as it is over the size limit
             while let Some(chunk) = field.next().await {
                 let chunk =
                     chunk.map_err(|e| AppError::BadRequest(format!("Chunk read error: {}", e)))?;
+                upload::check_upload_size(&config, (file_data.len() + chunk.len()) as u64)?;
                 file_data.extend_from_slice(&chunk);
             }
         }
@@ -139,11 +149,30 @@ async fn upload_file(
         return Err(AppError::BadRequest("No file uploaded".to_string()));
     }
 
+    let mut content_type =
+        upload::validate_upload(&config, &filename, declared_type.as_deref(), &file_data)?;
+
+    // Downscale oversized images before they count against the quota
+    if let Some(bounds) = ImageBounds::from_config(&config) {
+        (file_data, content_type) = compress_blocking(move || {
+            let image = compress_image(&file_data, &content_type, bounds)?;
+            Ok(match image {
+                Some(image) => (image.bytes, image.content_type.to_string()),
+                None => (file_data, content_type),
+            })
+        })
+        .await?;
+    }
+    if config.file_user_quota.is_some() {
+        let used = service.get_storage_used_by_user(&user.id).await?;
+        upload::check_user_quota(&config, used, file_data.len() as u64)?;
+    }
+
     // Generate file ID
     let file_id = uuid::Uuid::new_v4().to_string();
 
     // Create upload directory if it doesn't exist
-    let upload_dir = std::path::Path::new("./data/uploads");
+    let upload_dir = std::path::Path::new(&config.upload_dir);
     std::fs::create_dir_all(upload_dir)
         .map_err(|e| AppError::BadRequest(format!("Failed to create upload directory: {}", e)))?;
 
@@ -161,15 +190,30 @@ async fn upload_file(
     let meta = serde_json::json!({
         "source": "upload",
         "size": file_data.len(),
-        "content_type": mime_guess::from_path(&filename).first_or_octet_stream().to_string(),
+        "content_type": content_type,
     });
 
     // Create file record in database
     let file = service
-        .create_file(&file_id, &user.id, &filename, &hash, Some(meta))
+        .create_file(
+            &file_id,
+            &user.id,
+            &filename,
+            &file_path.to_string_lossy(),
+            Some(&hash),
+            Some(meta),
+        )
         .await?;
 
-    Ok(HttpResponse::Ok().json(FileResponse::from(file)))
+    // Link the frontend can load the file from without sending the session
+    let mut response = serde_json::to_value(FileResponse::from(file))
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+    response["url"] = serde_json::json!(signed_url::sign_path(
+        &config,
+        &format!("/api/v1/files/{}/content", file_id)
+    ));
+
+    Ok(HttpResponse::Ok().json(response))
 }
 
 // DELETE /all - Delete all files (admin only)
@@ -186,6 +230,36 @@ async fn delete_all_files(db: web::Data<Database>, _user: AuthUser) -> AppResult
     })))
 }
 
+#[derive(Debug, Deserialize)]
+struct CleanupQuery {
+    dry_run: Option<bool>,
+    /// Skip files uploaded within this many hours (default 24)
+    older_than_hours: Option<i64>,
+}
+
+// POST /cleanup - Purge files nothing refers to, in the background (admin only)
+async fn start_file_cleanup(
+    state: web::Data<AppState>,
+    _user: AuthUser,
+    query: web::Query<CleanupQuery>,
+) -> AppResult<HttpResponse> {
+    let options = CleanupOptions {
+        dry_run: query.dry_run.unwrap_or(false),
+        min_age_secs: query.older_than_hours.unwrap_or(24).max(0) * 3600,
+    };
+    let report = file_cleanup::start_cleanup(state.db.clone(), state.vector_db.clone(), options)?;
+
+    Ok(HttpResponse::Accepted().json(report))
+}
+
+// GET /cleanup - Progress of the running or last cleanup (admin only)
+async fn get_file_cleanup_status(_user: AuthUser) -> AppResult<HttpResponse> {
+    match file_cleanup::cleanup_status() {
+        Some(report) => Ok(HttpResponse::Ok().json(report)),
+        None => Err(AppError::NotFound("No file cleanup has run".to_string())),
+    }
+}
+
 // GET /{id} - Get file by ID
 async fn get_file(
     db: web::Data<Database>,
@@ -351,37 +425,90 @@ async fn update_file_data_content(
     })))
 }
 
-// GET /{id}/content - Get file content (download)
+// GET /{id}/content - Download a file, by signed URL or as its owner
 async fn get_file_content(
-    db: web::Data<Database>,
-    user: AuthUser,
+    state: web::Data<AppState>,
+    req: HttpRequest,
     file_id: web::Path<String>,
+    query: web::Query<SignedQuery>,
 ) -> AppResult<HttpResponse> {
-    let service = FileService::new(&db);
-
-    let file = service.get_file_by_id(&file_id).await?;
+    // A valid signed URL is enough; without one, only the owner or an admin may download.
+    // With signing disabled the query proves nothing, so the session is always checked
+    let config = state.config.snapshot();
+    let user = if config.enable_signed_urls && query.is_present() {
+        signed_url::verify_path(&config, req.path(), &query)?;
+        None
+    } else {
+        let user = authenticate(&state, &req)
+            .await
+            .map_err(|_| AppError::Forbidden("Signed URL required".to_string()))?;
+        Some(user)
+    };
 
-    if file.is_none() {
-        return Ok(HttpResponse::NotFound().json(serde_json::json!({
-            "detail": "File not found"
-        })));
+    let not_found = || AppError::NotFound("File not found".to_string());
+    let mut file = FileService::new(&state.db)
+        .get_file_by_id(&file_id)
+        .await?
+        .ok_or_else(not_found)?;
+    if let Some(user) = user {
+        if file.user_id != user.id && user.role != "admin" {
+            return Err(not_found());
+        }
     }
 
-    let file = file.unwrap();
+    let path = file.path.clone().ok_or_else(not_found)?;
+    let bytes = tokio::fs::read(&path).await.map_err(|_| not_found())?;
 
-    // Check access
-    if file.user_id != user.id && user.role != "admin" {
-        return Ok(HttpResponse::NotFound().json(serde_json::json!({
-            "detail": "File not found"
-        })));
-    }
+    file.parse_json_fields();
+    let content_type = file
+        .meta
+        .as_ref()
+        .and_then(|meta| meta.get("content_type"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+
+    // Files that could run script, such as HTML and SVG, are only downloaded
+    let disposition = if displays_inline(&content_type) {
+        "inline"
+    } else {
+        "attachment"
+    };
 
-    // TODO: Implement file download from storage
-    // This requires storage provider integration and FileResponse streaming
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            actix_web::http::header::CONTENT_DISPOSITION,
+            format!(
+                "{}; filename=\"{}\"",
+                disposition,
+                file.filename.replace(['"', '\\'], "_")
+            ),
+        ))
+        .insert_header((actix_web::http::header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((actix_web::http::header::CONTENT_SECURITY_POLICY, "sandbox"))
+        .body(bytes))
+}
 
-    Ok(HttpResponse::NotImplemented().json(serde_json::json!({
-        "detail": "File download not yet implemented - requires storage integration"
-    })))
+/// Whether browsers can show files of the content type without running their content
+fn displays_inline(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    matches!(
+        mime.as_str(),
+        "image/png"
+            | "image/jpeg"
+            | "image/gif"
+            | "image/webp"
+            | "image/avif"
+            | "image/bmp"
+            | "application/pdf"
+    ) || mime.starts_with("audio/")
+        || mime.starts_with("video/")
 }
 
 // POST /{id}/update - Update file metadata
@@ -454,28 +581,195 @@ async fn delete_file(
 }
 
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::scope("/files")
-            .route("", web::get().to(list_files))
-            .route("/search", web::get().to(search_files))
-            .route("", web::post().to(upload_file))
-            .route("/{id}", web::get().to(get_file))
-            .route(
-                "/{id}/process/status",
-                web::get().to(get_file_process_status),
-            )
-            .route("/{id}/data/content", web::get().to(get_file_data_content))
-            .route(
-                "/{id}/data/content/update",
-                web::post().to(update_file_data_content),
-            )
-            .route("/{id}/content", web::get().to(get_file_content))
-            .route("/{id}/update", web::post().to(update_file))
-            .route("/{id}", web::delete().to(delete_file)),
-    )
-    .service(
-        web::resource("/files/all")
-            .wrap(AdminMiddleware)
-            .route(web::delete().to(delete_all_files)),
-    );
+    // Downloads are authorized in the handler, so signed URLs work without a session
+    cfg.route("/{id}/content", web::get().to(get_file_content))
+        .service(
+            web::resource("/all")
+                .wrap(AdminMiddleware)
+                .route(web::delete().to(delete_all_files)),
+        )
+        .service(
+            web::resource("/cleanup")
+                .wrap(AdminMiddleware)
+                .route(web::post().to(start_file_cleanup))
+                .route(web::get().to(get_file_cleanup_status)),
+        )
+        .service(
+            web::scope("")
+                .wrap(AuthMiddleware)
+                .route("", web::get().to(list_files))
+                .route("/search", web::get().to(search_files))
+                .route(
+                    "",
+                    web::post()
+                        .to(upload_file)
+                        .wrap(RateLimit::new(RouteGroup::Upload)),
+                )
+                .route("/{id}", web::get().to(get_file))
+                .route(
+                    "/{id}/process/status",
+                    web::get().to(get_file_process_status),
+                )
+                .route("/{id}/data/content", web::get().to(get_file_data_content))
+                .route(
+                    "/{id}/data/content/update",
+                    web::post().to(update_file_data_content),
+                )
+                .route("/{id}/update", web::post().to(update_file))
+                .route("/{id}", web::delete().to(delete_file)),
+        );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_utils;
+    use actix_web::{http::header, test, App};
+
+    async fn test_state(dir: &tempfile::TempDir) -> web::Data<AppState> {
+        let mut config = Config::from_env().unwrap();
+        config.upload_dir = dir.path().join("uploads").to_string_lossy().to_string();
+        test_utils::test_state(dir, config).await
+    }
+
+    /// Upload a small text file as the token's user, returning its content URL
+    async fn upload_text<S, B>(app: &S, token: &str) -> String
+    where
+        S: actix_web::dev::Service<
+            actix_http::Request,
+            Response = actix_web::dev::ServiceResponse<B>,
+            Error = actix_web::Error,
+        >,
+        B: actix_web::body::MessageBody,
+    {
+        let body = "--boundary\r\n\
+                    Content-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\n\
+                    Content-Type: text/plain\r\n\r\n\
+                    private notes\r\n\
+                    --boundary--\r\n";
+        let req = test::TestRequest::post()
+            .uri("/api/v1/files")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=boundary",
+            ))
+            .set_payload(body)
+            .to_request();
+        let uploaded: serde_json::Value = test::call_and_read_body_json(app, req).await;
+        uploaded["url"].as_str().unwrap().to_string()
+    }
+
+    #[actix_web::test]
+    async fn test_uploaded_html_is_not_served_inline() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir).await;
+        test_utils::create_user(&state.db, "user-1", "user").await;
+        let token = test_utils::user_token(&state, "user-1");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(web::scope("/api/v1/files").configure(create_routes)),
+        )
+        .await;
+
+        let body = "--boundary\r\n\
+                    Content-Disposition: form-data; name=\"file\"; filename=\"page.html\"\r\n\
+                    Content-Type: text/html\r\n\r\n\
+                    <html><script>alert(document.cookie)</script></html>\r\n\
+                    --boundary--\r\n";
+        let req = test::TestRequest::post()
+            .uri("/api/v1/files")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=boundary",
+            ))
+            .set_payload(body)
+            .to_request();
+        let uploaded: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let url = uploaded["url"].as_str().unwrap();
+
+        let req = test::TestRequest::get()
+            .uri(url)
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert!(response.status().is_success());
+        let headers = response.headers();
+        assert!(headers
+            .get(header::CONTENT_DISPOSITION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("attachment;"));
+        assert_eq!(
+            headers.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(),
+            "nosniff"
+        );
+        assert_eq!(
+            headers.get(header::CONTENT_SECURITY_POLICY).unwrap(),
+            "sandbox"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_signature_query_is_ignored_when_signing_is_disabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::from_env().unwrap();
+        config.upload_dir = dir.path().join("uploads").to_string_lossy().to_string();
+        config.enable_signed_urls = false;
+        let state = test_utils::test_state(&dir, config).await;
+        test_utils::create_user(&state.db, "owner", "user").await;
+        test_utils::create_user(&state.db, "other", "user").await;
+        let owner_token = test_utils::user_token(&state, "owner");
+        let other_token = test_utils::user_token(&state, "other");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(web::scope("/api/v1/files").configure(create_routes)),
+        )
+        .await;
+        let url = upload_text(&app, &owner_token).await;
+        let spoofed = format!("{}?expires=1", url);
+
+        // No session: the bare query doesn't stand in for a signature
+        let req = test::TestRequest::get().uri(&spoofed).to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), 403);
+
+        // Another user still fails the owner check
+        let req = test::TestRequest::get()
+            .uri(&spoofed)
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", other_token)))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), 404);
+
+        let req = test::TestRequest::get()
+            .uri(&spoofed)
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", owner_token)))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert!(response.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_only_passive_content_displays_inline() {
+        for content_type in ["image/png", "IMAGE/JPEG", "application/pdf", "video/mp4"] {
+            assert!(displays_inline(content_type), "{}", content_type);
+        }
+        for content_type in [
+            "text/html; charset=utf-8",
+            "image/svg+xml",
+            "application/xhtml+xml",
+            "text/plain",
+            "application/octet-stream",
+        ] {
+            assert!(!displays_inline(content_type), "{}", content_type);
+        }
+    }
 }
@@ -8,7 +8,7 @@ use crate::models::folder::{
 };
 use crate::services::chat::ChatService;
 use crate::services::folder::FolderService;
-use crate::utils::misc::has_permission;
+use crate::utils::permissions::user_has_permission;
 use crate::AppState;
 
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
@@ -291,9 +291,10 @@ async fn delete_folder_by_id(
         .await?;
 
     if chat_count > 0 {
-        let config = state.config.read().unwrap();
+        let user_permissions = state.config.snapshot().user_permissions.clone();
         let has_delete_permission = auth_user.role == "admin"
-            || has_permission(&auth_user.id, "chat.delete", &config.user_permissions);
+            || user_has_permission(&state.db, &user_permissions, &auth_user.id, "chat.delete")
+                .await?;
 
         if !has_delete_permission {
             return Err(AppError::Forbidden("Access prohibited".to_string()));
@@ -192,7 +192,7 @@ async fn export_functions(
 }
 
 async fn load_function_from_url(
-    _state: web::Data<AppState>,
+    state: web::Data<AppState>,
     auth_user: AuthUser,
     form: web::Json<LoadUrlForm>,
 ) -> AppResult<HttpResponse> {
@@ -223,13 +223,10 @@ async fn load_function_from_url(
     };
 
     // Fetch content from URL
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()
-        .map_err(|e| AppError::BadRequest(format!("Failed to create HTTP client: {}", e)))?;
-
-    let response = client
+    let response = state
+        .http_client
         .get(&url)
+        .timeout(std::time::Duration::from_secs(30))
         .send()
         .await
         .map_err(|e| AppError::BadRequest(format!("Failed to fetch URL: {}", e)))?;
@@ -364,8 +361,7 @@ async fn create_new_function(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    form.validate()
-        .map_err(|e| AppError::Validation(e.to_string()))?;
+    form.validate()?;
 
     // Validate ID contains only alphanumeric and underscores
     if !form.id.chars().all(|c| c.is_alphanumeric() || c == '_') {
@@ -1,8 +1,10 @@
 use actix_web::{web, HttpResponse};
 
-use crate::error::AppResult;
+use crate::error::{AppError, AppResult};
 use crate::middleware::{AdminMiddleware, AuthMiddleware, AuthUser};
-use crate::models::group::{GroupForm, GroupResponse, GroupUpdateForm, UserIdsForm};
+use crate::models::group::{
+    GroupEmailsResponse, GroupForm, GroupResponse, GroupUpdateForm, UserEmailsForm, UserIdsForm,
+};
 use crate::services::group::GroupService;
 use crate::services::user::UserService;
 use crate::AppState;
@@ -38,6 +40,11 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AdminMiddleware)
             .route(web::post().to(add_users_to_group)),
     )
+    .service(
+        web::resource("/id/{id}/users/add/emails")
+            .wrap(AdminMiddleware)
+            .route(web::post().to(add_users_to_group_by_email)),
+    )
     .service(
         web::resource("/id/{id}/users/remove")
             .wrap(AdminMiddleware)
@@ -69,6 +76,8 @@ async fn create_new_group(
     auth_user: AuthUser,
     payload: web::Json<GroupForm>,
 ) -> AppResult<HttpResponse> {
+    validate_permissions(payload.permissions.as_ref())?;
+
     let group_service = GroupService::new(&state.db);
 
     let group = group_service
@@ -88,7 +97,7 @@ async fn get_group_by_id(
     let group = group_service
         .get_group_by_id(&id)
         .await?
-        .ok_or_else(|| crate::error::AppError::NotFound("Group not found".to_string()))?;
+        .ok_or_else(|| AppError::NotFound("Group not found".to_string()))?;
 
     Ok(HttpResponse::Ok().json(GroupResponse::from(group)))
 }
@@ -99,6 +108,8 @@ async fn update_group_by_id(
     id: web::Path<String>,
     payload: web::Json<GroupUpdateForm>,
 ) -> AppResult<HttpResponse> {
+    validate_permissions(payload.permissions.as_ref())?;
+
     let group_service = GroupService::new(&state.db);
     let user_service = UserService::new(&state.db);
 
@@ -133,6 +144,32 @@ async fn add_users_to_group(
     Ok(HttpResponse::Ok().json(GroupResponse::from(group)))
 }
 
+async fn add_users_to_group_by_email(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser,
+    id: web::Path<String>,
+    payload: web::Json<UserEmailsForm>,
+) -> AppResult<HttpResponse> {
+    let group_service = GroupService::new(&state.db);
+    let user_service = UserService::new(&state.db);
+
+    let mut user_ids = Vec::new();
+    let mut not_found = Vec::new();
+    for email in payload.emails() {
+        match user_service.get_user_by_email(&email).await? {
+            Some(user) => user_ids.push(user.id),
+            None => not_found.push(email),
+        }
+    }
+
+    let group = group_service.add_users_to_group(&id, &user_ids).await?;
+
+    Ok(HttpResponse::Ok().json(GroupEmailsResponse {
+        group: GroupResponse::from(group),
+        not_found,
+    }))
+}
+
 async fn remove_users_from_group(
     state: web::Data<AppState>,
     _auth_user: AuthUser,
@@ -160,3 +197,13 @@ async fn delete_group_by_id(
 
     Ok(HttpResponse::Ok().json(result))
 }
+
+/// Group permissions are merged into user permissions, so they must be a JSON object
+fn validate_permissions(permissions: Option<&serde_json::Value>) -> AppResult<()> {
+    match permissions {
+        Some(permissions) if !permissions.is_object() => Err(AppError::BadRequest(
+            "Group permissions must be a JSON object".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
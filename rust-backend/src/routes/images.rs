@@ -93,7 +93,7 @@ async fn generate_image(
     _auth_user: AuthUser,
     _form_data: web::Json<GenerateImageForm>,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_image_generation {
         return Err(AppError::Forbidden(
@@ -117,7 +117,7 @@ async fn get_models(
     state: web::Data<AppState>,
     _auth_user: AuthUser,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_image_generation {
         return Err(AppError::Forbidden(
@@ -151,7 +151,7 @@ async fn get_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(ImagesConfigResponse {
         enabled: config.enable_image_generation,
@@ -191,7 +191,7 @@ async fn get_image_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let _config = state.config.read().unwrap();
+    let _config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "MODEL": "dall-e-2",
@@ -247,7 +247,7 @@ async fn update_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let mut config = state.config.write().unwrap();
+    let mut config = state.config.write();
 
     config.image_generation_engine = form_data.engine.clone();
     config.enable_image_generation = form_data.enabled;
@@ -13,7 +13,8 @@ use crate::services::file::FileService;
 use crate::services::group::GroupService;
 use crate::services::knowledge::KnowledgeService;
 use crate::services::user::UserService;
-use crate::utils::misc::{has_access, has_permission};
+use crate::utils::misc::has_access;
+use crate::utils::permissions::user_has_permission;
 use crate::AppState;
 
 #[derive(Debug, Deserialize)]
@@ -108,9 +109,8 @@ async fn get_knowledge_bases(
     let user_service = UserService::new(&state.db);
     let file_service = FileService::new(&state.db);
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let bypass_admin_access = config.bypass_admin_access_control.unwrap_or(false);
-    drop(config);
 
     let knowledge_bases = if auth_user.user.role == "admin" && bypass_admin_access {
         knowledge_service.get_all_knowledge().await?
@@ -233,9 +233,8 @@ async fn get_knowledge_list(
     let user_service = UserService::new(&state.db);
     let file_service = FileService::new(&state.db);
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let bypass_admin_access = config.bypass_admin_access_control.unwrap_or(false);
-    drop(config);
 
     let knowledge_bases = if auth_user.user.role == "admin" && bypass_admin_access {
         knowledge_service.get_all_knowledge().await?
@@ -357,11 +356,17 @@ async fn create_knowledge(
 ) -> AppResult<HttpResponse> {
     // Check workspace.knowledge permission
     if auth_user.user.role != "admin" {
-        let config = state.config.read().unwrap();
+        let config = state.config.snapshot();
         let user_permissions = config.user_permissions.clone();
-        drop(config);
 
-        if !has_permission(&auth_user.user.id, "workspace.knowledge", &user_permissions) {
+        if !user_has_permission(
+            &state.db,
+            &user_permissions,
+            &auth_user.user.id,
+            "workspace.knowledge",
+        )
+        .await?
+        {
             return Err(AppError::Unauthorized("Unauthorized".to_string()));
         }
     }
@@ -369,15 +374,17 @@ async fn create_knowledge(
     // Check if user can share publicly
     let mut access_control = form.access_control.clone();
     if auth_user.user.role != "admin" && access_control.is_none() {
-        let config = state.config.read().unwrap();
+        let config = state.config.snapshot();
         let user_permissions = config.user_permissions.clone();
-        drop(config);
 
-        if !has_permission(
+        if !user_has_permission(
+            &state.db,
+            &user_permissions,
             &auth_user.user.id,
             "sharing.public_knowledge",
-            &user_permissions,
-        ) {
+        )
+        .await?
+        {
             access_control = Some(json!({}));
         }
     }
@@ -499,15 +506,17 @@ async fn update_knowledge(
     // Check if user can share publicly
     let mut access_control = form.access_control.clone();
     if auth_user.user.role != "admin" && access_control.is_none() {
-        let config = state.config.read().unwrap();
+        let config = state.config.snapshot();
         let user_permissions = config.user_permissions.clone();
-        drop(config);
 
-        if !has_permission(
+        if !user_has_permission(
+            &state.db,
+            &user_permissions,
             &auth_user.user.id,
             "sharing.public_knowledge",
-            &user_permissions,
-        ) {
+        )
+        .await?
+        {
             access_control = Some(json!({}));
         }
     }
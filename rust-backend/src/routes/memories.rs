@@ -2,11 +2,12 @@ use actix_web::{web, HttpResponse};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::db::Database;
-use crate::error::AppResult;
-use crate::middleware::auth::AuthUser;
-use crate::models::memory::MemoryResponse;
+use crate::error::{AppError, AppResult};
+use crate::middleware::{AuthMiddleware, AuthUser};
+use crate::models::memory::{Memory, MemoryResponse};
+use crate::routes::{knowledge_vector, memory_vector};
 use crate::services::memory::MemoryService;
+use crate::AppState;
 
 #[derive(Debug, Deserialize)]
 pub struct AddMemoryForm {
@@ -35,17 +36,49 @@ pub struct QueryMemoryResult {
 }
 
 // GET /ef - Get embeddings (testing endpoint)
-async fn get_embeddings(_user: AuthUser) -> AppResult<HttpResponse> {
-    // TODO: Implement embedding function integration
-    // For now, return a placeholder
+async fn get_embeddings(state: web::Data<AppState>, _user: AuthUser) -> AppResult<HttpResponse> {
+    let embedding_provider = match &state.embedding_provider {
+        Some(provider) => provider,
+        None => {
+            return Err(AppError::BadRequest(
+                "Embedding provider is not configured".to_string(),
+            ))
+        }
+    };
+
+    let embeddings = embedding_provider
+        .embed(vec!["hello world".to_string()])
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to generate embeddings: {}", e)))?;
+
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "result": "Embedding function not yet implemented"
+        "result": embeddings.into_iter().next().unwrap_or_default()
     })))
 }
 
+/// Index memories for the user, logging instead of failing when embedding is unavailable;
+/// the row is already saved and `/reset` re-indexes everything
+async fn index_memories(state: &AppState, user_id: &str, memories: &[Memory]) {
+    match knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider) {
+        Some((vector_db, embedding_provider)) => {
+            if let Err(e) = memory_vector::upsert_memory_vectors(
+                &vector_db,
+                &embedding_provider,
+                user_id,
+                memories,
+            )
+            .await
+            {
+                tracing::error!("Failed to index memories for user {}: {}", user_id, e);
+            }
+        }
+        None => knowledge_vector::log_rag_disabled("index memory"),
+    }
+}
+
 // GET / - Get memories by user
-async fn get_memories(db: web::Data<Database>, user: AuthUser) -> AppResult<HttpResponse> {
-    let service = MemoryService::new(&db);
+async fn get_memories(state: web::Data<AppState>, user: AuthUser) -> AppResult<HttpResponse> {
+    let service = MemoryService::new(&state.db);
     let memories = service.get_memories_by_user_id(&user.id).await?;
 
     let responses: Vec<MemoryResponse> = memories.into_iter().map(|m| m.into()).collect();
@@ -54,27 +87,18 @@ async fn get_memories(db: web::Data<Database>, user: AuthUser) -> AppResult<Http
 
 // POST /add - Add memory with vector upsert
 async fn add_memory(
-    db: web::Data<Database>,
+    state: web::Data<AppState>,
     user: AuthUser,
     form: web::Json<AddMemoryForm>,
 ) -> AppResult<HttpResponse> {
-    let service = MemoryService::new(&db);
+    let service = MemoryService::new(&state.db);
 
     let memory_id = Uuid::new_v4().to_string();
     let memory = service
         .create_memory(&memory_id, &user.id, &form.content, None)
         .await?;
 
-    // TODO: Implement vector DB upsert
-    // VECTOR_DB_CLIENT.upsert(
-    //     collection_name=f"user-memory-{user.id}",
-    //     items=[{
-    //         "id": memory.id,
-    //         "text": memory.content,
-    //         "vector": EMBEDDING_FUNCTION(memory.content),
-    //         "metadata": {"created_at": memory.created_at},
-    //     }],
-    // )
+    index_memories(&state, &user.id, std::slice::from_ref(&memory)).await;
 
     let response: MemoryResponse = memory.into();
     Ok(HttpResponse::Ok().json(response))
@@ -82,11 +106,11 @@ async fn add_memory(
 
 // POST /query - Query memories with vector search
 async fn query_memory(
-    db: web::Data<Database>,
+    state: web::Data<AppState>,
     user: AuthUser,
     form: web::Json<QueryMemoryForm>,
 ) -> AppResult<HttpResponse> {
-    let service = MemoryService::new(&db);
+    let service = MemoryService::new(&state.db);
 
     // First check if user has any memories
     let memories = service.get_memories_by_user_id(&user.id).await?;
@@ -96,11 +120,28 @@ async fn query_memory(
         })));
     }
 
-    // TODO: Implement vector DB search
-    // For now, do a simple text search
-    let results = service
-        .query_memories(&user.id, &form.content, form.k)
-        .await?;
+    let k = form.k.max(1);
+    let results =
+        match knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider) {
+            Some((vector_db, embedding_provider)) => {
+                let matches = memory_vector::search_memories(
+                    &vector_db,
+                    &embedding_provider,
+                    &user.id,
+                    &form.content,
+                    k as usize,
+                )
+                .await?;
+
+                // Keep the relevance order from the vector search
+                matches
+                    .iter()
+                    .filter_map(|m| memories.iter().find(|memory| memory.id == m.id).cloned())
+                    .collect::<Vec<Memory>>()
+            }
+            // Without a vector DB fall back to a plain text search
+            None => service.query_memories(&user.id, &form.content, k).await?,
+        };
 
     let responses: Vec<MemoryResponse> = results.into_iter().map(|m| m.into()).collect();
     Ok(HttpResponse::Ok().json(serde_json::json!({
@@ -109,46 +150,51 @@ async fn query_memory(
 }
 
 // POST /reset - Reset memory from vector DB
-async fn reset_memory(db: web::Data<Database>, user: AuthUser) -> AppResult<HttpResponse> {
-    let service = MemoryService::new(&db);
+async fn reset_memory(state: web::Data<AppState>, user: AuthUser) -> AppResult<HttpResponse> {
+    let service = MemoryService::new(&state.db);
 
-    // TODO: Implement vector DB operations
-    // VECTOR_DB_CLIENT.delete_collection(f"user-memory-{user.id}")
+    let (vector_db, embedding_provider) =
+        match knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider) {
+            Some(components) => components,
+            None => {
+                knowledge_vector::log_rag_disabled("reset memory");
+                return Ok(HttpResponse::Ok().json(true));
+            }
+        };
 
-    let _memories = service.get_memories_by_user_id(&user.id).await?;
+    memory_vector::delete_memory_collection(&vector_db, &user.id).await?;
 
-    // TODO: Re-index all memories in vector DB
-    // VECTOR_DB_CLIENT.upsert(
-    //     collection_name=f"user-memory-{user.id}",
-    //     items=[...],
-    // )
+    let memories = service.get_memories_by_user_id(&user.id).await?;
+    memory_vector::upsert_memory_vectors(&vector_db, &embedding_provider, &user.id, &memories)
+        .await?;
 
     Ok(HttpResponse::Ok().json(true))
 }
 
 // DELETE /delete/user - Delete all memories by user ID
 async fn delete_memories_by_user(
-    db: web::Data<Database>,
+    state: web::Data<AppState>,
     user: AuthUser,
 ) -> AppResult<HttpResponse> {
-    let service = MemoryService::new(&db);
+    let service = MemoryService::new(&state.db);
 
     service.delete_memories_by_user_id(&user.id).await?;
 
-    // TODO: Delete vector collection
-    // VECTOR_DB_CLIENT.delete_collection(f"user-memory-{user.id}")
+    if let Some(vector_db) = &state.vector_db {
+        memory_vector::delete_memory_collection(vector_db, &user.id).await?;
+    }
 
     Ok(HttpResponse::Ok().json(true))
 }
 
 // POST /{memory_id}/update - Update memory by ID
 async fn update_memory(
-    db: web::Data<Database>,
+    state: web::Data<AppState>,
     user: AuthUser,
     memory_id: web::Path<String>,
     form: web::Json<UpdateMemoryForm>,
 ) -> AppResult<HttpResponse> {
-    let service = MemoryService::new(&db);
+    let service = MemoryService::new(&state.db);
 
     // First verify the memory exists and belongs to the user
     let existing = service.get_memory_by_id(&memory_id).await?;
@@ -169,10 +215,9 @@ async fn update_memory(
         .update_memory(&memory_id, form.content.as_deref(), None)
         .await?;
 
-    // TODO: Update vector DB
-    // if form.content.is_some() {
-    //     VECTOR_DB_CLIENT.upsert(...)
-    // }
+    if form.content.is_some() {
+        index_memories(&state, &user.id, std::slice::from_ref(&memory)).await;
+    }
 
     let response: MemoryResponse = memory.into();
     Ok(HttpResponse::Ok().json(response))
@@ -180,11 +225,11 @@ async fn update_memory(
 
 // DELETE /{memory_id} - Delete memory by ID
 async fn delete_memory(
-    db: web::Data<Database>,
+    state: web::Data<AppState>,
     user: AuthUser,
     memory_id: web::Path<String>,
 ) -> AppResult<HttpResponse> {
-    let service = MemoryService::new(&db);
+    let service = MemoryService::new(&state.db);
 
     // First verify the memory exists and belongs to the user
     let existing = service.get_memory_by_id(&memory_id).await?;
@@ -199,25 +244,57 @@ async fn delete_memory(
 
     service.delete_memory(&memory_id).await?;
 
-    // TODO: Delete from vector DB
-    // VECTOR_DB_CLIENT.delete(
-    //     collection_name=f"user-memory-{user.id}",
-    //     ids=[memory_id]
-    // )
+    if let Some(vector_db) = &state.vector_db {
+        memory_vector::delete_memory_vector(vector_db, &user.id, &memory_id).await?;
+    }
 
     Ok(HttpResponse::Ok().json(true))
 }
 
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
-        web::scope("/memories")
-            .route("/ef", web::get().to(get_embeddings))
-            .route("", web::get().to(get_memories))
-            .route("/add", web::post().to(add_memory))
-            .route("/query", web::post().to(query_memory))
-            .route("/reset", web::post().to(reset_memory))
-            .route("/delete/user", web::delete().to(delete_memories_by_user))
-            .route("/{memory_id}/update", web::post().to(update_memory))
-            .route("/{memory_id}", web::delete().to(delete_memory)),
+        web::resource("")
+            .wrap(AuthMiddleware)
+            .route(web::get().to(get_memories)),
+    )
+    .service(
+        web::resource("/")
+            .wrap(AuthMiddleware)
+            .route(web::get().to(get_memories)),
+    )
+    .service(
+        web::resource("/ef")
+            .wrap(AuthMiddleware)
+            .route(web::get().to(get_embeddings)),
+    )
+    .service(
+        web::resource("/add")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(add_memory)),
+    )
+    .service(
+        web::resource("/query")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(query_memory)),
+    )
+    .service(
+        web::resource("/reset")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(reset_memory)),
+    )
+    .service(
+        web::resource("/delete/user")
+            .wrap(AuthMiddleware)
+            .route(web::delete().to(delete_memories_by_user)),
+    )
+    .service(
+        web::resource("/{memory_id}/update")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(update_memory)),
+    )
+    .service(
+        web::resource("/{memory_id}")
+            .wrap(AuthMiddleware)
+            .route(web::delete().to(delete_memory)),
     );
 }
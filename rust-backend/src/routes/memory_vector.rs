@@ -0,0 +1,303 @@
+/// Helper functions for vector database operations on user memories
+use crate::error::{AppError, AppResult};
+use crate::models::memory::Memory;
+use crate::retrieval::vector::types::VectorItem;
+use crate::retrieval::{EmbeddingProvider, VectorDB};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::{debug, info};
+
+/// Number of memories injected into a chat when `features.memory` is enabled
+pub const MEMORY_CONTEXT_TOP_K: usize = 3;
+
+/// A memory returned by a vector search
+#[derive(Debug, Clone)]
+pub struct MemoryMatch {
+    pub id: String,
+    pub content: String,
+    pub created_at: Option<i64>,
+    pub distance: Option<f32>,
+}
+
+/// Name of the vector collection holding a user's memories
+pub fn memory_collection_name(user_id: &str) -> String {
+    format!("user-memory-{}", user_id)
+}
+
+/// Embed memories and upsert them into the user's memory collection
+pub async fn upsert_memory_vectors(
+    vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
+    user_id: &str,
+    memories: &[Memory],
+) -> AppResult<()> {
+    if memories.is_empty() {
+        return Ok(());
+    }
+
+    let texts: Vec<String> = memories.iter().map(|m| m.content.clone()).collect();
+    let embeddings = embedding_provider
+        .embed(texts)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to generate embeddings: {}", e)))?;
+
+    let items: Vec<VectorItem> = memories
+        .iter()
+        .zip(embeddings)
+        .map(|(memory, embedding)| VectorItem {
+            id: memory.id.clone(),
+            text: memory.content.clone(),
+            vector: embedding,
+            metadata: json!({
+                "created_at": memory.created_at,
+                "updated_at": memory.updated_at,
+            }),
+        })
+        .collect();
+
+    let collection_name = memory_collection_name(user_id);
+    vector_db
+        .upsert(&collection_name, items)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to index memory: {}", e)))?;
+
+    debug!(
+        "Indexed {} memory item(s) into {}",
+        memories.len(),
+        collection_name
+    );
+
+    Ok(())
+}
+
+/// Delete a single memory's vector from the user's memory collection
+pub async fn delete_memory_vector(
+    vector_db: &Arc<dyn VectorDB>,
+    user_id: &str,
+    memory_id: &str,
+) -> AppResult<()> {
+    let collection_name = memory_collection_name(user_id);
+
+    let has_collection = vector_db
+        .has_collection(&collection_name)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to check collection: {}", e)))?;
+
+    if !has_collection {
+        return Ok(());
+    }
+
+    vector_db
+        .delete(&collection_name, Some(vec![memory_id.to_string()]), None)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to delete memory vector: {}", e)))?;
+
+    Ok(())
+}
+
+/// Drop the user's whole memory collection
+pub async fn delete_memory_collection(
+    vector_db: &Arc<dyn VectorDB>,
+    user_id: &str,
+) -> AppResult<()> {
+    let collection_name = memory_collection_name(user_id);
+
+    let has_collection = vector_db
+        .has_collection(&collection_name)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to check collection: {}", e)))?;
+
+    if !has_collection {
+        debug!(
+            "Collection {} does not exist, nothing to delete",
+            collection_name
+        );
+        return Ok(());
+    }
+
+    vector_db
+        .delete_collection(&collection_name)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to delete collection: {}", e)))?;
+
+    info!("Deleted memory collection {}", collection_name);
+
+    Ok(())
+}
+
+/// Find the `k` memories most relevant to `query`
+pub async fn search_memories(
+    vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
+    user_id: &str,
+    query: &str,
+    k: usize,
+) -> AppResult<Vec<MemoryMatch>> {
+    let collection_name = memory_collection_name(user_id);
+
+    let has_collection = vector_db
+        .has_collection(&collection_name)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to check collection: {}", e)))?;
+
+    if !has_collection || k == 0 {
+        return Ok(Vec::new());
+    }
+
+    let query_embedding = embedding_provider
+        .embed(vec![query.to_string()])
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to generate embeddings: {}", e)))?;
+
+    let result = vector_db
+        .search(&collection_name, query_embedding, k)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to search memories: {}", e)))?;
+
+    // Results are batched per query vector; we only sent one
+    let ids = result
+        .ids
+        .and_then(|batches| batches.into_iter().next())
+        .unwrap_or_default();
+    let documents = result
+        .documents
+        .and_then(|batches| batches.into_iter().next())
+        .unwrap_or_default();
+    let metadatas = result
+        .metadatas
+        .and_then(|batches| batches.into_iter().next())
+        .unwrap_or_default();
+    let distances = result
+        .distances
+        .and_then(|batches| batches.into_iter().next())
+        .unwrap_or_default();
+
+    let matches = ids
+        .into_iter()
+        .enumerate()
+        .filter_map(|(idx, id)| {
+            let content = documents.get(idx)?.clone();
+            Some(MemoryMatch {
+                id,
+                content,
+                created_at: metadatas
+                    .get(idx)
+                    .and_then(|m| m.get("created_at"))
+                    .and_then(|v| v.as_i64()),
+                distance: distances.get(idx).copied(),
+            })
+        })
+        .collect();
+
+    Ok(matches)
+}
+
+/// Format matched memories as the "User Context" block added to the system message
+pub fn format_memory_context(matches: &[MemoryMatch]) -> String {
+    let lines: Vec<String> = matches
+        .iter()
+        .enumerate()
+        .map(|(idx, memory)| {
+            let date = memory
+                .created_at
+                .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+                .map(|dt| dt.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| "Unknown Date".to_string());
+            format!("{}. [{}] {}", idx + 1, date, memory.content)
+        })
+        .collect();
+
+    format!("User Context:\n{}", lines.join("\n"))
+}
+
+/// Append the memory context to the system message, adding one if the chat has none
+pub fn add_memory_context_to_messages(messages: &mut Vec<Value>, context: &str) {
+    let system_message = messages
+        .iter_mut()
+        .find(|m| m.get("role").and_then(|r| r.as_str()) == Some("system"));
+
+    match system_message {
+        Some(message) => {
+            let existing = message
+                .get("content")
+                .and_then(|c| c.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let content = if existing.is_empty() {
+                context.to_string()
+            } else {
+                format!("{}\n\n{}", existing, context)
+            };
+            message["content"] = json!(content);
+        }
+        None => {
+            messages.insert(0, json!({"role": "system", "content": context}));
+        }
+    }
+}
+
+/// Citation entry so the UI can show which memories were used
+pub fn memory_citation_source(matches: &[MemoryMatch]) -> Value {
+    json!({
+        "source": {"id": "memory", "name": "Memories", "type": "memory"},
+        "document": matches.iter().map(|m| m.content.clone()).collect::<Vec<_>>(),
+        "metadata": matches
+            .iter()
+            .map(|m| json!({
+                "source": "memory",
+                "memory_id": m.id,
+                "created_at": m.created_at,
+                "distance": m.distance,
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_match(content: &str, created_at: Option<i64>) -> MemoryMatch {
+        MemoryMatch {
+            id: content.to_string(),
+            content: content.to_string(),
+            created_at,
+            distance: None,
+        }
+    }
+
+    #[test]
+    fn test_format_memory_context() {
+        let context = format_memory_context(&[
+            memory_match("Prefers metric units", Some(1_700_000_000)),
+            memory_match("Lives in Berlin", None),
+        ]);
+        assert_eq!(
+            context,
+            "User Context:\n1. [2023-11-14] Prefers metric units\n2. [Unknown Date] Lives in Berlin"
+        );
+    }
+
+    #[test]
+    fn test_add_memory_context_appends_to_system_message() {
+        let mut messages = vec![
+            json!({"role": "system", "content": "Be brief."}),
+            json!({"role": "user", "content": "Hi"}),
+        ];
+        add_memory_context_to_messages(&mut messages, "User Context:\n1. [x] y");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0]["content"],
+            "Be brief.\n\nUser Context:\n1. [x] y"
+        );
+    }
+
+    #[test]
+    fn test_add_memory_context_inserts_system_message() {
+        let mut messages = vec![json!({"role": "user", "content": "Hi"})];
+        add_memory_context_to_messages(&mut messages, "User Context:");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[0]["content"], "User Context:");
+    }
+}
@@ -0,0 +1,48 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+
+use crate::error::{AppError, AppResult};
+use crate::middleware::auth::{AdminMiddleware, AuthUser};
+use crate::AppState;
+
+pub fn create_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("")
+            .wrap(AdminMiddleware)
+            .route("/migrations", web::get().to(get_migrations))
+            .route("/migrations/run", web::post().to(run_migrations)),
+    );
+}
+
+// GET /migrations - Applied and pending schema migrations
+async fn get_migrations(state: web::Data<AppState>, _user: AuthUser) -> AppResult<HttpResponse> {
+    let status = state
+        .db
+        .migration_status()
+        .await
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+// POST /migrations/run - Apply pending migrations without a restart
+async fn run_migrations(state: web::Data<AppState>, user: AuthUser) -> AppResult<HttpResponse> {
+    let applied = state
+        .db
+        .try_run_migrations()
+        .await
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?
+        .ok_or_else(|| AppError::Conflict("Migrations are already running".to_string()))?;
+
+    if !applied.is_empty() {
+        tracing::info!("User {} applied migrations {:?}", user.user.id, applied);
+    }
+    let status = state
+        .db
+        .migration_status()
+        .await
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(json!({
+        "applied": applied,
+        "status": status,
+    })))
+}
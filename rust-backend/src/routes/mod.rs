@@ -14,6 +14,8 @@ pub mod images;
 pub mod knowledge;
 pub mod knowledge_vector; // Vector DB operations for knowledge
 pub mod memories;
+pub mod memory_vector; // Vector DB operations for memories
+pub mod migrations;
 pub mod models;
 pub mod notes;
 pub mod openai;
@@ -23,6 +25,7 @@ pub mod retrieval;
 pub mod scim;
 pub mod tasks;
 pub mod tools;
+pub mod usage;
 pub mod users;
 pub mod utils;
 
@@ -35,6 +38,7 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
         .service(web::scope("/channels").configure(channels::create_routes))
         .service(web::scope("/chats").configure(chats::create_routes))
         .service(web::scope("/configs").configure(configs::create_routes))
+        .service(web::scope("/db").configure(migrations::create_routes))
         .service(web::scope("/evaluations").configure(evaluations::create_routes))
         .service(web::scope("/files").configure(files::create_routes))
         .service(web::scope("/folders").configure(folders::create_routes))
@@ -52,6 +56,7 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
         .service(web::scope("/scim/v2").configure(scim::create_routes))
         .service(web::scope("/tasks").configure(tasks::create_routes))
         .service(web::scope("/tools").configure(tools::create_routes))
+        .service(web::scope("/usage").configure(usage::create_routes))
         .service(web::scope("/users").configure(users::create_routes))
         .service(web::scope("/utils").configure(utils::create_routes));
 }
@@ -9,7 +9,9 @@ use crate::models::model::{Model, ModelForm, ModelResponse, ModelUserResponse};
 use crate::services::group::GroupService;
 use crate::services::model::ModelService;
 use crate::services::user::UserService;
-use crate::utils::misc::{has_access, has_permission};
+use crate::utils::misc::has_access;
+use crate::utils::permissions::user_has_permission;
+use crate::utils::model_images;
 use crate::AppState;
 
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
@@ -23,12 +25,15 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .route("/export", web::get().to(export_models))
             .route("/import", web::post().to(import_models))
             .route("/sync", web::post().to(sync_models))
+            .route("/pinned", web::get().to(get_pinned_models))
+            .route("/pinned", web::post().to(update_pinned_models))
             .route("/model", web::get().to(get_model_by_id))
             .route(
                 "/model/profile/image",
                 web::get().to(get_model_profile_image),
             )
             .route("/model/toggle", web::post().to(toggle_model_by_id))
+            .route("/model/pin", web::post().to(toggle_model_pin))
             .route("/model/update", web::post().to(update_model_by_id))
             .route("/model/delete", web::delete().to(delete_model_by_id))
             .route("/delete/all", web::delete().to(delete_all_models)),
@@ -41,9 +46,8 @@ async fn get_models(state: web::Data<AppState>, auth_user: AuthUser) -> AppResul
     let user_service = UserService::new(&state.db);
 
     // Admins with bypass can see all models
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let bypass_admin_access_control = config.bypass_admin_access_control.unwrap_or(false);
-    drop(config);
 
     let models = if auth_user.user.role == "admin" && bypass_admin_access_control {
         model_service.get_models().await?
@@ -93,7 +97,11 @@ async fn get_models(state: web::Data<AppState>, auth_user: AuthUser) -> AppResul
 
     let response: Vec<ModelUserResponse> = models
         .into_iter()
-        .map(|m| {
+        .map(|mut m| {
+            // Keep the list small; images are fetched from /model/profile/image
+            if let Some(meta) = m.meta.as_mut() {
+                model_images::compact_profile_image(&m.id, meta);
+            }
             let user = users_map.get(&m.user_id).cloned();
             ModelUserResponse::from_model_and_user(m, user)
         })
@@ -127,12 +135,18 @@ async fn create_model(
 ) -> AppResult<HttpResponse> {
     // Check user permissions
     if auth_user.user.role != "admin" {
-        let config = state.config.read().unwrap();
+        let config = state.config.snapshot();
         let user_permissions = config.user_permissions.clone();
-        drop(config);
 
         // Check if user has workspace.models permission
-        if !has_permission(&auth_user.user.id, "workspace.models", &user_permissions) {
+        if !user_has_permission(
+            &state.db,
+            &user_permissions,
+            &auth_user.user.id,
+            "workspace.models",
+        )
+        .await?
+        {
             return Err(AppError::Forbidden("Permission denied".to_string()));
         }
     }
@@ -148,8 +162,11 @@ async fn create_model(
         return Err(AppError::BadRequest("Model ID already taken".to_string()));
     }
 
+    let mut form = form_data.into_inner();
+    store_form_profile_image(&state, &mut form)?;
+
     let model = model_service
-        .insert_new_model(form_data.into_inner(), &auth_user.user.id)
+        .insert_new_model(form, &auth_user.user.id)
         .await?;
 
     Ok(HttpResponse::Ok().json(ModelResponse::from(model)))
@@ -163,8 +180,18 @@ async fn export_models(state: web::Data<AppState>, auth_user: AuthUser) -> AppRe
 
     let model_service = ModelService::new(&state.db);
     let models = model_service.get_models().await?;
+    let cache_dir = state.config.snapshot().cache_dir.clone();
 
-    let response: Vec<ModelResponse> = models.into_iter().map(ModelResponse::from).collect();
+    // Inline stored images so the export can be imported on another instance
+    let response: Vec<ModelResponse> = models
+        .into_iter()
+        .map(|mut m| {
+            if let Some(meta) = m.meta.as_mut() {
+                model_images::inline_profile_image(&cache_dir, &m.id, meta);
+            }
+            ModelResponse::from(m)
+        })
+        .collect();
 
     Ok(HttpResponse::Ok().json(response))
 }
@@ -191,7 +218,7 @@ async fn import_models(
             // Check if model exists
             if let Some(existing) = model_service.get_model_by_id(model_id).await? {
                 // Update existing model
-                let form = ModelForm {
+                let mut form = ModelForm {
                     id: existing.id.clone(),
                     base_model_id: model_data
                         .get("base_model_id")
@@ -214,12 +241,14 @@ async fn import_models(
                         .cloned()
                         .or(existing.access_control),
                 };
+                store_form_profile_image(&state, &mut form)?;
 
                 model_service.update_model_by_id(model_id, form).await?;
             } else {
                 // Insert new model
-                let form: ModelForm = serde_json::from_value(model_data.clone())
+                let mut form: ModelForm = serde_json::from_value(model_data.clone())
                     .map_err(|e| AppError::BadRequest(format!("Invalid model data: {}", e)))?;
+                store_form_profile_image(&state, &mut form)?;
 
                 model_service
                     .insert_new_model(form, &auth_user.user.id)
@@ -273,9 +302,8 @@ async fn get_model_by_id(
         .ok_or(AppError::NotFound("Model not found".to_string()))?;
 
     // Check access
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let bypass_admin_access_control = config.bypass_admin_access_control.unwrap_or(false);
-    drop(config);
 
     if auth_user.user.role == "admin" && bypass_admin_access_control {
         return Ok(HttpResponse::Ok().json(ModelResponse::from(model)));
@@ -317,6 +345,16 @@ async fn get_model_profile_image(
         .await?
         .ok_or(AppError::NotFound("Model not found".to_string()))?;
 
+    let cache_dir = state.config.snapshot().cache_dir.clone();
+    if let Some((image_data, content_type)) =
+        model_images::load_profile_image(&cache_dir, &model.id)
+    {
+        return Ok(HttpResponse::Ok()
+            .content_type(content_type)
+            .append_header(("Cache-Control", "private, max-age=86400"))
+            .body(image_data));
+    }
+
     // Check if model has profile image URL
     if let Some(meta) = &model.meta {
         if let Some(profile_image_url) = meta.get("profile_image_url").and_then(|v| v.as_str()) {
@@ -326,22 +364,20 @@ async fn get_model_profile_image(
                     .append_header(("Location", profile_image_url))
                     .finish());
             } else if profile_image_url.starts_with("data:image") {
-                // Return base64 encoded image
-                if let Some(comma_pos) = profile_image_url.find(',') {
-                    let base64_data = &profile_image_url[comma_pos + 1..];
-                    use base64::{engine::general_purpose, Engine};
-                    if let Ok(image_data) = general_purpose::STANDARD.decode(base64_data) {
-                        return Ok(HttpResponse::Ok()
-                            .content_type("image/png")
-                            .body(image_data));
-                    }
+                // Legacy inline image saved before images were stored as files
+                if let Some((content_type, image_data)) =
+                    model_images::decode_data_url(profile_image_url)
+                {
+                    return Ok(HttpResponse::Ok()
+                        .content_type(content_type)
+                        .body(image_data));
                 }
             }
         }
     }
 
     // Return default favicon
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let static_dir = &config.static_dir;
     let favicon_path = std::path::Path::new(static_dir).join("favicon.png");
     
@@ -433,9 +469,11 @@ async fn update_model_by_id(
         }
     }
 
-    let updated = model_service
-        .update_model_by_id(&query.id, form_data.into_inner())
-        .await?;
+    let mut form = form_data.into_inner();
+    form.id = query.id.clone();
+    store_form_profile_image(&state, &mut form)?;
+
+    let updated = model_service.update_model_by_id(&query.id, form).await?;
 
     Ok(HttpResponse::Ok().json(ModelResponse::from(updated)))
 }
@@ -474,6 +512,9 @@ async fn delete_model_by_id(
 
     let result = model_service.delete_model_by_id(&query.id).await?;
 
+    let cache_dir = state.config.snapshot().cache_dir.clone();
+    model_images::delete_profile_image(&cache_dir, &query.id);
+
     Ok(HttpResponse::Ok().json(result))
 }
 
@@ -487,7 +528,123 @@ async fn delete_all_models(
     }
 
     let model_service = ModelService::new(&state.db);
+    let models = model_service.get_models().await?;
     let result = model_service.delete_all_models().await?;
 
+    let cache_dir = state.config.snapshot().cache_dir.clone();
+    for model in models {
+        model_images::delete_profile_image(&cache_dir, &model.id);
+    }
+
     Ok(HttpResponse::Ok().json(result))
 }
+
+// POST /model/pin?id= - Pin or unpin a model for the current user
+async fn toggle_model_pin(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    query: web::Query<ModelQuery>,
+) -> AppResult<HttpResponse> {
+    let mut pinned = pinned_model_ids(&auth_user.user.settings);
+
+    let is_pinned = if let Some(pos) = pinned.iter().position(|id| id == &query.id) {
+        pinned.remove(pos);
+        false
+    } else {
+        pinned.push(query.id.clone());
+        true
+    };
+
+    save_pinned_model_ids(&state, &auth_user, &pinned).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "id": query.id,
+        "pinned": is_pinned,
+        "pinned_models": pinned,
+    })))
+}
+
+// GET /pinned - Get the current user's pinned models in display order
+async fn get_pinned_models(auth_user: AuthUser) -> AppResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(pinned_model_ids(&auth_user.user.settings)))
+}
+
+// POST /pinned - Replace the current user's pinned models (also used to reorder)
+#[derive(Debug, Deserialize)]
+struct PinnedModelsForm {
+    model_ids: Vec<String>,
+}
+
+async fn update_pinned_models(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    form_data: web::Json<PinnedModelsForm>,
+) -> AppResult<HttpResponse> {
+    let mut seen = HashSet::new();
+    let pinned: Vec<String> = form_data
+        .into_inner()
+        .model_ids
+        .into_iter()
+        .filter(|id| !id.is_empty() && seen.insert(id.clone()))
+        .collect();
+
+    save_pinned_model_ids(&state, &auth_user, &pinned).await?;
+
+    Ok(HttpResponse::Ok().json(pinned))
+}
+
+/// Pinned model IDs are kept in `settings.ui.pinnedModels`, where the frontend reads them
+fn pinned_model_ids(settings: &Option<serde_json::Value>) -> Vec<String> {
+    settings
+        .as_ref()
+        .and_then(|s| s.get("ui"))
+        .and_then(|ui| ui.get("pinnedModels"))
+        .and_then(|v| v.as_array())
+        .map(|ids| {
+            ids.iter()
+                .filter_map(|id| id.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn save_pinned_model_ids(
+    state: &web::Data<AppState>,
+    auth_user: &AuthUser,
+    pinned: &[String],
+) -> AppResult<()> {
+    let mut settings = auth_user
+        .user
+        .settings
+        .clone()
+        .filter(|s| s.is_object())
+        .unwrap_or_else(|| json!({}));
+    if !settings.get("ui").map(|ui| ui.is_object()).unwrap_or(false) {
+        settings["ui"] = json!({});
+    }
+    settings["ui"]["pinnedModels"] = json!(pinned);
+
+    let user_service = UserService::new(&state.db);
+    user_service
+        .update_user_settings(&auth_user.user.id, &settings)
+        .await
+}
+
+/// Move an inline base64 profile image into the image cache before the model is saved
+fn store_form_profile_image(state: &web::Data<AppState>, form: &mut ModelForm) -> AppResult<()> {
+    let cache_dir = state.config.snapshot().cache_dir.clone();
+    model_images::store_profile_image(&cache_dir, &form.id, &mut form.meta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pinned_model_ids() {
+        let settings = Some(json!({"ui": {"pinnedModels": ["b", "a", 3]}}));
+        assert_eq!(pinned_model_ids(&settings), vec!["b", "a"]);
+        assert!(pinned_model_ids(&None).is_empty());
+        assert!(pinned_model_ids(&Some(json!({"ui": {}}))).is_empty());
+    }
+}
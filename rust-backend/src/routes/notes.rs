@@ -1,15 +1,79 @@
+use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse};
+use futures::stream::{self, StreamExt, TryStreamExt};
 use serde::Deserialize;
+use serde_json::json;
 
 use crate::{
     error::{AppError, AppResult},
-    middleware::{AuthMiddleware, AuthUser},
-    models::note::{NoteForm, NoteModel, NoteTitleIdResponse, NoteUpdateForm, NoteUserResponse},
-    services::{group::GroupService, note::NoteService, user::UserService},
-    utils::misc::{has_access, has_permission},
+    middleware::{request_id::propagate, AuthMiddleware, AuthUser},
+    models::note::{
+        Note, NoteForm, NoteModel, NoteTitleIdResponse, NoteUpdateForm, NoteUserResponse,
+    },
+    routes::tasks::{build_task_request, request_task_completion, resolve_task_model},
+    services::{
+        audio::{AudioService, STTRequest},
+        group::GroupService,
+        note::NoteService,
+        user::UserService,
+    },
+    utils::{
+        chat_completion::{
+            extract_completion_content, into_chat_completion_response,
+            DEFAULT_TITLE_GENERATION_PROMPT_TEMPLATE,
+        },
+        misc::has_access,
+        permissions::user_has_permission,
+    },
     AppState,
 };
 
+/// Notes longer than this (in bytes) are condensed chunk by chunk before the final AI pass
+const NOTE_AI_CONTEXT_CHARS: usize = 12_000;
+
+/// Overlap between chunks so sentences cut at a boundary keep their context
+const NOTE_AI_CHUNK_OVERLAP: usize = 200;
+
+/// Bound on reduce rounds, in case the model doesn't shorten its input
+const NOTE_AI_MAX_REDUCE_ROUNDS: usize = 3;
+
+/// Chunks condensed concurrently during the map step
+const NOTE_AI_MAP_CONCURRENCY: usize = 4;
+
+const NOTE_AI_MAX_TOKENS: i32 = 2048;
+
+/// Largest audio upload accepted for transcription (the OpenAI STT limit)
+const NOTE_TRANSCRIBE_MAX_BYTES: usize = 25 * 1024 * 1024;
+
+const NOTE_ENHANCE_PROMPT_TEMPLATE: &str = r#"### Task:
+Enhance the following note. Fix grammar and spelling, improve clarity and structure, and organize it with Markdown headings and lists where helpful. Preserve every fact and the note's original language; do not add new information.
+{{INSTRUCTION}}
+### Output:
+Respond only with the enhanced note in Markdown, without any introduction or explanation.
+### Note:
+<note>
+{{CONTENT}}
+</note>"#;
+
+const NOTE_SUMMARIZE_PROMPT_TEMPLATE: &str = r#"### Task:
+Summarize the following note in Markdown. Capture the key points, decisions, and action items, and write in the note's original language.
+{{INSTRUCTION}}
+### Output:
+Respond only with the summary, without any introduction or explanation.
+### Note:
+<note>
+{{CONTENT}}
+</note>"#;
+
+const NOTE_CHUNK_CONDENSE_PROMPT_TEMPLATE: &str = r#"### Task:
+This is part {{PART}} of {{TOTAL}} of a longer note. Condense it into dense Markdown notes that keep every fact, name, number, decision, and action item, in the note's original language.
+### Output:
+Respond only with the condensed notes.
+### Note part:
+<note_part>
+{{CONTENT}}
+</note_part>"#;
+
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("")
@@ -31,6 +95,11 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AuthMiddleware)
             .route(web::post().to(create_new_note)),
     )
+    .service(
+        web::resource("/transcribe")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(transcribe_to_note)),
+    )
     .service(
         web::resource("/{id}")
             .wrap(AuthMiddleware)
@@ -45,25 +114,18 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
         web::resource("/{id}/delete")
             .wrap(AuthMiddleware)
             .route(web::delete().to(delete_note_by_id)),
+    )
+    .service(
+        web::resource("/{id}/enhance")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(enhance_note)),
     );
 }
 
 /// GET / - Get notes with permission filtering
 async fn get_notes(state: web::Data<AppState>, auth_user: AuthUser) -> AppResult<HttpResponse> {
     // Check if user has notes feature permission
-    let config = state.config.read().unwrap();
-    if auth_user.user.role != "admin"
-        && !has_permission(
-            &auth_user.user.id,
-            "features.notes",
-            &config.user_permissions,
-        )
-    {
-        return Err(AppError::Unauthorized(
-            "User does not have permission for notes".to_string(),
-        ));
-    }
-    drop(config);
+    ensure_notes_permission(&state, &auth_user).await?;
 
     let note_service = NoteService::new(&state.db);
     let group_service = GroupService::new(&state.db);
@@ -112,19 +174,7 @@ async fn get_note_list(
     query: web::Query<ListQuery>,
 ) -> AppResult<HttpResponse> {
     // Check if user has notes feature permission
-    let config = state.config.read().unwrap();
-    if auth_user.user.role != "admin"
-        && !has_permission(
-            &auth_user.user.id,
-            "features.notes",
-            &config.user_permissions,
-        )
-    {
-        return Err(AppError::Unauthorized(
-            "User does not have permission for notes".to_string(),
-        ));
-    }
-    drop(config);
+    ensure_notes_permission(&state, &auth_user).await?;
 
     let note_service = NoteService::new(&state.db);
     let group_service = GroupService::new(&state.db);
@@ -170,19 +220,7 @@ async fn create_new_note(
     form_data: web::Json<NoteForm>,
 ) -> AppResult<HttpResponse> {
     // Check if user has notes feature permission
-    let config = state.config.read().unwrap();
-    if auth_user.user.role != "admin"
-        && !has_permission(
-            &auth_user.user.id,
-            "features.notes",
-            &config.user_permissions,
-        )
-    {
-        return Err(AppError::Unauthorized(
-            "User does not have permission for notes".to_string(),
-        ));
-    }
-    drop(config);
+    ensure_notes_permission(&state, &auth_user).await?;
 
     let note_service = NoteService::new(&state.db);
     let note = note_service
@@ -201,19 +239,7 @@ async fn get_note_by_id(
     let note_id = path.into_inner();
 
     // Check if user has notes feature permission
-    let config = state.config.read().unwrap();
-    if auth_user.user.role != "admin"
-        && !has_permission(
-            &auth_user.user.id,
-            "features.notes",
-            &config.user_permissions,
-        )
-    {
-        return Err(AppError::Unauthorized(
-            "User does not have permission for notes".to_string(),
-        ));
-    }
-    drop(config);
+    ensure_notes_permission(&state, &auth_user).await?;
 
     let note_service = NoteService::new(&state.db);
     let mut note = note_service
@@ -257,24 +283,15 @@ async fn update_note_by_id(
     let note_id = path.into_inner();
 
     // Check if user has notes feature permission
-    let config = state.config.read().unwrap();
-    if auth_user.user.role != "admin"
-        && !has_permission(
-            &auth_user.user.id,
-            "features.notes",
-            &config.user_permissions,
-        )
-    {
-        return Err(AppError::Unauthorized(
-            "User does not have permission for notes".to_string(),
-        ));
-    }
-    let can_share_public = has_permission(
+    ensure_notes_permission(&state, &auth_user).await?;
+    let user_permissions = state.config.snapshot().user_permissions.clone();
+    let can_share_public = user_has_permission(
+        &state.db,
+        &user_permissions,
         &auth_user.user.id,
         "sharing.public_notes",
-        &config.user_permissions,
-    );
-    drop(config);
+    )
+    .await?;
 
     let note_service = NoteService::new(&state.db);
     let mut note = note_service
@@ -338,19 +355,7 @@ async fn delete_note_by_id(
     let note_id = path.into_inner();
 
     // Check if user has notes feature permission
-    let config = state.config.read().unwrap();
-    if auth_user.user.role != "admin"
-        && !has_permission(
-            &auth_user.user.id,
-            "features.notes",
-            &config.user_permissions,
-        )
-    {
-        return Err(AppError::Unauthorized(
-            "User does not have permission for notes".to_string(),
-        ));
-    }
-    drop(config);
+    ensure_notes_permission(&state, &auth_user).await?;
 
     let note_service = NoteService::new(&state.db);
     let mut note = note_service
@@ -385,3 +390,670 @@ async fn delete_note_by_id(
 
     Ok(HttpResponse::Ok().json(true))
 }
+
+async fn ensure_notes_permission(state: &AppState, auth_user: &AuthUser) -> AppResult<()> {
+    if auth_user.user.role == "admin" {
+        return Ok(());
+    }
+    let user_permissions = state.config.snapshot().user_permissions.clone();
+    if !user_has_permission(
+        &state.db,
+        &user_permissions,
+        &auth_user.user.id,
+        "features.notes",
+    )
+    .await?
+    {
+        return Err(AppError::Unauthorized(
+            "User does not have permission for notes".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn ensure_note_access(
+    state: &AppState,
+    auth_user: &AuthUser,
+    note: &Note,
+    permission: &str,
+) -> AppResult<()> {
+    if auth_user.user.role == "admin" || auth_user.user.id == note.user_id {
+        return Ok(());
+    }
+
+    let group_service = GroupService::new(&state.db);
+    let user_groups = group_service
+        .get_groups_by_member_id(&auth_user.user.id)
+        .await?;
+    let user_group_ids: std::collections::HashSet<String> =
+        user_groups.into_iter().map(|g| g.id).collect();
+
+    if !has_access(
+        &auth_user.user.id,
+        permission,
+        &note.access_control,
+        &user_group_ids,
+    ) {
+        return Err(AppError::Forbidden("Access denied".to_string()));
+    }
+    Ok(())
+}
+
+/// Markdown content of a note (`data.content.md`), or `data.content` when stored as plain text
+fn note_markdown(note: &Note) -> String {
+    let content = note.data.as_ref().and_then(|d| d.get("content"));
+    content
+        .and_then(|c| c.get("md"))
+        .or(content)
+        .and_then(|c| c.as_str())
+        .unwrap_or("")
+        .to_string()
+}
+
+fn build_note_ai_prompt(
+    action: &str,
+    content: &str,
+    instruction: Option<&str>,
+) -> AppResult<String> {
+    let template = match action {
+        "enhance" => NOTE_ENHANCE_PROMPT_TEMPLATE,
+        "summarize" => NOTE_SUMMARIZE_PROMPT_TEMPLATE,
+        other => {
+            return Err(AppError::BadRequest(format!(
+                "Unsupported note action: {}",
+                other
+            )))
+        }
+    };
+
+    let instruction = instruction
+        .map(str::trim)
+        .filter(|i| !i.is_empty())
+        .map(|i| format!("Additional instructions: {}", i))
+        .unwrap_or_default();
+
+    Ok(template
+        .replace("{{INSTRUCTION}}", &instruction)
+        .replace("{{CONTENT}}", content))
+}
+
+/// Map-reduce text that doesn't fit the model's context: condense each chunk (map), join the
+/// partial results (reduce), and repeat until the text fits
+async fn condense_for_context(
+    state: &web::Data<AppState>,
+    auth_user: &AuthUser,
+    model: &str,
+    model_item: Option<&serde_json::Value>,
+    content: String,
+) -> AppResult<String> {
+    let mut text = content;
+
+    for round in 0..NOTE_AI_MAX_REDUCE_ROUNDS {
+        if text.len() <= NOTE_AI_CONTEXT_CHARS {
+            break;
+        }
+
+        let chunks =
+            crate::retrieval::chunk_text(&text, NOTE_AI_CONTEXT_CHARS, NOTE_AI_CHUNK_OVERLAP);
+        let total = chunks.len();
+        tracing::debug!("Condensing note in {} chunk(s), round {}", total, round + 1);
+
+        let condensed: Vec<String> = stream::iter(chunks.into_iter().enumerate())
+            .map(|(i, chunk)| {
+                let prompt = NOTE_CHUNK_CONDENSE_PROMPT_TEMPLATE
+                    .replace("{{PART}}", &(i + 1).to_string())
+                    .replace("{{TOTAL}}", &total.to_string())
+                    .replace("{{CONTENT}}", &chunk);
+                async move {
+                    let response = request_task_completion(
+                        state,
+                        auth_user,
+                        model,
+                        model_item,
+                        &prompt,
+                        NOTE_AI_MAX_TOKENS,
+                        0.3,
+                    )
+                    .await?;
+                    Ok::<_, AppError>(
+                        extract_completion_content(&response)
+                            .unwrap_or_default()
+                            .trim()
+                            .to_string(),
+                    )
+                }
+            })
+            .buffered(NOTE_AI_MAP_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        text = condensed.join("\n\n");
+    }
+
+    Ok(text)
+}
+
+/// A `data:` line of an OpenAI-compatible SSE stream
+#[derive(Debug, PartialEq)]
+enum StreamEvent {
+    Delta(String),
+    Done,
+}
+
+fn parse_stream_line(line: &str) -> Option<StreamEvent> {
+    let data = line.trim().strip_prefix("data:")?.trim();
+    if data == "[DONE]" {
+        return Some(StreamEvent::Done);
+    }
+
+    let value: serde_json::Value = serde_json::from_str(data).ok()?;
+    value
+        .get("choices")
+        .and_then(|c| c.get(0))
+        .and_then(|c| c.get("delta"))
+        .and_then(|d| d.get("content"))
+        .and_then(|c| c.as_str())
+        .filter(|c| !c.is_empty())
+        .map(|c| StreamEvent::Delta(c.to_string()))
+}
+
+async fn emit_note_ai_event(
+    state: &AppState,
+    session_id: &str,
+    note_id: &str,
+    data: serde_json::Value,
+) {
+    if let Some(event_handler) = &state.socketio_handler {
+        let event = json!({
+            "id": note_id,
+            "type": "note:ai",
+            "data": data,
+        });
+        if let Err(e) = event_handler
+            .emit_to_session(session_id, "note-events", event)
+            .await
+        {
+            tracing::warn!("Failed to emit note AI event: {}", e);
+        }
+    }
+}
+
+/// Stream a completion to one Socket.IO session as `note-events`, returning the full text
+#[allow(clippy::too_many_arguments)]
+async fn stream_note_completion(
+    state: &web::Data<AppState>,
+    auth_user: &AuthUser,
+    model: &str,
+    model_item: Option<&serde_json::Value>,
+    prompt: &str,
+    action: &str,
+    note_id: &str,
+    session_id: &str,
+) -> AppResult<String> {
+    let payload = json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": prompt }],
+        "max_tokens": NOTE_AI_MAX_TOKENS,
+        "temperature": 0.3,
+        "stream": true
+    });
+
+    let (request, api_config) = build_task_request(state, auth_user, model, model_item, &payload)?;
+    let response = into_chat_completion_response(request.send().await?, &api_config).await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(AppError::ExternalServiceError(format!(
+            "API call failed with status {}: {}",
+            status, error_text
+        )));
+    }
+
+    let mut bytes = response.bytes_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut content = String::new();
+
+    'stream: while let Some(chunk) = bytes.next().await {
+        buffer.extend_from_slice(&chunk?);
+
+        // SSE lines can be split across network chunks; only handle complete lines
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            match parse_stream_line(&String::from_utf8_lossy(&line)) {
+                Some(StreamEvent::Delta(delta)) => {
+                    content.push_str(&delta);
+                    emit_note_ai_event(
+                        state,
+                        session_id,
+                        note_id,
+                        json!({ "action": action, "delta": delta, "done": false }),
+                    )
+                    .await;
+                }
+                Some(StreamEvent::Done) => break 'stream,
+                None => {}
+            }
+        }
+    }
+
+    Ok(content)
+}
+
+#[derive(Debug, Deserialize)]
+struct NoteAIForm {
+    model: String,
+    #[serde(default)]
+    model_item: Option<serde_json::Value>,
+    /// "enhance" (default) or "summarize"
+    #[serde(default)]
+    action: Option<String>,
+    /// Extra instruction from the user, e.g. "make it more formal"
+    #[serde(default)]
+    prompt: Option<String>,
+    /// Unsaved editor content; defaults to the stored note content
+    #[serde(default)]
+    content: Option<String>,
+    /// Socket.IO session to stream the result to
+    #[serde(default)]
+    session_id: Option<String>,
+}
+
+/// POST /{id}/enhance - Enhance or summarize a note with the task model.
+/// With a `session_id` the result is streamed to that session as `note-events` and the
+/// request returns immediately; otherwise the full result is returned.
+async fn enhance_note(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    path: web::Path<String>,
+    form_data: web::Json<NoteAIForm>,
+) -> AppResult<HttpResponse> {
+    let note_id = path.into_inner();
+    ensure_notes_permission(&state, &auth_user).await?;
+
+    let note_service = NoteService::new(&state.db);
+    let mut note = note_service
+        .get_note_by_id(&note_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Note not found".to_string()))?;
+    note.parse_json_fields();
+    ensure_note_access(&state, &auth_user, &note, "write").await?;
+
+    let form = form_data.into_inner();
+    let action = form.action.unwrap_or_else(|| "enhance".to_string());
+    let content = form.content.unwrap_or_else(|| note_markdown(&note));
+    if content.trim().is_empty() {
+        return Err(AppError::BadRequest("Note is empty".to_string()));
+    }
+    // Reject unknown actions before doing any work
+    build_note_ai_prompt(&action, "", None)?;
+
+    let task_model = resolve_task_model(&state, &form.model, form.model_item.as_ref());
+    let model_item = if task_model == form.model {
+        form.model_item
+    } else {
+        None
+    };
+
+    // Only stream to sessions owned by the requesting user
+    let session_id = match (form.session_id, &state.socketio_handler) {
+        (Some(sid), Some(event_handler)) => {
+            let owner = event_handler
+                .manager()
+                .get_session(&sid)
+                .await
+                .and_then(|s| s.user_id());
+            if owner.as_deref() != Some(auth_user.user.id.as_str()) {
+                return Err(AppError::Forbidden("Invalid session".to_string()));
+            }
+            Some(sid)
+        }
+        _ => None,
+    };
+
+    let session_id = match session_id {
+        Some(session_id) => session_id,
+        None => {
+            let content = condense_for_context(
+                &state,
+                &auth_user,
+                &task_model,
+                model_item.as_ref(),
+                content,
+            )
+            .await?;
+            let prompt = build_note_ai_prompt(&action, &content, form.prompt.as_deref())?;
+            let response = request_task_completion(
+                &state,
+                &auth_user,
+                &task_model,
+                model_item.as_ref(),
+                &prompt,
+                NOTE_AI_MAX_TOKENS,
+                0.3,
+            )
+            .await?;
+            let result = extract_completion_content(&response)
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+
+            return Ok(HttpResponse::Ok().json(json!({
+                "id": note_id,
+                "action": action,
+                "content": result,
+            })));
+        }
+    };
+
+    let response = json!({
+        "status": "streaming",
+        "id": note_id,
+        "session_id": session_id,
+    });
+
+    tokio::spawn(propagate(async move {
+        let result = async {
+            let content = condense_for_context(
+                &state,
+                &auth_user,
+                &task_model,
+                model_item.as_ref(),
+                content,
+            )
+            .await?;
+            let prompt = build_note_ai_prompt(&action, &content, form.prompt.as_deref())?;
+            stream_note_completion(
+                &state,
+                &auth_user,
+                &task_model,
+                model_item.as_ref(),
+                &prompt,
+                &action,
+                &note_id,
+                &session_id,
+            )
+            .await
+        }
+        .await;
+
+        let data = match result {
+            Ok(content) => json!({ "action": action, "content": content, "done": true }),
+            Err(e) => {
+                tracing::error!("Note {} {} failed: {}", note_id, action, e);
+                json!({ "action": action, "error": e.to_string(), "done": true })
+            }
+        };
+        emit_note_ai_event(&state, &session_id, &note_id, data).await;
+    }));
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+fn is_supported_audio_type(content_type: &str, supported: &[String]) -> bool {
+    supported.iter().any(|allowed| {
+        allowed == content_type
+            || allowed
+                .strip_suffix("/*")
+                .map(|prefix| content_type.starts_with(&format!("{}/", prefix)))
+                .unwrap_or(false)
+    })
+}
+
+/// Pull the title out of a `{ "title": ... }` response, falling back to the first line
+fn parse_generated_title(text: &str) -> Option<String> {
+    let parsed = match (text.find('{'), text.rfind('}')) {
+        (Some(start), Some(end)) if start < end => {
+            serde_json::from_str::<serde_json::Value>(&text[start..=end])
+                .ok()
+                .and_then(|v| v.get("title").and_then(|t| t.as_str()).map(String::from))
+        }
+        _ => None,
+    };
+
+    parsed
+        .or_else(|| text.lines().next().map(String::from))
+        .map(|t| t.trim().trim_matches('"').trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+/// Render a plain-text transcript as editor HTML, one paragraph per line
+fn transcript_to_html(transcript: &str) -> String {
+    transcript
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let escaped = line
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            format!("<p>{}</p>", escaped)
+        })
+        .collect()
+}
+
+async fn read_text_field(field: &mut actix_multipart::Field) -> AppResult<String> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(|e| AppError::BadRequest(format!("Chunk read error: {}", e)))?;
+        bytes.extend_from_slice(&chunk);
+    }
+    String::from_utf8(bytes).map_err(|_| AppError::BadRequest("Invalid form field".to_string()))
+}
+
+/// POST /transcribe - Transcribe an uploaded audio file into a new note.
+/// Multipart fields: `file`, optional `language`, and `model` (plus `model_item` as JSON)
+/// to generate a title with the task model.
+async fn transcribe_to_note(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    mut payload: Multipart,
+) -> AppResult<HttpResponse> {
+    ensure_notes_permission(&state, &auth_user).await?;
+    let config = state.config.snapshot().as_ref().clone();
+
+    let mut filename = String::new();
+    let mut audio = Vec::new();
+    let mut language = None;
+    let mut model = None;
+    let mut model_item = None;
+
+    while let Some(field) = payload.next().await {
+        let mut field =
+            field.map_err(|e| AppError::BadRequest(format!("Multipart error: {}", e)))?;
+        let field_name = field
+            .content_disposition()
+            .and_then(|cd| cd.get_name())
+            .unwrap_or("")
+            .to_string();
+
+        match field_name.as_str() {
+            "file" => {
+                filename = field
+                    .content_disposition()
+                    .and_then(|cd| cd.get_filename())
+                    .unwrap_or("audio")
+                    .to_string();
+
+                let content_type = field
+                    .content_type()
+                    .map(|m| m.essence_str().to_string())
+                    .unwrap_or_else(|| {
+                        mime_guess::from_path(&filename)
+                            .first_or_octet_stream()
+                            .to_string()
+                    });
+                if !is_supported_audio_type(&content_type, &config.stt_supported_content_types) {
+                    return Err(AppError::BadRequest(format!(
+                        "Unsupported audio type: {}",
+                        content_type
+                    )));
+                }
+
+                while let Some(chunk) = field.next().await {
+                    let chunk = chunk
+                        .map_err(|e| AppError::BadRequest(format!("Chunk read error: {}", e)))?;
+                    if audio.len() + chunk.len() > NOTE_TRANSCRIBE_MAX_BYTES {
+                        return Err(AppError::BadRequest("Audio file is too large".to_string()));
+                    }
+                    audio.extend_from_slice(&chunk);
+                }
+            }
+            "language" => language = Some(read_text_field(&mut field).await?),
+            "model" => model = Some(read_text_field(&mut field).await?),
+            "model_item" => {
+                model_item = serde_json::from_str(&read_text_field(&mut field).await?).ok()
+            }
+            _ => {}
+        }
+    }
+
+    if audio.is_empty() {
+        return Err(AppError::BadRequest("No audio file uploaded".to_string()));
+    }
+
+    let transcript = AudioService::new(config.clone())
+        .speech_to_text(
+            audio,
+            STTRequest {
+                model: config.stt_model.clone(),
+                language: language.filter(|l: &String| !l.is_empty()),
+                file_name: Some(filename),
+            },
+        )
+        .await?;
+
+    let mut title = format!("Transcript {}", chrono::Utc::now().format("%Y-%m-%d %H:%M"));
+
+    // Title generation is best effort; the transcript is kept either way
+    if let Some(model) = model.filter(|m| !m.is_empty()) {
+        if config.enable_title_generation && !transcript.trim().is_empty() {
+            let task_model = resolve_task_model(&state, &model, model_item.as_ref());
+            let model_item = if task_model == model {
+                model_item
+            } else {
+                None
+            };
+
+            let template = if config.title_generation_prompt_template.is_empty() {
+                DEFAULT_TITLE_GENERATION_PROMPT_TEMPLATE
+            } else {
+                config.title_generation_prompt_template.as_str()
+            };
+
+            let generated = async {
+                let content = condense_for_context(
+                    &state,
+                    &auth_user,
+                    &task_model,
+                    model_item.as_ref(),
+                    transcript.clone(),
+                )
+                .await?;
+                let prompt = template.replace("{{MESSAGES:END:2}}", &format!("user: {}", content));
+                let response = request_task_completion(
+                    &state,
+                    &auth_user,
+                    &task_model,
+                    model_item.as_ref(),
+                    &prompt,
+                    50,
+                    0.1,
+                )
+                .await?;
+                Ok::<_, AppError>(
+                    extract_completion_content(&response).and_then(parse_generated_title),
+                )
+            }
+            .await;
+
+            match generated {
+                Ok(Some(generated)) => title = generated,
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to generate transcript title: {}", e),
+            }
+        }
+    }
+
+    let form = NoteForm {
+        title,
+        data: Some(json!({
+            "content": {
+                "md": transcript,
+                "html": transcript_to_html(&transcript),
+                "json": null,
+            }
+        })),
+        meta: None,
+        access_control: Some(json!({})),
+    };
+
+    let note = NoteService::new(&state.db)
+        .insert_new_note(&auth_user.user.id, &form)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(NoteModel::from(note)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_note_ai_prompt() {
+        let prompt =
+            build_note_ai_prompt("summarize", "meeting notes", Some(" be brief ")).unwrap();
+        assert!(prompt.contains("<note>\nmeeting notes\n</note>"));
+        assert!(prompt.contains("Additional instructions: be brief"));
+
+        let prompt = build_note_ai_prompt("enhance", "draft", None).unwrap();
+        assert!(!prompt.contains("{{INSTRUCTION}}"));
+        assert!(!prompt.contains("Additional instructions"));
+
+        assert!(build_note_ai_prompt("translate", "draft", None).is_err());
+    }
+
+    #[test]
+    fn test_parse_stream_line() {
+        assert_eq!(
+            parse_stream_line(r#"data: {"choices":[{"delta":{"content":"Hi"}}]}"#),
+            Some(StreamEvent::Delta("Hi".to_string()))
+        );
+        assert_eq!(parse_stream_line("data: [DONE]"), Some(StreamEvent::Done));
+        assert_eq!(
+            parse_stream_line(r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#),
+            None
+        );
+        assert_eq!(parse_stream_line(": keep-alive"), None);
+    }
+
+    #[test]
+    fn test_is_supported_audio_type() {
+        let supported = vec!["audio/*".to_string(), "video/webm".to_string()];
+        assert!(is_supported_audio_type("audio/mpeg", &supported));
+        assert!(is_supported_audio_type("video/webm", &supported));
+        assert!(!is_supported_audio_type("video/mp4", &supported));
+        assert!(!is_supported_audio_type("audiox/foo", &supported));
+    }
+
+    #[test]
+    fn test_parse_generated_title() {
+        assert_eq!(
+            parse_generated_title(r#"{ "title": "📝 Weekly Sync" }"#),
+            Some("📝 Weekly Sync".to_string())
+        );
+        assert_eq!(
+            parse_generated_title("\"Project Kickoff\"\nmore"),
+            Some("Project Kickoff".to_string())
+        );
+        assert_eq!(parse_generated_title("   "), None);
+    }
+
+    #[test]
+    fn test_transcript_to_html() {
+        assert_eq!(
+            transcript_to_html("Hello <team>\n\nA & B"),
+            "<p>Hello &lt;team&gt;</p><p>A &amp; B</p>"
+        );
+    }
+}
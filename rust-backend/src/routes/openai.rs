@@ -1,11 +1,26 @@
 use actix_web::{web, HttpResponse};
 use futures::stream::StreamExt;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 use crate::{
     error::AppError,
-    middleware::{AuthMiddleware, AuthUser},
-    utils::chat_completion::{self, StreamingContext},
+    middleware::{
+        rate_limit::{RateLimit, RouteGroup},
+        request_id::WithRequestId,
+        AuthMiddleware, AuthUser,
+    },
+    models::{config::DirectConnections, tool_runtime::UserContext},
+    services::{
+        group::GroupService,
+        models::{ModelService, OpenAIModelList},
+    },
+    utils::{
+        anthropic,
+        chat_completion::{self, StreamingContext},
+        chat_request, gemini, http_client, legacy_completions, permissions, prompt_variables,
+        response_format, telemetry, upstream,
+    },
     AppState,
 };
 
@@ -29,10 +44,21 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .route("/config/update", web::post().to(update_config))
             .route("/models", web::get().to(get_models))
             .route("/models/{url_idx}", web::get().to(get_models_by_idx))
+            .route("/v1/models", web::get().to(list_models_v1))
             .route("/verify", web::post().to(verify_connection))
             .route("/audio/speech", web::post().to(audio_speech))
             .route("/embeddings", web::post().to(embeddings_endpoint))
-            .route("/chat/completions", web::post().to(chat_completions))
+            // Same limit as /api/chat/completions, which these alias
+            .service(
+                web::resource("/chat/completions")
+                    .wrap(RateLimit::new(RouteGroup::Chat))
+                    .route(web::post().to(chat_completions)),
+            )
+            .service(
+                web::resource("/completions")
+                    .wrap(RateLimit::new(RouteGroup::Chat))
+                    .route(web::post().to(completions)),
+            )
             .route("/{path:.*}", web::to(proxy_request)),
     );
 }
@@ -46,7 +72,7 @@ async fn get_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(OpenAIConfigResponse {
         enable_openai_api: config.enable_openai_api,
@@ -68,7 +94,7 @@ async fn update_config(
 
     // Update in-memory config
     {
-        let mut config = state.config.write().unwrap();
+        let mut config = state.config.write();
 
         config.enable_openai_api = form_data.enable_openai_api;
         config.openai_api_base_urls = form_data.openai_api_base_urls.clone();
@@ -92,7 +118,7 @@ async fn update_config(
     }
 
     // Persist to database (best-effort, like Python)
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let openai_json = serde_json::json!({
         "enable": config.enable_openai_api,
         "api_base_urls": config.openai_api_base_urls,
@@ -115,7 +141,7 @@ async fn get_models(
     state: web::Data<AppState>,
     _auth_user: AuthUser,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_openai_api {
         return Ok(HttpResponse::Ok().json(serde_json::json!({
@@ -124,7 +150,7 @@ async fn get_models(
     }
 
     let mut all_models = Vec::new();
-    let client = reqwest::Client::new();
+    let client = http_client::client(&config);
 
     // Fetch models from each configured OpenAI endpoint
     for (idx, url) in config.openai_api_base_urls.iter().enumerate() {
@@ -172,15 +198,30 @@ async fn get_models(
                 }
             } else {
                 // Fetch models from the endpoint
-                match client
+                let request = client
                     .get(format!("{}/models", url))
-                    .header("Authorization", format!("Bearer {}", key))
-                    .header("Content-Type", "application/json")
-                    .send()
-                    .await
-                {
+                    .header("Content-Type", "application/json");
+                let api_config_value = serde_json::json!(api_config);
+                let is_gemini = gemini::is_gemini(&api_config_value);
+                let request = if anthropic::is_anthropic(&api_config_value) {
+                    anthropic::with_auth(request, key)
+                } else if is_gemini {
+                    gemini::with_auth(request, key)
+                } else {
+                    request.header("Authorization", format!("Bearer {}", key))
+                };
+                let span = telemetry::upstream_span("list_models", url, None);
+                match telemetry::send_traced(request, span).await {
                     Ok(response) if response.status().is_success() => {
-                        if let Ok(models_response) = response.json::<serde_json::Value>().await {
+                        let models_response =
+                            response.json::<serde_json::Value>().await.map(|models| {
+                                if is_gemini {
+                                    gemini::to_model_list(&models)
+                                } else {
+                                    models
+                                }
+                            });
+                        if let Ok(models_response) = models_response {
                             if let Some(data) =
                                 models_response.get("data").and_then(|v| v.as_array())
                             {
@@ -205,15 +246,23 @@ async fn get_models(
                                             }
                                         }
 
-                                        all_models.push(serde_json::json!({
+                                        let mut entry = serde_json::json!({
                                             "id": model_id,
-                                            "name": model.get("name").and_then(|v| v.as_str()).unwrap_or(model_id),
+                                            "name": model
+                                                .get("name")
+                                                .or_else(|| model.get("display_name"))
+                                                .and_then(|v| v.as_str())
+                                                .unwrap_or(model_id),
                                             "object": "model",
                                             "owned_by": model.get("owned_by").and_then(|v| v.as_str()).unwrap_or("openai"),
                                             "openai": model,
                                             "connection_type": "external",
                                             "urlIdx": idx
-                                        }));
+                                        });
+                                        if let Some(tags) = model.get("tags") {
+                                            entry["tags"] = tags.clone();
+                                        }
+                                        all_models.push(entry);
                                     }
                                 }
                             }
@@ -250,6 +299,32 @@ async fn get_models(
     })))
 }
 
+/// Strict OpenAI-compatible model list for external SDK clients (LangChain etc.);
+/// the frontend keeps using the richer `/api/models` shape
+pub async fn list_models_v1(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> Result<HttpResponse, AppError> {
+    let config = state.config.snapshot().as_ref().clone();
+    let model_service = ModelService::new(config);
+
+    let models = model_service.get_all_models(&state.db).await?;
+    let user_group_ids: HashSet<String> = GroupService::new(&state.db)
+        .get_groups_by_member_id(&auth_user.user.id)
+        .await?
+        .into_iter()
+        .map(|g| g.id)
+        .collect();
+    let models = model_service.filter_models_by_access(
+        models,
+        &auth_user.user.id,
+        &auth_user.user.role,
+        &user_group_ids,
+    );
+
+    Ok(HttpResponse::Ok().json(OpenAIModelList::from_models(&models)))
+}
+
 // Get models from a specific OpenAI endpoint by index
 async fn get_models_by_idx(
     state: web::Data<AppState>,
@@ -257,7 +332,7 @@ async fn get_models_by_idx(
     _auth_user: AuthUser,
 ) -> Result<HttpResponse, AppError> {
     let idx = url_idx.into_inner();
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if idx >= config.openai_api_base_urls.len() {
         return Err(AppError::NotFound("OpenAI endpoint not found".to_string()));
@@ -300,17 +375,31 @@ async fn get_models_by_idx(
     }
 
     // Fetch models from the endpoint
-    let client = reqwest::Client::new();
+    let client = http_client::client(&config);
 
-    match client
+    let request = client
         .get(format!("{}/models", url))
-        .header("Authorization", format!("Bearer {}", key))
-        .header("Content-Type", "application/json")
-        .send()
-        .await
-    {
+        .header("Content-Type", "application/json");
+    let api_config_value = serde_json::json!(api_config);
+    let is_gemini = gemini::is_gemini(&api_config_value);
+    let request = if anthropic::is_anthropic(&api_config_value) {
+        anthropic::with_auth(request, key)
+    } else if is_gemini {
+        gemini::with_auth(request, key)
+    } else {
+        request.header("Authorization", format!("Bearer {}", key))
+    };
+    let span = telemetry::upstream_span("list_models", url, None);
+    match telemetry::send_traced(request, span).await {
         Ok(response) if response.status().is_success() => {
-            if let Ok(mut models_response) = response.json::<serde_json::Value>().await {
+            let models_response = response.json::<serde_json::Value>().await.map(|models| {
+                if is_gemini {
+                    gemini::to_model_list(&models)
+                } else {
+                    models
+                }
+            });
+            if let Ok(mut models_response) = models_response {
                 // Filter OpenAI API models
                 if url.contains("api.openai.com") {
                     if let Some(data) = models_response
@@ -376,53 +465,32 @@ async fn get_models_by_idx(
     }
 }
 
-/// Public helper to get first enabled OpenAI endpoint for tasks like title generation
+/// Public helper resolving the endpoint that serves `model_id` (by its cached urlIdx), or
+/// the first enabled OpenAI endpoint, for tasks like title generation
 pub fn get_openai_endpoint(
-    config: &crate::config::Config,
-    _model_id: &str,
+    state: &web::Data<AppState>,
+    model_id: &str,
 ) -> Result<(String, String, serde_json::Value), AppError> {
-    // Find first enabled endpoint
-    for (idx, endpoint_url) in config.openai_api_base_urls.iter().enumerate() {
-        let api_cfg = config
-            .openai_api_configs
-            .get(idx.to_string())
-            .or_else(|| config.openai_api_configs.get(endpoint_url))
-            .and_then(|v| v.as_object())
-            .cloned()
-            .unwrap_or_default();
-
-        let enabled = api_cfg
-            .get("enable")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(true);
-
-        if enabled {
-            let endpoint_key = config
-                .openai_api_keys
-                .get(idx)
-                .map(|s| s.to_string())
-                .unwrap_or_default();
-            return Ok((
-                endpoint_url.clone(),
-                endpoint_key,
-                serde_json::json!(api_cfg),
-            ));
-        }
-    }
-
-    Err(AppError::NotFound(
-        "No enabled OpenAI endpoint configured".to_string(),
-    ))
+    let config = state.config.snapshot();
+    get_endpoint_from_cache_or_config(
+        state,
+        &config,
+        model_id,
+        &serde_json::json!({}),
+        &serde_json::json!({}),
+    )
+    .map(|(_, url, key, api_config)| (url, key, api_config))
 }
 
-/// Helper function to get endpoint from cache or config (non-direct routing)
+/// Helper function to get endpoint from cache or config (non-direct routing), returning
+/// the endpoint's urlIdx along with its url, key and config
 fn get_endpoint_from_cache_or_config(
     state: &web::Data<AppState>,
     config: &crate::config::Config,
     model_id: &str,
     model_item: &serde_json::Value,
     payload_obj: &serde_json::Value,
-) -> Result<(String, String, serde_json::Value), AppError> {
+) -> Result<(usize, String, String, serde_json::Value), AppError> {
     // Try to get urlIdx from model_item, payload, or cache
     let url_idx = model_item
         .get("urlIdx")
@@ -495,7 +563,7 @@ fn get_endpoint_from_cache_or_config(
         selected_idx,
         model_id
     );
-    Ok((endpoint_url, endpoint_key, api_cfg))
+    Ok((selected_idx, endpoint_url, endpoint_key, api_cfg))
 }
 
 // Verify connection endpoint - test OpenAI API connection
@@ -508,7 +576,7 @@ struct VerifyConnectionRequest {
 }
 
 async fn verify_connection(
-    _state: web::Data<AppState>,
+    state: web::Data<AppState>,
     _auth_user: AuthUser,
     payload: web::Json<VerifyConnectionRequest>,
 ) -> Result<HttpResponse, AppError> {
@@ -520,7 +588,7 @@ async fn verify_connection(
         payload.config.clone()
     };
 
-    let client = reqwest::Client::new();
+    let client = http_client::client(&state.config.snapshot());
 
     // Check if it's Azure
     let is_azure = api_config
@@ -547,7 +615,7 @@ async fn verify_connection(
             request_builder = request_builder.header("api-key", key);
         }
 
-        match request_builder.send().await {
+        match request_builder.with_request_id().send().await {
             Ok(response) => {
                 let status_code = response.status().as_u16();
                 if let Ok(json_body) = response.json::<serde_json::Value>().await {
@@ -570,13 +638,17 @@ async fn verify_connection(
             ))),
         }
     } else {
-        // Regular OpenAI endpoint
-        match client
-            .get(format!("{}/models", url))
-            .header("Authorization", format!("Bearer {}", key))
-            .send()
-            .await
-        {
+        // Regular OpenAI endpoint, or an Anthropic or Gemini one with its own auth headers
+        let request = client.get(format!("{}/models", url));
+        let request = if anthropic::is_anthropic(&api_config) {
+            anthropic::with_auth(request, key)
+        } else if gemini::is_gemini(&api_config) {
+            gemini::with_auth(request, key)
+        } else {
+            request.header("Authorization", format!("Bearer {}", key))
+        };
+        let span = telemetry::upstream_span("list_models", url, None);
+        match telemetry::send_traced(request, span).await {
             Ok(response) => {
                 let status_code = response.status().as_u16();
                 if let Ok(json_body) = response.json::<serde_json::Value>().await {
@@ -609,7 +681,7 @@ async fn audio_speech(
     body: web::Bytes,
 ) -> Result<HttpResponse, AppError> {
     // Try to find OpenAI endpoint
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     let openai_idx = config
         .openai_api_base_urls
@@ -638,14 +710,13 @@ async fn audio_speech(
         .cloned()
         .unwrap_or(serde_json::json!({}));
 
-    drop(config); // Release lock
+    let cache_dir = config.speech_cache_dir();
 
     // Calculate hash for caching
     let hash = format!("{:x}", md5::compute(&body));
 
     // Check cache directory
-    let cache_dir = std::path::Path::new("./data/cache/audio/speech");
-    if let Err(e) = std::fs::create_dir_all(cache_dir) {
+    if let Err(e) = std::fs::create_dir_all(&cache_dir) {
         tracing::warn!("Failed to create cache directory: {}", e);
     }
 
@@ -661,7 +732,7 @@ async fn audio_speech(
     }
 
     // Make request to OpenAI
-    let client = reqwest::Client::new();
+    let client = http_client::client(&state.config.snapshot());
     let mut request_builder = client
         .post(format!("{}/audio/speech", url))
         .header("Content-Type", "application/json")
@@ -676,7 +747,7 @@ async fn audio_speech(
         request_builder = request_builder.header("Authorization", format!("Bearer {}", key));
     }
 
-    match request_builder.send().await {
+    match request_builder.with_request_id().send().await {
         Ok(response) if response.status().is_success() => {
             let audio_bytes = response.bytes().await.map_err(|e| {
                 AppError::InternalServerError(format!("Failed to read audio response: {}", e))
@@ -712,7 +783,7 @@ async fn embeddings_endpoint(
     auth_user: AuthUser,
     payload: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_openai_api {
         return Err(AppError::NotImplemented(
@@ -753,10 +824,10 @@ async fn embeddings_endpoint(
         .cloned()
         .unwrap_or(serde_json::json!({}));
 
-    drop(config);
+    let span = telemetry::upstream_span("embeddings", &url, Some(model_id));
 
     // Make request
-    let client = reqwest::Client::new();
+    let client = http_client::client(&state.config.snapshot());
     let mut request_builder = client
         .post(format!("{}/embeddings", url))
         .header("Content-Type", "application/json")
@@ -771,7 +842,7 @@ async fn embeddings_endpoint(
         request_builder = request_builder.header("Authorization", format!("Bearer {}", key));
     }
 
-    match request_builder.send().await {
+    match telemetry::send_traced(request_builder, span).await {
         Ok(response) if response.status().is_success() => {
             let json_response = response.json::<serde_json::Value>().await.map_err(|e| {
                 AppError::InternalServerError(format!("Failed to parse response: {}", e))
@@ -793,6 +864,205 @@ async fn embeddings_endpoint(
     }
 }
 
+/// Ask for usage on the final chunk of a streamed response so consumption can be counted
+pub(crate) fn request_stream_usage(payload: &mut serde_json::Value) {
+    let is_stream = payload
+        .get("stream")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if !is_stream {
+        return;
+    }
+    if let Some(obj) = payload.as_object_mut() {
+        let stream_options = obj
+            .entry("stream_options")
+            .or_insert_with(|| serde_json::json!({}));
+        if let Some(opts) = stream_options.as_object_mut() {
+            opts.entry("include_usage")
+                .or_insert(serde_json::json!(true));
+        }
+    }
+}
+
+/// Connection serving `model_id` when it implements the legacy `/completions` endpoint,
+/// as `(base_url, api_key, api_config)`
+fn legacy_completions_upstream(
+    state: &AppState,
+    model_id: &str,
+) -> Option<(String, String, serde_json::Value)> {
+    let idx = state
+        .models_cache
+        .read()
+        .unwrap()
+        .get(model_id)
+        .and_then(|model| model.get("urlIdx"))
+        .and_then(|v| v.as_u64())? as usize;
+
+    let config = state.config.snapshot();
+    let url = config.openai_api_base_urls.get(idx)?.clone();
+    let api_config = config
+        .openai_api_configs
+        .get(idx.to_string())
+        .or_else(|| config.openai_api_configs.get(&url))
+        .cloned()
+        .unwrap_or(serde_json::json!({}));
+
+    let supported = api_config
+        .get("legacy_completions")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let is_azure = api_config
+        .get("azure")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if !supported || is_azure {
+        return None;
+    }
+
+    let key = config.openai_api_keys.get(idx).cloned().unwrap_or_default();
+    Some((url, key, api_config))
+}
+
+/// Legacy `/completions` endpoint for clients that still send a `prompt` string.
+///
+/// Requests for models on connections flagged with `legacy_completions` are proxied
+/// unchanged, after the same model access and token budget checks as chat requests;
+/// everything else is sent through chat completions with the prompt as a
+/// user message, and the response (streamed or not) is converted back.
+async fn completions(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    payload: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    if !state.config.snapshot().enable_openai_legacy_completions {
+        return Err(AppError::NotImplemented(
+            "Legacy completions are disabled, use /chat/completions instead".to_string(),
+        ));
+    }
+
+    let mut payload = payload.into_inner();
+    let model_id = payload
+        .get("model")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| AppError::BadRequest("Model ID is required".to_string()))?
+        .to_string();
+
+    if let Some((url, key, api_config)) = legacy_completions_upstream(&state, &model_id) {
+        if check_model_request(&state, &auth_user, &model_id).await? {
+            request_stream_usage(&mut payload);
+        }
+
+        let auth_type = api_config
+            .get("auth_type")
+            .and_then(|v| v.as_str())
+            .unwrap_or("bearer");
+
+        let mut request_builder = state
+            .http_client
+            .post(format!("{}/completions", url.trim_end_matches('/')))
+            .json(&payload);
+        if auth_type != "none" && !key.is_empty() {
+            request_builder = request_builder.header("Authorization", format!("Bearer {}", key));
+        }
+
+        let response = request_builder
+            .with_request_id()
+            .send()
+            .await
+            .map_err(|e| AppError::InternalServerError(format!("Request error: {}", e)))?;
+        let status = actix_web::http::StatusCode::from_u16(response.status().as_u16())
+            .unwrap_or(actix_web::http::StatusCode::BAD_GATEWAY);
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("application/json")
+            .to_string();
+
+        if content_type.contains("text/event-stream") {
+            let mut usage = chat_completion::SseUsageRecorder::new(
+                state.db.clone(),
+                auth_user.user.id.clone(),
+                model_id.clone(),
+            );
+            let stream = response.bytes_stream().map(move |result| {
+                if let Ok(bytes) = &result {
+                    usage.feed(bytes);
+                }
+                result.map_err(actix_web::error::ErrorBadGateway)
+            });
+            return Ok(HttpResponse::build(status)
+                .content_type(content_type)
+                .streaming(stream));
+        }
+
+        let body = response.bytes().await.map_err(|e| {
+            AppError::InternalServerError(format!("Failed to read response: {}", e))
+        })?;
+        let usage = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|response| response.get("usage").cloned());
+        if let Some(usage) = usage {
+            chat_completion::record_token_usage(&state.db, &auth_user.user.id, &model_id, &usage)
+                .await;
+        }
+        return Ok(HttpResponse::build(status)
+            .content_type(content_type)
+            .body(body));
+    }
+
+    let prompt = legacy_completions::prompt_text(&payload)?;
+    let echo = payload
+        .get("echo")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+        .then(|| prompt.clone());
+    let chat_payload = legacy_completions::to_chat_payload(&payload, &prompt);
+
+    let response = handle_chat_completions(state, auth_user, web::Json(chat_payload)).await?;
+    if !response.status().is_success() {
+        return Ok(response);
+    }
+
+    let status = response.status();
+    let is_stream = response
+        .headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.contains("text/event-stream"))
+        .unwrap_or(false);
+
+    if is_stream {
+        let mut body = response.into_body();
+        let mut converter = legacy_completions::CompletionStreamConverter::new(echo);
+        let stream = futures::stream::poll_fn(move |cx| {
+            actix_web::body::MessageBody::poll_next(std::pin::Pin::new(&mut body), cx)
+        })
+        .map(move |result| match result {
+            Ok(bytes) => Ok::<_, actix_web::Error>(bytes::Bytes::from(converter.push(&bytes))),
+            Err(e) => Err(actix_web::error::ErrorInternalServerError(e.to_string())),
+        });
+
+        return Ok(HttpResponse::build(status)
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            .streaming(stream));
+    }
+
+    let body = actix_web::body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to read response: {}", e)))?;
+    let chat_response: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InternalServerError(format!("Failed to parse response: {}", e)))?;
+
+    Ok(
+        HttpResponse::build(status).json(legacy_completions::chat_response_to_completion(
+            &chat_response,
+            echo.as_deref(),
+        )),
+    )
+}
+
 // Proxy endpoint - catch-all for other OpenAI API paths
 async fn proxy_request(
     state: web::Data<AppState>,
@@ -801,7 +1071,7 @@ async fn proxy_request(
     path: web::Path<String>,
     body: web::Bytes,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     let idx = 0; // Default to first endpoint
 
@@ -825,8 +1095,6 @@ async fn proxy_request(
         .cloned()
         .unwrap_or(serde_json::json!({}));
 
-    drop(config);
-
     let request_path = path.into_inner();
     let method = req.method().clone();
 
@@ -836,7 +1104,7 @@ async fn proxy_request(
         .and_then(|v| v.as_bool())
         .unwrap_or(false);
 
-    let client = reqwest::Client::new();
+    let client = http_client::client(&state.config.snapshot());
     let request_url = if is_azure {
         let api_version = api_config
             .get("api_version")
@@ -892,7 +1160,7 @@ async fn proxy_request(
         request_builder = request_builder.header("Authorization", format!("Bearer {}", key));
     }
 
-    match request_builder.send().await {
+    match request_builder.with_request_id().send().await {
         Ok(response) => {
             let status_code = response.status().as_u16();
             let actix_status = actix_web::http::StatusCode::from_u16(status_code)
@@ -940,40 +1208,170 @@ async fn chat_completions(
     handle_chat_completions(state, auth_user, payload).await
 }
 
+/// Fields only the web UI sends, dropped from `/v1` requests so external clients always
+/// get a plain JSON or SSE response instead of Socket.IO events
+const FRONTEND_ONLY_FIELDS: &[&str] = &[
+    "session_id",
+    "chat_id",
+    "id",
+    "model_item",
+    "background_tasks",
+];
+
+/// Chat completions for OpenAI SDK clients authenticated with an API key
+///
+/// The model must be one the key's owner can see in `/v1/models`; its connection is
+/// taken from the model list, where the web UI sends it as `model_item`.
+pub async fn chat_completions_v1(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    payload: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let mut payload = payload.into_inner();
+    let model_id = payload
+        .get("model")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| AppError::BadRequest("Model ID is required".to_string()))?
+        .to_string();
+
+    let model = accessible_model(&state, &auth_user, &model_id).await?;
+
+    let url_idx = model
+        .info
+        .as_ref()
+        .and_then(|info| info.params.as_ref())
+        .and_then(|params| params.get("urlIdx"))
+        .cloned();
+    to_v1_payload(&mut payload, url_idx)?;
+
+    handle_chat_completions(state, auth_user, web::Json(payload)).await
+}
+
+/// A model the user can see in `/v1/models`; any other model is reported as not found
+async fn accessible_model(
+    state: &AppState,
+    auth_user: &AuthUser,
+    model_id: &str,
+) -> Result<crate::services::models::Model, AppError> {
+    let model_service = ModelService::new(state.config.snapshot().as_ref().clone());
+    let user_group_ids: HashSet<String> = GroupService::new(&state.db)
+        .get_groups_by_member_id(&auth_user.user.id)
+        .await?
+        .into_iter()
+        .map(|g| g.id)
+        .collect();
+    model_service
+        .get_all_models(&state.db)
+        .await?
+        .into_iter()
+        .find(|m| m.id == model_id)
+        .filter(|m| {
+            model_service.check_model_access(
+                m,
+                &auth_user.user.id,
+                &auth_user.user.role,
+                &user_group_ids,
+            )
+        })
+        .ok_or_else(|| AppError::NotFound(format!("Model '{}' not found", model_id)))
+}
+
+/// Model access and token budget checks for requests that are sent upstream without
+/// going through [`handle_chat_completions`]; returns whether the budget is enforced,
+/// in which case streamed responses should report their usage
+pub(crate) async fn check_model_request(
+    state: &AppState,
+    auth_user: &AuthUser,
+    model_id: &str,
+) -> Result<bool, AppError> {
+    accessible_model(state, auth_user, model_id).await?;
+
+    let token_budget = {
+        let config = state.config.snapshot();
+        crate::services::usage::TokenBudget::from_config(&config)
+    };
+    let enforce_budget = token_budget.is_enabled() && auth_user.user.role != "admin";
+    if enforce_budget {
+        crate::services::usage::UsageService::new(&state.db)
+            .check_budget(&token_budget, &auth_user.user.id, model_id)
+            .await?;
+    }
+    Ok(enforce_budget)
+}
+
+/// Strip web UI fields from a `/v1` chat request and route it to the model's connection
+fn to_v1_payload(
+    payload: &mut serde_json::Value,
+    url_idx: Option<serde_json::Value>,
+) -> Result<(), AppError> {
+    let obj = payload
+        .as_object_mut()
+        .ok_or_else(|| AppError::BadRequest("Request body must be a JSON object".to_string()))?;
+    for field in FRONTEND_ONLY_FIELDS {
+        obj.remove(*field);
+    }
+    if let Some(url_idx) = url_idx {
+        obj.insert(
+            "model_item".to_string(),
+            serde_json::json!({ "urlIdx": url_idx }),
+        );
+    }
+    Ok(())
+}
+
 /// Process streaming response and emit events via Socket.IO (wrapper function)
 /// Delegates to chat_completion module for actual implementation
 async fn process_streaming_via_socketio(
     response: reqwest::Response,
     state: &web::Data<AppState>,
-    user_id: &str,
+    user: UserContext,
     model_id: String,
     messages: Vec<serde_json::Value>,
     chat_id: Option<String>,
     message_id: Option<String>,
     session_id: Option<String>,
     should_generate_title: bool,
+    should_generate_tags: bool,
+    should_generate_follow_ups: bool,
     model_item: serde_json::Value,
     endpoint_url: String,
     endpoint_key: String,
+    endpoint_config: serde_json::Value,
     tool_ids: Vec<String>,
     tool_specs: Vec<serde_json::Value>,
+    sources: Vec<serde_json::Value>,
+    citations: Vec<serde_json::Value>,
+    arena_model_id: Option<String>,
+    validate_json: bool,
+    temporary: bool,
+    delta_chunk_size: usize,
+    cancel: tokio_util::sync::CancellationToken,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Create streaming context
     let context = StreamingContext {
         state: state.clone(),
-        user_id: user_id.to_string(),
+        user,
         model_id,
         messages,
         chat_id,
         message_id,
         session_id,
         should_generate_title,
+        should_generate_tags,
+        should_generate_follow_ups,
         model_item,
         endpoint_url,
         endpoint_key,
+        endpoint_config,
         tool_ids,
         tool_specs,
-        delta_chunk_size: None, // TODO: Extract from request params when frontend supports it
+        sources,
+        citations,
+        delta_chunk_size: Some(delta_chunk_size),
+        arena_model_id,
+        validate_json,
+        temporary,
+        cancel,
     };
 
     // Delegate to chat_completion module
@@ -997,7 +1395,7 @@ pub async fn handle_chat_completions(
 ) -> Result<HttpResponse, AppError> {
     // Check if OpenAI API is enabled
     let enable_openai_api = {
-        let config = state.config.read().unwrap();
+        let config = state.config.snapshot();
         config.enable_openai_api
     };
 
@@ -1007,20 +1405,98 @@ pub async fn handle_chat_completions(
         ));
     }
 
-    // Get model ID from payload
-    let model_id = payload
+    // Streams already running are being drained for shutdown
+    if state.tasks.is_draining() {
+        return Err(AppError::ServiceUnavailable(
+            "Server is shutting down".to_string(),
+        ));
+    }
+
+    // Check the request before routing it; the cleaned payload is what gets forwarded
+    let mut limits = chat_request::RequestLimits::from_config(&state.config.snapshot());
+    let requested_model = payload
         .get("model")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| AppError::BadRequest("Model ID is required".to_string()))?
+        .unwrap_or("")
         .to_string();
+    // A custom model's system prompt, with the model's name for CURRENT_MODEL
+    let mut model_system_prompt: Option<(String, String)> = None;
+    if let Some(model) = crate::services::model::ModelService::new(&state.db)
+        .get_model_by_id(&requested_model)
+        .await?
+    {
+        limits = limits.with_model_params(&model.params);
+        model_system_prompt = model
+            .params
+            .get("system")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.trim().is_empty())
+            .map(|system| (system.to_string(), model.name.clone()));
+    }
+    let chat_request::ValidatedRequest {
+        payload,
+        warnings: request_warnings,
+    } = chat_request::validate_chat_request(payload.into_inner(), &limits)?;
+    for warning in &request_warnings {
+        tracing::warn!("Chat completion request: {}", warning);
+    }
+
+    let mut model_id = requested_model;
+
+    // Arena models answer with a random pick from their candidate pool
+    let mut arena_model_id: Option<String> = None;
+    let arena_model_service = {
+        let config = state.config.snapshot();
+        config
+            .enable_evaluation_arena_models
+            .then(|| crate::services::models::ModelService::new(config.as_ref().clone()))
+    };
+    if let Some(model_service) = arena_model_service.filter(|s| s.is_arena_model(&model_id)) {
+        let user_group_ids: std::collections::HashSet<String> =
+            crate::services::group::GroupService::new(&state.db)
+                .get_groups_by_member_id(&auth_user.user.id)
+                .await?
+                .into_iter()
+                .map(|g| g.id)
+                .collect();
+        let selected = model_service
+            .select_arena_model(
+                &state.db,
+                &model_id,
+                &auth_user.user.id,
+                &auth_user.user.role,
+                &user_group_ids,
+            )
+            .await?;
+        tracing::info!("Arena model {} selected {}", model_id, selected);
+        arena_model_id = Some(std::mem::replace(&mut model_id, selected));
+    }
+
+    // Enforce token budgets before doing any upstream work
+    let token_budget = {
+        let config = state.config.snapshot();
+        crate::services::usage::TokenBudget::from_config(&config)
+    };
+    let enforce_budget = token_budget.is_enabled() && auth_user.user.role != "admin";
+    if enforce_budget {
+        crate::services::usage::UsageService::new(&state.db)
+            .check_budget(&token_budget, &auth_user.user.id, &model_id)
+            .await?;
+    }
 
     // Extract model_item from payload (matching Python's behavior exactly)
-    let mut payload_obj = payload.into_inner();
-    let model_item = payload_obj
+    let mut payload_obj = payload;
+    let mut model_item = payload_obj
         .as_object_mut()
         .and_then(|obj| obj.remove("model_item"))
         .unwrap_or(serde_json::json!({}));
 
+    if arena_model_id.is_some() {
+        // Route as the selected model; the arena's own model_item has no connection info
+        payload_obj["model"] = serde_json::json!(model_id);
+        model_item = serde_json::json!({});
+    }
+
     // Extract Socket.IO streaming metadata from top-level (frontend sends these at root level)
     let session_id = payload_obj
         .get("session_id")
@@ -1035,6 +1511,54 @@ pub async fn handle_chat_completions(
         .and_then(|v| v.as_str())
         .map(String::from);
 
+    // Temporary chats stream like any other but are never saved; groups may be limited to them
+    let temporary = if chat_completion::is_temporary_chat(chat_id.as_deref(), &payload_obj) {
+        true
+    } else {
+        let defaults = state.config.snapshot().user_permissions.clone();
+        let user_permissions =
+            permissions::get_user_permissions(&state.db, &defaults, &auth_user.user.id).await?;
+        permissions::temporary_chat_access(&user_permissions, &auth_user.user.role).1
+    };
+
+    // Content deltas per Socket.IO event; the request's params override the configured size
+    let delta_chunk_size = payload_obj
+        .pointer("/params/stream_delta_chunk_size")
+        .and_then(|v| v.as_u64())
+        .filter(|size| *size > 0)
+        .map_or_else(
+            || {
+                state
+                    .config
+                    .snapshot()
+                    .chat_response_stream_delta_chunk_size
+            },
+            |size| size as usize,
+        );
+
+    // Record the arena pick on the assistant message so feedback can credit the right model
+    let persisted_chat = chat_id.as_ref().filter(|_| !temporary);
+    if let (Some(arena_id), Some(cid), Some(mid)) = (&arena_model_id, persisted_chat, &message_id) {
+        if let Err(e) = crate::services::chat::ChatService::new(&state.db)
+            .upsert_message_to_chat(
+                cid,
+                mid,
+                serde_json::json!({
+                    "model": arena_id,
+                    "selectedModelId": model_id,
+                    "arena": true,
+                }),
+            )
+            .await
+        {
+            tracing::warn!(
+                "Failed to record arena selection for message {}: {}",
+                mid,
+                e
+            );
+        }
+    }
+
     // Extract background_tasks for title generation, etc.
     let background_tasks = payload_obj
         .as_object_mut()
@@ -1046,6 +1570,16 @@ pub async fn handle_chat_completions(
         .and_then(|v| v.as_bool())
         .unwrap_or(false);
 
+    let should_generate_tags = background_tasks
+        .get("tags_generation")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let should_generate_follow_ups = background_tasks
+        .get("follow_up_generation")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
     // Extract messages for title generation before removing from payload
     let messages = payload_obj
         .get("messages")
@@ -1053,6 +1587,14 @@ pub async fn handle_chat_completions(
         .cloned()
         .unwrap_or_default();
 
+    // The custom model's system prompt, with its variables filled in for this user
+    if let Some((system_prompt, model_name)) = &model_system_prompt {
+        let variables = prompt_variables::PromptVariableContext::for_user(&auth_user.user, None)
+            .with_model(model_name);
+        let rendered = prompt_variables::render_prompt_variables(system_prompt, &variables);
+        prompt_variables::add_model_system_prompt(&mut payload_obj, &rendered.content);
+    }
+
     // Extract tool_ids BEFORE removing from payload
     let tool_ids = payload_obj
         .get("tool_ids")
@@ -1072,8 +1614,23 @@ pub async fn handle_chat_completions(
         tracing::info!("🔧 Tools requested in chat: {}", tool_ids.join(", "));
     }
 
+    // Web search can be requested via features.web_search or a top-level web_search flag
+    let web_search_requested = payload_obj
+        .get("features")
+        .and_then(|f| f.get("web_search"))
+        .or_else(|| payload_obj.get("web_search"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let memory_requested = payload_obj
+        .get("features")
+        .and_then(|f| f.get("memory"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
     // Remove these from payload before forwarding to LLM API
     if let Some(obj) = payload_obj.as_object_mut() {
+        obj.remove("web_search");
         obj.remove("session_id");
         obj.remove("chat_id");
         obj.remove("id");
@@ -1088,6 +1645,8 @@ pub async fn handle_chat_completions(
 
     // Prepare tool specs storage (moved outside if block for later use)
     let mut all_tool_specs = Vec::new();
+    // Only tools the user may use are run, as they get the user's details
+    let mut granted_tool_ids = Vec::new();
 
     // Load and inject tools if tool_ids are provided
     if !tool_ids.is_empty() {
@@ -1133,6 +1692,7 @@ pub async fn handle_chat_completions(
                             continue;
                         }
                     }
+                    granted_tool_ids.push(tool_id.clone());
 
                     // Parse tool definition and extract OpenAI specs
                     match ToolDefinition::from_json(&tool.content) {
@@ -1293,38 +1853,6 @@ pub async fn handle_chat_completions(
                             sources.len(),
                             unique_ids.len()
                         );
-
-                        // Inject sources into messages if we have any
-                        if !sources.is_empty() {
-                            // Get RAG template from config
-                            let rag_template = {
-                                let config = state.config.read().unwrap();
-                                config.rag_template.clone()
-                            };
-
-                            // Get mutable reference to messages array
-                            if let Some(messages_value) = payload_obj.get_mut("messages") {
-                                if let Some(messages_array) = messages_value.as_array_mut() {
-                                    match crate::utils::retrieval::inject_sources_into_messages(
-                                        sources.clone(),
-                                        messages_array,
-                                        &rag_template,
-                                    ) {
-                                        Ok(_) => {
-                                            tracing::info!(
-                                                "✅ Successfully injected RAG context into user message"
-                                            );
-                                        }
-                                        Err(e) => {
-                                            tracing::error!(
-                                                "❌ Failed to inject RAG context: {}",
-                                                e
-                                            );
-                                        }
-                                    }
-                                }
-                            }
-                        }
                     }
                     Err(e) => {
                         tracing::error!("❌ Failed to extract sources from file items: {}", e);
@@ -1336,6 +1864,114 @@ pub async fn handle_chat_completions(
         tracing::debug!("ℹ️  No file attachments in this chat completion request");
     }
 
+    // Run web search and add the most relevant passages as sources
+    let enable_web_search = {
+        let config = state.config.snapshot();
+        config.enable_web_search
+    };
+    if web_search_requested && enable_web_search {
+        match crate::utils::retrieval::get_last_user_message(&messages) {
+            Some(query) => {
+                match crate::utils::web_search::get_web_search_sources(&state, &query).await {
+                    Ok(web_sources) => {
+                        tracing::info!("🔎 Adding {} web search source(s)", web_sources.len());
+                        sources.extend(web_sources);
+                    }
+                    Err(e) => {
+                        tracing::error!("❌ Web search failed: {}", e);
+                    }
+                }
+            }
+            None => {
+                tracing::warn!("Web search requested but no user message found");
+            }
+        }
+    } else if web_search_requested {
+        tracing::warn!("Web search requested but ENABLE_WEB_SEARCH is disabled");
+    }
+
+    // Leave out documents that aren't relevant enough, so they don't dilute the context
+    let relevance_filter =
+        crate::utils::retrieval::RelevanceFilter::from_config(&state.config.snapshot());
+    let sources = crate::utils::retrieval::filter_sources(sources, &relevance_filter);
+
+    let citation_options =
+        crate::utils::retrieval::CitationOptions::from_config(&state.config.snapshot());
+
+    // Inject sources into messages if we have any
+    if !sources.is_empty() {
+        // Get RAG template from config
+        let rag_template = {
+            let config = state.config.snapshot();
+            config.rag_template.clone()
+        };
+
+        // Get mutable reference to messages array
+        if let Some(messages_array) = payload_obj
+            .get_mut("messages")
+            .and_then(|m| m.as_array_mut())
+        {
+            match crate::utils::retrieval::inject_sources_into_messages(
+                sources.clone(),
+                messages_array,
+                &rag_template,
+                &citation_options,
+            ) {
+                Ok(_) => {
+                    tracing::info!("✅ Successfully injected RAG context into user message");
+                }
+                Err(e) => {
+                    tracing::error!("❌ Failed to inject RAG context: {}", e);
+                }
+            }
+        }
+    }
+
+    // Citations for the saved message and the response
+    let mut citation_sources =
+        crate::utils::retrieval::sources_for_response(&sources, &citation_options);
+    let citations = crate::utils::retrieval::build_citations(&sources, &citation_options);
+
+    // Add the user's most relevant memories to the system context
+    if memory_requested {
+        use crate::routes::{knowledge_vector, memory_vector};
+
+        let query = crate::utils::retrieval::get_last_user_message(&messages);
+        let components =
+            knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider);
+        match (query, components) {
+            (Some(query), Some((vector_db, embedding_provider))) => {
+                match memory_vector::search_memories(
+                    &vector_db,
+                    &embedding_provider,
+                    &auth_user.user.id,
+                    &query,
+                    memory_vector::MEMORY_CONTEXT_TOP_K,
+                )
+                .await
+                {
+                    Ok(matches) if !matches.is_empty() => {
+                        if let Some(messages_array) = payload_obj
+                            .get_mut("messages")
+                            .and_then(|m| m.as_array_mut())
+                        {
+                            let context = memory_vector::format_memory_context(&matches);
+                            memory_vector::add_memory_context_to_messages(messages_array, &context);
+                        }
+                        citation_sources.push(memory_vector::memory_citation_source(&matches));
+                        tracing::info!("🧠 Added {} memory item(s) to context", matches.len());
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::error!("❌ Memory retrieval failed: {}", e);
+                    }
+                }
+            }
+            (_, None) => knowledge_vector::log_rag_disabled("memory retrieval"),
+            (None, _) => {}
+        }
+    }
+
     tracing::debug!(
         "Chat completion request - model_id: {}, model_item: {}",
         model_id,
@@ -1356,8 +1992,10 @@ pub async fn handle_chat_completions(
     );
 
     // If direct connections not enabled, just use regular OpenAI routing
-    let config = state.config.read().unwrap();
-    let (url, key, api_config) = if is_direct && config.enable_direct_connections {
+    let config = state.config.snapshot();
+    // Only global endpoints (Some(urlIdx)) are guarded by a circuit breaker; direct
+    // connections belong to individual users
+    let (url, key, api_config, endpoint_idx) = if is_direct && config.enable_direct_connections {
         // Direct connection - look up URL and key from user settings using urlIdx
         // The frontend sends urlIdx which points to the user's directConnections settings
 
@@ -1382,7 +2020,12 @@ pub async fn handle_chat_completions(
                 .cloned()
                 .unwrap_or(serde_json::json!({}));
 
-            (url_str.to_string(), direct_key.to_string(), item_config)
+            (
+                url_str.to_string(),
+                direct_key.to_string(),
+                item_config,
+                None,
+            )
         } else {
             // No explicit URL - look up from user settings using urlIdx
             let url_idx = if let Some(idx_str) = model_item.get("urlIdx").and_then(|v| v.as_str()) {
@@ -1400,101 +2043,51 @@ pub async fn handle_chat_completions(
                 url_idx
             );
 
-            // Get user settings to find their direct connections
-            tracing::debug!("User settings: {:?}", auth_user.user.settings);
-            let user_settings = auth_user.user.settings.as_ref()
-                .ok_or_else(|| AppError::BadRequest(
-                    "User settings not found. Please configure your direct connections in Settings > Connections.".to_string()
-                ))?;
-
-            // Direct connections can be at settings.directConnections OR settings.ui.directConnections
-            let direct_connections = user_settings.get("directConnections").or_else(|| {
-                user_settings
-                    .get("ui")
-                    .and_then(|ui| ui.get("directConnections"))
-            });
+            // The user's own connections come first, followed by the instance defaults
+            let connections =
+                DirectConnections::from_user_settings(auth_user.user.settings.as_ref())
+                    .merged_with(&DirectConnections::from_value(
+                        &config.default_direct_connections,
+                    ));
 
-            if direct_connections.is_none() {
+            if connections.is_empty() {
                 tracing::warn!(
-                    "Direct connections not configured for user {}. User settings structure: {:?}",
-                    auth_user.user.email,
-                    user_settings
+                    "Direct connections not configured for user {}",
+                    auth_user.user.email
                 );
                 return Err(AppError::BadRequest(
                     "Direct connections not configured. Please add your OpenAI API connections in Settings > Connections.".to_string()
                 ));
             }
 
-            let direct_connections = direct_connections.unwrap();
-
-            // Extract URL and key arrays from settings
-            tracing::debug!("Direct connections object: {:?}", direct_connections);
-
-            let urls = direct_connections.get("OPENAI_API_BASE_URLS")
-                .and_then(|v| v.as_array())
-                .ok_or_else(|| {
-                    tracing::error!("OPENAI_API_BASE_URLS missing or not an array in direct connections");
-                    AppError::BadRequest("Direct connections configuration is invalid. Please check your Settings > Connections.".to_string())
-                })?;
-
-            let keys = direct_connections.get("OPENAI_API_KEYS")
-                .and_then(|v| v.as_array())
-                .ok_or_else(|| {
-                    tracing::error!("OPENAI_API_KEYS missing or not an array in direct connections");
-                    AppError::BadRequest("Direct connections configuration is invalid. Please check your Settings > Connections.".to_string())
-                })?;
-
-            let configs = direct_connections.get("OPENAI_API_CONFIGS")
-                .and_then(|v| v.as_object())
-                .ok_or_else(|| {
-                    tracing::error!("OPENAI_API_CONFIGS missing or not an object in direct connections");
-                    AppError::BadRequest("Direct connections configuration is invalid. Please check your Settings > Connections.".to_string())
-                })?;
-
             // Parse urlIdx as number
             let idx: usize = url_idx
                 .parse()
                 .map_err(|_| AppError::BadRequest(format!("Invalid urlIdx: {}", url_idx)))?;
 
-            tracing::debug!(
-                "Looking up connection at index {} from {} total URLs",
-                idx,
-                urls.len()
-            );
-
-            // Get URL and key at that index
-            let connection_url = urls.get(idx)
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| {
-                    tracing::error!("No URL found at index {}. Available URLs: {} total", idx, urls.len());
+            let (connection_url, connection_key, connection_config) =
+                connections.get(idx).ok_or_else(|| {
+                    tracing::error!(
+                        "No URL found at index {}. Available URLs: {} total",
+                        idx,
+                        connections.base_urls.len()
+                    );
                     AppError::BadRequest(format!(
-                        "Connection not found at index {}. You have {} connection(s) configured. Please check your Settings > Connections.", 
-                        idx, urls.len()
+                        "Connection not found at index {}. You have {} connection(s) configured. Please check your Settings > Connections.",
+                        idx,
+                        connections.base_urls.len()
                     ))
                 })?;
 
-            let connection_key = keys.get(idx).and_then(|v| v.as_str()).unwrap_or("");
-
-            // Get config for this connection (might be at string index)
-            let connection_config = configs
-                .get(&url_idx)
-                .or_else(|| configs.get(connection_url))
-                .cloned()
-                .unwrap_or(serde_json::json!({}));
-
             tracing::info!(
-                "Using direct connection from user settings: {} (idx: {}) for model {} by user {}",
+                "Using direct connection: {} (idx: {}) for model {} by user {}",
                 connection_url,
                 idx,
                 model_id,
                 auth_user.user.email
             );
 
-            (
-                connection_url.to_string(),
-                connection_key.to_string(),
-                connection_config,
-            )
+            (connection_url, connection_key, connection_config, None)
         }
     } else if is_direct && !config.enable_direct_connections {
         // Direct requested but not enabled - return error message
@@ -1503,106 +2096,58 @@ pub async fn handle_chat_completions(
         ));
     } else {
         // Regular (non-direct) routing
-        // SMART FALLBACK: If user has direct connections configured, use their first one
-        // This allows chat/notes to work even when frontend doesn't pass model_item
-        if config.enable_direct_connections {
-            if let Some(user_settings) = auth_user.user.settings.as_ref() {
-                let direct_connections = user_settings.get("directConnections").or_else(|| {
-                    user_settings
-                        .get("ui")
-                        .and_then(|ui| ui.get("directConnections"))
-                });
-
-                if let Some(dc) = direct_connections {
-                    if let (Some(urls), Some(keys)) = (
-                        dc.get("OPENAI_API_BASE_URLS").and_then(|v| v.as_array()),
-                        dc.get("OPENAI_API_KEYS").and_then(|v| v.as_array()),
-                    ) {
-                        if !urls.is_empty() {
-                            let first_url = urls[0].as_str().unwrap_or("");
-                            let first_key = keys.get(0).and_then(|k| k.as_str()).unwrap_or("");
-
-                            if !first_url.is_empty() {
-                                let configs =
-                                    dc.get("OPENAI_API_CONFIGS").and_then(|v| v.as_object());
-
-                                let first_config = configs
-                                    .and_then(|c| c.get("0").or_else(|| c.get(first_url)))
-                                    .cloned()
-                                    .unwrap_or(serde_json::json!({}));
-
-                                tracing::info!(
-                                    "Chat using user's first direct connection (smart fallback): {} for model {} by user {}",
-                                    first_url,
-                                    model_id,
-                                    auth_user.user.email
-                                );
-
-                                (first_url.to_string(), first_key.to_string(), first_config)
-                            } else {
-                                // Empty URL, fall back to global config
-                                get_endpoint_from_cache_or_config(
-                                    &state,
-                                    &config,
-                                    &model_id,
-                                    &model_item,
-                                    &payload_obj,
-                                )?
-                            }
-                        } else {
-                            // No URLs configured, fall back to global config
-                            get_endpoint_from_cache_or_config(
-                                &state,
-                                &config,
-                                &model_id,
-                                &model_item,
-                                &payload_obj,
-                            )?
-                        }
-                    } else {
-                        // Invalid structure, fall back to global config
-                        get_endpoint_from_cache_or_config(
-                            &state,
-                            &config,
-                            &model_id,
-                            &model_item,
-                            &payload_obj,
-                        )?
-                    }
-                } else {
-                    // No direct connections in settings, fall back to global config
+        // SMART FALLBACK: use the first direct connection, preferring the user's own over
+        // the instance defaults. This allows chat/notes to work even when frontend doesn't
+        // pass model_item
+        let fallback = if config.enable_direct_connections {
+            DirectConnections::from_user_settings(auth_user.user.settings.as_ref())
+                .merged_with(&DirectConnections::from_value(
+                    &config.default_direct_connections,
+                ))
+                .get(0)
+                .filter(|(first_url, _, _)| !first_url.is_empty())
+        } else {
+            None
+        };
+
+        match fallback {
+            Some((first_url, first_key, first_config)) => {
+                tracing::info!(
+                    "Chat using first direct connection (smart fallback): {} for model {} by user {}",
+                    first_url,
+                    model_id,
+                    auth_user.user.email
+                );
+                (first_url, first_key, first_config, None)
+            }
+            // No usable direct connection, fall back to global config
+            None => {
+                let (idx, endpoint_url, endpoint_key, endpoint_config) =
                     get_endpoint_from_cache_or_config(
                         &state,
                         &config,
                         &model_id,
                         &model_item,
                         &payload_obj,
-                    )?
-                }
-            } else {
-                // No user settings, fall back to global config
-                get_endpoint_from_cache_or_config(
-                    &state,
-                    &config,
-                    &model_id,
-                    &model_item,
-                    &payload_obj,
-                )?
+                    )?;
+                (endpoint_url, endpoint_key, endpoint_config, Some(idx))
             }
-        } else {
-            // Direct connections not enabled, use global config
-            get_endpoint_from_cache_or_config(
-                &state,
-                &config,
-                &model_id,
-                &model_item,
-                &payload_obj,
-            )?
         }
     };
 
+    // JSON mode is forwarded unchanged to upstreams that support it; others are asked
+    // for JSON in the system prompt and the streamed answer is validated when done
+    let validate_json = !response_format::supports_response_format(&api_config)
+        && response_format::apply_prompted_json(&mut payload_obj);
+    if validate_json {
+        tracing::debug!(
+            "Connection {} does not support response_format, prompting for JSON",
+            url
+        );
+    }
+
     // Prepare the request to the OpenAI-compatible endpoint
-    let client = reqwest::Client::new();
+    let client = http_client::client(&state.config.snapshot());
     let mut request_builder = client
         .post(format!("{}/chat/completions", url))
         .header("Content-Type", "application/json");
@@ -1626,9 +2171,41 @@ pub async fn handle_chat_completions(
         } // TODO: Add support for other auth types like "session", "system_oauth", "azure_ad"
     }
 
-    // Forward the modified payload (already extracted earlier)
+    if enforce_budget {
+        request_stream_usage(&mut payload_obj);
+    }
+
+    // Fail fast while the endpoint's circuit is open instead of waiting on a dead upstream
+    let breaker = match endpoint_idx {
+        Some(idx) => Some(state.upstream_breakers.acquire(idx, &url).await?),
+        None => None,
+    };
+
+    crate::utils::metrics::metrics().chat_completion(&model_id, &url);
 
-    match request_builder.json(&payload_obj).send().await {
+    // Forward the modified payload (already extracted earlier). Anthropic and Gemini
+    // connections get it translated to their own API and answer in the chat completion format.
+    let is_anthropic = anthropic::is_anthropic(&api_config);
+    let is_gemini = gemini::is_gemini(&api_config);
+    let request = if is_anthropic {
+        anthropic::messages_request(&client, &url, &key, &payload_obj)
+    } else if is_gemini {
+        gemini::generate_content_request(&client, &url, &key, &payload_obj)
+    } else {
+        request_builder.json(&payload_obj)
+    };
+    let span = telemetry::upstream_span("chat_completion", &url, Some(&model_id));
+    let result = telemetry::send_traced(request, span).await;
+    if let Some(breaker) = &breaker {
+        upstream::record_outcome(breaker, &result).await;
+    }
+    let result = match result {
+        Ok(response) if is_anthropic => anthropic::into_chat_completion_response(response).await,
+        Ok(response) if is_gemini => gemini::into_chat_completion_response(response).await,
+        other => other,
+    };
+
+    match result {
         Ok(response) if response.status().is_success() => {
             // Check if it's a streaming response
             let content_type = response
@@ -1669,55 +2246,148 @@ pub async fn handle_chat_completions(
 
                     // Spawn a task to process the stream via Socket.IO
                     let state_clone = state.clone();
-                    let user_id = auth_user.user.id.clone();
+                    let user = UserContext::from(&auth_user.user);
                     let session_id_owned = session_id.clone();
                     let model_id_owned = model_id.clone();
                     let messages_owned = messages.clone();
                     let should_generate_title_owned = should_generate_title;
+                    let should_generate_tags_owned = should_generate_tags;
+                    let should_generate_follow_ups_owned = should_generate_follow_ups;
                     let model_item_owned = model_item.clone();
                     let url_owned = url.clone();
                     let key_owned = key.clone();
-                    let tool_ids_owned = tool_ids.clone();
+                    let api_config_owned = api_config.clone();
+                    let tool_ids_owned = granted_tool_ids.clone();
                     let all_tool_specs_owned = all_tool_specs.clone();
-
-                    tokio::spawn(async move {
-                        if let Err(e) = process_streaming_via_socketio(
-                            response,
-                            &state_clone,
-                            &user_id,
-                            model_id_owned,
-                            messages_owned,
-                            chat_id,
-                            message_id,
-                            session_id_owned,
-                            should_generate_title_owned,
-                            model_item_owned,
-                            url_owned,
-                            key_owned,
-                            tool_ids_owned,
-                            all_tool_specs_owned,
+                    let citation_sources_owned = citation_sources.clone();
+                    let citations_owned = citations.clone();
+                    let arena_model_id_owned = arena_model_id.clone();
+                    let task_item_id = chat_id.clone();
+
+                    // Registered as a task so shutdown can drain it and the user can stop it
+                    let task_id = state
+                        .tasks
+                        .create_cancellable_task(
+                            &auth_user.user.id,
+                            task_item_id,
+                            move |cancel| async move {
+                                if let Err(e) = process_streaming_via_socketio(
+                                    response,
+                                    &state_clone,
+                                    user,
+                                    model_id_owned,
+                                    messages_owned,
+                                    chat_id,
+                                    message_id,
+                                    session_id_owned,
+                                    should_generate_title_owned,
+                                    should_generate_tags_owned,
+                                    should_generate_follow_ups_owned,
+                                    model_item_owned,
+                                    url_owned,
+                                    key_owned,
+                                    api_config_owned,
+                                    tool_ids_owned,
+                                    all_tool_specs_owned,
+                                    citation_sources_owned,
+                                    citations_owned,
+                                    arena_model_id_owned,
+                                    validate_json,
+                                    temporary,
+                                    delta_chunk_size,
+                                    cancel,
+                                )
+                                .await
+                                {
+                                    tracing::error!("Error processing Socket.IO stream: {}", e);
+                                }
+                            },
                         )
-                        .await
-                        {
-                            tracing::error!("Error processing Socket.IO stream: {}", e);
-                        }
-                    });
+                        .await?;
 
                     // Return an immediate success response
                     // The actual streaming happens via Socket.IO
-                    Ok(HttpResponse::Ok().json(serde_json::json!({
+                    let mut body = serde_json::json!({
                         "status": "streaming",
-                        "message": "Streaming via Socket.IO"
-                    })))
+                        "message": "Streaming via Socket.IO",
+                        "task_id": task_id
+                    });
+                    if arena_model_id.is_some() {
+                        body["selected_model_id"] = serde_json::json!(model_id);
+                    }
+                    Ok(HttpResponse::Ok().json(body))
                 } else {
                     // Use traditional HTTP SSE streaming (no Socket.IO)
                     tracing::debug!("Using HTTP SSE streaming (no Socket.IO metadata)");
-                    chat_completion::create_sse_stream(response)
+                    let usage = chat_completion::SseUsageRecorder::new(
+                        state.db.clone(),
+                        auth_user.user.id.clone(),
+                        model_id.clone(),
+                    );
+                    let mut sse_response =
+                        chat_completion::create_sse_stream(response, &model_id, Some(usage))?;
+                    if arena_model_id.is_some() {
+                        if let Ok(value) = actix_web::http::header::HeaderValue::from_str(&model_id)
+                        {
+                            sse_response.headers_mut().insert(
+                                actix_web::http::header::HeaderName::from_static(
+                                    "x-selected-model-id",
+                                ),
+                                value,
+                            );
+                        }
+                    }
+                    Ok(sse_response)
                 }
             } else {
                 // Return JSON response
                 tracing::debug!("Returning JSON response");
-                if let Ok(json_response) = response.json::<serde_json::Value>().await {
+                if let Ok(mut json_response) = response.json::<serde_json::Value>().await {
+                    if let Some(usage) = json_response.get("usage") {
+                        chat_completion::record_token_usage(
+                            &state.db,
+                            &auth_user.user.id,
+                            &model_id,
+                            usage,
+                        )
+                        .await;
+                    }
+                    if let Some(watermark) = chat_completion::ResponseWatermark::from_state(&state)
+                    {
+                        watermark.apply_to_response(&mut json_response);
+                    }
+                    if validate_json {
+                        let content = json_response
+                            .pointer("/choices/0/message/content")
+                            .and_then(|c| c.as_str())
+                            .unwrap_or_default();
+                        if !response_format::is_valid_json_output(content) {
+                            if let Some(obj) = json_response.as_object_mut() {
+                                obj.insert(
+                                    "warning".to_string(),
+                                    serde_json::json!(response_format::INVALID_JSON_ERROR),
+                                );
+                            }
+                        }
+                    }
+                    if !citation_sources.is_empty() {
+                        if let Some(obj) = json_response.as_object_mut() {
+                            obj.insert("sources".to_string(), serde_json::json!(citation_sources));
+                        }
+                    }
+                    if !request_warnings.is_empty() {
+                        if let Some(obj) = json_response.as_object_mut() {
+                            obj.insert("warnings".to_string(), serde_json::json!(request_warnings));
+                        }
+                    }
+                    if arena_model_id.is_some() {
+                        if let Some(obj) = json_response.as_object_mut() {
+                            obj.insert(
+                                "selected_model_id".to_string(),
+                                serde_json::json!(model_id),
+                            );
+                        }
+                    }
                     Ok(HttpResponse::Ok().json(json_response))
                 } else {
                     Err(AppError::InternalServerError(
@@ -1744,3 +2414,167 @@ pub async fn handle_chat_completions(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::middleware::{ApiKeyMiddleware, OpenAIErrors};
+    use crate::test_utils::{self, test_state};
+    use actix_web::test as actix_test;
+    use actix_web::{http::header, App, HttpServer};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_v1_payload_never_streams_over_socketio() {
+        let mut payload = json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "stream": true,
+            "session_id": "sid",
+            "chat_id": "chat-1",
+            "id": "msg-1",
+            "model_item": {"direct": true, "url": "http://attacker.example"},
+            "background_tasks": {"title_generation": true}
+        });
+        to_v1_payload(&mut payload, Some(json!(2))).unwrap();
+        assert_eq!(
+            payload,
+            json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "stream": true,
+                "model_item": {"urlIdx": 2}
+            })
+        );
+
+        let mut payload = json!({"model": "gpt-4o", "messages": []});
+        to_v1_payload(&mut payload, None).unwrap();
+        assert_eq!(payload, json!({"model": "gpt-4o", "messages": []}));
+
+        assert!(to_v1_payload(&mut json!(["not", "an", "object"]), None).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_v1_models_matches_openai_list_shape() {
+        // Upstream listing its models with extra fields the list must not leak
+        let upstream = HttpServer::new(|| {
+            App::new().route(
+                "/models",
+                web::get().to(|| async {
+                    HttpResponse::Ok().json(json!({
+                        "object": "list",
+                        "data": [
+                            {
+                                "id": "gpt-4o",
+                                "name": "GPT-4o",
+                                "created": 1715367049,
+                                "owned_by": "system"
+                            },
+                            {
+                                "id": "llama3:8b",
+                                "created": 1700000000,
+                                "owned_by": "ollama",
+                                "tags": []
+                            }
+                        ]
+                    }))
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let upstream_url = format!("http://{}", upstream.addrs()[0]);
+        actix_web::rt::spawn(upstream.run());
+
+        let mut config = Config::from_env().unwrap();
+        config.enable_openai_api = true;
+        config.openai_api_base_urls = vec![upstream_url];
+        config.openai_api_keys = vec!["sk-upstream".to_string()];
+        config.enable_evaluation_arena_models = false;
+        config.enable_api_key = true;
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir, config).await;
+        test_utils::create_user(&state.db, "user-1", "admin").await;
+        sqlx::query(r#"UPDATE "user" SET api_key = $1 WHERE id = $2"#)
+            .bind("sk-test")
+            .bind("user-1")
+            .execute(&state.db.pool)
+            .await
+            .unwrap();
+
+        // Mounted the way main.rs serves it to external clients
+        let app = actix_test::init_service(
+            App::new().app_data(state.clone()).service(
+                web::scope("/v1")
+                    .wrap(ApiKeyMiddleware)
+                    .wrap(OpenAIErrors)
+                    .route("/models", web::get().to(list_models_v1)),
+            ),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::get()
+            .uri("/v1/models")
+            .insert_header((header::AUTHORIZATION, "Bearer sk-test"))
+            .to_request();
+        let body: Value = actix_test::call_and_read_body_json(&app, req).await;
+
+        // Documented response of GET https://api.openai.com/v1/models
+        assert_eq!(
+            body,
+            json!({
+                "object": "list",
+                "data": [
+                    {"id": "gpt-4o", "object": "model", "created": 1715367049, "owned_by": "system"},
+                    {"id": "llama3:8b", "object": "model", "created": 1700000000, "owned_by": "ollama"}
+                ]
+            })
+        );
+
+        // Round-trips through the typed response
+        let list: OpenAIModelList = serde_json::from_value(body).unwrap();
+        assert_eq!(list.data[1].id, "llama3:8b");
+    }
+
+    #[actix_web::test]
+    async fn test_completion_aliases_share_the_chat_rate_limit() {
+        let mut config = Config::from_env().unwrap();
+        config.enable_rate_limit = true;
+        config.rate_limit_chat = "1/60".to_string();
+        config.rate_limit_exempt_roles = Vec::new();
+        config.rate_limit_role_rules = Vec::new();
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir, config).await;
+        test_utils::create_user(&state.db, "user-1", "user").await;
+        let token = test_utils::user_token(&state, "user-1");
+
+        // Mounted the way main.rs serves it
+        let app = actix_test::init_service(
+            App::new().app_data(state.clone()).service(
+                web::scope("/openai")
+                    .wrap(OpenAIErrors)
+                    .configure(create_routes),
+            ),
+        )
+        .await;
+
+        // One request through either alias uses up the user's chat budget
+        for (uri, limited) in [
+            ("/openai/chat/completions", false),
+            ("/openai/completions", true),
+            ("/openai/chat/completions", true),
+        ] {
+            let req = actix_test::TestRequest::post()
+                .uri(uri)
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+                .set_json(json!({}))
+                .to_request();
+            let status = actix_test::call_service(&app, req).await.status();
+            assert_eq!(status.as_u16() == 429, limited, "{} {}", uri, status);
+        }
+    }
+}
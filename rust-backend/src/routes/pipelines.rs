@@ -2,8 +2,8 @@ use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse};
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::io::Write;
 
+use crate::config::Config;
 use crate::error::{AppError, AppResult};
 use crate::middleware::auth::{AdminMiddleware, AuthUser};
 use crate::AppState;
@@ -39,38 +39,110 @@ struct PipelineQuery {
     url_idx: Option<usize>,
 }
 
-/// Helper function to get all models responses from OpenAI endpoints
-async fn get_all_models_responses(state: &web::Data<AppState>) -> Vec<Option<serde_json::Value>> {
-    let config = state.config.read().unwrap();
-    let mut responses = Vec::new();
+/// Whether the OpenAI connection at `idx` is enabled (connections default to enabled)
+fn connection_enabled(config: &Config, idx: usize, url: &str) -> bool {
+    config
+        .openai_api_configs
+        .get(idx.to_string())
+        .or_else(|| config.openai_api_configs.get(url))
+        .and_then(|c| c.get("enable"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true)
+}
+
+/// URL and key of the pipeline server at `url_idx`; disabled connections are rejected
+fn pipeline_connection(state: &web::Data<AppState>, url_idx: usize) -> AppResult<(String, String)> {
+    let config = state.config.snapshot();
+
+    let url = config
+        .openai_api_base_urls
+        .get(url_idx)
+        .cloned()
+        .ok_or_else(|| AppError::NotFound("Pipeline endpoint not found".to_string()))?;
+
+    if !connection_enabled(&config, url_idx, &url) {
+        return Err(AppError::BadRequest(format!(
+            "Connection {} is disabled",
+            url_idx
+        )));
+    }
 
-    let client = reqwest::Client::new();
+    let key = config
+        .openai_api_keys
+        .get(url_idx)
+        .cloned()
+        .unwrap_or_default();
 
-    for (idx, url) in config.openai_api_base_urls.iter().enumerate() {
-        let key = config
-            .openai_api_keys
-            .get(idx)
-            .map(|s| s.as_str())
-            .unwrap_or("");
+    Ok((url.trim_end_matches('/').to_string(), key))
+}
+
+/// Send a request to a pipeline server and relay its status and body unchanged, so the
+/// server's own validation errors reach the UI
+async fn forward(request: reqwest::RequestBuilder) -> AppResult<HttpResponse> {
+    let response = request
+        .send()
+        .await
+        .map_err(|e| AppError::ExternalServiceError(format!("Connection error: {}", e)))?;
+
+    let status = actix_web::http::StatusCode::from_u16(response.status().as_u16())
+        .unwrap_or(actix_web::http::StatusCode::BAD_GATEWAY);
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/json")
+        .to_string();
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| AppError::ExternalServiceError(format!("Connection error: {}", e)))?;
+
+    if !status.is_success() {
+        tracing::warn!("Pipeline server returned {}", status);
+    }
+
+    Ok(HttpResponse::build(status)
+        .content_type(content_type)
+        .body(body))
+}
+
+/// Probe every enabled connection's model list; pipeline servers include a `pipelines` field
+async fn get_pipeline_servers(state: &web::Data<AppState>) -> Vec<PipelineInfo> {
+    let connections: Vec<(usize, String, String)> = {
+        let config = state.config.snapshot();
+        config
+            .openai_api_base_urls
+            .iter()
+            .enumerate()
+            .filter(|(idx, url)| connection_enabled(&config, *idx, url))
+            .map(|(idx, url)| {
+                let key = config.openai_api_keys.get(idx).cloned().unwrap_or_default();
+                (idx, url.clone(), key)
+            })
+            .collect()
+    };
 
-        // Try to fetch models from this endpoint
-        match client
-            .get(format!("{}/models", url))
-            .header("Authorization", format!("Bearer {}", key))
+    let probes = connections.into_iter().map(|(idx, url, key)| async move {
+        let response = state
+            .http_client
+            .get(format!("{}/models", url.trim_end_matches('/')))
+            .bearer_auth(&key)
+            .timeout(std::time::Duration::from_secs(10))
             .send()
             .await
-        {
-            Ok(response) if response.status().is_success() => {
-                match response.json::<serde_json::Value>().await {
-                    Ok(data) => responses.push(Some(data)),
-                    Err(_) => responses.push(None),
-                }
-            }
-            _ => responses.push(None),
+            .ok()?;
+        if !response.status().is_success() {
+            return None;
         }
-    }
+        let data = response.json::<serde_json::Value>().await.ok()?;
+        data.get("pipelines").map(|_| PipelineInfo { url, idx })
+    });
 
-    responses
+    futures::future::join_all(probes)
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
 }
 
 // GET /list - Get pipelines list (admin only)
@@ -78,32 +150,8 @@ async fn get_pipelines_list(
     state: web::Data<AppState>,
     _user: AuthUser,
 ) -> AppResult<HttpResponse> {
-    // Get all models responses to check which endpoints have pipelines
-    let responses = get_all_models_responses(&state).await;
-
-    let config = state.config.read().unwrap();
-
-    // Find URL indices that have "pipelines" field in their response
-    let pipeline_urls: Vec<PipelineInfo> = responses
-        .iter()
-        .enumerate()
-        .filter_map(|(idx, response)| {
-            if let Some(data) = response {
-                if data.get("pipelines").is_some() {
-                    if idx < config.openai_api_base_urls.len() {
-                        return Some(PipelineInfo {
-                            url: config.openai_api_base_urls[idx].clone(),
-                            idx,
-                        });
-                    }
-                }
-            }
-            None
-        })
-        .collect();
-
     Ok(HttpResponse::Ok().json(PipelineListResponse {
-        data: pipeline_urls,
+        data: get_pipeline_servers(&state).await,
     }))
 }
 
@@ -170,70 +218,23 @@ async fn upload_pipeline(
         ));
     }
 
-    let (url, key) = {
-        let config = state.config.read().unwrap();
-
-        if url_idx >= config.openai_api_base_urls.len() {
-            return Err(AppError::NotFound(
-                "Pipeline endpoint not found".to_string(),
-            ));
-        }
-
-        let url = config.openai_api_base_urls[url_idx].clone();
-        let key = config
-            .openai_api_keys
-            .get(url_idx)
-            .map(|s| s.to_string())
-            .unwrap_or_default();
-
-        (url, key)
-    };
+    let (url, key) = pipeline_connection(&state, url_idx)?;
 
     // Forward the file upload to the pipeline endpoint
-    let client = reqwest::Client::new();
-
-    let form = reqwest::multipart::Form::new().part(
-        "file",
-        reqwest::multipart::Part::bytes(file_data)
-            .file_name(filename)
-            .mime_str("text/x-python")
-            .unwrap(),
-    );
-
-    match client
-        .post(format!("{}/pipelines/upload", url))
-        .header("Authorization", format!("Bearer {}", key))
-        .multipart(form)
-        .send()
-        .await
-    {
-        Ok(response) if response.status().is_success() => {
-            let data = response
-                .json::<serde_json::Value>()
-                .await
-                .map_err(|e| AppError::InternalServerError(format!("Parse error: {}", e)))?;
-            Ok(HttpResponse::Ok().json(data))
-        }
-        Ok(response) => {
-            let status = response.status();
-            let error_data = response.json::<serde_json::Value>().await.ok();
-
-            let detail = error_data
-                .as_ref()
-                .and_then(|d| d.get("detail"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("Pipeline not found");
-
-            Err(AppError::ExternalServiceError(format!(
-                "Pipeline endpoint error ({}): {}",
-                status, detail
-            )))
-        }
-        Err(e) => Err(AppError::InternalServerError(format!(
-            "Connection error: {}",
-            e
-        ))),
-    }
+    let part = reqwest::multipart::Part::bytes(file_data)
+        .file_name(filename)
+        .mime_str("text/x-python")
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+    let form = reqwest::multipart::Form::new().part("file", part);
+
+    forward(
+        state
+            .http_client
+            .post(format!("{}/pipelines/upload", url))
+            .bearer_auth(&key)
+            .multipart(form),
+    )
+    .await
 }
 
 // POST /add - Add pipeline from URL (admin only)
@@ -242,64 +243,16 @@ async fn add_pipeline(
     _user: AuthUser,
     form: web::Json<AddPipelineForm>,
 ) -> AppResult<HttpResponse> {
-    let (endpoint_url, key) = {
-        let config = state.config.read().unwrap();
-
-        if form.url_idx >= config.openai_api_base_urls.len() {
-            return Err(AppError::NotFound(
-                "Pipeline endpoint not found".to_string(),
-            ));
-        }
-
-        let endpoint_url = config.openai_api_base_urls[form.url_idx].clone();
-        let key = config
-            .openai_api_keys
-            .get(form.url_idx)
-            .map(|s| s.to_string())
-            .unwrap_or_default();
-
-        (endpoint_url, key)
-    };
-
-    // Forward the request to the pipeline endpoint
-    let client = reqwest::Client::new();
-
-    match client
-        .post(format!("{}/pipelines/add", endpoint_url))
-        .header("Authorization", format!("Bearer {}", key))
-        .json(&serde_json::json!({
-            "url": form.url
-        }))
-        .send()
-        .await
-    {
-        Ok(response) if response.status().is_success() => {
-            let data = response
-                .json::<serde_json::Value>()
-                .await
-                .map_err(|e| AppError::InternalServerError(format!("Parse error: {}", e)))?;
-            Ok(HttpResponse::Ok().json(data))
-        }
-        Ok(response) => {
-            let status = response.status();
-            let error_data = response.json::<serde_json::Value>().await.ok();
-
-            let detail = error_data
-                .as_ref()
-                .and_then(|d| d.get("detail"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("Pipeline not found");
-
-            Err(AppError::ExternalServiceError(format!(
-                "Pipeline endpoint error ({}): {}",
-                status, detail
-            )))
-        }
-        Err(e) => Err(AppError::InternalServerError(format!(
-            "Connection error: {}",
-            e
-        ))),
-    }
+    let (url, key) = pipeline_connection(&state, form.url_idx)?;
+
+    forward(
+        state
+            .http_client
+            .post(format!("{}/pipelines/add", url))
+            .bearer_auth(&key)
+            .json(&serde_json::json!({ "url": form.url })),
+    )
+    .await
 }
 
 // DELETE /delete - Delete pipeline (admin only)
@@ -308,64 +261,22 @@ async fn delete_pipeline(
     _user: AuthUser,
     form: web::Json<DeletePipelineForm>,
 ) -> AppResult<HttpResponse> {
-    let (endpoint_url, key) = {
-        let config = state.config.read().unwrap();
-
-        if form.url_idx >= config.openai_api_base_urls.len() {
-            return Err(AppError::NotFound(
-                "Pipeline endpoint not found".to_string(),
-            ));
-        }
-
-        let endpoint_url = config.openai_api_base_urls[form.url_idx].clone();
-        let key = config
-            .openai_api_keys
-            .get(form.url_idx)
-            .map(|s| s.to_string())
-            .unwrap_or_default();
-
-        (endpoint_url, key)
-    };
-
-    // Forward the request to the pipeline endpoint
-    let client = reqwest::Client::new();
+    let (url, key) = pipeline_connection(&state, form.url_idx)?;
+
+    forward(
+        state
+            .http_client
+            .delete(format!("{}/pipelines/delete", url))
+            .bearer_auth(&key)
+            .json(&serde_json::json!({ "id": form.id })),
+    )
+    .await
+}
 
-    match client
-        .delete(format!("{}/pipelines/delete", endpoint_url))
-        .header("Authorization", format!("Bearer {}", key))
-        .json(&serde_json::json!({
-            "id": form.id
-        }))
-        .send()
-        .await
-    {
-        Ok(response) if response.status().is_success() => {
-            let data = response
-                .json::<serde_json::Value>()
-                .await
-                .map_err(|e| AppError::InternalServerError(format!("Parse error: {}", e)))?;
-            Ok(HttpResponse::Ok().json(data))
-        }
-        Ok(response) => {
-            let status = response.status();
-            let error_data = response.json::<serde_json::Value>().await.ok();
-
-            let detail = error_data
-                .as_ref()
-                .and_then(|d| d.get("detail"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("Pipeline not found");
-
-            Err(AppError::ExternalServiceError(format!(
-                "Pipeline endpoint error ({}): {}",
-                status, detail
-            )))
-        }
-        Err(e) => Err(AppError::InternalServerError(format!(
-            "Connection error: {}",
-            e
-        ))),
-    }
+fn required_url_idx(query: &PipelineQuery) -> AppResult<usize> {
+    query
+        .url_idx
+        .ok_or_else(|| AppError::BadRequest("urlIdx parameter is required".to_string()))
 }
 
 // GET / - Get all pipelines (admin only)
@@ -374,65 +285,15 @@ async fn get_all_pipelines(
     _user: AuthUser,
     query: web::Query<PipelineQuery>,
 ) -> AppResult<HttpResponse> {
-    let url_idx = query
-        .url_idx
-        .ok_or_else(|| AppError::BadRequest("urlIdx parameter is required".to_string()))?;
-
-    let (endpoint_url, key) = {
-        let config = state.config.read().unwrap();
-
-        if url_idx >= config.openai_api_base_urls.len() {
-            return Err(AppError::NotFound(
-                "Pipeline endpoint not found".to_string(),
-            ));
-        }
-
-        let endpoint_url = config.openai_api_base_urls[url_idx].clone();
-        let key = config
-            .openai_api_keys
-            .get(url_idx)
-            .map(|s| s.to_string())
-            .unwrap_or_default();
-
-        (endpoint_url, key)
-    };
-
-    // Fetch pipelines from the endpoint
-    let client = reqwest::Client::new();
-
-    match client
-        .get(format!("{}/pipelines", endpoint_url))
-        .header("Authorization", format!("Bearer {}", key))
-        .send()
-        .await
-    {
-        Ok(response) if response.status().is_success() => {
-            let data = response
-                .json::<serde_json::Value>()
-                .await
-                .map_err(|e| AppError::InternalServerError(format!("Parse error: {}", e)))?;
-            Ok(HttpResponse::Ok().json(data))
-        }
-        Ok(response) => {
-            let status = response.status();
-            let error_data = response.json::<serde_json::Value>().await.ok();
-
-            let detail = error_data
-                .as_ref()
-                .and_then(|d| d.get("detail"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("Pipeline not found");
-
-            Err(AppError::ExternalServiceError(format!(
-                "Pipeline endpoint error ({}): {}",
-                status, detail
-            )))
-        }
-        Err(e) => Err(AppError::InternalServerError(format!(
-            "Connection error: {}",
-            e
-        ))),
-    }
+    let (url, key) = pipeline_connection(&state, required_url_idx(&query)?)?;
+
+    forward(
+        state
+            .http_client
+            .get(format!("{}/pipelines", url))
+            .bearer_auth(&key),
+    )
+    .await
 }
 
 // GET /{pipeline_id}/valves - Get pipeline valves (admin only)
@@ -442,64 +303,15 @@ async fn get_pipeline_valves(
     pipeline_id: web::Path<String>,
     query: web::Query<PipelineQuery>,
 ) -> AppResult<HttpResponse> {
-    let url_idx = query
-        .url_idx
-        .ok_or_else(|| AppError::BadRequest("urlIdx parameter is required".to_string()))?;
-
-    let (endpoint_url, key) = {
-        let config = state.config.read().unwrap();
-
-        if url_idx >= config.openai_api_base_urls.len() {
-            return Err(AppError::NotFound(
-                "Pipeline endpoint not found".to_string(),
-            ));
-        }
-
-        let endpoint_url = config.openai_api_base_urls[url_idx].clone();
-        let key = config
-            .openai_api_keys
-            .get(url_idx)
-            .map(|s| s.to_string())
-            .unwrap_or_default();
-
-        (endpoint_url, key)
-    };
-
-    let client = reqwest::Client::new();
-
-    match client
-        .get(format!("{}/{}/valves", endpoint_url, pipeline_id))
-        .header("Authorization", format!("Bearer {}", key))
-        .send()
-        .await
-    {
-        Ok(response) if response.status().is_success() => {
-            let data = response
-                .json::<serde_json::Value>()
-                .await
-                .map_err(|e| AppError::InternalServerError(format!("Parse error: {}", e)))?;
-            Ok(HttpResponse::Ok().json(data))
-        }
-        Ok(response) => {
-            let status = response.status();
-            let error_data = response.json::<serde_json::Value>().await.ok();
-
-            let detail = error_data
-                .as_ref()
-                .and_then(|d| d.get("detail"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("Pipeline not found");
-
-            Err(AppError::ExternalServiceError(format!(
-                "Pipeline endpoint error ({}): {}",
-                status, detail
-            )))
-        }
-        Err(e) => Err(AppError::InternalServerError(format!(
-            "Connection error: {}",
-            e
-        ))),
-    }
+    let (url, key) = pipeline_connection(&state, required_url_idx(&query)?)?;
+
+    forward(
+        state
+            .http_client
+            .get(format!("{}/{}/valves", url, pipeline_id))
+            .bearer_auth(&key),
+    )
+    .await
 }
 
 // GET /{pipeline_id}/valves/spec - Get pipeline valves spec (admin only)
@@ -509,64 +321,15 @@ async fn get_pipeline_valves_spec(
     pipeline_id: web::Path<String>,
     query: web::Query<PipelineQuery>,
 ) -> AppResult<HttpResponse> {
-    let url_idx = query
-        .url_idx
-        .ok_or_else(|| AppError::BadRequest("urlIdx parameter is required".to_string()))?;
-
-    let (endpoint_url, key) = {
-        let config = state.config.read().unwrap();
-
-        if url_idx >= config.openai_api_base_urls.len() {
-            return Err(AppError::NotFound(
-                "Pipeline endpoint not found".to_string(),
-            ));
-        }
-
-        let endpoint_url = config.openai_api_base_urls[url_idx].clone();
-        let key = config
-            .openai_api_keys
-            .get(url_idx)
-            .map(|s| s.to_string())
-            .unwrap_or_default();
-
-        (endpoint_url, key)
-    };
-
-    let client = reqwest::Client::new();
-
-    match client
-        .get(format!("{}/{}/valves/spec", endpoint_url, pipeline_id))
-        .header("Authorization", format!("Bearer {}", key))
-        .send()
-        .await
-    {
-        Ok(response) if response.status().is_success() => {
-            let data = response
-                .json::<serde_json::Value>()
-                .await
-                .map_err(|e| AppError::InternalServerError(format!("Parse error: {}", e)))?;
-            Ok(HttpResponse::Ok().json(data))
-        }
-        Ok(response) => {
-            let status = response.status();
-            let error_data = response.json::<serde_json::Value>().await.ok();
-
-            let detail = error_data
-                .as_ref()
-                .and_then(|d| d.get("detail"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("Pipeline not found");
-
-            Err(AppError::ExternalServiceError(format!(
-                "Pipeline endpoint error ({}): {}",
-                status, detail
-            )))
-        }
-        Err(e) => Err(AppError::InternalServerError(format!(
-            "Connection error: {}",
-            e
-        ))),
-    }
+    let (url, key) = pipeline_connection(&state, required_url_idx(&query)?)?;
+
+    forward(
+        state
+            .http_client
+            .get(format!("{}/{}/valves/spec", url, pipeline_id))
+            .bearer_auth(&key),
+    )
+    .await
 }
 
 // POST /{pipeline_id}/valves/update - Update pipeline valves (admin only)
@@ -577,65 +340,16 @@ async fn update_pipeline_valves(
     query: web::Query<PipelineQuery>,
     form: web::Json<serde_json::Value>,
 ) -> AppResult<HttpResponse> {
-    let url_idx = query
-        .url_idx
-        .ok_or_else(|| AppError::BadRequest("urlIdx parameter is required".to_string()))?;
-
-    let (endpoint_url, key) = {
-        let config = state.config.read().unwrap();
-
-        if url_idx >= config.openai_api_base_urls.len() {
-            return Err(AppError::NotFound(
-                "Pipeline endpoint not found".to_string(),
-            ));
-        }
-
-        let endpoint_url = config.openai_api_base_urls[url_idx].clone();
-        let key = config
-            .openai_api_keys
-            .get(url_idx)
-            .map(|s| s.to_string())
-            .unwrap_or_default();
-
-        (endpoint_url, key)
-    };
-
-    let client = reqwest::Client::new();
-
-    match client
-        .post(format!("{}/{}/valves/update", endpoint_url, pipeline_id))
-        .header("Authorization", format!("Bearer {}", key))
-        .json(&form.into_inner())
-        .send()
-        .await
-    {
-        Ok(response) if response.status().is_success() => {
-            let data = response
-                .json::<serde_json::Value>()
-                .await
-                .map_err(|e| AppError::InternalServerError(format!("Parse error: {}", e)))?;
-            Ok(HttpResponse::Ok().json(data))
-        }
-        Ok(response) => {
-            let status = response.status();
-            let error_data = response.json::<serde_json::Value>().await.ok();
-
-            let detail = error_data
-                .as_ref()
-                .and_then(|d| d.get("detail"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("Pipeline not found");
-
-            Err(AppError::ExternalServiceError(format!(
-                "Pipeline endpoint error ({}): {}",
-                status, detail
-            )))
-        }
-        Err(e) => Err(AppError::InternalServerError(format!(
-            "Connection error: {}",
-            e
-        ))),
-    }
+    let (url, key) = pipeline_connection(&state, required_url_idx(&query)?)?;
+
+    forward(
+        state
+            .http_client
+            .post(format!("{}/{}/valves/update", url, pipeline_id))
+            .bearer_auth(&key)
+            .json(&form.into_inner()),
+    )
+    .await
 }
 
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
@@ -1,4 +1,5 @@
 use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
 use serde_json::json;
 use std::collections::{HashMap, HashSet};
 
@@ -8,7 +9,9 @@ use crate::models::prompt::{PromptForm, PromptModel, PromptUserResponse};
 use crate::services::group::GroupService;
 use crate::services::prompt::PromptService;
 use crate::services::user::UserService;
-use crate::utils::misc::{has_access, has_permission};
+use crate::utils::misc::has_access;
+use crate::utils::permissions::user_has_permission;
+use crate::utils::prompt_variables::{render_prompt_variables, PromptVariableContext};
 use crate::AppState;
 
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
@@ -51,26 +54,28 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
 
 async fn get_prompts(state: web::Data<AppState>, auth_user: AuthUser) -> AppResult<HttpResponse> {
     let prompt_service = PromptService::new(&state.db);
-    let config = state.config.read().unwrap();
-
-    let all_prompts =
-        if auth_user.role == "admin" && config.bypass_admin_access_control.unwrap_or(false) {
-            prompt_service.get_all_prompts().await?
-        } else {
-            // Get user's groups
-            let group_service = GroupService::new(&state.db);
-            let groups = group_service.get_groups_by_member_id(&auth_user.id).await?;
-            let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
-
-            // Filter prompts by access control
-            let all = prompt_service.get_all_prompts().await?;
-            all.into_iter()
-                .filter(|p| {
-                    p.user_id == auth_user.id
-                        || has_access(&auth_user.id, "read", &p.access_control, &user_group_ids)
-                })
-                .collect()
-        };
+    let bypass_admin_access = {
+        let config = state.config.snapshot();
+        config.bypass_admin_access_control.unwrap_or(false)
+    };
+
+    let all_prompts = if auth_user.role == "admin" && bypass_admin_access {
+        prompt_service.get_all_prompts().await?
+    } else {
+        // Get user's groups
+        let group_service = GroupService::new(&state.db);
+        let groups = group_service.get_groups_by_member_id(&auth_user.id).await?;
+        let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
+
+        // Filter prompts by access control
+        let all = prompt_service.get_all_prompts().await?;
+        all.into_iter()
+            .filter(|p| {
+                p.user_id == auth_user.id
+                    || has_access(&auth_user.id, "read", &p.access_control, &user_group_ids)
+            })
+            .collect()
+    };
 
     let response: Vec<PromptModel> = all_prompts.into_iter().map(PromptModel::from).collect();
 
@@ -83,26 +88,28 @@ async fn get_prompt_list(
 ) -> AppResult<HttpResponse> {
     let prompt_service = PromptService::new(&state.db);
     let user_service = UserService::new(&state.db);
-    let config = state.config.read().unwrap();
-
-    let all_prompts =
-        if auth_user.role == "admin" && config.bypass_admin_access_control.unwrap_or(false) {
-            prompt_service.get_all_prompts().await?
-        } else {
-            // Get user's groups
-            let group_service = GroupService::new(&state.db);
-            let groups = group_service.get_groups_by_member_id(&auth_user.id).await?;
-            let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
-
-            // Filter prompts by write access
-            let all = prompt_service.get_all_prompts().await?;
-            all.into_iter()
-                .filter(|p| {
-                    p.user_id == auth_user.id
-                        || has_access(&auth_user.id, "write", &p.access_control, &user_group_ids)
-                })
-                .collect()
-        };
+    let bypass_admin_access = {
+        let config = state.config.snapshot();
+        config.bypass_admin_access_control.unwrap_or(false)
+    };
+
+    let all_prompts = if auth_user.role == "admin" && bypass_admin_access {
+        prompt_service.get_all_prompts().await?
+    } else {
+        // Get user's groups
+        let group_service = GroupService::new(&state.db);
+        let groups = group_service.get_groups_by_member_id(&auth_user.id).await?;
+        let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
+
+        // Filter prompts by write access
+        let all = prompt_service.get_all_prompts().await?;
+        all.into_iter()
+            .filter(|p| {
+                p.user_id == auth_user.id
+                    || has_access(&auth_user.id, "write", &p.access_control, &user_group_ids)
+            })
+            .collect()
+    };
 
     // Get unique user IDs
     let user_ids: HashSet<String> = all_prompts.iter().map(|p| p.user_id.clone()).collect();
@@ -141,13 +148,19 @@ async fn create_new_prompt(
     auth_user: AuthUser,
     payload: web::Json<PromptForm>,
 ) -> AppResult<HttpResponse> {
-    let config = state.config.read().unwrap();
-
     // Check workspace permissions
-    if auth_user.role != "admin"
-        && !has_permission(&auth_user.id, "workspace.prompts", &config.user_permissions)
-    {
-        return Err(AppError::Unauthorized("Unauthorized".to_string()));
+    if auth_user.role != "admin" {
+        let user_permissions = state.config.snapshot().user_permissions.clone();
+        if !user_has_permission(
+            &state.db,
+            &user_permissions,
+            &auth_user.id,
+            "workspace.prompts",
+        )
+        .await?
+        {
+            return Err(AppError::Unauthorized("Unauthorized".to_string()));
+        }
     }
 
     let prompt_service = PromptService::new(&state.db);
@@ -168,10 +181,17 @@ async fn create_new_prompt(
     Ok(HttpResponse::Ok().json(PromptModel::from(prompt)))
 }
 
+#[derive(Debug, Deserialize)]
+struct PromptCommandQuery {
+    /// IANA timezone used for date/time variables, e.g. `Europe/Berlin`
+    timezone: Option<String>,
+}
+
 async fn get_prompt_by_command(
     state: web::Data<AppState>,
     auth_user: AuthUser,
     command: web::Path<String>,
+    query: web::Query<PromptCommandQuery>,
 ) -> AppResult<HttpResponse> {
     let prompt_service = PromptService::new(&state.db);
 
@@ -180,24 +200,34 @@ async fn get_prompt_by_command(
         .await?
         .ok_or_else(|| AppError::NotFound("Prompt not found".to_string()))?;
 
-    // Check access
-    let group_service = GroupService::new(&state.db);
-    let groups = group_service.get_groups_by_member_id(&auth_user.id).await?;
-    let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
+    // Check access: owner, admin, or has read access
+    if auth_user.role != "admin" && prompt.user_id != auth_user.id {
+        let group_service = GroupService::new(&state.db);
+        let groups = group_service.get_groups_by_member_id(&auth_user.id).await?;
+        let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
 
-    if auth_user.role == "admin"
-        || prompt.user_id == auth_user.id
-        || has_access(
+        if !has_access(
             &auth_user.id,
             "read",
             &prompt.access_control,
             &user_group_ids,
-        )
-    {
-        Ok(HttpResponse::Ok().json(PromptModel::from(prompt)))
-    } else {
-        Err(AppError::Unauthorized("Not found".to_string()))
+        ) {
+            return Err(AppError::Unauthorized("Not found".to_string()));
+        }
+    }
+
+    // Raw content stays editable; the rendered copy is what gets inserted into the chat
+    let ctx = PromptVariableContext::for_user(&auth_user.user, query.timezone.as_deref());
+    let rendered = render_prompt_variables(&prompt.content, &ctx);
+
+    let mut response = serde_json::to_value(PromptModel::from(prompt))
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+    if let Some(obj) = response.as_object_mut() {
+        obj.insert("rendered_content".to_string(), json!(rendered.content));
+        obj.insert("variables".to_string(), json!(rendered.variables));
     }
+
+    Ok(HttpResponse::Ok().json(response))
 }
 
 async fn update_prompt_by_command(
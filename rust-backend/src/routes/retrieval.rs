@@ -12,6 +12,10 @@ use crate::{
 struct RetrievalConfigResponse {
     #[serde(rename = "RAG_TEMPLATE")]
     rag_template: String,
+    #[serde(rename = "RAG_CITATION_STYLE", default)]
+    rag_citation_style: Option<String>,
+    #[serde(rename = "RAG_CITATION_INCLUDE_SOURCE", default)]
+    rag_citation_include_source: Option<bool>,
     #[serde(rename = "TOP_K")]
     top_k: usize,
     #[serde(rename = "BYPASS_EMBEDDING_AND_RETRIEVAL")]
@@ -24,6 +28,8 @@ struct RetrievalConfigResponse {
     top_k_reranker: i32,
     #[serde(rename = "RELEVANCE_THRESHOLD")]
     relevance_threshold: f64,
+    #[serde(rename = "RAG_MAX_SOURCES", default)]
+    rag_max_sources: Option<usize>,
     #[serde(rename = "HYBRID_BM25_WEIGHT")]
     hybrid_bm25_weight: f64,
     #[serde(rename = "CONTENT_EXTRACTION_ENGINE")]
@@ -154,12 +160,14 @@ async fn get_rag_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(json!({
         "status": true,
         // RAG settings
         "RAG_TEMPLATE": config.rag_template,
+        "RAG_CITATION_STYLE": config.rag_citation_style,
+        "RAG_CITATION_INCLUDE_SOURCE": config.rag_citation_include_source,
         "TOP_K": config.rag_top_k,
         "BYPASS_EMBEDDING_AND_RETRIEVAL": config.bypass_embedding_and_retrieval,
         "RAG_FULL_CONTEXT": config.rag_full_context,
@@ -167,6 +175,7 @@ async fn get_rag_config(
         "ENABLE_RAG_HYBRID_SEARCH": config.enable_rag_hybrid_search,
         "TOP_K_RERANKER": config.top_k_reranker,
         "RELEVANCE_THRESHOLD": config.relevance_threshold,
+        "RAG_MAX_SOURCES": config.rag_max_sources,
         "HYBRID_BM25_WEIGHT": config.hybrid_bm25_weight,
         // Content extraction settings
         "CONTENT_EXTRACTION_ENGINE": config.content_extraction_engine,
@@ -176,16 +185,20 @@ async fn get_rag_config(
         "CHUNK_SIZE": config.chunk_size,
         "CHUNK_OVERLAP": config.chunk_overlap,
         // File upload settings
-        "FILE_MAX_SIZE": 25,
-        "FILE_MAX_COUNT": 10,
+        "FILE_MAX_SIZE": config.file_max_size / 1024 / 1024,
+        "FILE_MAX_COUNT": config.file_max_count,
         // Reranking settings
         "RAG_RERANKING_MODEL": "",
         "RAG_RERANKING_ENGINE": "",
         // Web search settings - nested object
         "web": {
             "ENABLE_WEB_SEARCH": config.enable_web_search,
-            "WEB_SEARCH_ENGINE": "",
-            "WEB_SEARCH_RESULT_COUNT": 3,
+            "WEB_SEARCH_ENGINE": config.web_search_engine,
+            "WEB_SEARCH_RESULT_COUNT": config.web_search_result_count,
+            "SEARXNG_QUERY_URL": config.searxng_query_url,
+            "BRAVE_SEARCH_API_KEY": config.brave_search_api_key,
+            "GOOGLE_PSE_API_KEY": config.google_pse_api_key,
+            "GOOGLE_PSE_ENGINE_ID": config.google_pse_engine_id,
             "YOUTUBE_LOADER_LANGUAGE": vec!["en"],
             "YOUTUBE_LOADER_PROXY_URL": "",
             "YOUTUBE_LOADER_TRANSLATION": "",
@@ -203,15 +216,24 @@ async fn update_rag_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let mut config = state.config.write().unwrap();
+    let mut config = state.config.write();
 
     config.rag_template = form_data.rag_template.clone();
+    if let Some(style) = &form_data.rag_citation_style {
+        config.rag_citation_style = style.clone();
+    }
+    if let Some(include_source) = form_data.rag_citation_include_source {
+        config.rag_citation_include_source = include_source;
+    }
     config.rag_top_k = form_data.top_k;
     config.bypass_embedding_and_retrieval = form_data.bypass_embedding_and_retrieval;
     config.rag_full_context = form_data.rag_full_context;
     config.enable_rag_hybrid_search = form_data.enable_rag_hybrid_search;
     config.top_k_reranker = form_data.top_k_reranker;
     config.relevance_threshold = form_data.relevance_threshold;
+    if let Some(max_sources) = form_data.rag_max_sources {
+        config.rag_max_sources = max_sources;
+    }
     config.hybrid_bm25_weight = form_data.hybrid_bm25_weight;
     config.content_extraction_engine = form_data.content_extraction_engine.clone();
     config.pdf_extract_images = form_data.pdf_extract_images;
@@ -223,12 +245,15 @@ async fn update_rag_config(
     Ok(HttpResponse::Ok().json(json!({
         "status": true,
         "RAG_TEMPLATE": config.rag_template,
+        "RAG_CITATION_STYLE": config.rag_citation_style,
+        "RAG_CITATION_INCLUDE_SOURCE": config.rag_citation_include_source,
         "TOP_K": config.rag_top_k,
         "BYPASS_EMBEDDING_AND_RETRIEVAL": config.bypass_embedding_and_retrieval,
         "RAG_FULL_CONTEXT": config.rag_full_context,
         "ENABLE_RAG_HYBRID_SEARCH": config.enable_rag_hybrid_search,
         "TOP_K_RERANKER": config.top_k_reranker,
         "RELEVANCE_THRESHOLD": config.relevance_threshold,
+        "RAG_MAX_SOURCES": config.rag_max_sources,
         "HYBRID_BM25_WEIGHT": config.hybrid_bm25_weight,
         "CONTENT_EXTRACTION_ENGINE": config.content_extraction_engine,
         "PDF_EXTRACT_IMAGES": config.pdf_extract_images,
@@ -246,7 +271,7 @@ async fn get_embedding_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(EmbeddingConfigResponse {
         status: true,
@@ -275,7 +300,7 @@ async fn update_embedding_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    let mut config = state.config.write().unwrap();
+    let mut config = state.config.write();
 
     config.rag_embedding_engine = form_data.embedding_engine.clone();
     config.rag_embedding_model = form_data.embedding_model.clone();
@@ -303,7 +328,7 @@ async fn update_embedding_config(
     )
     .await;
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     Ok(HttpResponse::Ok().json(EmbeddingConfigResponse {
         status: true,
@@ -1,10 +1,21 @@
 use actix_web::{web, HttpResponse};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
     error::AppError,
-    middleware::{AuthMiddleware, AuthUser},
+    middleware::{
+        rate_limit::{RateLimit, RouteGroup},
+        request_id::WithRequestId,
+        AuthMiddleware, AuthUser,
+    },
+    models::config::DirectConnections,
+    utils::chat_completion::{
+        chat_completion_request, extract_completion_content, into_chat_completion_response,
+        parse_generated_tags, DEFAULT_FOLLOW_UP_GENERATION_PROMPT_TEMPLATE,
+        DEFAULT_TAGS_GENERATION_PROMPT_TEMPLATE,
+    },
     AppState,
 };
 
@@ -21,26 +32,31 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
     )
     .service(
         web::resource("/title/completions")
+            .wrap(RateLimit::new(RouteGroup::Tasks))
             .wrap(AuthMiddleware)
             .route(web::post().to(generate_title)),
     )
     .service(
         web::resource("/follow_up/completions")
+            .wrap(RateLimit::new(RouteGroup::Tasks))
             .wrap(AuthMiddleware)
             .route(web::post().to(generate_follow_up)),
     )
     .service(
         web::resource("/tags/completions")
+            .wrap(RateLimit::new(RouteGroup::Tasks))
             .wrap(AuthMiddleware)
             .route(web::post().to(generate_tags)),
     )
     .service(
         web::resource("/image_prompt/completions")
+            .wrap(RateLimit::new(RouteGroup::Tasks))
             .wrap(AuthMiddleware)
             .route(web::post().to(generate_image_prompt)),
     )
     .service(
         web::resource("/queries/completions")
+            .wrap(RateLimit::new(RouteGroup::Tasks))
             .wrap(AuthMiddleware)
             .route(web::post().to(generate_queries)),
     )
@@ -49,13 +65,20 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AuthMiddleware)
             .route(web::post().to(generate_autocomplete)),
     )
+    .service(
+        web::resource("/autocomplete")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(generate_autocomplete)),
+    )
     .service(
         web::resource("/emoji/completions")
+            .wrap(RateLimit::new(RouteGroup::Tasks))
             .wrap(AuthMiddleware)
             .route(web::post().to(generate_emoji)),
     )
     .service(
         web::resource("/moa/completions")
+            .wrap(RateLimit::new(RouteGroup::Tasks))
             .wrap(AuthMiddleware)
             .route(web::post().to(generate_moa)),
     );
@@ -67,16 +90,24 @@ struct TaskConfig {
     task_model: Option<String>,
     #[serde(rename = "TASK_MODEL_EXTERNAL")]
     task_model_external: Option<String>,
+    #[serde(rename = "TITLE_GENERATION_MODEL")]
+    title_generation_model: Option<String>,
     #[serde(rename = "ENABLE_TITLE_GENERATION")]
     enable_title_generation: bool,
     #[serde(rename = "TITLE_GENERATION_PROMPT_TEMPLATE")]
     title_generation_prompt_template: String,
     #[serde(rename = "IMAGE_PROMPT_GENERATION_PROMPT_TEMPLATE")]
     image_prompt_generation_prompt_template: String,
+    #[serde(rename = "ENABLE_EMOJI_GENERATION")]
+    enable_emoji_generation: bool,
+    #[serde(rename = "EMOJI_GENERATION_PROMPT_TEMPLATE")]
+    emoji_generation_prompt_template: String,
     #[serde(rename = "ENABLE_AUTOCOMPLETE_GENERATION")]
     enable_autocomplete_generation: bool,
     #[serde(rename = "AUTOCOMPLETE_GENERATION_INPUT_MAX_LENGTH")]
     autocomplete_generation_input_max_length: i32,
+    #[serde(rename = "AUTOCOMPLETE_GENERATION_PROMPT_TEMPLATE")]
+    autocomplete_generation_prompt_template: String,
     #[serde(rename = "TAGS_GENERATION_PROMPT_TEMPLATE")]
     tags_generation_prompt_template: String,
     #[serde(rename = "FOLLOW_UP_GENERATION_PROMPT_TEMPLATE")]
@@ -101,16 +132,24 @@ struct TaskConfigUpdate {
     task_model: Option<String>,
     #[serde(rename = "TASK_MODEL_EXTERNAL")]
     task_model_external: Option<String>,
+    #[serde(rename = "TITLE_GENERATION_MODEL", default)]
+    title_generation_model: Option<String>,
     #[serde(rename = "ENABLE_TITLE_GENERATION")]
     enable_title_generation: bool,
     #[serde(rename = "TITLE_GENERATION_PROMPT_TEMPLATE")]
     title_generation_prompt_template: String,
     #[serde(rename = "IMAGE_PROMPT_GENERATION_PROMPT_TEMPLATE")]
     image_prompt_generation_prompt_template: String,
+    #[serde(rename = "ENABLE_EMOJI_GENERATION", default)]
+    enable_emoji_generation: Option<bool>,
+    #[serde(rename = "EMOJI_GENERATION_PROMPT_TEMPLATE", default)]
+    emoji_generation_prompt_template: Option<String>,
     #[serde(rename = "ENABLE_AUTOCOMPLETE_GENERATION")]
     enable_autocomplete_generation: bool,
     #[serde(rename = "AUTOCOMPLETE_GENERATION_INPUT_MAX_LENGTH")]
     autocomplete_generation_input_max_length: i32,
+    #[serde(rename = "AUTOCOMPLETE_GENERATION_PROMPT_TEMPLATE", default)]
+    autocomplete_generation_prompt_template: Option<String>,
     #[serde(rename = "TAGS_GENERATION_PROMPT_TEMPLATE")]
     tags_generation_prompt_template: String,
     #[serde(rename = "FOLLOW_UP_GENERATION_PROMPT_TEMPLATE")]
@@ -133,18 +172,24 @@ async fn get_task_config(
     state: web::Data<AppState>,
     _auth_user: AuthUser,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     let response = TaskConfig {
         task_model: config.task_model.clone(),
         task_model_external: config.task_model_external.clone(),
+        title_generation_model: config.title_generation_model.clone(),
         enable_title_generation: config.enable_title_generation,
         title_generation_prompt_template: config.title_generation_prompt_template.clone(),
         image_prompt_generation_prompt_template: config
             .image_prompt_generation_prompt_template
             .clone(),
+        enable_emoji_generation: config.enable_emoji_generation,
+        emoji_generation_prompt_template: config.emoji_generation_prompt_template.clone(),
         enable_autocomplete_generation: config.enable_autocomplete_generation,
         autocomplete_generation_input_max_length: config.autocomplete_generation_input_max_length,
+        autocomplete_generation_prompt_template: config
+            .autocomplete_generation_prompt_template
+            .clone(),
         tags_generation_prompt_template: config.tags_generation_prompt_template.clone(),
         follow_up_generation_prompt_template: config.follow_up_generation_prompt_template.clone(),
         enable_follow_up_generation: config.enable_follow_up_generation,
@@ -170,10 +215,11 @@ async fn update_task_config(
         return Err(AppError::Unauthorized("Admin access required".to_string()));
     }
 
-    let mut config = state.config.write().unwrap();
+    let mut config = state.config.write();
 
     config.task_model = payload.task_model.clone();
     config.task_model_external = payload.task_model_external.clone();
+    config.title_generation_model = payload.title_generation_model.clone();
     config.enable_title_generation = payload.enable_title_generation;
     config.title_generation_prompt_template = payload.title_generation_prompt_template.clone();
     config.image_prompt_generation_prompt_template =
@@ -181,6 +227,15 @@ async fn update_task_config(
     config.enable_autocomplete_generation = payload.enable_autocomplete_generation;
     config.autocomplete_generation_input_max_length =
         payload.autocomplete_generation_input_max_length;
+    if let Some(template) = &payload.autocomplete_generation_prompt_template {
+        config.autocomplete_generation_prompt_template = template.clone();
+    }
+    if let Some(enabled) = payload.enable_emoji_generation {
+        config.enable_emoji_generation = enabled;
+    }
+    if let Some(template) = &payload.emoji_generation_prompt_template {
+        config.emoji_generation_prompt_template = template.clone();
+    }
     config.tags_generation_prompt_template = payload.tags_generation_prompt_template.clone();
     config.follow_up_generation_prompt_template =
         payload.follow_up_generation_prompt_template.clone();
@@ -195,13 +250,19 @@ async fn update_task_config(
     let response = TaskConfig {
         task_model: config.task_model.clone(),
         task_model_external: config.task_model_external.clone(),
+        title_generation_model: config.title_generation_model.clone(),
         enable_title_generation: config.enable_title_generation,
         title_generation_prompt_template: config.title_generation_prompt_template.clone(),
         image_prompt_generation_prompt_template: config
             .image_prompt_generation_prompt_template
             .clone(),
+        enable_emoji_generation: config.enable_emoji_generation,
+        emoji_generation_prompt_template: config.emoji_generation_prompt_template.clone(),
         enable_autocomplete_generation: config.enable_autocomplete_generation,
         autocomplete_generation_input_max_length: config.autocomplete_generation_input_max_length,
+        autocomplete_generation_prompt_template: config
+            .autocomplete_generation_prompt_template
+            .clone(),
         tags_generation_prompt_template: config.tags_generation_prompt_template.clone(),
         follow_up_generation_prompt_template: config.follow_up_generation_prompt_template.clone(),
         enable_follow_up_generation: config.enable_follow_up_generation,
@@ -229,6 +290,8 @@ struct CompletionRequest {
     metadata: Option<serde_json::Value>,
     #[serde(default)]
     model_item: Option<serde_json::Value>,
+    #[serde(default, rename = "type")]
+    completion_type: Option<String>,
 }
 
 /// Generate a title for a chat based on its messages
@@ -237,7 +300,7 @@ async fn generate_title(
     auth_user: AuthUser,
     payload: web::Json<CompletionRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     // Check if title generation is enabled
     if !config.enable_title_generation {
@@ -276,19 +339,27 @@ async fn generate_title(
 
     let prompt = template.replace("{{MESSAGES:END:2}}", &messages_text);
 
-    drop(config); // Release lock before calling completion
+    // Titles may run on their own (cheaper) model and its endpoint
+    let title_model = resolve_title_model(&state, &payload.model, payload.model_item.as_ref());
+    let model_item = if title_model == payload.model {
+        payload.model_item.as_ref()
+    } else {
+        tracing::debug!("Title model override: {} -> {}", payload.model, title_model);
+        None
+    };
 
-    // Call OpenAI API for completion with direct connection support
-    call_openai_completion(
+    let json_response = request_model_completion(
         &state,
         &auth_user,
-        &payload.model,
-        payload.model_item.as_ref(),
+        &title_model,
+        model_item,
         &prompt,
         50,
         0.1,
     )
-    .await
+    .await?;
+
+    Ok(HttpResponse::Ok().json(json_response))
 }
 
 async fn generate_follow_up(
@@ -296,7 +367,7 @@ async fn generate_follow_up(
     auth_user: AuthUser,
     payload: web::Json<CompletionRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_follow_up_generation {
         return Ok(HttpResponse::Ok().json(json!({
@@ -313,8 +384,6 @@ async fn generate_follow_up(
     let messages_text = format_messages(&payload.messages);
     let prompt = template.replace("{{MESSAGES}}", &messages_text);
 
-    drop(config);
-
     call_openai_completion(
         &state,
         &auth_user,
@@ -332,7 +401,7 @@ async fn generate_tags(
     auth_user: AuthUser,
     payload: web::Json<CompletionRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_tags_generation {
         return Ok(HttpResponse::Ok().json(json!({
@@ -349,18 +418,23 @@ async fn generate_tags(
     let messages_text = format_messages(&payload.messages);
     let prompt = template.replace("{{MESSAGES}}", &messages_text);
 
-    drop(config);
-
-    call_openai_completion(
+    let response = request_task_completion(
         &state,
         &auth_user,
         &payload.model,
         payload.model_item.as_ref(),
         &prompt,
-        50,
-        0.5,
+        100,
+        0.1,
     )
-    .await
+    .await?;
+
+    // Normalize the model output so callers always get { "tags": [...] }
+    let tags = extract_completion_content(&response)
+        .map(parse_generated_tags)
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok().json(json!({ "tags": tags })))
 }
 
 async fn generate_image_prompt(
@@ -368,7 +442,7 @@ async fn generate_image_prompt(
     auth_user: AuthUser,
     payload: web::Json<CompletionRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     let template = if config.image_prompt_generation_prompt_template.is_empty() {
         DEFAULT_IMAGE_PROMPT_GENERATION_PROMPT_TEMPLATE.to_string()
@@ -379,8 +453,6 @@ async fn generate_image_prompt(
     let user_prompt = payload.prompt.as_deref().unwrap_or("");
     let prompt = template.replace("{{PROMPT}}", user_prompt);
 
-    drop(config);
-
     call_openai_completion(
         &state,
         &auth_user,
@@ -398,7 +470,7 @@ async fn generate_queries(
     auth_user: AuthUser,
     payload: web::Json<CompletionRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_search_query_generation {
         return Ok(HttpResponse::Ok().json(json!({
@@ -415,8 +487,6 @@ async fn generate_queries(
     let messages_text = format_messages(&payload.messages);
     let prompt = template.replace("{{MESSAGES}}", &messages_text);
 
-    drop(config);
-
     call_openai_completion(
         &state,
         &auth_user,
@@ -429,63 +499,236 @@ async fn generate_queries(
     .await
 }
 
+/// Type-ahead autocomplete for the message composer.
+/// Returns `{ "text": ... }` with only the continuation of the user's input; slow or failing
+/// models yield an empty suggestion so the composer never surfaces an error while typing.
+/// Dropping the request (the client aborting a stale suggestion) cancels the upstream call.
 async fn generate_autocomplete(
     state: web::Data<AppState>,
     auth_user: AuthUser,
     payload: web::Json<CompletionRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_autocomplete_generation {
-        return Ok(HttpResponse::Ok().json(json!({
-            "detail": "Autocomplete generation is disabled"
-        })));
+        return Err(AppError::BadRequest(
+            "Autocomplete generation is disabled".to_string(),
+        ));
     }
 
     let user_prompt = payload.prompt.as_deref().unwrap_or("");
+    if user_prompt.trim().is_empty() {
+        return Ok(HttpResponse::Ok().json(json!({ "text": "" })));
+    }
 
-    // Check max length
-    if user_prompt.len() > config.autocomplete_generation_input_max_length as usize {
-        return Ok(HttpResponse::Ok().json(json!({
-            "detail": "Input too long for autocomplete"
-        })));
+    // Check max length (0 or negative disables the threshold)
+    let max_length = config.autocomplete_generation_input_max_length;
+    if max_length > 0 && user_prompt.chars().count() > max_length as usize {
+        return Err(AppError::BadRequest(format!(
+            "Input prompt exceeds maximum length of {}",
+            max_length
+        )));
     }
 
-    let template = DEFAULT_AUTOCOMPLETE_GENERATION_PROMPT_TEMPLATE;
-    let prompt = template.replace("{{PROMPT}}", user_prompt);
+    let template = if config.autocomplete_generation_prompt_template.is_empty() {
+        DEFAULT_AUTOCOMPLETE_GENERATION_PROMPT_TEMPLATE.to_string()
+    } else {
+        config.autocomplete_generation_prompt_template.clone()
+    };
 
-    drop(config);
+    let completion_type = match payload.completion_type.as_deref() {
+        Some("search") => "search query",
+        _ => "general",
+    };
 
-    call_openai_completion(
-        &state,
-        &auth_user,
-        &payload.model,
-        payload.model_item.as_ref(),
-        &prompt,
-        100,
-        0.7,
+    // Only the most recent messages are useful context for a continuation
+    let recent_messages: Vec<_> = payload
+        .messages
+        .iter()
+        .rev()
+        .take(6)
+        .rev()
+        .cloned()
+        .collect();
+
+    let prompt = template
+        .replace("{{MESSAGES:END:6}}", &format_messages(&recent_messages))
+        .replace("{{TYPE}}", completion_type)
+        .replace("{{PROMPT}}", user_prompt);
+
+    let rate_limit = config.autocomplete_generation_rate_limit;
+    let timeout = std::time::Duration::from_secs(config.autocomplete_generation_timeout);
+
+    // The composer calls this on every typing pause, so throttle per user
+    if rate_limit > 0 {
+        state
+            .autocomplete_rate_limiter
+            .check_rate_limit_at(&auth_user.id, 1, rate_limit)
+            .await
+            .map_err(|_| {
+                AppError::TooManyRequests("Autocomplete rate limit exceeded".to_string())
+            })?;
+    }
+
+    let response = match tokio::time::timeout(
+        timeout,
+        request_task_completion(
+            &state,
+            &auth_user,
+            &payload.model,
+            payload.model_item.as_ref(),
+            &prompt,
+            100,
+            0.3,
+        ),
     )
     .await
+    {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => {
+            tracing::warn!("Autocomplete generation failed: {}", e);
+            return Ok(HttpResponse::Ok().json(json!({ "text": "" })));
+        }
+        Err(_) => {
+            // A late suggestion is useless to the composer; answer with nothing
+            tracing::debug!("Autocomplete timed out after {:?}", timeout);
+            return Ok(HttpResponse::Ok().json(json!({ "text": "" })));
+        }
+    };
+
+    let text = extract_completion_content(&response)
+        .map(|content| extract_autocomplete_text(content, user_prompt))
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok().json(json!({ "text": text })))
+}
+
+/// Pull the continuation out of the model output, dropping any echoed input prefix
+fn extract_autocomplete_text(content: &str, user_prompt: &str) -> String {
+    let parsed = match (content.find('{'), content.rfind('}')) {
+        (Some(start), Some(end)) if start < end => {
+            serde_json::from_str::<serde_json::Value>(&content[start..=end])
+                .ok()
+                .and_then(|v| {
+                    v.get("text")
+                        .or_else(|| v.get("completion"))
+                        .and_then(|t| t.as_str())
+                        .map(String::from)
+                })
+        }
+        _ => None,
+    };
+
+    let text = parsed.unwrap_or_else(|| content.trim().to_string());
+
+    if !user_prompt.is_empty() {
+        if let Some(rest) = text.strip_prefix(user_prompt) {
+            return rest.to_string();
+        }
+        if let Some(rest) = text.strip_prefix(user_prompt.trim_end()) {
+            return rest.to_string();
+        }
+    }
+
+    text
 }
 
+/// Pick a single emoji reflecting the last assistant message (voice call mode).
+/// Returns `{ "emoji": ... }`, falling back to a deterministic emoji on timeout or text output.
 async fn generate_emoji(
     state: web::Data<AppState>,
     auth_user: AuthUser,
     payload: web::Json<CompletionRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let messages_text = format_messages(&payload.messages);
-    let prompt = DEFAULT_EMOJI_GENERATION_PROMPT_TEMPLATE.replace("{{MESSAGES}}", &messages_text);
+    let config = state.config.snapshot();
 
-    call_openai_completion(
-        &state,
-        &auth_user,
-        &payload.model,
-        payload.model_item.as_ref(),
-        &prompt,
-        10,
-        0.5,
+    if !config.enable_emoji_generation {
+        return Err(AppError::BadRequest(
+            "Emoji generation is disabled".to_string(),
+        ));
+    }
+
+    let template = if config.emoji_generation_prompt_template.is_empty() {
+        DEFAULT_EMOJI_GENERATION_PROMPT_TEMPLATE.to_string()
+    } else {
+        config.emoji_generation_prompt_template.clone()
+    };
+
+    // The voice UI sends the reply being spoken; prefer the last assistant message
+    let message = payload
+        .messages
+        .iter()
+        .rev()
+        .find(|m| m.get("role").and_then(|r| r.as_str()) == Some("assistant"))
+        .and_then(|m| m.get("content"))
+        .and_then(|c| c.as_str())
+        .or(payload.prompt.as_deref())
+        .unwrap_or("")
+        .to_string();
+
+    let prompt = template
+        .replace("{{PROMPT}}", &message)
+        .replace("{{MESSAGES}}", &format_messages(&payload.messages));
+
+    let response = tokio::time::timeout(
+        std::time::Duration::from_secs(EMOJI_GENERATION_TIMEOUT_SECS),
+        request_task_completion(
+            &state,
+            &auth_user,
+            &payload.model,
+            payload.model_item.as_ref(),
+            &prompt,
+            10,
+            0.5,
+        ),
     )
-    .await
+    .await;
+
+    let emoji = match response {
+        Ok(Ok(result)) => extract_completion_content(&result)
+            .and_then(extract_emoji)
+            .unwrap_or_else(|| fallback_emoji(&message).to_string()),
+        Ok(Err(e)) => {
+            tracing::debug!("Emoji generation failed, using fallback: {}", e);
+            fallback_emoji(&message).to_string()
+        }
+        Err(_) => {
+            tracing::debug!("Emoji generation timed out, using fallback");
+            fallback_emoji(&message).to_string()
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(json!({ "emoji": emoji })))
+}
+
+/// Return the first emoji grapheme cluster in `text`, keeping multi-codepoint
+/// sequences (ZWJ families, skin tones, flags, keycaps) intact
+fn extract_emoji(text: &str) -> Option<String> {
+    text.graphemes(true)
+        .find(|g| {
+            g.chars().any(is_emoji_char)
+                // Keycap sequences such as 1️⃣ start with an ASCII digit
+                || g.contains('\u{20E3}')
+        })
+        .map(|g| g.to_string())
+}
+
+fn is_emoji_char(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF // Pictographs, emoticons, transport, flags, supplemental symbols
+            | 0x2600..=0x27BF // Miscellaneous symbols and dingbats
+            | 0x2300..=0x23FF // Miscellaneous technical (⌚, ⏰, ...)
+            | 0x2B00..=0x2BFF // Arrows and stars (⭐, ⬆, ...)
+    )
+}
+
+/// Deterministic fallback so the same message always maps to the same emoji
+fn fallback_emoji(text: &str) -> &'static str {
+    let hash = text.bytes().fold(0usize, |acc, b| {
+        acc.wrapping_mul(31).wrapping_add(b as usize)
+    });
+    FALLBACK_EMOJIS[hash % FALLBACK_EMOJIS.len()]
 }
 
 async fn generate_moa(
@@ -546,6 +789,64 @@ async fn call_openai_completion(
     max_tokens: i32,
     temperature: f32,
 ) -> Result<HttpResponse, AppError> {
+    let json_response = request_task_completion(
+        state,
+        auth_user,
+        model,
+        model_item,
+        prompt,
+        max_tokens,
+        temperature,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(json_response))
+}
+
+// Helper returning the raw upstream completion JSON, for tasks that post-process the output.
+// Every task goes through here (follow-ups, tags, image prompts, queries, autocomplete, emoji,
+// MoA and the note tasks), so all of them run on the configured task model, like Python's
+// get_task_model_id. Titles resolve their own model first and call request_model_completion.
+pub(crate) async fn request_task_completion(
+    state: &web::Data<AppState>,
+    auth_user: &AuthUser,
+    model: &str,
+    model_item: Option<&serde_json::Value>,
+    prompt: &str,
+    max_tokens: i32,
+    temperature: f32,
+) -> Result<serde_json::Value, AppError> {
+    // Route the task to the configured task model when one is set
+    let task_model = resolve_task_model(state, model, model_item);
+    let (model, model_item) = if task_model != model {
+        tracing::debug!("Task model override: {} -> {}", model, task_model);
+        (task_model.as_str(), None)
+    } else {
+        (model, model_item)
+    };
+
+    request_model_completion(
+        state,
+        auth_user,
+        model,
+        model_item,
+        prompt,
+        max_tokens,
+        temperature,
+    )
+    .await
+}
+
+// Helper sending a single-prompt completion to `model` as given, without task model routing
+async fn request_model_completion(
+    state: &web::Data<AppState>,
+    auth_user: &AuthUser,
+    model: &str,
+    model_item: Option<&serde_json::Value>,
+    prompt: &str,
+    max_tokens: i32,
+    temperature: f32,
+) -> Result<serde_json::Value, AppError> {
     // Build the chat completion request payload
     let mut completion_payload = json!({
         "model": model,
@@ -568,13 +869,57 @@ async fn call_openai_completion(
         }
     }
 
+    let (request_builder, api_config) =
+        build_task_request(state, auth_user, model, model_item, &completion_payload)?;
+
+    let response = match request_builder.with_request_id().send().await {
+        Ok(response) => into_chat_completion_response(response, &api_config).await,
+        Err(e) => Err(e),
+    };
+    match response {
+        Ok(response) if response.status().is_success() => response
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|e| AppError::InternalServerError(format!("Failed to parse response: {}", e))),
+        Ok(response) => {
+            let status = response.status();
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            tracing::error!("Task completion API error: {} - {}", status, error_text);
+            Err(AppError::InternalServerError(format!(
+                "API call failed with status {}: {}",
+                status, error_text
+            )))
+        }
+        Err(e) => {
+            tracing::error!("Task completion API request error: {}", e);
+            Err(AppError::InternalServerError(format!(
+                "API request failed: {}",
+                e
+            )))
+        }
+    }
+}
+
+/// Build an authenticated chat completion request for a task model carrying `payload`,
+/// routed the same way as chat requests (including direct connections), along with the
+/// endpoint's config to read the response with `into_chat_completion_response`
+pub(crate) fn build_task_request(
+    state: &web::Data<AppState>,
+    auth_user: &AuthUser,
+    model: &str,
+    model_item: Option<&serde_json::Value>,
+    payload: &serde_json::Value,
+) -> Result<(reqwest::RequestBuilder, serde_json::Value), AppError> {
     // Use the existing get_endpoint_and_route_request helper to properly handle direct connections
     let (url, key, api_config) = get_endpoint_and_route_request(
         state,
         auth_user,
         model,
         model_item.cloned().unwrap_or(serde_json::json!({})),
-        &completion_payload,
+        payload,
     )?;
 
     tracing::info!(
@@ -584,62 +929,89 @@ async fn call_openai_completion(
         auth_user.user.email
     );
 
-    // Make the API request
-    let client = reqwest::Client::new();
-    let mut request_builder = client
-        .post(format!("{}/chat/completions", url.trim_end_matches('/')))
-        .header("Content-Type", "application/json");
-
-    // Add authorization header based on auth_type
+    // Authenticate based on auth_type
     let auth_type = api_config
         .get("auth_type")
         .and_then(|v| v.as_str())
         .unwrap_or("bearer");
 
-    match auth_type {
+    let key = match auth_type {
         "none" => {
             // No authentication
             tracing::debug!("Task completion - no authentication required");
+            ""
         }
         _ => {
-            // Default to bearer token for all other cases
-            if !key.is_empty() {
-                request_builder =
-                    request_builder.header("Authorization", format!("Bearer {}", key));
-                tracing::debug!("Task completion - using bearer token authentication");
-            } else {
+            // The key is sent the way the endpoint's API type expects
+            if key.is_empty() {
                 tracing::warn!("Task completion - no API key available for model {}", model);
             }
+            key.as_str()
         }
+    };
+
+    // Make the API request over the shared, pooled client
+    let request_builder =
+        chat_completion_request(&state.http_client, &url, key, &api_config, payload);
+
+    Ok((request_builder, api_config))
+}
+
+// Helper to resolve the model a task should run on (like Python's get_task_model_id):
+// TASK_MODEL for local (Ollama) chat models, TASK_MODEL_EXTERNAL otherwise, falling back
+// to the chat model. Direct-connection models are kept since the task model may not be
+// reachable through the user's connection.
+pub(crate) fn resolve_task_model(
+    state: &web::Data<AppState>,
+    model: &str,
+    model_item: Option<&serde_json::Value>,
+) -> String {
+    let is_direct = model_item
+        .and_then(|item| item.get("direct"))
+        .and_then(|d| d.as_bool())
+        .unwrap_or(false);
+    if is_direct {
+        return model.to_string();
     }
 
-    match request_builder.json(&completion_payload).send().await {
-        Ok(response) if response.status().is_success() => {
-            let json_response = response.json::<serde_json::Value>().await.map_err(|e| {
-                AppError::InternalServerError(format!("Failed to parse response: {}", e))
-            })?;
+    let is_local = state
+        .models_cache
+        .read()
+        .unwrap()
+        .get(model)
+        .and_then(|m| m.get("owned_by"))
+        .and_then(|o| o.as_str())
+        == Some("ollama");
+
+    let config = state.config.snapshot();
+    let task_model = if is_local {
+        config.task_model.as_deref()
+    } else {
+        config.task_model_external.as_deref()
+    };
 
-            Ok(HttpResponse::Ok().json(json_response))
-        }
-        Ok(response) => {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            tracing::error!("Task completion API error: {} - {}", status, error_text);
-            Err(AppError::InternalServerError(format!(
-                "API call failed with status {}: {}",
-                status, error_text
-            )))
-        }
-        Err(e) => {
-            tracing::error!("Task completion API request error: {}", e);
-            Err(AppError::InternalServerError(format!(
-                "API request failed: {}",
-                e
-            )))
-        }
+    task_model
+        .filter(|m| !m.is_empty())
+        .unwrap_or(model)
+        .to_string()
+}
+
+// Helper to resolve the model used for chat titles: TITLE_GENERATION_MODEL when set,
+// otherwise the task model. Direct-connection models are kept, as for other tasks.
+pub(crate) fn resolve_title_model(
+    state: &web::Data<AppState>,
+    model: &str,
+    model_item: Option<&serde_json::Value>,
+) -> String {
+    let is_direct = model_item
+        .and_then(|item| item.get("direct"))
+        .and_then(|d| d.as_bool())
+        .unwrap_or(false);
+
+    let title_model = state.config.snapshot().title_generation_model.clone();
+    match title_model.filter(|m| !m.is_empty()) {
+        Some(title_model) if !is_direct => title_model,
+        _ => resolve_task_model(state, model, model_item),
     }
 }
 
@@ -657,7 +1029,7 @@ fn get_endpoint_and_route_request(
         .and_then(|d| d.as_bool())
         .unwrap_or(false);
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if is_direct && config.enable_direct_connections {
         // Direct connection - look up URL and key from user settings using urlIdx
@@ -701,98 +1073,45 @@ fn get_endpoint_and_route_request(
             url_idx
         );
 
-        // Get user settings to find their direct connections
-        let user_settings = auth_user.user.settings.as_ref()
-            .ok_or_else(|| AppError::BadRequest(
-                "User settings not found. Please configure your direct connections in Settings > Connections.".to_string()
-            ))?;
-
-        // Direct connections can be at settings.directConnections OR settings.ui.directConnections
-        let direct_connections = user_settings.get("directConnections").or_else(|| {
-            user_settings
-                .get("ui")
-                .and_then(|ui| ui.get("directConnections"))
-        });
+        // The user's own connections come first, followed by the instance defaults
+        let connections = DirectConnections::from_user_settings(auth_user.user.settings.as_ref())
+            .merged_with(&DirectConnections::from_value(
+                &config.default_direct_connections,
+            ));
 
-        if direct_connections.is_none() {
+        if connections.is_empty() {
             tracing::warn!(
-                "Direct connections not configured for user {}. User settings structure: {:?}",
-                auth_user.user.email,
-                user_settings
+                "Direct connections not configured for user {}",
+                auth_user.user.email
             );
             return Err(AppError::BadRequest(
                 "Direct connections not configured. Please add your OpenAI API connections in Settings > Connections.".to_string()
             ));
         }
 
-        let direct_connections = direct_connections.unwrap();
-
-        // Extract URL and key arrays from settings
-        let urls = direct_connections.get("OPENAI_API_BASE_URLS")
-            .and_then(|v| v.as_array())
-            .ok_or_else(|| {
-                tracing::error!("OPENAI_API_BASE_URLS missing or not an array in direct connections");
-                AppError::BadRequest("Direct connections configuration is invalid. Please check your Settings > Connections.".to_string())
-            })?;
-
-        let keys = direct_connections.get("OPENAI_API_KEYS")
-            .and_then(|v| v.as_array())
-            .ok_or_else(|| {
-                tracing::error!("OPENAI_API_KEYS missing or not an array in direct connections");
-                AppError::BadRequest("Direct connections configuration is invalid. Please check your Settings > Connections.".to_string())
-            })?;
-
-        let configs = direct_connections.get("OPENAI_API_CONFIGS")
-            .and_then(|v| v.as_object())
-            .ok_or_else(|| {
-                tracing::error!("OPENAI_API_CONFIGS missing or not an object in direct connections");
-                AppError::BadRequest("Direct connections configuration is invalid. Please check your Settings > Connections.".to_string())
-            })?;
-
         // Parse urlIdx as number
         let idx: usize = url_idx
             .parse()
             .map_err(|_| AppError::BadRequest(format!("Invalid urlIdx: {}", url_idx)))?;
 
-        tracing::debug!(
-            "Looking up connection at index {} from {} total URLs",
-            idx,
-            urls.len()
-        );
-
-        // Get URL and key at that index
-        let connection_url = urls.get(idx)
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| {
-                tracing::error!("No URL found at index {}. Available URLs: {} total", idx, urls.len());
+        let (connection_url, connection_key, connection_config) =
+            connections.get(idx).ok_or_else(|| {
                 AppError::BadRequest(format!(
-                    "Connection not found at index {}. You have {} connection(s) configured. Please check your Settings > Connections.", 
-                    idx, urls.len()
+                    "Connection not found at index {}. You have {} connection(s) configured. Please check your Settings > Connections.",
+                    idx,
+                    connections.base_urls.len()
                 ))
             })?;
 
-        let connection_key = keys.get(idx).and_then(|v| v.as_str()).unwrap_or("");
-
-        // Get config for this connection (might be at string index)
-        let connection_config = configs
-            .get(&url_idx)
-            .or_else(|| configs.get(connection_url))
-            .cloned()
-            .unwrap_or(serde_json::json!({}));
-
         tracing::info!(
-            "Task using direct connection from user settings: {} (idx: {}) for model {} by user {}",
+            "Task using direct connection: {} (idx: {}) for model {} by user {}",
             connection_url,
             idx,
             model_id,
             auth_user.user.email
         );
 
-        Ok((
-            connection_url.to_string(),
-            connection_key.to_string(),
-            connection_config,
-        ))
+        Ok((connection_url, connection_key, connection_config))
     } else if is_direct && !config.enable_direct_connections {
         // Direct requested but not enabled - return error message
         Err(AppError::BadRequest(
@@ -800,50 +1119,26 @@ fn get_endpoint_and_route_request(
         ))
     } else {
         // Regular (non-direct) routing
-        // SMART FALLBACK: If user has direct connections configured, use their first one
-        // This allows tasks to work even when frontend doesn't pass model_item
+        // SMART FALLBACK: use the first direct connection, preferring the user's own over
+        // the instance defaults. This allows tasks to work even when frontend doesn't pass
+        // model_item
         if config.enable_direct_connections {
-            if let Some(user_settings) = auth_user.user.settings.as_ref() {
-                let direct_connections = user_settings.get("directConnections").or_else(|| {
-                    user_settings
-                        .get("ui")
-                        .and_then(|ui| ui.get("directConnections"))
-                });
-
-                if let Some(dc) = direct_connections {
-                    if let (Some(urls), Some(keys)) = (
-                        dc.get("OPENAI_API_BASE_URLS").and_then(|v| v.as_array()),
-                        dc.get("OPENAI_API_KEYS").and_then(|v| v.as_array()),
-                    ) {
-                        if !urls.is_empty() {
-                            let first_url = urls[0].as_str().unwrap_or("");
-                            let first_key = keys.get(0).and_then(|k| k.as_str()).unwrap_or("");
-
-                            if !first_url.is_empty() {
-                                let configs =
-                                    dc.get("OPENAI_API_CONFIGS").and_then(|v| v.as_object());
-
-                                let first_config = configs
-                                    .and_then(|c| c.get("0").or_else(|| c.get(first_url)))
-                                    .cloned()
-                                    .unwrap_or(serde_json::json!({}));
-
-                                tracing::info!(
-                                    "Task using user's first direct connection (smart fallback): {} for model {} by user {}",
-                                    first_url,
-                                    model_id,
-                                    auth_user.user.email
-                                );
-
-                                return Ok((
-                                    first_url.to_string(),
-                                    first_key.to_string(),
-                                    first_config,
-                                ));
-                            }
-                        }
-                    }
-                }
+            let first = DirectConnections::from_user_settings(auth_user.user.settings.as_ref())
+                .merged_with(&DirectConnections::from_value(
+                    &config.default_direct_connections,
+                ))
+                .get(0)
+                .filter(|(first_url, _, _)| !first_url.is_empty());
+
+            if let Some((first_url, first_key, first_config)) = first {
+                tracing::info!(
+                    "Task using first direct connection (smart fallback): {} for model {} by user {}",
+                    first_url,
+                    model_id,
+                    auth_user.user.email
+                );
+
+                return Ok((first_url, first_key, first_config));
             }
         }
 
@@ -912,20 +1207,6 @@ JSON format: { "title": "your concise title here" }
 {{MESSAGES:END:2}}
 </chat_history>"#;
 
-const DEFAULT_FOLLOW_UP_GENERATION_PROMPT_TEMPLATE: &str = r#"### Task:
-Generate 3-5 relevant follow-up questions based on the conversation.
-### Output:
-JSON format: { "questions": ["question 1", "question 2", "question 3"] }
-### Chat History:
-{{MESSAGES}}"#;
-
-const DEFAULT_TAGS_GENERATION_PROMPT_TEMPLATE: &str = r#"### Task:
-Generate 3-5 relevant tags for categorizing this conversation.
-### Output:
-JSON format: { "tags": ["tag1", "tag2", "tag3"] }
-### Chat History:
-{{MESSAGES}}"#;
-
 const DEFAULT_IMAGE_PROMPT_GENERATION_PROMPT_TEMPLATE: &str = r#"### Task:
 Enhance the following prompt for image generation, making it more detailed and descriptive.
 ### Original Prompt:
@@ -941,18 +1222,32 @@ JSON format: { "queries": ["query 1", "query 2"] }
 {{MESSAGES}}"#;
 
 const DEFAULT_AUTOCOMPLETE_GENERATION_PROMPT_TEMPLATE: &str = r#"### Task:
-Complete the following text naturally and concisely.
-### Input:
-{{PROMPT}}
+You are an autocompletion system. Continue the text in `<text>` based on the **completion type** in `<type>` and the given language.
+### Instructions:
+1. Analyze `<text>` for context and meaning.
+2. Use `<type>` to guide your output:
+   - **General**: Provide a natural, concise continuation.
+   - **Search Query**: Complete as if generating a realistic search query.
+3. Start as if you are directly continuing `<text>`. Do **not** repeat, paraphrase, or respond as a model. Simply complete the text.
+4. Keep the continuation short and avoid unrelated ideas.
+5. If unsure, return: { "text": "" }
 ### Output:
-JSON format: { "completion": "completed text here" }"#;
+JSON format: { "text": "<your_completion>" }
+### Context:
+<chat_history>
+{{MESSAGES:END:6}}
+</chat_history>
+<type>{{TYPE}}</type>
+<text>{{PROMPT}}</text>"#;
 
-const DEFAULT_EMOJI_GENERATION_PROMPT_TEMPLATE: &str = r#"### Task:
-Suggest a single emoji that best represents the mood or topic of this conversation.
-### Output:
-JSON format: { "emoji": "😊" }
-### Chat History:
-{{MESSAGES}}"#;
+const DEFAULT_EMOJI_GENERATION_PROMPT_TEMPLATE: &str = r#"Your task is to reflect the speaker's likely facial expression through a fitting emoji. Interpret emotions from the message and reflect their facial expression using fitting, diverse emojis (e.g., 😊, 😢, 😡, 😱).
+
+Message: ```{{PROMPT}}```"#;
+
+// Emoji task timeout; the voice UI shows the emoji while speaking, so late answers are useless
+const EMOJI_GENERATION_TIMEOUT_SECS: u64 = 5;
+
+const FALLBACK_EMOJIS: &[&str] = &["🙂", "😊", "🤔", "😄", "👍", "💡", "😌", "🙌"];
 
 const DEFAULT_MOA_GENERATION_PROMPT_TEMPLATE: &str = r#"### Task:
 Synthesize the following responses into a single, coherent answer to the query.
@@ -962,3 +1257,148 @@ Synthesize the following responses into a single, coherent answer to the query.
 {{RESPONSES}}
 ### Output:
 Provide a synthesized response that combines the best aspects of all responses."#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_utils::{self, test_state};
+    use actix_web::{http::header, App, HttpServer};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_extract_emoji_multi_codepoint() {
+        // ZWJ family sequence
+        assert_eq!(
+            extract_emoji("Here you go: 👨\u{200D}👩\u{200D}👧 family").as_deref(),
+            Some("👨\u{200D}👩\u{200D}👧")
+        );
+        // Skin tone modifier
+        assert_eq!(extract_emoji("👍🏽").as_deref(), Some("👍🏽"));
+        // Regional indicator flag
+        assert_eq!(extract_emoji("🇯🇵!").as_deref(), Some("🇯🇵"));
+        // Variation selector and keycap
+        assert_eq!(extract_emoji("❤\u{FE0F}").as_deref(), Some("❤\u{FE0F}"));
+        assert_eq!(
+            extract_emoji("1\u{FE0F}\u{20E3}").as_deref(),
+            Some("1\u{FE0F}\u{20E3}")
+        );
+    }
+
+    #[test]
+    fn test_extract_emoji_text_fallback() {
+        assert_eq!(extract_emoji("happy"), None);
+        assert_eq!(fallback_emoji("happy"), fallback_emoji("happy"));
+        assert!(FALLBACK_EMOJIS.contains(&fallback_emoji("happy")));
+    }
+
+    /// Upstream recording the model each completion was sent to
+    async fn recording_upstream(
+        models: web::Data<Mutex<Vec<String>>>,
+        body: web::Json<serde_json::Value>,
+    ) -> HttpResponse {
+        let model = body["model"].as_str().unwrap_or_default().to_string();
+        models.lock().unwrap().push(model);
+        HttpResponse::Ok().json(json!({
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "😀"}}]
+        }))
+    }
+
+    #[actix_web::test]
+    async fn test_each_task_runs_on_the_task_model() {
+        use actix_web::test;
+
+        let models = web::Data::new(Mutex::new(Vec::<String>::new()));
+        let upstream_models = models.clone();
+        let upstream = HttpServer::new(move || {
+            App::new()
+                .app_data(upstream_models.clone())
+                .route("/chat/completions", web::post().to(recording_upstream))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let upstream_url = format!("http://{}", upstream.addrs()[0]);
+        actix_web::rt::spawn(upstream.run());
+
+        let mut config = Config::from_env().unwrap();
+        config.openai_api_base_urls = vec![upstream_url];
+        config.enable_direct_connections = false;
+        config.task_model_external = Some("task-model".to_string());
+        config.title_generation_model = None;
+        config.enable_title_generation = true;
+        config.enable_tags_generation = true;
+        config.enable_search_query_generation = true;
+        config.enable_autocomplete_generation = true;
+        config.enable_emoji_generation = true;
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(&dir, config).await;
+        test_utils::create_user(&state.db, "user-1", "user").await;
+        let token = test_utils::user_token(&state, "user-1");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(web::scope("/api/v1/tasks").configure(create_routes)),
+        )
+        .await;
+
+        let payload = json!({
+            "model": "chat-model",
+            "messages": [
+                {"role": "user", "content": "Hello"},
+                {"role": "assistant", "content": "Hi there"}
+            ],
+            "prompt": "What is",
+        });
+        let moa = json!({
+            "model": "chat-model",
+            "query": "Hello",
+            "responses": [{"content": "Hi"}],
+        });
+        for (task, body) in [
+            ("title", &payload),
+            ("follow_up", &payload),
+            ("tags", &payload),
+            ("image_prompt", &payload),
+            ("queries", &payload),
+            ("auto", &payload),
+            ("emoji", &payload),
+            ("moa", &moa),
+        ] {
+            let req = test::TestRequest::post()
+                .uri(&format!("/api/v1/tasks/{}/completions", task))
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+                .set_json(body)
+                .to_request();
+            let response = test::call_service(&app, req).await;
+            assert!(response.status().is_success(), "{}", task);
+
+            let sent = models.lock().unwrap().pop();
+            assert_eq!(sent.as_deref(), Some("task-model"), "{}", task);
+        }
+    }
+
+    #[test]
+    fn test_extract_autocomplete_text_json() {
+        let content = r#"{ "text": " the weather today?" }"#;
+        assert_eq!(
+            extract_autocomplete_text(content, "What is"),
+            " the weather today?"
+        );
+    }
+
+    #[test]
+    fn test_extract_autocomplete_text_strips_echoed_prefix() {
+        let content = r#"{ "text": "What is the weather today?" }"#;
+        assert_eq!(
+            extract_autocomplete_text(content, "What is"),
+            " the weather today?"
+        );
+        assert_eq!(
+            extract_autocomplete_text("How do I cook rice", "How do I "),
+            "cook rice"
+        );
+    }
+}
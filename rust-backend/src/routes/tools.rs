@@ -7,12 +7,15 @@ use validator::Validate;
 use crate::error::{AppError, AppResult};
 use crate::middleware::{AuthMiddleware, AuthUser};
 use crate::models::tool::ToolUserResponse;
-use crate::models::tool_runtime::{ExecutionContext, ToolExecutionRequest, UserContext};
+use crate::models::tool_runtime::{
+    ExecutionContext, ToolDefinition, ToolExecutionRequest, UserContext,
+};
 use crate::services::group::GroupService;
 use crate::services::tool::ToolService;
 use crate::services::tool_runtime::ToolRuntimeService;
 use crate::services::user::UserService;
-use crate::utils::misc::{has_access, has_permission};
+use crate::utils::misc::has_access;
+use crate::utils::permissions::user_has_permission;
 use crate::AppState;
 
 /// Parse JSON tool definition and extract OpenAI-compatible function specs
@@ -116,6 +119,71 @@ struct LoadUrlForm {
     url: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct ValidateToolForm {
+    content: String,
+}
+
+/// Whether OpenAI accepts `name` as a function name
+fn is_valid_function_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Parse a tool definition and build its OpenAI function specs, returning them with the
+/// tool names, or the problems found with the line and column of parse errors
+fn validate_tool_definition(content: &str) -> Value {
+    let tool_def = match ToolDefinition::from_json(content) {
+        Ok(tool_def) => tool_def,
+        Err(e) => {
+            return json!({
+                "valid": false,
+                "errors": [{
+                    "message": e.to_string(),
+                    "line": e.line(),
+                    "column": e.column(),
+                }]
+            });
+        }
+    };
+
+    let mut errors = Vec::new();
+    if tool_def.tools.is_empty() {
+        errors.push(json!({"message": "The definition has no tools"}));
+    }
+    let mut seen = HashSet::new();
+    for tool in &tool_def.tools {
+        if !is_valid_function_name(&tool.name) {
+            errors.push(json!({
+                "message": format!(
+                    "Tool name '{}' must be 1 to 64 letters, digits, underscores or dashes",
+                    tool.name
+                ),
+                "tool": tool.name,
+            }));
+        }
+        if !seen.insert(tool.name.as_str()) {
+            errors.push(json!({
+                "message": format!("Tool name '{}' is used more than once", tool.name),
+                "tool": tool.name,
+            }));
+        }
+    }
+    if !errors.is_empty() {
+        return json!({"valid": false, "errors": errors});
+    }
+
+    let tool_names: Vec<&str> = tool_def.tools.iter().map(|t| t.name.as_str()).collect();
+    json!({
+        "valid": true,
+        "name": tool_def.name,
+        "tool_names": tool_names,
+        "specs": tool_def.to_openai_specs(),
+    })
+}
+
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("")
@@ -207,6 +275,11 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AuthMiddleware)
             .route(web::post().to(import_tools)),
     )
+    .service(
+        web::resource("/validate")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(validate_tool)),
+    )
     .service(
         web::resource("/id/{id}/test")
             .wrap(AuthMiddleware)
@@ -275,7 +348,7 @@ fn github_url_to_raw_url(url: &str) -> String {
 
 // POST /load/url - Load tool from URL (admin only)
 async fn load_tool_from_url(
-    _state: web::Data<AppState>,
+    state: web::Data<AppState>,
     auth_user: AuthUser,
     form: web::Json<LoadUrlForm>,
 ) -> AppResult<HttpResponse> {
@@ -306,13 +379,10 @@ async fn load_tool_from_url(
     };
 
     // Fetch content from URL
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()
-        .map_err(|e| AppError::BadRequest(format!("Failed to create HTTP client: {}", e)))?;
-
-    let response = client
+    let response = state
+        .http_client
         .get(&url)
+        .timeout(std::time::Duration::from_secs(30))
         .send()
         .await
         .map_err(|e| AppError::BadRequest(format!("Failed to fetch URL: {}", e)))?;
@@ -346,9 +416,8 @@ async fn get_tools(state: web::Data<AppState>, auth_user: AuthUser) -> AppResult
     let tool_service = ToolService::new(&state.db);
     let user_service = UserService::new(&state.db);
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let bypass_admin_access = config.bypass_admin_access_control.unwrap_or(false);
-    drop(config);
 
     let tools = if auth_user.user.role == "admin" && bypass_admin_access {
         tool_service.get_all_tools().await?
@@ -414,9 +483,8 @@ async fn get_tool_list(state: web::Data<AppState>, auth_user: AuthUser) -> AppRe
     let tool_service = ToolService::new(&state.db);
     let user_service = UserService::new(&state.db);
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let bypass_admin_access = config.bypass_admin_access_control.unwrap_or(false);
-    drop(config);
 
     let tools = if auth_user.user.role == "admin" && bypass_admin_access {
         tool_service.get_all_tools().await?
@@ -493,17 +561,22 @@ async fn create_new_tool(
 ) -> AppResult<HttpResponse> {
     // Check workspace.tools permission
     if auth_user.user.role != "admin" {
-        let config = state.config.read().unwrap();
+        let config = state.config.snapshot();
         let user_permissions = config.user_permissions.clone();
-        drop(config);
 
-        if !has_permission(&auth_user.user.id, "workspace.tools", &user_permissions) {
+        if !user_has_permission(
+            &state.db,
+            &user_permissions,
+            &auth_user.user.id,
+            "workspace.tools",
+        )
+        .await?
+        {
             return Err(AppError::Unauthorized("Unauthorized".to_string()));
         }
     }
 
-    form.validate()
-        .map_err(|e| AppError::Validation(e.to_string()))?;
+    form.validate()?;
 
     // Validate tool ID (alphanumeric and underscores only)
     if !form.id.chars().all(|c| c.is_alphanumeric() || c == '_') {
@@ -812,25 +885,10 @@ async fn execute_tool(
         }
     }
 
-    // Get environment variables from config or form
-    let mut environment = form.environment.clone();
-
-    // Add system environment variables if needed
-    if let Ok(val) = std::env::var("OPENWEATHER_API_KEY") {
-        environment
-            .entry("OPENWEATHER_API_KEY".to_string())
-            .or_insert(val);
-    }
-
-    // Build execution context
+    // Build execution context; the runtime adds the configured secrets the tool declares
     let context = ExecutionContext {
-        user: Some(UserContext {
-            id: auth_user.user.id.clone(),
-            name: auth_user.user.name.clone(),
-            email: auth_user.user.email.clone(),
-            role: Some(auth_user.user.role.clone()),
-        }),
-        environment,
+        user: Some(UserContext::from(&auth_user.user)),
+        environment: form.environment.clone(),
         session: HashMap::new(),
     };
 
@@ -843,7 +901,15 @@ async fn execute_tool(
     };
 
     // Execute tool
-    let runtime_service = ToolRuntimeService::new();
+    let config = state.config.snapshot();
+    let runtime_service = ToolRuntimeService::new()
+        .with_execution_timeout(std::time::Duration::from_secs(
+            config.tool_execution_timeout,
+        ))
+        .with_secrets(
+            config.tool_secrets.clone(),
+            config.tool_secret_grants.clone(),
+        );
     let response = runtime_service.execute_tool(&state.db, request).await?;
 
     Ok(HttpResponse::Ok().json(response))
@@ -890,28 +956,23 @@ async fn execute_tool_chain(
         }
     }
 
-    // Get environment variables
-    let mut environment = form.environment.clone();
-    if let Ok(val) = std::env::var("OPENWEATHER_API_KEY") {
-        environment
-            .entry("OPENWEATHER_API_KEY".to_string())
-            .or_insert(val);
-    }
-
-    // Build execution context
+    // Build execution context; the runtime adds the configured secrets the tool declares
     let context = ExecutionContext {
-        user: Some(UserContext {
-            id: auth_user.user.id.clone(),
-            name: auth_user.user.name.clone(),
-            email: auth_user.user.email.clone(),
-            role: Some(auth_user.user.role.clone()),
-        }),
-        environment,
+        user: Some(UserContext::from(&auth_user.user)),
+        environment: form.environment.clone(),
         session: HashMap::new(),
     };
 
     // Execute tool chain
-    let runtime_service = ToolRuntimeService::new();
+    let config = state.config.snapshot();
+    let runtime_service = ToolRuntimeService::new()
+        .with_execution_timeout(std::time::Duration::from_secs(
+            config.tool_execution_timeout,
+        ))
+        .with_secrets(
+            config.tool_secrets.clone(),
+            config.tool_secret_grants.clone(),
+        );
     let response = runtime_service
         .execute_tool_chain(
             &state.db,
@@ -951,11 +1012,17 @@ async fn import_tools(
 ) -> AppResult<HttpResponse> {
     // Check admin or workspace.tools permission
     if auth_user.user.role != "admin" {
-        let config = state.config.read().unwrap();
+        let config = state.config.snapshot();
         let user_permissions = config.user_permissions.clone();
-        drop(config);
 
-        if !has_permission(&auth_user.user.id, "workspace.tools", &user_permissions) {
+        if !user_has_permission(
+            &state.db,
+            &user_permissions,
+            &auth_user.user.id,
+            "workspace.tools",
+        )
+        .await?
+        {
             return Err(AppError::Unauthorized("Unauthorized".to_string()));
         }
     }
@@ -1057,6 +1124,14 @@ async fn import_tools(
     })))
 }
 
+// POST /validate - Check a tool definition without saving it
+async fn validate_tool(
+    _auth_user: AuthUser,
+    form: web::Json<ValidateToolForm>,
+) -> AppResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(validate_tool_definition(&form.content)))
+}
+
 // POST /id/{id}/test - Test tool execution with sample parameters
 #[derive(Debug, Deserialize)]
 struct TestToolForm {
@@ -1098,24 +1173,10 @@ async fn test_tool(
         }
     }
 
-    // Build execution context
-    let mut environment = form.environment.clone();
-
-    // Add system environment variables
-    for key in &["OPENWEATHER_API_KEY", "OPENAI_API_KEY", "ANTHROPIC_API_KEY"] {
-        if let Ok(val) = std::env::var(key) {
-            environment.entry(key.to_string()).or_insert(val);
-        }
-    }
-
+    // Build execution context; the runtime adds the configured secrets the tool declares
     let context = ExecutionContext {
-        user: Some(UserContext {
-            id: auth_user.user.id.clone(),
-            name: auth_user.user.name.clone(),
-            email: auth_user.user.email.clone(),
-            role: Some(auth_user.user.role.clone()),
-        }),
-        environment,
+        user: Some(UserContext::from(&auth_user.user)),
+        environment: form.environment.clone(),
         session: HashMap::new(),
     };
 
@@ -1129,7 +1190,15 @@ async fn test_tool(
 
     // Execute tool
     let start_time = std::time::Instant::now();
-    let runtime_service = ToolRuntimeService::new();
+    let config = state.config.snapshot();
+    let runtime_service = ToolRuntimeService::new()
+        .with_execution_timeout(std::time::Duration::from_secs(
+            config.tool_execution_timeout,
+        ))
+        .with_secrets(
+            config.tool_secrets.clone(),
+            config.tool_secret_grants.clone(),
+        );
     let response = runtime_service.execute_tool(&state.db, request).await?;
     let execution_time = start_time.elapsed();
 
@@ -1230,11 +1299,17 @@ async fn install_from_library(
 ) -> AppResult<HttpResponse> {
     // Check admin or workspace.tools permission
     if auth_user.user.role != "admin" {
-        let config = state.config.read().unwrap();
+        let config = state.config.snapshot();
         let user_permissions = config.user_permissions.clone();
-        drop(config);
 
-        if !has_permission(&auth_user.user.id, "workspace.tools", &user_permissions) {
+        if !user_has_permission(
+            &state.db,
+            &user_permissions,
+            &auth_user.user.id,
+            "workspace.tools",
+        )
+        .await?
+        {
             return Err(AppError::Unauthorized("Unauthorized".to_string()));
         }
     }
@@ -2208,3 +2283,61 @@ async fn generate_from_builder(
         "parsed": tool_content
     })))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_definitions_return_their_specs() {
+        let content = r#"{
+            "name": "Weather",
+            "tools": [{
+                "name": "get_weather",
+                "description": "Current weather",
+                "type": "http_api",
+                "parameters": {
+                    "city": {"type": "string", "description": "City", "required": true}
+                },
+                "handler": {"type": "http", "method": "GET", "url": "https://example.com"}
+            }]
+        }"#;
+
+        let result = validate_tool_definition(content);
+        assert_eq!(result["valid"], true, "{}", result);
+        assert_eq!(result["tool_names"], json!(["get_weather"]));
+        let parameters = &result["specs"][0]["parameters"];
+        assert_eq!(parameters["properties"]["city"]["type"], "string");
+        assert_eq!(parameters["required"], json!(["city"]));
+    }
+
+    #[test]
+    fn test_invalid_definitions_report_where() {
+        let result = validate_tool_definition("{\n  \"name\": \"Broken\",\n  \"tools\": [\n}");
+        assert_eq!(result["valid"], false);
+        assert_eq!(result["errors"][0]["line"], 4);
+        assert_eq!(result["errors"][0]["column"], 1);
+
+        // Missing required fields are reported too
+        let result = validate_tool_definition(r#"{"name": "No tools"}"#);
+        assert_eq!(result["valid"], false);
+        assert!(result["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("missing field `tools`"));
+
+        let tool = json!({
+            "name": "bad name",
+            "description": "",
+            "type": "expression",
+            "handler": {"type": "expression", "engine": "evalexpr", "expression": "1"}
+        });
+        let content = json!({"name": "Dupes", "tools": [tool, tool]}).to_string();
+        let errors = validate_tool_definition(&content)["errors"].clone();
+        assert_eq!(errors.as_array().unwrap().len(), 3);
+        assert!(errors[2]["message"]
+            .as_str()
+            .unwrap()
+            .contains("used more than once"));
+    }
+}
@@ -0,0 +1,70 @@
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use serde_json::json;
+
+use crate::error::AppResult;
+use crate::middleware::{AuthMiddleware, AuthUser};
+use crate::services::usage::{BudgetStatus, TokenBudget, UsageService};
+use crate::AppState;
+
+pub fn create_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/me")
+            .wrap(AuthMiddleware)
+            .route(web::get().to(get_my_usage)),
+    );
+}
+
+// GET /me - Token usage and remaining budget for the current period
+async fn get_my_usage(state: web::Data<AppState>, auth_user: AuthUser) -> AppResult<HttpResponse> {
+    let budget = {
+        let config = state.config.snapshot();
+        TokenBudget::from_config(&config)
+    };
+
+    let now = Utc::now();
+    let period_key = budget.period.key(now);
+    let usage = UsageService::new(&state.db)
+        .get_usage_for_period(&auth_user.user.id, &period_key)
+        .await?;
+
+    let prompt_tokens: i64 = usage.iter().map(|u| u.prompt_tokens).sum();
+    let completion_tokens: i64 = usage.iter().map(|u| u.completion_tokens).sum();
+    let total_tokens = prompt_tokens + completion_tokens;
+
+    let models: Vec<serde_json::Value> = usage
+        .iter()
+        .map(|u| {
+            json!({
+                "model_id": u.model_id,
+                "prompt_tokens": u.prompt_tokens,
+                "completion_tokens": u.completion_tokens,
+                "total_tokens": u.total_tokens(),
+                "budget": budget
+                    .model_limit(&u.model_id)
+                    .map(|limit| BudgetStatus::new(limit, u.total_tokens())),
+            })
+        })
+        .collect();
+
+    // Models with a limit but no usage yet still report their full allowance
+    let unused_model_budgets: serde_json::Map<String, serde_json::Value> = budget
+        .model_limits
+        .iter()
+        .filter(|(model_id, _)| !usage.iter().any(|u| &u.model_id == *model_id))
+        .map(|(model_id, limit)| (model_id.clone(), json!(BudgetStatus::new(*limit, 0))))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "period": budget.period,
+        "period_key": period_key,
+        "resets_at": budget.period.resets_at(now),
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": total_tokens,
+        "budget": (budget.user_limit > 0)
+            .then(|| BudgetStatus::new(budget.user_limit, total_tokens)),
+        "models": models,
+        "model_budgets": unused_model_budgets,
+    })))
+}
@@ -4,7 +4,9 @@ use serde_json::json;
 
 use crate::error::AppResult;
 use crate::middleware::{AuthMiddleware, AuthUser};
+use crate::models::group::GroupResponse;
 use crate::models::{UpdateUserRoleRequest, UserResponse};
+use crate::services::group::GroupService;
 use crate::services::UserService;
 use crate::AppState;
 
@@ -105,14 +107,22 @@ async fn get_all_users(state: web::Data<AppState>, auth_user: AuthUser) -> AppRe
     })))
 }
 
-// Get active users (returns list of active user IDs)
+// Get active users (returns list of IDs of users connected over Socket.IO)
 async fn get_active_users(
-    _state: web::Data<AppState>,
-    _auth_user: AuthUser,
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
 ) -> AppResult<HttpResponse> {
-    // TODO: Implement active users tracking via WebSocket/Socket.IO state
-    // For now, return empty list
-    Ok(HttpResponse::Ok().json(json!({ "user_ids": [] })))
+    if auth_user.user.role != "admin" {
+        return Err(crate::error::AppError::Forbidden(
+            "Admin access required".to_string(),
+        ));
+    }
+
+    let user_ids = match &state.socketio_handler {
+        Some(handler) => handler.active_user_ids().await,
+        None => Vec::new(),
+    };
+    Ok(HttpResponse::Ok().json(json!({ "user_ids": user_ids })))
 }
 
 // Search users by query
@@ -137,23 +147,30 @@ async fn search_users(
 
 // Get current user's groups
 async fn get_user_groups(
-    _state: web::Data<AppState>,
-    _auth_user: AuthUser,
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
 ) -> AppResult<HttpResponse> {
-    // TODO: Implement groups retrieval
-    Ok(HttpResponse::Ok().json(Vec::<serde_json::Value>::new()))
+    let groups = GroupService::new(&state.db)
+        .get_groups_by_member_id(&auth_user.user.id)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(
+        groups
+            .into_iter()
+            .map(GroupResponse::from)
+            .collect::<Vec<_>>(),
+    ))
 }
 
-// Get current user's permissions
+// Get current user's permissions: the defaults merged with those of their groups
 async fn get_user_permissions(
     state: web::Data<AppState>,
     auth_user: AuthUser,
 ) -> AppResult<HttpResponse> {
-    let config = state.config.read().unwrap();
-
-    // TODO: Implement actual permission checking based on user groups and user-specific permissions
-    // For now, return default permissions
-    let permissions = config.user_permissions.clone();
+    let defaults = state.config.snapshot().user_permissions.clone();
+    let permissions =
+        crate::utils::permissions::get_user_permissions(&state.db, &defaults, &auth_user.user.id)
+            .await?;
 
     Ok(HttpResponse::Ok().json(permissions))
 }
@@ -225,7 +242,7 @@ async fn get_user_profile_image(
     }
 
     // Return default user avatar
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
     let static_dir = &config.static_dir;
     let user_avatar_path = std::path::Path::new(static_dir).join("user.png");
     
@@ -251,12 +268,15 @@ async fn get_user_profile_image(
 
 // Get user active status
 async fn get_user_active_status(
-    _state: web::Data<AppState>,
+    state: web::Data<AppState>,
     _auth_user: AuthUser,
-    _id: web::Path<String>,
+    id: web::Path<String>,
 ) -> AppResult<HttpResponse> {
-    // TODO: Get from Socket.IO state
-    Ok(HttpResponse::Ok().json(json!({ "active": false })))
+    let active = match &state.socketio_handler {
+        Some(handler) => handler.active_user_ids().await.contains(&*id),
+        None => false,
+    };
+    Ok(HttpResponse::Ok().json(json!({ "active": active })))
 }
 
 // Get user groups by ID (admin only)
@@ -542,7 +562,7 @@ async fn get_default_user_permissions(
         ));
     }
 
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     // Parse user_permissions from config or return defaults
     let permissions = if let Some(workspace) = config.user_permissions.get("workspace") {
@@ -613,7 +633,7 @@ async fn update_default_user_permissions(
         ));
     }
 
-    let mut config = state.config.write().unwrap();
+    let mut config = state.config.write();
     config.user_permissions = form_data.into_inner();
 
     // TODO: Persist to database
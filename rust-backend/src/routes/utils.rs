@@ -5,6 +5,8 @@ use std::sync::Arc;
 use crate::{
     error::{AppError, AppResult},
     middleware::{AdminMiddleware, AuthMiddleware, AuthUser},
+    models::audit::AuditLogFilter,
+    services::audit::AuditService,
     AppState,
 };
 
@@ -25,6 +27,11 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
                 web::scope("/db")
                     .wrap(AdminMiddleware)
                     .route("/download", web::get().to(download_db)),
+            )
+            .service(
+                web::scope("/audit")
+                    .wrap(AdminMiddleware)
+                    .route("", web::get().to(get_audit_log)),
             ),
     );
 }
@@ -167,7 +174,7 @@ async fn execute_code(
     auth_user: AuthUser, // AdminMiddleware already checked
     form_data: web::Json<CodeForm>,
 ) -> AppResult<HttpResponse> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_code_execution {
         return Err(AppError::BadRequest(
@@ -177,17 +184,15 @@ async fn execute_code(
 
     let engine = config.code_execution_engine.clone();
     let timeout = config.code_execution_sandbox_timeout;
-    drop(config);
 
     match engine.as_str() {
         "sandbox" => {
             // Use sandbox executor for code execution
             // Get the current sandbox URL from config (supports dynamic updates)
-            let config = state.config.read().unwrap();
+            let config = state.config.snapshot();
             let sandbox_url = config.code_execution_sandbox_url.clone().ok_or_else(|| {
                 AppError::InternalServerError("Sandbox executor URL not configured".to_string())
             })?;
-            drop(config);
 
             // Create a new client or use the existing one with URL validation
             let client = if let Some(existing_client) = state.sandbox_executor_client.as_ref() {
@@ -247,7 +252,7 @@ async fn download_db(
     state: web::Data<AppState>,
     _auth_user: AuthUser, // AdminMiddleware already checked
 ) -> AppResult<HttpResponse> {
-    let config = state.config.read().unwrap();
+    let config = state.config.snapshot();
 
     if !config.enable_admin_export {
         return Err(AppError::Unauthorized(
@@ -258,9 +263,51 @@ async fn download_db(
     // Check if database is SQLite
     // For PostgreSQL, this endpoint doesn't make sense
     // TODO: Implement SQLite database file download if using SQLite
-    drop(config);
 
     Err(AppError::NotImplemented(
         "Database download only supported for SQLite".to_string(),
     ))
 }
+
+/// Audit log entries per page
+const AUDIT_LOG_PAGE_SIZE: i64 = 50;
+
+#[derive(Debug, Deserialize)]
+struct AuditLogQuery {
+    user_id: Option<String>,
+    path: Option<String>,
+    /// Unix timestamp (seconds)
+    since: Option<i64>,
+    page: Option<i64>,
+}
+
+/// GET /audit - Query the audit log, newest first (admin only)
+async fn get_audit_log(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+    query: web::Query<AuditLogQuery>,
+) -> AppResult<HttpResponse> {
+    let query = query.into_inner();
+    let page = query.page.unwrap_or(1).max(1);
+    let filter = AuditLogFilter {
+        user_id: query.user_id.filter(|id| !id.is_empty()),
+        path: query.path.filter(|path| !path.is_empty()),
+        since: query.since,
+    };
+
+    let service = AuditService::new(&state.db);
+    let items = service
+        .get_entries(
+            &filter,
+            (page - 1) * AUDIT_LOG_PAGE_SIZE,
+            AUDIT_LOG_PAGE_SIZE,
+        )
+        .await?;
+    let total = service.count_entries(&filter).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "items": items,
+        "total": total,
+        "page": page,
+    })))
+}
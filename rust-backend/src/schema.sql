@@ -56,6 +56,7 @@ CREATE INDEX IF NOT EXISTS idx_chat_user_id ON chat(user_id);
 CREATE INDEX IF NOT EXISTS idx_chat_share_id ON chat(share_id);
 CREATE INDEX IF NOT EXISTS idx_chat_updated_at ON chat(updated_at DESC);
 CREATE INDEX IF NOT EXISTS idx_chat_created_at ON chat(created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_chat_user_id_updated_at ON chat(user_id, updated_at DESC);
 
 -- Message table (with support for both chat and channel messages)
 CREATE TABLE IF NOT EXISTS message (
@@ -94,6 +95,12 @@ CREATE TABLE IF NOT EXISTS message_reaction (
 CREATE INDEX IF NOT EXISTS idx_message_reaction_message_id ON message_reaction(message_id);
 CREATE INDEX IF NOT EXISTS idx_message_reaction_user_id ON message_reaction(user_id);
 
+-- Reactions are unique per user and emoji; drop duplicates left by older versions first
+DELETE FROM message_reaction WHERE rowid NOT IN (
+    SELECT MIN(rowid) FROM message_reaction GROUP BY message_id, user_id, name
+);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_message_reaction_unique ON message_reaction(message_id, user_id, name);
+
 -- Model table
 CREATE TABLE IF NOT EXISTS model (
     id TEXT PRIMARY KEY,
@@ -305,6 +312,16 @@ CREATE TABLE IF NOT EXISTS channel_member (
 CREATE INDEX IF NOT EXISTS idx_channel_member_channel_id ON channel_member(channel_id);
 CREATE INDEX IF NOT EXISTS idx_channel_member_user_id ON channel_member(user_id);
 
+-- Channel read state table (per-user unread tracking)
+CREATE TABLE IF NOT EXISTS channel_read (
+    channel_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    last_read_at INTEGER NOT NULL,
+    PRIMARY KEY (channel_id, user_id),
+    FOREIGN KEY (channel_id) REFERENCES channel(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES "user"(id) ON DELETE CASCADE
+);
+
 -- Tag table
 CREATE TABLE IF NOT EXISTS tag (
     id TEXT PRIMARY KEY,
@@ -318,6 +335,46 @@ CREATE TABLE IF NOT EXISTS tag (
 
 CREATE INDEX IF NOT EXISTS idx_tag_user_id ON tag(user_id);
 
+-- Token usage per user, model and period (YYYY-MM-DD for days, YYYY-MM for months)
+CREATE TABLE IF NOT EXISTS token_usage (
+    user_id TEXT NOT NULL,
+    model_id TEXT NOT NULL,
+    period TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL DEFAULT 0,
+    completion_tokens INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, model_id, period),
+    FOREIGN KEY (user_id) REFERENCES "user"(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_token_usage_user_period ON token_usage(user_id, period);
+
+-- Audit trail of mutating API requests
+CREATE TABLE IF NOT EXISTS audit_log (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    source_ip TEXT,
+    request_body TEXT,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_user_id ON audit_log(user_id, created_at);
+
+-- Tokens users granted to OAuth clients, encrypted
+CREATE TABLE IF NOT EXISTS oauth_client_token (
+    user_id TEXT NOT NULL,
+    client_id TEXT NOT NULL,
+    data TEXT NOT NULL,
+    expires_at INTEGER,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, client_id)
+);
+
 -- Config table for persistent configuration
 CREATE TABLE IF NOT EXISTS config (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -1,5 +1,6 @@
 use crate::config::Config;
 use crate::error::{AppError, AppResult};
+use crate::utils::http_client;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
@@ -14,6 +15,9 @@ pub struct TTSRequest {
 pub struct STTRequest {
     pub model: String,
     pub language: Option<String>,
+    /// Original upload name; the extension tells the engine the audio format
+    #[serde(default)]
+    pub file_name: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -26,7 +30,7 @@ pub struct AudioService {
 impl AudioService {
     pub fn new(config: Config) -> Self {
         AudioService {
-            client: Client::new(),
+            client: http_client::client(&config),
             config,
         }
     }
@@ -180,13 +184,18 @@ impl AudioService {
     }
 
     async fn openai_stt(&self, audio_data: Vec<u8>, request: STTRequest) -> AppResult<String> {
-        let api_key = std::env::var("OPENAI_API_KEY")
-            .map_err(|_| AppError::InternalServerError("OpenAI API key not set".to_string()))?;
+        let api_key = if self.config.stt_openai_api_key.is_empty() {
+            std::env::var("OPENAI_API_KEY")
+                .map_err(|_| AppError::InternalServerError("OpenAI API key not set".to_string()))?
+        } else {
+            self.config.stt_openai_api_key.clone()
+        };
 
+        let file_name = request.file_name.unwrap_or_else(|| "audio.mp3".to_string());
         let form = reqwest::multipart::Form::new()
             .part(
                 "file",
-                reqwest::multipart::Part::bytes(audio_data).file_name("audio.mp3"),
+                reqwest::multipart::Part::bytes(audio_data).file_name(file_name),
             )
             .text("model", request.model);
 
@@ -198,7 +207,10 @@ impl AudioService {
 
         let response = self
             .client
-            .post("https://api.openai.com/v1/audio/transcriptions")
+            .post(format!(
+                "{}/audio/transcriptions",
+                self.config.stt_openai_api_base_url.trim_end_matches('/')
+            ))
             .header("Authorization", format!("Bearer {}", api_key))
             .multipart(form)
             .send()
@@ -0,0 +1,102 @@
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+use crate::db::Database;
+use crate::error::AppResult;
+use crate::models::audit::{AuditLog, AuditLogFilter};
+
+pub struct AuditService<'a> {
+    db: &'a Database,
+}
+
+impl<'a> AuditService<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        AuditService { db }
+    }
+
+    pub async fn insert_entry(&self, entry: &AuditLog) -> AppResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (id, user_id, method, path, status, source_ip, request_body, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(&entry.id)
+        .bind(&entry.user_id)
+        .bind(&entry.method)
+        .bind(&entry.path)
+        .bind(entry.status)
+        .bind(&entry.source_ip)
+        .bind(&entry.request_body)
+        .bind(entry.created_at)
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Matching entries, newest first
+    pub async fn get_entries(
+        &self,
+        filter: &AuditLogFilter,
+        skip: i64,
+        limit: i64,
+    ) -> AppResult<Vec<AuditLog>> {
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "SELECT id, user_id, method, path, status, source_ip, request_body, created_at \
+             FROM audit_log WHERE 1 = 1",
+        );
+        push_filters(&mut query, filter);
+        query.push(" ORDER BY created_at DESC, id DESC LIMIT ");
+        query.push_bind(limit);
+        query.push(" OFFSET ");
+        query.push_bind(skip);
+
+        let entries = query
+            .build_query_as::<AuditLog>()
+            .fetch_all(&self.db.pool)
+            .await?;
+
+        Ok(entries)
+    }
+
+    pub async fn count_entries(&self, filter: &AuditLogFilter) -> AppResult<i64> {
+        let mut query =
+            QueryBuilder::<Sqlite>::new("SELECT COUNT(*) as count FROM audit_log WHERE 1 = 1");
+        push_filters(&mut query, filter);
+
+        let count: i64 = query
+            .build()
+            .fetch_one(&self.db.pool)
+            .await?
+            .try_get("count")?;
+
+        Ok(count)
+    }
+
+    /// Remove entries created before `cutoff` (unix seconds), returning how many were deleted
+    pub async fn delete_entries_before(&self, cutoff: i64) -> AppResult<u64> {
+        let result = sqlx::query("DELETE FROM audit_log WHERE created_at < $1")
+            .bind(cutoff)
+            .execute(&self.db.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+fn push_filters(query: &mut QueryBuilder<'_, Sqlite>, filter: &AuditLogFilter) {
+    if let Some(user_id) = &filter.user_id {
+        query.push(" AND user_id = ");
+        query.push_bind(user_id.clone());
+    }
+    if let Some(path) = &filter.path {
+        query.push(" AND substr(path, 1, length(");
+        query.push_bind(path.clone());
+        query.push(")) = ");
+        query.push_bind(path.clone());
+    }
+    if let Some(since) = filter.since {
+        query.push(" AND created_at >= ");
+        query.push_bind(since);
+    }
+}
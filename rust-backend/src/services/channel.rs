@@ -2,6 +2,7 @@ use crate::db::Database;
 use crate::error::{AppError, AppResult};
 use crate::models::channel::Channel;
 use crate::utils::time::current_timestamp;
+use std::collections::HashMap;
 
 #[allow(dead_code)]
 pub struct ChannelService<'a> {
@@ -283,6 +284,83 @@ impl<'a> ChannelService<'a> {
         Ok(())
     }
 
+    /// Record that the user has read the channel up to now
+    pub async fn mark_channel_read(&self, channel_id: &str, user_id: &str) -> AppResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO channel_read (channel_id, user_id, last_read_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT(channel_id, user_id) DO UPDATE SET last_read_at = excluded.last_read_at
+            "#,
+        )
+        .bind(channel_id)
+        .bind(user_id)
+        .bind(current_timestamp())
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Count top-level messages posted by other users since the user last read the channel
+    pub async fn get_unread_count(&self, channel_id: &str, user_id: &str) -> AppResult<i64> {
+        let count: (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*) FROM message
+            WHERE channel_id = $1
+              AND parent_id IS NULL
+              AND user_id != $2
+              AND created_at > COALESCE(
+                  (SELECT last_read_at FROM channel_read WHERE channel_id = $1 AND user_id = $2),
+                  0
+              )
+            "#,
+        )
+        .bind(channel_id)
+        .bind(user_id)
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok(count.0)
+    }
+
+    /// [`get_unread_count`](Self::get_unread_count) for several channels in a single query;
+    /// channels with nothing unread are left out
+    pub async fn get_unread_counts(
+        &self,
+        channel_ids: &[String],
+        user_id: &str,
+    ) -> AppResult<HashMap<String, i64>> {
+        if channel_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let placeholders: Vec<String> = (2..=channel_ids.len() + 1)
+            .map(|i| format!("${}", i))
+            .collect();
+        let query_str = format!(
+            r#"
+            SELECT m.channel_id, COUNT(*)
+            FROM message m
+            LEFT JOIN channel_read r ON r.channel_id = m.channel_id AND r.user_id = $1
+            WHERE m.channel_id IN ({})
+              AND m.parent_id IS NULL
+              AND m.user_id != $1
+              AND m.created_at > COALESCE(r.last_read_at, 0)
+            GROUP BY m.channel_id
+            "#,
+            placeholders.join(", ")
+        );
+
+        let mut query = sqlx::query_as::<_, (String, i64)>(&query_str).bind(user_id);
+        for id in channel_ids {
+            query = query.bind(id);
+        }
+        let rows = query.fetch_all(&self.db.pool).await?;
+
+        Ok(rows.into_iter().collect())
+    }
+
     pub async fn delete_channels_by_user_id(&self, user_id: &str) -> AppResult<()> {
         sqlx::query("DELETE FROM channel WHERE user_id = $1")
             .bind(user_id)
@@ -292,3 +370,138 @@ impl<'a> ChannelService<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::message::MessageForm;
+    use crate::services::message::MessageService;
+    use crate::test_utils;
+
+    fn message_form(content: &str) -> MessageForm {
+        MessageForm {
+            content: content.to_string(),
+            reply_to_id: None,
+            parent_id: None,
+            data: None,
+            meta: None,
+        }
+    }
+
+    /// Database with the users "alice" and "bob"
+    async fn test_db(dir: &tempfile::TempDir) -> Database {
+        let db = test_utils::test_db(dir).await;
+        for id in ["alice", "bob"] {
+            test_utils::create_user(&db, id, "user").await;
+        }
+        db
+    }
+
+    #[tokio::test]
+    async fn test_unread_count_tracks_last_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = test_db(&dir).await;
+
+        let channel_service = ChannelService::new(&db);
+        channel_service
+            .create_channel("general", "alice", "general", None, None, None, None, None)
+            .await
+            .unwrap();
+
+        let message_service = MessageService::new(&db);
+        message_service
+            .create_message("general", "alice", &message_form("hi"))
+            .await
+            .unwrap();
+        message_service
+            .create_message("general", "alice", &message_form("anyone?"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            channel_service
+                .get_unread_count("general", "bob")
+                .await
+                .unwrap(),
+            2
+        );
+        // Own messages never count as unread
+        assert_eq!(
+            channel_service
+                .get_unread_count("general", "alice")
+                .await
+                .unwrap(),
+            0
+        );
+
+        channel_service
+            .mark_channel_read("general", "bob")
+            .await
+            .unwrap();
+        assert_eq!(
+            channel_service
+                .get_unread_count("general", "bob")
+                .await
+                .unwrap(),
+            0
+        );
+
+        message_service
+            .create_message("general", "alice", &message_form("welcome"))
+            .await
+            .unwrap();
+        assert_eq!(
+            channel_service
+                .get_unread_count("general", "bob")
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unread_counts_for_several_channels() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = test_db(&dir).await;
+
+        let channel_service = ChannelService::new(&db);
+        let message_service = MessageService::new(&db);
+        for channel in ["general", "random", "quiet"] {
+            channel_service
+                .create_channel(channel, "alice", channel, None, None, None, None, None)
+                .await
+                .unwrap();
+        }
+        for (channel, content) in [("general", "hi"), ("general", "anyone?"), ("random", "lol")] {
+            message_service
+                .create_message(channel, "alice", &message_form(content))
+                .await
+                .unwrap();
+        }
+        channel_service
+            .mark_channel_read("random", "bob")
+            .await
+            .unwrap();
+
+        let ids: Vec<String> = ["general", "random", "quiet"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        let counts = channel_service
+            .get_unread_counts(&ids, "bob")
+            .await
+            .unwrap();
+        assert_eq!(counts, HashMap::from([("general".to_string(), 2)]));
+        for id in &ids {
+            assert_eq!(
+                counts.get(id).copied().unwrap_or(0),
+                channel_service.get_unread_count(id, "bob").await.unwrap()
+            );
+        }
+        assert!(channel_service
+            .get_unread_counts(&[], "bob")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,6 +1,9 @@
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
-use crate::models::chat::{Chat, CreateChatRequest, UpdateChatRequest};
+use crate::models::chat::{
+    BookmarkedMessageResponse, Chat, ChatListFilter, ChatListItem, CreateChatRequest,
+    UpdateChatRequest,
+};
 use crate::utils::time::current_timestamp_seconds;
 use sqlx::types::JsonValue;
 use sqlx::Row;
@@ -122,6 +125,40 @@ impl<'a> ChatService<'a> {
         Ok(chats)
     }
 
+    /// A page of the user's chats for list views, newest first; only the columns the
+    /// sidebar shows are read, never the chat JSON
+    pub async fn get_chat_list_items_by_user_id(
+        &self,
+        user_id: &str,
+        filter: ChatListFilter,
+        skip: i64,
+        limit: i64,
+    ) -> AppResult<Vec<ChatListItem>> {
+        let condition = match filter {
+            ChatListFilter::Active => "archived = 0",
+            ChatListFilter::Pinned => "pinned = 1 AND archived = 0",
+            ChatListFilter::Archived => "archived = 1",
+        };
+
+        let items = sqlx::query_as::<_, ChatListItem>(&format!(
+            r#"
+            SELECT id, title, folder_id, archived, pinned, created_at, updated_at
+            FROM chat
+            WHERE user_id = $1 AND {}
+            ORDER BY updated_at DESC, id DESC
+            LIMIT $2 OFFSET $3
+            "#,
+            condition
+        ))
+        .bind(user_id)
+        .bind(limit)
+        .bind(skip)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        Ok(items)
+    }
+
     pub async fn get_pinned_chats_by_user_id(&self, user_id: &str) -> AppResult<Vec<Chat>> {
         let chats = sqlx::query_as::<_, Chat>(
             r#"
@@ -943,6 +980,80 @@ impl<'a> ChatService<'a> {
             .ok_or_else(|| AppError::NotFound("Chat not found".to_string()))
     }
 
+    /// Set or toggle (when `bookmarked` is `None`) the `bookmarked` flag on a message in
+    /// `chat.history.messages`, returning the new state
+    pub async fn set_message_bookmark(
+        &self,
+        chat_id: &str,
+        message_id: &str,
+        user_id: &str,
+        bookmarked: Option<bool>,
+    ) -> AppResult<bool> {
+        let chat = self
+            .get_chat_by_id_and_user_id(chat_id, user_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Chat not found".to_string()))?;
+
+        let mut chat_data = chat.chat.clone();
+
+        let message = chat_data
+            .get_mut("history")
+            .and_then(|h| h.get_mut("messages"))
+            .and_then(|m| m.get_mut(message_id))
+            .and_then(|m| m.as_object_mut())
+            .ok_or_else(|| AppError::NotFound("Message not found".to_string()))?;
+
+        let current = message
+            .get("bookmarked")
+            .and_then(|b| b.as_bool())
+            .unwrap_or(false);
+        let bookmarked = bookmarked.unwrap_or(!current);
+        message.insert(
+            "bookmarked".to_string(),
+            serde_json::Value::Bool(bookmarked),
+        );
+
+        let now = current_timestamp_seconds();
+        sqlx::query(
+            r#"
+            UPDATE chat
+            SET chat = $1, updated_at = $2
+            WHERE id = $3 AND user_id = $4
+            "#,
+        )
+        .bind(&chat_data)
+        .bind(now)
+        .bind(chat_id)
+        .bind(user_id)
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(bookmarked)
+    }
+
+    /// Bookmarked messages across all of the user's chats, archived ones included
+    pub async fn get_bookmarked_messages_by_user_id(
+        &self,
+        user_id: &str,
+    ) -> AppResult<Vec<BookmarkedMessageResponse>> {
+        let chats = sqlx::query_as::<_, Chat>(
+            r#"
+            SELECT id, user_id, title, chat, folder_id, archived, pinned, share_id, meta, created_at, updated_at
+            FROM chat
+            WHERE user_id = $1
+            ORDER BY updated_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        Ok(chats
+            .iter()
+            .flat_map(BookmarkedMessageResponse::from_chat)
+            .collect())
+    }
+
     pub async fn add_chat_tag(
         &self,
         chat_id: &str,
@@ -996,6 +1107,55 @@ impl<'a> ChatService<'a> {
             .ok_or_else(|| AppError::NotFound("Chat not found".to_string()))
     }
 
+    /// Merge generated tags into the chat's existing `meta.tags`, keeping user-set tags.
+    /// Returns the resulting tag list.
+    pub async fn merge_chat_tags(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+        tags: &[String],
+    ) -> AppResult<Vec<String>> {
+        let chat = self
+            .get_chat_by_id_and_user_id(chat_id, user_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Chat not found".to_string()))?;
+
+        let mut meta = chat.meta.unwrap_or_else(|| serde_json::json!({}));
+
+        let existing: Vec<String> = meta
+            .get("tags")
+            .and_then(|t| t.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|t| t.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let merged = crate::utils::chat_completion::merge_tags(&existing, tags);
+        if merged.len() == existing.len() {
+            return Ok(merged);
+        }
+
+        meta["tags"] = serde_json::json!(merged);
+
+        // Background tagging should not bump updated_at and reorder the chat list
+        sqlx::query(
+            r#"
+            UPDATE chat
+            SET meta = $1
+            WHERE id = $2 AND user_id = $3
+            "#,
+        )
+        .bind(&meta)
+        .bind(chat_id)
+        .bind(user_id)
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(merged)
+    }
+
     pub async fn delete_chat_tag(
         &self,
         chat_id: &str,
@@ -1061,3 +1221,67 @@ impl<'a> ChatService<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[tokio::test]
+    async fn test_chat_list_items_skip_chat_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = test_utils::test_db(&dir).await;
+        test_utils::create_user(&db, "alice", "user").await;
+
+        let service = ChatService::new(&db);
+        let body = "x".repeat(10_000);
+        for i in 0..1000 {
+            service
+                .create_chat(
+                    "alice",
+                    CreateChatRequest {
+                        id: format!("chat-{}", i),
+                        title: Some(format!("Chat {}", i)),
+                        chat: serde_json::json!({"messages": [{"content": body}]}),
+                        folder_id: None,
+                        archived: Some(i % 10 == 0),
+                        pinned: Some(i % 100 == 1),
+                        share_id: None,
+                        meta: None,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        let page = service
+            .get_chat_list_items_by_user_id("alice", ChatListFilter::Active, 0, 1000)
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 900);
+        assert!(page.iter().all(|item| !item.archived));
+        // The serialized list carries titles only, not the message bodies
+        let json = serde_json::to_string(&page).unwrap();
+        assert!(!json.contains(&body[..100]));
+        assert!(json.len() < 200 * 1000);
+
+        let second_page = service
+            .get_chat_list_items_by_user_id("alice", ChatListFilter::Active, 50, 50)
+            .await
+            .unwrap();
+        assert_eq!(second_page.len(), 50);
+        assert_eq!(second_page[0].id, page[50].id);
+
+        let pinned = service
+            .get_chat_list_items_by_user_id("alice", ChatListFilter::Pinned, 0, 100)
+            .await
+            .unwrap();
+        assert_eq!(pinned.len(), 10);
+
+        let archived = service
+            .get_chat_list_items_by_user_id("alice", ChatListFilter::Archived, 0, 1000)
+            .await
+            .unwrap();
+        assert_eq!(archived.len(), 100);
+    }
+}
@@ -1,6 +1,140 @@
 use crate::{config::Config, db::Database, error::AppError, models::config::ConfigModel};
 use serde_json::json;
 
+/// Top-level sections that can be restored through config import, with the keys each
+/// section is persisted with
+pub const IMPORTABLE_SECTIONS: &[(&str, &[&str])] = &[
+    ("direct", &["enable", "default_connections"]),
+    (
+        "connections",
+        &["enable_direct_connections", "enable_base_models_cache"],
+    ),
+    (
+        "openai",
+        &["enable", "api_keys", "api_base_urls", "api_configs"],
+    ),
+    (
+        "admin",
+        &[
+            "show_admin_details",
+            "webui_url",
+            "enable_signup",
+            "enable_api_key",
+            "enable_api_key_endpoint_restrictions",
+            "api_key_allowed_endpoints",
+            "default_user_role",
+            "default_group_id",
+            "jwt_expires_in",
+            "enable_community_sharing",
+            "enable_message_rating",
+            "enable_channels",
+            "enable_notes",
+            "enable_user_webhooks",
+            "pending_user_overlay_title",
+            "pending_user_overlay_content",
+            "response_watermark",
+            "response_watermark_mode",
+            "response_watermark_in_stream",
+            "chat_response_stream_delta_chunk_size",
+        ],
+    ),
+    (
+        "features",
+        &[
+            "enable_channels",
+            "enable_notes",
+            "enable_image_generation",
+            "enable_code_execution",
+            "enable_code_interpreter",
+            "enable_web_search",
+            "enable_admin_chat_access",
+            "enable_admin_export",
+            "enable_community_sharing",
+            "enable_message_rating",
+        ],
+    ),
+    ("models", &["default_models", "model_order_list"]),
+    (
+        "code_execution",
+        &[
+            "engine",
+            "jupyter_url",
+            "jupyter_auth",
+            "jupyter_auth_token",
+            "jupyter_auth_password",
+            "jupyter_timeout",
+            "sandbox_url",
+            "sandbox_timeout",
+            "sandbox_enable_pool",
+            "sandbox_pool_size",
+            "sandbox_pool_max_reuse",
+            "sandbox_pool_max_age",
+        ],
+    ),
+    (
+        "code_interpreter",
+        &[
+            "engine",
+            "prompt_template",
+            "jupyter_url",
+            "jupyter_auth",
+            "jupyter_auth_token",
+            "jupyter_auth_password",
+            "jupyter_timeout",
+            "sandbox_url",
+            "sandbox_timeout",
+        ],
+    ),
+    ("ui", &["banners", "default_prompt_suggestions"]),
+    ("tool_servers", &["connections"]),
+    ("audio", &["tts", "stt"]),
+    ("image", &["model", "size", "steps"]),
+    (
+        "rag_embedding",
+        &[
+            "engine",
+            "model",
+            "batch_size",
+            "openai_url",
+            "openai_key",
+            "azure_url",
+            "azure_key",
+            "azure_version",
+        ],
+    ),
+    ("evaluation", &["enable_arena_models", "arena_models"]),
+    (
+        "security_headers",
+        &[
+            "mode",
+            "enable_csp",
+            "content_security_policy",
+            "csp_connect_src",
+            "csp_img_src",
+            "enable_hsts",
+            "hsts_max_age",
+            "x_frame_options",
+            "referrer_policy",
+            "permissions_policy",
+        ],
+    ),
+    (
+        "body_limits",
+        &["file_max_size", "chat_body_max_size", "body_max_size"],
+    ),
+    ("branding", &["assets"]),
+];
+
+fn importable_keys(section: &str) -> Option<&'static [&'static str]> {
+    IMPORTABLE_SECTIONS
+        .iter()
+        .find(|(name, _)| *name == section)
+        .map(|(_, keys)| *keys)
+}
+
+/// Placeholder written in place of secrets when exporting with redaction
+pub const REDACTED_VALUE: &str = "**REDACTED**";
+
 /// Service for handling configuration persistence
 pub struct ConfigService;
 
@@ -128,11 +262,53 @@ impl ConfigService {
         Ok(())
     }
 
+    /// Export the persisted configuration document, optionally redacting secrets
+    pub async fn export_config(
+        db: &Database,
+        config: &Config,
+        redact: bool,
+    ) -> Result<serde_json::Value, AppError> {
+        let mut data = match Self::get_latest_config(db).await? {
+            Some(config_model) => config_model.data,
+            None => Self::config_to_json(config),
+        };
+
+        if redact {
+            redact_secrets(&mut data);
+        }
+
+        Ok(data)
+    }
+
+    /// Import a configuration document, merging known sections into the persisted config.
+    /// Returns the reloaded runtime config and the names of sections that changed.
+    pub async fn import_config(
+        db: &Database,
+        mut config: Config,
+        payload: &serde_json::Value,
+    ) -> Result<(Config, Vec<String>), AppError> {
+        let mut data = match Self::get_latest_config(db).await? {
+            Some(config_model) => config_model.data,
+            None => Self::config_to_json(&config),
+        };
+
+        let changed = merge_import(&mut data, payload)?;
+
+        for section in &changed {
+            Self::update_section(db, section, data[section.as_str()].clone()).await?;
+        }
+
+        Self::merge_config(&mut config, &data);
+        tracing::info!("Configuration imported, changed sections: {:?}", changed);
+        Ok((config, changed))
+    }
+
     /// Convert Config struct to JSON for database storage
     fn config_to_json(config: &Config) -> serde_json::Value {
         json!({
             "direct": {
-                "enable": config.enable_direct_connections
+                "enable": config.enable_direct_connections,
+                "default_connections": config.default_direct_connections
             },
             "connections": {
                 "enable_direct_connections": config.enable_direct_connections,
@@ -187,7 +363,14 @@ impl ConfigService {
             },
             "tool_servers": {
                 "connections": config.tool_server_connections
-            }
+            },
+            "evaluation": {
+                "enable_arena_models": config.enable_evaluation_arena_models,
+                "arena_models": config.evaluation_arena_models
+            },
+            "security_headers": security_headers_json(config),
+            "body_limits": body_limits_json(config),
+            "branding": branding_json(config)
         })
     }
 
@@ -280,6 +463,10 @@ impl ConfigService {
             &["connections", "enable_direct_connections"],
             config.enable_direct_connections,
         );
+        config.default_direct_connections = get_json(
+            &["direct", "default_connections"],
+            config.default_direct_connections.clone(),
+        );
         config.enable_base_models_cache = get_bool(
             &["connections", "enable_base_models_cache"],
             config.enable_base_models_cache,
@@ -316,6 +503,8 @@ impl ConfigService {
             &["admin", "default_user_role"],
             config.default_user_role.clone(),
         );
+        config.default_group_id =
+            get_option_string(&["admin", "default_group_id"]).or(config.default_group_id.clone());
         config.jwt_expires_in =
             get_string(&["admin", "jwt_expires_in"], config.jwt_expires_in.clone());
         config.enable_user_webhooks = get_bool(
@@ -330,6 +519,18 @@ impl ConfigService {
                 .or(config.pending_user_overlay_content.clone());
         config.response_watermark = get_option_string(&["admin", "response_watermark"])
             .or(config.response_watermark.clone());
+        config.response_watermark_mode = get_option_string(&["admin", "response_watermark_mode"])
+            .unwrap_or_else(|| config.response_watermark_mode.clone());
+        config.response_watermark_in_stream = get_bool(
+            &["admin", "response_watermark_in_stream"],
+            config.response_watermark_in_stream,
+        );
+        config.chat_response_stream_delta_chunk_size =
+            get_option_i32(&["admin", "chat_response_stream_delta_chunk_size"])
+                .filter(|size| *size > 0)
+                .map_or(config.chat_response_stream_delta_chunk_size, |size| {
+                    size as usize
+                });
 
         // Merge Features (admin settings override features)
         config.enable_channels = get_bool(
@@ -436,10 +637,375 @@ impl ConfigService {
             config.default_prompt_suggestions.clone(),
         );
 
+        // Merge Branding
+        if let Ok(custom_branding) =
+            serde_json::from_value(get_json(&["branding", "assets"], serde_json::Value::Null))
+        {
+            config.custom_branding = custom_branding;
+        }
+
         // Merge Tool Servers
         config.tool_server_connections = get_json(
             &["tool_servers", "connections"],
             config.tool_server_connections.clone(),
         );
+
+        // Merge Evaluations
+        config.enable_evaluation_arena_models = get_bool(
+            &["evaluation", "enable_arena_models"],
+            config.enable_evaluation_arena_models,
+        );
+        config.evaluation_arena_models = get_json(
+            &["evaluation", "arena_models"],
+            config.evaluation_arena_models.clone(),
+        );
+
+        // Merge Security Headers
+        config.security_headers_mode = get_string(
+            &["security_headers", "mode"],
+            config.security_headers_mode.clone(),
+        );
+        config.enable_csp = get_bool(&["security_headers", "enable_csp"], config.enable_csp);
+        if let Some(csp) = get_json(
+            &["security_headers", "content_security_policy"],
+            json!(null),
+        )
+        .as_str()
+        .map(|s| s.to_string())
+        {
+            config.content_security_policy = Some(csp).filter(|s| !s.trim().is_empty());
+        }
+        config.csp_connect_src = get_vec_string(
+            &["security_headers", "csp_connect_src"],
+            config.csp_connect_src.clone(),
+        );
+        config.csp_img_src = get_vec_string(
+            &["security_headers", "csp_img_src"],
+            config.csp_img_src.clone(),
+        );
+        config.enable_hsts = get_bool(&["security_headers", "enable_hsts"], config.enable_hsts);
+        config.hsts_max_age = get_json(&["security_headers", "hsts_max_age"], json!(null))
+            .as_u64()
+            .unwrap_or(config.hsts_max_age);
+        config.x_frame_options = get_string(
+            &["security_headers", "x_frame_options"],
+            config.x_frame_options.clone(),
+        );
+        config.referrer_policy = get_string(
+            &["security_headers", "referrer_policy"],
+            config.referrer_policy.clone(),
+        );
+        config.permissions_policy = get_string(
+            &["security_headers", "permissions_policy"],
+            config.permissions_policy.clone(),
+        );
+
+        // Merge Body Limits (bytes)
+        let get_size = |key: &str, default: u64| {
+            get_json(&["body_limits", key], json!(null))
+                .as_u64()
+                .filter(|size| *size > 0)
+                .unwrap_or(default)
+        };
+        config.file_max_size = get_size("file_max_size", config.file_max_size);
+        config.chat_body_max_size = get_size("chat_body_max_size", config.chat_body_max_size);
+        config.body_max_size = get_size("body_max_size", config.body_max_size);
+    }
+}
+
+/// The persisted "security_headers" section
+pub fn security_headers_json(config: &Config) -> serde_json::Value {
+    json!({
+        "mode": config.security_headers_mode,
+        "enable_csp": config.enable_csp,
+        // Stored as "" when unset so clearing the override survives a restart
+        "content_security_policy": config.content_security_policy.clone().unwrap_or_default(),
+        "csp_connect_src": config.csp_connect_src,
+        "csp_img_src": config.csp_img_src,
+        "enable_hsts": config.enable_hsts,
+        "hsts_max_age": config.hsts_max_age,
+        "x_frame_options": config.x_frame_options,
+        "referrer_policy": config.referrer_policy,
+        "permissions_policy": config.permissions_policy
+    })
+}
+
+/// The persisted "body_limits" section, in bytes
+pub fn body_limits_json(config: &Config) -> serde_json::Value {
+    json!({
+        "file_max_size": config.file_max_size,
+        "chat_body_max_size": config.chat_body_max_size,
+        "body_max_size": config.body_max_size
+    })
+}
+
+/// Persisted form of the uploaded branding images
+pub fn branding_json(config: &Config) -> serde_json::Value {
+    json!({
+        "assets": config.custom_branding
+    })
+}
+
+/// Whether a config key holds a credential that must not leave the server on export
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    key == "webui_secret_key"
+        || key.ends_with("api_key")
+        || key.ends_with("api_keys")
+        || key.ends_with("token")
+        || key.ends_with("password")
+        || key.ends_with("secret")
+}
+
+/// Replace every secret string (or list of strings) in the document with [`REDACTED_VALUE`]
+pub fn redact_secrets(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if is_secret_key(key) {
+                    match child {
+                        serde_json::Value::String(s) if !s.is_empty() => {
+                            *s = REDACTED_VALUE.to_string();
+                        }
+                        serde_json::Value::Array(items) => {
+                            for item in items.iter_mut() {
+                                if matches!(item, serde_json::Value::String(s) if !s.is_empty()) {
+                                    *item = json!(REDACTED_VALUE);
+                                }
+                            }
+                        }
+                        _ => redact_secrets(child),
+                    }
+                } else {
+                    redact_secrets(child);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_secrets),
+        _ => {}
+    }
+}
+
+/// Swap redaction placeholders in an imported value back to the currently persisted secrets
+fn restore_redacted(
+    incoming: &serde_json::Value,
+    existing: Option<&serde_json::Value>,
+) -> serde_json::Value {
+    match incoming {
+        serde_json::Value::String(s) if s == REDACTED_VALUE => existing
+            .cloned()
+            .unwrap_or_else(|| serde_json::Value::String(String::new())),
+        serde_json::Value::Array(items) => serde_json::Value::Array(
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| restore_redacted(item, existing.and_then(|e| e.get(i))))
+                .collect(),
+        ),
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(key, child)| {
+                    (
+                        key.clone(),
+                        restore_redacted(child, existing.and_then(|e| e.get(key))),
+                    )
+                })
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Merge an imported config document into the persisted one.
+///
+/// Known sections are merged key by key so settings missing from the payload are kept;
+/// sections not present in the payload are left untouched. Unknown sections, and keys a
+/// section is neither persisted with nor already holds, are rejected rather than dropped.
+/// Returns the sections whose content changed.
+pub fn merge_import(
+    data: &mut serde_json::Value,
+    payload: &serde_json::Value,
+) -> Result<Vec<String>, AppError> {
+    let incoming = payload
+        .as_object()
+        .ok_or_else(|| AppError::BadRequest("Config must be a JSON object".to_string()))?;
+
+    // Validate everything up front so a bad section doesn't leave a half-applied import
+    let unknown: Vec<&str> = incoming
+        .keys()
+        .map(String::as_str)
+        .filter(|section| importable_keys(section).is_none())
+        .collect();
+    if !unknown.is_empty() {
+        let known: Vec<&str> = IMPORTABLE_SECTIONS.iter().map(|(name, _)| *name).collect();
+        return Err(AppError::BadRequest(format!(
+            "Unknown config sections: {}. Known sections: {}",
+            unknown.join(", "),
+            known.join(", ")
+        )));
+    }
+    let mut unknown_keys = Vec::new();
+    for (section, value) in incoming {
+        let keys = match value.as_object() {
+            Some(keys) => keys,
+            None => {
+                return Err(AppError::BadRequest(format!(
+                    "Config section '{}' must be an object",
+                    section
+                )))
+            }
+        };
+        // Keys the section already stores are accepted too, so an export always imports back
+        let stored = data.get(section.as_str());
+        let known = importable_keys(section).unwrap_or_default();
+        unknown_keys.extend(
+            keys.keys()
+                .filter(|key| {
+                    !known.contains(&key.as_str())
+                        && stored.and_then(|s| s.get(key.as_str())).is_none()
+                })
+                .map(|key| format!("{}.{}", section, key)),
+        );
+    }
+    if !unknown_keys.is_empty() {
+        return Err(AppError::BadRequest(format!(
+            "Unknown config keys: {}",
+            unknown_keys.join(", ")
+        )));
+    }
+
+    if !data.is_object() {
+        *data = json!({});
+    }
+    let target = data.as_object_mut().expect("config data is an object");
+
+    let mut changed = Vec::new();
+    for (section, value) in incoming {
+        let entry = target.entry(section.clone()).or_insert_with(|| json!({}));
+        if !entry.is_object() {
+            *entry = json!({});
+        }
+        let before = entry.clone();
+        let merged = restore_redacted(value, Some(&*entry));
+        if let (Some(existing), Some(merged)) = (entry.as_object_mut(), merged.as_object()) {
+            for (key, child) in merged {
+                existing.insert(key.clone(), child.clone());
+            }
+        }
+        if *entry != before {
+            changed.push(section.clone());
+        }
+    }
+
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_secrets() {
+        let mut data = json!({
+            "openai": {
+                "enable": true,
+                "api_keys": ["sk-one", ""],
+                "api_base_urls": ["https://api.openai.com/v1"]
+            },
+            "admin": { "enable_api_key": true, "webui_secret_key": "s3cret" },
+            "code_execution": { "jupyter_auth": "token", "jupyter_auth_token": "abc" }
+        });
+
+        redact_secrets(&mut data);
+
+        assert_eq!(data["openai"]["api_keys"], json!([REDACTED_VALUE, ""]));
+        assert_eq!(
+            data["openai"]["api_base_urls"][0],
+            "https://api.openai.com/v1"
+        );
+        assert_eq!(data["admin"]["enable_api_key"], true);
+        assert_eq!(data["admin"]["webui_secret_key"], REDACTED_VALUE);
+        assert_eq!(data["code_execution"]["jupyter_auth"], "token");
+        assert_eq!(data["code_execution"]["jupyter_auth_token"], REDACTED_VALUE);
+    }
+
+    #[test]
+    fn test_merge_import_keeps_missing_sections_and_secrets() {
+        let mut data = json!({
+            "openai": { "enable": true, "api_keys": ["sk-one", "sk-two"] },
+            "ui": { "banners": [] }
+        });
+        let payload = json!({
+            "openai": { "enable": false, "api_keys": [REDACTED_VALUE, "sk-new", "sk-three"] },
+            "ui": { "banners": [] }
+        });
+
+        let changed = merge_import(&mut data, &payload).unwrap();
+
+        assert_eq!(changed, vec!["openai".to_string()]);
+        assert_eq!(data["openai"]["enable"], false);
+        assert_eq!(
+            data["openai"]["api_keys"],
+            json!(["sk-one", "sk-new", "sk-three"])
+        );
+        assert_eq!(data["ui"], json!({ "banners": [] }));
+    }
+
+    #[test]
+    fn test_merge_import_rejects_unknown_sections() {
+        let mut data = json!({ "ui": { "banners": [] } });
+        let payload = json!({
+            "ui": { "banners": [{ "id": "b1" }] },
+            "unknown": { "foo": 1 },
+            "legacy": {}
+        });
+
+        let err = merge_import(&mut data, &payload).unwrap_err().to_string();
+        assert!(err.contains("legacy, unknown"));
+        assert_eq!(data, json!({ "ui": { "banners": [] } }));
+    }
+
+    #[test]
+    fn test_merge_import_rejects_unknown_keys() {
+        let mut data = json!({ "ui": { "banners": [], "legacy_flag": true } });
+        let payload = json!({
+            "ui": { "banners": [{ "id": "b1" }], "legacy_flag": false, "bannners": [] },
+            "admin": { "enable_signup": false, "enable_sigup": true }
+        });
+
+        let err = merge_import(&mut data, &payload).unwrap_err().to_string();
+        assert!(err.contains("ui.bannners"));
+        assert!(err.contains("admin.enable_sigup"));
+        assert!(!err.contains("legacy_flag"));
+        assert_eq!(
+            data,
+            json!({ "ui": { "banners": [], "legacy_flag": true } })
+        );
+
+        // A key the section already stores imports back
+        let payload = json!({ "ui": { "legacy_flag": false } });
+        assert_eq!(merge_import(&mut data, &payload).unwrap(), vec!["ui"]);
+    }
+
+    #[test]
+    fn test_persisted_sections_are_importable() {
+        let config = Config::from_env().unwrap();
+        let data = ConfigService::config_to_json(&config);
+        for (section, value) in data.as_object().unwrap() {
+            let known = importable_keys(section)
+                .unwrap_or_else(|| panic!("section {} is not importable", section));
+            for key in value.as_object().unwrap().keys() {
+                assert!(known.contains(&key.as_str()), "{}.{}", section, key);
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_import_rejects_non_object_section() {
+        let mut data = json!({ "ui": { "banners": [] } });
+        let payload = json!({ "ui": [] });
+
+        assert!(merge_import(&mut data, &payload).is_err());
+        assert_eq!(data, json!({ "ui": { "banners": [] } }));
     }
 }
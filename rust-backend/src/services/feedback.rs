@@ -1,9 +1,27 @@
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
-use crate::models::feedback::{Feedback, FeedbackForm};
+use crate::models::feedback::{Feedback, FeedbackForm, FeedbackModel};
 use crate::utils::time::current_timestamp_seconds;
+use serde::Serialize;
+use std::collections::HashMap;
 use uuid::Uuid;
 
+/// Starting Elo rating for a model with no comparisons
+pub const INITIAL_ELO_RATING: f64 = 1000.0;
+
+/// How far a single comparison moves a rating
+const ELO_K_FACTOR: f64 = 32.0;
+
+/// A model's standing on the evaluation leaderboard
+#[derive(Debug, Clone, Serialize)]
+pub struct LeaderboardEntry {
+    pub model_id: String,
+    pub rating: i64,
+    pub won: i64,
+    pub lost: i64,
+    pub count: i64,
+}
+
 pub struct FeedbackService<'a> {
     db: &'a Database,
 }
@@ -277,3 +295,141 @@ impl<'a> FeedbackService<'a> {
         Ok(result.rows_affected() > 0)
     }
 }
+
+/// Compute Elo ratings from rating feedback.
+///
+/// Each feedback rates `data.model_id` (1 = better, -1 = worse, anything else = draw)
+/// against every model in `data.sibling_model_ids`; feedback without siblings has nothing
+/// to compare and is ignored. Feedback is applied oldest first.
+pub fn compute_leaderboard(feedbacks: &[FeedbackModel]) -> Vec<LeaderboardEntry> {
+    let mut ordered: Vec<&FeedbackModel> = feedbacks
+        .iter()
+        .filter(|f| f.feedback_type == "rating")
+        .collect();
+    ordered.sort_by_key(|f| f.created_at);
+
+    let mut ratings: HashMap<String, f64> = HashMap::new();
+    let mut stats: HashMap<String, (i64, i64, i64)> = HashMap::new();
+
+    for feedback in ordered {
+        let data = match &feedback.data {
+            Some(data) => data,
+            None => continue,
+        };
+        let model_id = match data.get("model_id").and_then(|v| v.as_str()) {
+            Some(model_id) => model_id,
+            None => continue,
+        };
+        let outcome = match data.get("rating").and_then(|v| v.as_i64()) {
+            Some(1) => 1.0,
+            Some(-1) => 0.0,
+            _ => 0.5,
+        };
+        let siblings: Vec<&str> = data
+            .get("sibling_model_ids")
+            .and_then(|v| v.as_array())
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| id.as_str())
+                    .filter(|id| *id != model_id)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for sibling in siblings {
+            let rating_a = *ratings.get(model_id).unwrap_or(&INITIAL_ELO_RATING);
+            let rating_b = *ratings.get(sibling).unwrap_or(&INITIAL_ELO_RATING);
+            let expected_a = 1.0 / (1.0 + 10f64.powf((rating_b - rating_a) / 400.0));
+
+            ratings.insert(
+                model_id.to_string(),
+                rating_a + ELO_K_FACTOR * (outcome - expected_a),
+            );
+            ratings.insert(
+                sibling.to_string(),
+                rating_b + ELO_K_FACTOR * (expected_a - outcome),
+            );
+
+            let (won_a, won_b) = if outcome == 1.0 {
+                (1, 0)
+            } else if outcome == 0.0 {
+                (0, 1)
+            } else {
+                (0, 0)
+            };
+            for (id, won, lost) in [(model_id, won_a, won_b), (sibling, won_b, won_a)] {
+                let entry = stats.entry(id.to_string()).or_insert((0, 0, 0));
+                entry.0 += won;
+                entry.1 += lost;
+                entry.2 += 1;
+            }
+        }
+    }
+
+    let mut leaderboard: Vec<LeaderboardEntry> = ratings
+        .into_iter()
+        .map(|(model_id, rating)| {
+            let (won, lost, count) = stats.get(&model_id).copied().unwrap_or_default();
+            LeaderboardEntry {
+                model_id,
+                rating: rating.round() as i64,
+                won,
+                lost,
+                count,
+            }
+        })
+        .collect();
+    leaderboard.sort_by(|a, b| {
+        b.rating
+            .cmp(&a.rating)
+            .then_with(|| a.model_id.cmp(&b.model_id))
+    });
+    leaderboard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rating(created_at: i64, model_id: &str, siblings: &[&str], rating: i64) -> FeedbackModel {
+        FeedbackModel {
+            id: created_at.to_string(),
+            user_id: "u1".to_string(),
+            version: 0,
+            feedback_type: "rating".to_string(),
+            data: Some(json!({
+                "model_id": model_id,
+                "sibling_model_ids": siblings,
+                "rating": rating,
+            })),
+            meta: None,
+            snapshot: None,
+            created_at,
+            updated_at: created_at,
+        }
+    }
+
+    #[test]
+    fn test_compute_leaderboard() {
+        let leaderboard = compute_leaderboard(&[
+            rating(1, "a", &["b"], 1),
+            rating(2, "a", &["b"], 1),
+            rating(3, "c", &["b"], -1),
+            rating(4, "a", &[], 1),
+        ]);
+
+        let ids: Vec<&str> = leaderboard.iter().map(|e| e.model_id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+        assert_eq!(leaderboard[0].rating, 1031);
+        assert_eq!((leaderboard[0].won, leaderboard[0].lost), (2, 0));
+        assert_eq!(leaderboard[1].count, 3);
+        assert_eq!((leaderboard[2].won, leaderboard[2].lost), (0, 1));
+    }
+
+    #[test]
+    fn test_draw_leaves_equal_ratings() {
+        let leaderboard = compute_leaderboard(&[rating(1, "a", &["b"], 0)]);
+        assert!(leaderboard.iter().all(|e| e.rating == 1000 && e.won == 0));
+    }
+}
@@ -20,6 +20,7 @@ impl<'a> FileService<'a> {
         user_id: &str,
         filename: &str,
         path: &str,
+        hash: Option<&str>,
         meta: Option<serde_json::Value>,
     ) -> AppResult<File> {
         let now = current_timestamp_seconds();
@@ -35,7 +36,7 @@ impl<'a> FileService<'a> {
         .bind(filename)
         .bind(path)
         .bind(&meta)
-        .bind(None::<String>)
+        .bind(hash)
         .bind(now)
         .bind(now)
         .execute(&self.db.pool)
@@ -103,6 +104,22 @@ impl<'a> FileService<'a> {
         Ok(files)
     }
 
+    /// Total size in bytes of the files a user has uploaded
+    pub async fn get_storage_used_by_user(&self, user_id: &str) -> AppResult<u64> {
+        let used: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(CAST(json_extract(meta, '$.size') AS INTEGER)), 0)
+            FROM file
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok(used.max(0) as u64)
+    }
+
     pub async fn get_all_files(&self) -> AppResult<Vec<File>> {
         let files = sqlx::query_as::<_, File>(
             r#"
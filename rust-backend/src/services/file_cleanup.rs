@@ -0,0 +1,388 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::retrieval::VectorDB;
+use crate::services::file::FileService;
+use crate::utils::time::current_timestamp_seconds;
+
+/// Columns that may mention file IDs: chat histories, knowledge `file_ids`, notes,
+/// folder attachments, channel message attachments and model knowledge
+const REFERENCE_SOURCES: &[(&str, &str)] = &[
+    ("chat", "chat"),
+    ("knowledge", "data"),
+    ("note", "data"),
+    ("folder", "data"),
+    ("message", "data"),
+    ("model", "meta"),
+];
+
+/// Rows read per query while scanning for references
+const SCAN_BATCH_SIZE: i64 = 500;
+
+/// Longest list of orphaned files kept in a report
+const MAX_REPORTED_FILES: usize = 1000;
+
+/// Progress of the last cleanup run, shown by the status endpoint
+static LAST_CLEANUP: Mutex<Option<CleanupReport>> = Mutex::new(None);
+
+#[derive(Debug, Clone)]
+pub struct CleanupOptions {
+    /// Report what would be deleted without deleting anything
+    pub dry_run: bool,
+    /// Skip files uploaded more recently than this, as they may not be attached yet
+    pub min_age_secs: i64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CleanupStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// A file row no chat, knowledge base, note, folder, message or model refers to
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct OrphanedFile {
+    pub id: String,
+    pub user_id: String,
+    pub filename: String,
+    #[serde(skip)]
+    pub path: String,
+    #[serde(skip)]
+    pub meta: Option<String>,
+    pub created_at: i64,
+}
+
+impl OrphanedFile {
+    fn size(&self) -> u64 {
+        self.meta
+            .as_deref()
+            .and_then(|meta| serde_json::from_str::<serde_json::Value>(meta).ok())
+            .and_then(|meta| meta.get("size").and_then(|size| size.as_u64()))
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CleanupReport {
+    pub status: CleanupStatus,
+    pub dry_run: bool,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    /// Files old enough to be considered
+    pub scanned: usize,
+    pub orphaned: usize,
+    /// Orphans handled so far
+    pub processed: usize,
+    /// Total size of the orphaned files, as recorded at upload
+    pub orphaned_bytes: u64,
+    /// Orphans that have a `file-{id}` vector collection
+    pub collections: usize,
+    pub deleted_files: usize,
+    pub deleted_collections: usize,
+    /// The orphaned files, capped at 1000 entries
+    pub files: Vec<OrphanedFile>,
+    pub error: Option<String>,
+}
+
+impl CleanupReport {
+    fn new(dry_run: bool) -> Self {
+        CleanupReport {
+            status: CleanupStatus::Running,
+            dry_run,
+            started_at: current_timestamp_seconds(),
+            finished_at: None,
+            scanned: 0,
+            orphaned: 0,
+            processed: 0,
+            orphaned_bytes: 0,
+            collections: 0,
+            deleted_files: 0,
+            deleted_collections: 0,
+            files: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+pub struct FileCleanupService<'a> {
+    db: &'a Database,
+}
+
+impl<'a> FileCleanupService<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    /// Files created before `created_before` that nothing refers to
+    pub async fn find_orphaned_files(
+        &self,
+        created_before: i64,
+    ) -> AppResult<(usize, Vec<OrphanedFile>)> {
+        let files = sqlx::query_as::<_, OrphanedFile>(
+            "SELECT id, user_id, filename, path, meta, created_at FROM file WHERE created_at < $1 ORDER BY created_at",
+        )
+        .bind(created_before)
+        .fetch_all(&self.db.pool)
+        .await?;
+        let scanned = files.len();
+
+        let mut unreferenced: HashSet<String> = files.iter().map(|f| f.id.clone()).collect();
+        for (table, column) in REFERENCE_SOURCES {
+            let mut offset = 0;
+            while !unreferenced.is_empty() {
+                let rows: Vec<(String,)> = sqlx::query_as(&format!(
+                    "SELECT {column} FROM {table} WHERE {column} IS NOT NULL ORDER BY id LIMIT $1 OFFSET $2"
+                ))
+                .bind(SCAN_BATCH_SIZE)
+                .bind(offset)
+                .fetch_all(&self.db.pool)
+                .await?;
+                for (text,) in &rows {
+                    remove_referenced(text, &mut unreferenced);
+                }
+                if (rows.len() as i64) < SCAN_BATCH_SIZE {
+                    break;
+                }
+                offset += SCAN_BATCH_SIZE;
+            }
+        }
+
+        let orphans = files
+            .into_iter()
+            .filter(|f| unreferenced.contains(&f.id))
+            .collect();
+        Ok((scanned, orphans))
+    }
+
+    /// Find orphaned files and, unless it is a dry run, delete them with their vector
+    /// collections and stored content. `on_progress` sees the report after each step.
+    pub async fn run_cleanup(
+        &self,
+        options: &CleanupOptions,
+        vector_db: Option<&Arc<dyn VectorDB>>,
+        on_progress: impl Fn(&CleanupReport),
+    ) -> AppResult<CleanupReport> {
+        let mut report = CleanupReport::new(options.dry_run);
+        on_progress(&report);
+
+        let cutoff = current_timestamp_seconds() - options.min_age_secs;
+        let (scanned, orphans) = self.find_orphaned_files(cutoff).await?;
+        report.scanned = scanned;
+        report.orphaned = orphans.len();
+        report.orphaned_bytes = orphans.iter().map(OrphanedFile::size).sum();
+        report.files = orphans.iter().take(MAX_REPORTED_FILES).cloned().collect();
+        on_progress(&report);
+
+        let file_service = FileService::new(self.db);
+        for file in &orphans {
+            let collection = format!("file-{}", file.id);
+            let has_collection = match vector_db {
+                Some(vector_db) => vector_db.has_collection(&collection).await.unwrap_or(false),
+                None => false,
+            };
+            if has_collection {
+                report.collections += 1;
+            }
+
+            if !options.dry_run {
+                if let (true, Some(vector_db)) = (has_collection, vector_db) {
+                    match vector_db.delete_collection(&collection).await {
+                        Ok(()) => report.deleted_collections += 1,
+                        Err(e) => {
+                            tracing::warn!("Failed to delete collection {}: {}", collection, e)
+                        }
+                    }
+                }
+                if !file.path.is_empty() {
+                    if let Err(e) = tokio::fs::remove_file(&file.path).await {
+                        if e.kind() != std::io::ErrorKind::NotFound {
+                            tracing::warn!("Failed to remove stored file {}: {}", file.path, e);
+                        }
+                    }
+                }
+                file_service.delete_file(&file.id).await?;
+                report.deleted_files += 1;
+            }
+
+            report.processed += 1;
+            on_progress(&report);
+        }
+
+        report.status = CleanupStatus::Completed;
+        report.finished_at = Some(current_timestamp_seconds());
+        Ok(report)
+    }
+}
+
+/// Drop every candidate ID mentioned in `text`, either bare or as a `file-{id}`
+/// collection name
+fn remove_referenced(text: &str, unreferenced: &mut HashSet<String>) {
+    for token in text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_')) {
+        if token.is_empty() {
+            continue;
+        }
+        if !unreferenced.remove(token) {
+            if let Some(id) = token.strip_prefix("file-") {
+                unreferenced.remove(id);
+            }
+        }
+    }
+}
+
+/// The running or last finished cleanup
+pub fn cleanup_status() -> Option<CleanupReport> {
+    LAST_CLEANUP.lock().unwrap().clone()
+}
+
+/// Start a cleanup in the background, unless one is already running
+pub fn start_cleanup(
+    db: Database,
+    vector_db: Option<Arc<dyn VectorDB>>,
+    options: CleanupOptions,
+) -> AppResult<CleanupReport> {
+    let report = {
+        let mut last = LAST_CLEANUP.lock().unwrap();
+        if let Some(running) = last.as_ref() {
+            if running.status == CleanupStatus::Running {
+                return Err(AppError::Conflict(
+                    "A file cleanup is already running".to_string(),
+                ));
+            }
+        }
+        let report = CleanupReport::new(options.dry_run);
+        *last = Some(report.clone());
+        report
+    };
+
+    tokio::spawn(async move {
+        let service = FileCleanupService::new(&db);
+        let result = service
+            .run_cleanup(&options, vector_db.as_ref(), |progress| {
+                *LAST_CLEANUP.lock().unwrap() = Some(progress.clone());
+            })
+            .await;
+
+        let mut last = LAST_CLEANUP.lock().unwrap();
+        match result {
+            Ok(report) => {
+                tracing::info!(
+                    "File cleanup finished (dry run: {}): {} orphaned, {} files and {} collections deleted",
+                    report.dry_run,
+                    report.orphaned,
+                    report.deleted_files,
+                    report.deleted_collections
+                );
+                *last = Some(report);
+            }
+            Err(e) => {
+                tracing::error!("File cleanup failed: {}", e);
+                if let Some(report) = last.as_mut() {
+                    report.status = CleanupStatus::Failed;
+                    report.finished_at = Some(current_timestamp_seconds());
+                    report.error = Some(e.to_string());
+                }
+            }
+        }
+    });
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    async fn setup() -> (tempfile::TempDir, Database) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = test_utils::test_db(&dir).await;
+        test_utils::create_user(&db, "alice", "user").await;
+        (dir, db)
+    }
+
+    async fn insert_file(db: &Database, id: &str, path: &str, created_at: i64) {
+        sqlx::query(
+            "INSERT INTO file (id, user_id, filename, path, meta, created_at, updated_at) VALUES ($1, 'alice', $1, $2, '{\"size\": 100}', $3, $3)",
+        )
+        .bind(id)
+        .bind(path)
+        .bind(created_at)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_finds_and_deletes_orphans() {
+        let (dir, db) = setup().await;
+        let old = current_timestamp_seconds() - 7 * 86400;
+        let stored = dir.path().join("orphan");
+        std::fs::write(&stored, b"content").unwrap();
+
+        insert_file(&db, "in-chat", "", old).await;
+        insert_file(&db, "in-knowledge", "", old).await;
+        insert_file(&db, "in-note", "", old).await;
+        insert_file(&db, "orphan", stored.to_str().unwrap(), old).await;
+        insert_file(&db, "recent", "", current_timestamp_seconds()).await;
+
+        sqlx::query(
+            "INSERT INTO chat (id, user_id, title, chat, created_at, updated_at) VALUES ('c1', 'alice', 't', $1, 0, 0)",
+        )
+        .bind(r#"{"messages": [{"files": [{"type": "file", "id": "in-chat"}]}]}"#)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO knowledge (id, user_id, name, data, created_at, updated_at) VALUES ('k1', 'alice', 'k', $1, 0, 0)",
+        )
+        .bind(r#"{"file_ids": ["in-knowledge"]}"#)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO note (id, user_id, title, data, created_at, updated_at) VALUES ('n1', 'alice', 'n', $1, 0, 0)",
+        )
+        .bind(r#"{"collection": "file-in-note"}"#)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+        let service = FileCleanupService::new(&db);
+        let mut options = CleanupOptions {
+            dry_run: true,
+            min_age_secs: 86400,
+        };
+
+        // Dry run reports without deleting
+        let steps = Mutex::new(0);
+        let report = service
+            .run_cleanup(&options, None, |_| *steps.lock().unwrap() += 1)
+            .await
+            .unwrap();
+        assert_eq!(report.status, CleanupStatus::Completed);
+        assert_eq!(report.scanned, 4);
+        assert_eq!(report.orphaned, 1);
+        assert_eq!(report.orphaned_bytes, 100);
+        assert_eq!(report.files[0].id, "orphan");
+        assert_eq!(report.deleted_files, 0);
+        assert_eq!(*steps.lock().unwrap(), 3);
+        assert!(stored.exists());
+
+        options.dry_run = false;
+        let report = service.run_cleanup(&options, None, |_| {}).await.unwrap();
+        assert_eq!(report.deleted_files, 1);
+        assert!(!stored.exists());
+
+        let remaining: Vec<(String,)> = sqlx::query_as("SELECT id FROM file ORDER BY id")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap();
+        let remaining: Vec<String> = remaining.into_iter().map(|(id,)| id).collect();
+        assert_eq!(remaining, ["in-chat", "in-knowledge", "in-note", "recent"]);
+    }
+}
@@ -1,7 +1,9 @@
+use serde_json::Value;
+
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
 use crate::models::group::{Group, GroupForm, GroupUpdateForm};
-use crate::utils::time::current_timestamp_seconds;
+use crate::utils::time::{current_timestamp_nanos, current_timestamp_seconds};
 
 pub struct GroupService<'a> {
     db: &'a Database,
@@ -299,6 +301,159 @@ impl<'a> GroupService<'a> {
             .execute(&self.db.pool)
             .await?;
 
+        if result.rows_affected() > 0 {
+            self.remove_group_from_access_controls(id).await?;
+        }
+
         Ok(result.rows_affected() > 0)
     }
+
+    /// Drop a deleted group from every resource's `access_control` so a new group can never
+    /// inherit its grants
+    async fn remove_group_from_access_controls(&self, group_id: &str) -> AppResult<()> {
+        let search_pattern = format!("%\"{}\"%", group_id);
+
+        for (table, key, timestamp) in ACCESS_CONTROLLED_TABLES {
+            let rows = sqlx::query_as::<_, (String, Option<String>)>(&format!(
+                "SELECT {key}, CAST(access_control AS TEXT) FROM {table} \
+                 WHERE CAST(access_control AS TEXT) LIKE $1",
+            ))
+            .bind(&search_pattern)
+            .fetch_all(&self.db.pool)
+            .await?;
+
+            for (row_key, access_control) in rows {
+                let mut access_control: Value =
+                    match access_control.and_then(|ac| serde_json::from_str(&ac).ok()) {
+                        Some(ac) => ac,
+                        None => continue,
+                    };
+                if !remove_group_from_access_control(&mut access_control, group_id) {
+                    continue;
+                }
+
+                sqlx::query(&format!(
+                    "UPDATE {table} SET access_control = $1, updated_at = $2 WHERE {key} = $3",
+                ))
+                .bind(access_control.to_string())
+                .bind(timestamp())
+                .bind(&row_key)
+                .execute(&self.db.pool)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A table with an `access_control` column, the column identifying each row, and the
+/// clock its `updated_at` is kept in
+type AccessControlledTable = (&'static str, &'static str, fn() -> i64);
+
+const ACCESS_CONTROLLED_TABLES: &[AccessControlledTable] = &[
+    ("knowledge", "id", current_timestamp_seconds),
+    ("model", "id", current_timestamp_seconds),
+    ("prompt", "command", current_timestamp_seconds),
+    ("tool", "id", current_timestamp_seconds),
+    ("note", "id", current_timestamp_nanos),
+    ("channel", "id", current_timestamp_nanos),
+];
+
+/// Remove `group_id` from the `read` and `write` group lists; returns whether anything changed
+pub fn remove_group_from_access_control(access_control: &mut Value, group_id: &str) -> bool {
+    let mut changed = false;
+    for access_type in ["read", "write"] {
+        let group_ids = access_control
+            .get_mut(access_type)
+            .and_then(|access| access.get_mut("group_ids"))
+            .and_then(|ids| ids.as_array_mut());
+        if let Some(group_ids) = group_ids {
+            let before = group_ids.len();
+            group_ids.retain(|id| id.as_str() != Some(group_id));
+            changed |= group_ids.len() != before;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_remove_group_from_access_control() {
+        let mut access_control = json!({
+            "read": {"group_ids": ["g1", "g2"], "user_ids": ["u1"]},
+            "write": {"group_ids": ["g1"], "user_ids": []}
+        });
+
+        assert!(remove_group_from_access_control(&mut access_control, "g1"));
+        assert_eq!(
+            access_control,
+            json!({
+                "read": {"group_ids": ["g2"], "user_ids": ["u1"]},
+                "write": {"group_ids": [], "user_ids": []}
+            })
+        );
+
+        assert!(!remove_group_from_access_control(&mut access_control, "g1"));
+        // Private ({}) and malformed values are left alone
+        assert!(!remove_group_from_access_control(&mut json!({}), "g1"));
+        assert!(!remove_group_from_access_control(
+            &mut json!({"read": null}),
+            "g1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_deleting_group_keeps_note_timestamps_in_nanoseconds() {
+        use crate::models::note::NoteForm;
+        use crate::services::note::NoteService;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = crate::test_utils::test_db(&dir).await;
+        crate::test_utils::create_user(&db, "user-1", "user").await;
+
+        let groups = GroupService::new(&db);
+        let group = groups
+            .insert_new_group(
+                "user-1",
+                &GroupForm {
+                    name: "Team".to_string(),
+                    description: String::new(),
+                    permissions: None,
+                },
+            )
+            .await
+            .unwrap();
+        let note = NoteService::new(&db)
+            .insert_new_note(
+                "user-1",
+                &NoteForm {
+                    title: "Shared".to_string(),
+                    data: None,
+                    meta: None,
+                    access_control: Some(json!({
+                        "read": {"group_ids": [group.id], "user_ids": []},
+                        "write": {"group_ids": [], "user_ids": []}
+                    })),
+                },
+            )
+            .await
+            .unwrap();
+
+        assert!(groups.delete_group_by_id(&group.id).await.unwrap());
+
+        let (updated_at, access_control): (i64, String) = sqlx::query_as(
+            "SELECT updated_at, CAST(access_control AS TEXT) FROM note WHERE id = $1",
+        )
+        .bind(&note.id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert!(!access_control.contains(&group.id));
+        assert!(updated_at >= note.updated_at);
+    }
 }
@@ -1,5 +1,6 @@
 use crate::config::Config;
 use crate::error::{AppError, AppResult};
+use crate::utils::http_client;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
@@ -34,7 +35,7 @@ pub struct ImageService {
 impl ImageService {
     pub fn new(config: Config) -> Self {
         ImageService {
-            client: Client::new(),
+            client: http_client::client(&config),
             config,
         }
     }
@@ -98,28 +98,23 @@ impl<'a> MemoryService<'a> {
         meta: Option<serde_json::Value>,
     ) -> AppResult<Memory> {
         let now = current_timestamp_seconds();
+        let meta_str = meta
+            .as_ref()
+            .map(|v| serde_json::to_string(v).unwrap_or_else(|_| "{}".to_string()));
 
-        let mut query_parts = vec!["UPDATE memory SET updated_at = $2"];
-        let mut bind_values: Vec<String> = vec![now.to_string()];
-
-        if let Some(content) = content {
-            query_parts.push("content = $1");
-            bind_values.push(content.to_string());
-        }
-        if let Some(meta) = meta {
-            query_parts.push("meta = ?");
-            bind_values.push(serde_json::to_string(&meta).unwrap());
-        }
-
-        let query_str = format!("{} WHERE id = $1", query_parts.join(", "));
-
-        let mut query = sqlx::query(&query_str);
-        for value in bind_values {
-            query = query.bind(value);
-        }
-        query = query.bind(id);
-
-        query.execute(&self.db.pool).await?;
+        sqlx::query(
+            r#"
+            UPDATE memory
+            SET content = COALESCE($1, content), meta = COALESCE($2, meta), updated_at = $3
+            WHERE id = $4
+            "#,
+        )
+        .bind(content)
+        .bind(&meta_str)
+        .bind(now)
+        .bind(id)
+        .execute(&self.db.pool)
+        .await?;
 
         self.get_memory_by_id(id)
             .await?
@@ -244,13 +244,23 @@ impl<'a> MessageService<'a> {
             .ok_or_else(|| AppError::NotFound("Message not found".to_string()))
     }
 
+    /// Delete a message. Deleting a thread parent cascades to its replies so threads don't orphan.
     pub async fn delete_message(&self, message_id: &str) -> AppResult<()> {
-        // Delete reactions first
+        // Delete reactions on replies and on the message itself
+        sqlx::query(
+            "DELETE FROM message_reaction WHERE message_id IN (SELECT id FROM message WHERE parent_id = $1)",
+        )
+        .bind(message_id)
+        .execute(&self.db.pool)
+        .await?;
         sqlx::query("DELETE FROM message_reaction WHERE message_id = $1")
             .bind(message_id)
             .execute(&self.db.pool)
             .await?;
 
+        // Delete thread replies
+        self.delete_replies(message_id).await?;
+
         // Delete message
         sqlx::query("DELETE FROM message WHERE id = $1")
             .bind(message_id)
@@ -269,10 +279,15 @@ impl<'a> MessageService<'a> {
         let id = uuid::Uuid::new_v4().to_string();
         let now = current_timestamp();
 
+        // A user can react with the same emoji only once per message
         sqlx::query(
             r#"
             INSERT INTO message_reaction (id, message_id, user_id, name, created_at)
-            VALUES ($1, $2, $3, $4, $5)
+            SELECT $1, $2, $3, $4, $5
+            WHERE NOT EXISTS (
+                SELECT 1 FROM message_reaction
+                WHERE message_id = $2 AND user_id = $3 AND name = $4
+            )
             "#,
         )
         .bind(&id)
@@ -310,33 +325,107 @@ impl<'a> MessageService<'a> {
         Ok(())
     }
 
+    pub async fn has_reaction(
+        &self,
+        message_id: &str,
+        user_id: &str,
+        name: &str,
+    ) -> AppResult<bool> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM message_reaction WHERE message_id = $1 AND user_id = $2 AND name = $3",
+        )
+        .bind(message_id)
+        .bind(user_id)
+        .bind(name)
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok(count > 0)
+    }
+
     pub async fn get_reactions(&self, message_id: &str) -> AppResult<Vec<Reaction>> {
         let reactions = sqlx::query_as::<_, MessageReaction>(
-            "SELECT id, message_id, user_id, name, created_at FROM message_reaction WHERE message_id = $1"
+            "SELECT id, message_id, user_id, name, created_at FROM message_reaction WHERE message_id = $1 ORDER BY created_at ASC"
         )
         .bind(message_id)
         .fetch_all(&self.db.pool)
         .await?;
 
-        // Group by name
-        let mut grouped: HashMap<String, Vec<String>> = HashMap::new();
+        Ok(group_reactions(reactions))
+    }
+
+    /// Reaction summaries for several messages in a single query
+    pub async fn get_reactions_by_message_ids(
+        &self,
+        message_ids: &[String],
+    ) -> AppResult<HashMap<String, Vec<Reaction>>> {
+        if message_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let placeholders: Vec<String> =
+            (1..=message_ids.len()).map(|i| format!("${}", i)).collect();
+        let query_str = format!(
+            r#"
+            SELECT id, message_id, user_id, name, created_at
+            FROM message_reaction
+            WHERE message_id IN ({})
+            ORDER BY created_at ASC
+            "#,
+            placeholders.join(", ")
+        );
+
+        let mut query = sqlx::query_as::<_, MessageReaction>(&query_str);
+        for id in message_ids {
+            query = query.bind(id);
+        }
+        let reactions = query.fetch_all(&self.db.pool).await?;
+
+        let mut by_message: HashMap<String, Vec<MessageReaction>> = HashMap::new();
         for reaction in reactions {
-            grouped
-                .entry(reaction.name.clone())
-                .or_insert_with(Vec::new)
-                .push(reaction.user_id);
+            by_message
+                .entry(reaction.message_id.clone())
+                .or_default()
+                .push(reaction);
         }
 
-        let result = grouped
+        Ok(by_message
             .into_iter()
-            .map(|(name, user_ids)| Reaction {
-                count: user_ids.len(),
-                name,
-                user_ids,
-            })
-            .collect();
+            .map(|(message_id, reactions)| (message_id, group_reactions(reactions)))
+            .collect())
+    }
 
-        Ok(result)
+    /// Reply count and latest reply timestamp for several thread parents in a single query
+    pub async fn get_thread_summaries(
+        &self,
+        message_ids: &[String],
+    ) -> AppResult<HashMap<String, (i64, Option<i64>)>> {
+        if message_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let placeholders: Vec<String> =
+            (1..=message_ids.len()).map(|i| format!("${}", i)).collect();
+        let query_str = format!(
+            r#"
+            SELECT parent_id, COUNT(*), MAX(created_at)
+            FROM message
+            WHERE parent_id IN ({})
+            GROUP BY parent_id
+            "#,
+            placeholders.join(", ")
+        );
+
+        let mut query = sqlx::query_as::<_, (String, i64, Option<i64>)>(&query_str);
+        for id in message_ids {
+            query = query.bind(id);
+        }
+        let rows = query.fetch_all(&self.db.pool).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(parent_id, count, latest)| (parent_id, (count, latest)))
+            .collect())
     }
 
     pub async fn delete_replies(&self, parent_id: &str) -> AppResult<()> {
@@ -363,3 +452,133 @@ impl<'a> MessageService<'a> {
         Ok(response)
     }
 }
+
+/// Group raw reactions by emoji, keeping the order in which each emoji was first used
+fn group_reactions(reactions: Vec<MessageReaction>) -> Vec<Reaction> {
+    let mut grouped: Vec<Reaction> = Vec::new();
+    for reaction in reactions {
+        match grouped.iter_mut().find(|r| r.name == reaction.name) {
+            Some(existing) => {
+                existing.user_ids.push(reaction.user_id);
+                existing.count += 1;
+            }
+            None => grouped.push(Reaction {
+                name: reaction.name,
+                user_ids: vec![reaction.user_id],
+                count: 1,
+            }),
+        }
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::channel::ChannelService;
+    use crate::test_utils;
+
+    fn message_form(content: &str, parent_id: Option<&str>) -> MessageForm {
+        MessageForm {
+            content: content.to_string(),
+            reply_to_id: None,
+            parent_id: parent_id.map(|id| id.to_string()),
+            data: None,
+            meta: None,
+        }
+    }
+
+    async fn setup_db(dir: &tempfile::TempDir) -> Database {
+        let db = test_utils::test_db(dir).await;
+        test_utils::create_user(&db, "alice", "user").await;
+
+        ChannelService::new(&db)
+            .create_channel("general", "alice", "general", None, None, None, None, None)
+            .await
+            .unwrap();
+
+        db
+    }
+
+    #[tokio::test]
+    async fn test_reactions_are_unique_per_user_and_emoji() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = setup_db(&dir).await;
+        let service = MessageService::new(&db);
+
+        let message = service
+            .create_message("general", "alice", &message_form("hi", None))
+            .await
+            .unwrap();
+        service
+            .add_reaction(&message.id, "alice", "👍")
+            .await
+            .unwrap();
+        service
+            .add_reaction(&message.id, "alice", "👍")
+            .await
+            .unwrap();
+        service
+            .add_reaction(&message.id, "alice", "🎉")
+            .await
+            .unwrap();
+
+        let reactions = service.get_reactions(&message.id).await.unwrap();
+        assert_eq!(reactions.len(), 2);
+        assert_eq!(reactions[0].name, "👍");
+        assert_eq!(reactions[0].count, 1);
+        assert!(service
+            .has_reaction(&message.id, "alice", "🎉")
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_thread_summaries_and_cascading_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = setup_db(&dir).await;
+        let service = MessageService::new(&db);
+
+        let parent = service
+            .create_message("general", "alice", &message_form("topic", None))
+            .await
+            .unwrap();
+        let reply = service
+            .create_message("general", "alice", &message_form("first", Some(&parent.id)))
+            .await
+            .unwrap();
+        service
+            .create_message(
+                "general",
+                "alice",
+                &message_form("second", Some(&parent.id)),
+            )
+            .await
+            .unwrap();
+        service
+            .add_reaction(&reply.id, "alice", "👀")
+            .await
+            .unwrap();
+
+        let summaries = service
+            .get_thread_summaries(&[parent.id.clone(), reply.id.clone()])
+            .await
+            .unwrap();
+        assert_eq!(summaries[&parent.id].0, 2);
+        assert!(summaries[&parent.id].1.is_some());
+        assert!(!summaries.contains_key(&reply.id));
+
+        service.delete_message(&parent.id).await.unwrap();
+
+        assert!(service
+            .get_message_by_id(&reply.id)
+            .await
+            .unwrap()
+            .is_none());
+        let reactions = service
+            .get_reactions_by_message_ids(std::slice::from_ref(&reply.id))
+            .await
+            .unwrap();
+        assert!(reactions.is_empty());
+    }
+}
@@ -1,10 +1,12 @@
 pub mod audio;
+pub mod audit;
 pub mod auth;
 pub mod channel;
 pub mod chat;
 pub mod config;
 pub mod feedback;
 pub mod file;
+pub mod file_cleanup;
 pub mod folder;
 pub mod function;
 pub mod group;
@@ -26,6 +28,7 @@ pub mod sandbox_executor;
 pub mod static_files;
 pub mod tool;
 pub mod tool_runtime;
+pub mod usage;
 pub mod user;
 
 pub use auth::*;
@@ -1,10 +1,20 @@
 use crate::config::Config;
 use crate::error::{AppError, AppResult};
+use crate::utils::anthropic;
+use crate::utils::gemini;
+use crate::utils::http_client;
+use crate::utils::misc::has_access;
+use crate::utils::model_images;
+use crate::utils::telemetry;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::HashSet;
 use tracing::warn;
 
+/// Arena model offered when `EVALUATION_ARENA_MODELS` is empty
+pub const DEFAULT_ARENA_MODEL_ID: &str = "arena-model";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Model {
     pub id: String,
@@ -28,6 +38,12 @@ pub struct ModelInfo {
     pub meta: Option<ModelMeta>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub params: Option<Value>,
+    /// Owner of a workspace model; `None` for models served directly by a backend
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// Workspace access control; `None` means public
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_control: Option<Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,6 +94,42 @@ pub struct Tag {
     pub name: String,
 }
 
+/// Model entry in the strict OpenAI `GET /v1/models` shape
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIModel {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    pub owned_by: String,
+}
+
+/// Response body of the OpenAI-compatible `GET /v1/models` endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIModelList {
+    pub object: String,
+    pub data: Vec<OpenAIModel>,
+}
+
+impl From<&Model> for OpenAIModel {
+    fn from(model: &Model) -> Self {
+        OpenAIModel {
+            id: model.id.clone(),
+            object: "model".to_string(),
+            created: model.created,
+            owned_by: model.owned_by.clone(),
+        }
+    }
+}
+
+impl OpenAIModelList {
+    pub fn from_models(models: &[Model]) -> Self {
+        OpenAIModelList {
+            object: "list".to_string(),
+            data: models.iter().map(OpenAIModel::from).collect(),
+        }
+    }
+}
+
 pub struct ModelService {
     client: Client,
     config: Config,
@@ -86,7 +138,7 @@ pub struct ModelService {
 impl ModelService {
     pub fn new(config: Config) -> Self {
         Self {
-            client: Client::new(),
+            client: http_client::client(&config),
             config,
         }
     }
@@ -216,6 +268,8 @@ impl ModelService {
                                     profile_image_url: None,
                                 }),
                                 params: Some(json!({ "urlIdx": idx })),
+                                user_id: None,
+                                access_control: None,
                             });
                         }
                     }
@@ -241,6 +295,7 @@ impl ModelService {
             .and_then(|c| c.get("azure"))
             .and_then(|v| v.as_bool())
             .unwrap_or(false);
+        let is_gemini = config.is_some_and(|c| gemini::is_gemini(&json!(c)));
 
         let url = if is_azure {
             let api_version = config
@@ -271,6 +326,10 @@ impl ModelService {
                     request = request.header("api-key", api_key);
                 }
             }
+        } else if config.is_some_and(|c| anthropic::is_anthropic(&json!(c))) {
+            request = anthropic::with_auth(request, api_key);
+        } else if is_gemini {
+            request = gemini::with_auth(request, api_key);
         } else {
             // Standard Bearer token for OpenAI and compatible APIs
             if !api_key.is_empty() {
@@ -278,7 +337,8 @@ impl ModelService {
             }
         }
 
-        let response = request.send().await?;
+        let span = telemetry::upstream_span("list_models", base_url, None);
+        let response = telemetry::send_traced(request, span).await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -290,6 +350,11 @@ impl ModelService {
         }
 
         let response_data: Value = response.json().await?;
+        let response_data = if is_gemini {
+            gemini::to_model_list(&response_data)
+        } else {
+            response_data
+        };
 
         let models: Vec<Model> = response_data
             .get("data")
@@ -298,8 +363,10 @@ impl ModelService {
                 arr.iter()
                     .filter_map(|v| {
                         let id = v.get("id")?.as_str()?;
+                        // Anthropic lists models with a `display_name`
                         let name = v
                             .get("name")
+                            .or_else(|| v.get("display_name"))
                             .and_then(|n| n.as_str().map(|s| s.to_string()))
                             .or_else(|| Some(id.to_string()));
                         Some(Model {
@@ -318,7 +385,9 @@ impl ModelService {
                                 .to_string(),
                             info: None,
                             pipeline: None,
-                            tags: None,
+                            tags: v
+                                .get("tags")
+                                .and_then(|t| serde_json::from_value(t.clone()).ok()),
                             arena: None,
                         })
                     })
@@ -372,6 +441,8 @@ impl ModelService {
                         }),
                     }),
                     params: None,
+                    user_id: None,
+                    access_control: None,
                 }),
                 pipeline: Some(PipelineInfo {
                     pipeline_type: Some("pipe".to_string()),
@@ -402,46 +473,52 @@ impl ModelService {
                 .await?;
 
         for custom in custom_models {
-            if let Some(base_model_id) = &custom.base_model_id {
-                // Find and overlay on existing base model
-                if let Some(model) = base_models.iter_mut().find(|m| &m.id == base_model_id) {
-                    // Update model with custom data
-                    model.name = Some(custom.name.clone());
-
-                    // Merge info/meta
-                    if let Some(ref mut info) = model.info {
-                        if let Some(ref mut meta) = info.meta {
-                            // Merge tags from custom model
-                            if let Some(custom_meta) = &custom.meta {
-                                if let Some(action_ids) = custom_meta.get("actionIds") {
-                                    meta.tags = Some(vec![Tag {
-                                        name: "custom".to_string(),
-                                    }]);
-                                }
+            let custom_meta = custom
+                .meta
+                .as_ref()
+                .map(|m| custom_model_meta(&custom.id, m));
+
+            if let Some(model) = base_models.iter_mut().find(|m| m.id == custom.id) {
+                // A workspace entry with the same ID overrides the backend model
+                model.name = Some(custom.name.clone());
+
+                // Merge info/meta
+                if let Some(ref mut info) = model.info {
+                    if let Some(ref mut meta) = info.meta {
+                        // Merge tags from custom model
+                        if let Some(custom_meta_value) = &custom.meta {
+                            if custom_meta_value.get("actionIds").is_some() {
+                                meta.tags = Some(vec![Tag {
+                                    name: "custom".to_string(),
+                                }]);
+                            }
+                        }
+                        if let Some(custom_meta) = &custom_meta {
+                            if custom_meta.description.is_some() {
+                                meta.description = custom_meta.description.clone();
+                            }
+                            if custom_meta.profile_image_url.is_some() {
+                                meta.profile_image_url = custom_meta.profile_image_url.clone();
                             }
                         }
-
-                        // Store custom model params
-                        info.params = Some(custom.params.clone());
                     } else {
-                        model.info = Some(ModelInfo {
-                            meta: custom.meta.as_ref().map(|m| ModelMeta {
-                                description: m
-                                    .get("description")
-                                    .and_then(|d| d.as_str().map(|s| s.to_string())),
-                                capabilities: None,
-                                tags: None,
-                                knowledge: None,
-                                profile_image_url: m
-                                    .get("profile_image_url")
-                                    .and_then(|d| d.as_str().map(|s| s.to_string())),
-                            }),
-                            params: Some(custom.params.clone()),
-                        });
+                        info.meta = custom_meta;
                     }
+
+                    // Store custom model params
+                    info.params = Some(custom.params.clone());
+                    info.user_id = Some(custom.user_id.clone());
+                    info.access_control = custom.access_control.clone();
+                } else {
+                    model.info = Some(ModelInfo {
+                        meta: custom_meta,
+                        params: Some(custom.params.clone()),
+                        user_id: Some(custom.user_id.clone()),
+                        access_control: custom.access_control.clone(),
+                    });
                 }
             } else {
-                // Add as a new custom model (not based on existing model)
+                // Add as a new custom model built on top of a base model
                 base_models.push(Model {
                     id: custom.id.clone(),
                     name: Some(custom.name.clone()),
@@ -449,18 +526,10 @@ impl ModelService {
                     created: custom.created_at,
                     owned_by: "custom".to_string(),
                     info: Some(ModelInfo {
-                        meta: custom.meta.as_ref().map(|m| ModelMeta {
-                            description: m
-                                .get("description")
-                                .and_then(|d| d.as_str().map(|s| s.to_string())),
-                            capabilities: None,
-                            tags: None,
-                            knowledge: None,
-                            profile_image_url: m
-                                .get("profile_image_url")
-                                .and_then(|d| d.as_str().map(|s| s.to_string())),
-                        }),
+                        meta: custom_meta,
                         params: Some(custom.params.clone()),
+                        user_id: Some(custom.user_id.clone()),
+                        access_control: custom.access_control.clone(),
                     }),
                     pipeline: None,
                     tags: None,
@@ -472,6 +541,18 @@ impl ModelService {
         Ok(base_models)
     }
 
+    /// Configured arena models, or the default arena over every model when none are set
+    fn arena_model_configs(&self) -> Vec<Value> {
+        match self.config.evaluation_arena_models.as_array() {
+            Some(models) if !models.is_empty() => models.clone(),
+            _ => vec![json!({
+                "id": DEFAULT_ARENA_MODEL_ID,
+                "name": "Arena Model",
+                "meta": {"description": "Model for evaluation arena"},
+            })],
+        }
+    }
+
     /// Get arena models from configuration
     fn get_arena_models(&self) -> Vec<Model> {
         use std::time::{SystemTime, UNIX_EPOCH};
@@ -481,63 +562,91 @@ impl ModelService {
             .unwrap()
             .as_secs() as i64;
 
-        // Check if custom arena models are configured
-        if let Some(models_array) = self.config.evaluation_arena_models.as_array() {
-            return models_array
-                .iter()
-                .filter_map(|m| {
-                    let id = m.get("id")?.as_str()?;
-                    let name = m.get("name")?.as_str()?;
-
-                    Some(Model {
-                        id: id.to_string(),
-                        name: Some(name.to_string()),
-                        object: "model".to_string(),
-                        created,
-                        owned_by: "arena".to_string(),
-                        info: Some(ModelInfo {
-                            meta: m.get("meta").and_then(|meta| {
-                                Some(ModelMeta {
-                                    description: meta
-                                        .get("description")
-                                        .and_then(|d| d.as_str().map(|s| s.to_string())),
-                                    capabilities: None,
-                                    tags: None,
-                                    knowledge: None,
-                                    profile_image_url: None,
-                                })
-                            }),
-                            params: None,
+        self.arena_model_configs()
+            .iter()
+            .filter_map(|m| {
+                let id = m.get("id")?.as_str()?;
+                let name = m.get("name")?.as_str()?;
+
+                Some(Model {
+                    id: id.to_string(),
+                    name: Some(name.to_string()),
+                    object: "model".to_string(),
+                    created,
+                    owned_by: "arena".to_string(),
+                    info: Some(ModelInfo {
+                        meta: m.get("meta").map(|meta| ModelMeta {
+                            description: meta
+                                .get("description")
+                                .and_then(|d| d.as_str().map(|s| s.to_string())),
+                            capabilities: None,
+                            tags: None,
+                            knowledge: None,
+                            profile_image_url: meta
+                                .get("profile_image_url")
+                                .and_then(|d| d.as_str().map(|s| s.to_string())),
                         }),
-                        pipeline: None,
-                        tags: None,
-                        arena: Some(true),
-                    })
+                        params: None,
+                        user_id: None,
+                        access_control: None,
+                    }),
+                    pipeline: None,
+                    tags: None,
+                    arena: Some(true),
                 })
-                .collect();
-        }
+            })
+            .collect()
+    }
 
-        // Return default arena model if no custom models configured
-        vec![Model {
-            id: "arena-model".to_string(),
-            name: Some("Arena Model".to_string()),
-            object: "model".to_string(),
-            created,
-            owned_by: "arena".to_string(),
-            info: Some(ModelInfo {
-                meta: Some(ModelMeta {
-                    description: Some("Model for evaluation arena".to_string()),
-                    capabilities: None,
-                    tags: None,
-                    knowledge: None,
-                    profile_image_url: None,
-                }),
-                params: None,
-            }),
-            pipeline: None,
-            tags: None,
-            arena: Some(true),
-        }]
+    /// Whether `model_id` names an enabled arena model
+    pub fn is_arena_model(&self, model_id: &str) -> bool {
+        self.config.enable_evaluation_arena_models
+            && self
+                .arena_model_configs()
+                .iter()
+                .any(|m| m.get("id").and_then(|v| v.as_str()) == Some(model_id))
+    }
+
+    /// Pick the model that answers for an arena model, at random from the candidates the
+    /// user can access
+    pub async fn select_arena_model(
+        &self,
+        db: &crate::db::Database,
+        arena_model_id: &str,
+        user_id: &str,
+        user_role: &str,
+        user_group_ids: &HashSet<String>,
+    ) -> AppResult<String> {
+        use rand::seq::IndexedRandom;
+
+        let arena_meta = self
+            .arena_model_configs()
+            .into_iter()
+            .find(|m| m.get("id").and_then(|v| v.as_str()) == Some(arena_model_id))
+            .and_then(|m| m.get("meta").cloned())
+            .ok_or_else(|| AppError::NotFound("Arena model not found".to_string()))?;
+
+        let available: Vec<String> = self
+            .filter_models_by_access(
+                self.get_all_models(db).await?,
+                user_id,
+                user_role,
+                user_group_ids,
+            )
+            .into_iter()
+            .filter(|m| m.arena != Some(true))
+            .map(|m| m.id)
+            .collect();
+
+        arena_candidates(&arena_meta, &available)
+            .choose(&mut rand::rng())
+            .cloned()
+            .ok_or_else(|| {
+                AppError::BadRequest(format!(
+                    "No models available for arena model {}",
+                    arena_model_id
+                ))
+            })
     }
 
     /// Attach global actions and filters to models
@@ -661,7 +770,13 @@ impl ModelService {
     }
 
     /// Check if user has access to a model
-    pub fn check_model_access(&self, model: &Model, user_id: &str, user_role: &str) -> bool {
+    pub fn check_model_access(
+        &self,
+        model: &Model,
+        user_id: &str,
+        user_role: &str,
+        user_group_ids: &HashSet<String>,
+    ) -> bool {
         // Admins have access to all models
         if user_role == "admin" {
             return true;
@@ -672,56 +787,17 @@ impl ModelService {
             return true; // Arena models are accessible to all authenticated users
         }
 
-        // Check access control from model info
-        if let Some(info) = &model.info {
-            if let Some(params) = &info.params {
-                // Check if model has access_control
-                if let Some(access_control) = params.get("access_control") {
-                    // If access control exists, check permissions
-                    if let Some(access_obj) = access_control.as_object() {
-                        // Check read access
-                        if let Some(read) = access_obj.get("read") {
-                            // Check if user is in read.user_ids
-                            if let Some(user_ids) = read.get("user_ids").and_then(|v| v.as_array())
-                            {
-                                for id in user_ids {
-                                    if id.as_str() == Some(user_id) {
-                                        return true;
-                                    }
-                                }
-                            }
-
-                            // Check if user is in read.group_ids (would need group membership check)
-                            if let Some(group_ids) =
-                                read.get("group_ids").and_then(|v| v.as_array())
-                            {
-                                if !group_ids.is_empty() {
-                                    // TODO: Check user's group memberships
-                                    // For now, allow if user has any groups
-                                }
-                            }
-                        }
-                    }
-
-                    // If access control is defined but user not in list, deny access
-                    return false;
-                }
-            }
-        }
+        // Only workspace models carry access control; backend models are public
+        let info = match &model.info {
+            Some(info) if info.user_id.is_some() => info,
+            _ => return true,
+        };
 
-        // Check model ownership (from database model records)
-        if let Some(info) = &model.info {
-            if let Some(params) = &info.params {
-                if let Some(owner_id) = params.get("user_id").and_then(|v| v.as_str()) {
-                    if owner_id == user_id {
-                        return true;
-                    }
-                }
-            }
+        if info.user_id.as_deref() == Some(user_id) && !user_id.is_empty() {
+            return true;
         }
 
-        // Default: allow access for models without explicit access control
-        true
+        has_access(user_id, "read", &info.access_control, user_group_ids)
     }
 
     /// Filter models based on user access
@@ -730,6 +806,7 @@ impl ModelService {
         models: Vec<Model>,
         user_id: &str,
         user_role: &str,
+        user_group_ids: &HashSet<String>,
     ) -> Vec<Model> {
         models
             .into_iter()
@@ -742,16 +819,111 @@ impl ModelService {
                 }
 
                 // Check user access
-                self.check_model_access(m, user_id, user_role)
+                self.check_model_access(m, user_id, user_role, user_group_ids)
             })
             .collect()
     }
 }
 
+/// Candidate pool for an arena model: `meta.model_ids` narrowed to `available`, or every
+/// available model except those IDs when `meta.filter_mode` is `"exclude"`
+pub fn arena_candidates(arena_meta: &Value, available: &[String]) -> Vec<String> {
+    let model_ids: HashSet<&str> = arena_meta
+        .get("model_ids")
+        .and_then(|v| v.as_array())
+        .map(|ids| ids.iter().filter_map(|id| id.as_str()).collect())
+        .unwrap_or_default();
+    let exclude = arena_meta.get("filter_mode").and_then(|v| v.as_str()) == Some("exclude");
+
+    available
+        .iter()
+        .filter(|id| model_ids.is_empty() || model_ids.contains(id.as_str()) != exclude)
+        .cloned()
+        .collect()
+}
+
+/// Listing metadata for a workspace model; inline images are replaced by their URL
+fn custom_model_meta(model_id: &str, meta: &Value) -> ModelMeta {
+    let mut meta = meta.clone();
+    model_images::compact_profile_image(model_id, &mut meta);
+
+    ModelMeta {
+        description: meta
+            .get("description")
+            .and_then(|d| d.as_str().map(|s| s.to_string())),
+        capabilities: None,
+        tags: None,
+        knowledge: None,
+        profile_image_url: meta
+            .get("profile_image_url")
+            .and_then(|d| d.as_str().map(|s| s.to_string())),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn workspace_model(owner: &str, access_control: Option<Value>) -> Model {
+        Model {
+            id: "custom".to_string(),
+            name: None,
+            object: "model".to_string(),
+            created: 0,
+            owned_by: "custom".to_string(),
+            info: Some(ModelInfo {
+                meta: None,
+                params: None,
+                user_id: Some(owner.to_string()),
+                access_control,
+            }),
+            pipeline: None,
+            tags: None,
+            arena: None,
+        }
+    }
+
+    #[test]
+    fn test_check_model_access() {
+        std::env::set_var("DATABASE_URL", "postgres://localhost/test");
+        let service = ModelService::new(Config::from_env().expect("Failed to create test config"));
+        let no_groups = HashSet::new();
+        let group = HashSet::from(["g1".to_string()]);
+
+        let private = workspace_model("owner", Some(json!({})));
+        assert!(service.check_model_access(&private, "owner", "user", &no_groups));
+        assert!(!service.check_model_access(&private, "other", "user", &no_groups));
+        assert!(service.check_model_access(&private, "other", "admin", &no_groups));
+
+        let public = workspace_model("owner", None);
+        assert!(service.check_model_access(&public, "other", "user", &no_groups));
+
+        let shared = workspace_model(
+            "owner",
+            Some(json!({"read": {"group_ids": ["g1"], "user_ids": []}})),
+        );
+        assert!(service.check_model_access(&shared, "other", "user", &group));
+        assert!(!service.check_model_access(&shared, "other", "user", &no_groups));
+    }
+
+    #[test]
+    fn test_arena_candidates() {
+        let available = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        assert_eq!(arena_candidates(&json!({}), &available), available);
+        assert_eq!(
+            arena_candidates(&json!({"model_ids": ["c", "a", "gone"]}), &available),
+            vec!["a", "c"]
+        );
+        assert_eq!(
+            arena_candidates(
+                &json!({"model_ids": ["a"], "filter_mode": "exclude"}),
+                &available
+            ),
+            vec!["b", "c"]
+        );
+    }
+
     #[tokio::test]
     async fn test_model_service_creation() {
         // Use Config::from_env() which provides defaults for all fields
@@ -1,12 +1,22 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 
+use crate::config::Config;
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
+use crate::utils::encryption;
+
+/// Seconds a user has to complete an authorization after it was started
+const AUTHORIZATION_TTL_SECS: i64 = 600;
+
+/// Access tokens are refreshed this many seconds before they expire
+const TOKEN_EXPIRY_LEEWAY_SECS: i64 = 60;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OAuthClientInfo {
@@ -26,6 +36,18 @@ pub struct OAuthToken {
     pub expires_in: Option<i64>,
     pub refresh_token: Option<String>,
     pub scope: Option<String>,
+    /// Unix time the access token expires, worked out from `expires_in` when stored
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+/// What `/authorize` hands to `/callback` through the `state` parameter, encrypted
+#[derive(Debug, Serialize, Deserialize)]
+struct AuthorizationState {
+    client_id: String,
+    user_id: String,
+    code_verifier: String,
+    expires_at: i64,
 }
 
 /// OAuth Client Manager for MCP OAuth 2.1 and other integrations
@@ -35,6 +57,8 @@ pub struct OAuthClientManager {
     tokens: Arc<RwLock<HashMap<String, HashMap<String, OAuthToken>>>>, // user_id -> client_id -> token
     client: Client,
     db: Option<Database>,
+    /// Encrypts stored tokens and authorization state
+    secret_key: Option<String>,
 }
 
 impl OAuthClientManager {
@@ -44,9 +68,47 @@ impl OAuthClientManager {
             tokens: Arc::new(RwLock::new(HashMap::new())),
             client: Client::new(),
             db,
+            secret_key: None,
         }
     }
 
+    /// Manager for the clients registered in `OAUTH_CLIENTS`, storing tokens in the
+    /// database encrypted with `WEBUI_SECRET_KEY`
+    ///
+    /// Clients without a redirect URI use this server's callback route.
+    pub fn from_config(db: &Database, config: &Config) -> Self {
+        let clients = config
+            .oauth_clients
+            .iter()
+            .map(|(id, info)| {
+                let mut info = info.clone();
+                info.redirect_uri.get_or_insert_with(|| {
+                    format!(
+                        "{}/oauth/clients/{}/callback",
+                        config.webui_url.trim_end_matches('/'),
+                        urlencoding::encode(id)
+                    )
+                });
+                (id.clone(), info)
+            })
+            .collect();
+
+        let mut manager = Self::new(Some(db.clone())).with_secret_key(&config.webui_secret_key);
+        manager.clients = Arc::new(RwLock::new(clients));
+        manager
+    }
+
+    pub fn with_secret_key(mut self, secret_key: &str) -> Self {
+        self.secret_key = Some(secret_key.to_string());
+        self
+    }
+
+    fn secret_key(&self) -> AppResult<&str> {
+        self.secret_key
+            .as_deref()
+            .ok_or_else(|| AppError::Internal("No secret key for OAuth clients".to_string()))
+    }
+
     /// Register a new OAuth client
     pub async fn add_client(
         &self,
@@ -80,42 +142,77 @@ impl OAuthClientManager {
         client_id: &str,
         force_refresh: bool,
     ) -> AppResult<Option<OAuthToken>> {
-        let tokens = self.tokens.read().await;
+        let cached = self
+            .tokens
+            .read()
+            .await
+            .get(user_id)
+            .and_then(|user_tokens| user_tokens.get(client_id))
+            .cloned();
+        let token = match cached {
+            Some(token) => token,
+            None => match self.load_token(user_id, client_id).await? {
+                Some(token) => token,
+                None => return Ok(None),
+            },
+        };
 
-        if let Some(user_tokens) = tokens.get(user_id) {
-            if let Some(token) = user_tokens.get(client_id) {
-                // Check if token is expired
-                if !force_refresh && !self.is_token_expired(token) {
-                    return Ok(Some(token.clone()));
-                }
+        // Check if token is expired
+        if !force_refresh && !self.is_token_expired(&token) {
+            return Ok(Some(token));
+        }
 
-                // Try to refresh the token
-                if let Some(refresh_token) = token.refresh_token.clone() {
-                    drop(tokens); // Release read lock before write
-                    return match self
-                        .refresh_access_token(user_id, client_id, &refresh_token)
-                        .await
-                    {
-                        Ok(new_token) => Ok(Some(new_token)),
-                        Err(e) => {
-                            warn!("Failed to refresh token: {}", e);
-                            Ok(None)
-                        }
-                    };
+        // Try to refresh the token
+        match token.refresh_token {
+            Some(refresh_token) => match self
+                .refresh_access_token(user_id, client_id, &refresh_token)
+                .await
+            {
+                Ok(new_token) => Ok(Some(new_token)),
+                Err(e) => {
+                    warn!("Failed to refresh token: {}", e);
+                    Ok(None)
                 }
-            }
+            },
+            None => Ok(None),
         }
-
-        Ok(None)
     }
 
-    /// Store OAuth token for a user and client
+    /// Store OAuth token for a user and client, encrypted in the database when the
+    /// manager has one
     pub async fn store_token(
         &self,
         user_id: &str,
         client_id: &str,
-        token: OAuthToken,
+        mut token: OAuthToken,
     ) -> AppResult<()> {
+        let now = chrono::Utc::now().timestamp();
+        if token.expires_at.is_none() {
+            token.expires_at = token.expires_in.map(|expires_in| now + expires_in);
+        }
+
+        if let (Some(db), Some(secret_key)) = (&self.db, &self.secret_key) {
+            let data = encryption::encrypt(secret_key, &serde_json::to_vec(&token).unwrap());
+            sqlx::query(
+                r#"
+                INSERT INTO oauth_client_token
+                    (user_id, client_id, data, expires_at, created_at, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $5)
+                ON CONFLICT (user_id, client_id) DO UPDATE
+                SET data = excluded.data,
+                    expires_at = excluded.expires_at,
+                    updated_at = excluded.updated_at
+                "#,
+            )
+            .bind(user_id)
+            .bind(client_id)
+            .bind(data)
+            .bind(token.expires_at)
+            .bind(now)
+            .execute(&db.pool)
+            .await?;
+        }
+
         let mut tokens = self.tokens.write().await;
         tokens
             .entry(user_id.to_string())
@@ -129,11 +226,42 @@ impl OAuthClientManager {
         Ok(())
     }
 
-    /// Check if token is expired
-    fn is_token_expired(&self, _token: &OAuthToken) -> bool {
-        // TODO: Implement actual expiration checking
-        // For now, assume not expired
-        false
+    /// Stored token of a user for a client, if it can still be decrypted
+    async fn load_token(&self, user_id: &str, client_id: &str) -> AppResult<Option<OAuthToken>> {
+        let (db, secret_key) = match (&self.db, &self.secret_key) {
+            (Some(db), Some(secret_key)) => (db, secret_key),
+            _ => return Ok(None),
+        };
+
+        let data: Option<String> = sqlx::query_scalar(
+            "SELECT data FROM oauth_client_token WHERE user_id = $1 AND client_id = $2",
+        )
+        .bind(user_id)
+        .bind(client_id)
+        .fetch_optional(&db.pool)
+        .await?;
+
+        let token = data.and_then(|data| {
+            let token = encryption::decrypt(secret_key, &data)
+                .ok()
+                .and_then(|json| serde_json::from_slice(&json).ok());
+            if token.is_none() {
+                // Encrypted with a previous secret key; the user has to authorize again
+                warn!(
+                    "Ignoring unreadable OAuth token for user {} and client {}",
+                    user_id, client_id
+                );
+            }
+            token
+        });
+        Ok(token)
+    }
+
+    /// Check if token is expired, or about to
+    fn is_token_expired(&self, token: &OAuthToken) -> bool {
+        token.expires_at.is_some_and(|expires_at| {
+            expires_at - TOKEN_EXPIRY_LEEWAY_SECS <= chrono::Utc::now().timestamp()
+        })
     }
 
     /// Refresh access token using refresh token
@@ -155,10 +283,10 @@ impl OAuthClientManager {
         let mut params = vec![
             ("grant_type", "refresh_token"),
             ("refresh_token", refresh_token),
+            ("client_id", &client_info.client_id),
         ];
 
         if let Some(client_secret) = &client_info.client_secret {
-            params.push(("client_id", &client_info.client_id));
             params.push(("client_secret", client_secret));
         }
 
@@ -173,7 +301,11 @@ impl OAuthClientManager {
             )));
         }
 
-        let new_token: OAuthToken = response.json().await?;
+        let mut new_token: OAuthToken = response.json().await?;
+        // Providers that don't rotate refresh tokens leave them out
+        if new_token.refresh_token.is_none() {
+            new_token.refresh_token = Some(refresh_token.to_string());
+        }
 
         // Store the new token
         self.store_token(user_id, client_id, new_token.clone())
@@ -187,13 +319,14 @@ impl OAuthClientManager {
         Ok(new_token)
     }
 
-    /// Generate authorization URL
+    /// Generate authorization URL, with a PKCE challenge when given one
     pub async fn get_authorization_url(
         &self,
         client_id: &str,
         redirect_uri: Option<&str>,
         state: Option<&str>,
         scope: Option<&str>,
+        code_challenge: Option<&str>,
     ) -> AppResult<String> {
         let client_info = self
             .get_client(client_id)
@@ -224,9 +357,58 @@ impl OAuthClientManager {
             url.push_str(&format!("&state={}", urlencoding::encode(state)));
         }
 
+        if let Some(code_challenge) = code_challenge {
+            url.push_str(&format!(
+                "&code_challenge={}&code_challenge_method=S256",
+                urlencoding::encode(code_challenge)
+            ));
+        }
+
         Ok(url)
     }
 
+    /// Start the authorization code flow for a user, returning the provider URL to
+    /// send them to
+    ///
+    /// The PKCE verifier travels encrypted in `state`, which only the same user can
+    /// redeem at the callback, within [`AUTHORIZATION_TTL_SECS`].
+    pub async fn start_authorization(&self, client_id: &str, user_id: &str) -> AppResult<String> {
+        let secret_key = self.secret_key()?;
+        let code_verifier = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+        let state = AuthorizationState {
+            client_id: client_id.to_string(),
+            user_id: user_id.to_string(),
+            code_verifier,
+            expires_at: chrono::Utc::now().timestamp() + AUTHORIZATION_TTL_SECS,
+        };
+        let state = encryption::encrypt(secret_key, &serde_json::to_vec(&state).unwrap());
+
+        self.get_authorization_url(client_id, None, Some(&state), None, Some(&code_challenge))
+            .await
+    }
+
+    /// Finish an authorization started by [`Self::start_authorization`], storing the
+    /// user's tokens
+    pub async fn complete_authorization(
+        &self,
+        client_id: &str,
+        user_id: &str,
+        code: &str,
+        state: &str,
+    ) -> AppResult<OAuthToken> {
+        let secret_key = self.secret_key()?;
+        let now = chrono::Utc::now().timestamp();
+        let state = encryption::decrypt(secret_key, state)
+            .ok()
+            .and_then(|json| serde_json::from_slice::<AuthorizationState>(&json).ok())
+            .filter(|s| s.client_id == client_id && s.user_id == user_id && s.expires_at > now)
+            .ok_or_else(|| AppError::BadRequest("Invalid or expired OAuth state".to_string()))?;
+
+        self.exchange_code_for_token(user_id, client_id, code, None, Some(&state.code_verifier))
+            .await
+    }
+
     /// Exchange authorization code for access token
     pub async fn exchange_code_for_token(
         &self,
@@ -234,6 +416,7 @@ impl OAuthClientManager {
         client_id: &str,
         code: &str,
         redirect_uri: Option<&str>,
+        code_verifier: Option<&str>,
     ) -> AppResult<OAuthToken> {
         let client_info = self
             .get_client(client_id)
@@ -259,6 +442,10 @@ impl OAuthClientManager {
             params.push(("client_secret", client_secret.clone()));
         }
 
+        if let Some(code_verifier) = code_verifier {
+            params.push(("code_verifier", code_verifier.to_string()));
+        }
+
         let response = self.client.post(&token_url).form(&params).send().await?;
 
         if !response.status().is_success() {
@@ -285,6 +472,14 @@ impl OAuthClientManager {
 
     /// Revoke OAuth token
     pub async fn revoke_token(&self, user_id: &str, client_id: &str) -> AppResult<()> {
+        if let Some(db) = &self.db {
+            sqlx::query("DELETE FROM oauth_client_token WHERE user_id = $1 AND client_id = $2")
+                .bind(user_id)
+                .bind(client_id)
+                .execute(&db.pool)
+                .await?;
+        }
+
         let mut tokens = self.tokens.write().await;
 
         if let Some(user_tokens) = tokens.get_mut(user_id) {
@@ -303,9 +498,42 @@ impl OAuthClientManager {
     }
 }
 
+/// Page shown once an authorization is complete. A popup tells the window that
+/// opened it and closes; otherwise the page goes back to the app.
+pub fn authorization_complete_page(client_id: &str) -> String {
+    // Safe to embed in the script: `<` is escaped so it can't end the tag
+    let client_id = serde_json::to_string(client_id)
+        .unwrap()
+        .replace('<', "\\u003c");
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Connected</title></head>
+<body>
+<p>Connected. You can close this window.</p>
+<script>
+if (window.opener) {{
+    window.opener.postMessage(
+        {{ type: "oauth_client_callback", client_id: {}, status: "success" }},
+        window.location.origin
+    );
+    window.close();
+}} else {{
+    window.location.replace("/");
+}}
+</script>
+</body>
+</html>
+"#,
+        client_id
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde_json::json;
 
     #[tokio::test]
     async fn test_oauth_client_manager() {
@@ -341,6 +569,7 @@ mod tests {
             expires_in: Some(3600),
             refresh_token: Some("refresh-token".to_string()),
             scope: Some("openid profile".to_string()),
+            expires_at: None,
         };
 
         manager
@@ -355,4 +584,108 @@ mod tests {
         assert!(retrieved.is_some());
         assert_eq!(retrieved.unwrap().access_token, "test-token");
     }
+
+    /// Token endpoint of a provider whose access tokens expire within the refresh leeway
+    async fn token_endpoint(form: web::Form<HashMap<String, String>>) -> HttpResponse {
+        let field = |name: &str| form.get(name).map(String::as_str);
+        match field("grant_type") {
+            Some("authorization_code")
+                if field("code") == Some("good-code") && field("code_verifier").is_some() =>
+            {
+                HttpResponse::Ok().json(json!({
+                    "access_token": "access-1",
+                    "token_type": "Bearer",
+                    "expires_in": 30,
+                    "refresh_token": "refresh-1"
+                }))
+            }
+            Some("refresh_token") if field("refresh_token") == Some("refresh-1") => {
+                HttpResponse::Ok().json(json!({
+                    "access_token": "access-2",
+                    "token_type": "Bearer",
+                    "expires_in": 3600
+                }))
+            }
+            _ => HttpResponse::BadRequest().json(json!({"error": "invalid_grant"})),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_authorization_code_flow() {
+        let server = HttpServer::new(|| App::new().route("/token", web::post().to(token_endpoint)))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let token_url = format!("http://{}/token", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = crate::test_utils::test_db(&dir).await;
+
+        let mut config = Config::from_env().unwrap();
+        config.webui_url = "https://chat.example.com".to_string();
+        config.oauth_clients = HashMap::from([(
+            "mcp".to_string(),
+            OAuthClientInfo {
+                client_id: "webui".to_string(),
+                client_secret: None,
+                issuer: None,
+                scope: Some("tools".to_string()),
+                auth_url: Some("https://auth.example.com/authorize".to_string()),
+                token_url: Some(token_url),
+                redirect_uri: None,
+            },
+        )]);
+        let manager = OAuthClientManager::from_config(&db, &config);
+
+        let authorize_url = manager.start_authorization("mcp", "user-1").await.unwrap();
+        let authorize_url = url::Url::parse(&authorize_url).unwrap();
+        let query: HashMap<_, _> = authorize_url.query_pairs().into_owned().collect();
+        assert_eq!(
+            query["redirect_uri"],
+            "https://chat.example.com/oauth/clients/mcp/callback"
+        );
+        assert_eq!(query["code_challenge_method"], "S256");
+        let state = &query["state"];
+
+        assert!(matches!(
+            manager.start_authorization("unknown", "user-1").await,
+            Err(AppError::NotFound(_))
+        ));
+
+        // The state only works for the user who started the authorization
+        assert!(manager
+            .complete_authorization("mcp", "user-2", "good-code", state)
+            .await
+            .is_err());
+        let token = manager
+            .complete_authorization("mcp", "user-1", "good-code", state)
+            .await
+            .unwrap();
+        assert_eq!(token.access_token, "access-1");
+
+        let stored: String = sqlx::query_scalar("SELECT data FROM oauth_client_token")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert!(!stored.contains("access-1"));
+
+        // Another manager reads the stored token, which is refreshed as it's about to expire
+        let manager = OAuthClientManager::from_config(&db, &config);
+        let token = manager
+            .get_oauth_token("user-1", "mcp", false)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.access_token, "access-2");
+        assert_eq!(token.refresh_token.as_deref(), Some("refresh-1"));
+
+        manager.revoke_token("user-1", "mcp").await.unwrap();
+        let manager = OAuthClientManager::from_config(&db, &config);
+        assert!(manager
+            .get_oauth_token("user-1", "mcp", false)
+            .await
+            .unwrap()
+            .is_none());
+    }
 }
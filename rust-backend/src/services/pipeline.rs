@@ -79,8 +79,8 @@ pub async fn process_pipeline_inlet_filter(
         }
     }
 
-    let config = state.config.read().unwrap();
-    let client = reqwest::Client::new();
+    let config = state.config.snapshot();
+    let client = &state.http_client;
 
     // Create user object
     let user_obj = serde_json::json!({
@@ -209,8 +209,8 @@ pub async fn process_pipeline_outlet_filter(
         }
     }
 
-    let config = state.config.read().unwrap();
-    let client = reqwest::Client::new();
+    let config = state.config.snapshot();
+    let client = &state.http_client;
 
     // Create user object
     let user_obj = serde_json::json!({
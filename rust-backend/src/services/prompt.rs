@@ -46,7 +46,7 @@ impl<'a> PromptService<'a> {
     }
 
     pub async fn get_prompt_by_command(&self, command: &str) -> AppResult<Option<Prompt>> {
-        let result = sqlx::query_as::<_, Prompt>(
+        let mut result = sqlx::query_as::<_, Prompt>(
             r#"
             SELECT command, user_id, title, content, updated_at as timestamp,
                    CAST(access_control AS TEXT) as access_control_str
@@ -58,11 +58,15 @@ impl<'a> PromptService<'a> {
         .fetch_optional(&self.db.pool)
         .await?;
 
+        if let Some(ref mut prompt) = result {
+            prompt.parse_access_control();
+        }
+
         Ok(result)
     }
 
     pub async fn get_all_prompts(&self) -> AppResult<Vec<Prompt>> {
-        let prompts = sqlx::query_as::<_, Prompt>(
+        let mut prompts = sqlx::query_as::<_, Prompt>(
             r#"
             SELECT command, user_id, title, content, updated_at as timestamp,
                    CAST(access_control AS TEXT) as access_control_str
@@ -73,6 +77,10 @@ impl<'a> PromptService<'a> {
         .fetch_all(&self.db.pool)
         .await?;
 
+        for prompt in &mut prompts {
+            prompt.parse_access_control();
+        }
+
         Ok(prompts)
     }
 
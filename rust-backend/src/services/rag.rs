@@ -1,5 +1,6 @@
 use crate::config::Config;
 use crate::error::{AppError, AppResult};
+use crate::utils::http_client;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
@@ -34,7 +35,7 @@ pub struct RAGService {
 impl RAGService {
     pub async fn new(config: Config) -> AppResult<Self> {
         Ok(RAGService {
-            client: Client::new(),
+            client: http_client::client(&config),
             config,
         })
     }
@@ -43,6 +44,7 @@ impl RAGService {
     pub async fn generate_embedding(&mut self, text: &str) -> AppResult<Vec<f32>> {
         // Use the centralized embedding generation from utils
         let embeddings = crate::utils::embeddings::generate_embeddings(
+            &self.client,
             &self.config.rag_embedding_engine,
             &self.config.rag_embedding_model,
             None, // base_url - would need to be added to config if using API
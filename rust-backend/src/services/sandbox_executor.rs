@@ -1,8 +1,15 @@
 /// Integration client for Sandbox Executor service
 /// This replaces the Jupyter code execution with secure sandbox execution
-use reqwest::Client;
+use futures::StreamExt;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::{mpsc, OnceCell};
+
+use crate::middleware::request_id::{current_request_id, WithRequestId};
+use crate::utils::metrics::metrics;
+use crate::utils::telemetry;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SandboxExecuteRequest {
@@ -14,6 +21,9 @@ pub struct SandboxExecuteRequest {
     pub user_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub request_id: Option<String>,
+    /// Python packages to install first, which the sandbox must allow
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub packages: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,12 +37,50 @@ pub struct SandboxExecuteResponse {
     pub memory_used_mb: Option<f64>,
     pub exit_code: Option<i32>,
     pub error: Option<String>,
+    /// Files the code wrote, such as plots and CSVs
+    #[serde(default)]
+    pub artifacts: Vec<SandboxArtifact>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxArtifact {
+    /// Path relative to the sandbox workspace
+    pub name: String,
+    pub mime_type: String,
+    pub size: u64,
+    /// Base64 encoded content
+    pub content: String,
+}
+
+/// Session the sandbox opened, keeping state between executions
+#[derive(Debug, Clone, Deserialize)]
+pub struct SandboxSession {
+    pub session_id: String,
+}
+
+/// Line of the NDJSON response of a streamed execution
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+enum SandboxExecutionEvent {
+    Stdout(String),
+    Result(SandboxExecuteResponse),
+    Error { message: String },
+}
+
+/// Take the next complete line out of `buffer`
+fn take_line(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let end = buffer.iter().position(|b| *b == b'\n')?;
+    let mut line: Vec<u8> = buffer.drain(..=end).collect();
+    line.pop();
+    Some(line)
 }
 
 #[derive(Debug, Clone)]
 pub struct SandboxExecutorClient {
     client: Client,
     base_url: String,
+    /// Languages the sandbox advertises, once fetched
+    supported_languages: Arc<OnceCell<Vec<String>>>,
 }
 
 impl SandboxExecutorClient {
@@ -42,7 +90,11 @@ impl SandboxExecutorClient {
             .build()
             .expect("Failed to create HTTP client");
 
-        Self { client, base_url }
+        Self {
+            client,
+            base_url,
+            supported_languages: Arc::new(OnceCell::new()),
+        }
     }
 
     /// Get the current base URL
@@ -58,21 +110,156 @@ impl SandboxExecutorClient {
         user_id: Option<String>,
         request_id: Option<String>,
     ) -> Result<SandboxExecuteResponse, String> {
-        let url = format!("{}/api/v1/execute", self.base_url);
-
         let request = SandboxExecuteRequest {
             code,
             language,
             timeout,
             user_id,
-            request_id,
+            request_id: request_id.or_else(current_request_id),
+            packages: Vec::new(),
         };
 
+        let result = self.post_execute(&request).await;
+        record_execution(&result);
+        result
+    }
+
+    /// Execute code, sending its stdout to `output` as it's written
+    ///
+    /// With a `session_key`, the code runs in the user's sandbox session for the key, so
+    /// it sees the files and variables earlier code left there. Sandboxes without
+    /// sessions run it in a fresh container, and sandboxes without the streaming
+    /// endpoint run it as a regular execution, with no output sent.
+    pub async fn execute_code_streaming(
+        &self,
+        mut request: SandboxExecuteRequest,
+        session_key: Option<&str>,
+        output: mpsc::UnboundedSender<String>,
+    ) -> Result<SandboxExecuteResponse, String> {
+        if request.request_id.is_none() {
+            request.request_id = current_request_id();
+        }
+
+        let mut path = "/api/v1/execute/stream".to_string();
+        if let Some(session_key) = session_key {
+            match self
+                .open_session(&request.language, session_key, request.user_id.as_deref())
+                .await
+            {
+                Ok(session) => {
+                    path = format!("/api/v1/sessions/{}/execute/stream", session.session_id)
+                }
+                Err(e) => tracing::debug!("Executing code outside a session: {}", e),
+            }
+        }
+
+        let result = self.post_execute_stream(&path, &request, &output).await;
+        record_execution(&result);
+        result
+    }
+
+    /// Open the user's session for `session_key`, or get it if it's already open
+    async fn open_session(
+        &self,
+        language: &str,
+        session_key: &str,
+        user_id: Option<&str>,
+    ) -> Result<SandboxSession, String> {
+        let url = format!("{}/api/v1/sessions", self.base_url);
+
         let response = self
             .client
             .post(&url)
-            .json(&request)
+            .with_request_id()
+            .json(&serde_json::json!({
+                "language": language,
+                "session_key": session_key,
+                "user_id": user_id,
+            }))
             .send()
+            .await
+            .map_err(|e| format!("Failed to open sandbox session: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!(
+                "Failed to open sandbox session: {}",
+                response.status()
+            ));
+        }
+
+        response
+            .json::<SandboxSession>()
+            .await
+            .map_err(|e| format!("Failed to parse session response: {}", e))
+    }
+
+    /// Post a streamed execution to `path`. When the sandbox doesn't know it (an older
+    /// sandbox, or a session that has closed), the code runs as a regular execution.
+    async fn post_execute_stream(
+        &self,
+        path: &str,
+        request: &SandboxExecuteRequest,
+        output: &mpsc::UnboundedSender<String>,
+    ) -> Result<SandboxExecuteResponse, String> {
+        let url = format!("{}{}", self.base_url, path);
+
+        let span = telemetry::upstream_span("sandbox_execute", &url, None);
+        let response = telemetry::send_traced(self.client.post(&url).json(request), span)
+            .await
+            .map_err(|e| format!("Failed to send request to sandbox executor: {}", e))?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return self.post_execute(request).await;
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(format!(
+                "Sandbox executor returned error {}: {}",
+                status, error_text
+            ));
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut buffer = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk =
+                chunk.map_err(|e| format!("Failed to read sandbox executor response: {}", e))?;
+            buffer.extend_from_slice(&chunk);
+
+            while let Some(line) = take_line(&mut buffer) {
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let event = serde_json::from_slice::<SandboxExecutionEvent>(&line)
+                    .map_err(|e| format!("Failed to parse sandbox executor response: {}", e))?;
+                match event {
+                    SandboxExecutionEvent::Stdout(text) => {
+                        // The caller may have stopped listening
+                        let _ = output.send(text);
+                    }
+                    SandboxExecutionEvent::Result(result) => return Ok(result),
+                    SandboxExecutionEvent::Error { message } => {
+                        return Err(format!("Sandbox executor returned error: {}", message));
+                    }
+                }
+            }
+        }
+
+        Err("Sandbox executor response ended without a result".to_string())
+    }
+
+    async fn post_execute(
+        &self,
+        request: &SandboxExecuteRequest,
+    ) -> Result<SandboxExecuteResponse, String> {
+        let url = format!("{}/api/v1/execute", self.base_url);
+
+        let span = telemetry::upstream_span("sandbox_execute", &url, None);
+        let response = telemetry::send_traced(self.client.post(&url).json(request), span)
             .await
             .map_err(|e| format!("Failed to send request to sandbox executor: {}", e))?;
 
@@ -102,6 +289,7 @@ impl SandboxExecutorClient {
         let response = self
             .client
             .get(&url)
+            .with_request_id()
             .send()
             .await
             .map_err(|e| format!("Failed to connect to sandbox executor: {}", e))?;
@@ -121,12 +309,40 @@ impl SandboxExecutorClient {
         Ok(health)
     }
 
+    /// Whether the sandbox runs code in `language`, as named by
+    /// `CodeBlockDetector::normalize_language`
+    ///
+    /// When the sandbox's config can't be fetched, the language is assumed to be
+    /// supported and the execution reports the error.
+    pub async fn supports_language(&self, language: &str) -> bool {
+        let supported = self
+            .supported_languages
+            .get_or_try_init(|| async {
+                let config = self.get_config().await?;
+                serde_json::from_value::<Vec<String>>(config["supported_languages"].clone())
+                    .map_err(|e| format!("Invalid supported languages: {}", e))
+            })
+            .await;
+
+        match supported {
+            // Older sandboxes call bash "shell"
+            Ok(supported) => supported
+                .iter()
+                .any(|name| name == language || (name == "shell" && language == "bash")),
+            Err(e) => {
+                tracing::warn!("Failed to get the sandbox's languages: {}", e);
+                true
+            }
+        }
+    }
+
     pub async fn get_config(&self) -> Result<serde_json::Value, String> {
         let url = format!("{}/api/v1/config", self.base_url);
 
         let response = self
             .client
             .get(&url)
+            .with_request_id()
             .send()
             .await
             .map_err(|e| format!("Failed to get sandbox executor config: {}", e))?;
@@ -149,6 +365,7 @@ impl SandboxExecutorClient {
         let response = self
             .client
             .get(&url)
+            .with_request_id()
             .send()
             .await
             .map_err(|e| format!("Failed to get sandbox executor stats: {}", e))?;
@@ -166,6 +383,14 @@ impl SandboxExecutorClient {
     }
 }
 
+fn record_execution(result: &Result<SandboxExecuteResponse, String>) {
+    let success = match result {
+        Ok(response) => response.error.is_none() && response.exit_code.unwrap_or(0) == 0,
+        Err(_) => false,
+    };
+    metrics().tool_execution("code", success);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -175,4 +400,40 @@ mod tests {
         let client = SandboxExecutorClient::new("http://localhost:8090".to_string());
         assert_eq!(client.base_url, "http://localhost:8090");
     }
+
+    #[test]
+    fn test_stream_lines_are_split_across_chunks() {
+        let result = serde_json::json!({"type": "result", "data": {
+            "execution_id": "e1", "status": "success", "stdout": "a", "stderr": "",
+            "result": null, "execution_time_ms": 5, "memory_used_mb": null,
+            "exit_code": 0, "error": null, "created_at": "2024-01-01T00:00:00Z",
+        }})
+        .to_string();
+        let (head, tail) = result.split_at(20);
+
+        let mut buffer = br#"{"type":"stdout","data":"a\n"}"#.to_vec();
+        buffer.push(b'\n');
+        buffer.extend_from_slice(head.as_bytes());
+        let line = take_line(&mut buffer).unwrap();
+        match serde_json::from_slice::<SandboxExecutionEvent>(&line).unwrap() {
+            SandboxExecutionEvent::Stdout(text) => assert_eq!(text, "a\n"),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(take_line(&mut buffer).is_none());
+
+        buffer.extend_from_slice(tail.as_bytes());
+        buffer.push(b'\n');
+        let line = take_line(&mut buffer).unwrap();
+        match serde_json::from_slice::<SandboxExecutionEvent>(&line).unwrap() {
+            SandboxExecutionEvent::Result(result) => assert_eq!(result.execution_id, "e1"),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(buffer.is_empty());
+
+        let event: SandboxExecutionEvent = serde_json::from_str(
+            r#"{"type":"error","data":{"error":"InvalidInput","message":"bad"}}"#,
+        )
+        .unwrap();
+        assert!(matches!(event, SandboxExecutionEvent::Error { message } if message == "bad"));
+    }
 }
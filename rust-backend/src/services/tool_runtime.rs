@@ -2,6 +2,7 @@ use crate::db::Database;
 use crate::error::{AppError, AppResult};
 use crate::models::tool_runtime::*;
 use crate::services::tool::ToolService;
+use crate::utils::metrics::metrics;
 use crate::utils::template::TemplateEngine;
 use evalexpr::{
     eval_with_context, ContextWithMutableVariables, DefaultNumericTypes, HashMapContext,
@@ -17,7 +18,10 @@ use std::num::NonZeroU32;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
-use tokio::time::sleep;
+use tokio::time::{sleep, timeout};
+
+/// How long a tool may run when neither the config nor its definition say otherwise
+const DEFAULT_EXECUTION_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// Cache entry with expiration
 #[derive(Debug, Clone)]
@@ -33,21 +37,47 @@ pub struct ToolRuntimeService {
     cache: Arc<RwLock<HashMap<String, CacheEntry>>>,
     rate_limiters:
         Arc<RwLock<HashMap<String, Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>>>>,
+    execution_timeout: Duration,
+    /// Values for the environment variables tools declare
+    secrets: HashMap<String, String>,
+    /// Names of the secrets each tool may receive, by tool id
+    secret_grants: HashMap<String, Vec<String>>,
 }
 
 impl ToolRuntimeService {
     pub fn new() -> Self {
         ToolRuntimeService {
-            http_client: Client::builder()
-                .timeout(std::time::Duration::from_secs(30))
-                .build()
-                .unwrap_or_else(|_| Client::new()),
+            // Requests are bounded by the execution timeout instead of a client timeout,
+            // so tools can be given longer than the default
+            http_client: Client::new(),
             template_engine: TemplateEngine::new(),
             cache: Arc::new(RwLock::new(HashMap::new())),
             rate_limiters: Arc::new(RwLock::new(HashMap::new())),
+            execution_timeout: DEFAULT_EXECUTION_TIMEOUT,
+            secrets: HashMap::new(),
+            secret_grants: HashMap::new(),
         }
     }
 
+    /// Abort tool calls running longer than `timeout`, unless the tool's definition
+    /// sets its own
+    pub fn with_execution_timeout(mut self, timeout: Duration) -> Self {
+        self.execution_timeout = timeout;
+        self
+    }
+
+    /// Secrets tools are given when they declare them in their environment and the
+    /// tool's id is granted them
+    pub fn with_secrets(
+        mut self,
+        secrets: HashMap<String, String>,
+        secret_grants: HashMap<String, Vec<String>>,
+    ) -> Self {
+        self.secrets = secrets;
+        self.secret_grants = secret_grants;
+        self
+    }
+
     /// Check and enforce rate limit for a tool
     async fn check_rate_limit(
         &self,
@@ -107,7 +137,7 @@ impl ToolRuntimeService {
     pub async fn execute_tool(
         &self,
         db: &Database,
-        request: ToolExecutionRequest,
+        mut request: ToolExecutionRequest,
     ) -> AppResult<ToolExecutionResponse> {
         let start_time = Instant::now();
 
@@ -155,16 +185,51 @@ impl ToolRuntimeService {
         // Validate required parameters
         self.validate_parameters(tool_spec, &request.parameters)?;
 
-        // Validate required environment variables
-        self.validate_environment(&tool_def.environment, &request.context.environment)?;
+        // Only the variables the tool declares, failing if a required one is missing
+        request.context.environment = self.resolve_environment(
+            &request.tool_id,
+            &tool_def.environment,
+            &request.context.environment,
+        )?;
 
         // Execute with error handling strategy
         let result = self
-            .execute_with_error_handling(tool_spec, &tool_def, &request)
+            .execute_with_deadline(tool_spec, &tool_def, &request)
             .await;
 
         let execution_time = start_time.elapsed().as_millis() as u64;
 
+        // A result that doesn't match the tool's output schema is a failed run
+        let violations = match &result {
+            Ok((value, _)) => check_output(tool_spec, value).err(),
+            Err(_) => None,
+        };
+        metrics().tool_execution("tool", result.is_ok() && violations.is_none());
+        if let Some(violations) = violations {
+            let message = format!(
+                "Tool '{}' returned a result that doesn't match its output schema: {}",
+                tool_spec.name,
+                violations.join("; ")
+            );
+            tracing::warn!("{}", message);
+            return Ok(ToolExecutionResponse {
+                success: false,
+                result: Some(serde_json::json!({
+                    "error": {
+                        "type": "invalid_tool_output",
+                        "message": message,
+                        "violations": violations,
+                    }
+                })),
+                error: Some(message),
+                metadata: Some(ExecutionMetadata {
+                    execution_time_ms: execution_time,
+                    tool_type: format!("{:?}", tool_spec.tool_type),
+                    http_status: None,
+                }),
+            });
+        }
+
         match result {
             Ok((value, metadata)) => {
                 // Cache result if enabled
@@ -202,6 +267,38 @@ impl ToolRuntimeService {
         }
     }
 
+    /// Execute tool with error handling strategy, failing once its timeout expires
+    ///
+    /// On expiry the run is dropped, which aborts its in-flight requests.
+    async fn execute_with_deadline(
+        &self,
+        tool_spec: &ToolSpec,
+        tool_def: &ToolDefinition,
+        request: &ToolExecutionRequest,
+    ) -> Result<(Value, Option<HashMap<String, Value>>), AppError> {
+        let deadline = tool_spec
+            .timeout_seconds
+            .map(Duration::from_secs)
+            .unwrap_or(self.execution_timeout);
+
+        match timeout(
+            deadline,
+            self.execute_with_error_handling(tool_spec, tool_def, request),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => {
+                tracing::warn!("Tool {} timed out after {:?}", tool_spec.name, deadline);
+                Err(AppError::Timeout(format!(
+                    "Tool '{}' timed out after {}s",
+                    tool_spec.name,
+                    deadline.as_secs()
+                )))
+            }
+        }
+    }
+
     /// Execute tool with error handling strategy
     async fn execute_with_error_handling(
         &self,
@@ -347,21 +444,45 @@ impl ToolRuntimeService {
         Ok(())
     }
 
-    /// Validate environment variables
-    fn validate_environment(
+    /// Environment a tool runs with: the variables it declares, each taken from the
+    /// caller's values or else the secrets granted to the tool. Undeclared variables
+    /// are dropped.
+    fn resolve_environment(
         &self,
+        tool_id: &str,
         env_config: &EnvironmentConfig,
-        environment: &HashMap<String, String>,
-    ) -> AppResult<()> {
+        provided: &HashMap<String, String>,
+    ) -> AppResult<HashMap<String, String>> {
+        let granted = |env_var: &String| {
+            self.secret_grants
+                .get(tool_id)
+                .is_some_and(|names| names.contains(env_var))
+                .then(|| self.secrets.get(env_var))
+                .flatten()
+        };
+
+        let mut environment = HashMap::new();
         for env_var in &env_config.required {
-            if !environment.contains_key(env_var) {
-                return Err(AppError::BadRequest(format!(
-                    "Missing required environment variable: {}",
-                    env_var
-                )));
+            match provided.get(env_var).or_else(|| granted(env_var)) {
+                Some(value) => {
+                    environment.insert(env_var.clone(), value.clone());
+                }
+                None => {
+                    return Err(AppError::BadRequest(format!(
+                        "Tool requires the secret {}, which is not configured for it. An \
+                         admin can add it to TOOL_SECRETS and grant it to {} in \
+                         TOOL_SECRET_GRANTS.",
+                        env_var, tool_id
+                    )))
+                }
             }
         }
-        Ok(())
+        for env_var in &env_config.optional {
+            if let Some(value) = provided.get(env_var).or_else(|| granted(env_var)) {
+                environment.insert(env_var.clone(), value.clone());
+            }
+        }
+        Ok(environment)
     }
 
     /// Execute HTTP API tool
@@ -672,7 +793,7 @@ impl ToolRuntimeService {
         tool_id: &str,
         chain_name: &str,
         initial_parameters: HashMap<String, Value>,
-        context: ExecutionContext,
+        mut context: ExecutionContext,
     ) -> AppResult<ToolExecutionResponse> {
         let start_time = Instant::now();
 
@@ -687,6 +808,9 @@ impl ToolRuntimeService {
         let tool_def = ToolDefinition::from_json(&tool.content)
             .map_err(|e| AppError::BadRequest(format!("Invalid tool definition: {}", e)))?;
 
+        context.environment =
+            self.resolve_environment(tool_id, &tool_def.environment, &context.environment)?;
+
         // Find the tool chain
         let chain = tool_def
             .find_chain(chain_name)
@@ -724,27 +848,31 @@ impl ToolRuntimeService {
                 context: context.clone(),
             };
 
+            let tool_spec = tool_def
+                .find_tool(&step.tool_name)
+                .ok_or_else(|| AppError::NotFound(format!("Tool not found: {}", step.tool_name)))?;
             let result = if let Some(error_strategy) = &step.error_handling {
                 // Execute with custom error handling for this step
-                let tool_spec = tool_def.find_tool(&step.tool_name).ok_or_else(|| {
-                    AppError::NotFound(format!("Tool not found: {}", step.tool_name))
-                })?;
-
                 // Temporarily override error handling
                 let mut modified_spec = tool_spec.clone();
                 modified_spec.error_handling = Some(error_strategy.clone());
 
-                self.execute_with_error_handling(&modified_spec, &tool_def, &request)
+                self.execute_with_deadline(&modified_spec, &tool_def, &request)
                     .await
             } else {
                 // Use default error handling
-                let tool_spec = tool_def.find_tool(&step.tool_name).ok_or_else(|| {
-                    AppError::NotFound(format!("Tool not found: {}", step.tool_name))
-                })?;
-
-                self.execute_with_error_handling(tool_spec, &tool_def, &request)
+                self.execute_with_deadline(tool_spec, &tool_def, &request)
                     .await
             };
+            // A step whose result doesn't match its output schema fails the chain
+            let result =
+                result.and_then(|(value, metadata)| match check_output(tool_spec, &value) {
+                    Ok(()) => Ok((value, metadata)),
+                    Err(violations) => Err(AppError::ExternalServiceError(format!(
+                        "result doesn't match the output schema: {}",
+                        violations.join("; ")
+                    ))),
+                });
 
             match result {
                 Ok((value, _)) => {
@@ -845,3 +973,268 @@ impl Default for ToolRuntimeService {
         Self::new()
     }
 }
+
+/// Check a tool's result against its output schema, returning the violations
+fn check_output(tool_spec: &ToolSpec, value: &Value) -> Result<(), Vec<String>> {
+    let schema = match &tool_spec.output_schema {
+        Some(schema) => schema,
+        None => return Ok(()),
+    };
+    let validator = jsonschema::validator_for(schema)
+        .map_err(|e| vec![format!("the output schema is invalid: {}", e)])?;
+    let violations: Vec<String> = validator
+        .iter_errors(value)
+        .map(|e| match e.instance_path.to_string() {
+            path if path.is_empty() => e.to_string(),
+            path => format!("{} at {}", e, path),
+        })
+        .collect();
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use serde_json::json;
+
+    /// A server that accepts connections and never answers
+    async fn hanging_server() -> std::net::SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                connections.push(socket);
+            }
+        });
+        addr
+    }
+
+    async fn setup_db(dir: &tempfile::TempDir, addr: std::net::SocketAddr) -> Database {
+        let db = test_utils::test_db(dir).await;
+        test_utils::create_user(&db, "alice", "admin").await;
+        let handler = json!({"type": "http", "method": "GET", "url": format!("http://{}", addr)});
+        let definition = json!({
+            "name": "Slow tools",
+            "tools": [
+                {
+                    "name": "slow_lookup",
+                    "description": "Never answers",
+                    "type": "http_api",
+                    "handler": handler,
+                    "timeout_seconds": 1
+                },
+                {
+                    "name": "slow_search",
+                    "description": "Never answers either",
+                    "type": "http_api",
+                    "handler": handler
+                }
+            ]
+        });
+        ToolService::new(&db)
+            .create_tool(
+                "slow",
+                "alice",
+                "Slow tools",
+                &definition.to_string(),
+                json!([]),
+                json!({}),
+                None,
+            )
+            .await
+            .unwrap();
+
+        db
+    }
+
+    fn request(tool_name: &str) -> ToolExecutionRequest {
+        ToolExecutionRequest {
+            tool_id: "slow".to_string(),
+            tool_name: tool_name.to_string(),
+            parameters: HashMap::new(),
+            context: ExecutionContext::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_tool_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = setup_db(&dir, hanging_server().await).await;
+
+        // The tool's own timeout wins over the service's
+        let service = ToolRuntimeService::new().with_execution_timeout(Duration::from_secs(60));
+        let started = Instant::now();
+        let response = service
+            .execute_tool(&db, request("slow_lookup"))
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!response.success);
+        assert!(response
+            .error
+            .unwrap()
+            .contains("Tool 'slow_lookup' timed out after 1s"));
+
+        // Tools without one use the service's
+        let service = ToolRuntimeService::new().with_execution_timeout(Duration::from_millis(200));
+        let started = Instant::now();
+        let response = service
+            .execute_tool(&db, request("slow_search"))
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(!response.success);
+        assert!(response.error.unwrap().contains("timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_results_are_checked_against_the_output_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = setup_db(&dir, hanging_server().await).await;
+        let schema = json!({
+            "type": "object",
+            "required": ["total"],
+            "properties": {"total": {"type": "integer"}}
+        });
+        let definition = json!({
+            "name": "Math",
+            "tools": [
+                {
+                    "name": "add",
+                    "description": "Adds two numbers",
+                    "type": "expression",
+                    "handler": {"type": "expression", "engine": "evalexpr", "expression": "1 + 1"},
+                    "output_schema": {"type": "integer"}
+                },
+                {
+                    "name": "total",
+                    "description": "Returns the total as text",
+                    "type": "expression",
+                    "handler": {
+                        "type": "expression",
+                        "engine": "evalexpr",
+                        "expression": "\"two\""
+                    },
+                    "output_schema": schema
+                }
+            ]
+        });
+        ToolService::new(&db)
+            .create_tool(
+                "math",
+                "alice",
+                "Math",
+                &definition.to_string(),
+                json!([]),
+                json!({}),
+                None,
+            )
+            .await
+            .unwrap();
+        let service = ToolRuntimeService::new();
+
+        let mut conforming = request("add");
+        conforming.tool_id = "math".to_string();
+        let response = service.execute_tool(&db, conforming).await.unwrap();
+        assert!(response.success);
+        assert_eq!(response.result, Some(json!(2)));
+
+        let mut non_conforming = request("total");
+        non_conforming.tool_id = "math".to_string();
+        let response = service.execute_tool(&db, non_conforming).await.unwrap();
+        assert!(!response.success);
+        assert!(response
+            .error
+            .unwrap()
+            .contains("doesn't match its output schema"));
+        let error = &response.result.unwrap()["error"];
+        assert_eq!(error["type"], "invalid_tool_output");
+        assert_eq!(
+            error["violations"],
+            json!(["\"two\" is not of type \"object\""])
+        );
+    }
+
+    #[test]
+    fn test_tools_only_get_the_secrets_they_declare() {
+        let secrets = HashMap::from([
+            ("WEATHER_KEY".to_string(), "weather-secret".to_string()),
+            ("SEARCH_KEY".to_string(), "search-secret".to_string()),
+            ("OPENAI_API_KEY".to_string(), "openai-secret".to_string()),
+        ]);
+        let grants = HashMap::from([(
+            "weather".to_string(),
+            vec!["WEATHER_KEY".to_string(), "SEARCH_KEY".to_string()],
+        )]);
+        let service = ToolRuntimeService::new().with_secrets(secrets, grants);
+        let env_config = EnvironmentConfig {
+            required: vec!["WEATHER_KEY".to_string()],
+            optional: vec!["SEARCH_KEY".to_string(), "UNITS".to_string()],
+        };
+        let provided = HashMap::from([
+            ("SEARCH_KEY".to_string(), "my-key".to_string()),
+            ("UNDECLARED".to_string(), "dropped".to_string()),
+        ]);
+
+        let environment = service
+            .resolve_environment("weather", &env_config, &provided)
+            .unwrap();
+        assert_eq!(
+            environment,
+            HashMap::from([
+                ("WEATHER_KEY".to_string(), "weather-secret".to_string()),
+                ("SEARCH_KEY".to_string(), "my-key".to_string()),
+            ])
+        );
+
+        // A required secret that isn't configured stops the run
+        let error = ToolRuntimeService::new()
+            .resolve_environment("weather", &env_config, &HashMap::new())
+            .unwrap_err();
+        assert!(error.to_string().contains("WEATHER_KEY"));
+    }
+
+    #[test]
+    fn test_tools_without_a_grant_get_no_secrets() {
+        let secrets = HashMap::from([
+            ("WEATHER_KEY".to_string(), "weather-secret".to_string()),
+            ("OPENAI_API_KEY".to_string(), "openai-secret".to_string()),
+        ]);
+        let grants = HashMap::from([("weather".to_string(), vec!["WEATHER_KEY".to_string()])]);
+        let service = ToolRuntimeService::new().with_secrets(secrets, grants);
+
+        // A user's tool declaring someone else's secret gets nothing
+        let env_config = EnvironmentConfig {
+            required: vec![],
+            optional: vec!["OPENAI_API_KEY".to_string(), "WEATHER_KEY".to_string()],
+        };
+        let environment = service
+            .resolve_environment("user-tool", &env_config, &HashMap::new())
+            .unwrap();
+        assert!(environment.is_empty());
+
+        // Nor can a granted tool reach a secret granted to no one
+        let environment = service
+            .resolve_environment("weather", &env_config, &HashMap::new())
+            .unwrap();
+        assert_eq!(
+            environment,
+            HashMap::from([("WEATHER_KEY".to_string(), "weather-secret".to_string())])
+        );
+
+        let env_config = EnvironmentConfig {
+            required: vec!["OPENAI_API_KEY".to_string()],
+            optional: vec![],
+        };
+        let error = service
+            .resolve_environment("user-tool", &env_config, &HashMap::new())
+            .unwrap_err();
+        assert!(error.to_string().contains("TOOL_SECRET_GRANTS"));
+    }
+}
@@ -0,0 +1,297 @@
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::models::usage::TokenUsage;
+use crate::utils::time::current_timestamp_seconds;
+
+/// Window over which token budgets are counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetPeriod {
+    Day,
+    Month,
+}
+
+impl BudgetPeriod {
+    /// Parse `TOKEN_BUDGET_PERIOD`, defaulting to monthly budgets
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "day" | "daily" => BudgetPeriod::Day,
+            _ => BudgetPeriod::Month,
+        }
+    }
+
+    /// Key of the period containing `at` (`YYYY-MM-DD` or `YYYY-MM`)
+    pub fn key(&self, at: DateTime<Utc>) -> String {
+        match self {
+            BudgetPeriod::Day => at.format("%Y-%m-%d").to_string(),
+            BudgetPeriod::Month => at.format("%Y-%m").to_string(),
+        }
+    }
+
+    /// Unix timestamp at which the period containing `at` ends
+    pub fn resets_at(&self, at: DateTime<Utc>) -> i64 {
+        let start_of_day = Utc
+            .with_ymd_and_hms(at.year(), at.month(), at.day(), 0, 0, 0)
+            .single()
+            .unwrap_or(at);
+        match self {
+            BudgetPeriod::Day => (start_of_day + Duration::days(1)).timestamp(),
+            BudgetPeriod::Month => {
+                let (year, month) = if at.month() == 12 {
+                    (at.year() + 1, 1)
+                } else {
+                    (at.year(), at.month() + 1)
+                };
+                Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0)
+                    .single()
+                    .map(|dt| dt.timestamp())
+                    .unwrap_or_else(|| (start_of_day + Duration::days(31)).timestamp())
+            }
+        }
+    }
+}
+
+/// Configured token limits; a limit of 0 means unlimited
+#[derive(Debug, Clone)]
+pub struct TokenBudget {
+    pub period: BudgetPeriod,
+    pub user_limit: i64,
+    pub model_limits: HashMap<String, i64>,
+}
+
+impl TokenBudget {
+    pub fn from_config(config: &Config) -> Self {
+        let model_limits = config
+            .model_token_limits
+            .as_object()
+            .map(|limits| {
+                limits
+                    .iter()
+                    .filter_map(|(model_id, limit)| {
+                        limit.as_i64().map(|limit| (model_id.clone(), limit))
+                    })
+                    .filter(|(_, limit)| *limit > 0)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        TokenBudget {
+            period: BudgetPeriod::parse(&config.token_budget_period),
+            user_limit: config.user_token_limit.max(0),
+            model_limits,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.user_limit > 0 || !self.model_limits.is_empty()
+    }
+
+    pub fn model_limit(&self, model_id: &str) -> Option<i64> {
+        self.model_limits.get(model_id).copied()
+    }
+}
+
+/// Remaining allowance for one limit
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetStatus {
+    pub limit: i64,
+    pub used: i64,
+    pub remaining: i64,
+}
+
+impl BudgetStatus {
+    pub fn new(limit: i64, used: i64) -> Self {
+        BudgetStatus {
+            limit,
+            used,
+            remaining: (limit - used).max(0),
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.used >= self.limit
+    }
+}
+
+pub struct UsageService<'a> {
+    db: &'a Database,
+}
+
+impl<'a> UsageService<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        UsageService { db }
+    }
+
+    /// Add tokens to both the daily and monthly counters so the period can be switched
+    /// without losing history
+    pub async fn record_usage(
+        &self,
+        user_id: &str,
+        model_id: &str,
+        prompt_tokens: i64,
+        completion_tokens: i64,
+    ) -> AppResult<()> {
+        if prompt_tokens <= 0 && completion_tokens <= 0 {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        for period in [BudgetPeriod::Day, BudgetPeriod::Month] {
+            sqlx::query(
+                r#"
+                INSERT INTO token_usage (user_id, model_id, period, prompt_tokens, completion_tokens, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (user_id, model_id, period) DO UPDATE SET
+                    prompt_tokens = token_usage.prompt_tokens + excluded.prompt_tokens,
+                    completion_tokens = token_usage.completion_tokens + excluded.completion_tokens,
+                    updated_at = excluded.updated_at
+                "#,
+            )
+            .bind(user_id)
+            .bind(model_id)
+            .bind(period.key(now))
+            .bind(prompt_tokens.max(0))
+            .bind(completion_tokens.max(0))
+            .bind(current_timestamp_seconds())
+            .execute(&self.db.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_usage_for_period(
+        &self,
+        user_id: &str,
+        period_key: &str,
+    ) -> AppResult<Vec<TokenUsage>> {
+        let usage = sqlx::query_as::<_, TokenUsage>(
+            r#"
+            SELECT user_id, model_id, period, prompt_tokens, completion_tokens, updated_at
+            FROM token_usage
+            WHERE user_id = $1 AND period = $2
+            ORDER BY model_id
+            "#,
+        )
+        .bind(user_id)
+        .bind(period_key)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        Ok(usage)
+    }
+
+    /// Reject the request with 429 when the user has used up their overall or per-model budget
+    pub async fn check_budget(
+        &self,
+        budget: &TokenBudget,
+        user_id: &str,
+        model_id: &str,
+    ) -> AppResult<()> {
+        if !budget.is_enabled() {
+            return Ok(());
+        }
+
+        let usage = self
+            .get_usage_for_period(user_id, &budget.period.key(Utc::now()))
+            .await?;
+
+        if budget.user_limit > 0 {
+            let used: i64 = usage.iter().map(|u| u.total_tokens()).sum();
+            if BudgetStatus::new(budget.user_limit, used).is_exhausted() {
+                return Err(AppError::TooManyRequests(
+                    "Token budget exceeded for this period".to_string(),
+                ));
+            }
+        }
+
+        if let Some(limit) = budget.model_limit(model_id) {
+            let used: i64 = usage
+                .iter()
+                .filter(|u| u.model_id == model_id)
+                .map(|u| u.total_tokens())
+                .sum();
+            if BudgetStatus::new(limit, used).is_exhausted() {
+                return Err(AppError::TooManyRequests(format!(
+                    "Token budget for model {} exceeded for this period",
+                    model_id
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Read `(prompt_tokens, completion_tokens)` from an OpenAI-style `usage` object
+pub fn parse_usage(usage: &serde_json::Value) -> Option<(i64, i64)> {
+    let prompt = usage
+        .get("prompt_tokens")
+        .or_else(|| usage.get("input_tokens"))
+        .and_then(|v| v.as_i64())
+        .unwrap_or(0);
+    let completion = usage
+        .get("completion_tokens")
+        .or_else(|| usage.get("output_tokens"))
+        .and_then(|v| v.as_i64())
+        .unwrap_or(0);
+
+    if prompt == 0 && completion == 0 {
+        None
+    } else {
+        Some((prompt, completion))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_period_keys_and_reset() {
+        let at = Utc.with_ymd_and_hms(2024, 12, 31, 15, 30, 0).unwrap();
+        assert_eq!(BudgetPeriod::Day.key(at), "2024-12-31");
+        assert_eq!(BudgetPeriod::Month.key(at), "2024-12");
+        assert_eq!(
+            BudgetPeriod::Day.resets_at(at),
+            Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0)
+                .unwrap()
+                .timestamp()
+        );
+        assert_eq!(
+            BudgetPeriod::Month.resets_at(at),
+            Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0)
+                .unwrap()
+                .timestamp()
+        );
+        assert_eq!(BudgetPeriod::parse("Daily"), BudgetPeriod::Day);
+        assert_eq!(BudgetPeriod::parse("bogus"), BudgetPeriod::Month);
+    }
+
+    #[test]
+    fn test_budget_status() {
+        let status = BudgetStatus::new(100, 120);
+        assert_eq!(status.remaining, 0);
+        assert!(status.is_exhausted());
+        assert!(!BudgetStatus::new(100, 99).is_exhausted());
+    }
+
+    #[test]
+    fn test_parse_usage() {
+        assert_eq!(
+            parse_usage(&json!({"prompt_tokens": 12, "completion_tokens": 30})),
+            Some((12, 30))
+        );
+        assert_eq!(
+            parse_usage(&json!({"input_tokens": 5, "output_tokens": 7})),
+            Some((5, 7))
+        );
+        assert_eq!(parse_usage(&json!({})), None);
+    }
+}
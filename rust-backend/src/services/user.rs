@@ -146,6 +146,32 @@ impl<'a> UserService<'a> {
         Ok(())
     }
 
+    /// Write the last-seen times of many users at once
+    pub async fn update_users_last_active(&self, seen: &[(String, i64)]) -> AppResult<()> {
+        if seen.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.db.pool.begin().await?;
+        for (id, seen_at) in seen {
+            // Never move a user's last activity backwards
+            sqlx::query(
+                r#"
+                UPDATE "user"
+                SET last_active_at = $1
+                WHERE id = $2 AND last_active_at < $1
+                "#,
+            )
+            .bind(seen_at)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
     pub async fn list_users(&self, skip: i64, limit: i64) -> AppResult<Vec<User>> {
         let users = sqlx::query_as::<_, User>(
             r#"
@@ -22,7 +22,7 @@ pub fn get_event_emitter(
     user_id: String,
     chat_id: Option<String>,
     message_id: Option<String>,
-    _session_id: Option<String>,
+    session_id: Option<String>,
 ) -> impl Fn(serde_json::Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>
        + Send
        + Clone {
@@ -31,6 +31,7 @@ pub fn get_event_emitter(
         let user_id = user_id.clone();
         let chat_id = chat_id.clone();
         let message_id = message_id.clone();
+        let session_id = session_id.clone();
 
         Box::pin(async move {
             // Prepare event payload
@@ -40,10 +41,13 @@ pub fn get_event_emitter(
                 "data": event_data,
             });
 
-            // Emit via native Socket.IO handler
+            // Emit via native Socket.IO handler as a recoverable event, so deltas sent
+            // while the client is reconnecting are replayed to it. Only the sessions
+            // showing the chat and the one that asked for the response get it, or all of
+            // the user's when none joined its room.
             if let Err(e) = socket_state
                 .native_handler
-                .emit_to_user(&user_id, "chat-events", payload)
+                .emit_chat_event(&user_id, chat_id.as_deref(), session_id.as_deref(), payload)
                 .await
             {
                 tracing::warn!("Failed to emit via native Socket.IO: {}", e);
@@ -0,0 +1,197 @@
+/// Chat Streams for Socket.IO
+///
+/// Keeps the content streamed so far for each chat with a response in progress, so a
+/// session that opens the chat mid-stream can be sent what it missed
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Prefix of the room each chat's sessions join
+pub const CHAT_ROOM_PREFIX: &str = "chat:";
+
+/// Streams without an event for this long are dropped, e.g. when a stream failed
+/// without a final event
+const STALE_STREAM_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Room the sessions showing `chat_id` join
+pub fn chat_room(chat_id: &str) -> String {
+    format!("{}{}", CHAT_ROOM_PREFIX, chat_id)
+}
+
+/// A response being streamed into a chat
+#[derive(Debug, Clone)]
+pub struct ChatStreamSnapshot {
+    pub user_id: String,
+    pub message_id: Option<String>,
+    pub content: String,
+    updated_at: Instant,
+}
+
+/// Streamed content of the chats with a response in progress, by chat id
+#[derive(Default)]
+pub struct ChatStreamRegistry {
+    streams: RwLock<HashMap<String, ChatStreamSnapshot>>,
+}
+
+impl ChatStreamRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply a `chat-events` event to the chat's snapshot
+    ///
+    /// Content deltas are appended and full `content` replaces what was streamed so
+    /// far; a `done` or error event ends the stream.
+    pub async fn record(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+        message_id: Option<&str>,
+        event: &JsonValue,
+    ) {
+        if event.get("type").and_then(|t| t.as_str()) != Some("chat:completion") {
+            return;
+        }
+        let data = match event.get("data") {
+            Some(data) => data,
+            None => return,
+        };
+
+        let mut streams = self.streams.write().await;
+        let finished = data.get("done").and_then(|d| d.as_bool()) == Some(true)
+            || data.get("error").is_some_and(|e| !e.is_null());
+        if finished {
+            streams.remove(chat_id);
+            return;
+        }
+
+        let delta = data
+            .pointer("/choices/0/delta/content")
+            .and_then(|c| c.as_str());
+        let content = data.get("content").and_then(|c| c.as_str());
+        if delta.is_none() && content.is_none() {
+            return;
+        }
+
+        let now = Instant::now();
+        if !streams.contains_key(chat_id) {
+            streams.retain(|_, s| now.duration_since(s.updated_at) < STALE_STREAM_TIMEOUT);
+        }
+        let stream = streams
+            .entry(chat_id.to_string())
+            .or_insert_with(|| ChatStreamSnapshot {
+                user_id: user_id.to_string(),
+                message_id: message_id.map(|m| m.to_string()),
+                content: String::new(),
+                updated_at: now,
+            });
+        // A new response in the chat starts over
+        if stream.message_id.as_deref() != message_id || stream.user_id != user_id {
+            stream.user_id = user_id.to_string();
+            stream.message_id = message_id.map(|m| m.to_string());
+            stream.content.clear();
+        }
+        if let Some(content) = content {
+            stream.content = content.to_string();
+        }
+        if let Some(delta) = delta {
+            stream.content.push_str(delta);
+        }
+        stream.updated_at = now;
+    }
+
+    /// The response streaming into `chat_id`, when it belongs to `user_id`
+    pub async fn snapshot(&self, chat_id: &str, user_id: &str) -> Option<ChatStreamSnapshot> {
+        let streams = self.streams.read().await;
+        streams
+            .get(chat_id)
+            .filter(|s| s.user_id == user_id)
+            .filter(|s| s.updated_at.elapsed() < STALE_STREAM_TIMEOUT)
+            .cloned()
+    }
+
+    /// Number of chats with a response in progress
+    pub async fn active_count(&self) -> usize {
+        self.streams.read().await.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn delta(content: &str) -> JsonValue {
+        json!({
+            "type": "chat:completion",
+            "data": {"choices": [{"index": 0, "delta": {"content": content}}]}
+        })
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_accumulates_until_done() {
+        let registry = ChatStreamRegistry::new();
+
+        registry
+            .record("chat-1", "user-1", Some("msg-1"), &delta("Hello"))
+            .await;
+        registry
+            .record("chat-1", "user-1", Some("msg-1"), &delta(", world"))
+            .await;
+        let snapshot = registry.snapshot("chat-1", "user-1").await.unwrap();
+        assert_eq!(snapshot.content, "Hello, world");
+        assert_eq!(snapshot.message_id.as_deref(), Some("msg-1"));
+
+        // Only the chat's owner gets its snapshot
+        assert!(registry.snapshot("chat-1", "user-2").await.is_none());
+
+        // Full content replaces the deltas
+        let full = json!({"type": "chat:completion", "data": {"content": "Rewritten"}});
+        registry
+            .record("chat-1", "user-1", Some("msg-1"), &full)
+            .await;
+        let snapshot = registry.snapshot("chat-1", "user-1").await.unwrap();
+        assert_eq!(snapshot.content, "Rewritten");
+
+        let done = json!({"type": "chat:completion", "data": {"done": true}});
+        registry
+            .record("chat-1", "user-1", Some("msg-1"), &done)
+            .await;
+        assert!(registry.snapshot("chat-1", "user-1").await.is_none());
+        assert_eq!(registry.active_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_new_message_starts_over_and_errors_end_stream() {
+        let registry = ChatStreamRegistry::new();
+
+        registry
+            .record("chat-1", "user-1", Some("msg-1"), &delta("First"))
+            .await;
+        registry
+            .record("chat-1", "user-1", Some("msg-2"), &delta("Second"))
+            .await;
+        let snapshot = registry.snapshot("chat-1", "user-1").await.unwrap();
+        assert_eq!(snapshot.content, "Second");
+
+        // Other event types leave the snapshot alone
+        let status = json!({"type": "status", "data": {"description": "Searching"}});
+        registry
+            .record("chat-1", "user-1", Some("msg-2"), &status)
+            .await;
+        assert_eq!(registry.active_count().await, 1);
+
+        let error = json!({"type": "chat:completion", "data": {"error": {"content": "boom"}}});
+        registry
+            .record("chat-1", "user-1", Some("msg-2"), &error)
+            .await;
+        assert!(registry.snapshot("chat-1", "user-1").await.is_none());
+    }
+
+    #[test]
+    fn test_chat_room() {
+        assert_eq!(chat_room("abc"), "chat:abc");
+        assert!(chat_room("abc").starts_with(CHAT_ROOM_PREFIX));
+    }
+}
@@ -2,31 +2,46 @@
 ///
 /// Handles all Socket.IO events including:
 /// - Authentication (user-join)
-/// - Chat events (chat-events)
+/// - Chat events (chat-events) and chat rooms (join-chat, leave-chat)
 /// - Channel events (channel-events)
 /// - Yjs collaborative editing (ydoc:*)
 /// - Usage tracking
 use crate::db::Database;
+use crate::socketio::chat_streams::{chat_room, ChatStreamRegistry, CHAT_ROOM_PREFIX};
 use crate::socketio::manager::SocketIOManager;
-use crate::socketio::protocol::{EnginePacket, SocketPacket};
-use crate::socketio::redis_adapter::RedisAdapter;
+use crate::socketio::protocol::{
+    binary_placeholder, binary_value, AckTracker, EnginePacket, Parser, SocketPacket, WsFrame,
+};
+use crate::socketio::recovery::ReplayEvent;
+use crate::socketio::redis_adapter::{RedisAdapter, RedisMessage, RedisMessageType};
+use crate::socketio::transport::queue_polling_packet;
 use crate::socketio::ydoc::YDocManager;
+use crate::utils::time::current_timestamp_seconds;
 use actix_web::web;
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
 /// Connection registry - maps session IDs to their websocket senders
 /// This allows us to send messages to specific sessions
-type ConnectionRegistry = Arc<RwLock<HashMap<String, tokio::sync::mpsc::UnboundedSender<String>>>>;
+type ConnectionRegistry = Arc<RwLock<HashMap<String, tokio::sync::mpsc::UnboundedSender<WsFrame>>>>;
 
-use crate::socketio::metrics::SocketIOMetrics;
+use crate::socketio::health::{
+    ConnectionHealth, HealthMonitor, HealthStatus, HeartbeatReport, LatencyPercentiles,
+};
+use crate::socketio::last_seen::LastSeenTracker;
+use crate::socketio::metrics::{EventRates, SocketIOMetrics};
 use crate::socketio::presence::PresenceManager;
-use crate::socketio::rate_limit::RateLimiter;
+use crate::socketio::rate_limit::{RateLimitError, RateLimiter};
 use crate::socketio::recovery::RecoveryManager;
 
+/// Key in `note.data` holding the base64-encoded Yjs state of a collaborative note
+const YDOC_NOTE_STATE_KEY: &str = "ydoc_state";
+
 /// Event handler for Socket.IO events
 #[derive(Clone)]
 pub struct EventHandler {
@@ -39,7 +54,15 @@ pub struct EventHandler {
     rate_limiter: Arc<RateLimiter>,
     presence_manager: Arc<PresenceManager>,
     recovery_manager: Arc<RecoveryManager>,
+    health_monitor: HealthMonitor,
     db: Database,
+    acks: Arc<AckTracker>,
+    /// Content streamed so far into chats with a response in progress
+    chat_streams: Arc<ChatStreamRegistry>,
+    /// Last-seen times of users, written to the database in batches
+    last_seen: Arc<LastSeenTracker>,
+    /// Packet encoding of each session that switched away from the default JSON
+    parsers: Arc<RwLock<HashMap<String, Parser>>>,
 }
 
 impl EventHandler {
@@ -52,6 +75,7 @@ impl EventHandler {
         rate_limiter: Arc<RateLimiter>,
         presence_manager: Arc<PresenceManager>,
         recovery_manager: Arc<RecoveryManager>,
+        health_monitor: HealthMonitor,
         db: Database,
     ) -> Self {
         Self {
@@ -64,7 +88,12 @@ impl EventHandler {
             rate_limiter,
             presence_manager,
             recovery_manager,
+            health_monitor,
             db,
+            acks: Arc::new(AckTracker::new()),
+            chat_streams: Arc::new(ChatStreamRegistry::new()),
+            last_seen: Arc::new(LastSeenTracker::default()),
+            parsers: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
@@ -88,6 +117,11 @@ impl EventHandler {
         &self.recovery_manager
     }
 
+    /// Get health monitor reference
+    pub fn health_monitor(&self) -> &HealthMonitor {
+        &self.health_monitor
+    }
+
     /// Get auth endpoint
     pub fn auth_endpoint(&self) -> &str {
         &self.auth_endpoint
@@ -97,7 +131,7 @@ impl EventHandler {
     pub async fn register_connection(
         &self,
         sid: &str,
-        sender: tokio::sync::mpsc::UnboundedSender<String>,
+        sender: tokio::sync::mpsc::UnboundedSender<WsFrame>,
     ) {
         let mut connections = self.connections.write().await;
         connections.insert(sid.to_string(), sender);
@@ -105,29 +139,78 @@ impl EventHandler {
 
         // Record metrics
         self.metrics.record_connection().await;
+        self.health_monitor.register_connection(sid).await;
 
         tracing::info!("Registered connection: {}", sid);
     }
 
+    /// Encode everything sent to `sid` from now on with `parser`
+    pub async fn set_parser(&self, sid: &str, parser: Parser) {
+        let mut parsers = self.parsers.write().await;
+        if parser == Parser::Json {
+            parsers.remove(sid);
+        } else if parsers.insert(sid.to_string(), parser) != Some(parser) {
+            tracing::debug!("Session {} switched to {:?} packets", sid, parser);
+        }
+    }
+
+    /// The packet encoding the client of `sid` speaks
+    pub async fn parser_of(&self, sid: &str) -> Parser {
+        self.parsers
+            .read()
+            .await
+            .get(sid)
+            .copied()
+            .unwrap_or_default()
+    }
+
     /// Unregister a connection
     pub async fn unregister_connection(&self, sid: &str) {
-        // Get user ID before removing from connections
-        let user_id = self
-            .manager
-            .get_session(sid)
-            .await
-            .and_then(|s| s.user_id());
+        // Get user ID and rooms before removing from connections
+        let session = self.manager.get_session(sid).await;
+        let user_id = session.as_ref().and_then(|s| s.user_id());
 
         let mut connections = self.connections.write().await;
-        connections.remove(sid);
+        let was_connected = connections.remove(sid).is_some();
         drop(connections); // Release lock before async operations
+        self.parsers.write().await.remove(sid);
 
-        // Record metrics
-        self.metrics.record_disconnection().await;
+        // Record metrics once, even when cleanup and the transport both unregister the session
+        if was_connected {
+            self.metrics.record_disconnection().await;
+        }
+        self.health_monitor.remove_connection(sid).await;
+
+        // The client may resume this session if it reconnects within the recovery window
+        self.recovery_manager
+            .session_disconnected(
+                sid,
+                session.as_ref().and_then(|s| s.user.clone()),
+                session
+                    .as_ref()
+                    .map(|s| s.rooms.iter().cloned().collect())
+                    .unwrap_or_default(),
+            )
+            .await;
 
         // Update presence if user was authenticated
         if let Some(uid) = &user_id {
-            self.presence_manager.user_offline(uid).await;
+            let rooms: Vec<String> = session
+                .map(|s| s.rooms.into_iter().collect())
+                .unwrap_or_default();
+
+            if self.presence_manager.user_offline(uid).await {
+                self.last_seen
+                    .disconnected(uid, current_timestamp_seconds())
+                    .await;
+                self.broadcast_presence(uid, &rooms, "offline", Some(sid))
+                    .await;
+            }
+
+            // Keep recovery state so events emitted while the user is away can be replayed
+            if let Err(e) = self.recovery_manager.save_state(sid, uid, rooms).await {
+                tracing::warn!("Failed to save recovery state for {}: {}", sid, e);
+            }
         }
 
         // Clean up rate limiter
@@ -141,40 +224,186 @@ impl EventHandler {
         tracing::info!("Unregistered connection: {}", sid);
     }
 
-    /// Emit event to a specific session
+    /// Emit event to a specific session, through Redis when it is connected to
+    /// another server
     pub async fn emit_to_session(
         &self,
         sid: &str,
         event: &str,
         data: JsonValue,
     ) -> Result<(), String> {
+        let is_local = self.connections.read().await.contains_key(sid);
+        match &self.redis_adapter {
+            Some(redis) if !is_local => redis
+                .publish_emit(
+                    None,
+                    Some(sid.to_string()),
+                    None,
+                    event.to_string(),
+                    data,
+                    false,
+                )
+                .await
+                .map_err(|e| format!("Failed to publish to Redis: {}", e)),
+            _ => {
+                self.send_packet(sid, SocketPacket::event("/", event, data))
+                    .await
+            }
+        }
+    }
+
+    /// Emit an event with binary attachments to a specific session, without base64
+    /// overhead on websockets. `data` refers to each attachment with
+    /// [`crate::socketio::protocol::binary_placeholder`].
+    pub async fn emit_binary_to_session(
+        &self,
+        sid: &str,
+        event: &str,
+        data: JsonValue,
+        attachments: Vec<Bytes>,
+    ) -> Result<(), String> {
+        self.send_packet(
+            sid,
+            SocketPacket::binary_event("/", event, data, attachments),
+        )
+        .await
+    }
+
+    /// Hand `frame` to the websocket writer of `sid`. It counts as queued before the
+    /// writer can take it off the queue, so the writer's decrement is never lost.
+    async fn queue_frame(
+        &self,
+        sid: &str,
+        sender: &tokio::sync::mpsc::UnboundedSender<WsFrame>,
+        frame: WsFrame,
+    ) -> Result<(), String> {
+        self.rate_limiter.increment_queue(sid, 1).await;
+        if let Err(e) = sender.send(frame) {
+            self.rate_limiter.decrement_queue(sid, 1).await;
+            return Err(e.to_string());
+        }
+        Ok(())
+    }
+
+    async fn send_packet(&self, sid: &str, packet: SocketPacket) -> Result<(), String> {
+        let parser = self.parser_of(sid).await;
         let connections = self.connections.read().await;
         if let Some(sender) = connections.get(sid) {
-            let socket_packet = SocketPacket::event("/", event, data);
-            let engine_packet = EnginePacket::message(socket_packet.encode().into_bytes());
-
-            sender
-                .send(engine_packet.encode())
-                .map_err(|e| e.to_string())?;
+            for frame in packet.into_frames_for(parser) {
+                self.queue_frame(sid, sender, frame).await?;
+            }
+            Ok(())
+        } else if self.manager.get_session(sid).await.is_some() {
+            // No websocket: a long-polling session, which picks it up on its next GET
+            queue_polling_packet(sid, &packet, parser).await;
             Ok(())
         } else {
             Err(format!("Session not found: {}", sid))
         }
     }
 
-    /// Emit event to all sessions of a user
+    /// Emit an event to a session on this server and wait for the client to
+    /// acknowledge it. Resolves with the arguments the client passed to its ACK
+    /// callback, or fails when the session is unknown or `timeout` passes first.
+    pub async fn emit_with_ack(
+        &self,
+        sid: &str,
+        event: &str,
+        data: JsonValue,
+        timeout: Duration,
+    ) -> Result<JsonValue, String> {
+        let (ack_id, reply) = self.acks.expect_ack(sid, timeout).await;
+        let packet = SocketPacket::event_with_ack("/", event, data, ack_id);
+        if let Err(e) = self.send_packet(sid, packet).await {
+            self.acks.cancel(ack_id).await;
+            return Err(e);
+        }
+
+        match tokio::time::timeout(timeout, reply).await {
+            Ok(Ok(data)) => Ok(data),
+            _ => {
+                self.acks.cancel(ack_id).await;
+                Err(format!(
+                    "No ACK for {} from {} within {:?}",
+                    event, sid, timeout
+                ))
+            }
+        }
+    }
+
+    /// Emit an event to every session of a user on this server, each asked to
+    /// acknowledge it. Returns the ACK payloads of the sessions that answered in time.
+    pub async fn emit_to_user_with_ack(
+        &self,
+        user_id: &str,
+        event: &str,
+        data: JsonValue,
+        timeout: Duration,
+    ) -> Vec<JsonValue> {
+        let sids = self.manager.get_user_sessions(user_id).await;
+        let pending = sids
+            .iter()
+            .map(|sid| self.emit_with_ack(sid, event, data.clone(), timeout));
+        futures::future::join_all(pending)
+            .await
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect()
+    }
+
+    /// Hand an ACK packet from `sid` to the [`Self::emit_with_ack`] waiting for it
+    pub async fn handle_ack(&self, sid: &str, packet: SocketPacket) {
+        let id = match packet.id {
+            Some(id) => id,
+            None => {
+                tracing::debug!("Ignoring ACK without an ID from {}", sid);
+                return;
+            }
+        };
+        let data = packet
+            .filled_data()
+            .unwrap_or_else(|| JsonValue::Array(Vec::new()));
+        if !self.acks.process_session_ack(sid, id, data).await {
+            tracing::debug!("Ignoring unexpected or late ACK {} from {}", id, sid);
+        }
+    }
+
+    /// Emit event to all sessions of a user, on this server and through Redis on the
+    /// others. Returns the number of local sessions reached.
     pub async fn emit_to_user(
         &self,
         user_id: &str,
         event: &str,
         data: JsonValue,
     ) -> Result<usize, String> {
+        let sent = self.emit_to_local_user(user_id, event, data.clone()).await;
+
+        if let Some(redis) = &self.redis_adapter {
+            if let Err(e) = redis
+                .publish_emit(
+                    Some(user_id.to_string()),
+                    None,
+                    None,
+                    event.to_string(),
+                    data,
+                    false,
+                )
+                .await
+            {
+                tracing::warn!("Failed to publish to Redis: {}", e);
+            }
+        }
+
+        Ok(sent)
+    }
+
+    async fn emit_to_local_user(&self, user_id: &str, event: &str, data: JsonValue) -> usize {
         let sids = self.manager.get_user_sessions(user_id).await;
         let mut sent = 0;
 
         for sid in sids {
             if self
-                .emit_to_session(&sid, event, data.clone())
+                .send_packet(&sid, SocketPacket::event("/", event, data.clone()))
                 .await
                 .is_ok()
             {
@@ -182,35 +411,319 @@ impl EventHandler {
             }
         }
 
-        Ok(sent)
+        sent
     }
 
-    /// Broadcast event to all sessions in a room
-    pub async fn broadcast_to_room(
+    /// Emit a recoverable event to all sessions of a user
+    ///
+    /// Each event carries an offset, and is kept for sessions that just disconnected,
+    /// so a client that briefly loses its connection is replayed what it missed. When
+    /// the user has no session at all, the event is buffered for the next one to join.
+    pub async fn emit_to_user_or_buffer(
         &self,
-        room: &str,
+        user_id: &str,
         event: &str,
         data: JsonValue,
-        exclude_sid: Option<&str>,
     ) -> Result<usize, String> {
-        // Broadcast to local sessions
-        let sids = self.manager.get_room_sessions(room).await;
+        let sent = self
+            .emit_recoverable_to_local_user(user_id, event, data.clone())
+            .await;
+        let pending = self
+            .recovery_manager
+            .record_for_disconnected_user(user_id, event, &data)
+            .await;
+
+        if let Some(redis) = &self.redis_adapter {
+            if let Err(e) = redis
+                .publish_emit(
+                    Some(user_id.to_string()),
+                    None,
+                    None,
+                    event.to_string(),
+                    data.clone(),
+                    true,
+                )
+                .await
+            {
+                tracing::warn!("Failed to publish to Redis: {}", e);
+            }
+        }
+
+        if sent == 0 && pending == 0 && self.manager.remote_user_session_count(user_id).await == 0 {
+            self.recovery_manager
+                .buffer_user_message(user_id, event.to_string(), data)
+                .await;
+        }
+
+        Ok(sent)
+    }
+
+    async fn emit_recoverable_to_local_user(
+        &self,
+        user_id: &str,
+        event: &str,
+        data: JsonValue,
+    ) -> usize {
+        let sids = self.manager.get_user_sessions(user_id).await;
+        self.emit_recoverable_to_sessions(sids, event, data).await
+    }
+
+    async fn emit_recoverable_to_sessions(
+        &self,
+        sids: Vec<String>,
+        event: &str,
+        data: JsonValue,
+    ) -> usize {
         let mut sent = 0;
 
         for sid in sids {
-            if Some(sid.as_str()) == exclude_sid {
-                continue;
+            let packet = match self.recovery_manager.record_event(&sid, event, &data).await {
+                Some(offset) => SocketPacket::event_with_offset("/", event, data.clone(), offset),
+                None => SocketPacket::event("/", event, data.clone()),
+            };
+            if self.send_packet(&sid, packet).await.is_ok() {
+                sent += 1;
+            }
+        }
+
+        sent
+    }
+
+    /// Emit a recoverable `chat-events` event for a chat's response
+    ///
+    /// Goes to the user's sessions that joined the chat's room, or to all of the user's
+    /// sessions when none did. The session the response was requested from always gets
+    /// it, so it isn't cut off when another session joins the chat mid-stream. The event
+    /// also advances the chat's stream snapshot, which sessions joining the chat
+    /// mid-stream are sent.
+    pub async fn emit_chat_event(
+        &self,
+        user_id: &str,
+        chat_id: Option<&str>,
+        origin_sid: Option<&str>,
+        data: JsonValue,
+    ) -> Result<usize, String> {
+        const EVENT: &str = "chat-events";
+        let chat_id = match chat_id {
+            Some(chat_id) => chat_id,
+            None => return self.emit_to_user_or_buffer(user_id, EVENT, data).await,
+        };
+
+        if let Some(event) = data.get("data") {
+            let message_id = data.get("message_id").and_then(|m| m.as_str());
+            self.chat_streams
+                .record(chat_id, user_id, message_id, event)
+                .await;
+        }
+
+        let room = chat_room(chat_id);
+        let sids = self.manager.get_user_room_sessions(&room, user_id).await;
+        let joined_elsewhere = sids.is_empty()
+            && self.redis_adapter.is_some()
+            && self
+                .manager
+                .get_room_user_ids(&room)
+                .await
+                .iter()
+                .any(|id| id == user_id);
+        if sids.is_empty() && !joined_elsewhere {
+            return self.emit_to_user_or_buffer(user_id, EVENT, data).await;
+        }
+
+        let sids = self.with_origin_session(sids, origin_sid, user_id).await;
+        let sent = self
+            .emit_recoverable_to_sessions(sids, EVENT, data.clone())
+            .await;
+
+        if let Some(redis) = &self.redis_adapter {
+            if let Err(e) = redis
+                .publish_emit(
+                    Some(user_id.to_string()),
+                    origin_sid.map(String::from),
+                    Some(room),
+                    EVENT.to_string(),
+                    data,
+                    true,
+                )
+                .await
+            {
+                tracing::warn!("Failed to publish to Redis: {}", e);
             }
+        }
 
+        Ok(sent)
+    }
+
+    /// Add the session a chat response was requested from to its recipients, when that
+    /// session is connected here and belongs to the user
+    async fn with_origin_session(
+        &self,
+        mut sids: Vec<String>,
+        origin_sid: Option<&str>,
+        user_id: &str,
+    ) -> Vec<String> {
+        if let Some(origin_sid) = origin_sid {
+            let owned = self
+                .manager
+                .get_session(origin_sid)
+                .await
+                .and_then(|session| session.user_id())
+                .is_some_and(|id| id == user_id);
+            if owned && !sids.iter().any(|sid| sid == origin_sid) {
+                sids.push(origin_sid.to_string());
+            }
+        }
+        sids
+    }
+
+    /// Send a session the response streamed so far into a chat, if one is in progress
+    async fn send_chat_snapshot(&self, sid: &str, user_id: &str, chat_id: &str) {
+        let snapshot = match self.chat_streams.snapshot(chat_id, user_id).await {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        let data = serde_json::json!({
+            "chat_id": chat_id,
+            "message_id": snapshot.message_id,
+            "data": {
+                "type": "chat:completion",
+                "data": {
+                    "content": snapshot.content,
+                    "snapshot": true,
+                },
+            },
+        });
+        if let Err(e) = self.emit_to_session(sid, "chat-events", data).await {
+            tracing::warn!("Failed to send chat snapshot to {}: {}", sid, e);
+        }
+    }
+
+    /// Resume an earlier session when a CONNECT packet carries its `pid` and the
+    /// `offset` of the last event the client received
+    ///
+    /// On success the session gets its user and rooms back and the missed events are
+    /// sent right away; returns the pid for the CONNECT response. `None` means the
+    /// client starts a fresh session.
+    pub async fn resume_session(
+        &self,
+        sid: &str,
+        connect_data: Option<&JsonValue>,
+    ) -> Option<String> {
+        let pid = connect_data?.get("pid")?.as_str()?;
+        // Clients that haven't received a recoverable event yet send no offset
+        let offset = connect_data
+            .and_then(|data| data.get("offset"))
+            .and_then(|offset| offset.as_str())
+            .unwrap_or("0");
+
+        let recovered = match self
+            .recovery_manager
+            .recover_session(sid, pid, offset)
+            .await
+        {
+            Ok(recovered) => recovered,
+            Err(reason) => {
+                self.metrics.record_recovery(false).await;
+                tracing::info!("Could not recover session {} on {}: {}", pid, sid, reason);
+                return None;
+            }
+        };
+        self.metrics.record_recovery(true).await;
+
+        if let Some(user) = recovered.user {
+            let user_id = user
+                .get("id")
+                .and_then(|id| id.as_str())
+                .map(|id| id.to_string());
+            match self.manager.set_session_user(sid, user).await {
+                Ok(()) => {
+                    if let Some(user_id) = user_id {
+                        self.mark_user_online(sid, &user_id).await;
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to restore user of session {}: {}", sid, e),
+            }
+        }
+        for room in &recovered.rooms {
+            if let Err(e) = self.manager.join_room(sid, room).await {
+                tracing::warn!("Failed to rejoin {} on session {}: {}", room, sid, e);
+            }
+        }
+
+        tracing::info!(
+            "Recovered session {} on {}, replaying {} missed event(s)",
+            pid,
+            sid,
+            recovered.events.len()
+        );
+        self.replay_events(sid, recovered.events).await;
+
+        // Events of chats that were streamed to a room while the session was away
+        // aren't kept for it, so catch its chat up
+        if let Some(user_id) = self
+            .manager
+            .get_session(sid)
+            .await
+            .and_then(|s| s.user_id())
+        {
+            for room in &recovered.rooms {
+                if let Some(chat_id) = room.strip_prefix(CHAT_ROOM_PREFIX) {
+                    self.send_chat_snapshot(sid, &user_id, chat_id).await;
+                }
+            }
+        }
+        Some(pid.to_string())
+    }
+
+    async fn replay_events(&self, sid: &str, events: Vec<ReplayEvent>) {
+        for event in events {
+            let packet =
+                SocketPacket::event_with_offset("/", &event.event, event.data, event.offset);
+            if self.send_packet(sid, packet).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Replay events buffered while the user had no live sessions
+    pub async fn replay_buffered_messages(&self, sid: &str, user_id: &str) -> usize {
+        let messages = self.recovery_manager.take_user_messages(user_id).await;
+        let mut replayed = 0;
+
+        for message in messages {
             if self
-                .emit_to_session(&sid, event, data.clone())
+                .emit_to_session(sid, &message.event, message.data)
                 .await
                 .is_ok()
             {
-                sent += 1;
+                replayed += 1;
             }
         }
 
+        if replayed > 0 {
+            tracing::info!(
+                "Replayed {} buffered event(s) for user {} on session {}",
+                replayed,
+                user_id,
+                sid
+            );
+        }
+
+        replayed
+    }
+
+    /// Broadcast event to all sessions in a room
+    pub async fn broadcast_to_room(
+        &self,
+        room: &str,
+        event: &str,
+        data: JsonValue,
+        exclude_sid: Option<&str>,
+    ) -> Result<usize, String> {
+        let sent = self
+            .broadcast_to_local_room(room, event, data.clone(), exclude_sid)
+            .await;
+
         // Publish to Redis for cross-server broadcasting
         if let Some(redis) = &self.redis_adapter {
             if let Err(e) = redis
@@ -231,6 +744,114 @@ impl EventHandler {
         Ok(sent)
     }
 
+    async fn broadcast_to_local_room(
+        &self,
+        room: &str,
+        event: &str,
+        data: JsonValue,
+        exclude_sid: Option<&str>,
+    ) -> usize {
+        let sids = self.manager.get_room_sessions(room).await;
+        let mut sent = 0;
+
+        for sid in sids {
+            if Some(sid.as_str()) == exclude_sid {
+                continue;
+            }
+
+            if self
+                .send_packet(&sid, SocketPacket::event("/", event, data.clone()))
+                .await
+                .is_ok()
+            {
+                sent += 1;
+            }
+        }
+
+        sent
+    }
+
+    /// Deliver an event published by another server to the sessions connected here
+    pub async fn handle_redis_message(&self, message: RedisMessage) {
+        match message.message_type {
+            RedisMessageType::Emit {
+                user_id,
+                session_id,
+                room,
+                event,
+                data,
+                recoverable,
+            } => {
+                if let (Some(user_id), Some(room)) = (&user_id, &room) {
+                    // A chat's events, for the user's sessions in its room and the one
+                    // the response was requested from
+                    let sids = self.manager.get_user_room_sessions(room, user_id).await;
+                    let sids = self
+                        .with_origin_session(sids, session_id.as_deref(), user_id)
+                        .await;
+                    self.emit_recoverable_to_sessions(sids, &event, data).await;
+                } else if let Some(user_id) = user_id {
+                    if recoverable {
+                        self.emit_recoverable_to_local_user(&user_id, &event, data.clone())
+                            .await;
+                        self.recovery_manager
+                            .record_for_disconnected_user(&user_id, &event, &data)
+                            .await;
+                    } else {
+                        self.emit_to_local_user(&user_id, &event, data).await;
+                    }
+                } else if let Some(sid) = session_id {
+                    // Every server gets the message; only the one holding the session sends
+                    let _ = self
+                        .send_packet(&sid, SocketPacket::event("/", &event, data))
+                        .await;
+                } else if let Some(room) = room {
+                    self.broadcast_to_local_room(&room, &event, data, None)
+                        .await;
+                }
+            }
+            RedisMessageType::Broadcast {
+                room,
+                event,
+                data,
+                exclude_sid,
+            } => {
+                self.broadcast_to_local_room(&room, &event, data, exclude_sid.as_deref())
+                    .await;
+            }
+            // Membership is kept in Redis sets by the manager
+            RedisMessageType::UserJoined { .. } | RedisMessageType::UserLeft { .. } => {}
+        }
+    }
+
+    /// Subscribe to events from other servers and deliver them, in the order they were
+    /// published, for as long as the server runs
+    pub fn start_redis_subscription(&self) {
+        let redis = match &self.redis_adapter {
+            Some(redis) => redis.clone(),
+            None => return,
+        };
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<RedisMessage>();
+
+        tokio::spawn(async move {
+            if let Err(e) = redis
+                .subscribe(move |message| {
+                    let _ = tx.send(message);
+                })
+                .await
+            {
+                tracing::error!("Redis subscription error: {:?}", e);
+            }
+        });
+
+        let handler = self.clone();
+        tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                handler.handle_redis_message(message).await;
+            }
+        });
+    }
+
     /// Handle authentication (user-join event)
     pub async fn handle_user_join(
         &self,
@@ -261,6 +882,14 @@ impl EventHandler {
                 .await
                 .map_err(|e| format!("Failed to parse user data: {}", e))?;
 
+            // Sessions authenticated during CONNECT are already counted in presence
+            let already_authenticated = self
+                .manager
+                .get_session(sid)
+                .await
+                .and_then(|s| s.user_id())
+                .is_some();
+
             // Set session user
             self.manager.set_session_user(sid, user.clone()).await?;
 
@@ -269,9 +898,6 @@ impl EventHandler {
                 .and_then(|id| id.as_str())
                 .ok_or("Missing user ID")?;
 
-            // Update presence
-            self.presence_manager.user_online(user_id).await;
-
             // Record metric
             self.metrics.record_event_received("user-join").await;
 
@@ -288,6 +914,14 @@ impl EventHandler {
                 tracing::warn!("Failed to auto-join user {} to channels: {}", user_id, e);
             }
 
+            // Update presence once the session has joined its channel rooms
+            if !already_authenticated {
+                self.mark_user_online(sid, user_id).await;
+            }
+
+            // Resume streams that kept emitting while the user was disconnected
+            self.replay_buffered_messages(sid, user_id).await;
+
             Ok(user)
         } else {
             Err(format!("Authentication failed: {}", response.status()))
@@ -369,6 +1003,51 @@ impl EventHandler {
         Ok(())
     }
 
+    /// Handle chat join: the session shows a chat and gets its streaming events
+    ///
+    /// A session shows one chat at a time, so it leaves the room of the chat it showed
+    /// before. If a response is streaming into the chat, the session is sent what was
+    /// streamed so far. Events are only ever sent to the chat owner's sessions, so
+    /// joining someone else's chat gets nothing.
+    pub async fn handle_chat_join(&self, sid: &str, data: JsonValue) -> Result<(), String> {
+        let chat_id = data
+            .get("chat_id")
+            .and_then(|c| c.as_str())
+            .ok_or("Missing chat_id")?;
+
+        let session = self
+            .manager
+            .get_session(sid)
+            .await
+            .ok_or("Session not found")?;
+        let user_id = session.user_id().ok_or("Not authenticated")?;
+
+        let room = chat_room(chat_id);
+        for previous in &session.rooms {
+            if previous.starts_with(CHAT_ROOM_PREFIX) && *previous != room {
+                self.manager.leave_room(sid, previous).await?;
+            }
+        }
+        self.manager.join_room(sid, &room).await?;
+        self.send_chat_snapshot(sid, &user_id, chat_id).await;
+
+        tracing::debug!("Session {} joined chat room: {}", sid, chat_id);
+        Ok(())
+    }
+
+    /// Handle chat leave: the session no longer shows the chat
+    pub async fn handle_chat_leave(&self, sid: &str, data: JsonValue) -> Result<(), String> {
+        let chat_id = data
+            .get("chat_id")
+            .and_then(|c| c.as_str())
+            .ok_or("Missing chat_id")?;
+
+        self.manager.leave_room(sid, &chat_room(chat_id)).await?;
+
+        tracing::debug!("Session {} left chat room: {}", sid, chat_id);
+        Ok(())
+    }
+
     /// Handle channel events (broadcast to channel room)
     pub async fn handle_channel_event(&self, sid: &str, data: JsonValue) -> Result<(), String> {
         let channel_id = data
@@ -455,6 +1134,10 @@ impl EventHandler {
 
         let user_role = user.get("role").and_then(|r| r.as_str()).unwrap_or("user");
 
+        // Non-note documents are editable by anyone in the room
+        let mut can_write = true;
+        let mut persisted_state: Option<Vec<u8>> = None;
+
         // Access control check for notes (note:xxx format)
         if doc_id.starts_with("note:") {
             let note_id = doc_id
@@ -476,33 +1159,43 @@ impl EventHandler {
 
             note.parse_json_fields();
 
-            // Admin has full access
-            if user_role != "admin" {
-                // Owner has full access
-                if user_id != note.user_id {
-                    // Check access control for other users
-                    let group_service = GroupService::new(&self.db);
-                    let user_groups = group_service
-                        .get_groups_by_member_id(user_id)
-                        .await
-                        .map_err(|e| format!("Failed to get user groups: {}", e))?;
-                    let user_group_ids: std::collections::HashSet<String> =
-                        user_groups.into_iter().map(|g| g.id).collect();
-
-                    use crate::utils::misc::has_access;
-                    if !has_access(user_id, "read", &note.access_control, &user_group_ids) {
-                        return Err(format!(
-                            "User {} does not have access to note {}",
-                            user_id, note_id
-                        ));
-                    }
+            // Admin and owner have full access; others need read access to join
+            // and write access to edit
+            if user_role != "admin" && user_id != note.user_id {
+                let group_service = GroupService::new(&self.db);
+                let user_groups = group_service
+                    .get_groups_by_member_id(user_id)
+                    .await
+                    .map_err(|e| format!("Failed to get user groups: {}", e))?;
+                let user_group_ids: std::collections::HashSet<String> =
+                    user_groups.into_iter().map(|g| g.id).collect();
+
+                use crate::utils::misc::has_access;
+                if !has_access(user_id, "read", &note.access_control, &user_group_ids) {
+                    return Err(format!(
+                        "User {} does not have access to note {}",
+                        user_id, note_id
+                    ));
                 }
+                can_write = has_access(user_id, "write", &note.access_control, &user_group_ids);
             }
 
+            // Previously flushed CRDT state, used when the document isn't loaded
+            persisted_state = note
+                .data
+                .as_ref()
+                .and_then(|d| d.get(YDOC_NOTE_STATE_KEY))
+                .and_then(|s| s.as_str())
+                .and_then(|s| {
+                    use base64::{engine::general_purpose::STANDARD, Engine as _};
+                    STANDARD.decode(s).ok()
+                });
+
             tracing::info!(
-                "User {} (role: {}) granted access to note document {}",
+                "User {} (role: {}) granted {} access to note document {}",
                 user_id,
                 user_role,
+                if can_write { "write" } else { "read" },
                 note_id
             );
         }
@@ -513,6 +1206,16 @@ impl EventHandler {
 
         // Add user to Yjs document
         self.ydoc_manager.add_user(doc_id, sid).await?;
+        if !can_write {
+            self.ydoc_manager.set_read_only(doc_id, sid).await;
+        }
+
+        // Restore the persisted state if this is the first editor since eviction
+        if let Some(state) = persisted_state {
+            if let Err(e) = self.ydoc_manager.seed_document(doc_id, state).await {
+                tracing::warn!("Ignoring persisted state for {}: {}", doc_id, e);
+            }
+        }
 
         // Get the current document state
         let state_update = self.ydoc_manager.get_state_as_update(doc_id).await?;
@@ -521,13 +1224,7 @@ impl EventHandler {
         let active_sessions = self.manager.get_room_sessions(&room).await;
 
         // Send the document state to the joining client
-        let state_data = serde_json::json!({
-            "document_id": doc_id,
-            "state": state_update,
-            "sessions": active_sessions,
-        });
-
-        self.emit_to_session(sid, "ydoc:document:state", state_data)
+        self.send_document_state(sid, doc_id, state_update, active_sessions)
             .await?;
 
         // Notify other users about the new user
@@ -564,16 +1261,10 @@ impl EventHandler {
         let room = format!("doc_{}", doc_id);
         self.manager.leave_room(sid, &room).await?;
 
-        // Remove user from Yjs document
+        // Remove user from Yjs document; empty documents are flushed and evicted
+        // by evict_idle_ydoc_documents once they have been idle long enough
         self.ydoc_manager.remove_user(doc_id, sid).await?;
 
-        // Check if document is now empty and clean up if needed
-        let users = self.ydoc_manager.get_users(doc_id).await?;
-        if users.is_empty() {
-            tracing::info!("Document {} is now empty, clearing from memory", doc_id);
-            self.ydoc_manager.clear_document(doc_id).await?;
-        }
-
         // Notify other users
         let leave_notification = serde_json::json!({
             "document_id": doc_id,
@@ -587,40 +1278,76 @@ impl EventHandler {
         Ok(())
     }
 
+    /// Send `state` of a document to `sid` as a binary attachment, which the
+    /// client applies as a Yjs update
+    async fn send_document_state(
+        &self,
+        sid: &str,
+        doc_id: &str,
+        state: Vec<u8>,
+        sessions: Vec<String>,
+    ) -> Result<(), String> {
+        let state_data = serde_json::json!({
+            "document_id": doc_id,
+            "state": binary_placeholder(0),
+            "sessions": sessions,
+        });
+        self.emit_binary_to_session(sid, "ydoc:document:state", state_data, vec![state.into()])
+            .await
+    }
+
     /// Handle Yjs document update (broadcast to room)
-    pub async fn handle_ydoc_update(&self, sid: &str, data: JsonValue) -> Result<(), String> {
+    ///
+    /// The update may come as a binary attachment or as a JSON array of bytes.
+    pub async fn handle_ydoc_update(
+        &self,
+        sid: &str,
+        data: JsonValue,
+        attachments: &[Bytes],
+    ) -> Result<(), String> {
         let doc_id = data
             .get("document_id")
             .and_then(|d| d.as_str())
             .ok_or("Missing document_id")?;
 
         // Extract the update bytes
-        let update_array = data
+        let update_bytes = data
             .get("update")
-            .and_then(|u| u.as_array())
-            .ok_or("Missing or invalid update array")?;
-
-        let update_bytes: Vec<u8> = update_array
-            .iter()
-            .filter_map(|v| v.as_u64().map(|n| n as u8))
-            .collect();
+            .and_then(|u| binary_value(u, attachments))
+            .ok_or("Missing or invalid update")?;
 
         if update_bytes.is_empty() {
             return Err("Empty update".to_string());
         }
 
+        // Only sessions that joined with write access may edit
+        let room = format!("doc_{}", doc_id);
+        let in_room = self
+            .manager
+            .get_session(sid)
+            .await
+            .map(|session| session.rooms.contains(&room))
+            .unwrap_or(false);
+        if !in_room {
+            return Err("Not in document room".to_string());
+        }
+        if self.ydoc_manager.is_read_only(doc_id, sid).await {
+            return Err(format!(
+                "Session {} has read-only access to {}",
+                sid, doc_id
+            ));
+        }
+
         // Store the update in Yjs manager
         self.ydoc_manager
-            .append_update(doc_id, update_bytes)
+            .append_update(doc_id, update_bytes.to_vec())
             .await?;
 
         // Broadcast update to all other clients in the room
-        let room = format!("doc_{}", doc_id);
-
         let broadcast_data = serde_json::json!({
             "document_id": doc_id,
             "user_id": data.get("user_id"),
-            "update": data.get("update"),
+            "update": update_bytes.as_ref(),
             "socket_id": sid,
         });
 
@@ -629,49 +1356,11 @@ impl EventHandler {
 
         tracing::debug!("Stored and broadcasted Yjs update for document: {}", doc_id);
 
-        // Save to database for notes (debounced)
-        if doc_id.starts_with("note:") {
-            if let Some(note_data) = data.get("data") {
-                let doc_id_clone = doc_id.to_string();
-                let note_data_clone = note_data.clone();
-                let db_clone = self.db.clone();
-
-                // Spawn debounced save task
-                tokio::spawn(async move {
-                    // Wait 500ms before saving (debounce)
-                    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-
-                    if let Some(note_id) = doc_id_clone.strip_prefix("note:") {
-                        use crate::models::note::NoteUpdateForm;
-                        use crate::services::note::NoteService;
-
-                        let note_service = NoteService::new(&db_clone);
-                        let update_form = NoteUpdateForm {
-                            title: None,
-                            data: Some(note_data_clone),
-                            meta: None,
-                            access_control: None,
-                        };
-
-                        match note_service.update_note_by_id(note_id, &update_form).await {
-                            Ok(_) => {
-                                tracing::debug!(
-                                    "Saved note {} to database after Yjs update",
-                                    note_id
-                                );
-                            }
-                            Err(e) => {
-                                tracing::error!(
-                                    "Failed to save note {} to database: {}",
-                                    note_id,
-                                    e
-                                );
-                            }
-                        }
-                    }
-                });
-            }
-        }
+        // Persisted periodically by flush_ydoc_documents, together with the
+        // latest materialized content the editor sent along
+        self.ydoc_manager
+            .mark_dirty(doc_id, data.get("data").cloned())
+            .await;
 
         Ok(())
     }
@@ -700,27 +1389,15 @@ impl EventHandler {
         if !self.ydoc_manager.document_exists(doc_id).await? {
             tracing::warn!("Document {} not found", doc_id);
             // Send empty state for new document
-            let state_data = serde_json::json!({
-                "document_id": doc_id,
-                "state": [],
-                "sessions": room_sessions,
-            });
-
             return self
-                .emit_to_session(sid, "ydoc:document:state", state_data)
+                .send_document_state(sid, doc_id, Vec::new(), room_sessions)
                 .await;
         }
 
         // Get the document state as a full update
         let state_update = self.ydoc_manager.get_state_as_update(doc_id).await?;
 
-        let state_data = serde_json::json!({
-            "document_id": doc_id,
-            "state": state_update,
-            "sessions": room_sessions,
-        });
-
-        self.emit_to_session(sid, "ydoc:document:state", state_data)
+        self.send_document_state(sid, doc_id, state_update, room_sessions)
             .await?;
 
         tracing::debug!("Sent Yjs state for document: {}", doc_id);
@@ -730,7 +1407,7 @@ impl EventHandler {
     /// Handle Yjs awareness update (broadcast to room)
     pub async fn handle_ydoc_awareness_update(
         &self,
-        _sid: &str,
+        sid: &str,
         data: JsonValue,
     ) -> Result<(), String> {
         let doc_id = data
@@ -743,6 +1420,9 @@ impl EventHandler {
         // Broadcast awareness update to all clients in the room (including sender for awareness)
         // Awareness needs to be sent to all including sender for cursor sync
         let room_sids = self.manager.get_room_sessions(&room).await;
+        if !room_sids.iter().any(|s| s == sid) {
+            return Err("Not in document room".to_string());
+        }
 
         let mut sent = 0;
         for session_sid in room_sids {
@@ -763,11 +1443,78 @@ impl EventHandler {
         Ok(())
     }
 
+    /// Persist documents changed since the last flush
+    ///
+    /// Merges each document's updates into a single update. For notes, the merged
+    /// CRDT state and the latest content reported by an editor are written back to
+    /// the note so REST reads see the collaborative edits.
+    pub async fn flush_ydoc_documents(&self) {
+        for (doc_id, snapshot) in self.ydoc_manager.take_dirty_documents().await {
+            let state = match self.ydoc_manager.compact(&doc_id).await {
+                Ok(state) => state,
+                Err(e) => {
+                    tracing::error!("Failed to compact Yjs document {}: {}", doc_id, e);
+                    continue;
+                }
+            };
+
+            let note_id = match doc_id.strip_prefix("note:") {
+                Some(note_id) => note_id,
+                None => continue,
+            };
+
+            use crate::models::note::NoteUpdateForm;
+            use crate::services::note::NoteService;
+            use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+            let mut note_data = snapshot
+                .filter(|s| s.is_object())
+                .unwrap_or_else(|| serde_json::json!({}));
+            note_data[YDOC_NOTE_STATE_KEY] = serde_json::json!(STANDARD.encode(&state));
+
+            let update_form = NoteUpdateForm {
+                title: None,
+                data: Some(note_data),
+                meta: None,
+                access_control: None,
+            };
+
+            match NoteService::new(&self.db)
+                .update_note_by_id(note_id, &update_form)
+                .await
+            {
+                Ok(_) => {
+                    tracing::debug!("Saved note {} to database after Yjs updates", note_id);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to save note {} to database: {}", note_id, e);
+                }
+            }
+        }
+    }
+
+    /// Flush pending changes, then drop documents that have had no editors for `idle_timeout`
+    pub async fn evict_idle_ydoc_documents(&self, idle_timeout: std::time::Duration) {
+        self.flush_ydoc_documents().await;
+
+        for doc_id in self.ydoc_manager.take_idle_documents(idle_timeout).await {
+            tracing::info!("Evicting idle Yjs document {} from memory", doc_id);
+            if let Err(e) = self.ydoc_manager.clear_document(&doc_id).await {
+                tracing::error!("Failed to evict Yjs document {}: {}", doc_id, e);
+            }
+        }
+    }
+
     /// Get manager reference
     pub fn manager(&self) -> &SocketIOManager {
         &self.manager
     }
 
+    /// Redis adapter, when cross-instance broadcasting is configured
+    pub fn redis_adapter(&self) -> Option<&RedisAdapter> {
+        self.redis_adapter.as_deref()
+    }
+
     /// Handle presence status update
     pub async fn handle_presence_status(&self, sid: &str, data: JsonValue) -> Result<(), String> {
         let session = self
@@ -873,6 +1620,338 @@ impl EventHandler {
         Ok(())
     }
 
+    /// Handle `typing` events for a channel or chat.
+    ///
+    /// Broadcasts are throttled per user and room; indicators that stop being refreshed are
+    /// expired by [`EventHandler::expire_typing_indicators`].
+    pub async fn handle_typing(&self, sid: &str, data: JsonValue) -> Result<(), String> {
+        let session = self
+            .manager
+            .get_session(sid)
+            .await
+            .ok_or("Session not found")?;
+
+        let user = session.user.as_ref().ok_or("User not authenticated")?;
+        let user_id = user
+            .get("id")
+            .and_then(|id| id.as_str())
+            .ok_or("Missing user ID")?;
+        let user_name = user
+            .get("name")
+            .and_then(|n| n.as_str())
+            .unwrap_or("Unknown");
+
+        let room = if let Some(channel_id) = data.get("channel_id").and_then(|c| c.as_str()) {
+            format!("channel:{}", channel_id)
+        } else if let Some(chat_id) = data.get("chat_id").and_then(|c| c.as_str()) {
+            format!("chat:{}", chat_id)
+        } else {
+            return Err("Missing channel_id or chat_id".to_string());
+        };
+
+        // Rooms are joined based on access control, so membership gates who may signal typing
+        if !session.rooms.contains(&room) {
+            return Err(format!("Session is not a member of room {}", room));
+        }
+
+        let typing = data.get("typing").and_then(|t| t.as_bool()).unwrap_or(true);
+        let should_broadcast = if typing {
+            self.presence_manager
+                .start_typing(user_id, user_name, &room)
+                .await
+        } else {
+            self.presence_manager.stop_typing(user_id, &room).await;
+            true
+        };
+        self.metrics.record_event_received("typing").await;
+
+        if should_broadcast {
+            let user_data = serde_json::json!({ "id": user_id, "name": user_name });
+            if let Some((event, payload)) = typing_event(&room, user_data, typing) {
+                self.broadcast_to_room(&room, event, payload, Some(sid))
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Expire typing indicators that haven't been refreshed and broadcast that typing stopped
+    pub async fn expire_typing_indicators(&self) {
+        for indicator in self.presence_manager.cleanup_typing_indicators().await {
+            let user_data = serde_json::json!({
+                "id": indicator.user_id,
+                "name": indicator.user_name,
+            });
+            if let Some((event, payload)) = typing_event(&indicator.room_id, user_data, false) {
+                let _ = self
+                    .broadcast_to_room(&indicator.room_id, event, payload, None)
+                    .await;
+            }
+        }
+    }
+
+    /// Count a newly authenticated session in presence and, if the user just came online,
+    /// announce it to the channels the session has joined
+    pub async fn mark_user_online(&self, sid: &str, user_id: &str) {
+        self.last_seen
+            .connected(user_id, current_timestamp_seconds())
+            .await;
+        if self.presence_manager.user_online(user_id).await {
+            let rooms: Vec<String> = self
+                .manager
+                .get_session(sid)
+                .await
+                .map(|s| s.rooms.into_iter().collect())
+                .unwrap_or_default();
+            self.broadcast_presence(user_id, &rooms, "online", Some(sid))
+                .await;
+        }
+    }
+
+    /// Broadcast a user's online/offline status to the channel rooms they belong to
+    async fn broadcast_presence(
+        &self,
+        user_id: &str,
+        rooms: &[String],
+        status: &str,
+        exclude_sid: Option<&str>,
+    ) {
+        let channel_rooms = rooms
+            .iter()
+            .filter_map(|room| room.strip_prefix("channel:").map(|id| (room, id)));
+
+        for (room, channel_id) in channel_rooms {
+            let payload = serde_json::json!({
+                "channel_id": channel_id,
+                "message_id": null,
+                "data": {
+                    "type": "presence",
+                    "data": { "user_id": user_id, "status": status },
+                },
+                "user": { "id": user_id },
+            });
+            let _ = self
+                .broadcast_to_room(room, "channel-events", payload, exclude_sid)
+                .await;
+        }
+    }
+
+    /// Record a client-initiated Engine.IO ping
+    pub async fn handle_engine_ping(&self, sid: &str) {
+        self.manager.update_ping(sid).await;
+        self.health_monitor.record_heartbeat(sid, None).await;
+        self.record_activity(sid).await;
+    }
+
+    /// Record the client's answer to a server ping
+    pub async fn handle_engine_pong(&self, sid: &str) {
+        self.manager.update_ping(sid).await;
+        self.health_monitor.record_pong(sid).await;
+        self.record_activity(sid).await;
+    }
+
+    /// Note that the session's user is still around, at most once a minute per user
+    async fn record_activity(&self, sid: &str) {
+        if let Some(user_id) = self
+            .manager
+            .get_session(sid)
+            .await
+            .and_then(|s| s.user_id())
+        {
+            self.last_seen
+                .touch(&user_id, current_timestamp_seconds())
+                .await;
+        }
+    }
+
+    /// Write the last-seen times collected since the previous flush to the database
+    pub async fn flush_last_seen(&self) {
+        let pending = self.last_seen.take_pending().await;
+        if pending.is_empty() {
+            return;
+        }
+        let user_service = crate::services::UserService::new(&self.db);
+        match user_service.update_users_last_active(&pending).await {
+            Ok(()) => tracing::debug!("Saved last-seen times of {} user(s)", pending.len()),
+            Err(e) => {
+                tracing::warn!("Failed to save last-seen times: {}", e);
+                self.last_seen.restore_pending(pending).await;
+            }
+        }
+    }
+
+    /// IDs of users with a session on this server or, with Redis, on any live server
+    pub async fn active_user_ids(&self) -> Vec<String> {
+        let mut user_ids = self.presence_manager.get_connected_user_ids().await;
+        if let Some(redis) = &self.redis_adapter {
+            user_ids.extend(redis.remote_user_ids().await);
+        }
+        user_ids.sort();
+        user_ids.dedup();
+        user_ids
+    }
+
+    /// Send an Engine.IO ping to a websocket session; false once it is no longer connected
+    pub async fn send_ping(&self, sid: &str) -> bool {
+        let connections = self.connections.read().await;
+        let sent = match connections.get(sid) {
+            Some(sender) => self
+                .queue_frame(sid, sender, EnginePacket::ping().encode().into())
+                .await
+                .is_ok(),
+            None => false,
+        };
+        drop(connections);
+
+        if sent {
+            self.health_monitor.record_ping_sent(sid).await;
+        }
+        sent
+    }
+
+    /// Count an incoming event against the session's rate limits. Events over a limit
+    /// are dropped, and the client is sent a `server:rate_limited` notice when they
+    /// start to be. Admin sessions are not limited.
+    pub async fn allow_event(&self, sid: &str, event: &str) -> bool {
+        let is_admin = self
+            .manager
+            .get_session(sid)
+            .await
+            .and_then(|session| session.user)
+            .is_some_and(|user| user.get("role").and_then(|r| r.as_str()) == Some("admin"));
+        if is_admin {
+            return true;
+        }
+
+        match self.rate_limiter.check_event(sid, event).await {
+            Ok(()) => true,
+            Err(e) => {
+                self.metrics.record_event_dropped(event).await;
+                if let RateLimitError::EventLimitExceeded {
+                    retry_after,
+                    repeated: false,
+                    ..
+                } = &e
+                {
+                    tracing::warn!("{}", e);
+                    let retry_after_ms = u64::try_from(retry_after.as_millis()).unwrap_or(u64::MAX);
+                    let notice = serde_json::json!({
+                        "event": event,
+                        "retry_after_ms": retry_after_ms,
+                    });
+                    let _ = self
+                        .emit_to_session(sid, "server:rate_limited", notice)
+                        .await;
+                }
+                false
+            }
+        }
+    }
+
+    /// Frames written to the websocket of `sid`, which no longer count as queued
+    pub async fn frames_written(&self, sid: &str, count: usize) {
+        self.rate_limiter.decrement_queue(sid, count).await;
+    }
+
+    /// Wait while the outbound queue of `sid` is full, so a client that doesn't keep up
+    /// with what it is sent stops being read from. False if the queue is still full
+    /// after the heartbeat timeout.
+    pub async fn wait_for_outbound_capacity(&self, sid: &str) -> bool {
+        let deadline = Instant::now() + Duration::from_millis(self.manager.ping_timeout());
+        while self.rate_limiter.check_queue_size(sid).await.is_err() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        true
+    }
+
+    /// Update missed-heartbeat counts and disconnect sessions silent for longer than the
+    /// heartbeat timeout, returning how many were dropped
+    pub async fn disconnect_unresponsive_sessions(&self) -> usize {
+        self.health_monitor.check_heartbeats().await;
+
+        let timed_out = self.health_monitor.timed_out_sessions().await;
+        for sid in &timed_out {
+            tracing::warn!("Disconnecting session {} after heartbeat timeout", sid);
+            self.metrics.record_heartbeat_timeout().await;
+
+            // Ask the client to close; the websocket is dropped once its sender goes away
+            if let Some(sender) = self.connections.read().await.get(sid) {
+                let _ = sender.send(EnginePacket::close().encode().into());
+            }
+            self.unregister_connection(sid).await;
+            self.manager.remove_session(sid).await;
+        }
+        timed_out.len()
+    }
+
+    /// Forcibly drop a session, returning false if there is no such session here
+    ///
+    /// WebSocket clients get a Socket.IO DISCONNECT first, which they treat as a server
+    /// decision and don't reconnect from on their own. Polling clients find their session
+    /// gone on the next request.
+    pub async fn disconnect_session(&self, sid: &str) -> bool {
+        if self.manager.get_session(sid).await.is_none() {
+            return false;
+        }
+        tracing::warn!("Disconnecting session {} on admin request", sid);
+
+        let parser = self.parser_of(sid).await;
+        if let Some(sender) = self.connections.read().await.get(sid) {
+            for frame in SocketPacket::disconnect("/").into_frames_for(parser) {
+                let _ = sender.send(frame);
+            }
+            let _ = sender.send(EnginePacket::close().encode().into());
+        }
+        self.unregister_connection(sid).await;
+        self.manager.remove_session(sid).await;
+        true
+    }
+
+    /// "websocket" for sessions with an open WebSocket, "polling" otherwise
+    pub async fn transport_of(&self, sid: &str) -> &'static str {
+        if self.connections.read().await.contains_key(sid) {
+            "websocket"
+        } else {
+            "polling"
+        }
+    }
+
+    /// Send every connected session a `server:shutdown` notice and close its transport
+    ///
+    /// Clients treat the close as a dropped transport and start reconnecting right away,
+    /// reaching the restarted server as soon as it is up. Returns the number notified.
+    pub async fn notify_shutdown(&self) -> usize {
+        let notice = SocketPacket::event(
+            "/",
+            "server:shutdown",
+            serde_json::json!({ "reconnect": true }),
+        );
+        let parsers = self.parsers.read().await.clone();
+        let connections = self.connections.read().await;
+        for (sid, sender) in connections.iter() {
+            let parser = parsers.get(sid).copied().unwrap_or_default();
+            for frame in notice.clone().into_frames_for(parser) {
+                let _ = sender.send(frame);
+            }
+            let _ = sender.send(EnginePacket::close().encode().into());
+        }
+        connections.len()
+    }
+
+    /// Remove sessions that stopped pinging, updating presence and last-seen times as if
+    /// they disconnected
+    pub async fn cleanup_stale_sessions(&self, timeout_seconds: i64) {
+        for sid in self.manager.get_stale_sessions(timeout_seconds).await {
+            tracing::warn!("Removing stale session: {}", sid);
+            self.unregister_connection(&sid).await;
+            self.manager.remove_session(&sid).await;
+        }
+    }
+
     /// Get presence for multiple users
     pub async fn handle_get_presences(
         &self,
@@ -897,6 +1976,38 @@ impl EventHandler {
     }
 }
 
+/// Build the typing notification for a `channel:{id}` or `chat:{id}` room
+fn typing_event(room: &str, user: JsonValue, typing: bool) -> Option<(&'static str, JsonValue)> {
+    let data = serde_json::json!({
+        "type": "typing",
+        "data": { "typing": typing },
+    });
+
+    if let Some(channel_id) = room.strip_prefix("channel:") {
+        Some((
+            "channel-events",
+            serde_json::json!({
+                "channel_id": channel_id,
+                "message_id": null,
+                "data": data,
+                "user": user,
+            }),
+        ))
+    } else {
+        room.strip_prefix("chat:").map(|chat_id| {
+            (
+                "chat-events",
+                serde_json::json!({
+                    "chat_id": chat_id,
+                    "message_id": null,
+                    "data": data,
+                    "user": user,
+                }),
+            )
+        })
+    }
+}
+
 /// HTTP endpoint for emitting events from Rust backend
 #[derive(Debug, Deserialize)]
 pub struct EmitRequest {
@@ -907,17 +2018,101 @@ pub struct EmitRequest {
     pub data: JsonValue,
 }
 
+/// Events users other than admins may send through the emit endpoint
+///
+/// Channel events carry the sender the server attributed them to, so only the
+/// server itself (admins or the service key) may emit them.
+const USER_EMIT_EVENTS: &[&str] = &["chat-events"];
+
 #[derive(Debug, Serialize)]
 pub struct EmitResponse {
     pub status: String,
     pub sent: usize,
 }
 
+impl EventHandler {
+    /// Whether `user` may emit to the target of `req`
+    ///
+    /// Admins may emit anything anywhere. Other users may only send
+    /// [`USER_EMIT_EVENTS`], to themselves, their own sessions, their own `user:`
+    /// room and their own chats.
+    pub async fn can_emit(&self, user: &crate::models::User, req: &EmitRequest) -> bool {
+        if user.role == "admin" {
+            return true;
+        }
+        if !USER_EMIT_EVENTS.contains(&req.event.as_str()) {
+            return false;
+        }
+
+        if let Some(user_id) = &req.user_id {
+            return *user_id == user.id;
+        }
+
+        if let Some(session_id) = &req.session_id {
+            return match self.manager.get_session(session_id).await {
+                Some(session) => {
+                    session
+                        .user
+                        .as_ref()
+                        .and_then(|u| u.get("id"))
+                        .and_then(|id| id.as_str())
+                        == Some(user.id.as_str())
+                }
+                None => false,
+            };
+        }
+
+        // Chat events only ever go to the user's own chats and rooms; in a shared
+        // channel they would pass for messages and completions from someone else
+        if let Some(room) = &req.room {
+            if let Some(user_id) = room.strip_prefix("user:") {
+                return user_id == user.id;
+            }
+
+            if let Some(chat_id) = room.strip_prefix("chat:") {
+                use crate::services::chat::ChatService;
+
+                return matches!(
+                    ChatService::new(&self.db)
+                        .get_chat_by_id_and_user_id(chat_id, &user.id)
+                        .await,
+                    Ok(Some(_))
+                );
+            }
+        }
+
+        false
+    }
+}
+
 /// Handle emit endpoint
+///
+/// `user` is `None` for server-to-server callers holding the service key,
+/// which may emit anything anywhere.
 pub async fn handle_emit_request(
     event_handler: web::Data<EventHandler>,
+    user: Option<&crate::models::User>,
     req: web::Json<EmitRequest>,
 ) -> Result<web::Json<EmitResponse>, actix_web::Error> {
+    if req.user_id.is_none() && req.session_id.is_none() && req.room.is_none() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Must specify user_id, session_id, or room",
+        ));
+    }
+    if let Some(user) = user {
+        if !event_handler.can_emit(user, &req).await {
+            tracing::warn!(
+                "User {} may not emit {} to {:?}",
+                user.id,
+                req.event,
+                req.room
+            );
+            return Err(actix_web::error::ErrorForbidden(
+                "Not allowed to emit this event to this target",
+            ));
+        }
+    }
+
     let sent = if let Some(user_id) = &req.user_id {
         // Emit to user
         event_handler
@@ -956,17 +2151,414 @@ pub struct HealthResponse {
     pub sessions: usize,
     pub users: usize,
     pub rooms: usize,
+    pub heartbeat: HeartbeatReport,
+    pub latency: LatencyPercentiles,
 }
 
 pub async fn handle_health_check(
     event_handler: web::Data<EventHandler>,
 ) -> Result<web::Json<HealthResponse>, actix_web::Error> {
     let stats = event_handler.manager().get_stats().await;
+    let heartbeat_timeouts = event_handler
+        .metrics()
+        .get_connection_metrics()
+        .await
+        .heartbeat_timeouts;
+    let heartbeat = event_handler
+        .health_monitor()
+        .heartbeat_report(heartbeat_timeouts)
+        .await;
+    let latency = event_handler.health_monitor().latency_percentiles().await;
 
     Ok(web::Json(HealthResponse {
         status: "ok".to_string(),
         sessions: *stats.get("sessions").unwrap_or(&0),
         users: *stats.get("users").unwrap_or(&0),
         rooms: *stats.get("rooms").unwrap_or(&0),
+        heartbeat,
+        latency,
+    }))
+}
+
+/// Per-session heartbeat details (admin only, since polling sessions are addressed by sid)
+pub async fn handle_heartbeat_sessions(
+    event_handler: web::Data<EventHandler>,
+) -> Result<web::Json<Vec<ConnectionHealth>>, actix_web::Error> {
+    let mut sessions = event_handler
+        .health_monitor()
+        .get_all_connection_health()
+        .await;
+    sessions.sort_by_key(|health| health.last_heartbeat);
+    Ok(web::Json(sessions))
+}
+
+/// A room and how many sessions on this server are in it
+#[derive(Debug, Serialize)]
+pub struct RoomInfo {
+    pub room: String,
+    pub members: usize,
+}
+
+/// Admin overview of the Socket.IO server
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    pub sessions: usize,
+    pub websocket_sessions: usize,
+    pub users: usize,
+    pub rooms: Vec<RoomInfo>,
+    pub active_chat_streams: usize,
+    pub uptime_secs: u64,
+    pub event_rates: EventRates,
+    pub latency: LatencyPercentiles,
+}
+
+pub async fn handle_stats(
+    event_handler: web::Data<EventHandler>,
+) -> Result<web::Json<StatsResponse>, actix_web::Error> {
+    let stats = event_handler.manager().get_stats().await;
+    let rooms = event_handler
+        .manager()
+        .get_room_sizes()
+        .await
+        .into_iter()
+        .map(|(room, members)| RoomInfo { room, members })
+        .collect();
+
+    Ok(web::Json(StatsResponse {
+        sessions: *stats.get("sessions").unwrap_or(&0),
+        websocket_sessions: event_handler.connections.read().await.len(),
+        users: *stats.get("users").unwrap_or(&0),
+        rooms,
+        active_chat_streams: event_handler.chat_streams.active_count().await,
+        uptime_secs: event_handler.metrics().uptime().as_secs(),
+        event_rates: event_handler.metrics().get_event_rates().await,
+        latency: event_handler.health_monitor().latency_percentiles().await,
     }))
 }
+
+/// One of a user's sessions, as listed to admins
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    pub sid: String,
+    pub transport: &'static str,
+    pub connected_at: i64,
+    pub last_ping: i64,
+    pub latency_ms: Option<f64>,
+    pub status: Option<HealthStatus>,
+    pub rooms: Vec<String>,
+}
+
+pub async fn handle_user_sessions(
+    event_handler: web::Data<EventHandler>,
+    user_id: &str,
+) -> Result<web::Json<Vec<SessionInfo>>, actix_web::Error> {
+    let mut sessions = Vec::new();
+    for sid in event_handler.manager().get_user_sessions(user_id).await {
+        let session = match event_handler.manager().get_session(&sid).await {
+            Some(session) => session,
+            None => continue,
+        };
+        let health = event_handler
+            .health_monitor()
+            .get_connection_health(&sid)
+            .await;
+        let mut rooms: Vec<String> = session.rooms.into_iter().collect();
+        rooms.sort();
+
+        sessions.push(SessionInfo {
+            transport: event_handler.transport_of(&sid).await,
+            connected_at: session.connected_at,
+            last_ping: session.last_ping,
+            latency_ms: health.as_ref().and_then(|h| h.latency_ms),
+            status: health.map(|h| h.status),
+            rooms,
+            sid,
+        });
+    }
+    sessions.sort_by_key(|session| session.connected_at);
+    Ok(web::Json(sessions))
+}
+
+pub async fn handle_disconnect_session(
+    event_handler: web::Data<EventHandler>,
+    sid: &str,
+) -> Result<web::Json<serde_json::Value>, actix_web::Error> {
+    if !event_handler.disconnect_session(sid).await {
+        return Err(actix_web::error::ErrorNotFound("Session not found"));
+    }
+    Ok(web::Json(serde_json::json!({ "status": "ok", "sid": sid })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::chat::CreateChatRequest;
+    use crate::services::{channel::ChannelService, chat::ChatService};
+    use crate::socketio::{
+        HealthConfig, HealthMonitor, PresenceConfig, PresenceManager, RateLimitConfig, RateLimiter,
+        RecoveryConfig, RecoveryManager, SocketIOMetrics,
+    };
+    use crate::test_utils;
+
+    async fn test_handler(dir: &tempfile::TempDir) -> EventHandler {
+        let db = test_utils::test_db(dir).await;
+        EventHandler::new(
+            SocketIOManager::new(),
+            String::new(),
+            YDocManager::new(None),
+            None,
+            SocketIOMetrics::new(),
+            Arc::new(RateLimiter::new(RateLimitConfig::default())),
+            Arc::new(PresenceManager::new(PresenceConfig::default())),
+            Arc::new(RecoveryManager::new(None, RecoveryConfig::default())),
+            HealthMonitor::new(HealthConfig::default()),
+            db,
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_outbound_queue_drains_to_zero() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = Arc::new(test_handler(&dir).await);
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        handler.register_connection("sid-1", tx).await;
+        handler.manager().create_session("sid-1").await;
+
+        // A writer that takes frames off the queue as soon as they arrive
+        let written = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let writer = {
+            let handler = handler.clone();
+            let written = written.clone();
+            tokio::spawn(async move {
+                while rx.recv().await.is_some() {
+                    handler.frames_written("sid-1", 1).await;
+                    written.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                }
+            })
+        };
+
+        for i in 0..500 {
+            handler
+                .emit_to_session("sid-1", "chat-events", serde_json::json!({"i": i}))
+                .await
+                .unwrap();
+            if i % 50 == 0 {
+                assert!(handler.send_ping("sid-1").await);
+            }
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while written.load(std::sync::atomic::Ordering::SeqCst) < 510 {
+            assert!(Instant::now() < deadline, "frames were not written");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(handler.rate_limiter.queue_size("sid-1").await, 0);
+
+        handler.unregister_connection("sid-1").await;
+        writer.abort();
+    }
+
+    #[tokio::test]
+    async fn test_requesting_session_keeps_chat_events_after_another_joins() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = test_handler(&dir).await;
+        let mut receivers = Vec::new();
+        for sid in ["sid-origin", "sid-other"] {
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+            handler.register_connection(sid, tx).await;
+            handler.manager().create_session(sid).await;
+            handler
+                .manager()
+                .set_session_user(sid, serde_json::json!({"id": "user-1"}))
+                .await
+                .unwrap();
+            receivers.push(rx);
+        }
+        let delta = |content: &str| {
+            serde_json::json!({
+                "chat_id": "chat-1",
+                "message_id": "msg-1",
+                "data": {"type": "chat:completion", "data": {"content": content}},
+            })
+        };
+
+        // Nobody shows the chat yet, so all of the user's sessions get the event
+        handler
+            .emit_chat_event("user-1", Some("chat-1"), Some("sid-origin"), delta("Hel"))
+            .await
+            .unwrap();
+        for rx in receivers.iter_mut() {
+            assert!(rx.try_recv().is_ok());
+        }
+
+        // Another session opens the chat mid-stream
+        handler
+            .handle_chat_join("sid-other", serde_json::json!({"chat_id": "chat-1"}))
+            .await
+            .unwrap();
+        while receivers[1].try_recv().is_ok() {}
+
+        handler
+            .emit_chat_event("user-1", Some("chat-1"), Some("sid-origin"), delta("lo"))
+            .await
+            .unwrap();
+        for rx in receivers.iter_mut() {
+            assert!(rx.try_recv().is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_document_state_is_sent_as_an_attachment() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = test_handler(&dir).await;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        handler.register_connection("sid-1", tx).await;
+        handler.manager().create_session("sid-1").await;
+
+        handler
+            .send_document_state("sid-1", "doc-1", vec![1, 2, 3], vec!["sid-1".to_string()])
+            .await
+            .unwrap();
+
+        match rx.try_recv().unwrap() {
+            WsFrame::Text(text) => {
+                assert!(text.starts_with("451-"), "not a binary event: {}", text);
+                assert!(text.contains(r#""state":{"_placeholder":true,"num":0}"#));
+            }
+            other => panic!("expected the packet text first, got {:?}", other),
+        }
+        match rx.try_recv().unwrap() {
+            WsFrame::Binary(bytes) => assert_eq!(bytes.as_ref(), [1, 2, 3]),
+            other => panic!("expected the state attachment, got {:?}", other),
+        }
+    }
+
+    fn emit_to(target: &str, value: &str) -> EmitRequest {
+        let mut req = EmitRequest {
+            user_id: None,
+            session_id: None,
+            room: None,
+            event: "chat-events".to_string(),
+            data: serde_json::json!({}),
+        };
+        match target {
+            "user" => req.user_id = Some(value.to_string()),
+            "session" => req.session_id = Some(value.to_string()),
+            _ => req.room = Some(value.to_string()),
+        }
+        req
+    }
+
+    #[tokio::test]
+    async fn test_can_emit_only_to_own_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = test_handler(&dir).await;
+        let admin = test_utils::create_user(&handler.db, "admin-1", "admin").await;
+        let user = test_utils::create_user(&handler.db, "user-1", "user").await;
+        test_utils::create_user(&handler.db, "user-2", "user").await;
+
+        // Users and sessions
+        handler.manager().create_session("sid-1").await;
+        handler
+            .manager()
+            .set_session_user("sid-1", serde_json::json!({"id": "user-1"}))
+            .await
+            .unwrap();
+        handler.manager().create_session("sid-2").await;
+        handler
+            .manager()
+            .set_session_user("sid-2", serde_json::json!({"id": "user-2"}))
+            .await
+            .unwrap();
+        assert!(handler.can_emit(&user, &emit_to("user", "user-1")).await);
+        assert!(!handler.can_emit(&user, &emit_to("user", "user-2")).await);
+        assert!(handler.can_emit(&user, &emit_to("session", "sid-1")).await);
+        assert!(!handler.can_emit(&user, &emit_to("session", "sid-2")).await);
+        assert!(
+            !handler
+                .can_emit(&user, &emit_to("session", "unknown"))
+                .await
+        );
+
+        // Not even to channels they own or can write to, where others would see the events
+        let channels = ChannelService::new(&handler.db);
+        let write_for = |user_id: &str| {
+            Some(serde_json::json!({"write": {"group_ids": [], "user_ids": [user_id]}}))
+        };
+        for (id, owner, access_control) in [
+            ("own", "user-1", write_for("user-2")),
+            ("shared", "user-2", write_for("user-1")),
+            ("private", "user-2", write_for("user-2")),
+        ] {
+            channels
+                .create_channel(id, owner, id, None, None, None, None, access_control)
+                .await
+                .unwrap();
+        }
+        for channel in ["own", "shared", "private", "missing"] {
+            let room = format!("channel:{}", channel);
+            assert!(
+                !handler.can_emit(&user, &emit_to("room", &room)).await,
+                "{}",
+                room
+            );
+        }
+
+        // Chats
+        for (id, owner) in [("chat-1", "user-1"), ("chat-2", "user-2")] {
+            ChatService::new(&handler.db)
+                .create_chat(
+                    owner,
+                    CreateChatRequest {
+                        id: id.to_string(),
+                        title: None,
+                        chat: serde_json::json!({}),
+                        folder_id: None,
+                        archived: None,
+                        pinned: None,
+                        share_id: None,
+                        meta: None,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+        assert!(
+            handler
+                .can_emit(&user, &emit_to("room", "chat:chat-1"))
+                .await
+        );
+        assert!(
+            !handler
+                .can_emit(&user, &emit_to("room", "chat:chat-2"))
+                .await
+        );
+
+        // Their own user room, but nobody else's
+        assert!(
+            handler
+                .can_emit(&user, &emit_to("room", "user:user-1"))
+                .await
+        );
+        assert!(
+            !handler
+                .can_emit(&user, &emit_to("room", "user:user-2"))
+                .await
+        );
+
+        // Only admins may send channel events, even to a channel the user owns
+        let mut channel_event = emit_to("room", "channel:own");
+        channel_event.event = "channel-events".to_string();
+        assert!(!handler.can_emit(&user, &channel_event).await);
+        assert!(handler.can_emit(&admin, &channel_event).await);
+        for (target, value) in [
+            ("user", "user-2"),
+            ("session", "sid-2"),
+            ("room", "channel:private"),
+            ("room", "chat:chat-2"),
+            ("room", "user:user-2"),
+        ] {
+            assert!(handler.can_emit(&admin, &emit_to(target, value)).await);
+        }
+    }
+}
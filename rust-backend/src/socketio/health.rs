@@ -35,6 +35,9 @@ pub struct ConnectionHealth {
     pub connection_quality: ConnectionQuality,
     #[serde(skip, default = "default_instant")]
     pub last_heartbeat_instant: Instant,
+    /// When the last server ping was sent, until the client's pong arrives
+    #[serde(skip)]
+    pub ping_sent_at: Option<Instant>,
 }
 
 /// Connection quality rating
@@ -58,6 +61,7 @@ impl ConnectionHealth {
             packet_loss: 0.0,
             connection_quality: ConnectionQuality::Excellent,
             last_heartbeat_instant: Instant::now(),
+            ping_sent_at: None,
         }
     }
 
@@ -145,8 +149,10 @@ impl ComponentHealth {
 /// Health monitor configuration
 #[derive(Debug, Clone)]
 pub struct HealthConfig {
-    /// Heartbeat interval (expected time between heartbeats)
+    /// Heartbeat interval (expected time between heartbeats, the Engine.IO `pingInterval`)
     pub heartbeat_interval: Duration,
+    /// How long a client may take to answer a ping (the Engine.IO `pingTimeout`)
+    pub ping_timeout: Duration,
     /// Maximum missed heartbeats before marking unhealthy
     pub max_missed_heartbeats: u32,
     /// Cleanup interval for stale connections
@@ -159,6 +165,7 @@ impl Default for HealthConfig {
     fn default() -> Self {
         Self {
             heartbeat_interval: Duration::from_secs(25),
+            ping_timeout: Duration::from_secs(20),
             max_missed_heartbeats: 3,
             cleanup_interval: Duration::from_secs(60),
             connection_timeout: Duration::from_secs(90),
@@ -166,7 +173,16 @@ impl Default for HealthConfig {
     }
 }
 
+impl HealthConfig {
+    /// Silence after which a session is disconnected: one ping interval plus the time
+    /// allowed for the pong, as Engine.IO clients do on their side
+    pub fn heartbeat_timeout(&self) -> Duration {
+        self.heartbeat_interval + self.ping_timeout
+    }
+}
+
 /// Health monitor for Socket.IO connections
+#[derive(Clone)]
 pub struct HealthMonitor {
     /// Per-connection health tracking
     connections: Arc<RwLock<HashMap<String, ConnectionHealth>>>,
@@ -212,45 +228,85 @@ impl HealthMonitor {
         tracing::debug!("Removed connection health monitoring for {}", session_id);
     }
 
-    /// Record a heartbeat
+    pub fn config(&self) -> &HealthConfig {
+        &self.config
+    }
+
+    /// Record a heartbeat, starting to monitor the session if it wasn't yet (polling
+    /// sessions are only seen through their heartbeats)
     pub async fn record_heartbeat(&self, session_id: &str, latency_ms: Option<f64>) {
         let mut connections = self.connections.write().await;
 
-        if let Some(health) = connections.get_mut(session_id) {
-            health.last_heartbeat = now_timestamp();
-            health.last_heartbeat_instant = Instant::now();
-            health.missed_heartbeats = 0;
-
-            if let Some(latency) = latency_ms {
-                health.latency_ms = Some(latency);
-
-                // Update rolling average
-                drop(connections); // Release write lock
-                let mut samples = self.latency_samples.write().await;
-                let session_samples = samples
-                    .entry(session_id.to_string())
-                    .or_insert_with(Vec::new);
-                session_samples.push(latency);
-
-                // Keep only last 100 samples
-                if session_samples.len() > 100 {
-                    session_samples.remove(0);
-                }
+        let health = connections
+            .entry(session_id.to_string())
+            .or_insert_with(|| ConnectionHealth::new(session_id.to_string()));
+        health.last_heartbeat = now_timestamp();
+        health.last_heartbeat_instant = Instant::now();
+        health.missed_heartbeats = 0;
+        health.update_quality();
 
-                let avg_latency: f64 =
-                    session_samples.iter().sum::<f64>() / session_samples.len() as f64;
+        if let Some(latency) = latency_ms {
+            health.latency_ms = Some(latency);
 
-                // Reacquire write lock
-                drop(samples);
-                let mut connections = self.connections.write().await;
-                if let Some(health) = connections.get_mut(session_id) {
-                    health.latency_ms = Some(avg_latency);
-                    health.update_quality();
-                }
+            // Update rolling average
+            drop(connections); // Release write lock
+            let mut samples = self.latency_samples.write().await;
+            let session_samples = samples
+                .entry(session_id.to_string())
+                .or_insert_with(Vec::new);
+            session_samples.push(latency);
+
+            // Keep only last 100 samples
+            if session_samples.len() > 100 {
+                session_samples.remove(0);
+            }
+
+            let avg_latency: f64 =
+                session_samples.iter().sum::<f64>() / session_samples.len() as f64;
+
+            // Reacquire write lock
+            drop(samples);
+            let mut connections = self.connections.write().await;
+            if let Some(health) = connections.get_mut(session_id) {
+                health.latency_ms = Some(avg_latency);
+                health.update_quality();
             }
         }
     }
 
+    /// Note that a server ping was sent so the pong's round trip can be measured
+    pub async fn record_ping_sent(&self, session_id: &str) {
+        let mut connections = self.connections.write().await;
+        if let Some(health) = connections.get_mut(session_id) {
+            health.ping_sent_at = Some(Instant::now());
+        }
+    }
+
+    /// Record a client pong, using the time since the last ping as its latency
+    pub async fn record_pong(&self, session_id: &str) {
+        let latency_ms = {
+            let mut connections = self.connections.write().await;
+            connections
+                .get_mut(session_id)
+                .and_then(|health| health.ping_sent_at.take())
+                .map(|sent| sent.elapsed().as_secs_f64() * 1000.0)
+        };
+        self.record_heartbeat(session_id, latency_ms).await;
+    }
+
+    /// Sessions that have been silent for longer than the heartbeat timeout
+    pub async fn timed_out_sessions(&self) -> Vec<String> {
+        let connections = self.connections.read().await;
+        let timeout = self.config.heartbeat_timeout();
+        let now = Instant::now();
+
+        connections
+            .iter()
+            .filter(|(_, health)| now.duration_since(health.last_heartbeat_instant) > timeout)
+            .map(|(sid, _)| sid.clone())
+            .collect()
+    }
+
     /// Check for missed heartbeats
     pub async fn check_heartbeats(&self) {
         let mut connections = self.connections.write().await;
@@ -355,6 +411,44 @@ impl HealthMonitor {
         }
     }
 
+    /// Heartbeat configuration with current session counts; `heartbeat_timeouts` comes
+    /// from the Socket.IO metrics
+    pub async fn heartbeat_report(&self, heartbeat_timeouts: u64) -> HeartbeatReport {
+        let stats = self.get_stats().await;
+        HeartbeatReport {
+            ping_interval_ms: self.config.heartbeat_interval.as_millis() as u64,
+            ping_timeout_ms: self.config.ping_timeout.as_millis() as u64,
+            heartbeat_timeout_ms: self.config.heartbeat_timeout().as_millis() as u64,
+            max_missed_heartbeats: self.config.max_missed_heartbeats,
+            monitored_sessions: stats.total_connections,
+            unhealthy_sessions: stats.unhealthy_connections,
+            heartbeat_timeouts,
+        }
+    }
+
+    /// Ping round-trip percentiles over the recent samples of every monitored session
+    pub async fn latency_percentiles(&self) -> LatencyPercentiles {
+        let samples = self.latency_samples.read().await;
+        let mut sorted: Vec<f64> = samples.values().flatten().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let percentile = |p: f64| -> f64 {
+            if sorted.is_empty() {
+                return 0.0;
+            }
+            let idx = ((sorted.len() as f64 * p) as usize).min(sorted.len() - 1);
+            sorted[idx]
+        };
+
+        LatencyPercentiles {
+            p50_ms: percentile(0.50),
+            p90_ms: percentile(0.90),
+            p99_ms: percentile(0.99),
+            max_ms: sorted.last().copied().unwrap_or(0.0),
+            samples: sorted.len(),
+        }
+    }
+
     /// Get health statistics
     pub async fn get_stats(&self) -> HealthStats {
         let connections = self.connections.read().await;
@@ -397,6 +491,29 @@ impl Default for HealthMonitor {
     }
 }
 
+/// Heartbeat settings and counts reported by the Socket.IO health endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct HeartbeatReport {
+    pub ping_interval_ms: u64,
+    pub ping_timeout_ms: u64,
+    pub heartbeat_timeout_ms: u64,
+    pub max_missed_heartbeats: u32,
+    pub monitored_sessions: usize,
+    pub unhealthy_sessions: usize,
+    /// Sessions disconnected since startup for exceeding the heartbeat timeout
+    pub heartbeat_timeouts: u64,
+}
+
+/// Ping round-trip times across sessions, in milliseconds
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyPercentiles {
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub samples: usize,
+}
+
 /// Health statistics
 #[derive(Debug, Clone, Serialize)]
 pub struct HealthStats {
@@ -454,6 +571,66 @@ mod tests {
         assert!(health.missed_heartbeats > 0);
     }
 
+    #[tokio::test]
+    async fn test_pong_latency_and_timeouts() {
+        let config = HealthConfig {
+            heartbeat_interval: Duration::from_millis(50),
+            ping_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let monitor = HealthMonitor::new(config);
+        monitor.register_connection("session-1").await;
+
+        monitor.record_ping_sent("session-1").await;
+        monitor.record_pong("session-1").await;
+        let health = monitor.get_connection_health("session-1").await.unwrap();
+        assert!(health.latency_ms.is_some());
+        assert!(health.ping_sent_at.is_none());
+
+        // Heartbeats from unregistered (polling) sessions start monitoring them
+        monitor.record_heartbeat("polling-1", None).await;
+        assert!(monitor.get_connection_health("polling-1").await.is_some());
+
+        assert!(monitor.timed_out_sessions().await.is_empty());
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let mut timed_out = monitor.timed_out_sessions().await;
+        timed_out.sort();
+        assert_eq!(timed_out, vec!["polling-1", "session-1"]);
+
+        let report = monitor.heartbeat_report(2).await;
+        assert_eq!(report.heartbeat_timeout_ms, 100);
+        assert_eq!(report.monitored_sessions, 2);
+        assert_eq!(report.heartbeat_timeouts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_latency_percentiles() {
+        let monitor = HealthMonitor::default();
+        assert_eq!(monitor.latency_percentiles().await.samples, 0);
+
+        for latency in 1..=90 {
+            monitor
+                .record_heartbeat("session-1", Some(latency as f64))
+                .await;
+        }
+        for latency in 91..=100 {
+            monitor
+                .record_heartbeat("session-2", Some(latency as f64))
+                .await;
+        }
+
+        let latency = monitor.latency_percentiles().await;
+        assert_eq!(latency.samples, 100);
+        assert_eq!(latency.p50_ms, 51.0);
+        assert_eq!(latency.p90_ms, 91.0);
+        assert_eq!(latency.p99_ms, 100.0);
+        assert_eq!(latency.max_ms, 100.0);
+
+        // Samples go with the session
+        monitor.remove_connection("session-2").await;
+        assert_eq!(monitor.latency_percentiles().await.max_ms, 90.0);
+    }
+
     #[tokio::test]
     async fn test_connection_quality() {
         let mut health = ConnectionHealth::new("test".to_string());
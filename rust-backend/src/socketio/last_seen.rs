@@ -0,0 +1,141 @@
+/// Last Seen Tracking for Socket.IO
+///
+/// Collects when users were last active on a socket so `user.last_active_at` can be
+/// written in periodic batches instead of once per ping
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+#[derive(Default)]
+struct LastSeenState {
+    /// Last time recorded for each connected user, for throttling
+    recorded: HashMap<String, i64>,
+    /// Times not written to the database yet
+    pending: HashMap<String, i64>,
+}
+
+/// Throttled, batched last-seen times of socket users
+pub struct LastSeenTracker {
+    interval: i64,
+    state: Mutex<LastSeenState>,
+}
+
+impl LastSeenTracker {
+    /// Record activity at most once per `interval` for each user
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: interval.as_secs() as i64,
+            state: Mutex::new(LastSeenState::default()),
+        }
+    }
+
+    /// Record activity of a connected user at `now`, unless it was recorded less than
+    /// the interval ago. Returns whether it was recorded.
+    pub async fn touch(&self, user_id: &str, now: i64) -> bool {
+        let mut state = self.state.lock().await;
+        let due = state
+            .recorded
+            .get(user_id)
+            .is_none_or(|last| now - last >= self.interval);
+        if due {
+            state.recorded.insert(user_id.to_string(), now);
+            state.pending.insert(user_id.to_string(), now);
+        }
+        due
+    }
+
+    /// Record that a user connected at `now`
+    pub async fn connected(&self, user_id: &str, now: i64) {
+        let mut state = self.state.lock().await;
+        state.recorded.insert(user_id.to_string(), now);
+        state.pending.insert(user_id.to_string(), now);
+    }
+
+    /// Record that a user's last session ended at `now`
+    pub async fn disconnected(&self, user_id: &str, now: i64) {
+        let mut state = self.state.lock().await;
+        state.recorded.remove(user_id);
+        state.pending.insert(user_id.to_string(), now);
+    }
+
+    /// Take the times to write to the database
+    pub async fn take_pending(&self) -> Vec<(String, i64)> {
+        let mut state = self.state.lock().await;
+        state.pending.drain().collect()
+    }
+
+    /// Put back times that failed to be written, unless newer ones were recorded since
+    pub async fn restore_pending(&self, pending: Vec<(String, i64)>) {
+        let mut state = self.state.lock().await;
+        for (user_id, seen_at) in pending {
+            state.pending.entry(user_id).or_insert(seen_at);
+        }
+    }
+}
+
+impl Default for LastSeenTracker {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_activity_is_throttled_and_batched() {
+        let tracker = LastSeenTracker::new(Duration::from_secs(60));
+
+        tracker.connected("user-1", 1000).await;
+        assert!(!tracker.touch("user-1", 1030).await);
+        assert!(tracker.touch("user-2", 1030).await);
+
+        let mut pending = tracker.take_pending().await;
+        pending.sort();
+        assert_eq!(
+            pending,
+            vec![("user-1".to_string(), 1000), ("user-2".to_string(), 1030)]
+        );
+        assert!(tracker.take_pending().await.is_empty());
+
+        // A minute after the last recorded time, activity counts again
+        assert!(tracker.touch("user-1", 1060).await);
+        assert_eq!(
+            tracker.take_pending().await,
+            vec![("user-1".to_string(), 1060)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_records_and_resets_throttle() {
+        let tracker = LastSeenTracker::new(Duration::from_secs(60));
+
+        tracker.connected("user-1", 1000).await;
+        tracker.take_pending().await;
+        tracker.disconnected("user-1", 1010).await;
+        assert_eq!(
+            tracker.take_pending().await,
+            vec![("user-1".to_string(), 1010)]
+        );
+
+        // Reconnecting right away is recorded again
+        assert!(tracker.touch("user-1", 1020).await);
+    }
+
+    #[tokio::test]
+    async fn test_failed_writes_are_restored() {
+        let tracker = LastSeenTracker::new(Duration::from_secs(60));
+
+        tracker.connected("user-1", 1000).await;
+        let failed = tracker.take_pending().await;
+        tracker.disconnected("user-1", 1050).await;
+        tracker.restore_pending(failed).await;
+
+        // The newer time wins over the restored one
+        assert_eq!(
+            tracker.take_pending().await,
+            vec![("user-1".to_string(), 1050)]
+        );
+    }
+}
@@ -6,6 +6,8 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
+use crate::middleware::request_id::current_request_id;
+
 /// Correlation ID for tracing requests across services
 #[derive(Debug, Clone)]
 pub struct CorrelationId(String);
@@ -15,6 +17,15 @@ impl CorrelationId {
         Self(Uuid::new_v4().to_string())
     }
 
+    /// The id of the HTTP request the current task serves (see
+    /// [`crate::middleware::request_id::propagate`]), or a fresh one outside a request
+    pub fn current() -> Self {
+        match current_request_id() {
+            Some(id) => Self(id),
+            None => Self::new(),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn from_string(id: String) -> Self {
         Self(id)
@@ -49,7 +60,7 @@ pub struct LogContext {
 impl LogContext {
     pub fn new() -> Self {
         Self {
-            correlation_id: CorrelationId::new(),
+            correlation_id: CorrelationId::current(),
             session_id: None,
             user_id: None,
             event: None,
@@ -5,6 +5,10 @@
 /// - User pools (user_id -> [sids])
 /// - Rooms (room_id -> [sids])
 /// - Usage tracking (model_id -> {sid -> timestamp})
+///
+/// With a Redis adapter, room and user membership is mirrored to Redis so other
+/// servers can tell who is connected where.
+use crate::socketio::redis_adapter::{room_key, user_key, RedisAdapter};
 use serde_json::Value as JsonValue;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
@@ -59,6 +63,9 @@ pub struct SocketIOManager {
     /// Usage pool: model_id -> {sid -> timestamp}
     usage_pool: Arc<RwLock<HashMap<String, HashMap<String, i64>>>>,
 
+    /// Mirrors membership to Redis when running several servers
+    redis_adapter: Option<Arc<RedisAdapter>>,
+
     /// Configuration
     ping_interval: u64,
     ping_timeout: u64,
@@ -71,11 +78,29 @@ impl SocketIOManager {
             user_pool: Arc::new(RwLock::new(HashMap::new())),
             rooms: Arc::new(RwLock::new(HashMap::new())),
             usage_pool: Arc::new(RwLock::new(HashMap::new())),
+            redis_adapter: None,
             ping_interval: 25_000, // 25 seconds
             ping_timeout: 20_000,  // 20 seconds
         }
     }
 
+    /// Use the given Engine.IO ping interval and timeout (milliseconds)
+    pub fn with_ping_config(mut self, ping_interval: u64, ping_timeout: u64) -> Self {
+        self.ping_interval = ping_interval;
+        self.ping_timeout = ping_timeout;
+        self
+    }
+
+    /// Mirror room and user membership through `adapter`
+    pub fn with_redis_adapter(mut self, adapter: Arc<RedisAdapter>) -> Self {
+        self.redis_adapter = Some(adapter);
+        self
+    }
+
+    pub fn redis_adapter(&self) -> Option<&Arc<RedisAdapter>> {
+        self.redis_adapter.as_ref()
+    }
+
     pub fn ping_interval(&self) -> u64 {
         self.ping_interval
     }
@@ -84,6 +109,13 @@ impl SocketIOManager {
         self.ping_timeout
     }
 
+    /// Seconds without a ping after which a session is removed as stale: two missed
+    /// pings plus the time allowed for the answer, so it only catches sessions the
+    /// heartbeat check missed
+    pub fn stale_session_timeout(&self) -> i64 {
+        ((2 * self.ping_interval + self.ping_timeout) as i64 + 999) / 1000
+    }
+
     /// Generate a new session ID
     pub fn generate_sid() -> String {
         Uuid::new_v4().to_string()
@@ -126,6 +158,12 @@ impl SocketIOManager {
             .entry(user_id.clone())
             .or_insert_with(Vec::new)
             .push(sid.to_string());
+        drop(user_pool);
+
+        if let Some(adapter) = &self.redis_adapter {
+            adapter.add_member(&user_key(&user_id), sid).await;
+            adapter.set_session_user(sid, Some(&user_id)).await;
+        }
 
         tracing::info!("Authenticated session {} for user {}", sid, user_id);
         Ok(())
@@ -147,10 +185,22 @@ impl SocketIOManager {
                         user_pool.remove(&user_id);
                     }
                 }
+                drop(user_pool);
+
+                if let Some(adapter) = &self.redis_adapter {
+                    adapter.remove_member(&user_key(&user_id), sid).await;
+                    adapter.set_session_user(sid, None).await;
+                }
             } else {
                 drop(sessions);
             }
 
+            if let Some(adapter) = &self.redis_adapter {
+                for room in &session.rooms {
+                    adapter.remove_member(&room_key(room), sid).await;
+                }
+            }
+
             // Remove from all rooms
             let mut rooms = self.rooms.write().await;
             for (_room_id, sids) in rooms.iter_mut() {
@@ -183,6 +233,11 @@ impl SocketIOManager {
             .entry(room.to_string())
             .or_insert_with(HashSet::new)
             .insert(sid.to_string());
+        drop(rooms);
+
+        if let Some(adapter) = &self.redis_adapter {
+            adapter.add_member(&room_key(room), sid).await;
+        }
 
         tracing::debug!("Session {} joined room {}", sid, room);
         Ok(())
@@ -203,6 +258,11 @@ impl SocketIOManager {
                 rooms.remove(room);
             }
         }
+        drop(rooms);
+
+        if let Some(adapter) = &self.redis_adapter {
+            adapter.remove_member(&room_key(room), sid).await;
+        }
 
         tracing::debug!("Session {} left room {}", sid, room);
         Ok(())
@@ -217,6 +277,52 @@ impl SocketIOManager {
             .unwrap_or_default()
     }
 
+    /// Get a user's sessions in a room on this server
+    pub async fn get_user_room_sessions(&self, room: &str, user_id: &str) -> Vec<String> {
+        let sids = self.get_room_sessions(room).await;
+        let sessions = self.sessions.read().await;
+        sids.into_iter()
+            .filter(|sid| {
+                sessions
+                    .get(sid)
+                    .and_then(|s| s.user_id())
+                    .is_some_and(|id| id == user_id)
+            })
+            .collect()
+    }
+
+    /// Get the distinct authenticated users with a session in a room, on any server
+    pub async fn get_room_user_ids(&self, room: &str) -> Vec<String> {
+        let sids = self.get_room_sessions(room).await;
+        let sessions = self.sessions.read().await;
+
+        let mut user_ids: Vec<String> = sids
+            .iter()
+            .filter_map(|sid| sessions.get(sid).and_then(|s| s.user_id()))
+            .collect();
+        drop(sessions);
+
+        if let Some(adapter) = &self.redis_adapter {
+            let remote = adapter.remote_members(&room_key(room)).await;
+            user_ids.extend(adapter.member_user_ids(&remote).await);
+        }
+
+        user_ids.sort();
+        user_ids.dedup();
+        user_ids
+    }
+
+    /// Rooms on this server with their member counts, largest first
+    pub async fn get_room_sizes(&self) -> Vec<(String, usize)> {
+        let rooms = self.rooms.read().await;
+        let mut sizes: Vec<(String, usize)> = rooms
+            .iter()
+            .map(|(room, sids)| (room.clone(), sids.len()))
+            .collect();
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        sizes
+    }
+
     /// Get all sessions for a user
     pub async fn get_user_sessions(&self, user_id: &str) -> Vec<String> {
         let user_pool = self.user_pool.read().await;
@@ -226,6 +332,14 @@ impl SocketIOManager {
             .unwrap_or_default()
     }
 
+    /// Number of sessions a user has on other servers
+    pub async fn remote_user_session_count(&self, user_id: &str) -> usize {
+        match &self.redis_adapter {
+            Some(adapter) => adapter.remote_members(&user_key(user_id)).await.len(),
+            None => 0,
+        }
+    }
+
     /// Track usage
     pub async fn track_usage(&self, sid: &str, model_id: &str) {
         let now = chrono::Utc::now().timestamp();
@@ -237,6 +351,14 @@ impl SocketIOManager {
         tracing::debug!("Tracked usage: {} for session {}", model_id, sid);
     }
 
+    /// Models a connected session reported using, sorted
+    pub async fn get_models_in_use(&self) -> Vec<String> {
+        let usage_pool = self.usage_pool.read().await;
+        let mut model_ids: Vec<String> = usage_pool.keys().cloned().collect();
+        model_ids.sort();
+        model_ids
+    }
+
     /// Update last ping time
     pub async fn update_ping(&self, sid: &str) {
         let mut sessions = self.sessions.write().await;
@@ -259,19 +381,21 @@ impl SocketIOManager {
         stats
     }
 
-    /// Clean up stale sessions (called periodically)
-    pub async fn cleanup_stale_sessions(&self, timeout_seconds: i64) {
+    /// Get sessions that haven't pinged within the timeout
+    pub async fn get_stale_sessions(&self, timeout_seconds: i64) -> Vec<String> {
         let now = chrono::Utc::now().timestamp();
-        let mut sessions_to_remove = Vec::new();
+        let sessions = self.sessions.read().await;
 
-        {
-            let sessions = self.sessions.read().await;
-            for (sid, session) in sessions.iter() {
-                if now - session.last_ping > timeout_seconds {
-                    sessions_to_remove.push(sid.clone());
-                }
-            }
-        }
+        sessions
+            .iter()
+            .filter(|(_, session)| now - session.last_ping > timeout_seconds)
+            .map(|(sid, _)| sid.clone())
+            .collect()
+    }
+
+    /// Clean up stale sessions (called periodically)
+    pub async fn cleanup_stale_sessions(&self, timeout_seconds: i64) {
+        let sessions_to_remove = self.get_stale_sessions(timeout_seconds).await;
 
         for sid in sessions_to_remove {
             tracing::warn!("Removing stale session: {}", sid);
@@ -335,5 +459,60 @@ mod tests {
         manager.leave_room(sid1, "room-a").await.unwrap();
         let room_sessions = manager.get_room_sessions("room-a").await;
         assert_eq!(room_sessions.len(), 1);
+
+        manager.join_room(sid1, "room-b").await.unwrap();
+        manager.join_room(sid2, "room-b").await.unwrap();
+        assert_eq!(
+            manager.get_room_sizes().await,
+            vec![("room-b".to_string(), 2), ("room-a".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_user_room_sessions() {
+        let manager = SocketIOManager::new();
+        for (sid, user_id) in [
+            ("sid-1", "user-1"),
+            ("sid-2", "user-1"),
+            ("sid-3", "user-2"),
+        ] {
+            manager.create_session(sid).await;
+            manager
+                .set_session_user(sid, serde_json::json!({"id": user_id}))
+                .await
+                .unwrap();
+            manager.join_room(sid, "chat:abc").await.unwrap();
+        }
+
+        let mut sids = manager.get_user_room_sessions("chat:abc", "user-1").await;
+        sids.sort();
+        assert_eq!(sids, vec!["sid-1", "sid-2"]);
+
+        // Disconnected sessions don't stay in the room
+        manager.remove_session("sid-1").await;
+        assert_eq!(
+            manager.get_user_room_sessions("chat:abc", "user-1").await,
+            vec!["sid-2"]
+        );
+        manager.remove_session("sid-2").await;
+        manager.remove_session("sid-3").await;
+        assert!(manager.get_room_sizes().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_models_in_use() {
+        let manager = SocketIOManager::new();
+        manager.create_session("sid-1").await;
+        manager.create_session("sid-2").await;
+        manager.track_usage("sid-1", "model-b").await;
+        manager.track_usage("sid-2", "model-a").await;
+        manager.track_usage("sid-2", "model-b").await;
+        assert_eq!(
+            manager.get_models_in_use().await,
+            vec!["model-a", "model-b"]
+        );
+
+        manager.remove_session("sid-2").await;
+        assert_eq!(manager.get_models_in_use().await, vec!["model-b"]);
     }
 }
@@ -3,7 +3,7 @@
 /// Provides metrics collection for monitoring and observability
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
 /// Metrics collector for Socket.IO
@@ -20,6 +20,9 @@ pub struct SocketIOMetrics {
 
     /// Latency tracking
     latency: Arc<RwLock<LatencyMetrics>>,
+
+    /// When collection started, for uptime and event rates
+    started_at: Instant,
 }
 
 #[derive(Default)]
@@ -28,6 +31,9 @@ struct ConnectionMetrics {
     active_connections: u64,
     failed_connections: u64,
     reconnections: u64,
+    heartbeat_timeouts: u64,
+    recovery_hits: u64,
+    recovery_misses: u64,
 }
 
 #[derive(Default)]
@@ -35,6 +41,8 @@ struct EventMetrics {
     events_received: HashMap<String, u64>,
     events_sent: HashMap<String, u64>,
     events_failed: HashMap<String, u64>,
+    /// Incoming events dropped for exceeding a rate limit
+    events_dropped: HashMap<String, u64>,
 }
 
 #[derive(Default)]
@@ -59,6 +67,24 @@ impl SocketIOMetrics {
                 samples: Vec::new(),
                 max_samples: 1000,
             })),
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Time since the metrics started being collected
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Average events per second since startup
+    pub async fn get_event_rates(&self) -> EventRates {
+        let events = self.get_event_metrics().await;
+        let secs = self.uptime().as_secs_f64().max(1.0);
+        EventRates {
+            received_per_sec: events.total_received as f64 / secs,
+            sent_per_sec: events.total_sent as f64 / secs,
+            failed_per_sec: events.total_failed as f64 / secs,
+            dropped_per_sec: events.total_dropped as f64 / secs,
         }
     }
 
@@ -89,6 +115,23 @@ impl SocketIOMetrics {
         conn.reconnections += 1;
     }
 
+    /// Record a session disconnected for exceeding the heartbeat timeout
+    pub async fn record_heartbeat_timeout(&self) {
+        let mut conn = self.connections.write().await;
+        conn.heartbeat_timeouts += 1;
+    }
+
+    /// Record a reconnect that tried connection state recovery, and whether it resumed
+    /// the session
+    pub async fn record_recovery(&self, recovered: bool) {
+        let mut conn = self.connections.write().await;
+        if recovered {
+            conn.recovery_hits += 1;
+        } else {
+            conn.recovery_misses += 1;
+        }
+    }
+
     /// Record an event received
     pub async fn record_event_received(&self, event: &str) {
         let mut events = self.events.write().await;
@@ -107,6 +150,12 @@ impl SocketIOMetrics {
         *events.events_failed.entry(event.to_string()).or_insert(0) += 1;
     }
 
+    /// Record an incoming event dropped by the rate limiter
+    pub async fn record_event_dropped(&self, event: &str) {
+        let mut events = self.events.write().await;
+        *events.events_dropped.entry(event.to_string()).or_insert(0) += 1;
+    }
+
     /// Record room size
     pub async fn record_room_size(&self, room: &str, size: usize) {
         let mut rooms = self.rooms.write().await;
@@ -140,6 +189,9 @@ impl SocketIOMetrics {
             active: conn.active_connections,
             failed: conn.failed_connections,
             reconnections: conn.reconnections,
+            heartbeat_timeouts: conn.heartbeat_timeouts,
+            recovery_hits: conn.recovery_hits,
+            recovery_misses: conn.recovery_misses,
         }
     }
 
@@ -150,14 +202,17 @@ impl SocketIOMetrics {
         let total_received: u64 = events.events_received.values().sum();
         let total_sent: u64 = events.events_sent.values().sum();
         let total_failed: u64 = events.events_failed.values().sum();
+        let total_dropped: u64 = events.events_dropped.values().sum();
 
         EventStats {
             total_received,
             total_sent,
             total_failed,
+            total_dropped,
             by_type_received: events.events_received.clone(),
             by_type_sent: events.events_sent.clone(),
             by_type_failed: events.events_failed.clone(),
+            by_type_dropped: events.events_dropped.clone(),
         }
     }
 
@@ -233,14 +288,19 @@ impl SocketIOMetrics {
                 "active": conn.active,
                 "failed": conn.failed,
                 "reconnections": conn.reconnections,
+                "heartbeat_timeouts": conn.heartbeat_timeouts,
+                "recovery_hits": conn.recovery_hits,
+                "recovery_misses": conn.recovery_misses,
             },
             "events": {
                 "total_received": events.total_received,
                 "total_sent": events.total_sent,
                 "total_failed": events.total_failed,
+                "total_dropped": events.total_dropped,
                 "by_type_received": events.by_type_received,
                 "by_type_sent": events.by_type_sent,
                 "by_type_failed": events.by_type_failed,
+                "by_type_dropped": events.by_type_dropped,
             },
             "rooms": {
                 "total": rooms.total_rooms,
@@ -287,6 +347,9 @@ pub struct ConnectionStats {
     pub active: u64,
     pub failed: u64,
     pub reconnections: u64,
+    pub heartbeat_timeouts: u64,
+    pub recovery_hits: u64,
+    pub recovery_misses: u64,
 }
 
 /// Event statistics
@@ -295,9 +358,20 @@ pub struct EventStats {
     pub total_received: u64,
     pub total_sent: u64,
     pub total_failed: u64,
+    pub total_dropped: u64,
     pub by_type_received: HashMap<String, u64>,
     pub by_type_sent: HashMap<String, u64>,
     pub by_type_failed: HashMap<String, u64>,
+    pub by_type_dropped: HashMap<String, u64>,
+}
+
+/// Average event rates since startup
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EventRates {
+    pub received_per_sec: f64,
+    pub sent_per_sec: f64,
+    pub failed_per_sec: f64,
+    pub dropped_per_sec: f64,
 }
 
 /// Room statistics
@@ -361,6 +435,13 @@ mod tests {
         metrics.record_disconnection().await;
         let stats = metrics.get_connection_metrics().await;
         assert_eq!(stats.active, 1);
+
+        metrics.record_recovery(true).await;
+        metrics.record_recovery(false).await;
+        metrics.record_recovery(true).await;
+        let stats = metrics.get_connection_metrics().await;
+        assert_eq!(stats.recovery_hits, 2);
+        assert_eq!(stats.recovery_misses, 1);
     }
 
     #[tokio::test]
@@ -370,11 +451,19 @@ mod tests {
         metrics.record_event_received("user-join").await;
         metrics.record_event_received("user-join").await;
         metrics.record_event_sent("chat-events").await;
+        metrics.record_event_dropped("typing").await;
 
         let stats = metrics.get_event_metrics().await;
         assert_eq!(stats.total_received, 2);
         assert_eq!(stats.total_sent, 1);
         assert_eq!(stats.by_type_received.get("user-join"), Some(&2));
+        assert_eq!(stats.total_dropped, 1);
+
+        // Under a second of uptime counts as one second
+        let rates = metrics.get_event_rates().await;
+        assert_eq!(rates.received_per_sec, 2.0);
+        assert_eq!(rates.dropped_per_sec, 1.0);
+        assert_eq!(stats.by_type_dropped.get("typing"), Some(&1));
     }
 
     #[tokio::test]
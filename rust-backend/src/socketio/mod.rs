@@ -8,18 +8,22 @@
 /// - Transport: WebSocket and HTTP long-polling support
 /// - Manager: Session, room, and user management
 /// - Events: Event handlers for all Socket.IO events
+/// - ChatStreams: Snapshots of responses streaming into chat rooms
 /// - Redis: Optional Redis pub/sub for horizontal scaling
 /// - YDoc: Yjs CRDT for collaborative editing
 /// - Metrics: Performance monitoring and observability
 /// - RateLimit: Rate limiting and backpressure control
 /// - Presence: User presence tracking and typing indicators
+/// - LastSeen: Batched last-seen times of socket users
 /// - Recovery: Connection recovery and session persistence
 /// - Health: Connection health monitoring and heartbeat system
 /// - CircuitBreaker: Fault tolerance and graceful degradation
 /// - Prometheus: Metrics export for monitoring systems
+pub mod chat_streams;
 pub mod circuit_breaker;
 pub mod events;
 pub mod health;
+pub mod last_seen;
 pub mod logging;
 pub mod manager;
 pub mod metrics;
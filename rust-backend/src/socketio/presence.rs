@@ -43,6 +43,8 @@ pub struct TypingIndicator {
     pub started_at: u64, // Unix timestamp
     #[serde(skip, default = "default_instant")]
     pub expires_at: Instant, // Internal expiry tracking
+    #[serde(skip, default = "default_instant")]
+    pub last_broadcast_at: Instant, // Internal throttling of typing broadcasts
 }
 
 /// Presence manager
@@ -66,6 +68,9 @@ pub struct PresenceConfig {
     /// Time after which typing indicator expires
     pub typing_timeout: Duration,
 
+    /// Minimum time between typing broadcasts for the same user and room
+    pub typing_broadcast_interval: Duration,
+
     /// Time after which presence data is cleaned up (offline users)
     pub cleanup_timeout: Duration,
 }
@@ -73,8 +78,9 @@ pub struct PresenceConfig {
 impl Default for PresenceConfig {
     fn default() -> Self {
         Self {
-            away_timeout: Duration::from_secs(300),     // 5 minutes
-            typing_timeout: Duration::from_secs(5),     // 5 seconds
+            away_timeout: Duration::from_secs(300), // 5 minutes
+            typing_timeout: Duration::from_secs(5), // 5 seconds
+            typing_broadcast_interval: Duration::from_secs(2),
             cleanup_timeout: Duration::from_secs(3600), // 1 hour
         }
     }
@@ -97,8 +103,9 @@ impl PresenceManager {
             .as_secs()
     }
 
-    /// Mark user as online (new session)
-    pub async fn user_online(&self, user_id: &str) {
+    /// Mark user as online (new session).
+    /// Returns true if the user had no active sessions before (just came online).
+    pub async fn user_online(&self, user_id: &str) -> bool {
         let mut presences = self.presences.write().await;
 
         let presence = presences
@@ -112,6 +119,7 @@ impl PresenceManager {
                 session_count: 0,
             });
 
+        let came_online = presence.session_count == 0;
         presence.session_count += 1;
         presence.status = PresenceStatus::Online;
         presence.last_seen = Self::now_timestamp();
@@ -122,6 +130,8 @@ impl PresenceManager {
             user_id,
             presence.session_count
         );
+
+        came_online
     }
 
     /// Mark user as offline (session ended)
@@ -208,15 +218,43 @@ impl PresenceManager {
             .collect()
     }
 
-    /// Start typing indicator
-    pub async fn start_typing(&self, user_id: &str, user_name: &str, room_id: &str) {
+    /// IDs of users with at least one session on this server, whatever their status
+    pub async fn get_connected_user_ids(&self) -> Vec<String> {
+        let presences = self.presences.read().await;
+
+        presences
+            .values()
+            .filter(|p| p.session_count > 0)
+            .map(|p| p.user_id.clone())
+            .collect()
+    }
+
+    /// Start (or refresh) typing indicator.
+    /// Returns true if the change should be broadcast, false if throttled.
+    pub async fn start_typing(&self, user_id: &str, user_name: &str, room_id: &str) -> bool {
         let mut indicators = self.typing_indicators.write().await;
+        let now = Instant::now();
 
         let room_indicators = indicators
             .entry(room_id.to_string())
             .or_insert_with(Vec::new);
 
-        // Remove existing indicator for this user if any
+        // Refresh an active indicator without re-broadcasting if one went out recently
+        if let Some(existing) = room_indicators
+            .iter_mut()
+            .find(|ind| ind.user_id == user_id && ind.expires_at > now)
+        {
+            existing.expires_at = now + self.config.typing_timeout;
+            if now.duration_since(existing.last_broadcast_at)
+                < self.config.typing_broadcast_interval
+            {
+                return false;
+            }
+            existing.last_broadcast_at = now;
+            return true;
+        }
+
+        // Remove existing (expired) indicator for this user if any
         room_indicators.retain(|ind| ind.user_id != user_id);
 
         // Add new indicator
@@ -225,10 +263,12 @@ impl PresenceManager {
             user_name: user_name.to_string(),
             room_id: room_id.to_string(),
             started_at: Self::now_timestamp(),
-            expires_at: Instant::now() + self.config.typing_timeout,
+            expires_at: now + self.config.typing_timeout,
+            last_broadcast_at: now,
         });
 
         tracing::debug!("User {} started typing in room {}", user_id, room_id);
+        true
     }
 
     /// Stop typing indicator
@@ -302,15 +342,20 @@ impl PresenceManager {
         });
     }
 
-    /// Clean up expired typing indicators
-    pub async fn cleanup_typing_indicators(&self) {
+    /// Clean up expired typing indicators, returning the ones that expired
+    pub async fn cleanup_typing_indicators(&self) -> Vec<TypingIndicator> {
         let mut indicators = self.typing_indicators.write().await;
         let now = Instant::now();
+        let mut expired = Vec::new();
 
         // Remove expired indicators from all rooms
         for (room_id, room_indicators) in indicators.iter_mut() {
             let initial_count = room_indicators.len();
-            room_indicators.retain(|ind| ind.expires_at > now);
+            let (active, stale): (Vec<_>, Vec<_>) = room_indicators
+                .drain(..)
+                .partition(|ind| ind.expires_at > now);
+            *room_indicators = active;
+            expired.extend(stale);
 
             let removed = initial_count - room_indicators.len();
             if removed > 0 {
@@ -324,6 +369,8 @@ impl PresenceManager {
 
         // Remove empty rooms
         indicators.retain(|_, room_indicators| !room_indicators.is_empty());
+
+        expired
     }
 
     /// Get statistics
@@ -437,6 +484,39 @@ mod tests {
         assert_eq!(typing.len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_typing_broadcasts_are_throttled() {
+        let manager = PresenceManager::new(PresenceConfig {
+            typing_timeout: Duration::from_millis(50),
+            typing_broadcast_interval: Duration::from_secs(60),
+            ..PresenceConfig::default()
+        });
+
+        assert!(manager.start_typing("user-1", "Alice", "room-1").await);
+        // Repeated updates within the interval only refresh the indicator
+        assert!(!manager.start_typing("user-1", "Alice", "room-1").await);
+        assert_eq!(manager.get_typing_users("room-1").await.len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        let expired = manager.cleanup_typing_indicators().await;
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].room_id, "room-1");
+
+        // Typing again after expiry is a fresh indicator and broadcasts immediately
+        assert!(manager.start_typing("user-1", "Alice", "room-1").await);
+    }
+
+    #[tokio::test]
+    async fn test_user_online_reports_first_session() {
+        let manager = PresenceManager::default();
+
+        assert!(manager.user_online("user-1").await);
+        assert!(!manager.user_online("user-1").await);
+        manager.user_offline("user-1").await;
+        manager.user_offline("user-1").await;
+        assert!(manager.user_online("user-1").await);
+    }
+
     #[tokio::test]
     async fn test_custom_status() {
         let manager = PresenceManager::default();
@@ -449,4 +529,19 @@ mod tests {
         let presence = manager.get_presence("user-1").await.unwrap();
         assert_eq!(presence.custom_status, Some("In a meeting".to_string()));
     }
+
+    #[tokio::test]
+    async fn test_connected_user_ids() {
+        let manager = PresenceManager::default();
+
+        manager.user_online("user-1").await;
+        manager.user_online("user-2").await;
+        manager.set_status("user-2", PresenceStatus::Busy).await;
+        manager.user_online("user-3").await;
+        manager.user_offline("user-3").await;
+
+        let mut user_ids = manager.get_connected_user_ids().await;
+        user_ids.sort();
+        assert_eq!(user_ids, vec!["user-1", "user-2"]);
+    }
 }
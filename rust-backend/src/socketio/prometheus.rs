@@ -118,6 +118,38 @@ impl PrometheusExporter {
         )?;
         writeln!(&mut output)?;
 
+        writeln!(
+            &mut output,
+            "# HELP socketio_connections_heartbeat_timeouts Sessions disconnected for exceeding the heartbeat timeout"
+        )?;
+        writeln!(
+            &mut output,
+            "# TYPE socketio_connections_heartbeat_timeouts counter"
+        )?;
+        writeln!(
+            &mut output,
+            "socketio_connections_heartbeat_timeouts {}",
+            conn_metrics.heartbeat_timeouts
+        )?;
+        writeln!(&mut output)?;
+
+        writeln!(
+            &mut output,
+            "# HELP socketio_connections_recovered Reconnects by connection state recovery outcome"
+        )?;
+        writeln!(&mut output, "# TYPE socketio_connections_recovered counter")?;
+        writeln!(
+            &mut output,
+            "socketio_connections_recovered{{outcome=\"hit\"}} {}",
+            conn_metrics.recovery_hits
+        )?;
+        writeln!(
+            &mut output,
+            "socketio_connections_recovered{{outcome=\"miss\"}} {}",
+            conn_metrics.recovery_misses
+        )?;
+        writeln!(&mut output)?;
+
         // Event metrics
         let event_metrics = self.metrics.get_event_metrics().await;
 
@@ -157,6 +189,18 @@ impl PrometheusExporter {
         )?;
         writeln!(&mut output)?;
 
+        writeln!(
+            &mut output,
+            "# HELP socketio_events_dropped_total Incoming events dropped by the rate limiter"
+        )?;
+        writeln!(&mut output, "# TYPE socketio_events_dropped_total counter")?;
+        writeln!(
+            &mut output,
+            "socketio_events_dropped_total {}",
+            event_metrics.total_dropped
+        )?;
+        writeln!(&mut output)?;
+
         // Events by type
         writeln!(
             &mut output,
@@ -175,6 +219,23 @@ impl PrometheusExporter {
         }
         writeln!(&mut output)?;
 
+        writeln!(
+            &mut output,
+            "# HELP socketio_events_by_type_dropped Incoming events dropped by type"
+        )?;
+        writeln!(
+            &mut output,
+            "# TYPE socketio_events_by_type_dropped counter"
+        )?;
+        for (event_type, count) in &event_metrics.by_type_dropped {
+            writeln!(
+                &mut output,
+                "socketio_events_by_type_dropped{{type=\"{}\"}} {}",
+                event_type, count
+            )?;
+        }
+        writeln!(&mut output)?;
+
         // Room metrics
         let room_metrics = self.metrics.get_room_metrics().await;
 
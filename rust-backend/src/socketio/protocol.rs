@@ -7,8 +7,17 @@
 /// - CONNECT packet must include a data payload with {sid: "..."}
 /// - Client must send CONNECT for default namespace
 /// - CONNECT_ERROR for connection failures
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use bytes::Bytes;
+use rmpv::Value as MsgPackValue;
 use serde_json::Value as JsonValue;
 
+/// Prefix of a binary packet in an HTTP long-polling payload (followed by base64)
+const POLLING_BINARY_PREFIX: char = 'b';
+
+/// Separator between packets in an HTTP long-polling payload
+pub const POLLING_SEPARATOR: char = '\x1e';
+
 /// Engine.IO packet types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EnginePacketType {
@@ -98,6 +107,10 @@ impl EnginePacket {
         Self::new(EnginePacketType::Message, data)
     }
 
+    pub fn ping() -> Self {
+        Self::new(EnginePacketType::Ping, Vec::new())
+    }
+
     pub fn pong(data: Vec<u8>) -> Self {
         Self::new(EnginePacketType::Pong, data)
     }
@@ -167,6 +180,74 @@ impl EnginePacket {
     }
 }
 
+/// A frame queued for a WebSocket connection
+///
+/// Binary attachments of a Socket.IO packet are sent as raw binary frames
+/// right after the text frame carrying the packet itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WsFrame {
+    Text(String),
+    Binary(Bytes),
+}
+
+impl From<String> for WsFrame {
+    fn from(text: String) -> Self {
+        WsFrame::Text(text)
+    }
+}
+
+/// One packet of an HTTP long-polling payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PollingPacket {
+    /// An Engine.IO packet in its string encoding
+    Text(String),
+    /// A binary attachment
+    Binary(Vec<u8>),
+}
+
+/// Split an HTTP long-polling payload into its packets, decoding binary ones
+pub fn decode_polling_payload(payload: &str) -> Vec<PollingPacket> {
+    payload
+        .split(POLLING_SEPARATOR)
+        .filter(|packet| !packet.is_empty())
+        .filter_map(|packet| match packet.strip_prefix(POLLING_BINARY_PREFIX) {
+            Some(encoded) => STANDARD.decode(encoded).ok().map(PollingPacket::Binary),
+            None => Some(PollingPacket::Text(packet.to_string())),
+        })
+        .collect()
+}
+
+/// Placeholder standing in for binary attachment `num` in a packet's JSON data
+pub fn binary_placeholder(num: usize) -> JsonValue {
+    serde_json::json!({"_placeholder": true, "num": num})
+}
+
+fn placeholder_num(value: &JsonValue) -> Option<usize> {
+    let obj = value.as_object()?;
+    if obj.get("_placeholder").and_then(|p| p.as_bool()) != Some(true) {
+        return None;
+    }
+    obj.get("num").and_then(|n| n.as_u64()).map(|n| n as usize)
+}
+
+/// Replace placeholders with their attachment as a JSON byte array, the shape event
+/// handlers already accept for binary data (e.g. Yjs updates)
+fn fill_placeholders(value: &mut JsonValue, attachments: &[Bytes]) {
+    if let Some(bytes) = placeholder_num(value).and_then(|num| attachments.get(num)) {
+        *value = serde_json::json!(bytes.as_ref());
+        return;
+    }
+    match value {
+        JsonValue::Array(items) => items
+            .iter_mut()
+            .for_each(|item| fill_placeholders(item, attachments)),
+        JsonValue::Object(map) => map
+            .values_mut()
+            .for_each(|item| fill_placeholders(item, attachments)),
+        _ => {}
+    }
+}
+
 /// Socket.IO packet
 #[derive(Debug, Clone)]
 pub struct SocketPacket {
@@ -174,10 +255,38 @@ pub struct SocketPacket {
     pub namespace: String,
     pub data: Option<JsonValue>,
     pub id: Option<u64>,
+    /// Number of binary attachments announced by a decoded BINARY_EVENT/BINARY_ACK
+    pub attachment_count: usize,
+    /// Binary attachments, referenced from `data` by placeholders
+    pub attachments: Vec<Bytes>,
+}
+
+/// Binary data in an event payload: an attachment placeholder, or a JSON byte array
+/// from clients that don't send binary
+pub fn binary_value(value: &JsonValue, attachments: &[Bytes]) -> Option<Bytes> {
+    if let Some(num) = placeholder_num(value) {
+        return attachments.get(num).cloned();
+    }
+    let bytes = value
+        .as_array()?
+        .iter()
+        .map(|v| v.as_u64().and_then(|n| u8::try_from(n).ok()))
+        .collect::<Option<Vec<u8>>>()?;
+    Some(Bytes::from(bytes))
+}
+
+/// Packet encoding of a connection, detected from the first packet the client sends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Parser {
+    /// The default parser: JSON text, with binary attachments in packets of their own
+    #[default]
+    Json,
+    /// socket.io-msgpack-parser: every packet is one MessagePack-encoded binary packet,
+    /// with binary data inline
+    MsgPack,
 }
 
 /// Acknowledgment tracker for reliable message delivery
-#[allow(dead_code)]
 pub struct AckTracker {
     next_id: std::sync::atomic::AtomicU64,
     pending_acks: Arc<RwLock<HashMap<u64, PendingAck>>>,
@@ -185,12 +294,14 @@ pub struct AckTracker {
 
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{oneshot, RwLock};
 
 struct PendingAck {
     created_at: std::time::Instant,
     timeout: std::time::Duration,
-    callback: Option<Box<dyn FnOnce(JsonValue) + Send>>,
+    /// Session the event was sent to; an ACK from any other session is ignored
+    sid: Option<String>,
+    callback: Option<Box<dyn FnOnce(JsonValue) + Send + Sync>>,
 }
 
 #[allow(dead_code)]
@@ -211,7 +322,7 @@ impl AckTracker {
     /// Register a pending ACK with callback
     pub async fn register_ack<F>(&self, id: u64, timeout: std::time::Duration, callback: F)
     where
-        F: FnOnce(JsonValue) + Send + 'static,
+        F: FnOnce(JsonValue) + Send + Sync + 'static,
     {
         let mut pending = self.pending_acks.write().await;
         pending.insert(
@@ -219,11 +330,58 @@ impl AckTracker {
             PendingAck {
                 created_at: std::time::Instant::now(),
                 timeout,
+                sid: None,
                 callback: Some(Box::new(callback)),
             },
         );
     }
 
+    /// Expect an ACK from session `sid`: returns the ACK ID to send with the event and
+    /// a receiver resolving with the client's ACK arguments
+    pub async fn expect_ack(
+        &self,
+        sid: &str,
+        timeout: std::time::Duration,
+    ) -> (u64, oneshot::Receiver<JsonValue>) {
+        let id = self.next_ack_id();
+        let (tx, rx) = oneshot::channel();
+        self.pending_acks.write().await.insert(
+            id,
+            PendingAck {
+                created_at: std::time::Instant::now(),
+                timeout,
+                sid: Some(sid.to_string()),
+                callback: Some(Box::new(move |data| {
+                    let _ = tx.send(data);
+                })),
+            },
+        );
+        (id, rx)
+    }
+
+    /// Process an ACK response received from session `sid`
+    pub async fn process_session_ack(&self, sid: &str, id: u64, data: JsonValue) -> bool {
+        let mut pending = self.pending_acks.write().await;
+        let expected = match pending.get(&id) {
+            Some(ack) => ack.sid.as_deref().is_none_or(|expected| expected == sid),
+            None => false,
+        };
+        if !expected {
+            return false;
+        }
+        let callback = pending.remove(&id).and_then(|ack| ack.callback);
+        drop(pending);
+        if let Some(callback) = callback {
+            callback(data);
+        }
+        true
+    }
+
+    /// Forget a pending ACK, e.g. once its waiter timed out
+    pub async fn cancel(&self, id: u64) {
+        self.pending_acks.write().await.remove(&id);
+    }
+
     /// Process an ACK response
     pub async fn process_ack(&self, id: u64, data: JsonValue) -> bool {
         let mut pending = self.pending_acks.write().await;
@@ -264,6 +422,8 @@ impl SocketPacket {
             namespace: "/".to_string(),
             data: None,
             id: None,
+            attachment_count: 0,
+            attachments: Vec::new(),
         }
     }
 
@@ -276,6 +436,21 @@ impl SocketPacket {
             namespace: namespace.to_string(),
             data,
             id: None,
+            attachment_count: 0,
+            attachments: Vec::new(),
+        }
+    }
+
+    /// Create a CONNECT response for a resumable session, with the private session ID
+    /// (`pid`) the client presents to recover it
+    pub fn connect_recoverable(namespace: &str, sid: &str, pid: &str) -> Self {
+        Self {
+            packet_type: SocketPacketType::Connect,
+            namespace: namespace.to_string(),
+            data: Some(serde_json::json!({"sid": sid, "pid": pid})),
+            id: None,
+            attachment_count: 0,
+            attachments: Vec::new(),
         }
     }
 
@@ -288,6 +463,8 @@ impl SocketPacket {
             namespace: namespace.to_string(),
             data: auth,
             id: None,
+            attachment_count: 0,
+            attachments: Vec::new(),
         }
     }
 
@@ -298,6 +475,8 @@ impl SocketPacket {
             namespace: namespace.to_string(),
             data: None,
             id: None,
+            attachment_count: 0,
+            attachments: Vec::new(),
         }
     }
 
@@ -307,36 +486,184 @@ impl SocketPacket {
             namespace: namespace.to_string(),
             data: Some(serde_json::json!([event, data])),
             id: None,
+            attachment_count: 0,
+            attachments: Vec::new(),
         }
     }
 
-    /// Create an event with ACK ID for reliable delivery
-    #[allow(dead_code)]
+    /// Create a recoverable event; the offset goes last, where Socket.IO clients look
+    /// for it to resume from after reconnecting
+    pub fn event_with_offset(namespace: &str, event: &str, data: JsonValue, offset: u64) -> Self {
+        Self {
+            packet_type: SocketPacketType::Event,
+            namespace: namespace.to_string(),
+            data: Some(serde_json::json!([event, data, offset.to_string()])),
+            id: None,
+            attachment_count: 0,
+            attachments: Vec::new(),
+        }
+    }
+
+    /// Create an event asking the client to acknowledge it, e.g. `21["event",{...}]`
     pub fn event_with_ack(namespace: &str, event: &str, data: JsonValue, ack_id: u64) -> Self {
         Self {
             packet_type: SocketPacketType::Event,
             namespace: namespace.to_string(),
             data: Some(serde_json::json!([event, data])),
             id: Some(ack_id),
+            attachment_count: 0,
+            attachments: Vec::new(),
         }
     }
 
-    /// Create a binary event packet
-    #[allow(dead_code)]
+    /// Create a binary event packet; `data` refers to each attachment with
+    /// [`binary_placeholder`]
     pub fn binary_event(
         namespace: &str,
         event: &str,
         data: JsonValue,
-        _attachments: usize,
+        attachments: Vec<Bytes>,
     ) -> Self {
         Self {
             packet_type: SocketPacketType::BinaryEvent,
             namespace: namespace.to_string(),
             data: Some(serde_json::json!([event, data])),
             id: None,
+            attachment_count: attachments.len(),
+            attachments,
+        }
+    }
+
+    pub fn is_binary(&self) -> bool {
+        matches!(
+            self.packet_type,
+            SocketPacketType::BinaryEvent | SocketPacketType::BinaryAck
+        )
+    }
+
+    /// WebSocket frames carrying this packet: the encoded packet, then one binary
+    /// frame per attachment
+    pub fn into_frames(self) -> Vec<WsFrame> {
+        let header = EnginePacket::message(self.encode().into_bytes()).encode();
+        std::iter::once(WsFrame::Text(header))
+            .chain(self.attachments.into_iter().map(WsFrame::Binary))
+            .collect()
+    }
+
+    /// Packets of an HTTP long-polling payload carrying this packet: the encoded
+    /// packet followed by its base64-encoded attachments
+    pub fn to_polling_packets(&self) -> Vec<String> {
+        let header = EnginePacket::message(self.encode().into_bytes()).encode();
+        std::iter::once(header)
+            .chain(
+                self.attachments
+                    .iter()
+                    .map(|bytes| format!("{}{}", POLLING_BINARY_PREFIX, STANDARD.encode(bytes))),
+            )
+            .collect()
+    }
+
+    /// WebSocket frames carrying this packet for a connection using `parser`
+    pub fn into_frames_for(self, parser: Parser) -> Vec<WsFrame> {
+        match parser {
+            Parser::Json => self.into_frames(),
+            Parser::MsgPack => vec![WsFrame::Binary(Bytes::from(self.encode_msgpack()))],
+        }
+    }
+
+    /// Packets of an HTTP long-polling payload carrying this packet for a connection
+    /// using `parser`
+    pub fn to_polling_packets_for(&self, parser: Parser) -> Vec<String> {
+        match parser {
+            Parser::Json => self.to_polling_packets(),
+            Parser::MsgPack => vec![format!(
+                "{}{}",
+                POLLING_BINARY_PREFIX,
+                STANDARD.encode(self.encode_msgpack())
+            )],
+        }
+    }
+
+    /// Encode for socket.io-msgpack-parser: a map of `type`, `nsp`, `data` and `id`,
+    /// with attachments inline as MessagePack binary
+    pub fn encode_msgpack(&self) -> Vec<u8> {
+        // Binary data needs no packet type of its own
+        let packet_type = match self.packet_type {
+            SocketPacketType::BinaryEvent => SocketPacketType::Event,
+            SocketPacketType::BinaryAck => SocketPacketType::Ack,
+            other => other,
+        };
+        let mut fields = vec![
+            (
+                MsgPackValue::from("type"),
+                MsgPackValue::from(packet_type.to_u8()),
+            ),
+            (
+                MsgPackValue::from("nsp"),
+                MsgPackValue::from(self.namespace.as_str()),
+            ),
+        ];
+        if let Some(data) = &self.data {
+            fields.push((
+                MsgPackValue::from("data"),
+                json_to_msgpack(data, &self.attachments),
+            ));
         }
+        if let Some(id) = self.id {
+            fields.push((MsgPackValue::from("id"), MsgPackValue::from(id)));
+        }
+
+        let mut buf = Vec::new();
+        rmpv::encode::write_value(&mut buf, &MsgPackValue::Map(fields))
+            .expect("writing to a Vec cannot fail");
+        buf
     }
 
+    /// Decode a socket.io-msgpack-parser packet. Binary values become attachments with
+    /// placeholders in `data`, as in a reassembled binary packet of the JSON parser.
+    pub fn decode_msgpack(bytes: &[u8]) -> Result<Self, String> {
+        let value = rmpv::decode::read_value(&mut &bytes[..])
+            .map_err(|e| format!("Invalid MessagePack packet: {}", e))?;
+        let fields = match value {
+            MsgPackValue::Map(fields) => fields,
+            _ => return Err("MessagePack packet is not a map".to_string()),
+        };
+
+        let mut packet = SocketPacket::new(SocketPacketType::Connect);
+        let mut packet_type = None;
+        for (key, value) in fields {
+            match key.as_str() {
+                Some("type") => {
+                    packet_type = value
+                        .as_u64()
+                        .and_then(|t| SocketPacketType::from_u8(t as u8));
+                }
+                Some("nsp") => {
+                    if let Some(nsp) = value.as_str() {
+                        packet.namespace = nsp.to_string();
+                    }
+                }
+                Some("data") => {
+                    packet.data = Some(msgpack_to_json(value, &mut packet.attachments));
+                }
+                Some("id") => packet.id = value.as_u64(),
+                // e.g. `options`, which the JS client sends along
+                _ => {}
+            }
+        }
+
+        packet.packet_type = match packet_type.ok_or("Invalid packet type")? {
+            SocketPacketType::Event if !packet.attachments.is_empty() => {
+                SocketPacketType::BinaryEvent
+            }
+            SocketPacketType::Ack if !packet.attachments.is_empty() => SocketPacketType::BinaryAck,
+            other => other,
+        };
+        packet.attachment_count = packet.attachments.len();
+        Ok(packet)
+    }
+
+    /// Create an ACK packet answering the event with ID `id`
     #[allow(dead_code)]
     pub fn ack(namespace: &str, id: u64, data: JsonValue) -> Self {
         Self {
@@ -344,6 +671,8 @@ impl SocketPacket {
             namespace: namespace.to_string(),
             data: Some(data),
             id: Some(id),
+            attachment_count: 0,
+            attachments: Vec::new(),
         }
     }
 
@@ -356,6 +685,8 @@ impl SocketPacket {
             namespace: namespace.to_string(),
             data: Some(serde_json::json!({"message": message})),
             id: None,
+            attachment_count: 0,
+            attachments: Vec::new(),
         }
     }
 
@@ -366,6 +697,12 @@ impl SocketPacket {
         // Packet type
         result.push_str(&self.packet_type.to_u8().to_string());
 
+        // Attachment count for binary packets, e.g. `51-`
+        if self.is_binary() {
+            result.push_str(&self.attachments.len().to_string());
+            result.push('-');
+        }
+
         // Namespace (if not default)
         if self.namespace != "/" {
             result.push_str(&self.namespace);
@@ -400,7 +737,21 @@ impl SocketPacket {
             .and_then(|d| SocketPacketType::from_u8(d as u8))
             .ok_or("Invalid packet type")?;
 
-        let rest: String = chars.collect();
+        let mut rest: String = chars.collect();
+
+        // Attachment count of binary packets
+        let mut attachment_count = 0;
+        if matches!(
+            packet_type,
+            SocketPacketType::BinaryEvent | SocketPacketType::BinaryAck
+        ) {
+            let dash = rest.find('-').ok_or("Missing attachment count")?;
+            attachment_count = rest[..dash]
+                .parse::<usize>()
+                .map_err(|_| "Invalid attachment count")?;
+            rest = rest[dash + 1..].to_string();
+        }
+
         let mut namespace = "/".to_string();
         let mut id = None;
         let mut data = None;
@@ -446,26 +797,167 @@ impl SocketPacket {
             namespace,
             data,
             id,
+            attachment_count,
+            attachments: Vec::new(),
         })
     }
 
-    /// Get event name and data from Event packet
+    /// Get event name and data from an Event or (reassembled) BinaryEvent packet,
+    /// with attachments filled in as JSON byte arrays
     pub fn get_event(&self) -> Option<(String, JsonValue)> {
-        if self.packet_type != SocketPacketType::Event {
+        let (event, mut data, attachments) = self.clone().into_event()?;
+        fill_placeholders(&mut data, &attachments);
+        Some((event, data))
+    }
+
+    /// Split an Event or (reassembled) BinaryEvent packet into its event name, its data
+    /// with placeholders left in and its attachments; see [`binary_value`]
+    pub fn into_event(self) -> Option<(String, JsonValue, Vec<Bytes>)> {
+        if !matches!(
+            self.packet_type,
+            SocketPacketType::Event | SocketPacketType::BinaryEvent
+        ) {
             return None;
         }
 
-        let data = self.data.as_ref()?;
-        let arr = data.as_array()?;
+        let mut arr = match self.data? {
+            JsonValue::Array(arr) if arr.len() >= 2 => arr,
+            _ => return None,
+        };
+        let event = arr[0].as_str()?.to_string();
+        let event_data = arr.swap_remove(1);
 
-        if arr.len() < 2 {
-            return None;
+        Some((event, event_data, self.attachments))
+    }
+
+    /// The packet data with attachments filled in as JSON byte arrays
+    pub fn filled_data(&self) -> Option<JsonValue> {
+        let mut data = self.data.clone()?;
+        fill_placeholders(&mut data, &self.attachments);
+        Some(data)
+    }
+}
+
+fn json_to_msgpack(value: &JsonValue, attachments: &[Bytes]) -> MsgPackValue {
+    if let Some(bytes) = placeholder_num(value).and_then(|num| attachments.get(num)) {
+        return MsgPackValue::Binary(bytes.to_vec());
+    }
+    match value {
+        JsonValue::Null => MsgPackValue::Nil,
+        JsonValue::Bool(b) => MsgPackValue::from(*b),
+        JsonValue::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => MsgPackValue::from(u),
+            (None, Some(i)) => MsgPackValue::from(i),
+            _ => MsgPackValue::from(n.as_f64().unwrap_or_default()),
+        },
+        JsonValue::String(s) => MsgPackValue::from(s.as_str()),
+        JsonValue::Array(items) => MsgPackValue::Array(
+            items
+                .iter()
+                .map(|item| json_to_msgpack(item, attachments))
+                .collect(),
+        ),
+        JsonValue::Object(map) => MsgPackValue::Map(
+            map.iter()
+                .map(|(k, v)| {
+                    (
+                        MsgPackValue::from(k.as_str()),
+                        json_to_msgpack(v, attachments),
+                    )
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// MessagePack to JSON, moving binary values to `attachments` behind placeholders
+fn msgpack_to_json(value: MsgPackValue, attachments: &mut Vec<Bytes>) -> JsonValue {
+    match value {
+        MsgPackValue::Nil | MsgPackValue::Ext(..) => JsonValue::Null,
+        MsgPackValue::Boolean(b) => JsonValue::Bool(b),
+        MsgPackValue::Integer(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => JsonValue::from(u),
+            (None, Some(i)) => JsonValue::from(i),
+            _ => JsonValue::Null,
+        },
+        MsgPackValue::F32(f) => JsonValue::from(f as f64),
+        MsgPackValue::F64(f) => JsonValue::from(f),
+        MsgPackValue::String(s) => {
+            JsonValue::String(String::from_utf8_lossy(s.as_bytes()).into_owned())
         }
+        MsgPackValue::Binary(bytes) => {
+            attachments.push(Bytes::from(bytes));
+            binary_placeholder(attachments.len() - 1)
+        }
+        MsgPackValue::Array(items) => JsonValue::Array(
+            items
+                .into_iter()
+                .map(|item| msgpack_to_json(item, attachments))
+                .collect(),
+        ),
+        MsgPackValue::Map(fields) => JsonValue::Object(
+            fields
+                .into_iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        MsgPackValue::String(s) => {
+                            String::from_utf8_lossy(s.as_bytes()).into_owned()
+                        }
+                        other => other.to_string(),
+                    };
+                    (key, msgpack_to_json(v, attachments))
+                })
+                .collect(),
+        ),
+    }
+}
 
-        let event = arr[0].as_str()?.to_string();
-        let event_data = arr[1].clone();
+/// Reassembles binary packets from the packet and the attachments that follow it
+#[derive(Debug, Default)]
+pub struct BinaryReassembler {
+    pending: Option<SocketPacket>,
+}
+
+impl BinaryReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a decoded packet. Returns it right away unless it still waits for
+    /// attachments.
+    pub fn push_packet(&mut self, packet: SocketPacket) -> Option<SocketPacket> {
+        if self.pending.take().is_some() {
+            tracing::warn!("Discarding binary packet with missing attachments");
+        }
+        if packet.is_binary() && packet.attachment_count > 0 {
+            self.pending = Some(packet);
+            None
+        } else {
+            Some(packet)
+        }
+    }
+
+    /// Whether a packet is waiting for attachments, so the next binary packet is one
+    pub fn is_waiting(&self) -> bool {
+        self.pending.is_some()
+    }
 
-        Some((event, event_data))
+    /// Feed a binary attachment. Returns the packet once all of its attachments have
+    /// arrived.
+    pub fn push_attachment(&mut self, bytes: Bytes) -> Option<SocketPacket> {
+        let packet = match self.pending.as_mut() {
+            Some(packet) => packet,
+            None => {
+                tracing::debug!("Ignoring binary attachment without a pending packet");
+                return None;
+            }
+        };
+        packet.attachments.push(bytes);
+        if packet.attachments.len() < packet.attachment_count {
+            return None;
+        }
+
+        self.pending.take()
     }
 }
 
@@ -498,6 +990,19 @@ mod tests {
         assert_eq!(data, serde_json::json!({"message": "hello"}));
     }
 
+    #[test]
+    fn test_recoverable_event_carries_offset_last() {
+        let packet = SocketPacket::event_with_offset("/", "chat-events", serde_json::json!(1), 42);
+        assert_eq!(packet.encode(), r#"2["chat-events",1,"42"]"#);
+
+        let connect = SocketPacket::connect_recoverable("/", "sid-1", "pid-1");
+        let decoded = SocketPacket::decode(&connect.encode()).unwrap();
+        assert_eq!(
+            decoded.data,
+            Some(serde_json::json!({"sid": "sid-1", "pid": "pid-1"}))
+        );
+    }
+
     #[test]
     fn test_socket_packet_with_namespace() {
         let packet = SocketPacket::event("/admin", "test", serde_json::json!({"data": 123}));
@@ -507,4 +1012,312 @@ mod tests {
         let decoded = SocketPacket::decode(&encoded).unwrap();
         assert_eq!(decoded.namespace, "/admin");
     }
+
+    #[test]
+    fn test_binary_event_websocket_round_trip() {
+        let packet = SocketPacket::binary_event(
+            "/",
+            "ydoc:document:update",
+            serde_json::json!({"document_id": "note-1", "update": binary_placeholder(0)}),
+            vec![Bytes::from_static(&[1, 2, 255])],
+        );
+        let frames = packet.into_frames();
+        assert_eq!(
+            frames[0],
+            WsFrame::Text(
+                r#"451-["ydoc:document:update",{"document_id":"note-1","update":{"_placeholder":true,"num":0}}]"#
+                    .to_string()
+            )
+        );
+        assert_eq!(frames[1], WsFrame::Binary(Bytes::from_static(&[1, 2, 255])));
+
+        let mut reassembler = BinaryReassembler::new();
+        let mut received = None;
+        for frame in frames {
+            received = match frame {
+                WsFrame::Text(text) => {
+                    let engine_packet = EnginePacket::decode(&text).unwrap();
+                    let packet =
+                        SocketPacket::decode(&String::from_utf8_lossy(&engine_packet.data))
+                            .unwrap();
+                    assert_eq!(packet.attachment_count, 1);
+                    reassembler.push_packet(packet)
+                }
+                WsFrame::Binary(bytes) => reassembler.push_attachment(bytes),
+            };
+        }
+
+        let (event, data) = received.unwrap().get_event().unwrap();
+        assert_eq!(event, "ydoc:document:update");
+        assert_eq!(
+            data,
+            serde_json::json!({"document_id": "note-1", "update": [1, 2, 255]})
+        );
+    }
+
+    #[test]
+    fn test_binary_event_polling_round_trip() {
+        let packet = SocketPacket::binary_event(
+            "/admin",
+            "file:preview",
+            serde_json::json!([binary_placeholder(0), {"name": "a.png", "thumb": binary_placeholder(1)}]),
+            vec![
+                Bytes::from_static(&[0, 1]),
+                Bytes::from_static(&[137, 80, 78, 71]),
+            ],
+        );
+        let payload = packet
+            .to_polling_packets()
+            .join(&POLLING_SEPARATOR.to_string());
+        assert!(payload.starts_with("452-/admin,[\"file:preview\""));
+        assert!(payload.ends_with("\x1ebAAE=\x1ebiVBORw=="));
+
+        let mut reassembler = BinaryReassembler::new();
+        let mut received = None;
+        for polling_packet in decode_polling_payload(&payload) {
+            received = match polling_packet {
+                PollingPacket::Text(text) => {
+                    let engine_packet = EnginePacket::decode(&text).unwrap();
+                    let packet =
+                        SocketPacket::decode(&String::from_utf8_lossy(&engine_packet.data))
+                            .unwrap();
+                    reassembler.push_packet(packet)
+                }
+                PollingPacket::Binary(bytes) => reassembler.push_attachment(Bytes::from(bytes)),
+            };
+        }
+
+        let packet = received.unwrap();
+        assert_eq!(packet.namespace, "/admin");
+        assert_eq!(packet.attachments.len(), 2);
+        let (_, data) = packet.get_event().unwrap();
+        assert_eq!(
+            data,
+            serde_json::json!([[0, 1], {"name": "a.png", "thumb": [137, 80, 78, 71]}])
+        );
+    }
+
+    fn decode_message(text: &str) -> SocketPacket {
+        let engine_packet = EnginePacket::decode(text).unwrap();
+        SocketPacket::decode(&String::from_utf8_lossy(&engine_packet.data)).unwrap()
+    }
+
+    #[test]
+    fn test_ack_id_websocket_round_trip() {
+        let packet = SocketPacket::event_with_ack(
+            "/",
+            "chat-events",
+            serde_json::json!({"chat_id": "c1"}),
+            12,
+        );
+        let frames = packet.into_frames();
+        assert_eq!(
+            frames,
+            vec![WsFrame::Text(
+                r#"4212["chat-events",{"chat_id":"c1"}]"#.to_string()
+            )]
+        );
+
+        // The client's ACK names the same ID
+        let ack = decode_message(r#"4312["ok"]"#);
+        assert_eq!(ack.packet_type, SocketPacketType::Ack);
+        assert_eq!(ack.id, Some(12));
+        assert_eq!(ack.data, Some(serde_json::json!(["ok"])));
+
+        // ACK without arguments, in a namespace
+        let ack = decode_message("43/admin,7[]");
+        assert_eq!(ack.namespace, "/admin");
+        assert_eq!(ack.id, Some(7));
+        assert_eq!(ack.data, Some(serde_json::json!([])));
+    }
+
+    #[test]
+    fn test_ack_id_polling_round_trip() {
+        let packet = SocketPacket::event_with_ack("/admin", "ping", serde_json::json!(1), 345);
+        assert_eq!(
+            packet.to_polling_packets(),
+            vec![r#"42/admin,345["ping",1]"#]
+        );
+
+        // A binary ACK and a plain one in the same payload
+        let payload = [
+            r#"461-3[{"_placeholder":true,"num":0}]"#,
+            "bAQI=",
+            "43345[true]",
+        ]
+        .join(&POLLING_SEPARATOR.to_string());
+        let mut reassembler = BinaryReassembler::new();
+        let mut acks = Vec::new();
+        for polling_packet in decode_polling_payload(&payload) {
+            let complete = match polling_packet {
+                PollingPacket::Text(text) => reassembler.push_packet(decode_message(&text)),
+                PollingPacket::Binary(bytes) => reassembler.push_attachment(Bytes::from(bytes)),
+            };
+            acks.extend(complete);
+        }
+
+        assert_eq!(acks.len(), 2);
+        assert_eq!(acks[0].packet_type, SocketPacketType::BinaryAck);
+        assert_eq!(acks[0].id, Some(3));
+        assert_eq!(acks[0].filled_data(), Some(serde_json::json!([[1, 2]])));
+        assert_eq!(acks[1].packet_type, SocketPacketType::Ack);
+        assert_eq!(acks[1].id, Some(345));
+        assert_eq!(acks[1].data, Some(serde_json::json!([true])));
+    }
+
+    #[test]
+    fn test_ack_tracker_is_send_and_sync() {
+        // The tracker lives in AppState, which is shared across spawned tasks
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<AckTracker>();
+    }
+
+    #[tokio::test]
+    async fn test_ack_tracker_matches_session() {
+        let tracker = AckTracker::new();
+        let timeout = std::time::Duration::from_secs(5);
+        let (id, reply) = tracker.expect_ack("sid-a", timeout).await;
+
+        // Another session can't answer for it
+        assert!(
+            !tracker
+                .process_session_ack("sid-b", id, serde_json::json!([]))
+                .await
+        );
+        assert!(
+            tracker
+                .process_session_ack("sid-a", id, serde_json::json!(["done"]))
+                .await
+        );
+        assert_eq!(reply.await.unwrap(), serde_json::json!(["done"]));
+
+        // Answered once only
+        assert!(
+            !tracker
+                .process_session_ack("sid-a", id, serde_json::json!([]))
+                .await
+        );
+
+        let (id, _reply) = tracker.expect_ack("sid-a", timeout).await;
+        tracker.cancel(id).await;
+        assert_eq!(tracker.pending_count().await, 0);
+    }
+
+    #[test]
+    fn test_js_client_binary_event_exposes_attachments() {
+        // socket.emit("audio:chunk", { seq: 1, chunk: new Uint8Array([1, 2, 3]) }, ack)
+        let mut reassembler = BinaryReassembler::new();
+        let header = decode_message(
+            r#"451-0["audio:chunk",{"seq":1,"chunk":{"_placeholder":true,"num":0}}]"#,
+        );
+        assert!(reassembler.push_packet(header).is_none());
+        assert!(reassembler.is_waiting());
+        let packet = reassembler
+            .push_attachment(Bytes::from_static(&[1, 2, 3]))
+            .unwrap();
+        assert_eq!(packet.id, Some(0));
+
+        let (event, data, attachments) = packet.into_event().unwrap();
+        assert_eq!(event, "audio:chunk");
+        assert_eq!(data["seq"], 1);
+        assert_eq!(
+            binary_value(&data["chunk"], &attachments).unwrap(),
+            Bytes::from_static(&[1, 2, 3])
+        );
+        // Byte arrays from clients without binary support work too
+        assert_eq!(
+            binary_value(&serde_json::json!([1, 2, 3]), &[]).unwrap(),
+            Bytes::from_static(&[1, 2, 3])
+        );
+        assert!(binary_value(&serde_json::json!([1, 256]), &[]).is_none());
+    }
+
+    #[test]
+    fn test_msgpack_js_client_packets() {
+        // socket.emit("hello", "world") with socket.io-msgpack-parser
+        let packet = SocketPacket::decode_msgpack(
+            b"\x84\xa4type\x02\xa4data\x92\xa5hello\xa5world\
+              \xa7options\x81\xa8compress\xc3\xa3nsp\xa1/",
+        )
+        .unwrap();
+        assert_eq!(packet.packet_type, SocketPacketType::Event);
+        assert_eq!(packet.namespace, "/");
+        assert_eq!(
+            packet.get_event().unwrap(),
+            ("hello".to_string(), serde_json::json!("world"))
+        );
+
+        // socket.emit("audio:chunk", { seq: 1, chunk: new Uint8Array([1, 2, 3]) }, ack)
+        let packet = SocketPacket::decode_msgpack(
+            b"\x85\xa4type\x02\xa4data\x92\xabaudio:chunk\x82\xa3seq\x01\
+              \xa5chunk\xc4\x03\x01\x02\x03\xa7options\x81\xa8compress\xc3\
+              \xa2id\x00\xa3nsp\xa1/",
+        )
+        .unwrap();
+        assert_eq!(packet.packet_type, SocketPacketType::BinaryEvent);
+        assert_eq!(packet.id, Some(0));
+        let (event, data, attachments) = packet.into_event().unwrap();
+        assert_eq!(event, "audio:chunk");
+        assert_eq!(data["chunk"], binary_placeholder(0));
+        assert_eq!(attachments, vec![Bytes::from_static(&[1, 2, 3])]);
+
+        // CONNECT with auth
+        let packet = SocketPacket::decode_msgpack(
+            b"\x83\xa4type\x00\xa4data\x81\xa5token\xa3abc\xa3nsp\xa1/",
+        )
+        .unwrap();
+        assert_eq!(packet.packet_type, SocketPacketType::Connect);
+        assert_eq!(packet.data, Some(serde_json::json!({"token": "abc"})));
+
+        assert!(SocketPacket::decode_msgpack(b"\x92\x01\x02").is_err());
+    }
+
+    #[test]
+    fn test_msgpack_encoding() {
+        let packet = SocketPacket::event("/", "usage", serde_json::json!({"n": 1}));
+        assert_eq!(
+            packet.encode_msgpack(),
+            b"\x83\xa4type\x02\xa3nsp\xa1/\xa4data\x92\xa5usage\x81\xa1n\x01".to_vec()
+        );
+
+        // Attachments travel inline, on the websocket as a single binary frame
+        let packet = SocketPacket::binary_event(
+            "/",
+            "ydoc:document:update",
+            serde_json::json!({"update": binary_placeholder(0)}),
+            vec![Bytes::from_static(&[7, 8])],
+        );
+        let frames = packet.clone().into_frames_for(Parser::MsgPack);
+        assert_eq!(frames.len(), 1);
+        let bytes = match &frames[0] {
+            WsFrame::Binary(bytes) => bytes.clone(),
+            other => panic!("expected a binary frame, got {:?}", other),
+        };
+        let decoded = SocketPacket::decode_msgpack(&bytes).unwrap();
+        assert_eq!(decoded.packet_type, SocketPacketType::BinaryEvent);
+        assert_eq!(decoded.get_event(), packet.get_event());
+
+        // ...and base64-encoded in a polling payload
+        let polling = SocketPacket::event_with_ack("/admin", "ping", serde_json::json!(1), 9)
+            .to_polling_packets_for(Parser::MsgPack)
+            .join(&POLLING_SEPARATOR.to_string());
+        let bytes = match decode_polling_payload(&polling).pop() {
+            Some(PollingPacket::Binary(bytes)) => bytes,
+            other => panic!("expected a binary packet, got {:?}", other),
+        };
+        let decoded = SocketPacket::decode_msgpack(&bytes).unwrap();
+        assert_eq!(decoded.namespace, "/admin");
+        assert_eq!(decoded.id, Some(9));
+        assert_eq!(decoded.data, Some(serde_json::json!(["ping", 1])));
+    }
+
+    #[test]
+    fn test_plain_packets_pass_through_reassembler() {
+        let mut reassembler = BinaryReassembler::new();
+        let packet = SocketPacket::event("/", "usage", serde_json::json!({}));
+        assert!(reassembler.push_packet(packet).is_some());
+        assert!(reassembler
+            .push_attachment(Bytes::from_static(&[1]))
+            .is_none());
+    }
 }
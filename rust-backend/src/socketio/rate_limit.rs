@@ -17,8 +17,23 @@ pub struct RateLimitConfig {
     pub max_queue_size: usize,
     /// Burst allowance (extra events allowed in short bursts)
     pub burst_allowance: usize,
+    /// Events per window for particular event names, each counted separately from the
+    /// others; events not listed share `max_events`
+    pub event_limits: HashMap<String, usize>,
 }
 
+/// Per-event limits for events clients send often: typing notices are cheap to drop,
+/// usage pings come every second while a model runs, and collaborative editing sends
+/// a document update per keystroke
+const DEFAULT_EVENT_LIMITS: &[(&str, usize)] = &[
+    ("typing", 30),
+    ("typing:start", 30),
+    ("typing:stop", 30),
+    ("usage", 120),
+    ("ydoc:document:update", 1200),
+    ("ydoc:awareness:update", 1200),
+];
+
 impl Default for RateLimitConfig {
     fn default() -> Self {
         Self {
@@ -26,6 +41,10 @@ impl Default for RateLimitConfig {
             window_duration: Duration::from_secs(60), // per minute
             max_queue_size: 1000,                     // 1000 queued messages max
             burst_allowance: 20,                      // Allow 20 extra events in bursts
+            event_limits: DEFAULT_EVENT_LIMITS
+                .iter()
+                .map(|(event, limit)| (event.to_string(), *limit))
+                .collect(),
         }
     }
 }
@@ -62,6 +81,19 @@ impl TokenBucket {
         self.last_refill = now;
     }
 
+    /// Change the capacity to `capacity` per `window_duration`; tokens already
+    /// spent stay spent
+    fn resize(&mut self, capacity: usize, window_duration: Duration) {
+        let capacity = capacity as f64;
+        if capacity == self.capacity {
+            return;
+        }
+        self.refill();
+        self.tokens = (self.tokens + capacity - self.capacity).clamp(0.0, capacity);
+        self.capacity = capacity;
+        self.refill_rate = capacity / window_duration.as_secs_f64();
+    }
+
     fn try_consume(&mut self, count: usize) -> bool {
         self.refill();
 
@@ -78,6 +110,21 @@ impl TokenBucket {
         self.refill();
         self.tokens.floor() as usize
     }
+
+    /// Time until `count` tokens are available
+    fn wait_time(&self, count: usize) -> Duration {
+        let missing = (count as f64 - self.tokens).max(0.0);
+        // A limit of zero never refills
+        Duration::try_from_secs_f64(missing / self.refill_rate).unwrap_or(Duration::MAX)
+    }
+}
+
+/// A session's bucket for one event name (or the shared one), remembering whether it
+/// refused the last event so the client is warned once per run of dropped events
+#[derive(Debug, Clone)]
+struct EventBucket {
+    bucket: TokenBucket,
+    refusing: bool,
 }
 
 /// Rate limiter for Socket.IO events
@@ -87,6 +134,8 @@ pub struct RateLimiter {
     buckets: Arc<RwLock<HashMap<String, TokenBucket>>>,
     /// Per-session queue sizes
     queue_sizes: Arc<RwLock<HashMap<String, usize>>>,
+    /// Per-session event buckets: sid -> event name ("" for the shared bucket) -> bucket
+    event_buckets: Arc<RwLock<HashMap<String, HashMap<String, EventBucket>>>>,
 }
 
 impl RateLimiter {
@@ -95,7 +144,39 @@ impl RateLimiter {
             config,
             buckets: Arc::new(RwLock::new(HashMap::new())),
             queue_sizes: Arc::new(RwLock::new(HashMap::new())),
+            event_buckets: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Count an incoming `event` from session `sid` against its limit
+    pub async fn check_event(&self, sid: &str, event: &str) -> Result<(), RateLimitError> {
+        let (name, capacity) = match self.config.event_limits.get(event) {
+            Some(limit) => (event, *limit),
+            None => ("", self.config.max_events + self.config.burst_allowance),
+        };
+
+        let mut sessions = self.event_buckets.write().await;
+        let entry = sessions
+            .entry(sid.to_string())
+            .or_default()
+            .entry(name.to_string())
+            .or_insert_with(|| EventBucket {
+                bucket: TokenBucket::new(capacity, self.config.window_duration),
+                refusing: false,
+            });
+
+        if entry.bucket.try_consume(1) {
+            entry.refusing = false;
+            return Ok(());
         }
+        let repeated = entry.refusing;
+        entry.refusing = true;
+        Err(RateLimitError::EventLimitExceeded {
+            sid: sid.to_string(),
+            event: event.to_string(),
+            retry_after: entry.bucket.wait_time(1),
+            repeated,
+        })
     }
 
     /// Check if an event is allowed for a user
@@ -103,15 +184,29 @@ impl RateLimiter {
         &self,
         user_id: &str,
         event_count: usize,
+    ) -> Result<(), RateLimitError> {
+        self.check_rate_limit_at(
+            user_id,
+            event_count,
+            self.config.max_events + self.config.burst_allowance,
+        )
+        .await
+    }
+
+    /// Check if an event is allowed for a user under a limit of `max_events` per
+    /// window, for limits that can change while the server runs
+    pub async fn check_rate_limit_at(
+        &self,
+        user_id: &str,
+        event_count: usize,
+        max_events: usize,
     ) -> Result<(), RateLimitError> {
         let mut buckets = self.buckets.write().await;
 
-        let bucket = buckets.entry(user_id.to_string()).or_insert_with(|| {
-            TokenBucket::new(
-                self.config.max_events + self.config.burst_allowance,
-                self.config.window_duration,
-            )
-        });
+        let bucket = buckets
+            .entry(user_id.to_string())
+            .or_insert_with(|| TokenBucket::new(max_events, self.config.window_duration));
+        bucket.resize(max_events, self.config.window_duration);
 
         if bucket.try_consume(event_count) {
             Ok(())
@@ -159,10 +254,18 @@ impl RateLimiter {
         }
     }
 
+    /// Frames queued for a session
+    #[cfg(test)]
+    pub async fn queue_size(&self, sid: &str) -> usize {
+        self.queue_sizes.read().await.get(sid).copied().unwrap_or(0)
+    }
+
     /// Remove session from tracking
     pub async fn remove_session(&self, sid: &str) {
         let mut queue_sizes = self.queue_sizes.write().await;
         queue_sizes.remove(sid);
+        drop(queue_sizes);
+        self.event_buckets.write().await.remove(sid);
     }
 
     /// Remove user from tracking
@@ -192,6 +295,13 @@ impl RateLimiter {
         let now = Instant::now();
 
         buckets.retain(|_, bucket| now.duration_since(bucket.last_refill) < idle_threshold);
+        drop(buckets);
+
+        let mut sessions = self.event_buckets.write().await;
+        for events in sessions.values_mut() {
+            events.retain(|_, entry| now.duration_since(entry.bucket.last_refill) < idle_threshold);
+        }
+        sessions.retain(|_, events| !events.is_empty());
     }
 }
 
@@ -215,6 +325,18 @@ pub enum RateLimitError {
         requested: usize,
     },
 
+    /// `repeated` is set when the previous event counted in the same bucket was
+    /// refused too
+    #[error(
+        "Rate limit exceeded for {event} events from session {sid}, retry after {retry_after:?}"
+    )]
+    EventLimitExceeded {
+        sid: String,
+        event: String,
+        retry_after: Duration,
+        repeated: bool,
+    },
+
     #[error("Queue full for session {sid}: {current_size}/{max_size}")]
     QueueFull {
         sid: String,
@@ -234,6 +356,7 @@ mod tests {
             window_duration: Duration::from_secs(1),
             max_queue_size: 100,
             burst_allowance: 5,
+            ..RateLimitConfig::default()
         };
 
         let limiter = RateLimiter::new(config);
@@ -253,6 +376,29 @@ mod tests {
         assert!(limiter.check_rate_limit("user-1", 1).await.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_rate_limit_follows_a_changed_limit() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            window_duration: Duration::from_secs(60),
+            ..RateLimitConfig::default()
+        });
+
+        for _ in 0..2 {
+            assert!(limiter.check_rate_limit_at("user-1", 1, 2).await.is_ok());
+        }
+        assert!(limiter.check_rate_limit_at("user-1", 1, 2).await.is_err());
+
+        // Raising the limit grants the difference; the two used stay used
+        for _ in 0..3 {
+            assert!(limiter.check_rate_limit_at("user-1", 1, 5).await.is_ok());
+        }
+        assert!(limiter.check_rate_limit_at("user-1", 1, 5).await.is_err());
+
+        // Lowering it takes effect on the next check
+        assert!(limiter.check_rate_limit_at("user-2", 1, 5).await.is_ok());
+        assert!(limiter.check_rate_limit_at("user-2", 1, 1).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_queue_backpressure() {
         let config = RateLimitConfig {
@@ -260,6 +406,7 @@ mod tests {
             window_duration: Duration::from_secs(60),
             max_queue_size: 10,
             burst_allowance: 0,
+            ..RateLimitConfig::default()
         };
 
         let limiter = RateLimiter::new(config);
@@ -278,6 +425,73 @@ mod tests {
         assert!(limiter.check_queue_size(sid).await.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_event_limits() {
+        let config = RateLimitConfig {
+            max_events: 3,
+            window_duration: Duration::from_secs(60),
+            max_queue_size: 10,
+            burst_allowance: 0,
+            event_limits: HashMap::from([("typing".to_string(), 2)]),
+        };
+        let limiter = RateLimiter::new(config);
+
+        // Typing has a bucket of its own
+        assert!(limiter.check_event("s1", "typing").await.is_ok());
+        assert!(limiter.check_event("s1", "typing").await.is_ok());
+        match limiter.check_event("s1", "typing").await {
+            Err(RateLimitError::EventLimitExceeded {
+                retry_after,
+                repeated,
+                ..
+            }) => {
+                assert!(!repeated);
+                assert!(retry_after > Duration::from_secs(20));
+            }
+            other => panic!("expected the typing limit, got {:?}", other),
+        }
+        match limiter.check_event("s1", "typing").await {
+            Err(RateLimitError::EventLimitExceeded { repeated, .. }) => assert!(repeated),
+            other => panic!("expected the typing limit, got {:?}", other),
+        }
+
+        // Other events share the default bucket, untouched by typing
+        for event in ["chat-events", "usage-custom", "channel-events"] {
+            assert!(limiter.check_event("s1", event).await.is_ok());
+        }
+        assert!(limiter.check_event("s1", "chat-events").await.is_err());
+
+        // Sessions are limited independently
+        assert!(limiter.check_event("s2", "typing").await.is_ok());
+        assert!(limiter.check_event("s2", "chat-events").await.is_ok());
+
+        limiter.remove_session("s1").await;
+        assert!(limiter.check_event("s1", "typing").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_event_limits_refill_over_the_window() {
+        let config = RateLimitConfig {
+            window_duration: Duration::from_millis(200),
+            event_limits: HashMap::from([("typing".to_string(), 2)]),
+            ..RateLimitConfig::default()
+        };
+        let limiter = RateLimiter::new(config);
+
+        assert!(limiter.check_event("s1", "typing").await.is_ok());
+        assert!(limiter.check_event("s1", "typing").await.is_ok());
+        assert!(limiter.check_event("s1", "typing").await.is_err());
+
+        // A full window refills the bucket, up to the limit
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert!(limiter.check_event("s1", "typing").await.is_ok());
+        assert!(limiter.check_event("s1", "typing").await.is_ok());
+        match limiter.check_event("s1", "typing").await {
+            Err(RateLimitError::EventLimitExceeded { repeated, .. }) => assert!(!repeated),
+            other => panic!("expected the typing limit, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_token_bucket() {
         let mut bucket = TokenBucket::new(10, Duration::from_secs(1));
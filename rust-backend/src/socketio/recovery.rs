@@ -1,14 +1,22 @@
 /// Connection Recovery for Socket.IO
 ///
-/// Handles session persistence, reconnection, and state recovery
+/// Handles session persistence, reconnection, and state recovery.
+///
+/// Connection state recovery follows Socket.IO v4: the CONNECT response gives the
+/// client a private session ID (`pid`), and every recoverable event carries an offset
+/// as its last argument. A client reconnecting within the recovery window sends the
+/// pid and the last offset it saw, gets its user and rooms back, and is replayed the
+/// events it missed. Recovery state lives in this server's memory.
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 
+use crate::utils::redis_health::RedisPool;
+
 /// Recovery token for reconnection
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RecoveryToken {
@@ -101,13 +109,96 @@ impl RecoveryState {
     }
 }
 
+/// An event kept for replay, numbered by the offset sent along with it
+#[derive(Debug, Clone)]
+pub struct ReplayEvent {
+    pub offset: u64,
+    pub event: String,
+    pub data: JsonValue,
+    /// Approximate memory held by the event
+    size: usize,
+}
+
+/// A Socket.IO session that can be resumed with its private ID
+#[derive(Debug)]
+struct RecoverableSession {
+    /// Engine.IO sid of the live connection, `None` while disconnected
+    sid: Option<String>,
+    user: Option<JsonValue>,
+    rooms: Vec<String>,
+    events: VecDeque<ReplayEvent>,
+    bytes: usize,
+    next_offset: u64,
+    /// Highest offset dropped to stay within the buffer limits; clients that saw
+    /// less than this have a gap and can't be recovered
+    dropped_through: u64,
+    disconnected_at: Option<Instant>,
+}
+
+impl RecoverableSession {
+    fn user_id(&self) -> Option<&str> {
+        self.user
+            .as_ref()
+            .and_then(|u| u.get("id"))
+            .and_then(|id| id.as_str())
+    }
+
+    fn is_expired(&self, window: Duration) -> bool {
+        self.disconnected_at
+            .is_some_and(|disconnected_at| disconnected_at.elapsed() > window)
+    }
+
+    /// Buffer an event, dropping the oldest ones past `max_events` or `max_bytes`
+    fn push(&mut self, event: &str, data: JsonValue, max_events: usize, max_bytes: usize) -> u64 {
+        let offset = self.next_offset;
+        self.next_offset += 1;
+
+        let size = event.len() + serde_json::to_vec(&data).map_or(0, |json| json.len());
+        self.events.push_back(ReplayEvent {
+            offset,
+            event: event.to_string(),
+            data,
+            size,
+        });
+        self.bytes += size;
+
+        while self.events.len() > max_events || self.bytes > max_bytes {
+            match self.events.pop_front() {
+                Some(dropped) => {
+                    self.bytes -= dropped.size;
+                    self.dropped_through = dropped.offset;
+                }
+                None => break,
+            }
+        }
+        offset
+    }
+}
+
+#[derive(Default)]
+struct RecoverableSessions {
+    by_pid: HashMap<String, RecoverableSession>,
+    pid_by_sid: HashMap<String, String>,
+}
+
+/// User and rooms of a resumed session, with the events its client missed
+#[derive(Debug)]
+pub struct RecoveredSession {
+    pub user: Option<JsonValue>,
+    pub rooms: Vec<String>,
+    pub events: Vec<ReplayEvent>,
+}
+
 /// Connection recovery manager
 pub struct RecoveryManager {
     /// In-memory recovery states
     states: Arc<RwLock<std::collections::HashMap<String, RecoveryState>>>,
 
+    /// Sessions resumable through connection state recovery
+    recoverable: Arc<RwLock<RecoverableSessions>>,
+
     /// Redis connection pool for persistence
-    redis: Option<deadpool_redis::Pool>,
+    redis: Option<RedisPool>,
 
     /// Redis key prefix
     redis_prefix: String,
@@ -127,6 +218,16 @@ pub struct RecoveryConfig {
 
     /// How long recovery tokens are valid (seconds)
     pub token_ttl: u64,
+
+    /// How long a disconnected session can be resumed; zero disables connection
+    /// state recovery
+    pub recovery_window: Duration,
+
+    /// Most events kept for replay per session
+    pub max_replay_events: usize,
+
+    /// Most bytes of events kept for replay per session
+    pub max_replay_bytes: usize,
 }
 
 impl Default for RecoveryConfig {
@@ -135,20 +236,158 @@ impl Default for RecoveryConfig {
             max_buffered_messages: 100,
             state_ttl: 300, // 5 minutes
             token_ttl: 300, // 5 minutes
+            recovery_window: Duration::from_secs(120),
+            max_replay_events: 2000,
+            max_replay_bytes: 1024 * 1024,
         }
     }
 }
 
 impl RecoveryManager {
-    pub fn new(redis: Option<deadpool_redis::Pool>, config: RecoveryConfig) -> Self {
+    pub fn new(redis: Option<RedisPool>, config: RecoveryConfig) -> Self {
         Self {
             states: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            recoverable: Arc::new(RwLock::new(RecoverableSessions::default())),
             redis,
             redis_prefix: "socketio:recovery".to_string(),
             config,
         }
     }
 
+    /// Make the session on `sid` resumable, returning its private ID for the CONNECT
+    /// response. `None` when recovery is disabled.
+    pub async fn open_session(&self, sid: &str) -> Option<String> {
+        if self.config.recovery_window.is_zero() {
+            return None;
+        }
+        let pid = uuid::Uuid::new_v4().to_string();
+        let mut sessions = self.recoverable.write().await;
+        sessions.by_pid.insert(
+            pid.clone(),
+            RecoverableSession {
+                sid: Some(sid.to_string()),
+                user: None,
+                rooms: Vec::new(),
+                events: VecDeque::new(),
+                bytes: 0,
+                next_offset: 1,
+                dropped_through: 0,
+                disconnected_at: None,
+            },
+        );
+        sessions.pid_by_sid.insert(sid.to_string(), pid.clone());
+        Some(pid)
+    }
+
+    /// Buffer a recoverable event for the live session on `sid`, returning the offset
+    /// to send with it. `None` when the session isn't resumable.
+    pub async fn record_event(&self, sid: &str, event: &str, data: &JsonValue) -> Option<u64> {
+        let mut sessions = self.recoverable.write().await;
+        let pid = sessions.pid_by_sid.get(sid)?.clone();
+        let session = sessions.by_pid.get_mut(&pid)?;
+        Some(session.push(
+            event,
+            data.clone(),
+            self.config.max_replay_events,
+            self.config.max_replay_bytes,
+        ))
+    }
+
+    /// Buffer a recoverable event for the user's disconnected sessions that can
+    /// still be resumed, returning how many there are
+    pub async fn record_for_disconnected_user(
+        &self,
+        user_id: &str,
+        event: &str,
+        data: &JsonValue,
+    ) -> usize {
+        let mut sessions = self.recoverable.write().await;
+        let mut buffered = 0;
+        for session in sessions.by_pid.values_mut() {
+            if session.sid.is_none()
+                && session.user_id() == Some(user_id)
+                && !session.is_expired(self.config.recovery_window)
+            {
+                session.push(
+                    event,
+                    data.clone(),
+                    self.config.max_replay_events,
+                    self.config.max_replay_bytes,
+                );
+                buffered += 1;
+            }
+        }
+        buffered
+    }
+
+    /// Keep the session of a closed connection resumable for the recovery window
+    pub async fn session_disconnected(
+        &self,
+        sid: &str,
+        user: Option<JsonValue>,
+        rooms: Vec<String>,
+    ) {
+        let mut sessions = self.recoverable.write().await;
+        let pid = match sessions.pid_by_sid.remove(sid) {
+            Some(pid) => pid,
+            None => return,
+        };
+        if let Some(session) = sessions.by_pid.get_mut(&pid) {
+            session.sid = None;
+            session.user = user;
+            session.rooms = rooms;
+            session.disconnected_at = Some(Instant::now());
+        }
+    }
+
+    /// Resume the session `pid` on the new connection `sid`, with the events after
+    /// `offset`. Fails when the session is unknown or expired, or when events the
+    /// client hasn't seen were already dropped; the client then starts afresh.
+    pub async fn recover_session(
+        &self,
+        sid: &str,
+        pid: &str,
+        offset: &str,
+    ) -> Result<RecoveredSession, String> {
+        let offset: u64 = offset
+            .parse()
+            .map_err(|_| format!("invalid offset {:?}", offset))?;
+
+        let mut sessions = self.recoverable.write().await;
+        let session = match sessions.by_pid.get_mut(pid) {
+            Some(session) => session,
+            None => return Err("unknown session".to_string()),
+        };
+        if session.is_expired(self.config.recovery_window) {
+            sessions.by_pid.remove(pid);
+            return Err("recovery window expired".to_string());
+        }
+        if offset < session.dropped_through || offset >= session.next_offset {
+            sessions.by_pid.remove(pid);
+            return Err(format!("offset {} no longer buffered", offset));
+        }
+
+        // The old connection may not have been noticed as closed yet
+        let previous_sid = session.sid.replace(sid.to_string());
+        session.disconnected_at = None;
+        let recovered = RecoveredSession {
+            user: session.user.clone(),
+            rooms: session.rooms.clone(),
+            events: session
+                .events
+                .iter()
+                .filter(|e| e.offset > offset)
+                .cloned()
+                .collect(),
+        };
+
+        if let Some(previous_sid) = previous_sid {
+            sessions.pid_by_sid.remove(&previous_sid);
+        }
+        sessions.pid_by_sid.insert(sid.to_string(), pid.to_string());
+        Ok(recovered)
+    }
+
     /// Save session state for recovery
     pub async fn save_state(
         &self,
@@ -167,7 +406,7 @@ impl RecoveryManager {
 
         // Save to Redis if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let key = format!("{}:{}", self.redis_prefix, session_id);
                 let serialized = serde_json::to_string(&state).map_err(|e| e.to_string())?;
                 let _ = conn
@@ -221,6 +460,51 @@ impl RecoveryManager {
         }
     }
 
+    /// Buffer a message for a user with no live sessions.
+    /// The message goes to the user's most recently disconnected session only,
+    /// so it is replayed once. Returns false if the user has no recovery state.
+    pub async fn buffer_user_message(&self, user_id: &str, event: String, data: JsonValue) -> bool {
+        let session_id = {
+            let states = self.states.read().await;
+            states
+                .values()
+                .filter(|s| s.user_id == user_id)
+                .max_by_key(|s| s.last_seen)
+                .map(|s| s.session_id.clone())
+        };
+
+        match session_id {
+            Some(sid) => self.buffer_message(&sid, event, data).await.is_ok(),
+            None => false,
+        }
+    }
+
+    /// Drain buffered messages from all of a user's recovery states, oldest first,
+    /// and drop those states.
+    pub async fn take_user_messages(&self, user_id: &str) -> Vec<BufferedMessage> {
+        let mut states = self.states.write().await;
+
+        let session_ids: Vec<String> = states
+            .values()
+            .filter(|s| s.user_id == user_id)
+            .map(|s| s.session_id.clone())
+            .collect();
+
+        let mut user_states: Vec<RecoveryState> = session_ids
+            .iter()
+            .filter_map(|sid| states.remove(sid))
+            .collect();
+        drop(states);
+
+        user_states.sort_by_key(|s| s.last_seen);
+
+        let mut messages = Vec::new();
+        for state in user_states {
+            messages.extend(state.buffered_messages);
+        }
+        messages
+    }
+
     /// Generate a recovery token for a session
     pub async fn generate_token(&self, session_id: &str, user_id: &str) -> Result<String, String> {
         let token = RecoveryToken::new(
@@ -231,7 +515,7 @@ impl RecoveryManager {
 
         // Store token in Redis if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let key = format!("{}:token:{}", self.redis_prefix, session_id);
                 let serialized = serde_json::to_string(&token).map_err(|e| e.to_string())?;
                 let _ = conn
@@ -250,7 +534,7 @@ impl RecoveryManager {
 
         // Verify token against Redis if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let key = format!("{}:token:{}", self.redis_prefix, token.session_id);
 
                 if let Ok(stored) = conn.get::<_, Option<String>>(&key).await {
@@ -281,7 +565,7 @@ impl RecoveryManager {
     pub async fn recover_state(&self, session_id: &str) -> Result<RecoveryState, String> {
         // Try Redis first
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let key = format!("{}:{}", self.redis_prefix, session_id);
 
                 if let Ok(stored) = conn.get::<_, Option<String>>(&key).await {
@@ -324,7 +608,7 @@ impl RecoveryManager {
 
         // Remove from Redis if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let state_key = format!("{}:{}", self.redis_prefix, session_id);
                 let token_key = format!("{}:token:{}", self.redis_prefix, session_id);
                 let _ = conn
@@ -360,6 +644,13 @@ impl RecoveryManager {
         if removed > 0 {
             tracing::info!("Cleaned up {} old recovery states", removed);
         }
+        drop(states);
+
+        let window = self.config.recovery_window;
+        let mut sessions = self.recoverable.write().await;
+        sessions
+            .by_pid
+            .retain(|_, session| !session.is_expired(window));
     }
 
     /// Get statistics
@@ -368,10 +659,14 @@ impl RecoveryManager {
 
         let total_states = states.len();
         let total_buffered: usize = states.values().map(|s| s.buffered_messages.len()).sum();
+        drop(states);
 
+        let sessions = self.recoverable.read().await;
         RecoveryStats {
             active_states: total_states,
             total_buffered_messages: total_buffered,
+            recoverable_sessions: sessions.by_pid.len(),
+            replay_buffer_bytes: sessions.by_pid.values().map(|s| s.bytes).sum(),
         }
     }
 }
@@ -387,6 +682,8 @@ impl Default for RecoveryManager {
 pub struct RecoveryStats {
     pub active_states: usize,
     pub total_buffered_messages: usize,
+    pub recoverable_sessions: usize,
+    pub replay_buffer_bytes: usize,
 }
 
 #[cfg(test)]
@@ -449,4 +746,145 @@ mod tests {
         assert_eq!(token.session_id, "session-1");
         assert_eq!(token.user_id, "user-1");
     }
+
+    #[tokio::test]
+    async fn test_user_messages_replayed_once() {
+        let manager = RecoveryManager::default();
+
+        manager
+            .save_state("session-1", "user-1", vec![])
+            .await
+            .unwrap();
+        manager
+            .save_state("session-2", "user-1", vec![])
+            .await
+            .unwrap();
+
+        assert!(
+            manager
+                .buffer_user_message("user-1", "chat-events".to_string(), serde_json::json!(1))
+                .await
+        );
+        assert!(
+            manager
+                .buffer_user_message("user-1", "chat-events".to_string(), serde_json::json!(2))
+                .await
+        );
+        assert!(
+            !manager
+                .buffer_user_message("user-2", "chat-events".to_string(), serde_json::json!(3))
+                .await
+        );
+
+        let messages = manager.take_user_messages("user-1").await;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].data, serde_json::json!(1));
+        assert!(manager.take_user_messages("user-1").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_connection_state_recovery() {
+        let manager = RecoveryManager::default();
+        let pid = manager.open_session("sid-1").await.unwrap();
+        let user = serde_json::json!({"id": "user-1"});
+
+        for i in 1..=3 {
+            let offset = manager
+                .record_event("sid-1", "chat-events", &serde_json::json!(i))
+                .await;
+            assert_eq!(offset, Some(i));
+        }
+        manager
+            .session_disconnected("sid-1", Some(user.clone()), vec!["room-a".to_string()])
+            .await;
+        // Emitted while the client is away
+        assert_eq!(
+            manager
+                .record_for_disconnected_user("user-1", "chat-events", &serde_json::json!(4))
+                .await,
+            1
+        );
+
+        // The client saw up to offset 2
+        let recovered = manager.recover_session("sid-2", &pid, "2").await.unwrap();
+        assert_eq!(recovered.user, Some(user));
+        assert_eq!(recovered.rooms, vec!["room-a"]);
+        let offsets: Vec<u64> = recovered.events.iter().map(|e| e.offset).collect();
+        assert_eq!(offsets, vec![3, 4]);
+        assert_eq!(recovered.events[1].data, serde_json::json!(4));
+
+        // The session now follows the new connection
+        assert_eq!(
+            manager
+                .record_event("sid-2", "chat-events", &serde_json::json!(5))
+                .await,
+            Some(5)
+        );
+        assert_eq!(
+            manager
+                .record_event("sid-1", "chat-events", &serde_json::json!(6))
+                .await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recovery_misses() {
+        let manager = RecoveryManager::new(
+            None,
+            RecoveryConfig {
+                max_replay_events: 2,
+                recovery_window: Duration::from_millis(50),
+                ..RecoveryConfig::default()
+            },
+        );
+
+        assert!(manager
+            .recover_session("sid-1", "unknown", "0")
+            .await
+            .is_err());
+
+        // Events the client never saw were dropped to stay within the limit
+        let pid = manager.open_session("sid-1").await.unwrap();
+        for i in 1..=4 {
+            manager
+                .record_event("sid-1", "chat-events", &serde_json::json!(i))
+                .await;
+        }
+        manager
+            .session_disconnected("sid-1", None, Vec::new())
+            .await;
+        assert!(manager.recover_session("sid-2", &pid, "1").await.is_err());
+
+        // Reconnecting after the window
+        let pid = manager.open_session("sid-3").await.unwrap();
+        manager
+            .session_disconnected("sid-3", None, Vec::new())
+            .await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(manager.recover_session("sid-4", &pid, "0").await.is_err());
+        manager.cleanup_old_states().await;
+        assert_eq!(manager.get_stats().await.recoverable_sessions, 0);
+    }
+
+    #[test]
+    fn test_replay_buffer_is_bounded_by_bytes() {
+        let mut session = RecoverableSession {
+            sid: None,
+            user: None,
+            rooms: Vec::new(),
+            events: VecDeque::new(),
+            bytes: 0,
+            next_offset: 1,
+            dropped_through: 0,
+            disconnected_at: None,
+        };
+        let chunk = serde_json::json!("x".repeat(100));
+        for _ in 0..10 {
+            session.push("chat-events", chunk.clone(), 1000, 500);
+        }
+        assert!(session.bytes <= 500);
+        assert_eq!(session.events.len(), 4);
+        assert_eq!(session.dropped_through, 6);
+    }
 }
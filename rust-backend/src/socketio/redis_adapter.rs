@@ -1,11 +1,16 @@
 /// Redis Adapter for Socket.IO
 ///
 /// Enables horizontal scaling by using Redis pub/sub to broadcast events
-/// across multiple server instances
+/// across multiple server instances. Room and user membership is mirrored into a
+/// Redis set per room and per user, holding `{server_id}:{sid}` members; each server
+/// refreshes a heartbeat, and members of servers that stopped refreshing it are
+/// dropped lazily when read.
 use futures_util::StreamExt;
+use redis::aio::MultiplexedConnection;
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
+use std::collections::HashMap;
 
 /// Redis message for inter-server communication
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +28,9 @@ pub enum RedisMessageType {
         room: Option<String>,
         event: String,
         data: JsonValue,
+        /// Numbered for connection state recovery on the receiving server
+        #[serde(default)]
+        recoverable: bool,
     },
     Broadcast {
         room: String,
@@ -40,9 +48,30 @@ pub enum RedisMessageType {
     },
 }
 
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
 
+use crate::utils::redis_health::RedisHealth;
+use crate::utils::time::current_timestamp_seconds;
+
+/// Hash of server ID -> Unix seconds of its last heartbeat
+const SERVERS_KEY: &str = "socketio:servers";
+/// Hash of `{server_id}:{sid}` -> user ID of authenticated sessions
+const SESSION_USERS_KEY: &str = "socketio:session_users";
+/// How often each server refreshes its heartbeat
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Seconds without a heartbeat after which a server's members are ignored
+const SERVER_TTL: i64 = 30;
+
+pub fn room_key(room: &str) -> String {
+    format!("socketio:room:{}", room)
+}
+
+pub fn user_key(user_id: &str) -> String {
+    format!("socketio:user:{}", user_id)
+}
+
 /// Redis adapter for Socket.IO scaling
 pub struct RedisAdapter {
     redis_client: redis::Client,
@@ -50,11 +79,15 @@ pub struct RedisAdapter {
     channel: String,
     reconnect_attempts: usize,
     reconnect_delay: Duration,
+    /// Shared by publishes and membership updates, so events keep their order
+    connection: tokio::sync::Mutex<Option<MultiplexedConnection>>,
+    /// Marked down when Redis can't be reached; events then stay on this instance
+    /// until the subscription reconnects
+    health: Arc<RedisHealth>,
 }
 
 impl RedisAdapter {
     /// Create a new Redis adapter
-    #[allow(dead_code)]
     pub fn new(redis_url: &str, server_id: String) -> Result<Self, redis::RedisError> {
         let redis_client = redis::Client::open(redis_url)?;
 
@@ -64,6 +97,8 @@ impl RedisAdapter {
             channel: "socketio:events".to_string(),
             reconnect_attempts: 10,
             reconnect_delay: Duration::from_secs(1),
+            connection: tokio::sync::Mutex::new(None),
+            health: Arc::new(RedisHealth::new("Socket.IO Redis adapter")),
         })
     }
 
@@ -82,6 +117,8 @@ impl RedisAdapter {
             channel: "socketio:events".to_string(),
             reconnect_attempts,
             reconnect_delay,
+            connection: tokio::sync::Mutex::new(None),
+            health: Arc::new(RedisHealth::new("Socket.IO Redis adapter")),
         })
     }
 
@@ -90,15 +127,212 @@ impl RedisAdapter {
         &self.server_id
     }
 
+    pub fn health(&self) -> &RedisHealth {
+        &self.health
+    }
+
+    async fn connection(&self) -> redis::RedisResult<MultiplexedConnection> {
+        let mut cached = self.connection.lock().await;
+        if let Some(conn) = cached.as_ref() {
+            return Ok(conn.clone());
+        }
+        let conn = self.redis_client.get_multiplexed_async_connection().await?;
+        *cached = Some(conn.clone());
+        Ok(conn)
+    }
+
+    /// Forget the shared connection so the next command reconnects
+    async fn reset_connection(&self) {
+        *self.connection.lock().await = None;
+    }
+
+    /// Run a command, or return `None` while Redis is down or when it fails
+    async fn query<T: redis::FromRedisValue>(&self, cmd: redis::Cmd) -> Option<T> {
+        if !self.health.is_available() {
+            return None;
+        }
+        self.run(cmd).await
+    }
+
+    /// Run a command even while Redis is marked down, so that it is marked reachable
+    /// again as soon as a command gets through
+    async fn run<T: redis::FromRedisValue>(&self, cmd: redis::Cmd) -> Option<T> {
+        let mut conn = match self.connection().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                self.health.mark_failed(&e);
+                return None;
+            }
+        };
+        match cmd.query_async(&mut conn).await {
+            Ok(value) => {
+                self.health.mark_ok();
+                Some(value)
+            }
+            Err(e) => {
+                tracing::debug!("Redis adapter command failed: {}", e);
+                self.reset_connection().await;
+                None
+            }
+        }
+    }
+
+    fn member(&self, sid: &str) -> String {
+        format!("{}:{}", self.server_id, sid)
+    }
+
+    /// Record `sid` of this server in the membership set `key`
+    pub async fn add_member(&self, key: &str, sid: &str) {
+        let _: Option<()> = self
+            .query(redis::cmd("SADD").arg(key).arg(self.member(sid)).clone())
+            .await;
+    }
+
+    pub async fn remove_member(&self, key: &str, sid: &str) {
+        let _: Option<()> = self
+            .query(redis::cmd("SREM").arg(key).arg(self.member(sid)).clone())
+            .await;
+    }
+
+    /// Record which user a session of this server belongs to, or forget it
+    pub async fn set_session_user(&self, sid: &str, user_id: Option<&str>) {
+        let cmd = match user_id {
+            Some(user_id) => redis::cmd("HSET")
+                .arg(SESSION_USERS_KEY)
+                .arg(self.member(sid))
+                .arg(user_id)
+                .clone(),
+            None => redis::cmd("HDEL")
+                .arg(SESSION_USERS_KEY)
+                .arg(self.member(sid))
+                .clone(),
+        };
+        let _: Option<()> = self.query(cmd).await;
+    }
+
+    /// Members of `key` on other live servers; those of dead servers are removed
+    pub async fn remote_members(&self, key: &str) -> Vec<String> {
+        let members: Vec<String> = match self.query(redis::cmd("SMEMBERS").arg(key).clone()).await {
+            Some(members) => members,
+            None => return Vec::new(),
+        };
+        if members.is_empty() {
+            return members;
+        }
+        let servers: HashMap<String, i64> = self
+            .query(redis::cmd("HGETALL").arg(SERVERS_KEY).clone())
+            .await
+            .unwrap_or_default();
+        let (live, dead) = partition_members(members, &servers, current_timestamp_seconds());
+
+        if !dead.is_empty() {
+            tracing::debug!(
+                "Dropping {} member(s) of dead servers from {}",
+                dead.len(),
+                key
+            );
+            let _: Option<()> = self
+                .query(redis::cmd("SREM").arg(key).arg(&dead).clone())
+                .await;
+            let _: Option<()> = self
+                .query(redis::cmd("HDEL").arg(SESSION_USERS_KEY).arg(&dead).clone())
+                .await;
+        }
+
+        live.into_iter()
+            .filter(|member| !member.starts_with(&format!("{}:", self.server_id)))
+            .collect()
+    }
+
+    /// User IDs of the given members, skipping unauthenticated sessions
+    pub async fn member_user_ids(&self, members: &[String]) -> Vec<String> {
+        if members.is_empty() {
+            return Vec::new();
+        }
+        let user_ids: Vec<Option<String>> = self
+            .query(
+                redis::cmd("HMGET")
+                    .arg(SESSION_USERS_KEY)
+                    .arg(members)
+                    .clone(),
+            )
+            .await
+            .unwrap_or_default();
+        user_ids.into_iter().flatten().collect()
+    }
+
+    /// IDs of users with an authenticated session on another live server
+    pub async fn remote_user_ids(&self) -> Vec<String> {
+        let session_users: HashMap<String, String> = self
+            .query(redis::cmd("HGETALL").arg(SESSION_USERS_KEY).clone())
+            .await
+            .unwrap_or_default();
+        if session_users.is_empty() {
+            return Vec::new();
+        }
+        let servers: HashMap<String, i64> = self
+            .query(redis::cmd("HGETALL").arg(SERVERS_KEY).clone())
+            .await
+            .unwrap_or_default();
+        let members = session_users.keys().cloned().collect();
+        let (live, _) = partition_members(members, &servers, current_timestamp_seconds());
+
+        let own = format!("{}:", self.server_id);
+        let mut user_ids: Vec<String> = live
+            .iter()
+            .filter(|member| !member.starts_with(&own))
+            .filter_map(|member| session_users.get(member).cloned())
+            .collect();
+        user_ids.sort();
+        user_ids.dedup();
+        user_ids
+    }
+
+    /// Mark this server as alive; also probes whether Redis is reachable again
+    pub async fn heartbeat(&self) {
+        let _: Option<()> = self
+            .run(
+                redis::cmd("HSET")
+                    .arg(SERVERS_KEY)
+                    .arg(&self.server_id)
+                    .arg(current_timestamp_seconds())
+                    .clone(),
+            )
+            .await;
+    }
+
+    /// Refresh the heartbeat in the background for as long as the server runs
+    pub fn spawn_heartbeat(self: &Arc<Self>) {
+        let adapter = self.clone();
+        tokio::spawn(async move {
+            loop {
+                adapter.heartbeat().await;
+                sleep(HEARTBEAT_INTERVAL).await;
+            }
+        });
+    }
+
     /// Publish an event to Redis with automatic retry on failure
+    ///
+    /// While Redis is unreachable events are only delivered locally: this returns
+    /// immediately instead of waiting on retries, so emits never stall on an outage.
     pub async fn publish(&self, message: RedisMessage) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.health.is_available() {
+            tracing::debug!(
+                "Redis adapter unavailable, not publishing {:?}",
+                message.message_type
+            );
+            return Ok(());
+        }
+
         let serialized = serde_json::to_string(&message)?;
 
         for attempt in 0..self.reconnect_attempts {
-            match self.redis_client.get_multiplexed_async_connection().await {
+            match self.connection().await {
                 Ok(mut conn) => match conn.publish::<_, _, ()>(&self.channel, &serialized).await {
                     Ok(_) => {
                         tracing::debug!("Published message to Redis: {:?}", message.message_type);
+                        self.health.mark_ok();
                         return Ok(());
                     }
                     Err(e) => {
@@ -108,6 +342,7 @@ impl RedisAdapter {
                             self.reconnect_attempts,
                             e
                         );
+                        self.reset_connection().await;
 
                         if attempt < self.reconnect_attempts - 1 {
                             sleep(self.reconnect_delay * (attempt as u32 + 1)).await;
@@ -115,20 +350,16 @@ impl RedisAdapter {
                     }
                 },
                 Err(e) => {
-                    tracing::warn!(
-                        "Failed to connect to Redis (attempt {}/{}): {}",
-                        attempt + 1,
-                        self.reconnect_attempts,
-                        e
-                    );
-
-                    if attempt < self.reconnect_attempts - 1 {
-                        sleep(self.reconnect_delay * (attempt as u32 + 1)).await;
-                    }
+                    // Redis is down rather than flaky; the heartbeat and the
+                    // subscription loop notice when it is back
+                    self.health.mark_failed(&e);
+                    return Err(e.into());
                 }
             }
         }
 
+        self.health
+            .mark_failed("failed to publish after all retry attempts");
         Err("Failed to publish to Redis after all retry attempts".into())
     }
 
@@ -138,16 +369,15 @@ impl RedisAdapter {
         F: FnMut(RedisMessage) + Send + 'static,
     {
         loop {
-            match self.subscribe_with_reconnect(&mut handler).await {
-                Ok(_) => {
-                    tracing::info!("Redis subscription ended normally");
-                    break Ok(());
-                }
-                Err(e) => {
-                    tracing::error!("Redis subscription error: {}, reconnecting...", e);
-                    sleep(self.reconnect_delay).await;
-                }
-            }
+            // The server closing the connection ends the stream; treat it like an
+            // error and keep reconnecting
+            let error = match self.subscribe_with_reconnect(&mut handler).await {
+                Ok(_) => "subscription closed".to_string(),
+                Err(e) => e,
+            };
+            self.health.mark_failed(&error);
+            tracing::debug!("Redis subscription error: {}, reconnecting...", error);
+            sleep(self.reconnect_delay).await;
         }
     }
 
@@ -167,6 +397,8 @@ impl RedisAdapter {
             .map_err(|e| e.to_string())?;
 
         tracing::info!("Subscribed to Redis channel: {}", self.channel);
+        self.health.mark_ok();
+        self.heartbeat().await;
 
         let mut stream = pubsub.on_message();
 
@@ -197,6 +429,7 @@ impl RedisAdapter {
         room: Option<String>,
         event: String,
         data: JsonValue,
+        recoverable: bool,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let message = RedisMessage {
             server_id: self.server_id.clone(),
@@ -206,6 +439,7 @@ impl RedisAdapter {
                 room,
                 event,
                 data,
+                recoverable,
             },
         };
 
@@ -268,9 +502,25 @@ impl RedisAdapter {
     }
 }
 
+/// Split `{server_id}:{sid}` members into those of servers with a recent heartbeat
+/// and the rest
+fn partition_members(
+    members: Vec<String>,
+    servers: &HashMap<String, i64>,
+    now: i64,
+) -> (Vec<String>, Vec<String>) {
+    members.into_iter().partition(|member| {
+        let server_id = member.split_once(':').map_or(member.as_str(), |(id, _)| id);
+        servers
+            .get(server_id)
+            .is_some_and(|last_seen| now - last_seen <= SERVER_TTL)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::socketio::protocol::WsFrame;
 
     #[test]
     fn test_redis_message_serialization() {
@@ -282,6 +532,7 @@ mod tests {
                 room: None,
                 event: "test-event".to_string(),
                 data: serde_json::json!({"message": "hello"}),
+                recoverable: false,
             },
         };
 
@@ -290,4 +541,114 @@ mod tests {
 
         assert_eq!(deserialized.server_id, "server-1");
     }
+
+    #[test]
+    fn test_members_of_dead_servers_are_dropped() {
+        let servers = HashMap::from([
+            ("alive".to_string(), 1_000),
+            ("stale".to_string(), 1_000 - SERVER_TTL - 1),
+        ]);
+        let members = vec![
+            "alive:sid-1".to_string(),
+            "stale:sid-2".to_string(),
+            "unknown:sid-3".to_string(),
+        ];
+        let (live, dead) = partition_members(members, &servers, 1_000);
+        assert_eq!(live, vec!["alive:sid-1"]);
+        assert_eq!(dead, vec!["stale:sid-2", "unknown:sid-3"]);
+    }
+
+    async fn next_frame(rx: &mut tokio::sync::mpsc::UnboundedReceiver<WsFrame>) -> String {
+        match tokio::time::timeout(Duration::from_secs(5), rx.recv()).await {
+            Ok(Some(WsFrame::Text(text))) => text,
+            other => panic!("expected a text frame, got {:?}", other),
+        }
+    }
+
+    /// Two servers sharing a Redis: events emitted on one reach sessions on the other
+    #[tokio::test]
+    #[ignore] // Requires a Redis server at REDIS_URL
+    async fn test_cross_instance_delivery() {
+        use crate::db::Database;
+        use crate::socketio::{
+            EventHandler, HealthConfig, HealthMonitor, PresenceConfig, PresenceManager,
+            RateLimitConfig, RateLimiter, RecoveryConfig, RecoveryManager, SocketIOManager,
+            SocketIOMetrics, YDocManager,
+        };
+
+        let redis_url =
+            std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        let db = Database::new("sqlite::memory:").await.unwrap();
+
+        let server = |server_id: String| {
+            let adapter = Arc::new(RedisAdapter::new(&redis_url, server_id).unwrap());
+            let handler = EventHandler::new(
+                SocketIOManager::new().with_redis_adapter(adapter.clone()),
+                String::new(),
+                YDocManager::new(None),
+                Some(adapter),
+                SocketIOMetrics::new(),
+                Arc::new(RateLimiter::new(RateLimitConfig::default())),
+                Arc::new(PresenceManager::new(PresenceConfig::default())),
+                Arc::new(RecoveryManager::new(None, RecoveryConfig::default())),
+                HealthMonitor::new(HealthConfig::default()),
+                db.clone(),
+            );
+            handler.start_redis_subscription();
+            handler
+        };
+        let a = server(uuid::Uuid::new_v4().to_string());
+        let b = server(uuid::Uuid::new_v4().to_string());
+        for handler in [&a, &b] {
+            handler.manager().redis_adapter().unwrap().heartbeat().await;
+        }
+
+        // A session of the user connected to server B only
+        let user_id = format!("user-{}", uuid::Uuid::new_v4());
+        let room = format!("room-{}", uuid::Uuid::new_v4());
+        let sid = uuid::Uuid::new_v4().to_string();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        b.register_connection(&sid, tx).await;
+        b.manager().create_session(&sid).await;
+        b.manager()
+            .set_session_user(&sid, serde_json::json!({"id": user_id}))
+            .await
+            .unwrap();
+        b.manager().join_room(&sid, &room).await.unwrap();
+
+        // Membership is visible from server A
+        assert_eq!(a.manager().remote_user_session_count(&user_id).await, 1);
+        assert_eq!(
+            a.manager().get_room_user_ids(&room).await,
+            vec![user_id.clone()]
+        );
+
+        // Wait for both subscriptions before publishing
+        sleep(Duration::from_millis(500)).await;
+
+        let mut received = Vec::new();
+        // Nothing is local to A, so this only arrives through Redis
+        assert_eq!(
+            a.emit_to_user(&user_id, "chat-events", serde_json::json!({"n": 1}))
+                .await
+                .unwrap(),
+            0
+        );
+        received.push(next_frame(&mut rx).await);
+        a.broadcast_to_room(&room, "channel-events", serde_json::json!({"n": 2}), None)
+            .await
+            .unwrap();
+        received.push(next_frame(&mut rx).await);
+        a.emit_to_session(&sid, "note-events", serde_json::json!({"n": 3}))
+            .await
+            .unwrap();
+        received.push(next_frame(&mut rx).await);
+
+        assert!(received[0].contains("chat-events") && received[0].contains("\"n\":1"));
+        assert!(received[1].contains("channel-events"));
+        assert!(received[2].contains("note-events"));
+
+        b.manager().remove_session(&sid).await;
+        assert_eq!(a.manager().remote_user_session_count(&user_id).await, 0);
+    }
 }
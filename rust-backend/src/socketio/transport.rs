@@ -9,9 +9,13 @@ use crate::socketio::manager::SocketIOManager;
 /// - Client sends CONNECT packet first
 /// - Server responds with CONNECT packet containing {sid: "..."}
 /// - All namespaces (including default "/") require explicit CONNECT
-use crate::socketio::protocol::{EnginePacket, EnginePacketType, SocketPacket, SocketPacketType};
+use crate::socketio::protocol::{
+    decode_polling_payload, BinaryReassembler, EnginePacket, EnginePacketType, Parser,
+    PollingPacket, SocketPacket, SocketPacketType, WsFrame, POLLING_SEPARATOR,
+};
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_ws::Message as WsMessage;
+use bytes::Bytes;
 use futures_util::StreamExt;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -34,6 +38,15 @@ async fn queue_polling_response(sid: &str, message: String) {
         .push(message);
 }
 
+/// Queue a Socket.IO packet for a polling session, encoded for the session's parser
+pub(crate) async fn queue_polling_packet(sid: &str, packet: &SocketPacket, parser: Parser) {
+    let mut queue = POLLING_RESPONSES.write().await;
+    queue
+        .entry(sid.to_string())
+        .or_insert_with(Vec::new)
+        .extend(packet.to_polling_packets_for(parser));
+}
+
 /// Get and clear queued responses for a polling session
 async fn get_polling_responses(sid: &str) -> Vec<String> {
     let mut queue = POLLING_RESPONSES.write().await;
@@ -46,7 +59,10 @@ pub async fn websocket_handler(
     stream: web::Payload,
     event_handler: web::Data<EventHandler>,
 ) -> Result<HttpResponse, Error> {
-    tracing::info!("WebSocket connection request from: {:?}", req.peer_addr());
+    tracing::info!(
+        "WebSocket connection request from: {}",
+        crate::middleware::RealIp::of(&req)
+    );
     tracing::debug!("WebSocket headers: {:?}", req.headers());
 
     // Perform WebSocket handshake
@@ -71,7 +87,7 @@ pub async fn websocket_handler(
     manager.create_session(&sid).await;
 
     // Create a channel for sending messages to this WebSocket
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<WsFrame>();
 
     // Register the connection
     event_handler.register_connection(&sid, tx).await;
@@ -89,22 +105,54 @@ pub async fn websocket_handler(
     // Spawn a task to handle outgoing messages
     let sid_outgoing = sid.clone();
     let mut session_outgoing = session.clone();
+    let event_handler_outgoing = event_handler.get_ref().clone();
     actix_web::rt::spawn(async move {
-        while let Some(message) = rx.recv().await {
-            if session_outgoing.text(message).await.is_err() {
+        while let Some(frame) = rx.recv().await {
+            let sent = match frame {
+                WsFrame::Text(text) => session_outgoing.text(text).await,
+                WsFrame::Binary(bytes) => session_outgoing.binary(bytes).await,
+            };
+            if sent.is_err() {
                 break;
             }
+            event_handler_outgoing.frames_written(&sid_outgoing, 1).await;
         }
+        // The sender is dropped when the session is unregistered (e.g. after a heartbeat
+        // timeout), so close the socket rather than waiting for the client
+        let _ = session_outgoing.close(None).await;
         tracing::debug!("Outgoing message handler closed for {}", sid_outgoing);
     });
 
+    // Engine.IO v4 heartbeat: the server pings and the client answers with a pong
+    let sid_ping = sid.clone();
+    let event_handler_ping = event_handler.get_ref().clone();
+    let ping_interval = std::time::Duration::from_millis(manager.ping_interval());
+    actix_web::rt::spawn(async move {
+        loop {
+            tokio::time::sleep(ping_interval).await;
+            if !event_handler_ping.send_ping(&sid_ping).await {
+                break;
+            }
+        }
+    });
+
     // Spawn a task to handle incoming messages
     actix_web::rt::spawn(async move {
         let event_handler = event_handler_clone;
         let sid = sid_clone;
         let http_client = reqwest::Client::new();
+        // Binary packets arrive as a text frame followed by one binary frame per attachment,
+        // unless the client uses the msgpack parser and sends every packet as binary
+        let mut reassembler = BinaryReassembler::new();
+        let mut parser = Parser::Json;
 
         while let Some(Ok(msg)) = msg_stream.next().await {
+            // Backpressure: stop reading while the client isn't taking what it is sent
+            if !event_handler.wait_for_outbound_capacity(&sid).await {
+                tracing::warn!("Closing session {}: outbound queue stayed full", sid);
+                break;
+            }
+
             match msg {
                 WsMessage::Text(text) => {
                     tracing::debug!("Received text message: {}", text);
@@ -114,14 +162,20 @@ pub async fn websocket_handler(
                         match engine_packet.packet_type {
                             EnginePacketType::Ping => {
                                 // Respond with pong
-                                manager.update_ping(&sid).await;
+                                event_handler.handle_engine_ping(&sid).await;
                                 let pong = EnginePacket::pong(engine_packet.data.clone());
                                 let _ = session.text(pong.encode()).await;
                             }
+                            EnginePacketType::Pong => {
+                                event_handler.handle_engine_pong(&sid).await;
+                            }
                             EnginePacketType::Message => {
                                 // Parse Socket.IO packet
                                 let data_str = String::from_utf8_lossy(&engine_packet.data);
-                                if let Ok(socket_packet) = SocketPacket::decode(&data_str) {
+                                let complete = SocketPacket::decode(&data_str)
+                                    .ok()
+                                    .and_then(|packet| reassembler.push_packet(packet));
+                                if let Some(socket_packet) = complete {
                                     // Handle Socket.IO packet
                                     handle_socket_packet(
                                         &event_handler,
@@ -129,6 +183,7 @@ pub async fn websocket_handler(
                                         socket_packet,
                                         &http_client,
                                         &mut session,
+                                        parser,
                                     )
                                     .await;
                                 }
@@ -143,7 +198,35 @@ pub async fn websocket_handler(
                 }
                 WsMessage::Binary(bytes) => {
                     tracing::debug!("Received binary message: {} bytes", bytes.len());
-                    // Handle binary messages if needed
+                    let complete = if reassembler.is_waiting() {
+                        reassembler.push_attachment(bytes)
+                    } else {
+                        // Not an attachment, so a whole packet of the msgpack parser
+                        match SocketPacket::decode_msgpack(&bytes) {
+                            Ok(packet) => {
+                                if parser != Parser::MsgPack {
+                                    parser = Parser::MsgPack;
+                                    event_handler.set_parser(&sid, parser).await;
+                                }
+                                Some(packet)
+                            }
+                            Err(e) => {
+                                tracing::debug!("Ignoring binary message: {}", e);
+                                None
+                            }
+                        }
+                    };
+                    if let Some(socket_packet) = complete {
+                        handle_socket_packet(
+                            &event_handler,
+                            &sid,
+                            socket_packet,
+                            &http_client,
+                            &mut session,
+                            parser,
+                        )
+                        .await;
+                    }
                 }
                 WsMessage::Ping(bytes) => {
                     let _ = session.pong(&bytes).await;
@@ -172,6 +255,7 @@ async fn handle_socket_packet(
     packet: SocketPacket,
     http_client: &reqwest::Client,
     session: &mut actix_ws::Session,
+    parser: Parser,
 ) {
     use crate::socketio::protocol::SocketPacketType;
 
@@ -185,48 +269,53 @@ async fn handle_socket_packet(
                 packet.namespace
             );
 
-            // Check if client sent auth data and authenticate immediately
-            if let Some(ref auth_data) = packet.data {
-                tracing::debug!("Auth data received during CONNECT: {:?}", auth_data);
+            // A reconnecting client may resume its previous session, user and rooms included
+            let recovered_pid = event_handler.resume_session(sid, packet.data.as_ref()).await;
+
+            if recovered_pid.is_none() {
+                // Check if client sent auth data and authenticate immediately
+                if let Some(ref auth_data) = packet.data {
+                    tracing::debug!("Auth data received during CONNECT: {:?}", auth_data);
                 
-                // Try to authenticate user with the auth data
-                if let Some(auth_obj) = auth_data.get("auth") {
-                    if let Some(token) = auth_obj.get("token").and_then(|t| t.as_str()) {
-                        tracing::info!("Authenticating user during CONNECT with token");
+                    // Try to authenticate user with the auth data
+                    if let Some(auth_obj) = auth_data.get("auth") {
+                        if let Some(token) = auth_obj.get("token").and_then(|t| t.as_str()) {
+                            tracing::info!("Authenticating user during CONNECT with token");
                         
-                        // Authenticate with backend
-                        let auth_url = format!("{}/api/socketio/auth", event_handler.auth_endpoint());
+                            // Authenticate with backend
+                            let auth_url = format!("{}/api/socketio/auth", event_handler.auth_endpoint());
                         
-                        match http_client
-                            .post(&auth_url)
-                            .json(&serde_json::json!({"token": token}))
-                            .send()
-                            .await
-                        {
-                            Ok(response) if response.status().is_success() => {
-                                if let Ok(user) = response.json::<serde_json::Value>().await {
-                                    // Set session user immediately
-                                    if let Err(e) = event_handler.manager().set_session_user(sid, user.clone()).await {
-                                        tracing::error!("Failed to set session user: {}", e);
-                                    } else {
-                                        let user_id = user.get("id").and_then(|id| id.as_str()).unwrap_or("unknown");
-                                        tracing::info!("User {} authenticated during CONNECT on session {}", user_id, sid);
+                            match http_client
+                                .post(&auth_url)
+                                .json(&serde_json::json!({"token": token}))
+                                .send()
+                                .await
+                            {
+                                Ok(response) if response.status().is_success() => {
+                                    if let Ok(user) = response.json::<serde_json::Value>().await {
+                                        // Set session user immediately
+                                        if let Err(e) = event_handler.manager().set_session_user(sid, user.clone()).await {
+                                            tracing::error!("Failed to set session user: {}", e);
+                                        } else {
+                                            let user_id = user.get("id").and_then(|id| id.as_str()).unwrap_or("unknown");
+                                            tracing::info!("User {} authenticated during CONNECT on session {}", user_id, sid);
                                         
-                                        // Update presence
-                                        event_handler.presence_manager().user_online(user_id).await;
+                                            // Auto-join user to their channels
+                                            if let Err(e) = event_handler.auto_join_user_channels(sid, user_id).await {
+                                                tracing::warn!("Failed to auto-join user {} to channels: {}", user_id, e);
+                                            }
                                         
-                                        // Auto-join user to their channels
-                                        if let Err(e) = event_handler.auto_join_user_channels(sid, user_id).await {
-                                            tracing::warn!("Failed to auto-join user {} to channels: {}", user_id, e);
+                                            // Update presence
+                                            event_handler.mark_user_online(sid, user_id).await;
                                         }
                                     }
                                 }
-                            }
-                            Ok(response) => {
-                                tracing::warn!("Authentication failed during CONNECT: {}", response.status());
-                            }
-                            Err(e) => {
-                                tracing::error!("Auth request failed during CONNECT: {}", e);
+                                Ok(response) => {
+                                    tracing::warn!("Authentication failed during CONNECT: {}", response.status());
+                                }
+                                Err(e) => {
+                                    tracing::error!("Auth request failed during CONNECT: {}", e);
+                                }
                             }
                         }
                     }
@@ -236,16 +325,33 @@ async fn handle_socket_packet(
             // Generate a Socket.IO session ID (different from Engine.IO sid)
             let socket_sid = SocketIOManager::generate_sid();
 
-            // Send CONNECT response with sid
-            let connect_response = SocketPacket::connect(&packet.namespace, Some(&socket_sid));
-            let engine_msg = EnginePacket::message(connect_response.encode().into_bytes());
-            tracing::info!("Sending CONNECT response: {}", engine_msg.encode());
-            let _ = session.text(engine_msg.encode()).await;
+            // Send CONNECT response with sid, and the private session ID that lets the
+            // client recover this session after a disconnect
+            let pid = match recovered_pid {
+                Some(pid) => Some(pid),
+                None => event_handler.recovery_manager().open_session(sid).await,
+            };
+            let connect_response = match &pid {
+                Some(pid) => SocketPacket::connect_recoverable(&packet.namespace, &socket_sid, pid),
+                None => SocketPacket::connect(&packet.namespace, Some(&socket_sid)),
+            };
+            tracing::info!("Sending CONNECT response: {}", connect_response.encode());
+            for frame in connect_response.into_frames_for(parser) {
+                let _ = match frame {
+                    WsFrame::Text(text) => session.text(text).await,
+                    WsFrame::Binary(bytes) => session.binary(bytes).await,
+                };
+            }
         }
-        SocketPacketType::Event => {
+        SocketPacketType::Event | SocketPacketType::BinaryEvent => {
             if let Some((event, data)) = packet.get_event() {
                 tracing::info!("Event from {}: {} - {:?}", sid, event, data);
 
+                // Over the session's rate limit: dropped, and the client has been told
+                if !event_handler.allow_event(sid, &event).await {
+                    return;
+                }
+
                 // Handle different event types
                 let result = match event.as_str() {
                     "user-join" => event_handler
@@ -255,12 +361,18 @@ async fn handle_socket_packet(
                     "join-channels" => event_handler.handle_join_channels(sid, data).await,
                     "usage" => event_handler.handle_usage(sid, data).await,
                     "chat-events" => event_handler.handle_chat_event(sid, data).await,
+                    "join-chat" => event_handler.handle_chat_join(sid, data).await,
+                    "leave-chat" => event_handler.handle_chat_leave(sid, data).await,
                     "channel-events" => event_handler.handle_channel_event(sid, data).await,
                     "channel:join" => event_handler.handle_channel_join(sid, data).await,
                     "channel:leave" => event_handler.handle_channel_leave(sid, data).await,
                     "ydoc:document:join" => event_handler.handle_ydoc_join(sid, data).await,
                     "ydoc:document:leave" => event_handler.handle_ydoc_leave(sid, data).await,
-                    "ydoc:document:update" => event_handler.handle_ydoc_update(sid, data).await,
+                    "ydoc:document:update" => {
+                        // Take the update as sent rather than filled in as a JSON byte array
+                        let (_, data, attachments) = packet.into_event().unwrap_or_default();
+                        event_handler.handle_ydoc_update(sid, data, &attachments).await
+                    }
                     "ydoc:document:state" => {
                         event_handler.handle_ydoc_state_request(sid, data).await
                     }
@@ -270,6 +382,7 @@ async fn handle_socket_packet(
                     "presence:status" => event_handler.handle_presence_status(sid, data).await,
                     "typing:start" => event_handler.handle_typing_start(sid, data).await,
                     "typing:stop" => event_handler.handle_typing_stop(sid, data).await,
+                    "typing" => event_handler.handle_typing(sid, data).await,
                     "presence:get" => {
                         match event_handler.handle_get_presences(sid, data).await {
                             Ok(response) => {
@@ -293,6 +406,9 @@ async fn handle_socket_packet(
                 }
             }
         }
+        SocketPacketType::Ack | SocketPacketType::BinaryAck => {
+            event_handler.handle_ack(sid, packet).await;
+        }
         SocketPacketType::Disconnect => {
             tracing::info!(
                 "Client {} disconnecting from namespace {}",
@@ -353,7 +469,10 @@ pub async fn polling_handler(
         ("GET", Some("polling"), Some(sid)) | ("GET", None, Some(sid)) => {
             // Polling request with session ID - client polling for messages
             if manager.get_session(sid).await.is_some() {
-                manager.update_ping(sid).await;
+                match &event_handler {
+                    Some(handler) => handler.handle_engine_ping(sid).await,
+                    None => manager.update_ping(sid).await,
+                }
 
                 // Get queued messages for this session
                 let messages = get_polling_responses(sid).await;
@@ -366,7 +485,7 @@ pub async fn polling_handler(
                         .body("6")) // NOOP packet
                 } else {
                     // Join messages with packet separator
-                    let response_body = messages.join("\x1e");
+                    let response_body = messages.join(&POLLING_SEPARATOR.to_string());
                     Ok(HttpResponse::Ok()
                         .content_type("text/plain; charset=UTF-8")
                         .append_header(("Access-Control-Allow-Credentials", "true"))
@@ -381,7 +500,10 @@ pub async fn polling_handler(
         // POST request - client sending messages
         ("POST", Some("polling"), Some(sid)) | ("POST", None, Some(sid)) => {
             if manager.get_session(sid).await.is_some() {
-                manager.update_ping(sid).await;
+                match &event_handler {
+                    Some(handler) => handler.handle_engine_ping(sid).await,
+                    None => manager.update_ping(sid).await,
+                }
 
                 // Parse incoming messages
                 let body_str = String::from_utf8_lossy(&body);
@@ -391,108 +513,140 @@ pub async fn polling_handler(
                 if let Some(_handler) = event_handler {
                     let _http_client = reqwest::Client::new();
 
-                    // Split by packet separator
-                    for packet_str in body_str.split('\x1e') {
-                        if packet_str.is_empty() {
-                            continue;
-                        }
+                    // Binary attachments follow their packet in the same payload
+                    let mut reassembler = BinaryReassembler::new();
+                    let mut parser = _handler.parser_of(sid).await;
 
-                        // Parse Engine.IO packet
-                        if let Ok(engine_packet) = EnginePacket::decode(packet_str) {
-                            match engine_packet.packet_type {
-                                EnginePacketType::Message => {
-                                    // Parse Socket.IO packet
-                                    let data_str = String::from_utf8_lossy(&engine_packet.data);
-                                    if let Ok(socket_packet) = SocketPacket::decode(&data_str) {
-                                        // Handle CONNECT specially for polling (need to queue response)
-                                        if socket_packet.packet_type == SocketPacketType::Connect {
-                                            tracing::info!(
-                                                "Polling client {} connecting to namespace: {}",
-                                                sid,
-                                                socket_packet.namespace
-                                            );
-
-                                            // Check if client sent auth data and authenticate immediately
-                                            if let Some(ref auth_data) = socket_packet.data {
-                                                tracing::debug!("Auth data received during polling CONNECT: {:?}", auth_data);
-                                                
-                                                // Try to authenticate user with the auth data
-                                                if let Some(auth_obj) = auth_data.get("auth") {
-                                                    if let Some(token) = auth_obj.get("token").and_then(|t| t.as_str()) {
-                                                        tracing::info!("Authenticating user during polling CONNECT with token");
-                                                        
-                                        // Authenticate with backend
-                                        let auth_url = format!("{}/api/socketio/auth", _handler.auth_endpoint());
-                                                        
-                                                        match _http_client
-                                                            .post(&auth_url)
-                                                            .json(&serde_json::json!({"token": token}))
-                                                            .send()
-                                                            .await
-                                                        {
-                                                            Ok(response) if response.status().is_success() => {
-                                                                if let Ok(user) = response.json::<serde_json::Value>().await {
-                                                                    // Set session user immediately
-                                                                    if let Err(e) = _handler.manager().set_session_user(sid, user.clone()).await {
-                                                                        tracing::error!("Failed to set session user: {}", e);
-                                                                    } else {
-                                                                        let user_id = user.get("id").and_then(|id| id.as_str()).unwrap_or("unknown");
-                                                                        tracing::info!("User {} authenticated during polling CONNECT on session {}", user_id, sid);
-                                                                        
-                                                                        // Update presence
-                                                                        _handler.presence_manager().user_online(user_id).await;
-                                                                        
-                                                                        // Auto-join user to their channels
-                                                                        if let Err(e) = _handler.auto_join_user_channels(sid, user_id).await {
-                                                                            tracing::warn!("Failed to auto-join user {} to channels: {}", user_id, e);
-                                                                        }
-                                                                    }
-                                                                }
-                                                            }
-                                                            Ok(response) => {
-                                                                tracing::warn!("Authentication failed during polling CONNECT: {}", response.status());
-                                                            }
-                                                            Err(e) => {
-                                                                tracing::error!("Auth request failed during polling CONNECT: {}", e);
+                    // Split by packet separator
+                    for polling_packet in decode_polling_payload(&body_str) {
+                        let complete = match polling_packet {
+                            PollingPacket::Binary(bytes) if reassembler.is_waiting() => {
+                                reassembler.push_attachment(Bytes::from(bytes))
+                            }
+                            // Otherwise a whole packet from a client using the msgpack parser
+                            PollingPacket::Binary(bytes) => {
+                                match SocketPacket::decode_msgpack(&bytes) {
+                                    Ok(packet) => {
+                                        if parser != Parser::MsgPack {
+                                            parser = Parser::MsgPack;
+                                            _handler.set_parser(sid, parser).await;
+                                        }
+                                        Some(packet)
+                                    }
+                                    Err(e) => {
+                                        tracing::debug!("Ignoring binary polling packet: {}", e);
+                                        None
+                                    }
+                                }
+                            }
+                            // Parse Engine.IO packet
+                            PollingPacket::Text(packet_str) => {
+                                match EnginePacket::decode(&packet_str) {
+                                    Ok(engine_packet)
+                                        if engine_packet.packet_type == EnginePacketType::Message =>
+                                    {
+                                        // Parse Socket.IO packet
+                                        let data_str = String::from_utf8_lossy(&engine_packet.data);
+                                        SocketPacket::decode(&data_str)
+                                            .ok()
+                                            .and_then(|packet| reassembler.push_packet(packet))
+                                    }
+                                    Ok(engine_packet)
+                                        if engine_packet.packet_type == EnginePacketType::Ping =>
+                                    {
+                                        // Queue pong response
+                                        let pong = EnginePacket::pong(engine_packet.data.clone());
+                                        queue_polling_response(sid, pong.encode()).await;
+                                        None
+                                    }
+                                    _ => None,
+                                }
+                            }
+                        };
+
+                        if let Some(socket_packet) = complete {
+                            // Handle CONNECT specially for polling (need to queue response)
+                            if socket_packet.packet_type == SocketPacketType::Connect {
+                                tracing::info!(
+                                    "Polling client {} connecting to namespace: {}",
+                                    sid,
+                                    socket_packet.namespace
+                                );
+
+                                // Check if client sent auth data and authenticate immediately
+                                if let Some(ref auth_data) = socket_packet.data {
+                                    tracing::debug!("Auth data received during polling CONNECT: {:?}", auth_data);
+                                    
+                                    // Try to authenticate user with the auth data
+                                    if let Some(auth_obj) = auth_data.get("auth") {
+                                        if let Some(token) = auth_obj.get("token").and_then(|t| t.as_str()) {
+                                            tracing::info!("Authenticating user during polling CONNECT with token");
+                                            
+                            // Authenticate with backend
+                            let auth_url = format!("{}/api/socketio/auth", _handler.auth_endpoint());
+                                            
+                                            match _http_client
+                                                .post(&auth_url)
+                                                .json(&serde_json::json!({"token": token}))
+                                                .send()
+                                                .await
+                                            {
+                                                Ok(response) if response.status().is_success() => {
+                                                    if let Ok(user) = response.json::<serde_json::Value>().await {
+                                                        // Set session user immediately
+                                                        if let Err(e) = _handler.manager().set_session_user(sid, user.clone()).await {
+                                                            tracing::error!("Failed to set session user: {}", e);
+                                                        } else {
+                                                            let user_id = user.get("id").and_then(|id| id.as_str()).unwrap_or("unknown");
+                                                            tracing::info!("User {} authenticated during polling CONNECT on session {}", user_id, sid);
+                                                            
+                                                            // Auto-join user to their channels
+                                                            if let Err(e) = _handler.auto_join_user_channels(sid, user_id).await {
+                                                                tracing::warn!("Failed to auto-join user {} to channels: {}", user_id, e);
                                                             }
+                                                            
+                                                            // Update presence
+                                                            _handler.mark_user_online(sid, user_id).await;
                                                         }
                                                     }
                                                 }
+                                                Ok(response) => {
+                                                    tracing::warn!("Authentication failed during polling CONNECT: {}", response.status());
+                                                }
+                                                Err(e) => {
+                                                    tracing::error!("Auth request failed during polling CONNECT: {}", e);
+                                                }
                                             }
-
-                                            // Generate Socket.IO session ID
-                                            let socket_sid = SocketIOManager::generate_sid();
-
-                                            // Create CONNECT response
-                                            let connect_response = SocketPacket::connect(
-                                                &socket_packet.namespace,
-                                                Some(&socket_sid),
-                                            );
-                                            let engine_msg = EnginePacket::message(
-                                                connect_response.encode().into_bytes(),
-                                            );
-
-                                            // Queue the response for next GET
-                                            queue_polling_response(sid, engine_msg.encode()).await;
-                                            tracing::info!(
-                                                "Queued CONNECT response for polling session {}",
-                                                sid
-                                            );
-                                        } else {
-                                            // Handle other Socket.IO packets (but polling doesn't support this easily)
-                                            tracing::debug!(
-                                                "Received Socket.IO packet in polling: {:?}",
-                                                socket_packet.packet_type
-                                            );
                                         }
                                     }
                                 }
-                                EnginePacketType::Ping => {
-                                    // Queue pong response
-                                    let pong = EnginePacket::pong(engine_packet.data.clone());
-                                    queue_polling_response(sid, pong.encode()).await;
-                                }
-                                _ => {}
+
+                                // Generate Socket.IO session ID
+                                let socket_sid = SocketIOManager::generate_sid();
+
+                                // Create CONNECT response
+                                let connect_response = SocketPacket::connect(
+                                    &socket_packet.namespace,
+                                    Some(&socket_sid),
+                                );
+
+                                // Queue the response for next GET
+                                queue_polling_packet(sid, &connect_response, parser).await;
+                                tracing::info!(
+                                    "Queued CONNECT response for polling session {}",
+                                    sid
+                                );
+                            } else if matches!(
+                                socket_packet.packet_type,
+                                SocketPacketType::Ack | SocketPacketType::BinaryAck
+                            ) {
+                                _handler.handle_ack(sid, socket_packet).await;
+                            } else {
+                                // Handle other Socket.IO packets (but polling doesn't support this easily)
+                                tracing::debug!(
+                                    "Received Socket.IO packet in polling: {:?}",
+                                    socket_packet.packet_type
+                                );
                             }
                         }
                     }
@@ -541,3 +695,34 @@ pub async fn broadcast_to_sessions(_sids: Vec<String>, _event: &str, _data: serd
     // This is now handled by EventHandler in events.rs
     // Keeping this as a placeholder for backward compatibility
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_handshake_uses_configured_ping_timing() {
+        let manager = SocketIOManager::new().with_ping_config(10_000, 5_000);
+        assert_eq!(manager.stale_session_timeout(), 25);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(manager))
+                .route("/socket.io/", web::get().to(polling_handler)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/socket.io/?EIO=4&transport=polling")
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+
+        // OPEN packet: type "0" followed by the handshake JSON
+        let handshake: serde_json::Value = serde_json::from_str(&body[1..]).unwrap();
+        assert!(body.starts_with('0'));
+        assert_eq!(handshake["pingInterval"], 10_000);
+        assert_eq!(handshake["pingTimeout"], 5_000);
+    }
+}
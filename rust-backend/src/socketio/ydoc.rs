@@ -4,13 +4,16 @@
 /// with Redis persistence for horizontal scaling
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use yrs::updates::decoder::Decode;
 use yrs::updates::encoder::Encode;
 use yrs::{Doc, ReadTxn, StateVector, Transact, Update};
 
+use crate::utils::redis_health::RedisPool;
+
 /// Yjs document manager
 #[derive(Clone)]
 pub struct YDocManager {
@@ -22,13 +25,44 @@ pub struct YDocManager {
     /// Key: document_id, Value: Set of session IDs
     users: Arc<RwLock<HashMap<String, Vec<String>>>>,
 
+    /// Persistence and idle tracking per document
+    /// Key: sanitized document_id
+    activity: Arc<RwLock<HashMap<String, DocumentActivity>>>,
+
     /// Redis connection pool for persistence (optional)
-    redis: Option<deadpool_redis::Pool>,
+    redis: Option<RedisPool>,
 
     /// Redis key prefix
     redis_prefix: String,
 }
 
+/// Per-document bookkeeping used to flush state and evict idle documents
+#[derive(Debug, Clone)]
+struct DocumentActivity {
+    /// Original (unsanitized) document ID, e.g. `note:<id>`
+    doc_id: String,
+    /// Updates were applied since the last flush
+    dirty: bool,
+    /// Latest materialized content reported by an editor
+    snapshot: Option<serde_json::Value>,
+    /// Set when the last editor left
+    idle_since: Option<Instant>,
+    /// Sessions that may receive updates but not send them
+    read_only: HashSet<String>,
+}
+
+impl DocumentActivity {
+    fn new(doc_id: &str) -> Self {
+        Self {
+            doc_id: doc_id.to_string(),
+            dirty: false,
+            snapshot: None,
+            idle_since: None,
+            read_only: HashSet::new(),
+        }
+    }
+}
+
 /// Yjs awareness state for presence information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AwarenessState {
@@ -46,10 +80,11 @@ pub struct CursorPosition {
 
 impl YDocManager {
     /// Create a new YDoc manager
-    pub fn new(redis: Option<deadpool_redis::Pool>) -> Self {
+    pub fn new(redis: Option<RedisPool>) -> Self {
         Self {
             updates: Arc::new(RwLock::new(HashMap::new())),
             users: Arc::new(RwLock::new(HashMap::new())),
+            activity: Arc::new(RwLock::new(HashMap::new())),
             redis,
             redis_prefix: "socketio:ydoc".to_string(),
         }
@@ -75,7 +110,7 @@ impl YDocManager {
 
         // Store in Redis if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let key = format!("{}:{}:updates", self.redis_prefix, sanitized_id);
 
                 // Store as JSON array for compatibility with Python backend
@@ -96,7 +131,7 @@ impl YDocManager {
 
         // Try Redis first if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let key = format!("{}:{}:updates", self.redis_prefix, sanitized_id);
 
                 if let Ok(updates_json) = conn.lrange::<_, Vec<String>>(&key, 0, -1).await {
@@ -157,33 +192,7 @@ impl YDocManager {
     /// Get document state as a full update (for client sync)
     pub async fn get_state_as_update(&self, doc_id: &str) -> Result<Vec<u8>, String> {
         let updates = self.get_updates(doc_id).await?;
-
-        // Create a new Yjs document
-        let doc = Doc::new();
-
-        // Apply all updates if any exist
-        if !updates.is_empty() {
-            let mut txn = doc.transact_mut();
-
-            for update_bytes in updates {
-                match Update::decode_v1(&update_bytes) {
-                    Ok(update) => {
-                        if let Err(e) = txn.apply_update(update) {
-                            tracing::error!("Failed to apply update: {}", e);
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to decode update: {}", e);
-                    }
-                }
-            }
-        }
-
-        // Encode entire state as update (even if empty, this produces a valid Yjs update)
-        let state_vector = StateVector::default();
-        let txn = doc.transact();
-        let update = txn.encode_diff_v1(&state_vector);
-        Ok(update)
+        Ok(merge_updates(&updates))
     }
 
     /// Check if a document exists
@@ -192,7 +201,7 @@ impl YDocManager {
 
         // Check Redis first
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let key = format!("{}:{}:updates", self.redis_prefix, sanitized_id);
 
                 if let Ok(exists) = conn.exists::<_, bool>(&key).await {
@@ -219,9 +228,18 @@ impl YDocManager {
                 .push(user_id.to_string());
         }
 
+        // Document is in use again
+        {
+            let mut activity = self.activity.write().await;
+            activity
+                .entry(sanitized_id.clone())
+                .or_insert_with(|| DocumentActivity::new(doc_id))
+                .idle_since = None;
+        }
+
         // Add to Redis if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let key = format!("{}:{}:users", self.redis_prefix, sanitized_id);
                 let _ = conn.sadd::<_, _, ()>(&key, user_id).await;
                 tracing::debug!("Added user {} to document {} in Redis", user_id, doc_id);
@@ -236,7 +254,7 @@ impl YDocManager {
         let sanitized_id = self.sanitize_doc_id(doc_id);
 
         // Remove from memory
-        {
+        let now_empty = {
             let mut users = self.users.write().await;
             if let Some(user_list) = users.get_mut(&sanitized_id) {
                 user_list.retain(|u| u != user_id);
@@ -244,11 +262,23 @@ impl YDocManager {
                     users.remove(&sanitized_id);
                 }
             }
+            !users.contains_key(&sanitized_id)
+        };
+
+        // Start the idle clock once the last editor leaves
+        {
+            let mut activity = self.activity.write().await;
+            if let Some(entry) = activity.get_mut(&sanitized_id) {
+                entry.read_only.remove(user_id);
+                if now_empty && entry.idle_since.is_none() {
+                    entry.idle_since = Some(Instant::now());
+                }
+            }
         }
 
         // Remove from Redis if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let key = format!("{}:{}:users", self.redis_prefix, sanitized_id);
                 let _ = conn.srem::<_, _, ()>(&key, user_id).await;
                 tracing::debug!("Removed user {} from document {} in Redis", user_id, doc_id);
@@ -264,7 +294,7 @@ impl YDocManager {
 
         // Try Redis first
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let key = format!("{}:{}:users", self.redis_prefix, sanitized_id);
                 if let Ok(users) = conn.smembers::<_, Vec<String>>(&key).await {
                     return Ok(users);
@@ -288,11 +318,14 @@ impl YDocManager {
 
             let mut users = self.users.write().await;
             users.remove(&sanitized_id);
+
+            let mut activity = self.activity.write().await;
+            activity.remove(&sanitized_id);
         }
 
         // Clear from Redis if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let updates_key = format!("{}:{}:updates", self.redis_prefix, sanitized_id);
                 let users_key = format!("{}:{}:users", self.redis_prefix, sanitized_id);
                 let _ = conn
@@ -306,6 +339,9 @@ impl YDocManager {
     }
 
     /// Remove user from all documents
+    ///
+    /// Documents left without editors are kept until [`Self::take_idle_documents`]
+    /// reports them, so pending state can be flushed first.
     pub async fn remove_user_from_all(&self, user_id: &str) -> Result<(), String> {
         // Get all documents this user is in
         let doc_ids: Vec<String> = {
@@ -320,16 +356,154 @@ impl YDocManager {
         // Remove user from each document
         for doc_id in doc_ids {
             self.remove_user(&doc_id, user_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Mark a session as read-only for a document
+    pub async fn set_read_only(&self, doc_id: &str, sid: &str) {
+        let sanitized_id = self.sanitize_doc_id(doc_id);
+        let mut activity = self.activity.write().await;
+        activity
+            .entry(sanitized_id)
+            .or_insert_with(|| DocumentActivity::new(doc_id))
+            .read_only
+            .insert(sid.to_string());
+    }
+
+    /// Check whether a session may only read a document
+    pub async fn is_read_only(&self, doc_id: &str, sid: &str) -> bool {
+        let sanitized_id = self.sanitize_doc_id(doc_id);
+        let activity = self.activity.read().await;
+        activity
+            .get(&sanitized_id)
+            .map(|entry| entry.read_only.contains(sid))
+            .unwrap_or(false)
+    }
 
-            // Check if document is now empty
-            let users = self.get_users(&doc_id).await?;
-            if users.is_empty() {
-                self.clear_document(&doc_id).await?;
+    /// Record that a document changed, along with the latest materialized content if known
+    pub async fn mark_dirty(&self, doc_id: &str, snapshot: Option<serde_json::Value>) {
+        let sanitized_id = self.sanitize_doc_id(doc_id);
+        let mut activity = self.activity.write().await;
+        let entry = activity
+            .entry(sanitized_id)
+            .or_insert_with(|| DocumentActivity::new(doc_id));
+        entry.dirty = true;
+        if snapshot.is_some() {
+            entry.snapshot = snapshot;
+        }
+    }
+
+    /// Take all documents changed since the last call, with their latest snapshots
+    pub async fn take_dirty_documents(&self) -> Vec<(String, Option<serde_json::Value>)> {
+        let mut activity = self.activity.write().await;
+        activity
+            .values_mut()
+            .filter(|entry| entry.dirty)
+            .map(|entry| {
+                entry.dirty = false;
+                (entry.doc_id.clone(), entry.snapshot.take())
+            })
+            .collect()
+    }
+
+    /// Remove and return documents that have had no editors for at least `timeout`
+    pub async fn take_idle_documents(&self, timeout: Duration) -> Vec<String> {
+        let mut activity = self.activity.write().await;
+        let idle: Vec<String> = activity
+            .iter()
+            .filter(|(_, entry)| {
+                !entry.dirty
+                    && entry
+                        .idle_since
+                        .map(|since| since.elapsed() >= timeout)
+                        .unwrap_or(false)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        idle.into_iter()
+            .filter_map(|key| activity.remove(&key).map(|entry| entry.doc_id))
+            .collect()
+    }
+
+    /// Seed an empty document with previously persisted state
+    pub async fn seed_document(&self, doc_id: &str, state: Vec<u8>) -> Result<bool, String> {
+        if state.is_empty() || self.document_exists(doc_id).await? {
+            return Ok(false);
+        }
+
+        Update::decode_v1(&state).map_err(|e| format!("Invalid document state: {}", e))?;
+        self.append_update(doc_id, state).await?;
+        Ok(true)
+    }
+
+    /// Merge all stored updates into a single update and return it
+    ///
+    /// Only the updates that were merged are replaced, so updates appended concurrently survive.
+    pub async fn compact(&self, doc_id: &str) -> Result<Vec<u8>, String> {
+        let updates = self.get_updates(doc_id).await?;
+        let merged_count = updates.len();
+        let state = merge_updates(&updates);
+        let sanitized_id = self.sanitize_doc_id(doc_id);
+
+        if merged_count <= 1 {
+            return Ok(state);
+        }
+
+        {
+            let mut updates = self.updates.write().await;
+            if let Some(stored) = updates.get_mut(&sanitized_id) {
+                let end = merged_count.min(stored.len());
+                stored.splice(0..end, [state.clone()]);
             }
         }
 
-        Ok(())
+        if let Some(redis) = &self.redis {
+            if let Some(mut conn) = redis.get().await {
+                let key = format!("{}:{}:updates", self.redis_prefix, sanitized_id);
+                let update_json = serde_json::to_string(&state).map_err(|e| e.to_string())?;
+                let _ = redis::pipe()
+                    .atomic()
+                    .ltrim(&key, merged_count as isize, -1)
+                    .lpush(&key, update_json)
+                    .query_async::<()>(&mut conn)
+                    .await;
+            }
+        }
+
+        Ok(state)
+    }
+}
+
+/// Apply updates to a fresh document and encode its entire state as a single update
+fn merge_updates(updates: &[Vec<u8>]) -> Vec<u8> {
+    // Create a new Yjs document
+    let doc = Doc::new();
+
+    // Apply all updates if any exist
+    if !updates.is_empty() {
+        let mut txn = doc.transact_mut();
+
+        for update_bytes in updates {
+            match Update::decode_v1(update_bytes) {
+                Ok(update) => {
+                    if let Err(e) = txn.apply_update(update) {
+                        tracing::error!("Failed to apply update: {}", e);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Failed to decode update: {}", e);
+                }
+            }
+        }
     }
+
+    // Encode entire state as update (even if empty, this produces a valid Yjs update)
+    let state_vector = StateVector::default();
+    let txn = doc.transact();
+    txn.encode_diff_v1(&state_vector)
 }
 
 #[cfg(test)]
@@ -371,6 +545,92 @@ mod tests {
         assert_eq!(updates.len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_idle_documents_are_kept_until_timeout() {
+        let manager = YDocManager::new(None);
+        let doc_id = "note:idle";
+
+        manager.append_update(doc_id, vec![1]).await.unwrap();
+        manager.add_user(doc_id, "sid-1").await.unwrap();
+        manager
+            .mark_dirty(doc_id, Some(serde_json::json!({"md": "hi"})))
+            .await;
+
+        // Leaving no longer clears the document immediately
+        manager.remove_user_from_all("sid-1").await.unwrap();
+        assert!(manager.document_exists(doc_id).await.unwrap());
+
+        // Dirty documents are not evicted before they are flushed
+        assert!(manager.take_idle_documents(Duration::ZERO).await.is_empty());
+
+        let dirty = manager.take_dirty_documents().await;
+        assert_eq!(
+            dirty,
+            vec![(doc_id.to_string(), Some(serde_json::json!({"md": "hi"})))]
+        );
+        assert!(manager.take_dirty_documents().await.is_empty());
+
+        assert!(manager
+            .take_idle_documents(Duration::from_secs(3600))
+            .await
+            .is_empty());
+        assert_eq!(
+            manager.take_idle_documents(Duration::ZERO).await,
+            vec![doc_id.to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejoin_resets_idle_and_read_only() {
+        let manager = YDocManager::new(None);
+        let doc_id = "note:rejoin";
+
+        manager.add_user(doc_id, "sid-1").await.unwrap();
+        manager.set_read_only(doc_id, "sid-1").await;
+        assert!(manager.is_read_only(doc_id, "sid-1").await);
+
+        manager.remove_user(doc_id, "sid-1").await.unwrap();
+        assert!(!manager.is_read_only(doc_id, "sid-1").await);
+
+        manager.add_user(doc_id, "sid-2").await.unwrap();
+        assert!(manager.take_idle_documents(Duration::ZERO).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_compact_and_seed() {
+        let manager = YDocManager::new(None);
+        let doc_id = "note:compact";
+
+        for word in ["Hello ", "World"] {
+            let doc = Doc::new();
+            {
+                let mut txn = doc.transact_mut();
+                let text = txn.get_or_insert_text("content");
+                text.push(&mut txn, word);
+            }
+            let update = doc.transact().encode_diff_v1(&StateVector::default());
+            manager.append_update(doc_id, update).await.unwrap();
+        }
+
+        let state = manager.compact(doc_id).await.unwrap();
+        assert_eq!(
+            manager.get_updates(doc_id).await.unwrap(),
+            vec![state.clone()]
+        );
+
+        // Seeding only applies to documents that aren't loaded
+        assert!(!manager.seed_document(doc_id, state.clone()).await.unwrap());
+
+        let other = "note:seeded";
+        assert!(manager.seed_document(other, state.clone()).await.unwrap());
+        assert!(!manager.seed_document(other, state.clone()).await.unwrap());
+        assert_eq!(manager.get_updates(other).await.unwrap(), vec![state]);
+        assert!(manager
+            .seed_document("note:bad", vec![0xff, 0xff])
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     async fn test_yjs_document_merge() {
         let manager = YDocManager::new(None);
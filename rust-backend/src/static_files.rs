@@ -1,5 +1,16 @@
-use actix_web::{HttpRequest, HttpResponse};
+use actix_files::{Files, NamedFile};
+use actix_web::body::BoxBody;
+use actix_web::dev::{HttpServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, ContentEncoding, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::{from_fn, Next};
+use actix_web::{web, HttpRequest, HttpResponse};
 use mime_guess::from_path;
+use std::borrow::Cow;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::AppState;
 
 #[cfg(feature = "embed-frontend")]
 use rust_embed::RustEmbed;
@@ -44,6 +55,238 @@ pub struct EmbeddedFile {
 #[cfg(feature = "embed-frontend")]
 pub use rust_embed::RustEmbed as _;
 
+/// Cache policy for everything but hashed assets: reuse only after revalidating the ETag
+const REVALIDATE: &str = "no-cache";
+
+/// Precompressed variants the frontend build may ship, in order of preference
+const PRECOMPRESSED: [(&str, &str); 3] = [("br", ".br"), ("zstd", ".zst"), ("gzip", ".gz")];
+
+/// An embedded file and the SHA-256 of its contents
+#[cfg_attr(not(feature = "embed-frontend"), allow(dead_code))]
+struct Asset {
+    data: Cow<'static, [u8]>,
+    hash: [u8; 32],
+}
+
+#[cfg(feature = "embed-frontend")]
+fn embedded_asset(path: &str) -> Option<Asset> {
+    FrontendAssets::get(path).map(|file| Asset {
+        hash: file.metadata.sha256_hash(),
+        data: file.data,
+    })
+}
+
+/// Build the response for an embedded file, or `None` if there is no such file
+///
+/// Responses carry a strong ETag derived from the content hash and answer
+/// `If-None-Match` with 304. When the build shipped `.br`/`.gz` variants and the
+/// client accepts them, the variant is sent as-is instead of compressing per request.
+#[cfg_attr(not(feature = "embed-frontend"), allow(dead_code))]
+fn asset_response(
+    req: &HttpRequest,
+    path: &str,
+    max_age: u64,
+    lookup: impl Fn(&str) -> Option<Asset>,
+) -> Option<HttpResponse> {
+    let mut asset = lookup(path)?;
+    let mut encoding = None;
+    for (name, extension) in PRECOMPRESSED {
+        if !accepts_encoding(req, name) {
+            continue;
+        }
+        if let Some(variant) = lookup(&format!("{}{}", path, extension)) {
+            asset = variant;
+            encoding = Some(name);
+            break;
+        }
+    }
+
+    let etag = match encoding {
+        Some(name) => format!("\"{}-{}\"", hex_prefix(&asset.hash), name),
+        None => format!("\"{}\"", hex_prefix(&asset.hash)),
+    };
+    let cache_control = cache_control(path, max_age);
+
+    if if_none_match(req, &etag) {
+        return Some(
+            HttpResponse::NotModified()
+                .insert_header((header::ETAG, etag))
+                .insert_header((header::CACHE_CONTROL, cache_control.as_ref()))
+                .insert_header((header::VARY, "Accept-Encoding"))
+                .finish(),
+        );
+    }
+
+    let mime_type = from_path(path).first_or_octet_stream();
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(mime_type.as_ref())
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, cache_control.as_ref()))
+        .insert_header((header::VARY, "Accept-Encoding"));
+    // A Content-Encoding header also stops the Compress middleware from re-encoding
+    if let Some(name) = encoding {
+        response.insert_header((header::CONTENT_ENCODING, name));
+    }
+    Some(response.body(asset.data.into_owned()))
+}
+
+/// `Cache-Control` for a static file
+///
+/// Files with a content hash in their name never change, so browsers may keep them for
+/// `max_age` seconds without asking again. Everything else, including `index.html` and
+/// the SPA fallback, is revalidated against its ETag so a new release shows up on the
+/// next load. A `max_age` of 0 revalidates every file.
+fn cache_control(path: &str, max_age: u64) -> Cow<'static, str> {
+    if max_age > 0 && is_hashed_asset(path) {
+        Cow::Owned(format!("public, max-age={}, immutable", max_age))
+    } else {
+        Cow::Borrowed(REVALIDATE)
+    }
+}
+
+/// STATIC_CACHE_MAX_AGE, read from the app state the request runs under
+#[cfg_attr(not(feature = "embed-frontend"), allow(dead_code))]
+fn cache_max_age(req: &HttpRequest) -> u64 {
+    req.app_data::<web::Data<AppState>>()
+        .map(|state| state.config.snapshot().static_cache_max_age)
+        .unwrap_or_default()
+}
+
+/// Serve a directory from disk (`STATIC_DIR`) like the embedded assets
+///
+/// actix-files already answers conditional requests from its ETag/Last-Modified; this
+/// adds the matching `Cache-Control`, and sends a `.br`/`.zst`/`.gz` file next to the
+/// requested one as-is when the client accepts that encoding. Files without a variant
+/// are left to the `Compress` middleware.
+pub fn files_service(mount: &str, dir: &str, max_age: u64) -> impl HttpServiceFactory {
+    let root: Arc<Path> = Arc::from(Path::new(dir));
+    web::scope(mount)
+        .wrap(from_fn(move |req: ServiceRequest, next: Next<BoxBody>| {
+            static_dir_response(req, next, root.clone(), max_age)
+        }))
+        .service(Files::new("", dir))
+}
+
+async fn static_dir_response(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+    root: Arc<Path>,
+    max_age: u64,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let path = req.path().trim_start_matches('/').to_string();
+    let mut res = match precompressed_file(&req, &root).await {
+        Some(file) => {
+            let mut res = file.into_response(req.request());
+            res.headers_mut()
+                .insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+            req.into_response(res)
+        }
+        None => next.call(req).await?,
+    };
+    if res.status().is_success() || res.status() == StatusCode::NOT_MODIFIED {
+        if let Ok(value) = HeaderValue::from_str(&cache_control(&path, max_age)) {
+            res.headers_mut().insert(header::CACHE_CONTROL, value);
+        }
+    }
+    Ok(res)
+}
+
+/// The precompressed variant of the requested file the client accepts, if one exists
+async fn precompressed_file(req: &ServiceRequest, root: &Path) -> Option<NamedFile> {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return None;
+    }
+    // Plain path segments only; anything unusual is left to actix-files to resolve
+    let relative = req.match_info().unprocessed().trim_start_matches('/');
+    let relative = Path::new(relative);
+    if relative.as_os_str().is_empty()
+        || relative.to_string_lossy().contains('%')
+        || relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let path = root.join(relative);
+    for (name, extension) in PRECOMPRESSED {
+        if !accepts_encoding(req.request(), name) {
+            continue;
+        }
+        let mut variant = path.clone().into_os_string();
+        variant.push(extension);
+        if let Ok(file) = NamedFile::open_async(PathBuf::from(variant)).await {
+            let encoding = match name {
+                "br" => ContentEncoding::Brotli,
+                "zstd" => ContentEncoding::Zstd,
+                _ => ContentEncoding::Gzip,
+            };
+            return Some(
+                file.set_content_type(from_path(&path).first_or_octet_stream())
+                    .set_content_encoding(encoding)
+                    .disable_content_disposition(),
+            );
+        }
+    }
+    None
+}
+
+/// Whether the file name carries a content hash, e.g. SvelteKit's `_app/immutable/`
+/// output or Vite's `index-B2xQ7mZk.js`
+fn is_hashed_asset(path: &str) -> bool {
+    if path.starts_with("_app/immutable/") {
+        return true;
+    }
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let stem = file_name.split('.').next().unwrap_or(file_name);
+    match stem.rsplit_once('-') {
+        Some((_, hash)) => {
+            hash.len() >= 8
+                && hash.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && hash.chars().any(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+fn hex_prefix(hash: &[u8; 32]) -> String {
+    hash[..16].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn accepts_encoding(req: &HttpRequest, encoding: &str) -> bool {
+    let accept = match req
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(accept) => accept,
+        None => return false,
+    };
+    accept.split(',').any(|part| {
+        let mut params = part.split(';');
+        let name = params.next().unwrap_or("").trim();
+        let rejected = params.any(|p| {
+            p.trim()
+                .strip_prefix("q=")
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .map(|q| q == 0.0)
+                .unwrap_or(false)
+        });
+        name.eq_ignore_ascii_case(encoding) && !rejected
+    })
+}
+
+/// `If-None-Match` check; uses the weak comparison the header calls for
+fn if_none_match(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get_all(header::IF_NONE_MATCH)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
 /// Serve embedded static files with SPA fallback
 /// This handler serves both static assets and handles SPA routing
 pub async fn serve(req: HttpRequest) -> HttpResponse {
@@ -59,13 +302,11 @@ pub async fn serve(req: HttpRequest) -> HttpResponse {
             path = "index.html";
         }
 
-        // Try to serve the requested file
-        if let Some(content) = FrontendAssets::get(path) {
-            let mime_type = from_path(path).first_or_octet_stream();
+        let max_age = cache_max_age(&req);
 
-            return HttpResponse::Ok()
-                .content_type(mime_type.as_ref())
-                .body(content.data.into_owned());
+        // Try to serve the requested file
+        if let Some(response) = asset_response(&req, path, max_age, embedded_asset) {
+            return response;
         }
 
         // For SPA routing: if file not found and it doesn't look like an API request,
@@ -83,10 +324,8 @@ pub async fn serve(req: HttpRequest) -> HttpResponse {
             && path != "favicon.png"
             && path != "user.png"
         {
-            if let Some(index) = FrontendAssets::get("index.html") {
-                return HttpResponse::Ok()
-                    .content_type("text/html")
-                    .body(index.data.into_owned());
+            if let Some(response) = asset_response(&req, "index.html", max_age, embedded_asset) {
+                return response;
             }
         }
 
@@ -111,3 +350,209 @@ pub async fn serve(req: HttpRequest) -> HttpResponse {
             .body(r#"{"error":"Frontend not embedded in this slim build. Please use a separate frontend or the full build."}"#)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    fn fixture(path: &str) -> Option<Asset> {
+        let (data, seed): (&'static [u8], u8) = match path {
+            "_app/immutable/entry/app.B2xQ7mZk.js" => (b"console.log('app')", 1),
+            "_app/immutable/entry/app.B2xQ7mZk.js.br" => (b"brotli", 2),
+            "_app/immutable/entry/app.B2xQ7mZk.js.gz" => (b"gzip", 3),
+            "index.html" => (b"<html></html>", 4),
+            _ => return None,
+        };
+        Some(Asset {
+            data: Cow::Borrowed(data),
+            hash: [seed; 32],
+        })
+    }
+
+    const APP_JS: &str = "_app/immutable/entry/app.B2xQ7mZk.js";
+    const YEAR: u64 = 31_536_000;
+
+    #[actix_web::test]
+    async fn test_conditional_requests() {
+        let req = TestRequest::get().to_http_request();
+        let resp = asset_response(&req, APP_JS, YEAR, fixture).unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=31536000, immutable"
+        );
+        let etag = resp.headers().get(header::ETAG).unwrap().to_str().unwrap();
+        assert_eq!(etag, format!("\"{}\"", "01".repeat(16)));
+
+        // Matching tag, alone, in a list or weak, gets a bodyless 304
+        for header_value in [
+            etag.to_string(),
+            format!("\"stale\", {}", etag),
+            format!("W/{}", etag),
+            "*".to_string(),
+        ] {
+            let req = TestRequest::get()
+                .insert_header((header::IF_NONE_MATCH, header_value))
+                .to_http_request();
+            let resp = asset_response(&req, APP_JS, YEAR, fixture).unwrap();
+            assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(resp.headers().get(header::ETAG).unwrap(), etag);
+        }
+
+        let req = TestRequest::get()
+            .insert_header((header::IF_NONE_MATCH, "\"stale\""))
+            .to_http_request();
+        let resp = asset_response(&req, APP_JS, YEAR, fixture).unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_precompressed_variants() {
+        let req = TestRequest::get()
+            .insert_header((header::ACCEPT_ENCODING, "gzip, deflate, br"))
+            .to_http_request();
+        let resp = asset_response(&req, APP_JS, YEAR, fixture).unwrap();
+        assert_eq!(resp.headers().get(header::CONTENT_ENCODING).unwrap(), "br");
+        let etag = resp.headers().get(header::ETAG).unwrap().clone();
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"brotli");
+
+        // Each encoding revalidates against its own tag
+        let req = TestRequest::get()
+            .insert_header((header::ACCEPT_ENCODING, "gzip, br;q=0"))
+            .insert_header((header::IF_NONE_MATCH, etag))
+            .to_http_request();
+        let resp = asset_response(&req, APP_JS, YEAR, fixture).unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_ENCODING).unwrap(),
+            "gzip"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_index_is_revalidated() {
+        let req = TestRequest::get()
+            .insert_header((header::ACCEPT_ENCODING, "br"))
+            .to_http_request();
+        let resp = asset_response(&req, "index.html", YEAR, fixture).unwrap();
+        assert_eq!(
+            resp.headers().get(header::CACHE_CONTROL).unwrap(),
+            "no-cache"
+        );
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+        assert!(asset_response(&req, "missing.js", YEAR, fixture).is_none());
+    }
+
+    #[test]
+    fn test_cache_control_max_age() {
+        assert_eq!(cache_control(APP_JS, 600), "public, max-age=600, immutable");
+        assert_eq!(cache_control(APP_JS, 0), "no-cache");
+        assert_eq!(cache_control("index.html", YEAR), "no-cache");
+    }
+
+    #[actix_web::test]
+    async fn test_files_service_cache_headers() {
+        use actix_web::{test, App};
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("splash.png"), b"png").unwrap();
+        std::fs::write(dir.path().join("app-B2xQ7mZ9.js"), b"js").unwrap();
+        let app = test::init_service(App::new().service(files_service(
+            "/static",
+            dir.path().to_str().unwrap(),
+            600,
+        )))
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/static/splash.png")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CACHE_CONTROL).unwrap(),
+            "no-cache"
+        );
+        assert!(resp.headers().contains_key(header::ETAG));
+
+        let req = test::TestRequest::get()
+            .uri("/static/app-B2xQ7mZ9.js")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=600, immutable"
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/static/missing.js")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.headers().get(header::CACHE_CONTROL).is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_files_service_precompressed() {
+        use actix_web::{test, App};
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.js"), b"console.log('app')").unwrap();
+        std::fs::write(dir.path().join("app.js.br"), b"brotli").unwrap();
+        std::fs::write(dir.path().join("app.js.gz"), b"gzip").unwrap();
+        let app = test::init_service(App::new().service(files_service(
+            "/static",
+            dir.path().to_str().unwrap(),
+            600,
+        )))
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/static/app.js")
+            .insert_header((header::ACCEPT_ENCODING, "gzip, br"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(header::CONTENT_ENCODING).unwrap(), "br");
+        // The type of the original file, not of the .br variant
+        let content_type = resp.headers().get(header::CONTENT_TYPE).unwrap();
+        assert!(content_type
+            .to_str()
+            .unwrap()
+            .starts_with(from_path("app.js").first_or_octet_stream().as_ref()));
+        assert_eq!(
+            resp.headers().get(header::CACHE_CONTROL).unwrap(),
+            "no-cache"
+        );
+        let body = test::read_body(resp).await;
+        assert_eq!(&body[..], b"brotli");
+
+        let req = test::TestRequest::get()
+            .uri("/static/app.js")
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get(header::CONTENT_ENCODING).unwrap(),
+            "gzip"
+        );
+
+        // Without Accept-Encoding the original file is served
+        let req = test::TestRequest::get().uri("/static/app.js").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+        let body = test::read_body(resp).await;
+        assert_eq!(&body[..], b"console.log('app')");
+    }
+
+    #[test]
+    fn test_is_hashed_asset() {
+        assert!(is_hashed_asset("_app/immutable/chunks/index.js"));
+        assert!(is_hashed_asset("assets/index-B2xQ7mZ9.js"));
+        assert!(!is_hashed_asset("favicon.png"));
+        assert!(!is_hashed_asset("static/splash-dark.png"));
+        assert!(!is_hashed_asset("index.html"));
+    }
+}
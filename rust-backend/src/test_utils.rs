@@ -0,0 +1,110 @@
+//! Fixtures shared by the unit tests
+
+use actix_web::web;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::config::{Config, SharedConfig};
+use crate::db::Database;
+use crate::models::User;
+use crate::services::user::UserService;
+use crate::utils::auth::create_jwt;
+use crate::AppState;
+
+/// SQLite database in `dir` with the migrations applied
+pub async fn test_db(dir: &tempfile::TempDir) -> Database {
+    let db_url = format!("sqlite://{}", dir.path().join("test.db").display());
+    let db = Database::new(&db_url).await.unwrap();
+    db.run_migrations().await.unwrap();
+    db
+}
+
+/// App state over `db` without Redis, Socket.IO or retrieval backends, for
+/// tests that fill in more of it before wrapping it up
+pub fn app_state(db: Database, config: Config) -> AppState {
+    AppState {
+        db,
+        config: Arc::new(SharedConfig::new(config)),
+        redis: None,
+        models_cache: Arc::new(RwLock::new(HashMap::new())),
+        socket_state: None,
+        socketio_handler: None,
+        http_client: reqwest::Client::new(),
+        vector_db: None,
+        embedding_provider: None,
+        sandbox_executor_client: None,
+        autocomplete_rate_limiter: Arc::new(crate::socketio::RateLimiter::new(Default::default())),
+        upstream_breakers: Arc::new(crate::utils::upstream::UpstreamBreakers::new(
+            Default::default(),
+        )),
+        request_rate_limits: Arc::new(crate::middleware::rate_limit::MemoryRateLimitStore::new()),
+        tasks: Arc::new(crate::utils::tasks::TaskManager::new(
+            None,
+            None,
+            "test".to_string(),
+        )),
+    }
+}
+
+/// [`app_state`] over a fresh database in `dir`
+pub async fn test_state(dir: &tempfile::TempDir, config: Config) -> web::Data<AppState> {
+    web::Data::new(app_state(test_db(dir).await, config))
+}
+
+/// Add the user `id`, named after its id
+pub async fn create_user(db: &Database, id: &str, role: &str) -> User {
+    UserService::new(db)
+        .create_user(id, id, &format!("{}@example.com", id), role, "")
+        .await
+        .unwrap()
+}
+
+/// Session token of user `id`
+pub fn user_token(state: &AppState, id: &str) -> String {
+    create_jwt(id, &state.config.snapshot().webui_secret_key, "1h").unwrap()
+}
+
+/// Forward proxy for plain HTTP: reads the absolute-form request line and tunnels the
+/// connection to the host it names. Counts the connections it handles.
+pub async fn start_forward_proxy() -> (SocketAddr, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((mut client, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut head = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match client.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => head.extend_from_slice(&buf[..n]),
+                    }
+                }
+                // GET http://127.0.0.1:1234/stream HTTP/1.1
+                let request_line = String::from_utf8_lossy(&head)
+                    .lines()
+                    .next()
+                    .unwrap()
+                    .to_string();
+                let target = request_line.split(' ').nth(1).unwrap();
+                let authority = target
+                    .trim_start_matches("http://")
+                    .split('/')
+                    .next()
+                    .unwrap()
+                    .to_string();
+                let mut upstream = TcpStream::connect(authority).await.unwrap();
+                upstream.write_all(&head).await.unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+            });
+        }
+    });
+    (addr, connections)
+}
@@ -0,0 +1,598 @@
+use serde_json::{json, Map, Value};
+
+use crate::utils::chat_translation::{
+    self, chat_usage, content_parts, inline_image, now, push_turn, text_of, tool_call_arguments,
+    ChunkConverter, ChunkHeader, ContentPart, SseData, DONE,
+};
+
+/// `anthropic-version` header sent with every Messages API request
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// The Messages API requires `max_tokens`; used when the chat request doesn't set one
+const DEFAULT_MAX_TOKENS: u64 = 4096;
+
+/// Whether a connection speaks Anthropic's Messages API (`"api_type": "anthropic"` in
+/// its `openai_api_configs` entry) rather than OpenAI chat completions
+pub fn is_anthropic(api_config: &Value) -> bool {
+    api_config.get("api_type").and_then(|v| v.as_str()) == Some("anthropic")
+}
+
+/// Authenticate `request` the way Anthropic expects: `x-api-key` instead of a bearer token
+pub fn with_auth(request: reqwest::RequestBuilder, key: &str) -> reqwest::RequestBuilder {
+    let request = request.header("anthropic-version", ANTHROPIC_VERSION);
+    if key.is_empty() {
+        request
+    } else {
+        request.header("x-api-key", key)
+    }
+}
+
+/// `POST {url}/messages` carrying the chat completion request `payload`
+pub fn messages_request(
+    client: &reqwest::Client,
+    url: &str,
+    key: &str,
+    payload: &Value,
+) -> reqwest::RequestBuilder {
+    let request = client
+        .post(format!("{}/messages", url.trim_end_matches('/')))
+        .header("Content-Type", "application/json");
+    with_auth(request, key).json(&to_messages_payload(payload))
+}
+
+/// Present a Messages API response as a chat completion response, streamed or not
+///
+/// Error responses are passed through untouched so callers report them as usual.
+pub async fn into_chat_completion_response(
+    response: reqwest::Response,
+) -> reqwest::Result<reqwest::Response> {
+    chat_translation::into_chat_completion_response(
+        response,
+        MessageStreamConverter::new,
+        message_to_chat_completion,
+    )
+    .await
+}
+
+/// Translate an OpenAI chat completion request into a Messages API request
+///
+/// System messages become the top-level `system` prompt, tool calls and results become
+/// `tool_use`/`tool_result` blocks, and consecutive messages of the same role are merged
+/// since the Messages API expects user and assistant turns to alternate.
+pub fn to_messages_payload(payload: &Value) -> Value {
+    let mut system = Vec::new();
+    let mut messages: Vec<Value> = Vec::new();
+
+    for message in payload
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let role = message
+            .get("role")
+            .and_then(|r| r.as_str())
+            .unwrap_or("user");
+        let (role, blocks) = match role {
+            "system" | "developer" => {
+                let text = text_of(message.get("content"));
+                if !text.is_empty() {
+                    system.push(text);
+                }
+                continue;
+            }
+            "assistant" => ("assistant", assistant_blocks(message)),
+            "tool" => ("user", vec![tool_result_block(message)]),
+            _ => ("user", content_blocks(message.get("content"))),
+        };
+        push_turn(&mut messages, role, "content", blocks);
+    }
+
+    let mut request = Map::new();
+    request.insert("model".to_string(), payload["model"].clone());
+    request.insert("messages".to_string(), json!(messages));
+    if !system.is_empty() {
+        request.insert("system".to_string(), json!(system.join("\n\n")));
+    }
+
+    let max_tokens = payload
+        .get("max_tokens")
+        .or_else(|| payload.get("max_completion_tokens"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+    request.insert("max_tokens".to_string(), json!(max_tokens));
+
+    for key in ["temperature", "top_p", "top_k", "stream"] {
+        if let Some(value) = payload.get(key).filter(|v| !v.is_null()) {
+            request.insert(key.to_string(), value.clone());
+        }
+    }
+    match payload.get("stop") {
+        Some(Value::String(stop)) => {
+            request.insert("stop_sequences".to_string(), json!([stop]));
+        }
+        Some(Value::Array(stops)) if !stops.is_empty() => {
+            request.insert("stop_sequences".to_string(), json!(stops));
+        }
+        _ => {}
+    }
+    if let Some(user) = payload.get("user").and_then(|u| u.as_str()) {
+        request.insert("metadata".to_string(), json!({ "user_id": user }));
+    }
+
+    let tools: Vec<Value> = payload
+        .get("tools")
+        .and_then(|t| t.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|tool| {
+            let function = tool.get("function")?;
+            let mut converted = json!({
+                "name": function.get("name")?,
+                "input_schema": function
+                    .get("parameters")
+                    .filter(|p| p.is_object())
+                    .cloned()
+                    .unwrap_or_else(|| json!({ "type": "object", "properties": {} })),
+            });
+            if let Some(description) = function.get("description").filter(|d| d.is_string()) {
+                converted["description"] = description.clone();
+            }
+            Some(converted)
+        })
+        .collect();
+    if !tools.is_empty() {
+        request.insert("tools".to_string(), json!(tools));
+        if let Some(choice) = payload.get("tool_choice").and_then(tool_choice) {
+            request.insert("tool_choice".to_string(), choice);
+        }
+    }
+
+    Value::Object(request)
+}
+
+fn tool_choice(choice: &Value) -> Option<Value> {
+    match choice {
+        Value::String(mode) => match mode.as_str() {
+            "auto" => Some(json!({ "type": "auto" })),
+            "required" => Some(json!({ "type": "any" })),
+            "none" => Some(json!({ "type": "none" })),
+            _ => None,
+        },
+        _ => choice
+            .pointer("/function/name")
+            .map(|name| json!({ "type": "tool", "name": name })),
+    }
+}
+
+/// Text blocks, and image blocks for `data:` URLs (sent inline) or plain URLs (fetched
+/// by Anthropic)
+fn content_blocks(content: Option<&Value>) -> Vec<Value> {
+    content_parts(content)
+        .into_iter()
+        .map(|part| match part {
+            ContentPart::Text(text) => json!({ "type": "text", "text": text }),
+            ContentPart::Image(url) => match inline_image(url) {
+                Some((media_type, data)) => json!({
+                    "type": "image",
+                    "source": { "type": "base64", "media_type": media_type, "data": data },
+                }),
+                None => json!({
+                    "type": "image",
+                    "source": { "type": "url", "url": url },
+                }),
+            },
+        })
+        .collect()
+}
+
+fn assistant_blocks(message: &Value) -> Vec<Value> {
+    let mut blocks = content_blocks(message.get("content"));
+    for call in message
+        .get("tool_calls")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+    {
+        blocks.push(json!({
+            "type": "tool_use",
+            "id": call.get("id").cloned().unwrap_or(Value::Null),
+            "name": call.pointer("/function/name").cloned().unwrap_or(Value::Null),
+            "input": tool_call_arguments(call),
+        }));
+    }
+    blocks
+}
+
+fn tool_result_block(message: &Value) -> Value {
+    json!({
+        "type": "tool_result",
+        "tool_use_id": message.get("tool_call_id").cloned().unwrap_or(Value::Null),
+        "content": text_of(message.get("content")),
+    })
+}
+
+fn finish_reason(stop_reason: Option<&str>) -> Value {
+    match stop_reason {
+        Some("max_tokens") => json!("length"),
+        Some("tool_use") => json!("tool_calls"),
+        Some(_) => json!("stop"),
+        None => Value::Null,
+    }
+}
+
+fn tokens(usage: Option<&Value>, key: &str) -> u64 {
+    usage
+        .and_then(|u| u.get(key))
+        .and_then(|t| t.as_u64())
+        .unwrap_or(0)
+}
+
+/// Convert a Messages API response into a `chat.completion` response
+pub fn message_to_chat_completion(message: &Value) -> Value {
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    for block in message
+        .get("content")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+    {
+        match block.get("type").and_then(|t| t.as_str()) {
+            Some("text") => text.push_str(block["text"].as_str().unwrap_or_default()),
+            Some("tool_use") => tool_calls.push(json!({
+                "id": block["id"],
+                "type": "function",
+                "function": {
+                    "name": block["name"],
+                    "arguments": block.get("input").unwrap_or(&json!({})).to_string(),
+                },
+            })),
+            _ => {}
+        }
+    }
+
+    let mut chat_message = json!({ "role": "assistant", "content": text });
+    if !tool_calls.is_empty() {
+        chat_message["tool_calls"] = json!(tool_calls);
+    }
+    let usage = message.get("usage");
+
+    json!({
+        "id": message.get("id").cloned().unwrap_or(Value::Null),
+        "object": "chat.completion",
+        "created": now(),
+        "model": message.get("model").cloned().unwrap_or(Value::Null),
+        "choices": [{
+            "index": 0,
+            "message": chat_message,
+            "finish_reason": finish_reason(message.get("stop_reason").and_then(|r| r.as_str())),
+        }],
+        "usage": chat_usage(tokens(usage, "input_tokens"), tokens(usage, "output_tokens")),
+    })
+}
+
+/// Rewrites a Messages API event stream into chat completion chunks
+///
+/// Only the `data:` lines are read; each carries its event `type`.
+#[derive(Default)]
+pub struct MessageStreamConverter {
+    lines: SseData,
+    header: ChunkHeader,
+    input_tokens: u64,
+    /// Content block index -> tool call index, for blocks that are tool calls
+    tool_calls: Vec<(u64, usize)>,
+}
+
+impl MessageStreamConverter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn convert_event(&mut self, event: &Value) -> Vec<String> {
+        match event.get("type").and_then(|t| t.as_str()) {
+            Some("message_start") => {
+                let message = &event["message"];
+                self.header.id = message.get("id").cloned().unwrap_or(Value::Null);
+                self.header.model = message.get("model").cloned().unwrap_or(Value::Null);
+                self.input_tokens = tokens(message.get("usage"), "input_tokens");
+                vec![self
+                    .header
+                    .chunk(json!({ "role": "assistant", "content": "" }), Value::Null)]
+            }
+            Some("content_block_start") => {
+                let block = &event["content_block"];
+                if block.get("type").and_then(|t| t.as_str()) != Some("tool_use") {
+                    return Vec::new();
+                }
+                let index = self.tool_calls.len();
+                let block_index = event["index"].as_u64().unwrap_or_default();
+                self.tool_calls.push((block_index, index));
+                vec![self.header.chunk(
+                    json!({ "tool_calls": [{
+                        "index": index,
+                        "id": block["id"],
+                        "type": "function",
+                        "function": { "name": block["name"], "arguments": "" },
+                    }]}),
+                    Value::Null,
+                )]
+            }
+            Some("content_block_delta") => {
+                let delta = &event["delta"];
+                match delta.get("type").and_then(|t| t.as_str()) {
+                    Some("text_delta") => {
+                        vec![self
+                            .header
+                            .chunk(json!({ "content": delta["text"] }), Value::Null)]
+                    }
+                    Some("thinking_delta") => vec![self.header.chunk(
+                        json!({ "reasoning_content": delta["thinking"] }),
+                        Value::Null,
+                    )],
+                    Some("input_json_delta") => {
+                        let block_index = event["index"].as_u64().unwrap_or_default();
+                        let index = match self.tool_calls.iter().find(|(b, _)| *b == block_index) {
+                            Some((_, index)) => *index,
+                            None => return Vec::new(),
+                        };
+                        vec![self.header.chunk(
+                            json!({ "tool_calls": [{
+                                "index": index,
+                                "function": { "arguments": delta["partial_json"] },
+                            }]}),
+                            Value::Null,
+                        )]
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            Some("message_delta") => {
+                let reason = event.pointer("/delta/stop_reason").and_then(|r| r.as_str());
+                let usage = chat_usage(
+                    self.input_tokens,
+                    tokens(event.get("usage"), "output_tokens"),
+                );
+                vec![
+                    self.header.chunk(json!({}), finish_reason(reason)),
+                    self.header.usage_chunk(usage),
+                ]
+            }
+            Some("message_stop") => vec![DONE.to_string()],
+            Some("error") => {
+                chat_translation::error_chunks(event.get("error").cloned().unwrap_or(Value::Null))
+            }
+            // ping, content_block_stop
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl ChunkConverter for MessageStreamConverter {
+    fn push(&mut self, bytes: &[u8]) -> String {
+        self.lines
+            .push(bytes)
+            .iter()
+            .flat_map(|event| self.convert_event(event))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_anthropic() {
+        assert!(is_anthropic(&json!({ "api_type": "anthropic" })));
+        assert!(!is_anthropic(&json!({ "api_type": "openai" })));
+        assert!(!is_anthropic(&json!({})));
+    }
+
+    #[test]
+    fn test_to_messages_payload() {
+        let payload = json!({
+            "model": "claude-sonnet-4-5",
+            "stream": true,
+            "temperature": 0.2,
+            "stop": "END",
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "system", "content": [{ "type": "text", "text": "Use tools." }] },
+                { "role": "user", "content": [
+                    { "type": "text", "text": "What's in this image and the weather?" },
+                    { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBO" } },
+                ]},
+                { "role": "assistant", "content": "", "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" },
+                }]},
+                { "role": "tool", "tool_call_id": "call_1", "content": "Sunny" },
+                { "role": "user", "content": "Thanks" },
+            ],
+            "tools": [{ "type": "function", "function": {
+                "name": "get_weather",
+                "description": "Current weather",
+                "parameters": { "type": "object", "properties": { "city": { "type": "string" } } },
+            }}],
+            "tool_choice": "auto",
+        });
+
+        let request = to_messages_payload(&payload);
+        assert_eq!(request["system"], "Be brief.\n\nUse tools.");
+        assert_eq!(request["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(request["stop_sequences"], json!(["END"]));
+        assert_eq!(request["stream"], true);
+        assert_eq!(request["temperature"], 0.2);
+
+        let messages = request["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["role"], "user");
+        assert_eq!(
+            messages[0]["content"][1]["source"],
+            json!({ "type": "base64", "media_type": "image/png", "data": "iVBO" })
+        );
+        assert_eq!(
+            messages[1]["content"],
+            json!([{
+                "type": "tool_use",
+                "id": "call_1",
+                "name": "get_weather",
+                "input": { "city": "Paris" },
+            }])
+        );
+        // The tool result and the next user message share one user turn
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(
+            messages[2]["content"],
+            json!([
+                { "type": "tool_result", "tool_use_id": "call_1", "content": "Sunny" },
+                { "type": "text", "text": "Thanks" },
+            ])
+        );
+
+        assert_eq!(
+            request["tools"],
+            json!([{
+                "name": "get_weather",
+                "description": "Current weather",
+                "input_schema": { "type": "object", "properties": { "city": { "type": "string" } } },
+            }])
+        );
+        assert_eq!(request["tool_choice"], json!({ "type": "auto" }));
+    }
+
+    #[test]
+    fn test_message_to_chat_completion() {
+        let message = json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-sonnet-4-5",
+            "content": [
+                { "type": "text", "text": "Checking." },
+                { "type": "tool_use", "id": "toolu_1", "name": "get_weather",
+                  "input": { "city": "Paris" } },
+            ],
+            "stop_reason": "tool_use",
+            "usage": { "input_tokens": 12, "output_tokens": 8 },
+        });
+
+        let completion = message_to_chat_completion(&message);
+        assert_eq!(completion["object"], "chat.completion");
+        assert_eq!(completion["choices"][0]["message"]["content"], "Checking.");
+        assert_eq!(completion["choices"][0]["finish_reason"], "tool_calls");
+        let call = &completion["choices"][0]["message"]["tool_calls"][0];
+        assert_eq!(call["id"], "toolu_1");
+        assert_eq!(call["function"]["name"], "get_weather");
+        assert_eq!(
+            serde_json::from_str::<Value>(call["function"]["arguments"].as_str().unwrap()).unwrap(),
+            json!({ "city": "Paris" })
+        );
+        assert_eq!(completion["usage"]["total_tokens"], 20);
+    }
+
+    fn data_lines(output: &str) -> Vec<Value> {
+        output
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_stream_conversion() {
+        let stream = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",",
+            "\"model\":\"claude-sonnet-4-5\",\"usage\":{\"input_tokens\":10}}}\n\n",
+            "event: content_block_start\n",
+            "data: {\"type\":\"content_block_start\",\"index\":0,",
+            "\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: ping\ndata: {\"type\":\"ping\"}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,",
+            "\"delta\":{\"type\":\"text_delta\",\"text\":\"Hel\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,",
+            "\"delta\":{\"type\":\"text_delta\",\"text\":\"lo\"}}\n\n",
+            "event: content_block_start\n",
+            "data: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":",
+            "{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"get_weather\",\"input\":{}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":1,",
+            "\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"city\\\":\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\"},",
+            "\"usage\":{\"output_tokens\":5}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+
+        // Split mid-line to exercise buffering
+        let mut converter = MessageStreamConverter::new();
+        let bytes = stream.as_bytes();
+        let mut output = String::new();
+        for piece in bytes.chunks(37) {
+            output.push_str(&converter.push(piece));
+        }
+        assert!(output.ends_with("data: [DONE]\n\n"));
+
+        let chunks = data_lines(&output);
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[0]["id"], "msg_1");
+        let text: String = chunks
+            .iter()
+            .filter_map(|c| {
+                c.pointer("/choices/0/delta/content")
+                    .and_then(|t| t.as_str())
+            })
+            .collect();
+        assert_eq!(text, "Hello");
+
+        let tool_start = &chunks[3]["choices"][0]["delta"]["tool_calls"][0];
+        assert_eq!(tool_start["index"], 0);
+        assert_eq!(tool_start["id"], "toolu_1");
+        assert_eq!(tool_start["function"]["name"], "get_weather");
+        assert_eq!(
+            chunks[4]["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"],
+            "{\"city\":"
+        );
+        assert_eq!(chunks[5]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(chunks[6]["usage"]["prompt_tokens"], 10);
+        assert_eq!(chunks[6]["usage"]["completion_tokens"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_into_chat_completion_response() {
+        let message = json!({
+            "id": "msg_1",
+            "model": "claude-sonnet-4-5",
+            "content": [{ "type": "text", "text": "Hi" }],
+            "stop_reason": "end_turn",
+            "usage": { "input_tokens": 1, "output_tokens": 1 },
+        });
+        let upstream = http::Response::builder()
+            .header("content-type", "application/json")
+            .body(message.to_string())
+            .unwrap();
+        let response = into_chat_completion_response(reqwest::Response::from(upstream))
+            .await
+            .unwrap();
+        let completion: Value = response.json().await.unwrap();
+        assert_eq!(completion["choices"][0]["message"]["content"], "Hi");
+        assert_eq!(completion["choices"][0]["finish_reason"], "stop");
+
+        // Errors keep their status and body
+        let upstream = http::Response::builder()
+            .status(401)
+            .body("{\"type\":\"error\"}")
+            .unwrap();
+        let response = into_chat_completion_response(reqwest::Response::from(upstream))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.text().await.unwrap(), "{\"type\":\"error\"}");
+    }
+}
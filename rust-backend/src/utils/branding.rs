@@ -0,0 +1,303 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+
+/// Largest branding image accepted on upload
+pub const MAX_BRANDING_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Images an admin can replace without rebuilding the embedded frontend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BrandingAsset {
+    Logo,
+    Favicon,
+    Splash,
+}
+
+impl BrandingAsset {
+    pub const ALL: [BrandingAsset; 3] = [
+        BrandingAsset::Logo,
+        BrandingAsset::Favicon,
+        BrandingAsset::Splash,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BrandingAsset::Logo => "logo",
+            BrandingAsset::Favicon => "favicon",
+            BrandingAsset::Splash => "splash",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|asset| asset.as_str() == name)
+    }
+
+    /// Embedded file served while no custom image is set
+    pub fn default_path(self) -> &'static str {
+        match self {
+            BrandingAsset::Logo => "static/logo.png",
+            BrandingAsset::Favicon => "static/favicon.png",
+            BrandingAsset::Splash => "static/splash.png",
+        }
+    }
+
+    /// Smallest and largest accepted edge in pixels, and whether the image must be square
+    fn dimension_limits(self) -> (u32, u32, bool) {
+        match self {
+            // Also used as the PWA icon, which browsers expect to be square
+            BrandingAsset::Logo => (64, 2048, true),
+            BrandingAsset::Favicon => (16, 512, true),
+            BrandingAsset::Splash => (64, 4096, false),
+        }
+    }
+}
+
+/// An uploaded image, as recorded in the persistent config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BrandingImage {
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+    /// Content hash prefix; changes with the image so URLs bust browser caches
+    pub version: String,
+}
+
+/// URL serving the asset, which falls back to the embedded default
+pub fn asset_url(asset: BrandingAsset, image: Option<&BrandingImage>) -> String {
+    match image {
+        Some(image) => format!("/static/custom/{}?v={}", asset.as_str(), image.version),
+        None => format!("/static/custom/{}", asset.as_str()),
+    }
+}
+
+fn branding_dir(static_dir: &str) -> PathBuf {
+    Path::new(static_dir).join("custom")
+}
+
+/// Content type and pixel size read from an image header
+///
+/// Only raster formats browsers display everywhere are recognised; SVG is rejected
+/// since it can carry scripts.
+pub fn image_info(bytes: &[u8]) -> Option<(&'static str, u32, u32)> {
+    let be16 = |i: usize| Some(u16::from_be_bytes(bytes.get(i..i + 2)?.try_into().ok()?) as u32);
+    let le16 = |i: usize| Some(u16::from_le_bytes(bytes.get(i..i + 2)?.try_into().ok()?) as u32);
+    let le24 = |i: usize| {
+        let b = bytes.get(i..i + 3)?;
+        Some(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+    };
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let width = u32::from_be_bytes(bytes.get(16..20)?.try_into().ok()?);
+        let height = u32::from_be_bytes(bytes.get(20..24)?.try_into().ok()?);
+        return Some(("image/png", width, height));
+    }
+
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some(("image/gif", le16(6)?, le16(8)?));
+    }
+
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        return match bytes.get(12..16)? {
+            b"VP8 " => Some(("image/webp", le16(26)? & 0x3fff, le16(28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(bytes.get(21..25)?.try_into().ok()?);
+                Some((
+                    "image/webp",
+                    (bits & 0x3fff) + 1,
+                    ((bits >> 14) & 0x3fff) + 1,
+                ))
+            }
+            b"VP8X" => Some(("image/webp", le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        };
+    }
+
+    if bytes.starts_with(&[0xff, 0xd8]) {
+        // Walk the segments up to the start-of-frame header holding the size
+        let mut i = 2;
+        loop {
+            if *bytes.get(i)? != 0xff {
+                return None;
+            }
+            let marker = *bytes.get(i + 1)?;
+            if marker == 0xff {
+                i += 1;
+                continue;
+            }
+            if matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+                return Some(("image/jpeg", be16(i + 7)?, be16(i + 5)?));
+            }
+            i += 2 + be16(i + 2)? as usize;
+        }
+    }
+
+    None
+}
+
+/// Check an upload is a supported image of acceptable size for the asset
+pub fn validate_image(asset: BrandingAsset, bytes: &[u8]) -> AppResult<BrandingImage> {
+    if bytes.len() > MAX_BRANDING_IMAGE_BYTES {
+        return Err(AppError::BadRequest(format!(
+            "Image is too large (max {} MB)",
+            MAX_BRANDING_IMAGE_BYTES / 1024 / 1024
+        )));
+    }
+    let (content_type, width, height) = image_info(bytes).ok_or_else(|| {
+        AppError::BadRequest("Unsupported image type, use PNG, JPEG, GIF or WebP".to_string())
+    })?;
+
+    let (min, max, square) = asset.dimension_limits();
+    if width < min || height < min || width > max || height > max {
+        return Err(AppError::BadRequest(format!(
+            "The {} must be between {}x{} and {}x{} pixels, got {}x{}",
+            asset.as_str(),
+            min,
+            min,
+            max,
+            max,
+            width,
+            height
+        )));
+    }
+    if square && width != height {
+        return Err(AppError::BadRequest(format!(
+            "The {} must be square, got {}x{}",
+            asset.as_str(),
+            width,
+            height
+        )));
+    }
+
+    let digest = Sha256::digest(bytes);
+    Ok(BrandingImage {
+        content_type: content_type.to_string(),
+        width,
+        height,
+        version: digest[..4].iter().map(|b| format!("{:02x}", b)).collect(),
+    })
+}
+
+/// Validate and store an uploaded image, replacing any previous one
+pub fn store_image(
+    static_dir: &str,
+    asset: BrandingAsset,
+    bytes: &[u8],
+) -> AppResult<BrandingImage> {
+    let image = validate_image(asset, bytes)?;
+    let dir = branding_dir(static_dir);
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join(asset.as_str()), bytes)?;
+    Ok(image)
+}
+
+/// Remove a stored image
+pub fn delete_image(static_dir: &str, asset: BrandingAsset) {
+    let _ = std::fs::remove_file(branding_dir(static_dir).join(asset.as_str()));
+}
+
+/// The uploaded image for an asset and its content type, if one is set
+pub fn load_custom_image(config: &Config, asset: BrandingAsset) -> Option<(Vec<u8>, String)> {
+    let image = config.custom_branding.get(&asset)?;
+    let bytes = std::fs::read(branding_dir(&config.static_dir).join(asset.as_str())).ok()?;
+    Some((bytes, image.content_type.clone()))
+}
+
+/// The uploaded image for an asset, else the one embedded in the frontend build
+pub fn load_image(config: &Config, asset: BrandingAsset) -> Option<(Vec<u8>, String)> {
+    if let Some(custom) = load_custom_image(config, asset) {
+        return Some(custom);
+    }
+
+    use crate::static_files::FrontendAssets;
+    let path = asset.default_path();
+    FrontendAssets::get(path).map(|content| {
+        (
+            content.data.into_owned(),
+            mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .to_string(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes.extend_from_slice(&[8, 6, 0, 0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn test_image_info() {
+        assert_eq!(image_info(&png(512, 256)), Some(("image/png", 512, 256)));
+
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&[32, 0, 16, 0]);
+        assert_eq!(image_info(&gif), Some(("image/gif", 32, 16)));
+
+        // SOI, an APP0 segment, then a baseline SOF0 with height 300 and width 400
+        let jpeg = [
+            0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, 0xff, 0xc0, 0x00, 0x11, 0x08, 0x01,
+            0x2c, 0x01, 0x90,
+        ];
+        assert_eq!(image_info(&jpeg), Some(("image/jpeg", 400, 300)));
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X".to_vec();
+        webp.extend_from_slice(&[0; 8]);
+        webp.extend_from_slice(&[255, 0, 0, 127, 0, 0]);
+        assert_eq!(image_info(&webp), Some(("image/webp", 256, 128)));
+
+        assert_eq!(
+            image_info(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"),
+            None
+        );
+        assert_eq!(image_info(b"\x89PNG\r\n\x1a\n"), None);
+    }
+
+    #[test]
+    fn test_validate_dimensions() {
+        let image = validate_image(BrandingAsset::Logo, &png(512, 512)).unwrap();
+        assert_eq!(image.content_type, "image/png");
+        assert_eq!((image.width, image.height), (512, 512));
+        assert_eq!(image.version.len(), 8);
+
+        assert!(validate_image(BrandingAsset::Logo, &png(512, 256)).is_err());
+        assert!(validate_image(BrandingAsset::Favicon, &png(8, 8)).is_err());
+        assert!(validate_image(BrandingAsset::Favicon, &png(1024, 1024)).is_err());
+        assert!(validate_image(BrandingAsset::Splash, &png(1920, 1080)).is_ok());
+        assert!(validate_image(BrandingAsset::Splash, b"not an image").is_err());
+    }
+
+    #[test]
+    fn test_store_and_reset() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::from_env().unwrap();
+        config.static_dir = dir.path().to_str().unwrap().to_string();
+
+        let bytes = png(64, 64);
+        let image = store_image(&config.static_dir, BrandingAsset::Favicon, &bytes).unwrap();
+        assert_eq!(
+            asset_url(BrandingAsset::Favicon, Some(&image)),
+            format!("/static/custom/favicon?v={}", image.version)
+        );
+
+        // Only served once recorded in the config
+        assert!(load_custom_image(&config, BrandingAsset::Favicon).is_none());
+        config.custom_branding.insert(BrandingAsset::Favicon, image);
+        let (served, content_type) = load_custom_image(&config, BrandingAsset::Favicon).unwrap();
+        assert_eq!(served, bytes);
+        assert_eq!(content_type, "image/png");
+
+        delete_image(&config.static_dir, BrandingAsset::Favicon);
+        assert!(load_custom_image(&config, BrandingAsset::Favicon).is_none());
+    }
+}
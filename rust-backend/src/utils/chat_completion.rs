@@ -7,12 +7,24 @@ use bytes::Bytes;
 use futures::stream::StreamExt;
 use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    error::AppError,
+    error::{AppError, AppResult},
     middleware::code_interpreter::{
         execute_code_block, format_execution_result, get_code_interpreter_timeout,
-        get_sandbox_client, is_code_interpreter_enabled, CodeBlockDetector,
+        get_sandbox_client, is_code_interpreter_enabled, store_artifacts, CodeBlockDetector,
+    },
+    middleware::request_id::{propagate, WithRequestId},
+    models::tool_runtime::{ExecutionContext, UserContext},
+    routes::{
+        openai::get_openai_endpoint,
+        tasks::{resolve_task_model, resolve_title_model},
+    },
+    utils::{
+        anthropic, gemini, metrics::metrics, misc::message_content_text,
+        reasoning::ReasoningParser, response_format, telemetry,
     },
     AppState,
 };
@@ -21,6 +33,15 @@ use crate::{
 /// Matches Python's CHAT_RESPONSE_STREAM_DELTA_CHUNK_SIZE
 pub const DEFAULT_DELTA_CHUNK_SIZE: usize = 1;
 
+/// Longest a batched content delta waits before it's sent, so slow models don't look frozen
+pub const DELTA_FLUSH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Persist the in-progress assistant message after this many content deltas...
+pub const STREAM_PERSIST_DELTA_INTERVAL: usize = 20;
+
+/// ...or once this much time has passed since the last write, whichever comes first
+pub const STREAM_PERSIST_TIME_INTERVAL: Duration = Duration::from_millis(1000);
+
 /// Default title generation prompt template
 pub const DEFAULT_TITLE_GENERATION_PROMPT_TEMPLATE: &str = r#"### Task:
 Generate a concise, 3-5 word title with an emoji summarizing the chat history.
@@ -46,37 +67,342 @@ JSON format: { "title": "your concise title here" }
 {{MESSAGES:END:2}}
 </chat_history>"#;
 
+/// Default tags generation prompt template
+pub const DEFAULT_TAGS_GENERATION_PROMPT_TEMPLATE: &str = r#"### Task:
+Generate 1-3 broad tags categorizing the main themes of the chat history, along with 1-3 more specific subtopic tags.
+### Guidelines:
+- Start with high-level domains (e.g. Science, Technology, Philosophy, Arts, Politics, Business, Health, Sports, Entertainment, Education)
+- Consider including relevant subfields/subdomains if they are strongly represented throughout the conversation
+- If content is too short (less than 3 messages) or too diverse, use only ["General"]
+- Use the chat's primary language; default to English if multilingual
+- Prioritize accuracy over specificity
+### Output:
+JSON format: { "tags": ["tag1", "tag2", "tag3"] }
+### Chat History:
+<chat_history>
+{{MESSAGES}}
+</chat_history>"#;
+
+/// Default follow-up generation prompt template
+pub const DEFAULT_FOLLOW_UP_GENERATION_PROMPT_TEMPLATE: &str = r#"### Task:
+Suggest 2-3 relevant follow-up questions or prompts that the user might naturally ask next, based on the chat history.
+### Guidelines:
+- Write all follow-up questions from the user's point of view, directed to the assistant.
+- Make questions concise, clear, and directly related to the discussed topic(s).
+- Use the conversation's primary language; default to English if multilingual.
+### Output:
+JSON format: { "follow_ups": ["Question 1?", "Question 2?", "Question 3?"] }
+### Chat History:
+<chat_history>
+{{MESSAGES}}
+</chat_history>"#;
+
+/// Maximum number of follow-up suggestions kept per message
+pub const MAX_GENERATED_FOLLOW_UPS: usize = 3;
+
+/// Maximum number of generated tags kept per chat
+pub const MAX_GENERATED_TAGS: usize = 5;
+
+/// Minimum number of messages (including the assistant reply) before tags are generated
+pub const TAGS_GENERATION_MIN_MESSAGES: usize = 4;
+
+/// Attempts made for a title generation request before giving up
+pub const TITLE_GENERATION_MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first title generation retry; doubled for each further retry
+pub const TITLE_GENERATION_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Chat id the web UI sends for temporary chats, alone or as `local:<session id>`
+pub const TEMPORARY_CHAT_ID: &str = "local";
+
+/// Whether a completion is for a temporary chat, which is never saved: the web UI's
+/// temporary chat id or a `temporary` flag at the top level or in the metadata
+pub fn is_temporary_chat(chat_id: Option<&str>, payload: &Value) -> bool {
+    let temporary_id = chat_id.and_then(|id| id.split(':').next()) == Some(TEMPORARY_CHAT_ID);
+    let flagged = |value: Option<&Value>| value.and_then(|v| v.as_bool()).unwrap_or(false);
+    temporary_id
+        || flagged(payload.get("temporary"))
+        || flagged(payload.pointer("/metadata/temporary"))
+}
+
+/// Decides when partially streamed content should be written to the database,
+/// so a client reloading mid-generation sees the text produced so far
+struct PartialPersistTracker {
+    delta_interval: usize,
+    time_interval: Duration,
+    deltas_since_persist: usize,
+    last_persist: Instant,
+}
+
+impl PartialPersistTracker {
+    fn new(delta_interval: usize, time_interval: Duration) -> Self {
+        Self {
+            delta_interval,
+            time_interval,
+            deltas_since_persist: 0,
+            last_persist: Instant::now(),
+        }
+    }
+
+    /// Record a content delta; returns true when a write is due
+    fn record_delta(&mut self) -> bool {
+        self.deltas_since_persist += 1;
+
+        if self.deltas_since_persist >= self.delta_interval
+            || self.last_persist.elapsed() >= self.time_interval
+        {
+            self.deltas_since_persist = 0;
+            self.last_persist = Instant::now();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Batches streamed chunks into one `chat:completion` event per `chunk_size` content deltas.
+/// The batched content is merged into the latest chunk, and a batch is sent early once its
+/// first delta waited `flush_interval`.
+struct DeltaBatcher {
+    chunk_size: usize,
+    flush_interval: Duration,
+    pending: Option<Value>,
+    count: usize,
+    pending_since: Instant,
+}
+
+impl DeltaBatcher {
+    fn new(chunk_size: usize, flush_interval: Duration) -> Self {
+        Self {
+            chunk_size: chunk_size.max(1),
+            flush_interval,
+            pending: None,
+            count: 0,
+            pending_since: Instant::now(),
+        }
+    }
+
+    /// Add a chunk with a content delta; returns the event data once a batch is due
+    fn push(&mut self, mut data: Value) -> Option<Value> {
+        match self.pending.take() {
+            Some(pending) => {
+                let earlier = pending
+                    .pointer("/choices/0/delta/content")
+                    .and_then(|c| c.as_str())
+                    .unwrap_or_default();
+                if let Some(content) = data.pointer_mut("/choices/0/delta/content") {
+                    *content = json!(format!(
+                        "{}{}",
+                        earlier,
+                        content.as_str().unwrap_or_default()
+                    ));
+                }
+            }
+            None => self.pending_since = Instant::now(),
+        }
+        self.pending = Some(data);
+        self.count += 1;
+
+        if self.count >= self.chunk_size || self.pending_since.elapsed() >= self.flush_interval {
+            self.take()
+        } else {
+            None
+        }
+    }
+
+    /// When the pending batch has to be sent, if there is one
+    fn deadline(&self) -> Option<Instant> {
+        self.pending
+            .as_ref()
+            .map(|_| self.pending_since + self.flush_interval)
+    }
+
+    /// Take the pending batch to send it now
+    fn take(&mut self) -> Option<Value> {
+        self.count = 0;
+        self.pending.take()
+    }
+}
+
+/// Wait for a batch's deadline, or forever when nothing is pending
+async fn batch_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
 /// Context for streaming chat completions
+#[derive(Clone)]
 pub struct StreamingContext {
     pub state: web::Data<AppState>,
-    pub user_id: String,
+    /// The requesting user, passed on to the tools they run
+    pub user: UserContext,
     pub model_id: String,
     pub messages: Vec<Value>,
     pub chat_id: Option<String>,
     pub message_id: Option<String>,
     pub session_id: Option<String>,
     pub should_generate_title: bool,
+    pub should_generate_tags: bool,
+    pub should_generate_follow_ups: bool,
     pub model_item: Value,
     pub endpoint_url: String,
     pub endpoint_key: String,
+    /// The endpoint's `openai_api_configs` entry
+    pub endpoint_config: Value,
     pub tool_ids: Vec<String>,
     pub tool_specs: Vec<Value>,
+    /// RAG/web search sources injected into the request, surfaced as citations
+    pub sources: Vec<Value>,
+    /// Numbered list of the cited sources, sent with the final `done` event
+    pub citations: Vec<Value>,
     pub delta_chunk_size: Option<usize>,
+    /// Arena model the user picked when `model_id` was chosen at random for it
+    pub arena_model_id: Option<String>,
+    /// JSON was requested through the prompt because the upstream lacks `response_format`,
+    /// so the final content is checked before the message is marked done
+    pub validate_json: bool,
+    /// Temporary chat: streamed like any other but never saved or given a title and tags
+    pub temporary: bool,
+    /// Cancelled when the user stops the response
+    pub cancel: CancellationToken,
+}
+
+impl StreamingContext {
+    /// Model recorded on the assistant message; arena picks stay behind the arena model so
+    /// the answer can be rated blind
+    pub fn message_model_id(&self) -> &str {
+        self.arena_model_id.as_deref().unwrap_or(&self.model_id)
+    }
+
+    /// Chat and message the response is saved to; `None` for temporary chats
+    pub fn persisted_message(&self) -> Option<(&str, &str)> {
+        if self.temporary {
+            return None;
+        }
+        Some((self.chat_id.as_deref()?, self.message_id.as_deref()?))
+    }
+
+    /// Model, endpoint URL, key and endpoint config for a background task. A task model
+    /// other than the chat model is sent to its own endpoint; if that can't be resolved
+    /// the chat model and endpoint are used.
+    fn task_target(&self, task_model: String) -> (String, String, String, Value) {
+        if task_model != self.model_id {
+            match get_openai_endpoint(&self.state, &task_model) {
+                Ok((url, key, api_config)) => return (task_model, url, key, api_config),
+                Err(e) => tracing::warn!(
+                    "No endpoint for task model {}, using chat model {}: {}",
+                    task_model,
+                    self.model_id,
+                    e
+                ),
+            }
+        }
+        (
+            self.model_id.clone(),
+            self.endpoint_url.clone(),
+            self.endpoint_key.clone(),
+            self.endpoint_config.clone(),
+        )
+    }
+}
+
+/// Watches a forwarded SSE stream for the upstream's `usage` chunk, and records it
+/// against the user's budget once the stream is dropped
+pub struct SseUsageRecorder {
+    db: crate::db::Database,
+    user_id: String,
+    model_id: String,
+    scanner: SseUsageScanner,
+}
+
+impl SseUsageRecorder {
+    pub fn new(db: crate::db::Database, user_id: String, model_id: String) -> Self {
+        Self {
+            db,
+            user_id,
+            model_id,
+            scanner: SseUsageScanner::default(),
+        }
+    }
+
+    /// Scan the next chunk of the forwarded stream
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.scanner.feed(bytes);
+    }
+}
+
+/// Last `usage` reported by an SSE stream fed to it chunk by chunk
+#[derive(Default)]
+struct SseUsageScanner {
+    /// Bytes of a line not terminated yet
+    partial_line: Vec<u8>,
+    usage: Option<Value>,
+}
+
+impl SseUsageScanner {
+    fn feed(&mut self, bytes: &[u8]) {
+        self.partial_line.extend_from_slice(bytes);
+        while let Some(end) = self.partial_line.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.partial_line.drain(..=end).collect();
+            if let Some(usage) = sse_line_usage(&line) {
+                self.usage = Some(usage);
+            }
+        }
+    }
+}
+
+impl Drop for SseUsageRecorder {
+    fn drop(&mut self) {
+        if let Some(usage) = self.scanner.usage.take() {
+            let db = self.db.clone();
+            let user_id = std::mem::take(&mut self.user_id);
+            let model_id = std::mem::take(&mut self.model_id);
+            tokio::spawn(async move {
+                record_token_usage(&db, &user_id, &model_id, &usage).await;
+            });
+        }
+    }
+}
+
+/// `usage` of an SSE `data:` line, if it reports any
+fn sse_line_usage(line: &[u8]) -> Option<Value> {
+    // Most chunks are content deltas; only parse the ones mentioning usage
+    if !line.windows(7).any(|w| w == b"\"usage\"") {
+        return None;
+    }
+    let line = std::str::from_utf8(line).ok()?.trim();
+    let data = line.strip_prefix("data:")?.trim_start();
+    let chunk: Value = serde_json::from_str(data).ok()?;
+    chunk.get("usage").filter(|u| u.is_object()).cloned()
 }
 
 /// Create an HTTP SSE streaming response
 /// This is used when Socket.IO metadata is not present (API calls, integrations, etc.)
-pub fn create_sse_stream(response: reqwest::Response) -> Result<HttpResponse, AppError> {
+pub fn create_sse_stream(
+    response: reqwest::Response,
+    model_id: &str,
+    mut usage: Option<SseUsageRecorder>,
+) -> Result<HttpResponse, AppError> {
     tracing::debug!("Creating HTTP SSE streaming response");
 
-    let stream = response.bytes_stream().map(move |result| match result {
-        Ok(bytes) => {
-            // Forward immediately without ANY processing
-            Ok::<Bytes, actix_web::Error>(bytes)
-        }
-        Err(e) => {
-            tracing::error!("SSE stream error: {}", e);
-            Err(actix_web::error::ErrorInternalServerError(e))
+    // Dropped with the stream, once it completes or the client disconnects
+    let timer = metrics().stream_timer(model_id);
+    let stream = response.bytes_stream().map(move |result| {
+        let _timer = &timer;
+        match result {
+            Ok(bytes) => {
+                if let Some(usage) = usage.as_mut() {
+                    usage.feed(&bytes);
+                }
+                // Forward immediately without further processing
+                Ok::<Bytes, actix_web::Error>(bytes)
+            }
+            Err(e) => {
+                tracing::error!("SSE stream error: {}", e);
+                Err(actix_web::error::ErrorInternalServerError(e))
+            }
         }
     });
 
@@ -111,12 +437,14 @@ pub async fn process_streaming_via_socketio(
     // Create event emitter
     let event_emitter = crate::socket::get_event_emitter(
         socket_state,
-        context.user_id.clone(),
+        context.user.id.clone(),
         context.chat_id.clone(),
         context.message_id.clone(),
         context.session_id.clone(),
     );
 
+    let _timer = metrics().stream_timer(&context.model_id);
+
     // Stream the response with batching like Python backend
     let mut stream = response.bytes_stream();
     let mut content = String::new();
@@ -124,8 +452,11 @@ pub async fn process_streaming_via_socketio(
     // Delta batching to prevent flooding frontend
     // Use configurable chunk size or default to 1
     let delta_chunk_size = context.delta_chunk_size.unwrap_or(DEFAULT_DELTA_CHUNK_SIZE);
-    let mut delta_count = 0;
-    let mut last_delta_data: Option<Value> = None;
+    let mut batcher = DeltaBatcher::new(delta_chunk_size, DELTA_FLUSH_INTERVAL);
+
+    // Periodic persistence of the partial message
+    let mut persist_tracker =
+        PartialPersistTracker::new(STREAM_PERSIST_DELTA_INTERVAL, STREAM_PERSIST_TIME_INTERVAL);
 
     tracing::debug!("💬 Delta chunk size: {}", delta_chunk_size);
 
@@ -133,6 +464,16 @@ pub async fn process_streaming_via_socketio(
     let mut collected_tool_calls: HashMap<usize, Value> = HashMap::new();
     let mut has_tool_calls = false;
 
+    // Token usage reported by the upstream, counted against the user's budget
+    let mut stream_usage: Option<Value> = None;
+
+    // Watermark appended once the response is complete
+    let watermark = ResponseWatermark::from_state(&context.state);
+
+    // Reasoning is streamed separately and only kept in the content when configured
+    let mut reasoning_parser = ReasoningParser::new();
+    let keep_reasoning = context.state.config.snapshot().keep_reasoning_in_content;
+
     // Code interpreter tracking
     let code_interpreter_enabled = is_code_interpreter_enabled(&context.state);
     let sandbox_client = if code_interpreter_enabled {
@@ -149,11 +490,93 @@ pub async fn process_streaming_via_socketio(
 
     tracing::info!(
         "🔴 Socket.IO STREAMING STARTED for user {} (code_interpreter: {})",
-        context.user_id,
+        context.user.id,
         code_interpreter_enabled
     );
 
-    while let Some(chunk_result) = stream.next().await {
+    // Send citations up front and persist them on the message
+    if !context.sources.is_empty() {
+        event_emitter(json!({
+            "type": "chat:completion",
+            "data": {
+                "sources": context.sources
+            }
+        }))
+        .await;
+
+        if let Some((chat_id, message_id)) = context.persisted_message() {
+            if let Err(e) = upsert_chat_message(
+                &context.state.db,
+                chat_id,
+                message_id,
+                json!({ "sources": context.sources }),
+            )
+            .await
+            {
+                tracing::error!("Failed to save message sources: {}", e);
+            }
+        }
+    }
+
+    loop {
+        let chunk_result = tokio::select! {
+            chunk = stream.next() => match chunk {
+                Some(chunk) => chunk,
+                None => break,
+            },
+            _ = batch_deadline(batcher.deadline()) => {
+                if let Some(pending_data) = batcher.take() {
+                    event_emitter(json!({
+                        "type": "chat:completion",
+                        "data": pending_data
+                    }))
+                    .await;
+                }
+                continue;
+            }
+            _ = context.cancel.cancelled() => {
+                // Stopped by the user: close the upstream connection and keep what we have
+                drop(stream);
+                if let Some(pending_data) = batcher.take() {
+                    event_emitter(json!({
+                        "type": "chat:completion",
+                        "data": pending_data
+                    }))
+                    .await;
+                }
+                save_stopped_response(
+                    &context.state.db,
+                    context.persisted_message(),
+                    context.message_model_id(),
+                    &content,
+                    &event_emitter,
+                )
+                .await;
+                return Ok(());
+            }
+            _ = context.state.tasks.cancelled() => {
+                // Server is shutting down: keep what we have so the user can continue it
+                if let Some(pending_data) = batcher.take() {
+                    event_emitter(json!({
+                        "type": "chat:completion",
+                        "data": pending_data
+                    }))
+                    .await;
+                }
+                if let Some((cid, mid)) = context.persisted_message() {
+                    persist_partial_message(
+                        &context.state.db,
+                        cid,
+                        mid,
+                        context.message_model_id(),
+                        &content,
+                    )
+                    .await;
+                }
+                event_emitter(shutdown_interrupted_event()).await;
+                return Ok(());
+            }
+        };
         match chunk_result {
             Ok(chunk) => {
                 // Convert bytes to text
@@ -178,7 +601,7 @@ pub async fn process_streaming_via_socketio(
                                 tracing::info!("✅ Streaming completed");
 
                                 // Flush any pending delta
-                                if let Some(pending_data) = last_delta_data.take() {
+                                if let Some(pending_data) = batcher.take() {
                                     let completion_event = json!({
                                         "type": "chat:completion",
                                         "data": pending_data
@@ -190,6 +613,17 @@ pub async fn process_streaming_via_socketio(
 
                             // Parse JSON data
                             if let Ok(mut data) = serde_json::from_str::<Value>(data_str) {
+                                // Usage arrives on the final chunk when include_usage is set
+                                if let Some(usage) = data.get("usage").filter(|u| !u.is_null()) {
+                                    stream_usage = Some(usage.clone());
+                                }
+
+                                // Leave only the answer in the chunk
+                                let reasoning_delta = reasoning_parser.take_from_chunk(&mut data);
+                                if let Some(event) = reasoning_parser.event_for(&reasoning_delta) {
+                                    event_emitter(event).await;
+                                }
+
                                 // Extract delta content
                                 if let Some(choices) =
                                     data.get("choices").and_then(|c| c.as_array())
@@ -216,12 +650,30 @@ pub async fn process_streaming_via_socketio(
                                                                 code_block.code.len()
                                                             );
 
+                                                            // Skip code the sandbox can't run
+                                                            let language =
+                                                                CodeBlockDetector::normalize_language(
+                                                                    &code_block.language,
+                                                                );
+                                                            if !client
+                                                                .supports_language(&language)
+                                                                .await
+                                                            {
+                                                                tracing::debug!(
+                                                                    "Sandbox doesn't run {}, not executing",
+                                                                    language
+                                                                );
+                                                                continue;
+                                                            }
+
                                                             // Execute the code block
                                                             match execute_code_block(
                                                                 &code_block,
                                                                 client,
-                                                                &context.user_id,
+                                                                &context.user.id,
+                                                                context.chat_id.as_deref(),
                                                                 code_interpreter_timeout,
+                                                                &event_emitter,
                                                             )
                                                             .await
                                                             {
@@ -232,10 +684,19 @@ pub async fn process_streaming_via_socketio(
                                                                         result.execution_time_ms
                                                                     );
 
+                                                                    // Keep the files it wrote
+                                                                    let artifacts =
+                                                                        store_artifacts(
+                                                                            &context.state,
+                                                                            &context.user.id,
+                                                                            &result,
+                                                                        )
+                                                                        .await;
+
                                                                     // Format and emit execution result
                                                                     let formatted_result =
                                                                         format_execution_result(
-                                                                            &result,
+                                                                            &result, &artifacts,
                                                                         );
 
                                                                     // Add the execution result to content
@@ -290,19 +751,29 @@ pub async fn process_streaming_via_socketio(
                                                     }
                                                 }
 
-                                                // Batch deltas like Python backend
-                                                delta_count += 1;
-                                                last_delta_data = Some(data.clone());
+                                                // Save in-progress content so a reload sees it
+                                                if persist_tracker.record_delta() {
+                                                    if let Some((cid, mid)) =
+                                                        context.persisted_message()
+                                                    {
+                                                        persist_partial_message(
+                                                            &context.state.db,
+                                                            cid,
+                                                            mid,
+                                                            context.message_model_id(),
+                                                            &content,
+                                                        )
+                                                        .await;
+                                                    }
+                                                }
 
-                                                // Only emit when batch size reached
-                                                if delta_count >= delta_chunk_size {
+                                                // Batch deltas like Python backend
+                                                if let Some(batch) = batcher.push(data.clone()) {
                                                     let completion_event = json!({
                                                         "type": "chat:completion",
-                                                        "data": data
+                                                        "data": batch
                                                     });
                                                     event_emitter(completion_event).await;
-                                                    delta_count = 0;
-                                                    last_delta_data = None;
                                                 }
                                             }
 
@@ -324,16 +795,27 @@ pub async fn process_streaming_via_socketio(
                                                     );
                                                 }
 
-                                                // Emit tool_calls immediately (don't batch)
+                                                // Emit tool_calls immediately (don't batch),
+                                                // after the content batched so far
+                                                if let Some(pending_data) = batcher.take() {
+                                                    event_emitter(json!({
+                                                        "type": "chat:completion",
+                                                        "data": pending_data
+                                                    }))
+                                                    .await;
+                                                }
+                                                let mut tool_calls_data = data.clone();
+                                                if let Some(delta) = tool_calls_data
+                                                    .pointer_mut("/choices/0/delta")
+                                                    .and_then(|d| d.as_object_mut())
+                                                {
+                                                    delta.remove("content");
+                                                }
                                                 let completion_event = json!({
                                                     "type": "chat:completion",
-                                                    "data": data
+                                                    "data": tool_calls_data
                                                 });
                                                 event_emitter(completion_event).await;
-
-                                                // Clear any pending deltas
-                                                last_delta_data = None;
-                                                delta_count = 0;
                                             }
                                         }
 
@@ -346,19 +828,91 @@ pub async fn process_streaming_via_socketio(
                                                     "✅ Stream finished with reason: {:?}",
                                                     finish_reason
                                                 );
+                                                let tool_calls_finish =
+                                                    finish_reason.as_str() == Some("tool_calls");
 
                                                 // Flush any pending delta first
-                                                if let Some(pending_data) = last_delta_data.take() {
+                                                if let Some(pending_data) = batcher.take() {
                                                     let completion_event = json!({
                                                         "type": "chat:completion",
                                                         "data": pending_data
                                                     });
                                                     event_emitter(completion_event).await;
-                                                    delta_count = 0;
+                                                }
+
+                                                // Then text the reasoning parser held back
+                                                let tail = reasoning_parser.finish();
+                                                if let Some(event) =
+                                                    reasoning_parser.event_for(&tail)
+                                                {
+                                                    event_emitter(event).await;
+                                                }
+                                                if !tail.content.is_empty() {
+                                                    content.push_str(&tail.content);
+                                                    event_emitter(json!({
+                                                        "type": "chat:completion",
+                                                        "data": {
+                                                            "choices": [{
+                                                                "index": 0,
+                                                                "delta": {"content": tail.content}
+                                                            }]
+                                                        }
+                                                    }))
+                                                    .await;
+                                                }
+
+                                                // Tool calls continue in a second response, which
+                                                // carries the watermark instead
+                                                let watermark_suffix = if tool_calls_finish {
+                                                    None
+                                                } else {
+                                                    watermark
+                                                        .as_ref()
+                                                        .and_then(|w| w.suffix_for(&content))
+                                                };
+
+                                                // The watermark goes out as the last delta
+                                                if let (Some(suffix), Some(w)) =
+                                                    (&watermark_suffix, &watermark)
+                                                {
+                                                    if let Some(event) = w.delta_event(suffix) {
+                                                        event_emitter(event).await;
+                                                    }
+                                                }
+                                                // This chunk's content was already flushed above
+                                                if let Some(delta) = data
+                                                    .pointer_mut("/choices/0/delta")
+                                                    .and_then(|d| d.as_object_mut())
+                                                {
+                                                    delta.remove("content");
+                                                }
+
+                                                let invalid_json = context.validate_json
+                                                    && !tool_calls_finish
+                                                    && !response_format::is_valid_json_output(
+                                                        &content,
+                                                    );
+                                                if invalid_json {
+                                                    tracing::warn!(
+                                                        "Model {} returned invalid JSON in JSON mode",
+                                                        context.model_id
+                                                    );
+                                                    event_emitter(json!({
+                                                        "type": "chat:message:error",
+                                                        "data": {
+                                                            "error": {
+                                                                "content": response_format::INVALID_JSON_ERROR
+                                                            }
+                                                        }
+                                                    }))
+                                                    .await;
                                                 }
 
                                                 // Mark as done and send final data with finish_reason
                                                 data["done"] = json!(true);
+                                                if !context.citations.is_empty() {
+                                                    data["citations"] = json!(context.citations);
+                                                }
                                                 let completion_event = json!({
                                                     "type": "chat:completion",
                                                     "data": data
@@ -366,20 +920,36 @@ pub async fn process_streaming_via_socketio(
                                                 event_emitter(completion_event).await;
 
                                                 // Save to database
-                                                if let (Some(cid), Some(mid)) = (
-                                                    context.chat_id.as_ref(),
-                                                    context.message_id.as_ref(),
-                                                ) {
+                                                if let Some((cid, mid)) =
+                                                    context.persisted_message()
+                                                {
+                                                    let kept_reasoning = reasoning_parser
+                                                        .details()
+                                                        .filter(|_| keep_reasoning)
+                                                        .unwrap_or_default();
+                                                    let mut assistant_message = json!({
+                                                        "role": "assistant",
+                                                        "content": format!(
+                                                            "{}{}{}",
+                                                            kept_reasoning,
+                                                            content,
+                                                            watermark_suffix
+                                                                .as_deref()
+                                                                .unwrap_or_default()
+                                                        ),
+                                                        "done": true,
+                                                        "model": context.message_model_id(),
+                                                    });
+                                                    if invalid_json {
+                                                        assistant_message["error"] = json!({
+                                                            "content": response_format::INVALID_JSON_ERROR
+                                                        });
+                                                    }
                                                     let _ = upsert_chat_message(
                                                         &context.state.db,
                                                         cid,
                                                         mid,
-                                                        json!({
-                                                            "role": "assistant",
-                                                            "content": content.clone(),
-                                                            "done": true,
-                                                            "model": context.model_id.clone(),
-                                                        }),
+                                                        assistant_message,
                                                     )
                                                     .await;
                                                 }
@@ -411,6 +981,16 @@ pub async fn process_streaming_via_socketio(
         }
     }
 
+    if let Some(usage) = stream_usage.as_ref() {
+        record_token_usage(
+            &context.state.db,
+            &context.user.id,
+            &context.model_id,
+            usage,
+        )
+        .await;
+    }
+
     // Execute tools if tool_calls were detected
     if has_tool_calls && !collected_tool_calls.is_empty() {
         execute_tools_and_continue(
@@ -422,11 +1002,8 @@ pub async fn process_streaming_via_socketio(
         )
         .await?;
     } else {
-        // No tool calls - generate title if requested (normal completion path)
-        if context.should_generate_title && context.chat_id.is_some() {
-            tracing::info!("🏷️  No tools used, triggering title generation");
-            spawn_title_generation(context).await;
-        }
+        // No tool calls - run background tasks if requested (normal completion path)
+        spawn_background_tasks(context, content).await;
     }
 
     Ok(())
@@ -497,13 +1074,26 @@ async fn execute_tools_and_continue(
     let mut tool_results: Vec<Value> = Vec::new();
 
     for tool_call in &final_tool_calls {
-        let result = execute_single_tool(
-            tool_call,
-            &context.state,
-            &context.user_id,
-            &context.tool_ids,
-        )
-        .await;
+        let result = tokio::select! {
+            result = execute_single_tool(
+                tool_call,
+                &context.state,
+                &context.user,
+                context.session_id.as_deref(),
+                &context.tool_ids,
+            ) => result,
+            _ = context.cancel.cancelled() => {
+                save_stopped_response(
+                    &context.state.db,
+                    context.persisted_message(),
+                    context.message_model_id(),
+                    &content,
+                    &event_emitter,
+                )
+                .await;
+                return Ok(());
+            }
+        };
         tool_results.push(result);
     }
 
@@ -542,16 +1132,34 @@ async fn execute_tools_and_continue(
         new_messages.push(result.clone());
     }
 
-    // Make second request to LLM
-    let second_response = make_tool_response_request(
-        &context.state.http_client,
-        &context.endpoint_url,
-        &context.endpoint_key,
-        &context.model_id,
-        &new_messages,
-        &context.tool_specs,
-    )
-    .await?;
+    // Make second request to LLM. The error becomes a String inside the select so
+    // nothing !Send is alive while the stopped response is saved
+    let second_response = tokio::select! {
+        response = make_tool_response_request(
+            &context.state.http_client,
+            &context.endpoint_url,
+            &context.endpoint_key,
+            &context.endpoint_config,
+            &context.model_id,
+            &new_messages,
+            &context.tool_specs,
+        ) => Some(response.map_err(|e| e.to_string())),
+        _ = context.cancel.cancelled() => None,
+    };
+    let second_response = match second_response {
+        Some(response) => response?,
+        None => {
+            save_stopped_response(
+                &context.state.db,
+                context.persisted_message(),
+                context.message_model_id(),
+                &content,
+                &event_emitter,
+            )
+            .await;
+            return Ok(());
+        }
+    };
 
     // Stream the second response
     stream_second_response(
@@ -559,26 +1167,29 @@ async fn execute_tools_and_continue(
         event_emitter,
         delta_chunk_size,
         &context.state,
-        &context.chat_id,
-        &context.message_id,
-        &context.model_id,
-        content,
+        context.persisted_message(),
+        context.message_model_id(),
+        content.clone(),
+        &context.citations,
+        &context.cancel,
     )
     .await?;
 
-    // Generate title if requested
-    if context.should_generate_title && context.chat_id.is_some() {
-        spawn_title_generation(context).await;
-    }
+    // Run title/tags generation if requested
+    spawn_background_tasks(context, content).await;
 
     Ok(())
 }
 
 /// Execute a single tool
+///
+/// `tool_ids` must only hold tools the user was granted, since they run with the
+/// user's details.
 async fn execute_single_tool(
     tool_call: &Value,
     state: &web::Data<AppState>,
-    user_id: &str,
+    user: &UserContext,
+    session_id: Option<&str>,
     tool_ids: &[String],
 ) -> Value {
     let tool_call_id = tool_call.get("id").and_then(|v| v.as_str()).unwrap_or("");
@@ -629,31 +1240,16 @@ async fn execute_single_tool(
                     );
 
                     // Execute the tool
-                    let runtime_service = crate::services::tool_runtime::ToolRuntimeService::new();
-
-                    // Build execution context
-                    let mut environment = HashMap::new();
-                    for key in &[
-                        "OPENWEATHER_API_KEY",
-                        "OPENAI_API_KEY",
-                        "ANTHROPIC_API_KEY",
-                        "GOOGLE_API_KEY",
-                    ] {
-                        if let Ok(val) = std::env::var(key) {
-                            environment.insert(key.to_string(), val);
-                        }
-                    }
+                    let config = state.config.snapshot();
+                    let runtime_service = crate::services::tool_runtime::ToolRuntimeService::new()
+                        .with_execution_timeout(Duration::from_secs(config.tool_execution_timeout))
+                        .with_secrets(
+                            config.tool_secrets.clone(),
+                            config.tool_secret_grants.clone(),
+                        );
 
-                    let execution_context = crate::models::tool_runtime::ExecutionContext {
-                        user: Some(crate::models::tool_runtime::UserContext {
-                            id: user_id.to_string(),
-                            name: "User".to_string(),
-                            email: "user@example.com".to_string(),
-                            role: Some("user".to_string()),
-                        }),
-                        environment,
-                        session: HashMap::new(),
-                    };
+                    // The runtime fills in the environment the tool declares
+                    let execution_context = tool_execution_context(user, session_id);
 
                     let exec_request = crate::models::tool_runtime::ToolExecutionRequest {
                         tool_id: tool_id.clone(),
@@ -662,20 +1258,8 @@ async fn execute_single_tool(
                         context: execution_context,
                     };
 
-                    match runtime_service.execute_tool(&state.db, exec_request).await {
-                        Ok(exec_response) => {
-                            tool_result_content = serde_json::to_string(&exec_response.result)
-                                .unwrap_or_else(|_| "Error serializing result".to_string());
-                            tracing::info!(
-                                "✅ Tool executed successfully: {}",
-                                tool_result_content
-                            );
-                        }
-                        Err(e) => {
-                            tool_result_content = format!("Error executing tool: {}", e);
-                            tracing::error!("❌ Tool execution error: {}", e);
-                        }
-                    }
+                    let result = runtime_service.execute_tool(&state.db, exec_request).await;
+                    tool_result_content = tool_result_content_for(result);
                     break;
                 }
             }
@@ -689,24 +1273,107 @@ async fn execute_single_tool(
     })
 }
 
+/// Context a tool called during a chat runs with: the requesting user and their session
+fn tool_execution_context(user: &UserContext, session_id: Option<&str>) -> ExecutionContext {
+    let mut session = HashMap::new();
+    if let Some(session_id) = session_id {
+        session.insert("session_id".to_string(), json!(session_id));
+    }
+    ExecutionContext {
+        user: Some(user.clone()),
+        environment: HashMap::new(),
+        session,
+    }
+}
+
+/// Content of the `role: tool` message for a tool run
+///
+/// Failed and timed-out runs still produce content, so the follow-up request always
+/// has a result for every tool call.
+fn tool_result_content_for(
+    result: AppResult<crate::models::tool_runtime::ToolExecutionResponse>,
+) -> String {
+    match result {
+        Ok(exec_response) if exec_response.success => {
+            let content = serde_json::to_string(&exec_response.result)
+                .unwrap_or_else(|_| "Error serializing result".to_string());
+            tracing::info!("✅ Tool executed successfully: {}", content);
+            content
+        }
+        // Failures with a structured error, like a result not matching the tool's
+        // output schema, pass it on to the model as is
+        Ok(exec_response) if exec_response.result.is_some() => {
+            tracing::error!(
+                "❌ Tool execution failed: {}",
+                exec_response.error.as_deref().unwrap_or("Unknown error")
+            );
+            serde_json::to_string(&exec_response.result)
+                .unwrap_or_else(|_| "Error serializing result".to_string())
+        }
+        Ok(exec_response) => {
+            let error = exec_response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string());
+            tracing::error!("❌ Tool execution failed: {}", error);
+            format!("Error executing tool: {}", error)
+        }
+        Err(e) => {
+            tracing::error!("❌ Tool execution error: {}", e);
+            format!("Error executing tool: {}", e)
+        }
+    }
+}
+
+/// Request for the chat completion `payload` in the format the endpoint's API type
+/// expects: the Anthropic Messages API, Gemini's `generateContent` or OpenAI's
+/// `/chat/completions`
+pub(crate) fn chat_completion_request(
+    client: &reqwest::Client,
+    url: &str,
+    key: &str,
+    api_config: &Value,
+    payload: &Value,
+) -> reqwest::RequestBuilder {
+    if anthropic::is_anthropic(api_config) {
+        return anthropic::messages_request(client, url, key, payload);
+    }
+    if gemini::is_gemini(api_config) {
+        return gemini::generate_content_request(client, url, key, payload);
+    }
+
+    let mut request_builder = client
+        .post(format!("{}/chat/completions", url.trim_end_matches('/')))
+        .header("Content-Type", "application/json");
+    if !key.is_empty() {
+        request_builder = request_builder.header("Authorization", format!("Bearer {}", key));
+    }
+    request_builder.json(payload)
+}
+
+/// Present the response to a [`chat_completion_request`] as a chat completion response
+pub(crate) async fn into_chat_completion_response(
+    response: reqwest::Response,
+    api_config: &Value,
+) -> reqwest::Result<reqwest::Response> {
+    if anthropic::is_anthropic(api_config) {
+        anthropic::into_chat_completion_response(response).await
+    } else if gemini::is_gemini(api_config) {
+        gemini::into_chat_completion_response(response).await
+    } else {
+        Ok(response)
+    }
+}
+
 /// Make a second request to LLM with tool results
 async fn make_tool_response_request(
     client: &reqwest::Client,
     endpoint_url: &str,
     endpoint_key: &str,
+    endpoint_config: &Value,
     model_id: &str,
     messages: &[Value],
     tool_specs: &[Value],
 ) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
-    let mut request_builder = client
-        .post(format!("{}/chat/completions", endpoint_url))
-        .header("Content-Type", "application/json");
-
-    if !endpoint_key.is_empty() {
-        request_builder =
-            request_builder.header("Authorization", format!("Bearer {}", endpoint_key));
-    }
-
     let payload = json!({
         "model": model_id,
         "messages": messages,
@@ -720,7 +1387,17 @@ async fn make_tool_response_request(
 
     tracing::info!("🔄 Sending second request to LLM with tool results");
 
-    let response = request_builder.json(&payload).send().await?;
+    let request_builder = chat_completion_request(
+        client,
+        endpoint_url,
+        endpoint_key,
+        endpoint_config,
+        &payload,
+    );
+
+    let span = telemetry::upstream_span("chat_completion", endpoint_url, Some(model_id));
+    let response = telemetry::send_traced(request_builder, span).await?;
+    let response = into_chat_completion_response(response, endpoint_config).await?;
 
     if !response.status().is_success() {
         return Err(format!("Second request failed with status: {}", response.status()).into());
@@ -736,19 +1413,71 @@ async fn stream_second_response(
         + Send,
     delta_chunk_size: usize,
     state: &web::Data<AppState>,
-    chat_id: &Option<String>,
-    message_id: &Option<String>,
+    persist_to: Option<(&str, &str)>,
     model_id: &str,
     previous_content: String,
+    citations: &[Value],
+    cancel: &CancellationToken,
 ) -> Result<(), Box<dyn std::error::Error>> {
     tracing::info!("✅ Second request successful, streaming response...");
 
     let mut second_stream = response.bytes_stream();
     let mut second_content = String::new();
-    let mut second_delta_count = 0;
-    let mut second_last_delta: Option<Value> = None;
-
-    while let Some(chunk_result) = second_stream.next().await {
+    let mut batcher = DeltaBatcher::new(delta_chunk_size, DELTA_FLUSH_INTERVAL);
+    let watermark = ResponseWatermark::from_state(state);
+
+    loop {
+        let chunk_result = tokio::select! {
+            chunk = second_stream.next() => match chunk {
+                Some(chunk) => chunk,
+                None => break,
+            },
+            _ = batch_deadline(batcher.deadline()) => {
+                if let Some(pending) = batcher.take() {
+                    event_emitter(json!({
+                        "type": "chat:completion",
+                        "data": pending
+                    }))
+                    .await;
+                }
+                continue;
+            }
+            _ = cancel.cancelled() => {
+                drop(second_stream);
+                if let Some(pending) = batcher.take() {
+                    event_emitter(json!({
+                        "type": "chat:completion",
+                        "data": pending
+                    }))
+                    .await;
+                }
+                let partial_content = format!("{}\n\n{}", previous_content, second_content);
+                save_stopped_response(
+                    &state.db,
+                    persist_to,
+                    model_id,
+                    &partial_content,
+                    &event_emitter,
+                )
+                .await;
+                return Ok(());
+            }
+            _ = state.tasks.cancelled() => {
+                if let Some(pending) = batcher.take() {
+                    event_emitter(json!({
+                        "type": "chat:completion",
+                        "data": pending
+                    }))
+                    .await;
+                }
+                if let Some((cid, mid)) = persist_to {
+                    let partial_content = format!("{}\n\n{}", previous_content, second_content);
+                    persist_partial_message(&state.db, cid, mid, model_id, &partial_content).await;
+                }
+                event_emitter(shutdown_interrupted_event()).await;
+                return Ok(());
+            }
+        };
         match chunk_result {
             Ok(chunk) => {
                 if let Ok(text) = std::str::from_utf8(&chunk) {
@@ -762,7 +1491,7 @@ async fn stream_second_response(
                             let data_str = &line[6..];
                             if data_str == "[DONE]" {
                                 // Flush pending delta
-                                if let Some(pending) = second_last_delta.take() {
+                                if let Some(pending) = batcher.take() {
                                     let event = json!({
                                         "type": "chat:completion",
                                         "data": pending
@@ -782,17 +1511,13 @@ async fn stream_second_response(
                                                 delta.get("content").and_then(|c| c.as_str())
                                             {
                                                 second_content.push_str(delta_content);
-                                                second_delta_count += 1;
-                                                second_last_delta = Some(data.clone());
 
-                                                if second_delta_count >= delta_chunk_size {
+                                                if let Some(batch) = batcher.push(data.clone()) {
                                                     let event = json!({
                                                         "type": "chat:completion",
-                                                        "data": data
+                                                        "data": batch
                                                     });
                                                     event_emitter(event).await;
-                                                    second_delta_count = 0;
-                                                    second_last_delta = None;
                                                 }
                                             }
                                         }
@@ -807,8 +1532,16 @@ async fn stream_second_response(
                                                     finish_reason
                                                 );
 
+                                                let final_content = format!(
+                                                    "{}\n\n{}",
+                                                    previous_content, second_content
+                                                );
+                                                let watermark_suffix = watermark
+                                                    .as_ref()
+                                                    .and_then(|w| w.suffix_for(&final_content));
+
                                                 // Flush pending delta
-                                                if let Some(pending) = second_last_delta.take() {
+                                                if let Some(pending) = batcher.take() {
                                                     let event = json!({
                                                         "type": "chat:completion",
                                                         "data": pending
@@ -816,8 +1549,25 @@ async fn stream_second_response(
                                                     event_emitter(event).await;
                                                 }
 
+                                                if let (Some(suffix), Some(w)) =
+                                                    (&watermark_suffix, &watermark)
+                                                {
+                                                    if let Some(event) = w.delta_event(suffix) {
+                                                        event_emitter(event).await;
+                                                    }
+                                                }
+                                                if let Some(delta) = data
+                                                    .pointer_mut("/choices/0/delta")
+                                                    .and_then(|d| d.as_object_mut())
+                                                {
+                                                    delta.remove("content");
+                                                }
+
                                                 // Send final message with done flag
                                                 data["done"] = json!(true);
+                                                if !citations.is_empty() {
+                                                    data["citations"] = json!(citations);
+                                                }
                                                 let event = json!({
                                                     "type": "chat:completion",
                                                     "data": data
@@ -825,12 +1575,13 @@ async fn stream_second_response(
                                                 event_emitter(event).await;
 
                                                 // Update database
-                                                if let (Some(cid), Some(mid)) =
-                                                    (chat_id.as_ref(), message_id.as_ref())
-                                                {
+                                                if let Some((cid, mid)) = persist_to {
                                                     let final_content = format!(
-                                                        "{}\n\n{}",
-                                                        previous_content, second_content
+                                                        "{}{}",
+                                                        final_content,
+                                                        watermark_suffix
+                                                            .as_deref()
+                                                            .unwrap_or_default()
                                                     );
                                                     let _ = upsert_chat_message(
                                                         &state.db,
@@ -866,6 +1617,220 @@ async fn stream_second_response(
     Ok(())
 }
 
+/// Delimits a zero-width encoded watermark
+const WATERMARK_BOUNDARY: char = '\u{2060}';
+/// Zero-width characters encoding 0 and 1 bits
+const WATERMARK_BIT_ZERO: char = '\u{200B}';
+const WATERMARK_BIT_ONE: char = '\u{200C}';
+
+/// `RESPONSE_WATERMARK` settings, present only when a watermark is configured
+#[derive(Debug, Clone)]
+pub struct ResponseWatermark {
+    pub text: String,
+    pub invisible: bool,
+    pub in_stream: bool,
+}
+
+impl ResponseWatermark {
+    pub fn from_state(state: &web::Data<AppState>) -> Option<Self> {
+        let config = state.config.snapshot();
+        let text = config
+            .response_watermark
+            .as_deref()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())?
+            .to_string();
+
+        Some(ResponseWatermark {
+            text,
+            invisible: config.response_watermark_mode == "invisible",
+            in_stream: config.response_watermark_in_stream,
+        })
+    }
+
+    /// Text to append to a completed response, or `None` when the response is empty,
+    /// already watermarked, JSON-only or ends inside an unclosed code block
+    pub fn suffix_for(&self, content: &str) -> Option<String> {
+        if content.trim().is_empty() {
+            return None;
+        }
+
+        let suffix = if self.invisible {
+            encode_zero_width_watermark(&self.text)
+        } else {
+            format!("\n\n{}", self.text)
+        };
+
+        if content.ends_with(&suffix) || is_json_only(content) || has_unclosed_code_fence(content) {
+            return None;
+        }
+
+        Some(suffix)
+    }
+
+    /// Delta streaming the watermark after the response, unless it's only saved
+    pub fn delta_event(&self, suffix: &str) -> Option<Value> {
+        self.in_stream.then(|| {
+            json!({
+                "type": "chat:completion",
+                "data": {"choices": [{"index": 0, "delta": {"content": suffix}}]}
+            })
+        })
+    }
+
+    /// Append the watermark to a non-streamed completion's message. Tool call turns
+    /// are left alone, the answer comes in a later response.
+    pub fn apply_to_response(&self, response: &mut Value) {
+        let message = match response.pointer_mut("/choices/0/message") {
+            Some(message) => message,
+            None => return,
+        };
+        let has_tool_calls = message
+            .get("tool_calls")
+            .and_then(|t| t.as_array())
+            .is_some_and(|t| !t.is_empty());
+        if has_tool_calls {
+            return;
+        }
+        if let Some(Value::String(content)) = message.get_mut("content") {
+            if let Some(suffix) = self.suffix_for(content) {
+                content.push_str(&suffix);
+            }
+        }
+    }
+}
+
+/// Encode text as zero-width characters between word joiners
+pub fn encode_zero_width_watermark(text: &str) -> String {
+    let mut encoded = String::new();
+    encoded.push(WATERMARK_BOUNDARY);
+    for byte in text.bytes() {
+        for bit in (0..8).rev() {
+            encoded.push(if (byte >> bit) & 1 == 1 {
+                WATERMARK_BIT_ONE
+            } else {
+                WATERMARK_BIT_ZERO
+            });
+        }
+    }
+    encoded.push(WATERMARK_BOUNDARY);
+    encoded
+}
+
+/// Structured output must stay parseable, so it is never watermarked
+fn is_json_only(content: &str) -> bool {
+    let trimmed = content.trim();
+    (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<Value>(trimmed).is_ok()
+}
+
+/// Whether the content stops inside a fenced code block
+fn has_unclosed_code_fence(content: &str) -> bool {
+    content
+        .lines()
+        .filter(|line| line.trim_start().starts_with("```"))
+        .count()
+        % 2
+        == 1
+}
+
+/// Add upstream-reported token usage to the user's budget counters
+pub async fn record_token_usage(
+    db: &crate::db::Database,
+    user_id: &str,
+    model_id: &str,
+    usage: &Value,
+) {
+    if let Some((prompt_tokens, completion_tokens)) = crate::services::usage::parse_usage(usage) {
+        if let Err(e) = crate::services::usage::UsageService::new(db)
+            .record_usage(user_id, model_id, prompt_tokens, completion_tokens)
+            .await
+        {
+            tracing::error!("Failed to record token usage for {}: {}", user_id, e);
+        }
+    }
+}
+
+/// Tells the client a response was cut off by a server shutdown, so it can offer to
+/// continue it once reconnected
+fn shutdown_interrupted_event() -> Value {
+    json!({
+        "type": "chat:completion",
+        "data": {
+            "interrupted": true,
+            "error": {
+                "content": "The server restarted while this response was being generated"
+            }
+        }
+    })
+}
+
+/// Save the content streamed before the user stopped the response, marked as
+/// interrupted, and tell the client the response is done
+async fn save_stopped_response<E>(
+    db: &crate::db::Database,
+    persist_to: Option<(&str, &str)>,
+    model_id: &str,
+    content: &str,
+    event_emitter: &E,
+) where
+    E: Fn(Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>,
+{
+    tracing::info!("⏹️  Response stopped by the user");
+    if let Some((chat_id, message_id)) = persist_to {
+        if let Err(e) = upsert_chat_message(
+            db,
+            chat_id,
+            message_id,
+            json!({
+                "role": "assistant",
+                "content": content,
+                "done": true,
+                "interrupted": true,
+                "model": model_id,
+            }),
+        )
+        .await
+        {
+            tracing::warn!("⚠️  Failed to save stopped message {}: {}", message_id, e);
+        }
+    }
+    event_emitter(json!({
+        "type": "chat:completion",
+        "data": {"done": true, "interrupted": true}
+    }))
+    .await;
+}
+
+/// Write the partially streamed assistant message (`done: false`)
+async fn persist_partial_message(
+    db: &crate::db::Database,
+    chat_id: &str,
+    message_id: &str,
+    model_id: &str,
+    content: &str,
+) {
+    if let Err(e) = upsert_chat_message(
+        db,
+        chat_id,
+        message_id,
+        json!({
+            "role": "assistant",
+            "content": content,
+            "done": false,
+            "model": model_id,
+        }),
+    )
+    .await
+    {
+        tracing::warn!(
+            "⚠️  Failed to persist partial message {}: {}",
+            message_id,
+            e
+        );
+    }
+}
+
 /// Upsert a message to a chat
 async fn upsert_chat_message(
     db: &crate::db::Database,
@@ -942,6 +1907,36 @@ async fn upsert_chat_message(
     Ok(())
 }
 
+/// Spawn the background tasks requested by the frontend (title, tags, follow-ups)
+async fn spawn_background_tasks(context: StreamingContext, assistant_content: String) {
+    if context.chat_id.is_none() || context.temporary {
+        return;
+    }
+
+    if context.should_generate_follow_ups {
+        let follow_ups_context = context.clone();
+        let content = assistant_content.clone();
+        tokio::spawn(propagate(async move {
+            if let Err(e) = generate_and_update_follow_ups(follow_ups_context, content).await {
+                tracing::error!("Failed to generate follow-ups: {}", e);
+            }
+        }));
+    }
+
+    if context.should_generate_tags {
+        let tags_context = context.clone();
+        tokio::spawn(propagate(async move {
+            if let Err(e) = generate_and_update_tags(tags_context, assistant_content).await {
+                tracing::error!("Failed to generate tags: {}", e);
+            }
+        }));
+    }
+
+    if context.should_generate_title {
+        spawn_title_generation(context).await;
+    }
+}
+
 /// Spawn title generation as background task
 async fn spawn_title_generation(context: StreamingContext) {
     tracing::info!(
@@ -949,11 +1944,11 @@ async fn spawn_title_generation(context: StreamingContext) {
         context.chat_id.as_ref().unwrap()
     );
 
-    tokio::spawn(async move {
+    tokio::spawn(propagate(async move {
         if let Err(e) = generate_and_update_title(context).await {
             tracing::error!("Failed to generate title: {}", e);
         }
-    });
+    }));
 }
 
 /// Generate title and update chat
@@ -965,16 +1960,14 @@ async fn generate_and_update_title(
     let chat_id = context.chat_id.as_ref().unwrap();
 
     tracing::info!(
-        "🏷️  Title generation starting - model: {}, user: {}, endpoint: {}, has_key: {}",
+        "🏷️  Title generation starting - chat model: {}, user: {}",
         context.model_id,
-        context.user_id,
-        context.endpoint_url,
-        !context.endpoint_key.is_empty()
+        context.user.id
     );
 
     // Check if title generation is enabled
     let prompt = {
-        let config = context.state.config.read().unwrap();
+        let config = context.state.config.snapshot();
 
         if !config.enable_title_generation {
             tracing::info!("🏷️  Title generation is DISABLED in config");
@@ -1005,15 +1998,7 @@ async fn generate_and_update_title(
             config.title_generation_prompt_template.clone()
         };
 
-        let messages_text = messages_for_title
-            .iter()
-            .map(|m| {
-                let role = m.get("role").and_then(|v| v.as_str()).unwrap_or("user");
-                let content = m.get("content").and_then(|v| v.as_str()).unwrap_or("");
-                format!("{}: {}", role, content)
-            })
-            .collect::<Vec<_>>()
-            .join("\n");
+        let messages_text = format_messages_for_prompt(&messages_for_title);
 
         let final_prompt = template.replace("{{MESSAGES:END:2}}", &messages_text);
         tracing::debug!("🏷️  Title generation prompt: {}", final_prompt);
@@ -1021,40 +2006,51 @@ async fn generate_and_update_title(
         final_prompt
     };
 
+    // Titles may run on a cheaper model than the chat, on that model's own endpoint
+    let (task_model, task_url, task_key, task_config) = context.task_target(resolve_title_model(
+        &context.state,
+        &context.model_id,
+        Some(&context.model_item),
+    ));
+    tracing::info!(
+        "🏷️  Title generation using model {} at {}",
+        task_model,
+        task_url
+    );
+
     // Build request payload
     let title_payload = json!({
-        "model": context.model_id,
+        "model": task_model,
         "messages": [{"role": "user", "content": prompt}],
         "max_tokens": 50,
         "temperature": 0.1,
         "stream": false
     });
 
-    let url = format!(
-        "{}/chat/completions",
-        context.endpoint_url.trim_end_matches('/')
-    );
-
-    tracing::info!("🏷️  Sending title generation request to: {}", url);
+    tracing::info!("🏷️  Sending title generation request to: {}", task_url);
     tracing::debug!("🏷️  Title payload: {:?}", title_payload);
 
-    // Use shared HTTP client for title generation
-    let mut request_builder = context
-        .state
-        .http_client
-        .post(&url)
-        .timeout(std::time::Duration::from_secs(30)) // 30 sec timeout for title gen
-        .header("Content-Type", "application/json");
-
-    if !context.endpoint_key.is_empty() {
-        request_builder =
-            request_builder.header("Authorization", format!("Bearer {}", context.endpoint_key));
+    if !task_key.is_empty() {
         tracing::debug!("🏷️  API key is present");
     } else {
         tracing::warn!("🏷️  NO API KEY provided for title generation!");
     }
 
-    match request_builder.json(&title_payload).send().await {
+    // Use shared HTTP client for title generation
+    let request_builder = chat_completion_request(
+        &context.state.http_client,
+        &task_url,
+        &task_key,
+        &task_config,
+        &title_payload,
+    )
+    .timeout(std::time::Duration::from_secs(30)); // 30 sec timeout for title gen
+
+    let response = match send_title_request(request_builder).await {
+        Ok(response) => into_chat_completion_response(response, &task_config).await,
+        Err(e) => Err(e),
+    };
+    match response {
         Ok(response) if response.status().is_success() => {
             tracing::info!("🏷️  Title generation response received successfully");
             let json_response = response.json::<Value>().await?;
@@ -1130,7 +2126,7 @@ async fn generate_and_update_title(
                                     match socket_state
                                         .native_handler
                                         .emit_to_user(
-                                            &context.user_id,
+                                            &context.user.id,
                                             "chat-events",
                                             event_payload,
                                         )
@@ -1140,7 +2136,7 @@ async fn generate_and_update_title(
                                             tracing::info!(
                                                 "🏷️  ✅ Emitted chat:title event to {} session(s) for user: {}",
                                                 sent_count,
-                                                context.user_id
+                                                context.user.id
                                             );
                                         }
                                         Err(e) => {
@@ -1189,3 +2185,1026 @@ async fn generate_and_update_title(
 
     Ok(())
 }
+
+/// Whether a failed task request may succeed on retry (rate limited or server error)
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Backoff before retry number `attempt` (1-based)
+fn title_retry_delay(attempt: u32) -> Duration {
+    TITLE_GENERATION_RETRY_BASE_DELAY * 2u32.pow(attempt.saturating_sub(1))
+}
+
+/// Send a title generation request, retrying timeouts, connection errors, 429 and 5xx
+/// responses with exponential backoff. Other 4xx responses are returned straight away,
+/// as is whatever the last attempt produced.
+async fn send_title_request(
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, reqwest::Error> {
+    let request = request.with_request_id();
+    let mut attempt = 1;
+    loop {
+        let current = match request.try_clone() {
+            Some(current) if attempt < TITLE_GENERATION_MAX_ATTEMPTS => current,
+            _ => return request.send().await,
+        };
+
+        match current.send().await {
+            Ok(response) if is_retryable_status(response.status()) => {
+                tracing::warn!(
+                    "🏷️  Title generation attempt {}/{} failed with status {}, retrying",
+                    attempt,
+                    TITLE_GENERATION_MAX_ATTEMPTS,
+                    response.status()
+                );
+            }
+            Ok(response) => return Ok(response),
+            Err(e) if e.is_timeout() || e.is_connect() => {
+                tracing::warn!(
+                    "🏷️  Title generation attempt {}/{} failed: {}, retrying",
+                    attempt,
+                    TITLE_GENERATION_MAX_ATTEMPTS,
+                    e
+                );
+            }
+            Err(e) => return Err(e),
+        }
+
+        tokio::time::sleep(title_retry_delay(attempt)).await;
+        attempt += 1;
+    }
+}
+
+/// Generate tags for the chat, merge them into `meta.tags` and emit `chat:tags`
+async fn generate_and_update_tags(
+    context: StreamingContext,
+    assistant_content: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use crate::services::chat::ChatService;
+
+    let chat_id = match context.chat_id.as_ref() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    // Include the assistant reply so the model sees the full exchange
+    let mut messages = context.messages.clone();
+    if !assistant_content.is_empty() {
+        messages.push(json!({"role": "assistant", "content": assistant_content}));
+    }
+
+    if messages.len() < TAGS_GENERATION_MIN_MESSAGES {
+        tracing::debug!(
+            "🏷️  Skipping tags generation for chat {}: only {} message(s)",
+            chat_id,
+            messages.len()
+        );
+        return Ok(());
+    }
+
+    let prompt = {
+        let config = context.state.config.snapshot();
+
+        if !config.enable_tags_generation {
+            tracing::debug!("🏷️  Tags generation is DISABLED in config");
+            return Ok(());
+        }
+
+        let template = if config.tags_generation_prompt_template.is_empty() {
+            DEFAULT_TAGS_GENERATION_PROMPT_TEMPLATE.to_string()
+        } else {
+            config.tags_generation_prompt_template.clone()
+        };
+
+        template.replace("{{MESSAGES}}", &format_messages_for_prompt(&messages))
+    };
+
+    let (task_model, task_url, task_key, task_config) = context.task_target(resolve_task_model(
+        &context.state,
+        &context.model_id,
+        Some(&context.model_item),
+    ));
+
+    let tags_payload = json!({
+        "model": task_model,
+        "messages": [{"role": "user", "content": prompt}],
+        "max_tokens": 100,
+        "temperature": 0.1,
+        "stream": false
+    });
+
+    let response = chat_completion_request(
+        &context.state.http_client,
+        &task_url,
+        &task_key,
+        &task_config,
+        &tags_payload,
+    )
+    .timeout(std::time::Duration::from_secs(30))
+    .with_request_id()
+    .send()
+    .await?;
+    let response = into_chat_completion_response(response, &task_config).await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        tracing::warn!(
+            "🏷️  Tags generation failed with status: {} - {}",
+            status,
+            error_text
+        );
+        return Ok(());
+    }
+
+    let json_response = response.json::<Value>().await?;
+    let generated = extract_completion_content(&json_response)
+        .map(parse_generated_tags)
+        .unwrap_or_default();
+
+    if generated.is_empty() {
+        tracing::warn!("🏷️  No tags found in tags generation response");
+        return Ok(());
+    }
+
+    let chat_service = ChatService::new(&context.state.db);
+    let chat = match chat_service.get_chat_by_id(chat_id).await? {
+        Some(chat) => chat,
+        None => {
+            tracing::warn!("🏷️  Chat {} not found in database", chat_id);
+            return Ok(());
+        }
+    };
+
+    let tags = chat_service
+        .merge_chat_tags(chat_id, &chat.user_id, &generated)
+        .await?;
+
+    tracing::info!("🏷️  Updated chat {} with tags: {:?}", chat_id, tags);
+
+    if let Some(socket_state) = &context.state.socket_state {
+        let event_payload = json!({
+            "chat_id": chat_id,
+            "message_id": context.message_id.as_deref(),
+            "data": {
+                "type": "chat:tags",
+                "data": tags,
+            }
+        });
+
+        if let Err(e) = socket_state
+            .native_handler
+            .emit_to_user(&context.user.id, "chat-events", event_payload)
+            .await
+        {
+            tracing::error!("🏷️  Failed to emit chat:tags event: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Generate follow-up suggestions for the assistant message, store them as the message's
+/// `followUps` and emit `chat:follow_ups`
+async fn generate_and_update_follow_ups(
+    context: StreamingContext,
+    assistant_content: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (chat_id, message_id) = match (context.chat_id.as_ref(), context.message_id.as_ref()) {
+        (Some(chat_id), Some(message_id)) => (chat_id, message_id),
+        _ => return Ok(()),
+    };
+
+    if assistant_content.is_empty() {
+        return Ok(());
+    }
+
+    // Include the assistant reply so suggestions follow from the latest answer
+    let mut messages = context.messages.clone();
+    messages.push(json!({"role": "assistant", "content": assistant_content}));
+
+    let prompt = {
+        let config = context.state.config.snapshot();
+
+        if !config.enable_follow_up_generation {
+            tracing::debug!("💡 Follow-up generation is DISABLED in config");
+            return Ok(());
+        }
+
+        let template = if config.follow_up_generation_prompt_template.is_empty() {
+            DEFAULT_FOLLOW_UP_GENERATION_PROMPT_TEMPLATE.to_string()
+        } else {
+            config.follow_up_generation_prompt_template.clone()
+        };
+
+        // Only the recent exchange matters for what the user might ask next
+        let recent: Vec<Value> = messages.iter().rev().take(6).rev().cloned().collect();
+        template.replace("{{MESSAGES}}", &format_messages_for_prompt(&recent))
+    };
+
+    let (task_model, task_url, task_key, task_config) = context.task_target(resolve_task_model(
+        &context.state,
+        &context.model_id,
+        Some(&context.model_item),
+    ));
+
+    let follow_ups_payload = json!({
+        "model": task_model,
+        "messages": [{"role": "user", "content": prompt}],
+        "max_tokens": 200,
+        "temperature": 0.7,
+        "stream": false
+    });
+
+    let response = chat_completion_request(
+        &context.state.http_client,
+        &task_url,
+        &task_key,
+        &task_config,
+        &follow_ups_payload,
+    )
+    .timeout(std::time::Duration::from_secs(30))
+    .with_request_id()
+    .send()
+    .await?;
+    let response = into_chat_completion_response(response, &task_config).await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        tracing::warn!(
+            "💡 Follow-up generation failed with status: {} - {}",
+            status,
+            error_text
+        );
+        return Ok(());
+    }
+
+    let json_response = response.json::<Value>().await?;
+    let follow_ups = extract_completion_content(&json_response)
+        .map(parse_generated_follow_ups)
+        .unwrap_or_default();
+
+    if follow_ups.is_empty() {
+        tracing::warn!("💡 No follow-ups found in follow-up generation response");
+        return Ok(());
+    }
+
+    upsert_chat_message(
+        &context.state.db,
+        chat_id,
+        message_id,
+        json!({ "followUps": follow_ups }),
+    )
+    .await?;
+
+    tracing::info!(
+        "💡 Saved {} follow-up(s) for message {} in chat {}",
+        follow_ups.len(),
+        message_id,
+        chat_id
+    );
+
+    if let Some(socket_state) = &context.state.socket_state {
+        let event_payload = json!({
+            "chat_id": chat_id,
+            "message_id": message_id,
+            "data": {
+                "type": "chat:follow_ups",
+                "data": {
+                    "follow_ups": follow_ups,
+                },
+            }
+        });
+
+        if let Err(e) = socket_state
+            .native_handler
+            .emit_to_user(&context.user.id, "chat-events", event_payload)
+            .await
+        {
+            tracing::error!("💡 Failed to emit chat:follow_ups event: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Format messages as `role: content` lines for task prompts; only the text parts of
+/// multimodal messages are included
+pub fn format_messages_for_prompt(messages: &[Value]) -> String {
+    messages
+        .iter()
+        .map(|m| {
+            let role = m.get("role").and_then(|v| v.as_str()).unwrap_or("user");
+            let content = m
+                .get("content")
+                .map(message_content_text)
+                .unwrap_or_default();
+            format!("{}: {}", role, content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Extract the assistant message content from a non-streaming completion response
+pub fn extract_completion_content(response: &Value) -> Option<&str> {
+    response
+        .get("choices")
+        .and_then(|c| c.get(0))
+        .and_then(|c| c.get("message"))
+        .and_then(|m| m.get("content"))
+        .and_then(|c| c.as_str())
+}
+
+/// Parse `{ "tags": [...] }` out of a model response and normalize the result
+pub fn parse_generated_tags(text: &str) -> Vec<String> {
+    let (start, end) = match (text.find('{'), text.rfind('}')) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        _ => return Vec::new(),
+    };
+
+    let raw: Vec<String> = serde_json::from_str::<Value>(&text[start..=end])
+        .ok()
+        .and_then(|v| v.get("tags").and_then(|t| t.as_array()).cloned())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|t| t.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    normalize_tags(&raw, MAX_GENERATED_TAGS)
+}
+
+/// Normalize tag names the same way manual tags are stored
+/// (trimmed, lowercase, spaces as underscores), dropping duplicates and capping the count
+pub fn normalize_tags(tags: &[String], max_count: usize) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+
+    for tag in tags {
+        let tag_id = tag.trim().replace(' ', "_").to_lowercase();
+        if tag_id.is_empty() || normalized.contains(&tag_id) {
+            continue;
+        }
+        normalized.push(tag_id);
+        if normalized.len() >= max_count {
+            break;
+        }
+    }
+
+    normalized
+}
+
+/// Parse `{ "follow_ups": [...] }` (or the older `questions` key) out of a model response,
+/// dropping blanks and duplicates and capping the count
+pub fn parse_generated_follow_ups(text: &str) -> Vec<String> {
+    let (start, end) = match (text.find('{'), text.rfind('}')) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        _ => return Vec::new(),
+    };
+
+    let raw: Vec<String> = serde_json::from_str::<Value>(&text[start..=end])
+        .ok()
+        .and_then(|v| {
+            v.get("follow_ups")
+                .or_else(|| v.get("questions"))
+                .and_then(|q| q.as_array())
+                .cloned()
+        })
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|q| q.as_str().map(|q| q.trim().to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut follow_ups: Vec<String> = Vec::new();
+    for question in raw {
+        if !question.is_empty() && !follow_ups.contains(&question) {
+            follow_ups.push(question);
+        }
+        if follow_ups.len() >= MAX_GENERATED_FOLLOW_UPS {
+            break;
+        }
+    }
+    follow_ups
+}
+
+/// Merge generated tags into existing ones, keeping user-set tags first
+pub fn merge_tags(existing: &[String], generated: &[String]) -> Vec<String> {
+    let mut merged = existing.to_vec();
+    for tag in generated {
+        if !merged.contains(tag) {
+            merged.push(tag.clone());
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Recover the text of a zero-width watermark embedded in `content`
+    fn decode_zero_width_watermark(content: &str) -> Option<String> {
+        let start = content.find(WATERMARK_BOUNDARY)? + WATERMARK_BOUNDARY.len_utf8();
+        let end = start + content[start..].find(WATERMARK_BOUNDARY)?;
+
+        let bits: Vec<u8> = content[start..end]
+            .chars()
+            .filter_map(|c| match c {
+                WATERMARK_BIT_ZERO => Some(0),
+                WATERMARK_BIT_ONE => Some(1),
+                _ => None,
+            })
+            .collect();
+
+        let bytes: Vec<u8> = bits
+            .chunks(8)
+            .filter(|chunk| chunk.len() == 8)
+            .map(|chunk| chunk.iter().fold(0u8, |acc, bit| (acc << 1) | bit))
+            .collect();
+
+        String::from_utf8(bytes).ok()
+    }
+
+    #[test]
+    fn test_tools_run_with_the_real_user() {
+        let user = UserContext {
+            id: "user-1".to_string(),
+            name: "Ada Lovelace".to_string(),
+            email: "ada@example.org".to_string(),
+            role: Some("admin".to_string()),
+        };
+
+        let context = tool_execution_context(&user, Some("sid-1"));
+        let context_user = context.user.unwrap();
+        assert_eq!(context_user.email, "ada@example.org");
+        assert_eq!(context_user.name, "Ada Lovelace");
+        assert_eq!(context_user.role.as_deref(), Some("admin"));
+        assert_eq!(context.session.get("session_id"), Some(&json!("sid-1")));
+
+        let context = tool_execution_context(&user, None);
+        assert!(context.session.is_empty());
+    }
+
+    #[test]
+    fn test_task_requests_follow_the_endpoint_api_type() {
+        let client = reqwest::Client::new();
+        let payload = json!({
+            "model": "claude-3-5-haiku",
+            "messages": [{"role": "user", "content": "Title?"}],
+            "stream": false
+        });
+
+        let request = chat_completion_request(
+            &client,
+            "https://api.anthropic.com/v1/",
+            "sk-ant",
+            &json!({"api_type": "anthropic"}),
+            &payload,
+        )
+        .build()
+        .unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "https://api.anthropic.com/v1/messages"
+        );
+        assert_eq!(request.headers()["x-api-key"], "sk-ant");
+        assert!(request.headers().get("authorization").is_none());
+
+        let request = chat_completion_request(
+            &client,
+            "https://generativelanguage.googleapis.com/v1beta",
+            "gm-key",
+            &json!({"api_type": "gemini"}),
+            &json!({"model": "models/gemini-2.0-flash", "messages": [], "stream": false}),
+        )
+        .build()
+        .unwrap();
+        assert!(request
+            .url()
+            .as_str()
+            .ends_with("/v1beta/models/gemini-2.0-flash:generateContent"));
+        assert_eq!(request.headers()["x-goog-api-key"], "gm-key");
+
+        let request =
+            chat_completion_request(&client, "http://llm:8000/v1", "sk", &json!({}), &payload)
+                .build()
+                .unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "http://llm:8000/v1/chat/completions"
+        );
+        assert_eq!(request.headers()["authorization"], "Bearer sk");
+    }
+
+    #[test]
+    fn test_sse_usage_is_found_across_chunks() {
+        assert_eq!(
+            sse_line_usage(br#"data: {"choices":[{"delta":{"content":"hi"}}]}"#),
+            None
+        );
+        assert_eq!(
+            sse_line_usage(br#"data: {"choices":[],"usage":null}"#),
+            None
+        );
+        assert_eq!(sse_line_usage(b"data: [DONE]"), None);
+
+        let chunks: [&[u8]; 3] = [
+            b"data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\ndata: {\"choices\":[],",
+            b"\"usage\":{\"prompt_tokens\":12,",
+            b"\"completion_tokens\":30}}\n\ndata: [DONE]\n\n",
+        ];
+        let mut scanner = SseUsageScanner::default();
+        scanner.feed(chunks[0]);
+        assert_eq!(scanner.usage, None);
+        scanner.feed(chunks[1]);
+        scanner.feed(chunks[2]);
+        assert_eq!(
+            scanner.usage,
+            Some(json!({"prompt_tokens": 12, "completion_tokens": 30}))
+        );
+    }
+
+    #[test]
+    fn test_failed_tool_runs_still_have_content() {
+        use crate::models::tool_runtime::ToolExecutionResponse;
+
+        let timed_out = ToolExecutionResponse {
+            success: false,
+            result: None,
+            error: Some("Request timeout: Tool 'slow' timed out after 30s".to_string()),
+            metadata: None,
+        };
+        assert_eq!(
+            tool_result_content_for(Ok(timed_out)),
+            "Error executing tool: Request timeout: Tool 'slow' timed out after 30s"
+        );
+
+        let succeeded = ToolExecutionResponse {
+            success: true,
+            result: Some(json!({"temperature": 21})),
+            error: None,
+            metadata: None,
+        };
+        assert_eq!(
+            tool_result_content_for(Ok(succeeded)),
+            r#"{"temperature":21}"#
+        );
+
+        let missing = Err(AppError::NotFound("Tool not found: slow".to_string()));
+        assert!(tool_result_content_for(missing).starts_with("Error executing tool: "));
+
+        let invalid_output = ToolExecutionResponse {
+            success: false,
+            result: Some(json!({"error": {"type": "invalid_tool_output"}})),
+            error: Some("Tool 'weather' returned a result that doesn't match".to_string()),
+            metadata: None,
+        };
+        assert_eq!(
+            tool_result_content_for(Ok(invalid_output)),
+            r#"{"error":{"type":"invalid_tool_output"}}"#
+        );
+    }
+
+    #[test]
+    fn test_partial_persist_tracker_delta_interval() {
+        let mut tracker = PartialPersistTracker::new(3, Duration::from_secs(3600));
+        assert!(!tracker.record_delta());
+        assert!(!tracker.record_delta());
+        assert!(tracker.record_delta());
+        assert!(!tracker.record_delta());
+    }
+
+    #[tokio::test]
+    async fn test_partial_content_persisted_before_done() {
+        use crate::models::chat::CreateChatRequest;
+        use crate::services::chat::ChatService;
+        use crate::test_utils;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = test_utils::test_db(&dir).await;
+        test_utils::create_user(&db, "user-1", "user").await;
+
+        let chat_service = ChatService::new(&db);
+        chat_service
+            .create_chat(
+                "user-1",
+                CreateChatRequest {
+                    id: "chat-1".to_string(),
+                    title: None,
+                    chat: json!({"history": {"messages": {}}}),
+                    folder_id: None,
+                    archived: None,
+                    pinned: None,
+                    share_id: None,
+                    meta: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let mut tracker = PartialPersistTracker::new(2, Duration::from_secs(3600));
+        let mut content = String::new();
+        for delta in ["Hel", "lo", " wor"] {
+            content.push_str(delta);
+            if tracker.record_delta() {
+                persist_partial_message(&db, "chat-1", "msg-1", "test-model", &content).await;
+            }
+        }
+
+        let chat = chat_service
+            .get_chat_by_id("chat-1")
+            .await
+            .unwrap()
+            .unwrap();
+        let message = &chat.chat["history"]["messages"]["msg-1"];
+        assert_eq!(message["content"], "Hello");
+        assert_eq!(message["done"], false);
+        assert_eq!(chat.chat["history"]["currentId"], "msg-1");
+    }
+
+    /// Events sent for a stream of `tokens` content deltas and the content they carry
+    fn batch_tokens(batcher: &mut DeltaBatcher, tokens: usize) -> (usize, String) {
+        let mut events = Vec::new();
+        for i in 0..tokens {
+            let chunk =
+                json!({"choices": [{"index": 0, "delta": {"content": format!("t{} ", i)}}]});
+            events.extend(batcher.push(chunk));
+        }
+        events.extend(batcher.take());
+
+        let content = events
+            .iter()
+            .map(|event| event["choices"][0]["delta"]["content"].as_str().unwrap())
+            .collect();
+        (events.len(), content)
+    }
+
+    #[test]
+    fn test_deltas_are_batched_by_chunk_size() {
+        let expected: String = (0..100).map(|i| format!("t{} ", i)).collect();
+        let hour = Duration::from_secs(3600);
+
+        let (events, content) = batch_tokens(&mut DeltaBatcher::new(1, hour), 100);
+        assert_eq!(events, 100);
+        assert_eq!(content, expected);
+
+        let (events, content) = batch_tokens(&mut DeltaBatcher::new(10, hour), 100);
+        assert_eq!(events, 10);
+        assert_eq!(content, expected);
+
+        // A partial batch is sent at the end
+        let (events, content) = batch_tokens(&mut DeltaBatcher::new(30, hour), 100);
+        assert_eq!(events, 4);
+        assert_eq!(content, expected);
+    }
+
+    #[test]
+    fn test_batched_deltas_are_flushed_in_time() {
+        let mut batcher = DeltaBatcher::new(10, Duration::from_millis(50));
+        assert_eq!(batcher.deadline(), None);
+
+        let chunk = json!({"choices": [{"index": 0, "delta": {"content": "a"}}]});
+        assert!(batcher.push(chunk.clone()).is_none());
+        let deadline = batcher.deadline().unwrap();
+        assert!(deadline > Instant::now());
+
+        // A slow token arriving after the interval is sent with the waiting one
+        std::thread::sleep(Duration::from_millis(60));
+        let batch = batcher.push(chunk).unwrap();
+        assert_eq!(batch["choices"][0]["delta"]["content"], "aa");
+        assert_eq!(batcher.deadline(), None);
+    }
+
+    #[test]
+    fn test_temporary_chats_are_detected() {
+        assert!(is_temporary_chat(Some("local"), &json!({})));
+        assert!(is_temporary_chat(Some("local:sid-1"), &json!({})));
+        assert!(!is_temporary_chat(Some("localhost-chat"), &json!({})));
+        assert!(!is_temporary_chat(Some("chat-1"), &json!({})));
+        assert!(!is_temporary_chat(None, &json!({"temporary": false})));
+
+        assert!(is_temporary_chat(
+            Some("chat-1"),
+            &json!({"temporary": true})
+        ));
+        assert!(is_temporary_chat(
+            None,
+            &json!({"metadata": {"temporary": true}})
+        ));
+    }
+
+    /// Sets the flag once the upstream's response body is dropped
+    struct ClosedOnDrop(std::sync::Arc<std::sync::atomic::AtomicBool>);
+
+    impl Drop for ClosedOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    /// Upstream streaming a token every 20ms until the client hangs up
+    async fn endless_upstream(
+        closed: web::Data<std::sync::Arc<std::sync::atomic::AtomicBool>>,
+    ) -> HttpResponse {
+        let guard = ClosedOnDrop(closed.get_ref().clone());
+        let body = futures::stream::iter(0..).then(move |i| {
+            let _guard = &guard;
+            async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                let chunk =
+                    json!({"choices": [{"index": 0, "delta": {"content": format!("t{} ", i)}}]});
+                Ok::<_, std::io::Error>(Bytes::from(format!("data: {}\n\n", chunk)))
+            }
+        });
+        HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .streaming(body)
+    }
+
+    async fn socket_test_state(dir: &tempfile::TempDir) -> web::Data<AppState> {
+        use crate::config::Config;
+        use crate::socketio::{
+            EventHandler, HealthConfig, HealthMonitor, PresenceConfig, PresenceManager,
+            RateLimitConfig, RateLimiter, RecoveryConfig, RecoveryManager, SocketIOManager,
+            SocketIOMetrics, YDocManager,
+        };
+        use crate::test_utils;
+        use std::sync::Arc;
+
+        let db = test_utils::test_db(dir).await;
+        let handler = EventHandler::new(
+            SocketIOManager::new(),
+            String::new(),
+            YDocManager::new(None),
+            None,
+            SocketIOMetrics::new(),
+            Arc::new(RateLimiter::new(RateLimitConfig::default())),
+            Arc::new(PresenceManager::new(PresenceConfig::default())),
+            Arc::new(RecoveryManager::new(None, RecoveryConfig::default())),
+            HealthMonitor::new(HealthConfig::default()),
+            db.clone(),
+        );
+
+        let mut state = test_utils::app_state(db, Config::from_env().unwrap());
+        state.socket_state = Some(crate::socket::SocketState::new(Arc::new(handler)));
+        web::Data::new(state)
+    }
+
+    #[actix_web::test]
+    async fn test_stopped_stream_closes_upstream_and_saves_partial_content() {
+        use crate::models::chat::CreateChatRequest;
+        use crate::services::chat::ChatService;
+        use actix_web::{App, HttpServer};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let upstream_closed = closed.clone();
+        let upstream = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(upstream_closed.clone()))
+                .route("/chat/completions", web::post().to(endless_upstream))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let upstream_url = format!("http://{}", upstream.addrs()[0]);
+        actix_web::rt::spawn(upstream.run());
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = socket_test_state(&dir).await;
+        crate::test_utils::create_user(&state.db, "user-1", "user").await;
+        let chat_service = ChatService::new(&state.db);
+        chat_service
+            .create_chat(
+                "user-1",
+                CreateChatRequest {
+                    id: "chat-1".to_string(),
+                    title: None,
+                    chat: json!({"history": {"messages": {}}}),
+                    folder_id: None,
+                    archived: None,
+                    pinned: None,
+                    share_id: None,
+                    meta: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/chat/completions", upstream_url))
+            .json(&json!({"model": "gpt", "stream": true}))
+            .send()
+            .await
+            .unwrap();
+        let cancel = CancellationToken::new();
+        let context = StreamingContext {
+            state: state.clone(),
+            user: UserContext {
+                id: "user-1".to_string(),
+                name: "Test".to_string(),
+                email: "test@example.com".to_string(),
+                role: Some("user".to_string()),
+            },
+            model_id: "gpt".to_string(),
+            messages: vec![json!({"role": "user", "content": "Count"})],
+            chat_id: Some("chat-1".to_string()),
+            message_id: Some("msg-1".to_string()),
+            session_id: None,
+            should_generate_title: false,
+            should_generate_tags: false,
+            should_generate_follow_ups: false,
+            model_item: json!({}),
+            endpoint_url: upstream_url,
+            endpoint_key: String::new(),
+            endpoint_config: json!({}),
+            tool_ids: Vec::new(),
+            tool_specs: Vec::new(),
+            sources: Vec::new(),
+            citations: Vec::new(),
+            delta_chunk_size: None,
+            arena_model_id: None,
+            validate_json: false,
+            temporary: false,
+            cancel: cancel.clone(),
+        };
+        let streaming = actix_web::rt::spawn(process_streaming_via_socketio(response, context));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        cancel.cancel();
+        streaming.await.unwrap().unwrap();
+
+        // The upstream sees the connection closed on its next write
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while !closed.load(Ordering::SeqCst) {
+            assert!(Instant::now() < deadline, "upstream connection still open");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let chat = chat_service
+            .get_chat_by_id("chat-1")
+            .await
+            .unwrap()
+            .unwrap();
+        let message = &chat.chat["history"]["messages"]["msg-1"];
+        assert_eq!(message["done"], true);
+        assert_eq!(message["interrupted"], true);
+        assert!(message["content"].as_str().unwrap().starts_with("t0 t1 "));
+    }
+
+    #[test]
+    fn test_parse_generated_tags() {
+        let text = r#"Sure! { "tags": ["Technology", "Rust Lang", "technology", " "] }"#;
+        assert_eq!(parse_generated_tags(text), vec!["technology", "rust_lang"]);
+        assert!(parse_generated_tags("no json here").is_empty());
+    }
+
+    #[test]
+    fn test_title_retry_policy() {
+        assert!(is_retryable_status(reqwest::StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(reqwest::StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable_status(reqwest::StatusCode::UNAUTHORIZED));
+        assert!(!is_retryable_status(reqwest::StatusCode::NOT_FOUND));
+
+        assert_eq!(title_retry_delay(1), Duration::from_secs(1));
+        assert_eq!(title_retry_delay(2), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_format_messages_for_prompt_multimodal() {
+        let messages = vec![
+            json!({
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "Describe this"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+                ]
+            }),
+            json!({"role": "assistant", "content": "A cat on a sofa"}),
+        ];
+
+        assert_eq!(
+            format_messages_for_prompt(&messages),
+            "user: Describe this\nassistant: A cat on a sofa"
+        );
+    }
+
+    fn watermark(invisible: bool) -> ResponseWatermark {
+        ResponseWatermark {
+            text: "Generated by Acme AI".to_string(),
+            invisible,
+            in_stream: true,
+        }
+    }
+
+    #[test]
+    fn test_visible_watermark_applied_once() {
+        let w = watermark(false);
+        let suffix = w.suffix_for("Hello there").unwrap();
+        assert_eq!(suffix, "\n\nGenerated by Acme AI");
+
+        let watermarked = format!("Hello there{}", suffix);
+        assert!(w.suffix_for(&watermarked).is_none());
+        assert!(w.suffix_for("   ").is_none());
+    }
+
+    #[test]
+    fn test_watermark_streamed_as_last_delta() {
+        let mut w = watermark(false);
+        let suffix = w.suffix_for("Hello there").unwrap();
+        let event = w.delta_event(&suffix).unwrap();
+        assert_eq!(event["type"], "chat:completion");
+        assert_eq!(
+            event["data"]["choices"][0]["delta"]["content"],
+            "\n\nGenerated by Acme AI"
+        );
+        assert!(event["data"].get("done").is_none());
+
+        // Only saved with the message when it's not shown in the stream
+        w.in_stream = false;
+        assert!(w.delta_event(&suffix).is_none());
+    }
+
+    #[test]
+    fn test_watermark_appended_to_json_response() {
+        let w = watermark(false);
+        let mut response = json!({
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}}]
+        });
+        w.apply_to_response(&mut response);
+        assert_eq!(
+            response["choices"][0]["message"]["content"],
+            "Hi\n\nGenerated by Acme AI"
+        );
+
+        // Tool call turns aren't the final answer
+        let mut tool_turn = json!({
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "Let me check",
+                    "tool_calls": [{"id": "call_1", "type": "function"}]
+                }
+            }]
+        });
+        w.apply_to_response(&mut tool_turn);
+        assert_eq!(
+            tool_turn["choices"][0]["message"]["content"],
+            "Let me check"
+        );
+    }
+
+    #[test]
+    fn test_watermark_skips_json_and_open_code_blocks() {
+        let w = watermark(false);
+        assert!(w.suffix_for(r#"{"answer": 42}"#).is_none());
+        assert!(w.suffix_for("```python\nprint(1)").is_none());
+        assert!(w.suffix_for("```python\nprint(1)\n```").is_some());
+    }
+
+    #[test]
+    fn test_invisible_watermark_round_trip() {
+        let w = watermark(true);
+        let suffix = w.suffix_for("Answer").unwrap();
+        assert!(suffix.chars().all(|c| !c.is_alphanumeric()));
+        assert_eq!(
+            decode_zero_width_watermark(&format!("Answer{}", suffix)).as_deref(),
+            Some("Generated by Acme AI")
+        );
+    }
+
+    #[test]
+    fn test_parse_generated_follow_ups() {
+        let text =
+            r#"Here you go: { "follow_ups": [" How? ", "Why?", "How?", "", "When?", "Where?"] }"#;
+        assert_eq!(
+            parse_generated_follow_ups(text),
+            vec!["How?", "Why?", "When?"]
+        );
+
+        let legacy = r#"{ "questions": ["What next?"] }"#;
+        assert_eq!(parse_generated_follow_ups(legacy), vec!["What next?"]);
+        assert!(parse_generated_follow_ups("no json here").is_empty());
+    }
+
+    #[test]
+    fn test_normalize_tags_caps_count() {
+        let tags: Vec<String> = (0..10).map(|i| format!("Tag {}", i)).collect();
+        let normalized = normalize_tags(&tags, 3);
+        assert_eq!(normalized, vec!["tag_0", "tag_1", "tag_2"]);
+    }
+
+    #[test]
+    fn test_merge_tags_keeps_existing() {
+        let existing = vec!["work".to_string(), "python".to_string()];
+        let generated = vec!["python".to_string(), "programming".to_string()];
+        assert_eq!(
+            merge_tags(&existing, &generated),
+            vec!["work", "python", "programming"]
+        );
+    }
+}
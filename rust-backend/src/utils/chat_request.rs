@@ -0,0 +1,469 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use validator::{ValidationError, ValidationErrors};
+
+use crate::{
+    config::Config,
+    error::{AppError, AppResult},
+    utils::misc::message_content_text,
+};
+
+/// Fields of OpenAI's chat completion API, common provider extensions and the web UI's
+/// own fields. Others are forwarded too, with a warning, since they are often typos.
+const KNOWN_FIELDS: &[&str] = &[
+    // OpenAI
+    "audio",
+    "frequency_penalty",
+    "function_call",
+    "functions",
+    "logit_bias",
+    "logprobs",
+    "max_completion_tokens",
+    "max_tokens",
+    "messages",
+    "metadata",
+    "modalities",
+    "model",
+    "n",
+    "parallel_tool_calls",
+    "prediction",
+    "presence_penalty",
+    "reasoning_effort",
+    "response_format",
+    "seed",
+    "service_tier",
+    "stop",
+    "store",
+    "stream",
+    "stream_options",
+    "temperature",
+    "tool_choice",
+    "tools",
+    "top_logprobs",
+    "top_p",
+    "user",
+    "web_search_options",
+    // Provider extensions
+    "keep_alive",
+    "min_p",
+    "num_ctx",
+    "options",
+    "reasoning",
+    "repeat_penalty",
+    "repetition_penalty",
+    "think",
+    "top_k",
+    // Web UI
+    "background_tasks",
+    "chat_id",
+    "features",
+    "files",
+    "filter_ids",
+    "id",
+    "model_item",
+    "params",
+    "session_id",
+    "tool_ids",
+    "tool_servers",
+    "urlIdx",
+    "variables",
+    "web_search",
+];
+
+/// How sampling parameters outside their range are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamPolicy {
+    /// Bring the value into range and forward the request
+    Clamp,
+    /// Answer with a 422
+    Reject,
+}
+
+/// Limits chat completion requests are checked against
+#[derive(Debug, Clone)]
+pub struct RequestLimits {
+    pub policy: ParamPolicy,
+    pub max_messages: usize,
+    /// Characters of text in a single message
+    pub max_message_chars: usize,
+    /// Cap on `max_tokens`/`max_completion_tokens`, if any
+    pub max_tokens: Option<i64>,
+}
+
+impl RequestLimits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            policy: if config.chat_param_policy.eq_ignore_ascii_case("reject") {
+                ParamPolicy::Reject
+            } else {
+                ParamPolicy::Clamp
+            },
+            max_messages: config.chat_max_messages,
+            max_message_chars: config.chat_max_message_chars,
+            max_tokens: (config.chat_max_tokens > 0).then_some(config.chat_max_tokens),
+        }
+    }
+
+    /// Lower the token cap to a model's own `max_tokens` param
+    pub fn with_model_params(mut self, params: &Value) -> Self {
+        let model_max = params
+            .get("max_tokens")
+            .and_then(|v| v.as_i64())
+            .filter(|max| *max > 0);
+        if let Some(model_max) = model_max {
+            self.max_tokens = Some(self.max_tokens.map_or(model_max, |max| max.min(model_max)));
+        }
+        self
+    }
+}
+
+/// Typed view of a chat completion request; fields it doesn't check are kept in `extra`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A request that passed validation: the payload to forward and what was changed or
+/// looked suspicious in it
+#[derive(Debug)]
+pub struct ValidatedRequest {
+    pub payload: Value,
+    pub warnings: Vec<String>,
+}
+
+fn field_error(code: &'static str, message: String) -> ValidationError {
+    ValidationError::new(code).with_message(Cow::Owned(message))
+}
+
+/// Check a chat completion request's shape and limits, bringing sampling parameters into
+/// range when the policy allows. Invalid requests fail with field errors (a 422).
+pub fn validate_chat_request(
+    payload: Value,
+    limits: &RequestLimits,
+) -> AppResult<ValidatedRequest> {
+    let mut errors = ValidationErrors::new();
+    let obj = match payload {
+        Value::Object(obj) => obj,
+        _ => {
+            return Err(AppError::InvalidRequest {
+                location: "body",
+                message: "request body must be a JSON object".to_string(),
+            })
+        }
+    };
+
+    // Types first, so the typed request below always deserializes
+    match obj.get("model") {
+        Some(Value::String(model)) if !model.is_empty() => {}
+        Some(Value::String(_)) => errors.add(
+            "model",
+            field_error("length", "ensure this value is not empty".to_string()),
+        ),
+        Some(_) => errors.add(
+            "model",
+            field_error("type", "value is not a valid string".to_string()),
+        ),
+        None => errors.add("model", ValidationError::new("required")),
+    }
+    match obj.get("messages") {
+        Some(Value::Array(_)) => {}
+        Some(_) => errors.add(
+            "messages",
+            field_error("type", "value is not a valid list".to_string()),
+        ),
+        None => errors.add("messages", ValidationError::new("required")),
+    }
+    for field in [
+        "temperature",
+        "top_p",
+        "presence_penalty",
+        "frequency_penalty",
+    ] {
+        if obj
+            .get(field)
+            .is_some_and(|v| !v.is_null() && !v.is_number())
+        {
+            errors.add(
+                field,
+                field_error("type", "value is not a valid number".to_string()),
+            );
+        }
+    }
+    for field in ["n", "max_tokens", "max_completion_tokens"] {
+        if obj.get(field).is_some_and(|v| !v.is_null() && !v.is_i64()) {
+            errors.add(
+                field,
+                field_error("type", "value is not a valid integer".to_string()),
+            );
+        }
+    }
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(errors));
+    }
+
+    let mut request: ChatCompletionRequest =
+        serde_json::from_value(Value::Object(obj)).map_err(|e| AppError::InvalidRequest {
+            location: "body",
+            message: e.to_string(),
+        })?;
+    let mut warnings: Vec<String> = request
+        .extra
+        .keys()
+        .filter(|key| !KNOWN_FIELDS.contains(&key.as_str()))
+        .map(|key| format!("Unknown field '{}' was forwarded unchanged", key))
+        .collect();
+
+    if request.messages.is_empty() {
+        errors.add(
+            "messages",
+            field_error("length", "ensure this list has at least 1 item".to_string()),
+        );
+    } else if request.messages.len() > limits.max_messages {
+        errors.add(
+            "messages",
+            field_error(
+                "length",
+                format!("ensure this list has at most {} items", limits.max_messages),
+            ),
+        );
+    }
+    for (index, message) in request.messages.iter().enumerate() {
+        if !message.get("role").is_some_and(|r| r.is_string()) {
+            errors.add(
+                "messages",
+                field_error("role", format!("message {} has no role", index)),
+            );
+        } else if message
+            .get("content")
+            .map(|c| message_content_text(c).chars().count())
+            .unwrap_or(0)
+            > limits.max_message_chars
+        {
+            errors.add(
+                "messages",
+                field_error(
+                    "length",
+                    format!(
+                        "message {} is longer than {} characters",
+                        index, limits.max_message_chars
+                    ),
+                ),
+            );
+        }
+    }
+
+    let mut check = |field: &'static str, value: &mut Option<f64>, min: f64, max: f64| {
+        if let Some(v) = value.filter(|v| *v < min || *v > max) {
+            match limits.policy {
+                ParamPolicy::Clamp => {
+                    let clamped = v.clamp(min, max);
+                    warnings.push(format!("{} {} was clamped to {}", field, v, clamped));
+                    *value = Some(clamped);
+                }
+                ParamPolicy::Reject => errors.add(
+                    field,
+                    field_error(
+                        "range",
+                        format!("ensure this value is between {} and {}", min, max),
+                    ),
+                ),
+            }
+        }
+    };
+    check("temperature", &mut request.temperature, 0.0, 2.0);
+    check("top_p", &mut request.top_p, 0.0, 1.0);
+    check("presence_penalty", &mut request.presence_penalty, -2.0, 2.0);
+    check(
+        "frequency_penalty",
+        &mut request.frequency_penalty,
+        -2.0,
+        2.0,
+    );
+
+    let mut check = |field: &'static str, value: &mut Option<i64>, max: Option<i64>| {
+        let v = match *value {
+            Some(v) => v,
+            None => return,
+        };
+        let clamped = max.map_or(v, |max| v.min(max)).max(1);
+        if clamped == v {
+            return;
+        }
+        match limits.policy {
+            ParamPolicy::Clamp => {
+                warnings.push(format!("{} {} was clamped to {}", field, v, clamped));
+                *value = Some(clamped);
+            }
+            ParamPolicy::Reject => {
+                let message = match max {
+                    Some(max) => format!("ensure this value is between 1 and {}", max),
+                    None => "ensure this value is at least 1".to_string(),
+                };
+                errors.add(field, field_error("range", message));
+            }
+        }
+    };
+    check("n", &mut request.n, None);
+    check("max_tokens", &mut request.max_tokens, limits.max_tokens);
+    check(
+        "max_completion_tokens",
+        &mut request.max_completion_tokens,
+        limits.max_tokens,
+    );
+
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(errors));
+    }
+
+    let payload =
+        serde_json::to_value(&request).map_err(|e| AppError::InternalServerError(e.to_string()))?;
+    Ok(ValidatedRequest { payload, warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn limits(policy: ParamPolicy) -> RequestLimits {
+        RequestLimits {
+            policy,
+            max_messages: 3,
+            max_message_chars: 10,
+            max_tokens: Some(1000),
+        }
+    }
+
+    fn field_names(error: AppError) -> Vec<String> {
+        match error {
+            AppError::InvalidFields(errors) => {
+                let mut fields: Vec<String> =
+                    errors.errors().keys().map(|k| k.to_string()).collect();
+                fields.sort();
+                fields
+            }
+            other => panic!("expected field errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_clamps_out_of_range_params() {
+        let validated = validate_chat_request(
+            json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "temperature": 3.5,
+                "top_p": -1,
+                "n": 0,
+                "max_tokens": 1000000,
+                "stream": true,
+                "temprature": 0.2
+            }),
+            &limits(ParamPolicy::Clamp),
+        )
+        .unwrap();
+
+        assert_eq!(validated.payload["temperature"], json!(2.0));
+        assert_eq!(validated.payload["top_p"], json!(0.0));
+        assert_eq!(validated.payload["n"], json!(1));
+        assert_eq!(validated.payload["max_tokens"], json!(1000));
+        // Fields that aren't checked are forwarded as they were
+        assert_eq!(validated.payload["stream"], json!(true));
+        assert_eq!(validated.payload["temprature"], json!(0.2));
+        assert!(validated
+            .warnings
+            .iter()
+            .any(|w| w.contains("'temprature'")));
+        assert_eq!(validated.warnings.len(), 5);
+    }
+
+    #[test]
+    fn test_rejects_out_of_range_params_with_field_errors() {
+        let error = validate_chat_request(
+            json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "temperature": 3.5,
+                "max_tokens": 1001,
+                "top_p": 0.5
+            }),
+            &limits(ParamPolicy::Reject),
+        )
+        .unwrap_err();
+        assert_eq!(field_names(error), vec!["max_tokens", "temperature"]);
+    }
+
+    #[test]
+    fn test_rejects_malformed_requests_under_either_policy() {
+        for policy in [ParamPolicy::Clamp, ParamPolicy::Reject] {
+            let error = validate_chat_request(
+                json!({"messages": "Hi", "temperature": "hot", "n": 1.5}),
+                &limits(policy),
+            )
+            .unwrap_err();
+            assert_eq!(
+                field_names(error),
+                vec!["messages", "model", "n", "temperature"]
+            );
+
+            let too_many = vec![json!({"role": "user", "content": "Hi"}); 4];
+            let error = validate_chat_request(
+                json!({"model": "gpt-4o", "messages": too_many}),
+                &limits(policy),
+            )
+            .unwrap_err();
+            assert_eq!(field_names(error), vec!["messages"]);
+
+            let error = validate_chat_request(
+                json!({
+                    "model": "gpt-4o",
+                    "messages": [{"role": "user", "content": "Far too long for the limit"}]
+                }),
+                &limits(policy),
+            )
+            .unwrap_err();
+            assert_eq!(field_names(error), vec!["messages"]);
+        }
+    }
+
+    #[test]
+    fn test_model_params_lower_the_token_cap() {
+        let model_limits =
+            limits(ParamPolicy::Clamp).with_model_params(&json!({"max_tokens": 256}));
+        assert_eq!(model_limits.max_tokens, Some(256));
+
+        let validated = validate_chat_request(
+            json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "max_completion_tokens": 512
+            }),
+            &model_limits,
+        )
+        .unwrap();
+        assert_eq!(validated.payload["max_completion_tokens"], json!(256));
+
+        // A model can't raise the instance-wide cap
+        let model_limits =
+            limits(ParamPolicy::Clamp).with_model_params(&json!({"max_tokens": 4096}));
+        assert_eq!(model_limits.max_tokens, Some(1000));
+    }
+}
@@ -0,0 +1,286 @@
+//! Pieces shared by the translations between OpenAI chat completions and other
+//! providers' APIs ([`super::anthropic`], [`super::gemini`])
+
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+
+/// Plain text of a message content, joining the text parts of multi-part content
+pub fn text_of(content: Option<&Value>) -> String {
+    match content {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// A part of a chat message content the providers can take
+#[derive(Debug, PartialEq, Eq)]
+pub enum ContentPart<'a> {
+    Text(&'a str),
+    /// A `data:` URL or a plain URL
+    Image(&'a str),
+}
+
+/// Non-empty text and image parts of a message content
+pub fn content_parts(content: Option<&Value>) -> Vec<ContentPart<'_>> {
+    match content {
+        Some(Value::String(text)) if !text.is_empty() => vec![ContentPart::Text(text)],
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| match part.get("type").and_then(|t| t.as_str()) {
+                Some("text") => part
+                    .get("text")
+                    .and_then(|t| t.as_str())
+                    .filter(|t| !t.is_empty())
+                    .map(ContentPart::Text),
+                Some("image_url") => part
+                    .pointer("/image_url/url")
+                    .or_else(|| part.get("image_url"))
+                    .and_then(|u| u.as_str())
+                    .map(ContentPart::Image),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Media type and base64 data of a `data:` URL
+pub fn inline_image(url: &str) -> Option<(&str, &str)> {
+    url.strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+}
+
+/// Arguments of a chat completion tool call as a JSON object
+///
+/// Arguments arrive as a JSON string; anything unparsable counts as no arguments.
+pub fn tool_call_arguments(call: &Value) -> Value {
+    call.pointer("/function/arguments")
+        .and_then(|a| a.as_str())
+        .and_then(|a| serde_json::from_str::<Value>(a).ok())
+        .filter(|a| a.is_object())
+        .unwrap_or_else(|| json!({}))
+}
+
+/// Append `items` to `turns` as a turn of `role`, merging them into the last turn when
+/// it has the same role since the providers expect user and model turns to alternate
+pub fn push_turn(turns: &mut Vec<Value>, role: &str, field: &str, items: Vec<Value>) {
+    if items.is_empty() {
+        return;
+    }
+    match turns.last_mut() {
+        Some(last) if last["role"] == role => {
+            if let Some(existing) = last[field].as_array_mut() {
+                existing.extend(items);
+            }
+        }
+        _ => turns.push(json!({ "role": role, field: items })),
+    }
+}
+
+pub fn chat_usage(prompt_tokens: u64, completion_tokens: u64) -> Value {
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+pub fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Splits an SSE byte stream into the JSON payloads of its `data:` lines
+///
+/// Input arrives in arbitrary byte chunks, so incomplete lines are buffered until
+/// their newline is seen.
+#[derive(Debug, Default)]
+pub struct SseData {
+    buffer: Vec<u8>,
+}
+
+impl SseData {
+    /// Feed upstream bytes and get back the payload of every complete `data:` line
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Value> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut payloads = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let data = match line.trim_end_matches(['\r', '\n']).strip_prefix("data:") {
+                Some(data) => data.trim().to_string(),
+                None => continue,
+            };
+            if let Ok(payload) = serde_json::from_str::<Value>(&data) {
+                payloads.push(payload);
+            }
+        }
+        payloads
+    }
+}
+
+/// Identity of the `chat.completion.chunk`s of one translated stream
+#[derive(Debug)]
+pub struct ChunkHeader {
+    pub id: Value,
+    pub model: Value,
+    pub created: i64,
+}
+
+impl Default for ChunkHeader {
+    fn default() -> Self {
+        ChunkHeader {
+            id: Value::Null,
+            model: Value::Null,
+            created: now(),
+        }
+    }
+}
+
+impl ChunkHeader {
+    pub fn chunk(&self, delta: Value, finish_reason: Value) -> String {
+        let chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        });
+        format!("data: {}\n\n", chunk)
+    }
+
+    /// The chunk carrying the stream's token usage, sent after the finish reason
+    pub fn usage_chunk(&self, usage: Value) -> String {
+        let chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [],
+            "usage": usage,
+        });
+        format!("data: {}\n\n", chunk)
+    }
+}
+
+/// The line ending every chat completion stream
+pub const DONE: &str = "data: [DONE]\n\n";
+
+/// An upstream error reported in the stream, which ends it
+pub fn error_chunks(error: Value) -> Vec<String> {
+    vec![
+        format!("data: {}\n\n", json!({ "error": error })),
+        DONE.to_string(),
+    ]
+}
+
+/// Rewrites a provider's event stream into chat completion chunks
+pub trait ChunkConverter: Send + 'static {
+    /// Feed upstream bytes and get back the converted output for every complete line
+    fn push(&mut self, bytes: &[u8]) -> String;
+}
+
+/// Present a provider response as a chat completion response, streamed or not
+///
+/// Streams are rewritten by the converter `new_converter` makes, whole responses by
+/// `to_completion`. Error responses are passed through untouched so callers report
+/// them as usual.
+pub async fn into_chat_completion_response<C: ChunkConverter>(
+    response: reqwest::Response,
+    new_converter: fn() -> C,
+    to_completion: fn(&Value) -> Value,
+) -> reqwest::Result<reqwest::Response> {
+    if !response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let is_stream = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/event-stream"));
+
+    let (content_type, body) = if is_stream {
+        let mut converter = new_converter();
+        let stream = response
+            .bytes_stream()
+            .map(move |chunk| chunk.map(|bytes| converter.push(&bytes)));
+        ("text/event-stream", reqwest::Body::wrap_stream(stream))
+    } else {
+        let upstream: Value = response.json().await?;
+        let completion = to_completion(&upstream);
+        (
+            "application/json",
+            reqwest::Body::from(completion.to_string()),
+        )
+    };
+
+    let response = http::Response::builder()
+        .status(status)
+        .header(reqwest::header::CONTENT_TYPE, content_type)
+        .body(body)
+        .expect("static response parts are valid");
+    Ok(reqwest::Response::from(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_parts() {
+        let content = json!([
+            { "type": "text", "text": "Look" },
+            { "type": "text", "text": "" },
+            { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBO" } },
+            { "type": "image_url", "image_url": "https://example.com/a.png" },
+            { "type": "input_audio" },
+        ]);
+        assert_eq!(
+            content_parts(Some(&content)),
+            vec![
+                ContentPart::Text("Look"),
+                ContentPart::Image("data:image/png;base64,iVBO"),
+                ContentPart::Image("https://example.com/a.png"),
+            ]
+        );
+        assert_eq!(text_of(Some(&content)), "Look\n");
+        assert!(content_parts(Some(&json!(""))).is_empty());
+        assert_eq!(
+            inline_image("data:image/png;base64,iVBO"),
+            Some(("image/png", "iVBO"))
+        );
+        assert_eq!(inline_image("https://example.com/a.png"), None);
+    }
+
+    #[test]
+    fn test_push_turn_merges_same_role() {
+        let mut turns = Vec::new();
+        push_turn(&mut turns, "user", "parts", vec![json!({ "text": "a" })]);
+        push_turn(&mut turns, "user", "parts", vec![json!({ "text": "b" })]);
+        push_turn(&mut turns, "model", "parts", Vec::new());
+        push_turn(&mut turns, "model", "parts", vec![json!({ "text": "c" })]);
+        assert_eq!(
+            json!(turns),
+            json!([
+                { "role": "user", "parts": [{ "text": "a" }, { "text": "b" }] },
+                { "role": "model", "parts": [{ "text": "c" }] },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_sse_data_buffers_partial_lines() {
+        let mut sse = SseData::default();
+        assert!(sse.push(b"event: x\ndata: {\"a\"").is_empty());
+        assert_eq!(
+            sse.push(b": 1}\r\n\ndata: [DONE]\n"),
+            vec![json!({ "a": 1 })]
+        );
+    }
+}
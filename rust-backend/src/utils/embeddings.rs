@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::error::AppError;
+use crate::utils::telemetry;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddingRequest {
@@ -133,14 +134,13 @@ pub fn create_mock_embedding(dimension: usize) -> Vec<f32> {
 /// Generate embeddings using OpenAI API
 #[allow(dead_code)]
 pub async fn generate_openai_embeddings(
+    client: &reqwest::Client,
     base_url: &str,
     api_key: &str,
     model: &str,
     texts: Vec<String>,
     dimension: Option<i32>,
 ) -> Result<Vec<Vec<f32>>, AppError> {
-    let client = reqwest::Client::new();
-
     let mut payload = json!({
         "model": model,
         "input": texts,
@@ -150,13 +150,13 @@ pub async fn generate_openai_embeddings(
         payload["dimensions"] = json!(dim);
     }
 
-    let response = client
+    let request = client
         .post(format!("{}/embeddings", base_url))
         .header("Authorization", format!("Bearer {}", api_key))
         .header("Content-Type", "application/json")
-        .json(&payload)
-        .send()
-        .await?;
+        .json(&payload);
+    let span = telemetry::upstream_span("embeddings", base_url, Some(model));
+    let response = telemetry::send_traced(request, span).await?;
 
     if !response.status().is_success() {
         let status = response.status();
@@ -179,6 +179,7 @@ pub async fn generate_openai_embeddings(
 /// Generate embeddings using Azure OpenAI API
 #[allow(dead_code)]
 pub async fn generate_azure_openai_embeddings(
+    client: &reqwest::Client,
     base_url: &str,
     api_key: &str,
     model: &str,
@@ -186,8 +187,6 @@ pub async fn generate_azure_openai_embeddings(
     api_version: &str,
     dimension: Option<i32>,
 ) -> Result<Vec<Vec<f32>>, AppError> {
-    let client = reqwest::Client::new();
-
     let url = format!(
         "{}/openai/deployments/{}/embeddings?api-version={}",
         base_url, model, api_version
@@ -201,13 +200,13 @@ pub async fn generate_azure_openai_embeddings(
         payload["dimensions"] = json!(dim);
     }
 
-    let response = client
+    let request = client
         .post(&url)
         .header("api-key", api_key)
         .header("Content-Type", "application/json")
-        .json(&payload)
-        .send()
-        .await?;
+        .json(&payload);
+    let span = telemetry::upstream_span("embeddings", base_url, Some(model));
+    let response = telemetry::send_traced(request, span).await?;
 
     if !response.status().is_success() {
         let status = response.status();
@@ -376,6 +375,7 @@ pub async fn generate_local_embeddings(
 /// This is the main entry point that dispatches to the appropriate backend
 #[allow(dead_code)]
 pub async fn generate_embeddings(
+    client: &reqwest::Client,
     engine: &str,
     model: &str,
     base_url: Option<&str>,
@@ -391,7 +391,7 @@ pub async fn generate_embeddings(
             let key = api_key.ok_or_else(|| {
                 AppError::BadRequest("API key required for OpenAI embeddings".to_string())
             })?;
-            generate_openai_embeddings(url, key, model, texts, dimension).await
+            generate_openai_embeddings(client, url, key, model, texts, dimension).await
         }
         "azure_openai" => {
             let url = base_url.ok_or_else(|| {
@@ -401,7 +401,8 @@ pub async fn generate_embeddings(
                 AppError::BadRequest("API key required for Azure OpenAI embeddings".to_string())
             })?;
             let api_version = "2023-05-15"; // Default version
-            generate_azure_openai_embeddings(url, key, model, texts, api_version, dimension).await
+            generate_azure_openai_embeddings(client, url, key, model, texts, api_version, dimension)
+                .await
         }
         "local" | "" => {
             // Use local fastembed
@@ -0,0 +1,66 @@
+/// Encryption of Stored Secrets
+///
+/// AES-256-GCM with a key derived from `WEBUI_SECRET_KEY`. Values are encoded as
+/// URL-safe base64 of the nonce followed by the ciphertext, so they can be stored in
+/// a text column or passed in a URL.
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+
+use crate::error::{AppError, AppResult};
+
+const NONCE_LEN: usize = 12;
+
+fn cipher(secret: &str) -> Aes256Gcm {
+    Aes256Gcm::new(&Sha256::digest(secret.as_bytes()))
+}
+
+/// Encrypt `plaintext` with a fresh random nonce
+pub fn encrypt(secret: &str, plaintext: &[u8]) -> String {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let ciphertext = cipher(secret)
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .expect("AES-GCM encryption of an in-memory buffer can't fail");
+
+    let mut encoded = nonce.to_vec();
+    encoded.extend(ciphertext);
+    URL_SAFE_NO_PAD.encode(encoded)
+}
+
+/// Decrypt a value from [`encrypt`], failing when it was altered or encrypted with
+/// another secret
+pub fn decrypt(secret: &str, encoded: &str) -> AppResult<Vec<u8>> {
+    let invalid = || AppError::BadRequest("Invalid encrypted value".to_string());
+    let data = URL_SAFE_NO_PAD.decode(encoded).map_err(|_| invalid())?;
+    if data.len() < NONCE_LEN {
+        return Err(invalid());
+    }
+
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    cipher(secret)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_tampering() {
+        let encrypted = encrypt("secret", b"access-token");
+        assert!(!encrypted.contains("access-token"));
+        assert_eq!(decrypt("secret", &encrypted).unwrap(), b"access-token");
+
+        // Every encryption uses a new nonce
+        assert_ne!(encrypted, encrypt("secret", b"access-token"));
+
+        assert!(decrypt("other-secret", &encrypted).is_err());
+        let mut tampered = encrypted.into_bytes();
+        let last = tampered.len() - 1;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert!(decrypt("secret", &String::from_utf8(tampered).unwrap()).is_err());
+        assert!(decrypt("secret", "short").is_err());
+    }
+}
@@ -0,0 +1,750 @@
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::utils::chat_translation::{
+    self, chat_usage, content_parts, inline_image, now, push_turn, text_of, tool_call_arguments,
+    ChunkConverter, ChunkHeader, ContentPart, SseData, DONE,
+};
+
+/// Whether a connection speaks Google's Gemini API (`"api_type": "gemini"` in its
+/// `openai_api_configs` entry) rather than OpenAI chat completions
+pub fn is_gemini(api_config: &Value) -> bool {
+    api_config.get("api_type").and_then(|v| v.as_str()) == Some("gemini")
+}
+
+/// Authenticate `request` the way Gemini expects: `x-goog-api-key` instead of a bearer token
+pub fn with_auth(request: reqwest::RequestBuilder, key: &str) -> reqwest::RequestBuilder {
+    if key.is_empty() {
+        request
+    } else {
+        request.header("x-goog-api-key", key)
+    }
+}
+
+/// `generateContent` (or `streamGenerateContent` as SSE) for the chat completion
+/// request `payload`
+pub fn generate_content_request(
+    client: &reqwest::Client,
+    url: &str,
+    key: &str,
+    payload: &Value,
+) -> reqwest::RequestBuilder {
+    let model = payload["model"].as_str().unwrap_or_default();
+    let model = model.strip_prefix("models/").unwrap_or(model);
+    let stream = payload["stream"].as_bool().unwrap_or(false);
+    let method = if stream {
+        "streamGenerateContent?alt=sse"
+    } else {
+        "generateContent"
+    };
+    let request = client
+        .post(format!(
+            "{}/models/{}:{}",
+            url.trim_end_matches('/'),
+            model,
+            method
+        ))
+        .header("Content-Type", "application/json");
+    with_auth(request, key).json(&to_generate_content_payload(payload))
+}
+
+/// Present a Gemini response as a chat completion response, streamed or not
+///
+/// Error responses are passed through untouched so callers report them as usual.
+pub async fn into_chat_completion_response(
+    response: reqwest::Response,
+) -> reqwest::Result<reqwest::Response> {
+    chat_translation::into_chat_completion_response(
+        response,
+        GenerateContentStreamConverter::new,
+        response_to_chat_completion,
+    )
+    .await
+}
+
+/// Translate Gemini's model list into an OpenAI one, keeping the models that can chat
+pub fn to_model_list(models: &Value) -> Value {
+    let data: Vec<Value> = models
+        .get("models")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+        .filter(|model| {
+            model
+                .get("supportedGenerationMethods")
+                .and_then(|m| m.as_array())
+                .is_none_or(|methods| methods.iter().any(|m| m == "generateContent"))
+        })
+        .filter_map(|model| {
+            let name = model.get("name")?.as_str()?;
+            let id = name.strip_prefix("models/").unwrap_or(name);
+            Some(json!({
+                "id": id,
+                "object": "model",
+                "name": model.get("displayName").and_then(|d| d.as_str()).unwrap_or(id),
+                "owned_by": "google",
+                "tags": [{ "name": "gemini" }],
+                "description": model.get("description").cloned().unwrap_or(Value::Null),
+            }))
+        })
+        .collect();
+    json!({ "object": "list", "data": data })
+}
+
+/// Translate an OpenAI chat completion request into a `generateContent` request
+///
+/// System messages become the `systemInstruction`, tool calls and results become
+/// `functionCall`/`functionResponse` parts, and consecutive messages of the same role
+/// are merged since Gemini expects user and model turns to alternate.
+pub fn to_generate_content_payload(payload: &Value) -> Value {
+    let messages: Vec<&Value> = payload
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+        .collect();
+
+    // Function responses are matched to their call by name, not id
+    let call_names: HashMap<&str, &Value> = messages
+        .iter()
+        .filter_map(|m| m.get("tool_calls").and_then(|c| c.as_array()))
+        .flatten()
+        .filter_map(|call| Some((call.get("id")?.as_str()?, call.pointer("/function/name")?)))
+        .collect();
+
+    let mut system = Vec::new();
+    let mut contents: Vec<Value> = Vec::new();
+    for message in messages {
+        let role = message
+            .get("role")
+            .and_then(|r| r.as_str())
+            .unwrap_or("user");
+        let (role, parts) = match role {
+            "system" | "developer" => {
+                let text = text_of(message.get("content"));
+                if !text.is_empty() {
+                    system.push(text);
+                }
+                continue;
+            }
+            "assistant" => ("model", model_parts(message)),
+            "tool" => {
+                let name = message
+                    .get("tool_call_id")
+                    .and_then(|id| id.as_str())
+                    .and_then(|id| call_names.get(id))
+                    .map(|name| (*name).clone())
+                    .or_else(|| message.get("name").cloned())
+                    .unwrap_or(Value::Null);
+                let part = json!({ "functionResponse": {
+                    "name": name,
+                    "response": { "content": text_of(message.get("content")) },
+                }});
+                ("user", vec![part])
+            }
+            _ => ("user", user_parts(message.get("content"))),
+        };
+        push_turn(&mut contents, role, "parts", parts);
+    }
+
+    let mut request = Map::new();
+    request.insert("contents".to_string(), json!(contents));
+    if !system.is_empty() {
+        request.insert(
+            "systemInstruction".to_string(),
+            json!({ "parts": [{ "text": system.join("\n\n") }] }),
+        );
+    }
+
+    let mut generation_config = Map::new();
+    for (from, to) in [
+        ("temperature", "temperature"),
+        ("top_p", "topP"),
+        ("top_k", "topK"),
+        ("seed", "seed"),
+        ("presence_penalty", "presencePenalty"),
+        ("frequency_penalty", "frequencyPenalty"),
+        ("max_tokens", "maxOutputTokens"),
+        ("max_completion_tokens", "maxOutputTokens"),
+    ] {
+        if let Some(value) = payload.get(from).filter(|v| !v.is_null()) {
+            generation_config.insert(to.to_string(), value.clone());
+        }
+    }
+    match payload.get("stop") {
+        Some(Value::String(stop)) => {
+            generation_config.insert("stopSequences".to_string(), json!([stop]));
+        }
+        Some(Value::Array(stops)) if !stops.is_empty() => {
+            generation_config.insert("stopSequences".to_string(), json!(stops));
+        }
+        _ => {}
+    }
+    match payload
+        .pointer("/response_format/type")
+        .and_then(|t| t.as_str())
+    {
+        Some("json_object") => {
+            generation_config.insert("responseMimeType".to_string(), json!("application/json"));
+        }
+        Some("json_schema") => {
+            generation_config.insert("responseMimeType".to_string(), json!("application/json"));
+            if let Some(schema) = payload.pointer("/response_format/json_schema/schema") {
+                generation_config.insert("responseSchema".to_string(), gemini_schema(schema));
+            }
+        }
+        _ => {}
+    }
+    if !generation_config.is_empty() {
+        request.insert(
+            "generationConfig".to_string(),
+            Value::Object(generation_config),
+        );
+    }
+
+    let declarations: Vec<Value> = payload
+        .get("tools")
+        .and_then(|t| t.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|tool| {
+            let function = tool.get("function")?;
+            let mut declaration = json!({ "name": function.get("name")? });
+            if let Some(description) = function.get("description").filter(|d| d.is_string()) {
+                declaration["description"] = description.clone();
+            }
+            // Gemini rejects an empty object schema; a function without parameters
+            // simply declares none
+            if let Some(parameters) = function
+                .get("parameters")
+                .filter(|p| p.get("properties").is_some_and(|props| props != &json!({})))
+            {
+                declaration["parameters"] = gemini_schema(parameters);
+            }
+            Some(declaration)
+        })
+        .collect();
+    if !declarations.is_empty() {
+        request.insert(
+            "tools".to_string(),
+            json!([{ "functionDeclarations": declarations }]),
+        );
+        if let Some(config) = payload.get("tool_choice").and_then(tool_config) {
+            request.insert("toolConfig".to_string(), config);
+        }
+    }
+
+    Value::Object(request)
+}
+
+/// A JSON schema without the keywords Gemini's OpenAPI subset rejects
+fn gemini_schema(schema: &Value) -> Value {
+    match schema {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(key, _)| !matches!(key.as_str(), "$schema" | "additionalProperties"))
+                .map(|(key, value)| (key.clone(), gemini_schema(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(gemini_schema).collect()),
+        other => other.clone(),
+    }
+}
+
+fn tool_config(choice: &Value) -> Option<Value> {
+    let config = match choice {
+        Value::String(mode) => match mode.as_str() {
+            "auto" => json!({ "mode": "AUTO" }),
+            "required" => json!({ "mode": "ANY" }),
+            "none" => json!({ "mode": "NONE" }),
+            _ => return None,
+        },
+        _ => json!({
+            "mode": "ANY",
+            "allowedFunctionNames": [choice.pointer("/function/name")?],
+        }),
+    };
+    Some(json!({ "functionCallingConfig": config }))
+}
+
+/// Text parts, and image parts for `data:` URLs (sent inline) or plain URLs (fetched
+/// by Gemini)
+fn user_parts(content: Option<&Value>) -> Vec<Value> {
+    content_parts(content)
+        .into_iter()
+        .map(|part| match part {
+            ContentPart::Text(text) => json!({ "text": text }),
+            ContentPart::Image(url) => match inline_image(url) {
+                Some((mime_type, data)) => json!({
+                    "inlineData": { "mimeType": mime_type, "data": data },
+                }),
+                None => json!({ "fileData": { "fileUri": url } }),
+            },
+        })
+        .collect()
+}
+
+fn model_parts(message: &Value) -> Vec<Value> {
+    let mut parts = user_parts(message.get("content"));
+    for call in message
+        .get("tool_calls")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+    {
+        parts.push(json!({ "functionCall": {
+            "name": call.pointer("/function/name").cloned().unwrap_or(Value::Null),
+            "args": tool_call_arguments(call),
+        }}));
+    }
+    parts
+}
+
+fn finish_reason(reason: Option<&str>, has_tool_calls: bool) -> Value {
+    match reason {
+        None => Value::Null,
+        Some(_) if has_tool_calls => json!("tool_calls"),
+        Some("MAX_TOKENS") => json!("length"),
+        Some("SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII") => {
+            json!("content_filter")
+        }
+        Some(_) => json!("stop"),
+    }
+}
+
+fn usage_of(usage: &Value) -> Value {
+    let count = |key: &str| usage.get(key).and_then(|t| t.as_u64()).unwrap_or(0);
+    // Thinking tokens are billed as output
+    chat_usage(
+        count("promptTokenCount"),
+        count("candidatesTokenCount") + count("thoughtsTokenCount"),
+    )
+}
+
+/// Text, thought text and function calls of a response's first candidate
+struct CandidateParts {
+    text: String,
+    reasoning: String,
+    function_calls: Vec<Value>,
+}
+
+fn candidate_parts(response: &Value) -> CandidateParts {
+    let mut parts = CandidateParts {
+        text: String::new(),
+        reasoning: String::new(),
+        function_calls: Vec::new(),
+    };
+    for part in response
+        .pointer("/candidates/0/content/parts")
+        .and_then(|p| p.as_array())
+        .into_iter()
+        .flatten()
+    {
+        if let Some(call) = part.get("functionCall") {
+            parts.function_calls.push(call.clone());
+        } else if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+            if part.get("thought").and_then(|t| t.as_bool()) == Some(true) {
+                parts.reasoning.push_str(text);
+            } else {
+                parts.text.push_str(text);
+            }
+        }
+    }
+    parts
+}
+
+/// Chat completion tool call for a Gemini function call; Gemini only sometimes gives
+/// calls an id, so the others get one from their position
+fn tool_call(call: &Value, index: usize) -> Value {
+    let id = call
+        .get("id")
+        .and_then(|id| id.as_str())
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| format!("call_{}", index));
+    json!({
+        "id": id,
+        "type": "function",
+        "function": {
+            "name": call["name"],
+            "arguments": call.get("args").unwrap_or(&json!({})).to_string(),
+        },
+    })
+}
+
+/// Convert a `generateContent` response into a `chat.completion` response
+pub fn response_to_chat_completion(response: &Value) -> Value {
+    let parts = candidate_parts(response);
+    let tool_calls: Vec<Value> = parts
+        .function_calls
+        .iter()
+        .enumerate()
+        .map(|(index, call)| tool_call(call, index))
+        .collect();
+
+    let mut message = json!({ "role": "assistant", "content": parts.text });
+    if !parts.reasoning.is_empty() {
+        message["reasoning_content"] = json!(parts.reasoning);
+    }
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = json!(tool_calls);
+    }
+    // A prompt Gemini blocked has no candidates at all
+    let reason = response
+        .pointer("/candidates/0/finishReason")
+        .and_then(|r| r.as_str())
+        .or_else(|| {
+            response
+                .pointer("/promptFeedback/blockReason")
+                .map(|_| "SAFETY")
+        });
+
+    json!({
+        "id": response.get("responseId").cloned().unwrap_or(Value::Null),
+        "object": "chat.completion",
+        "created": now(),
+        "model": response.get("modelVersion").cloned().unwrap_or(Value::Null),
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason(reason, !tool_calls.is_empty()),
+        }],
+        "usage": usage_of(response.get("usageMetadata").unwrap_or(&json!({}))),
+    })
+}
+
+/// Rewrites a `streamGenerateContent` SSE stream into chat completion chunks
+///
+/// Each `data:` line carries a whole partial response; function calls arrive complete
+/// in one of them.
+#[derive(Default)]
+pub struct GenerateContentStreamConverter {
+    lines: SseData,
+    header: ChunkHeader,
+    started: bool,
+    tool_calls: usize,
+}
+
+impl GenerateContentStreamConverter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn convert_response(&mut self, response: &Value) -> Vec<String> {
+        if let Some(error) = response.get("error") {
+            return chat_translation::error_chunks(error.clone());
+        }
+
+        let mut chunks = Vec::new();
+        if !self.started {
+            self.started = true;
+            self.header.id = response.get("responseId").cloned().unwrap_or(Value::Null);
+            self.header.model = response.get("modelVersion").cloned().unwrap_or(Value::Null);
+            chunks.push(
+                self.header
+                    .chunk(json!({ "role": "assistant", "content": "" }), Value::Null),
+            );
+        }
+
+        let parts = candidate_parts(response);
+        if !parts.reasoning.is_empty() {
+            chunks.push(
+                self.header
+                    .chunk(json!({ "reasoning_content": parts.reasoning }), Value::Null),
+            );
+        }
+        if !parts.text.is_empty() {
+            chunks.push(
+                self.header
+                    .chunk(json!({ "content": parts.text }), Value::Null),
+            );
+        }
+        for call in &parts.function_calls {
+            let mut call = tool_call(call, self.tool_calls);
+            call["index"] = json!(self.tool_calls);
+            self.tool_calls += 1;
+            chunks.push(
+                self.header
+                    .chunk(json!({ "tool_calls": [call] }), Value::Null),
+            );
+        }
+
+        let reason = response
+            .pointer("/candidates/0/finishReason")
+            .and_then(|r| r.as_str())
+            .or_else(|| {
+                response
+                    .pointer("/promptFeedback/blockReason")
+                    .map(|_| "SAFETY")
+            });
+        if reason.is_some() {
+            chunks.push(
+                self.header
+                    .chunk(json!({}), finish_reason(reason, self.tool_calls > 0)),
+            );
+            if let Some(usage) = response.get("usageMetadata") {
+                chunks.push(self.header.usage_chunk(usage_of(usage)));
+            }
+            chunks.push(DONE.to_string());
+        }
+        chunks
+    }
+}
+
+impl ChunkConverter for GenerateContentStreamConverter {
+    fn push(&mut self, bytes: &[u8]) -> String {
+        self.lines
+            .push(bytes)
+            .iter()
+            .flat_map(|response| self.convert_response(response))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_gemini() {
+        assert!(is_gemini(&json!({ "api_type": "gemini" })));
+        assert!(!is_gemini(&json!({ "api_type": "anthropic" })));
+        assert!(!is_gemini(&json!({})));
+    }
+
+    #[test]
+    fn test_to_generate_content_payload() {
+        let payload = json!({
+            "model": "gemini-2.5-flash",
+            "stream": true,
+            "temperature": 0.2,
+            "max_tokens": 512,
+            "stop": ["END"],
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": [
+                    { "type": "text", "text": "What's in this image and the weather?" },
+                    { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBO" } },
+                ]},
+                { "role": "assistant", "content": "", "tool_calls": [{
+                    "id": "call_0",
+                    "type": "function",
+                    "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" },
+                }]},
+                { "role": "tool", "tool_call_id": "call_0", "content": "Sunny" },
+                { "role": "user", "content": "Thanks" },
+            ],
+            "tools": [{ "type": "function", "function": {
+                "name": "get_weather",
+                "description": "Current weather",
+                "parameters": {
+                    "type": "object",
+                    "properties": { "city": { "type": "string" } },
+                    "additionalProperties": false,
+                },
+            }}],
+            "tool_choice": { "type": "function", "function": { "name": "get_weather" } },
+        });
+
+        let request = to_generate_content_payload(&payload);
+        assert_eq!(
+            request["systemInstruction"],
+            json!({ "parts": [{ "text": "Be brief." }] })
+        );
+        assert_eq!(
+            request["generationConfig"],
+            json!({ "temperature": 0.2, "maxOutputTokens": 512, "stopSequences": ["END"] })
+        );
+
+        let contents = request["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 3);
+        assert_eq!(contents[0]["role"], "user");
+        assert_eq!(
+            contents[0]["parts"][1],
+            json!({ "inlineData": { "mimeType": "image/png", "data": "iVBO" } })
+        );
+        assert_eq!(contents[1]["role"], "model");
+        assert_eq!(
+            contents[1]["parts"],
+            json!([{ "functionCall": { "name": "get_weather", "args": { "city": "Paris" } } }])
+        );
+        // The function response and the next user message share one user turn
+        assert_eq!(
+            contents[2],
+            json!({ "role": "user", "parts": [
+                { "functionResponse": {
+                    "name": "get_weather",
+                    "response": { "content": "Sunny" },
+                }},
+                { "text": "Thanks" },
+            ]})
+        );
+
+        assert_eq!(
+            request["tools"],
+            json!([{ "functionDeclarations": [{
+                "name": "get_weather",
+                "description": "Current weather",
+                "parameters": { "type": "object", "properties": { "city": { "type": "string" } } },
+            }]}])
+        );
+        assert_eq!(
+            request["toolConfig"],
+            json!({ "functionCallingConfig": {
+                "mode": "ANY",
+                "allowedFunctionNames": ["get_weather"],
+            }})
+        );
+    }
+
+    #[test]
+    fn test_response_to_chat_completion() {
+        let response = json!({
+            "responseId": "resp_1",
+            "modelVersion": "gemini-2.5-flash",
+            "candidates": [{
+                "content": { "role": "model", "parts": [
+                    { "text": "Let me think.", "thought": true },
+                    { "text": "Checking." },
+                    { "functionCall": { "name": "get_weather", "args": { "city": "Paris" } } },
+                ]},
+                "finishReason": "STOP",
+            }],
+            "usageMetadata": {
+                "promptTokenCount": 12,
+                "candidatesTokenCount": 8,
+                "thoughtsTokenCount": 4,
+                "totalTokenCount": 24,
+            },
+        });
+
+        let completion = response_to_chat_completion(&response);
+        assert_eq!(completion["id"], "resp_1");
+        let message = &completion["choices"][0]["message"];
+        assert_eq!(message["content"], "Checking.");
+        assert_eq!(message["reasoning_content"], "Let me think.");
+        assert_eq!(completion["choices"][0]["finish_reason"], "tool_calls");
+        let call = &message["tool_calls"][0];
+        assert_eq!(call["id"], "call_0");
+        assert_eq!(call["function"]["name"], "get_weather");
+        assert_eq!(
+            serde_json::from_str::<Value>(call["function"]["arguments"].as_str().unwrap()).unwrap(),
+            json!({ "city": "Paris" })
+        );
+        assert_eq!(completion["usage"]["completion_tokens"], 12);
+        assert_eq!(completion["usage"]["total_tokens"], 24);
+
+        // A blocked prompt has no candidates
+        let blocked = json!({ "promptFeedback": { "blockReason": "SAFETY" } });
+        let completion = response_to_chat_completion(&blocked);
+        assert_eq!(completion["choices"][0]["message"]["content"], "");
+        assert_eq!(completion["choices"][0]["finish_reason"], "content_filter");
+    }
+
+    #[test]
+    fn test_to_model_list() {
+        let models = json!({ "models": [
+            {
+                "name": "models/gemini-2.5-flash",
+                "displayName": "Gemini 2.5 Flash",
+                "supportedGenerationMethods": ["generateContent", "countTokens"],
+            },
+            {
+                "name": "models/text-embedding-004",
+                "displayName": "Text Embedding 004",
+                "supportedGenerationMethods": ["embedContent"],
+            },
+        ]});
+
+        let list = to_model_list(&models);
+        let data = list["data"].as_array().unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0]["id"], "gemini-2.5-flash");
+        assert_eq!(data[0]["name"], "Gemini 2.5 Flash");
+        assert_eq!(data[0]["owned_by"], "google");
+        assert_eq!(data[0]["tags"], json!([{ "name": "gemini" }]));
+    }
+
+    fn data_lines(output: &str) -> Vec<Value> {
+        output
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_stream_conversion() {
+        let stream = concat!(
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":",
+            "[{\"text\":\"Hel\"}]}}],\"responseId\":\"resp_1\",",
+            "\"modelVersion\":\"gemini-2.5-flash\"}\r\n\r\n",
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":",
+            "[{\"text\":\"lo\"}]}}]}\r\n\r\n",
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":",
+            "[{\"functionCall\":{\"name\":\"get_weather\",\"args\":{\"city\":\"Paris\"}}}]},",
+            "\"finishReason\":\"STOP\"}],",
+            "\"usageMetadata\":{\"promptTokenCount\":10,\"candidatesTokenCount\":5}}\r\n\r\n",
+        );
+
+        // Split mid-line to exercise buffering
+        let mut converter = GenerateContentStreamConverter::new();
+        let mut output = String::new();
+        for piece in stream.as_bytes().chunks(37) {
+            output.push_str(&converter.push(piece));
+        }
+        assert!(output.ends_with("data: [DONE]\n\n"));
+
+        let chunks = data_lines(&output);
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[0]["id"], "resp_1");
+        let text: String = chunks
+            .iter()
+            .filter_map(|c| {
+                c.pointer("/choices/0/delta/content")
+                    .and_then(|t| t.as_str())
+            })
+            .collect();
+        assert_eq!(text, "Hello");
+
+        let call = &chunks[3]["choices"][0]["delta"]["tool_calls"][0];
+        assert_eq!(call["index"], 0);
+        assert_eq!(call["id"], "call_0");
+        assert_eq!(call["function"]["name"], "get_weather");
+        assert_eq!(call["function"]["arguments"], "{\"city\":\"Paris\"}");
+        assert_eq!(chunks[4]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(chunks[5]["usage"]["prompt_tokens"], 10);
+        assert_eq!(chunks[5]["usage"]["completion_tokens"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_into_chat_completion_response() {
+        let generated = json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": "Hi" }] },
+                "finishReason": "STOP",
+            }],
+        });
+        let upstream = http::Response::builder()
+            .header("content-type", "application/json")
+            .body(generated.to_string())
+            .unwrap();
+        let response = into_chat_completion_response(reqwest::Response::from(upstream))
+            .await
+            .unwrap();
+        let completion: Value = response.json().await.unwrap();
+        assert_eq!(completion["choices"][0]["message"]["content"], "Hi");
+        assert_eq!(completion["choices"][0]["finish_reason"], "stop");
+
+        // Errors keep their status and body
+        let upstream = http::Response::builder()
+            .status(400)
+            .body("{\"error\":{\"code\":400}}")
+            .unwrap();
+        let response = into_chat_completion_response(reqwest::Response::from(upstream))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        assert_eq!(response.text().await.unwrap(), "{\"error\":{\"code\":400}}");
+    }
+}
@@ -0,0 +1,365 @@
+use reqwest::{Certificate, Client, ClientBuilder, Identity, NoProxy, Proxy};
+
+use crate::config::Config;
+
+/// Client builder for upstream requests: LLM endpoints, embeddings, web search,
+/// pipelines, webhooks, ...
+///
+/// Plain HTTP requests go through `HTTP_PROXY` and HTTPS requests through `HTTPS_PROXY`,
+/// except to hosts matching `NO_PROXY`. reqwest's own reading of the environment is
+/// turned off, so the config is the only source of proxy settings.
+///
+/// Upstream certificates are checked against the system roots plus `UPSTREAM_CA_BUNDLE`,
+/// and `UPSTREAM_CLIENT_CERT`/`UPSTREAM_CLIENT_KEY` are presented to servers asking for a
+/// client certificate. Fails if any of those files can't be loaded.
+pub fn builder(config: &Config) -> anyhow::Result<ClientBuilder> {
+    let mut builder = Client::builder().no_proxy();
+    let no_proxy = NoProxy::from_string(&config.no_proxy.join(","));
+    if let Some(url) = &config.http_proxy {
+        builder = builder.proxy(Proxy::http(url.as_str())?.no_proxy(no_proxy.clone()));
+    }
+    if let Some(url) = &config.https_proxy {
+        builder = builder.proxy(Proxy::https(url.as_str())?.no_proxy(no_proxy));
+    }
+
+    for cert in ca_bundle(config)? {
+        builder = builder.add_root_certificate(cert);
+    }
+    if let Some(identity) = client_identity(config)? {
+        builder = builder.identity(identity);
+    }
+    if config.upstream_tls_insecure_skip_verify {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder)
+}
+
+/// Upstream client with default settings, for code without the shared client in `AppState`
+pub fn client(config: &Config) -> Client {
+    match builder(config).and_then(|builder| Ok(builder.build()?)) {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!("Failed to apply the upstream client settings: {}", e);
+            Client::new()
+        }
+    }
+}
+
+/// Load the upstream TLS files once, so a bad path stops the server at startup
+pub fn check_tls(config: &Config) -> anyhow::Result<()> {
+    ca_bundle(config)?;
+    client_identity(config)?;
+    if config.upstream_tls_insecure_skip_verify {
+        tracing::warn!(
+            "⚠️  UPSTREAM_TLS_INSECURE_SKIP_VERIFY=true: upstream certificates are NOT \
+             verified, anyone on the network path can read and alter upstream traffic"
+        );
+    }
+    Ok(())
+}
+
+fn ca_bundle(config: &Config) -> anyhow::Result<Vec<Certificate>> {
+    let path = match &config.upstream_ca_bundle {
+        Some(path) => path,
+        None => return Ok(Vec::new()),
+    };
+    let pem = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read UPSTREAM_CA_BUNDLE {:?}: {}", path, e))?;
+    let certs = Certificate::from_pem_bundle(&pem)
+        .map_err(|e| anyhow::anyhow!("Invalid UPSTREAM_CA_BUNDLE {:?}: {}", path, e))?;
+    if certs.is_empty() {
+        anyhow::bail!("UPSTREAM_CA_BUNDLE {:?} contains no certificates", path);
+    }
+    Ok(certs)
+}
+
+fn client_identity(config: &Config) -> anyhow::Result<Option<Identity>> {
+    let (cert_file, key_file) = match (&config.upstream_client_cert, &config.upstream_client_key) {
+        (Some(cert_file), Some(key_file)) => (cert_file, key_file),
+        (None, None) => return Ok(None),
+        _ => anyhow::bail!("UPSTREAM_CLIENT_CERT and UPSTREAM_CLIENT_KEY must be set together"),
+    };
+    let cert = std::fs::read(cert_file).map_err(|e| {
+        anyhow::anyhow!("Failed to read UPSTREAM_CLIENT_CERT {:?}: {}", cert_file, e)
+    })?;
+    let key = std::fs::read(key_file)
+        .map_err(|e| anyhow::anyhow!("Failed to read UPSTREAM_CLIENT_KEY {:?}: {}", key_file, e))?;
+    let identity = Identity::from_pkcs8_pem(&cert, &key).map_err(|e| {
+        anyhow::anyhow!(
+            "Invalid UPSTREAM_CLIENT_CERT {:?} / UPSTREAM_CLIENT_KEY {:?} \
+             (the key must be PKCS#8 PEM): {}",
+            cert_file,
+            key_file,
+            e
+        )
+    })?;
+    Ok(Some(identity))
+}
+
+/// Whether any upstream request goes through a proxy
+pub fn proxy_enabled(config: &Config) -> bool {
+    config.http_proxy.is_some() || config.https_proxy.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::start_forward_proxy;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use futures_util::StreamExt;
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use rustls::crypto::ring::default_provider;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+    use rustls::server::WebPkiClientVerifier;
+    use std::net::SocketAddr;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    async fn stream() -> HttpResponse {
+        let chunks = futures_util::stream::iter(0..3).then(|i| async move {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            Ok::<_, actix_web::Error>(web::Bytes::from(format!("data: {}\n\n", i)))
+        });
+        HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .streaming(chunks)
+    }
+
+    async fn start_upstream() -> (SocketAddr, actix_web::dev::ServerHandle) {
+        let server = HttpServer::new(|| App::new().route("/stream", web::get().to(stream)))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        (addr, handle)
+    }
+
+    async fn read_stream(client: &Client, url: &str) -> (usize, String) {
+        let response = client.get(url).send().await.unwrap();
+        assert!(response.status().is_success());
+        let mut chunks = 0;
+        let mut body = Vec::new();
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            chunks += 1;
+            body.extend_from_slice(&chunk.unwrap());
+        }
+        (chunks, String::from_utf8(body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_streaming_through_proxy() {
+        let (upstream, handle) = start_upstream().await;
+        let (proxy, connections) = start_forward_proxy().await;
+
+        let mut config = Config::from_env().unwrap();
+        config.http_proxy = Some(format!("http://{}", proxy));
+        config.https_proxy = None;
+        config.no_proxy = Vec::new();
+        assert!(proxy_enabled(&config));
+
+        let client = builder(&config).unwrap().build().unwrap();
+        let (chunks, body) = read_stream(&client, &format!("http://{}/stream", upstream)).await;
+        assert_eq!(body, "data: 0\n\ndata: 1\n\ndata: 2\n\n");
+        assert!(chunks >= 1);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+        handle.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_no_proxy_hosts_are_reached_directly() {
+        let (upstream, handle) = start_upstream().await;
+        let (proxy, connections) = start_forward_proxy().await;
+
+        let mut config = Config::from_env().unwrap();
+        config.http_proxy = Some(format!("http://{}", proxy));
+        config.https_proxy = None;
+        config.no_proxy = vec!["localhost".to_string(), "127.0.0.0/8".to_string()];
+
+        let direct = client(&config);
+        let (_, body) = read_stream(&direct, &format!("http://{}/stream", upstream)).await;
+        assert_eq!(body, "data: 0\n\ndata: 1\n\ndata: 2\n\n");
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+
+        // Without a proxy configured nothing goes through one, whatever the environment says
+        config.http_proxy = None;
+        assert!(!proxy_enabled(&config));
+        let unproxied = client(&config);
+        read_stream(&unproxied, &format!("http://{}/stream", upstream)).await;
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+
+        handle.stop(false).await;
+    }
+
+    /// A private CA, with a server certificate for 127.0.0.1 and a client certificate
+    struct Pki {
+        ca_pem: String,
+        server: (CertificateDer<'static>, PrivateKeyDer<'static>),
+        client_cert_pem: String,
+        client_key_pem: String,
+    }
+
+    fn pki() -> Pki {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "Test Internal CA");
+        let ca = params.self_signed(&ca_key).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["127.0.0.1".to_string()]).unwrap();
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        let server = params.signed_by(&server_key, &ca, &ca_key).unwrap();
+
+        let client_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["open-webui".to_string()]).unwrap();
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let client = params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+        Pki {
+            ca_pem: ca.pem(),
+            server: (
+                server.der().clone(),
+                PrivatePkcs8KeyDer::from(server_key.serialize_der()).into(),
+            ),
+            client_cert_pem: client.pem(),
+            client_key_pem: client_key.serialize_pem(),
+        }
+    }
+
+    /// HTTPS upstream serving the PKI's server certificate, optionally requiring a client
+    /// certificate issued by its CA
+    async fn start_tls_upstream(
+        pki: &Pki,
+        require_client_cert: bool,
+    ) -> (SocketAddr, actix_web::dev::ServerHandle) {
+        let provider = Arc::new(default_provider());
+        let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .unwrap();
+        let builder = if require_client_cert {
+            let mut roots = rustls::RootCertStore::empty();
+            for cert in CertificateDer::pem_slice_iter(pki.ca_pem.as_bytes()) {
+                roots.add(cert.unwrap()).unwrap();
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .unwrap();
+            builder.with_client_cert_verifier(verifier)
+        } else {
+            builder.with_no_client_auth()
+        };
+        let (cert, key) = (pki.server.0.clone(), pki.server.1.clone_key());
+        let tls = builder.with_single_cert(vec![cert], key).unwrap();
+
+        let server = HttpServer::new(|| App::new().route("/stream", web::get().to(stream)))
+            .workers(1)
+            .bind_rustls_0_23(("127.0.0.1", 0), tls)
+            .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        (addr, handle)
+    }
+
+    fn tls_config() -> Config {
+        let mut config = Config::from_env().unwrap();
+        config.http_proxy = None;
+        config.https_proxy = None;
+        config.upstream_ca_bundle = None;
+        config.upstream_client_cert = None;
+        config.upstream_client_key = None;
+        config.upstream_tls_insecure_skip_verify = false;
+        config
+    }
+
+    #[actix_web::test]
+    async fn test_custom_ca_bundle() {
+        let pki = pki();
+        let dir = tempfile::tempdir().unwrap();
+        let ca_file = dir.path().join("ca.pem");
+        std::fs::write(&ca_file, &pki.ca_pem).unwrap();
+        let (upstream, handle) = start_tls_upstream(&pki, false).await;
+        let url = format!("https://{}/stream", upstream);
+
+        // The private CA is unknown to the system roots
+        let mut config = tls_config();
+        let system_only = builder(&config).unwrap().build().unwrap();
+        assert!(system_only.get(&url).send().await.is_err());
+
+        config.upstream_ca_bundle = Some(ca_file.to_string_lossy().to_string());
+        check_tls(&config).unwrap();
+        let (_, body) = read_stream(&client(&config), &url).await;
+        assert_eq!(body, "data: 0\n\ndata: 1\n\ndata: 2\n\n");
+
+        // Skipping verification gets through without the bundle too
+        config.upstream_ca_bundle = None;
+        config.upstream_tls_insecure_skip_verify = true;
+        read_stream(&client(&config), &url).await;
+
+        handle.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_client_certificate() {
+        let pki = pki();
+        let dir = tempfile::tempdir().unwrap();
+        let ca_file = dir.path().join("ca.pem");
+        let cert_file = dir.path().join("client.pem");
+        let key_file = dir.path().join("client-key.pem");
+        std::fs::write(&ca_file, &pki.ca_pem).unwrap();
+        std::fs::write(&cert_file, &pki.client_cert_pem).unwrap();
+        std::fs::write(&key_file, &pki.client_key_pem).unwrap();
+        let (upstream, handle) = start_tls_upstream(&pki, true).await;
+        let url = format!("https://{}/stream", upstream);
+
+        let mut config = tls_config();
+        config.upstream_ca_bundle = Some(ca_file.to_string_lossy().to_string());
+        let anonymous = builder(&config).unwrap().build().unwrap();
+        assert!(anonymous.get(&url).send().await.is_err());
+
+        config.upstream_client_cert = Some(cert_file.to_string_lossy().to_string());
+        config.upstream_client_key = Some(key_file.to_string_lossy().to_string());
+        check_tls(&config).unwrap();
+        let (_, body) = read_stream(&client(&config), &url).await;
+        assert_eq!(body, "data: 0\n\ndata: 1\n\ndata: 2\n\n");
+
+        handle.stop(false).await;
+    }
+
+    #[test]
+    fn test_unloadable_tls_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let garbage = dir.path().join("garbage.pem");
+        std::fs::write(&garbage, "not a certificate").unwrap();
+        let missing = dir.path().join("missing.pem");
+
+        let mut config = tls_config();
+        config.upstream_ca_bundle = Some(missing.to_string_lossy().to_string());
+        let err = check_tls(&config).unwrap_err().to_string();
+        assert!(err.contains("UPSTREAM_CA_BUNDLE"), "{}", err);
+        assert!(builder(&config).is_err());
+
+        config.upstream_ca_bundle = Some(garbage.to_string_lossy().to_string());
+        assert!(check_tls(&config).is_err());
+
+        config.upstream_ca_bundle = None;
+        config.upstream_client_cert = Some(garbage.to_string_lossy().to_string());
+        let err = check_tls(&config).unwrap_err().to_string();
+        assert!(err.contains("must be set together"), "{}", err);
+
+        config.upstream_client_key = Some(garbage.to_string_lossy().to_string());
+        let err = check_tls(&config).unwrap_err().to_string();
+        assert!(err.contains("UPSTREAM_CLIENT_CERT"), "{}", err);
+    }
+}
@@ -0,0 +1,211 @@
+use actix_web::web;
+use base64::{engine::general_purpose, Engine};
+use image::codecs::jpeg::JpegEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use std::io::Cursor;
+
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+use crate::utils::model_images::decode_data_url;
+
+/// JPEG quality used when re-encoding photos
+const JPEG_QUALITY: u8 = 85;
+
+/// Box an image must fit in, from FILE_IMAGE_COMPRESSION_WIDTH/HEIGHT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageBounds {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageBounds {
+    /// Configured bounds; an unset side is left unconstrained. `None` when both are off.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        match (
+            config.file_image_compression_width,
+            config.file_image_compression_height,
+        ) {
+            (None, None) => None,
+            (width, height) => Some(ImageBounds {
+                width: width.unwrap_or(u32::MAX),
+                height: height.unwrap_or(u32::MAX),
+            }),
+        }
+    }
+}
+
+/// A re-encoded image and its content type
+#[derive(Debug)]
+pub struct CompressedImage {
+    pub bytes: Vec<u8>,
+    pub content_type: &'static str,
+}
+
+/// Downscale an image to fit `bounds` and apply its EXIF orientation
+///
+/// The aspect ratio is kept, JPEGs are re-encoded at quality 85 and other formats as
+/// PNG so transparency survives. Returns `None`, meaning the original should be kept,
+/// for non-images, animated GIFs, and images already within bounds with no rotation.
+pub fn compress_image(
+    bytes: &[u8],
+    content_type: &str,
+    bounds: ImageBounds,
+) -> AppResult<Option<CompressedImage>> {
+    let format = match content_type {
+        "image/png" => ImageFormat::Png,
+        "image/jpeg" | "image/jpg" => ImageFormat::Jpeg,
+        "image/webp" => ImageFormat::WebP,
+        "image/bmp" => ImageFormat::Bmp,
+        // GIFs may be animated, which re-encoding would flatten
+        _ => return Ok(None),
+    };
+
+    let invalid = |e: image::ImageError| AppError::BadRequest(format!("Invalid image: {}", e));
+    let mut decoder = ImageReader::with_format(Cursor::new(bytes), format)
+        .into_decoder()
+        .map_err(invalid)?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let (width, height) = decoder.dimensions();
+    let oversized = width > bounds.width || height > bounds.height;
+    if !oversized && orientation == Orientation::NoTransforms {
+        return Ok(None);
+    }
+
+    let mut image = DynamicImage::from_decoder(decoder).map_err(invalid)?;
+    image.apply_orientation(orientation);
+    if image.width() > bounds.width || image.height() > bounds.height {
+        image = image.resize(
+            bounds.width,
+            bounds.height,
+            image::imageops::FilterType::Lanczos3,
+        );
+    }
+
+    let mut out = Cursor::new(Vec::new());
+    let content_type = if format == ImageFormat::Jpeg {
+        let encoder = JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY);
+        image
+            .to_rgb8()
+            .write_with_encoder(encoder)
+            .map_err(|e| AppError::InternalServerError(format!("Failed to encode image: {}", e)))?;
+        "image/jpeg"
+    } else {
+        image
+            .write_to(&mut out, ImageFormat::Png)
+            .map_err(|e| AppError::InternalServerError(format!("Failed to encode image: {}", e)))?;
+        "image/png"
+    };
+
+    Ok(Some(CompressedImage {
+        bytes: out.into_inner(),
+        content_type,
+    }))
+}
+
+/// Run a compression on the blocking thread pool, as decoding and resizing a large image
+/// takes long enough to stall the async workers serving other requests
+pub async fn compress_blocking<T, F>(compress: F) -> AppResult<T>
+where
+    F: FnOnce() -> AppResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(compress)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Image compression failed: {}", e)))?
+}
+
+/// [`compress_image`] for a `data:image/...;base64,` URL, as sent for avatars
+///
+/// Other URLs, and images that need no change, are returned as they are.
+pub fn compress_data_url(url: &str, bounds: ImageBounds) -> AppResult<String> {
+    let (content_type, bytes) = match decode_data_url(url) {
+        Some(decoded) if decoded.0.starts_with("image/") => decoded,
+        _ => return Ok(url.to_string()),
+    };
+    match compress_image(&bytes, &content_type, bounds)? {
+        Some(image) => Ok(format!(
+            "data:{};base64,{}",
+            image.content_type,
+            general_purpose::STANDARD.encode(image.bytes)
+        )),
+        None => Ok(url.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, RgbaImage};
+
+    const BOUNDS: ImageBounds = ImageBounds {
+        width: 1024,
+        height: 1024,
+    };
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        RgbaImage::new(width, height)
+            .write_to(&mut out, ImageFormat::Png)
+            .unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn test_large_png_is_capped() {
+        let compressed = compress_image(&png(3000, 1500), "image/png", BOUNDS)
+            .unwrap()
+            .unwrap();
+        assert_eq!(compressed.content_type, "image/png");
+
+        let stored = image::load_from_memory(&compressed.bytes).unwrap();
+        assert_eq!(stored.dimensions(), (1024, 512));
+    }
+
+    #[test]
+    fn test_small_and_non_images_are_kept() {
+        assert!(compress_image(&png(200, 100), "image/png", BOUNDS)
+            .unwrap()
+            .is_none());
+        assert!(compress_image(b"%PDF-1.7", "application/pdf", BOUNDS)
+            .unwrap()
+            .is_none());
+        assert!(compress_image(b"not a png", "image/png", BOUNDS).is_err());
+    }
+
+    #[test]
+    fn test_avatar_data_url() {
+        let url = format!(
+            "data:image/png;base64,{}",
+            general_purpose::STANDARD.encode(png(2048, 2048))
+        );
+        let compressed = compress_data_url(&url, BOUNDS).unwrap();
+        let (content_type, bytes) = decode_data_url(&compressed).unwrap();
+        assert_eq!(content_type, "image/png");
+        assert_eq!(
+            image::load_from_memory(&bytes).unwrap().dimensions(),
+            (1024, 1024)
+        );
+
+        assert_eq!(compress_data_url("/user.png", BOUNDS).unwrap(), "/user.png");
+    }
+
+    #[actix_web::test]
+    async fn test_compression_runs_off_the_async_worker() {
+        let worker = std::thread::current().id();
+        let (thread, compressed) = compress_blocking(move || {
+            let compressed = compress_image(&png(3000, 1500), "image/png", BOUNDS)?;
+            Ok((std::thread::current().id(), compressed))
+        })
+        .await
+        .unwrap();
+        assert_ne!(thread, worker);
+        assert!(compressed.is_some());
+
+        assert!(
+            compress_blocking(|| compress_image(b"not a png", "image/png", BOUNDS))
+                .await
+                .is_err()
+        );
+    }
+}
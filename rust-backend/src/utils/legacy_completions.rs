@@ -0,0 +1,267 @@
+use serde_json::{json, Map, Value};
+
+use crate::error::{AppError, AppResult};
+
+/// Sampling parameters shared by the legacy and chat completion APIs
+const SHARED_PARAMS: &[&str] = &[
+    "max_tokens",
+    "temperature",
+    "top_p",
+    "n",
+    "stop",
+    "presence_penalty",
+    "frequency_penalty",
+    "logit_bias",
+    "seed",
+    "user",
+    "stream",
+    "stream_options",
+];
+
+/// The prompt of a legacy completion request; only a single prompt is supported
+pub fn prompt_text(payload: &Value) -> AppResult<String> {
+    match payload.get("prompt") {
+        Some(Value::String(prompt)) => Ok(prompt.clone()),
+        Some(Value::Array(prompts)) => match prompts.as_slice() {
+            [Value::String(prompt)] => Ok(prompt.clone()),
+            _ => Err(AppError::BadRequest(
+                "Only a single string prompt is supported".to_string(),
+            )),
+        },
+        Some(_) => Err(AppError::BadRequest("prompt must be a string".to_string())),
+        None => Err(AppError::BadRequest("prompt is required".to_string())),
+    }
+}
+
+/// Build a chat completion request that sends `prompt` as a single user message
+pub fn to_chat_payload(payload: &Value, prompt: &str) -> Value {
+    let mut chat = Map::new();
+    chat.insert("model".to_string(), payload["model"].clone());
+    chat.insert(
+        "messages".to_string(),
+        json!([{ "role": "user", "content": prompt }]),
+    );
+    for key in SHARED_PARAMS {
+        if let Some(value) = payload.get(*key) {
+            chat.insert(key.to_string(), value.clone());
+        }
+    }
+    Value::Object(chat)
+}
+
+fn completion_choice(choice: &Value, text: String) -> Value {
+    json!({
+        "index": choice.get("index").cloned().unwrap_or(json!(0)),
+        "text": text,
+        "logprobs": null,
+        "finish_reason": choice.get("finish_reason").cloned().unwrap_or(Value::Null),
+    })
+}
+
+/// Convert a `chat.completion` response into a `text_completion` response.
+/// `echo` is prepended to every choice, matching the legacy `echo` parameter.
+pub fn chat_response_to_completion(response: &Value, echo: Option<&str>) -> Value {
+    let choices: Vec<Value> = response
+        .get("choices")
+        .and_then(|c| c.as_array())
+        .map(|choices| {
+            choices
+                .iter()
+                .map(|choice| {
+                    let content = choice
+                        .pointer("/message/content")
+                        .and_then(|c| c.as_str())
+                        .unwrap_or_default();
+                    completion_choice(choice, format!("{}{}", echo.unwrap_or(""), content))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut completion = json!({
+        "id": response.get("id").cloned().unwrap_or(Value::Null),
+        "object": "text_completion",
+        "created": response.get("created").cloned().unwrap_or(Value::Null),
+        "model": response.get("model").cloned().unwrap_or(Value::Null),
+        "choices": choices,
+    });
+    if let Some(usage) = response.get("usage") {
+        completion["usage"] = usage.clone();
+    }
+    completion
+}
+
+/// Rewrites a chat completion SSE stream into legacy completion chunks.
+///
+/// Input arrives in arbitrary byte chunks, so incomplete lines are buffered until their
+/// newline is seen.
+pub struct CompletionStreamConverter {
+    buffer: Vec<u8>,
+    echo: Option<String>,
+}
+
+impl CompletionStreamConverter {
+    pub fn new(echo: Option<String>) -> Self {
+        CompletionStreamConverter {
+            buffer: Vec::new(),
+            echo,
+        }
+    }
+
+    /// Feed upstream bytes and get back the converted output for every complete line
+    pub fn push(&mut self, bytes: &[u8]) -> String {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut output = String::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            output.push_str(&self.convert_line(line.trim_end_matches(['\r', '\n'])));
+        }
+        output
+    }
+
+    fn convert_line(&mut self, line: &str) -> String {
+        let data = match line.strip_prefix("data:") {
+            Some(data) => data.trim(),
+            // Blank separators are re-added after each event; comments pass through
+            None if line.is_empty() => return String::new(),
+            None => return format!("{}\n", line),
+        };
+
+        if data == "[DONE]" {
+            return "data: [DONE]\n\n".to_string();
+        }
+
+        match serde_json::from_str::<Value>(data) {
+            Ok(chunk) => format!("data: {}\n\n", self.convert_chunk(&chunk)),
+            Err(_) => format!("data: {}\n\n", data),
+        }
+    }
+
+    fn convert_chunk(&mut self, chunk: &Value) -> Value {
+        let choices: Vec<Value> = chunk
+            .get("choices")
+            .and_then(|c| c.as_array())
+            .map(|choices| {
+                choices
+                    .iter()
+                    .map(|choice| {
+                        let content = choice
+                            .pointer("/delta/content")
+                            .and_then(|c| c.as_str())
+                            .unwrap_or_default();
+                        let text = match self.echo.take() {
+                            Some(echo) => format!("{}{}", echo, content),
+                            None => content.to_string(),
+                        };
+                        completion_choice(choice, text)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut converted = json!({
+            "id": chunk.get("id").cloned().unwrap_or(Value::Null),
+            "object": "text_completion",
+            "created": chunk.get("created").cloned().unwrap_or(Value::Null),
+            "model": chunk.get("model").cloned().unwrap_or(Value::Null),
+            "choices": choices,
+        });
+        if let Some(usage) = chunk.get("usage").filter(|u| !u.is_null()) {
+            converted["usage"] = usage.clone();
+        }
+        converted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_text() {
+        assert_eq!(prompt_text(&json!({"prompt": "Hi"})).unwrap(), "Hi");
+        assert_eq!(prompt_text(&json!({"prompt": ["Hi"]})).unwrap(), "Hi");
+        assert!(prompt_text(&json!({"prompt": ["a", "b"]})).is_err());
+        assert!(prompt_text(&json!({"prompt": [1, 2]})).is_err());
+        assert!(prompt_text(&json!({})).is_err());
+    }
+
+    #[test]
+    fn test_to_chat_payload() {
+        let payload = json!({
+            "model": "gpt-3.5-turbo-instruct",
+            "prompt": "Say this is a test",
+            "max_tokens": 7,
+            "stream": true,
+            "echo": true,
+            "suffix": "ignored"
+        });
+
+        assert_eq!(
+            to_chat_payload(&payload, "Say this is a test"),
+            json!({
+                "model": "gpt-3.5-turbo-instruct",
+                "messages": [{"role": "user", "content": "Say this is a test"}],
+                "max_tokens": 7,
+                "stream": true
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_response_to_completion() {
+        let response = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1700000000,
+            "model": "m",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": " a test"},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}
+        });
+
+        let completion = chat_response_to_completion(&response, Some("This is"));
+        assert_eq!(completion["object"], "text_completion");
+        assert_eq!(completion["choices"][0]["text"], "This is a test");
+        assert_eq!(completion["choices"][0]["finish_reason"], "stop");
+        assert_eq!(completion["usage"]["total_tokens"], 7);
+    }
+
+    #[test]
+    fn test_stream_converter_handles_split_lines() {
+        let mut converter = CompletionStreamConverter::new(None);
+        let first = r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"m","choices":[{"index":0,"delta":{"content":"Hel"#;
+        let second = "lo\"},\"finish_reason\":null}]}\n\ndata: [DONE]\n\n";
+
+        assert_eq!(converter.push(first.as_bytes()), "");
+        let output = converter.push(second.as_bytes());
+
+        let mut events = output.split("\n\n").filter(|e| !e.is_empty());
+        let chunk: Value =
+            serde_json::from_str(events.next().unwrap().strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(chunk["object"], "text_completion");
+        assert_eq!(chunk["choices"][0]["text"], "Hello");
+        assert_eq!(events.next(), Some("data: [DONE]"));
+    }
+
+    #[test]
+    fn test_stream_converter_echoes_prompt_once() {
+        let mut converter = CompletionStreamConverter::new(Some("Q: ".to_string()));
+        let output = converter.push(
+            b"data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"A\"}}]}\n\
+              data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"B\"}}]}\n",
+        );
+
+        let texts: Vec<String> = output
+            .split("\n\n")
+            .filter_map(|e| e.strip_prefix("data: "))
+            .map(|e| serde_json::from_str::<Value>(e).unwrap()["choices"][0]["text"].to_string())
+            .collect();
+        assert_eq!(texts, vec!["\"Q: A\"", "\"B\""]);
+    }
+}
@@ -159,6 +159,21 @@ pub fn parse_data_url(data_url: &str) -> Option<(String, Vec<u8>)> {
     Some((mime_type, decoded))
 }
 
+/// Text of a message `content`, which is either a string or an array of content parts.
+/// Text parts are joined with newlines; images and other parts are ignored.
+pub fn message_content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter(|part| part.get("type").and_then(|t| t.as_str()) == Some("text"))
+            .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
 /// Truncate string to max length with ellipsis
 #[allow(dead_code)]
 pub fn truncate_string(s: &str, max_len: usize) -> String {
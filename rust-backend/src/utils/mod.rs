@@ -1,16 +1,39 @@
 pub mod access_control;
+pub mod anthropic;
 pub mod auth;
+pub mod branding;
 pub mod cache;
 pub mod chat;
 pub mod chat_completion;
 pub mod chat_middleware;
+pub mod chat_request;
+pub mod chat_translation;
 pub mod embeddings;
+pub mod encryption;
+pub mod gemini;
+pub mod http_client;
+pub mod image_compression;
+pub mod legacy_completions;
+pub mod metrics;
 pub mod misc;
+pub mod model_images;
 pub mod password;
+pub mod permissions;
 pub mod pipeline;
+pub mod prompt_variables;
+pub mod reasoning;
+pub mod redis_health;
+pub mod response_format;
 pub mod retrieval;
+pub mod shutdown;
+pub mod signed_url;
 pub mod tasks;
+pub mod telemetry;
 pub mod template;
 pub mod time;
+pub mod tls;
+pub mod upload;
+pub mod upstream;
 pub mod version;
+pub mod web_search;
 pub mod webhook;
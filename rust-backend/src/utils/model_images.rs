@@ -0,0 +1,204 @@
+use base64::{engine::general_purpose, Engine};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::error::{AppError, AppResult};
+
+/// Largest profile image accepted on create/update/import
+pub const MAX_PROFILE_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Image types stored for model profiles (SVG is rejected since it can carry scripts)
+const PROFILE_IMAGE_TYPES: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+];
+
+/// URL serving a model's profile image; `version` busts browser caches after an update
+pub fn profile_image_url(model_id: &str, version: Option<&str>) -> String {
+    let mut url = format!(
+        "/api/v1/models/model/profile/image?id={}",
+        urlencoding::encode(model_id)
+    );
+    if let Some(version) = version {
+        url.push_str("&v=");
+        url.push_str(version);
+    }
+    url
+}
+
+fn is_stored_image_url(url: &str) -> bool {
+    url.starts_with("/api/v1/models/model/profile/image")
+}
+
+/// Split a `data:image/...;base64,` URL into its content type and decoded bytes
+pub fn decode_data_url(url: &str) -> Option<(String, Vec<u8>)> {
+    let rest = url.strip_prefix("data:")?;
+    let (header, data) = rest.split_once(',')?;
+    let content_type = header.strip_suffix(";base64")?;
+    let bytes = general_purpose::STANDARD.decode(data.trim()).ok()?;
+    Some((content_type.to_lowercase(), bytes))
+}
+
+fn extension_for(content_type: &str) -> Option<&'static str> {
+    PROFILE_IMAGE_TYPES
+        .iter()
+        .find(|(ct, _)| *ct == content_type)
+        .map(|(_, ext)| *ext)
+}
+
+fn image_dir(cache_dir: &str) -> PathBuf {
+    Path::new(cache_dir).join("image").join("models")
+}
+
+/// Model IDs may contain `/` or `:`, so files are named by a hash of the ID
+fn image_stem(model_id: &str) -> String {
+    let digest = Sha256::digest(model_id.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Move an inline base64 profile image out of `meta` into the image cache and point
+/// `meta.profile_image_url` at the serving endpoint instead
+pub fn store_profile_image(cache_dir: &str, model_id: &str, meta: &mut Value) -> AppResult<()> {
+    let data_url = match meta.get("profile_image_url").and_then(|v| v.as_str()) {
+        Some(url) if url.starts_with("data:") => url.to_string(),
+        Some(url) if is_stored_image_url(url) => return Ok(()),
+        _ => {
+            // Image removed or replaced by an external URL
+            delete_profile_image(cache_dir, model_id);
+            return Ok(());
+        }
+    };
+
+    let (content_type, bytes) = decode_data_url(&data_url)
+        .ok_or_else(|| AppError::BadRequest("Invalid profile image data".to_string()))?;
+    let extension = extension_for(&content_type).ok_or_else(|| {
+        AppError::BadRequest(format!("Unsupported profile image type: {}", content_type))
+    })?;
+    if bytes.len() > MAX_PROFILE_IMAGE_BYTES {
+        return Err(AppError::BadRequest(
+            "Profile image is too large".to_string(),
+        ));
+    }
+
+    let dir = image_dir(cache_dir);
+    std::fs::create_dir_all(&dir)?;
+    delete_profile_image(cache_dir, model_id);
+
+    let stem = image_stem(model_id);
+    std::fs::write(dir.join(format!("{}.{}", stem, extension)), &bytes)?;
+
+    let version: String = image_stem(&data_url).chars().take(8).collect();
+    meta["profile_image_url"] = json!(profile_image_url(model_id, Some(&version)));
+    Ok(())
+}
+
+/// Load a stored profile image and its content type
+pub fn load_profile_image(cache_dir: &str, model_id: &str) -> Option<(Vec<u8>, &'static str)> {
+    let dir = image_dir(cache_dir);
+    let stem = image_stem(model_id);
+    PROFILE_IMAGE_TYPES.iter().find_map(|(content_type, ext)| {
+        std::fs::read(dir.join(format!("{}.{}", stem, ext)))
+            .ok()
+            .map(|bytes| (bytes, *content_type))
+    })
+}
+
+/// Remove any stored profile image for the model
+pub fn delete_profile_image(cache_dir: &str, model_id: &str) {
+    let dir = image_dir(cache_dir);
+    let stem = image_stem(model_id);
+    for (_, ext) in PROFILE_IMAGE_TYPES {
+        let _ = std::fs::remove_file(dir.join(format!("{}.{}", stem, ext)));
+    }
+}
+
+/// Replace a stored image URL with an inline data URL so exports are self-contained
+pub fn inline_profile_image(cache_dir: &str, model_id: &str, meta: &mut Value) {
+    let is_local = meta
+        .get("profile_image_url")
+        .and_then(|v| v.as_str())
+        .map(is_stored_image_url)
+        .unwrap_or(false);
+
+    if is_local {
+        if let Some((bytes, content_type)) = load_profile_image(cache_dir, model_id) {
+            meta["profile_image_url"] = json!(format!(
+                "data:{};base64,{}",
+                content_type,
+                general_purpose::STANDARD.encode(bytes)
+            ));
+        }
+    }
+}
+
+/// Keep list responses small by replacing any inline image left in `meta` with its URL
+pub fn compact_profile_image(model_id: &str, meta: &mut Value) {
+    let is_inline = meta
+        .get("profile_image_url")
+        .and_then(|v| v.as_str())
+        .map(|url| url.starts_with("data:"))
+        .unwrap_or(false);
+
+    if is_inline {
+        meta["profile_image_url"] = json!(profile_image_url(model_id, None));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1x1 transparent PNG
+    const PNG_DATA_URL: &str = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mNkYAAAAAYAAjCB0C8AAAAASUVORK5CYII=";
+
+    #[test]
+    fn test_decode_data_url() {
+        let (content_type, bytes) = decode_data_url(PNG_DATA_URL).unwrap();
+        assert_eq!(content_type, "image/png");
+        assert!(bytes.starts_with(&[0x89, b'P', b'N', b'G']));
+        assert!(decode_data_url("https://example.com/a.png").is_none());
+    }
+
+    #[test]
+    fn test_store_and_inline_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().to_str().unwrap();
+        let mut meta = json!({ "profile_image_url": PNG_DATA_URL });
+
+        store_profile_image(cache_dir, "org/model:latest", &mut meta).unwrap();
+        let url = meta["profile_image_url"].as_str().unwrap();
+        assert!(url.starts_with("/api/v1/models/model/profile/image?id=org%2Fmodel%3Alatest&v="));
+
+        let (_, content_type) = load_profile_image(cache_dir, "org/model:latest").unwrap();
+        assert_eq!(content_type, "image/png");
+
+        inline_profile_image(cache_dir, "org/model:latest", &mut meta);
+        assert_eq!(meta["profile_image_url"], PNG_DATA_URL);
+
+        delete_profile_image(cache_dir, "org/model:latest");
+        assert!(load_profile_image(cache_dir, "org/model:latest").is_none());
+    }
+
+    #[test]
+    fn test_rejects_svg() {
+        let mut meta = json!({ "profile_image_url": "data:image/svg+xml;base64,PHN2Zy8+" });
+        assert!(store_profile_image("/nonexistent", "m", &mut meta).is_err());
+    }
+
+    #[test]
+    fn test_compact_profile_image() {
+        let mut meta = json!({ "profile_image_url": PNG_DATA_URL });
+        compact_profile_image("m1", &mut meta);
+        assert_eq!(
+            meta["profile_image_url"],
+            "/api/v1/models/model/profile/image?id=m1"
+        );
+
+        let mut external = json!({ "profile_image_url": "https://example.com/a.png" });
+        compact_profile_image("m1", &mut external);
+        assert_eq!(external["profile_image_url"], "https://example.com/a.png");
+    }
+}
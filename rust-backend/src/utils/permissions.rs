@@ -0,0 +1,191 @@
+use serde_json::{Map, Value};
+
+use crate::db::Database;
+use crate::error::AppResult;
+use crate::models::group::Group;
+use crate::services::group::GroupService;
+use crate::utils::misc::has_permission;
+
+/// Merge `overlay` into `base`.
+///
+/// Nested objects are merged key by key. Booleans are OR'd so a grant from any source wins
+/// over a denial; other values from `overlay` replace those in `base`.
+pub fn combine_permissions(base: &Value, overlay: &Value) -> Value {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            let mut merged: Map<String, Value> = base.clone();
+            for (key, value) in overlay {
+                let combined = match merged.get(key) {
+                    Some(existing) => combine_permissions(existing, value),
+                    None => value.clone(),
+                };
+                merged.insert(key.clone(), combined);
+            }
+            Value::Object(merged)
+        }
+        (Value::Bool(a), Value::Bool(b)) => Value::Bool(*a || *b),
+        (_, overlay) => overlay.clone(),
+    }
+}
+
+/// Effective permissions: the configured defaults, then each group's permissions applied
+/// oldest group first
+pub fn resolve_permissions(defaults: &Value, groups: &[Group]) -> Value {
+    let mut ordered: Vec<&Group> = groups.iter().collect();
+    ordered.sort_by(|a, b| {
+        a.created_at
+            .cmp(&b.created_at)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
+    let base = if defaults.is_object() {
+        defaults.clone()
+    } else {
+        Value::Object(Map::new())
+    };
+
+    ordered
+        .into_iter()
+        .filter_map(|group| group.permissions.as_ref())
+        .fold(base, |acc, perms| combine_permissions(&acc, perms))
+}
+
+/// Look up a dotted permission such as `workspace.models` in a nested permissions object
+pub fn permission_enabled(permissions: &Value, permission: &str) -> bool {
+    let mut current = permissions;
+    for key in permission.split('.') {
+        match current.get(key) {
+            Some(next) => current = next,
+            None => return false,
+        }
+    }
+    current.as_bool().unwrap_or(false)
+}
+
+/// Whether temporary chats are allowed and whether every chat must be one. Temporary chats
+/// are allowed unless denied, and only enforced when granted `chat.temporary_enforced`,
+/// which never applies to admins.
+pub fn temporary_chat_access(permissions: &Value, role: &str) -> (bool, bool) {
+    let enforced = role != "admin" && permission_enabled(permissions, "chat.temporary_enforced");
+    let allowed = permissions
+        .pointer("/chat/temporary")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    (allowed || enforced, enforced)
+}
+
+/// Effective permissions for a user, combining `defaults` with their groups' permissions
+pub async fn get_user_permissions(
+    db: &Database,
+    defaults: &Value,
+    user_id: &str,
+) -> AppResult<Value> {
+    let groups = GroupService::new(db)
+        .get_groups_by_member_id(user_id)
+        .await?;
+    Ok(resolve_permissions(defaults, &groups))
+}
+
+/// Whether a user holds `permission`, either as a per-user entry in the configured
+/// permissions or through the defaults and their groups
+pub async fn user_has_permission(
+    db: &Database,
+    defaults: &Value,
+    user_id: &str,
+    permission: &str,
+) -> AppResult<bool> {
+    if has_permission(user_id, permission, defaults) {
+        return Ok(true);
+    }
+    let permissions = get_user_permissions(db, defaults, user_id).await?;
+    Ok(permission_enabled(&permissions, permission))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn group(id: &str, created_at: i64, permissions: Value) -> Group {
+        Group {
+            id: id.to_string(),
+            user_id: "admin".to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            data: None,
+            meta: None,
+            permissions: Some(permissions),
+            user_ids: Vec::new(),
+            data_str: None,
+            meta_str: None,
+            permissions_str: None,
+            user_ids_str: None,
+            created_at,
+            updated_at: created_at,
+        }
+    }
+
+    #[test]
+    fn test_group_grants_override_default_denials() {
+        let defaults = json!({"workspace": {"models": false, "tools": false}});
+        let groups = vec![group("g1", 1, json!({"workspace": {"models": true}}))];
+
+        let resolved = resolve_permissions(&defaults, &groups);
+        assert!(permission_enabled(&resolved, "workspace.models"));
+        assert!(!permission_enabled(&resolved, "workspace.tools"));
+    }
+
+    #[test]
+    fn test_denial_never_revokes_a_grant() {
+        let defaults = json!({"chat": {"delete": true}});
+        let groups = vec![
+            group("g1", 1, json!({"chat": {"delete": false}})),
+            group("g2", 2, json!({"features": {"notes": true}})),
+        ];
+
+        let resolved = resolve_permissions(&defaults, &groups);
+        assert!(permission_enabled(&resolved, "chat.delete"));
+        assert!(permission_enabled(&resolved, "features.notes"));
+    }
+
+    #[test]
+    fn test_non_boolean_values_follow_group_creation_order() {
+        let defaults = json!({"chat": {"max_files": 1}});
+        // Listed out of order; the newer group is applied last and wins
+        let groups = vec![
+            group("newer", 20, json!({"chat": {"max_files": 10}})),
+            group("older", 10, json!({"chat": {"max_files": 5}})),
+        ];
+
+        let resolved = resolve_permissions(&defaults, &groups);
+        assert_eq!(resolved["chat"]["max_files"], 10);
+    }
+
+    #[test]
+    fn test_permission_enabled_paths() {
+        let permissions = json!({"workspace": {"models": true}, "flat": true});
+        assert!(permission_enabled(&permissions, "workspace.models"));
+        assert!(permission_enabled(&permissions, "flat"));
+        assert!(!permission_enabled(&permissions, "workspace.prompts"));
+        assert!(!permission_enabled(&permissions, "workspace"));
+    }
+
+    #[test]
+    fn test_temporary_chats_enforced_by_group() {
+        let defaults = json!({"chat": {"temporary_enforced": false}});
+        assert_eq!(temporary_chat_access(&defaults, "user"), (true, false));
+        assert_eq!(
+            temporary_chat_access(&json!({"chat": {"temporary": false}}), "user"),
+            (false, false)
+        );
+
+        let groups = vec![group(
+            "g1",
+            1,
+            json!({"chat": {"temporary_enforced": true}}),
+        )];
+        let resolved = resolve_permissions(&defaults, &groups);
+        assert_eq!(temporary_chat_access(&resolved, "user"), (true, true));
+        assert_eq!(temporary_chat_access(&resolved, "admin"), (true, false));
+    }
+}
@@ -27,10 +27,14 @@ pub struct UserInfo {
     pub role: String,
 }
 
+/// Timeout for a single pipeline filter call
+const FILTER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
 /// Process pipeline inlet filters
 /// Inlet filters modify the request before it's sent to the model
 #[allow(dead_code)]
 pub async fn process_pipeline_inlet_filter(
+    client: &Client,
     mut payload: Value,
     model_id: &str,
     user: &UserInfo,
@@ -44,10 +48,6 @@ pub async fn process_pipeline_inlet_filter(
         model_id
     );
 
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
-
     for filter in pipeline_filters {
         if filter.url_idx >= base_urls.len() {
             warn!(
@@ -83,6 +83,7 @@ pub async fn process_pipeline_inlet_filter(
         match client
             .post(&url)
             .header("Authorization", format!("Bearer {}", api_key))
+            .timeout(FILTER_TIMEOUT)
             .json(&request_body)
             .send()
             .await
@@ -134,6 +135,7 @@ pub async fn process_pipeline_inlet_filter(
 /// Outlet filters modify the response after it's received from the model
 #[allow(dead_code)]
 pub async fn process_pipeline_outlet_filter(
+    client: &Client,
     mut payload: Value,
     model_id: &str,
     user: &UserInfo,
@@ -147,10 +149,6 @@ pub async fn process_pipeline_outlet_filter(
         model_id
     );
 
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
-
     // Outlet filters are processed in reverse order
     for filter in pipeline_filters.iter().rev() {
         if filter.url_idx >= base_urls.len() {
@@ -187,6 +185,7 @@ pub async fn process_pipeline_outlet_filter(
         match client
             .post(&url)
             .header("Authorization", format!("Bearer {}", api_key))
+            .timeout(FILTER_TIMEOUT)
             .json(&request_body)
             .send()
             .await
@@ -0,0 +1,305 @@
+/// Prompt Variables
+///
+/// `{{VARIABLE}}` placeholders the server fills in, in prompt commands and in the system
+/// prompts of custom models:
+///
+/// - `USER_NAME`, `USER_EMAIL` and `USER_LOCATION` (when the user shared it)
+/// - `CURRENT_DATE`, `CURRENT_TIME`, `CURRENT_DATETIME`, `CURRENT_WEEKDAY` and
+///   `CURRENT_TIMEZONE`, in the user's timezone
+/// - `CURRENT_MODEL`, the name of the model answering, in system prompts
+///
+/// Any other placeholder is left as it is.
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::models::user::User;
+
+/// Values the server can fill in for a prompt command
+#[derive(Debug, Clone)]
+pub struct PromptVariableContext {
+    pub user_name: String,
+    pub user_email: String,
+    pub user_location: Option<String>,
+    pub now: DateTime<Tz>,
+    /// Name of the model answering, when rendering its system prompt
+    pub model_name: Option<String>,
+}
+
+impl PromptVariableContext {
+    /// Build the context for `user`, using `timezone` (IANA name) when given, then the
+    /// user's saved timezone, then UTC
+    pub fn for_user(user: &User, timezone: Option<&str>) -> Self {
+        let settings_ui = user.settings.as_ref().and_then(|s| s.get("ui"));
+
+        let tz = timezone
+            .map(str::to_string)
+            .or_else(|| {
+                settings_ui
+                    .and_then(|ui| ui.get("timezone"))
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string)
+            })
+            .and_then(|name| name.parse::<Tz>().ok())
+            .unwrap_or(Tz::UTC);
+
+        // userLocation is `true` until the browser resolves it, so only accept strings
+        let user_location = settings_ui
+            .and_then(|ui| ui.get("userLocation"))
+            .and_then(|v| v.as_str())
+            .or_else(|| {
+                user.info
+                    .as_ref()
+                    .and_then(|info| info.get("location"))
+                    .and_then(|v| v.as_str())
+            })
+            .filter(|s| !s.trim().is_empty())
+            .map(str::to_string);
+
+        PromptVariableContext {
+            user_name: user.name.clone(),
+            user_email: user.email.clone(),
+            user_location,
+            now: Utc::now().with_timezone(&tz),
+            model_name: None,
+        }
+    }
+
+    /// Fill in `CURRENT_MODEL` with `name`
+    pub fn with_model(mut self, name: &str) -> Self {
+        self.model_name = Some(name.to_string());
+        self
+    }
+
+    fn resolve(&self, name: &str) -> Option<String> {
+        match name {
+            "USER_NAME" => Some(self.user_name.clone()),
+            "USER_EMAIL" => Some(self.user_email.clone()),
+            "USER_LOCATION" => self.user_location.clone(),
+            "CURRENT_DATE" => Some(self.now.format("%Y-%m-%d").to_string()),
+            "CURRENT_TIME" => Some(self.now.format("%H:%M:%S").to_string()),
+            "CURRENT_DATETIME" => Some(self.now.format("%Y-%m-%d %H:%M:%S").to_string()),
+            "CURRENT_WEEKDAY" => Some(self.now.format("%A").to_string()),
+            "CURRENT_TIMEZONE" => Some(self.now.timezone().name().to_string()),
+            "CURRENT_MODEL" => self.model_name.clone(),
+            _ => None,
+        }
+    }
+}
+
+/// Prompt content with server-side variables substituted
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedPrompt {
+    pub content: String,
+    /// Placeholders left for the client, e.g. `CLIPBOARD` or custom inputs
+    pub variables: Vec<String>,
+}
+
+/// Substitute server-resolvable `{{VARIABLE}}` placeholders in a prompt.
+///
+/// Unknown variables, custom inputs (`{{name | type}}`) and anything nested inside another
+/// placeholder are left intact; the names of the remaining top-level placeholders are
+/// returned in order of first appearance.
+pub fn render_prompt_variables(content: &str, ctx: &PromptVariableContext) -> RenderedPrompt {
+    let mut output = String::with_capacity(content.len());
+    let mut variables: Vec<String> = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+
+        let end = match find_closing(after_open) {
+            Some(end) => end,
+            None => {
+                // Unterminated placeholder, keep the remainder as-is
+                output.push_str(&rest[start..]);
+                rest = "";
+                break;
+            }
+        };
+
+        let inner = &after_open[..end];
+        let placeholder = &rest[start..start + 2 + end + 2];
+        rest = &after_open[end + 2..];
+
+        if inner.contains("{{") {
+            output.push_str(placeholder);
+            continue;
+        }
+
+        let name = inner.split('|').next().unwrap_or_default().trim();
+        let resolved = if inner.contains('|') {
+            None
+        } else {
+            ctx.resolve(name)
+        };
+
+        match resolved {
+            Some(value) => output.push_str(&value),
+            None => {
+                output.push_str(placeholder);
+                if !name.is_empty() && !variables.iter().any(|v| v == name) {
+                    variables.push(name.to_string());
+                }
+            }
+        }
+    }
+    output.push_str(rest);
+
+    RenderedPrompt {
+        content: output,
+        variables,
+    }
+}
+
+/// Put a custom model's system prompt before the chat's own system message, or add it as
+/// the first message
+pub fn add_model_system_prompt(payload: &mut Value, system_prompt: &str) {
+    let messages = match payload.get_mut("messages").and_then(|m| m.as_array_mut()) {
+        Some(messages) => messages,
+        None => return,
+    };
+
+    let existing_system = messages
+        .first_mut()
+        .filter(|m| m.get("role").and_then(|r| r.as_str()) == Some("system"))
+        .and_then(|m| m.get_mut("content"))
+        .filter(|c| c.is_string());
+
+    match existing_system {
+        Some(content) => {
+            let text = content.as_str().unwrap_or_default();
+            *content = json!(format!("{}\n\n{}", system_prompt, text));
+        }
+        None => messages.insert(0, json!({"role": "system", "content": system_prompt})),
+    }
+}
+
+/// Offset of the `}}` that closes a placeholder, accounting for nested `{{ }}` pairs
+fn find_closing(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        if bytes[i] == b'{' && bytes[i + 1] == b'{' {
+            depth += 1;
+            i += 2;
+        } else if bytes[i] == b'}' && bytes[i + 1] == b'}' {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn context() -> PromptVariableContext {
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        PromptVariableContext {
+            user_name: "Alice".to_string(),
+            user_email: "alice@example.com".to_string(),
+            user_location: None,
+            now: tz.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap(),
+            model_name: None,
+        }
+    }
+
+    #[test]
+    fn test_each_builtin_variable() {
+        let ctx = PromptVariableContext {
+            user_location: Some("Berlin, Germany".to_string()),
+            ..context()
+        }
+        .with_model("Research Assistant");
+
+        for (variable, expected) in [
+            ("USER_NAME", "Alice"),
+            ("USER_EMAIL", "alice@example.com"),
+            ("USER_LOCATION", "Berlin, Germany"),
+            ("CURRENT_DATE", "2024-03-01"),
+            ("CURRENT_TIME", "09:30:00"),
+            ("CURRENT_DATETIME", "2024-03-01 09:30:00"),
+            ("CURRENT_WEEKDAY", "Friday"),
+            ("CURRENT_TIMEZONE", "Europe/Berlin"),
+            ("CURRENT_MODEL", "Research Assistant"),
+        ] {
+            let rendered = render_prompt_variables(&format!("[{{{{{}}}}}]", variable), &ctx);
+            assert_eq!(rendered.content, format!("[{}]", expected), "{}", variable);
+        }
+
+        // Without a model, CURRENT_MODEL is left for the client like other unknowns
+        let rendered = render_prompt_variables("{{CURRENT_MODEL}}", &context());
+        assert_eq!(rendered.content, "{{CURRENT_MODEL}}");
+    }
+
+    #[test]
+    fn test_model_system_prompt_goes_first() {
+        let mut payload = json!({"messages": [{"role": "user", "content": "Hi"}]});
+        add_model_system_prompt(&mut payload, "You are {{name}}.");
+        assert_eq!(
+            payload["messages"][0],
+            json!({"role": "system", "content": "You are {{name}}."})
+        );
+
+        add_model_system_prompt(&mut payload, "Be brief.");
+        assert_eq!(
+            payload["messages"][0]["content"],
+            "Be brief.\n\nYou are {{name}}."
+        );
+        assert_eq!(payload["messages"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_substitutes_known_variables() {
+        let rendered = render_prompt_variables(
+            "Hi {{USER_NAME}} ({{ USER_EMAIL }}), today is {{CURRENT_DATE}} {{CURRENT_TIME}} {{CURRENT_TIMEZONE}}",
+            &context(),
+        );
+        assert_eq!(
+            rendered.content,
+            "Hi Alice (alice@example.com), today is 2024-03-01 09:30:00 Europe/Berlin"
+        );
+        assert!(rendered.variables.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_and_client_variables_left_intact() {
+        let rendered = render_prompt_variables(
+            "{{CLIPBOARD}} {{topic | text:placeholder=\"Topic\"}} {{USER_LOCATION}} {{CLIPBOARD}}",
+            &context(),
+        );
+        assert_eq!(
+            rendered.content,
+            "{{CLIPBOARD}} {{topic | text:placeholder=\"Topic\"}} {{USER_LOCATION}} {{CLIPBOARD}}"
+        );
+        assert_eq!(
+            rendered.variables,
+            vec!["CLIPBOARD", "topic", "USER_LOCATION"]
+        );
+    }
+
+    #[test]
+    fn test_nested_variables_left_intact() {
+        let rendered =
+            render_prompt_variables("A {{outer {{USER_NAME}} }} then {{USER_NAME}}", &context());
+        assert_eq!(rendered.content, "A {{outer {{USER_NAME}} }} then Alice");
+        assert!(rendered.variables.is_empty());
+    }
+
+    #[test]
+    fn test_unterminated_placeholder() {
+        let rendered = render_prompt_variables("{{USER_NAME}} and {{oops", &context());
+        assert_eq!(rendered.content, "Alice and {{oops");
+    }
+}
@@ -0,0 +1,349 @@
+/// Reasoning Content
+///
+/// Separates a model's reasoning from its answer in a streamed response. Reasoning
+/// arrives either as `reasoning_content` deltas or inside `<think>...</think>` in the
+/// content, where tags can be split across deltas and are literal inside code fences.
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+const OPEN_TAG: &str = "<think>";
+const CLOSE_TAG: &str = "</think>";
+const CODE_FENCE: &str = "```";
+
+/// Answer and reasoning text found in a delta
+#[derive(Debug, Default, PartialEq)]
+pub struct ReasoningDelta {
+    pub content: String,
+    pub reasoning: String,
+    /// The reasoning ended with this delta
+    pub reasoning_done: bool,
+}
+
+/// Splits streamed content into answer and reasoning
+#[derive(Default)]
+pub struct ReasoningParser {
+    /// Text held back because it may be the start of a tag or code fence
+    pending: String,
+    in_think: bool,
+    in_code_fence: bool,
+    /// Only whitespace since the last newline, so a code fence can start here
+    at_line_start: bool,
+    /// Drop the whitespace between a closing tag and the answer
+    after_close_tag: bool,
+    reasoning: String,
+    reasoning_open: bool,
+    started_at: Option<Instant>,
+    duration: Option<Duration>,
+}
+
+impl ReasoningParser {
+    pub fn new() -> Self {
+        Self {
+            at_line_start: true,
+            ..Self::default()
+        }
+    }
+
+    /// Split a `content` delta, holding back text that may start a tag until the
+    /// next delta
+    pub fn push_content(&mut self, delta: &str) -> ReasoningDelta {
+        self.pending.push_str(delta);
+        let mut out = self.scan(false);
+        // Answer text after `reasoning_content` deltas ends the reasoning
+        if !self.in_think && !out.content.is_empty() {
+            out.reasoning_done |= self.end_reasoning();
+        }
+        out
+    }
+
+    /// Take the reasoning out of a streamed chat completion chunk, leaving only the
+    /// answer in its delta
+    pub fn take_from_chunk(&mut self, chunk: &mut Value) -> ReasoningDelta {
+        let delta = match chunk
+            .pointer_mut("/choices/0/delta")
+            .and_then(|d| d.as_object_mut())
+        {
+            Some(delta) => delta,
+            None => return ReasoningDelta::default(),
+        };
+
+        let mut out = ReasoningDelta::default();
+        for field in ["reasoning_content", "reasoning"] {
+            if let Some(Value::String(text)) = delta.remove(field) {
+                out = self.push_reasoning(&text);
+            }
+        }
+        if let Some(Value::String(text)) = delta.get("content") {
+            let parsed = self.push_content(text);
+            out.reasoning.push_str(&parsed.reasoning);
+            out.reasoning_done |= parsed.reasoning_done;
+            if parsed.content.is_empty() {
+                delta.remove("content");
+            } else {
+                delta.insert("content".to_string(), json!(parsed.content));
+            }
+            out.content = parsed.content;
+        }
+        out
+    }
+
+    /// `chat:completion` event showing new reasoning, or all of it with its duration
+    /// once it ended
+    pub fn event_for(&self, delta: &ReasoningDelta) -> Option<Value> {
+        let reasoning = if delta.reasoning_done {
+            json!({
+                "content": self.reasoning.trim(),
+                "done": true,
+                "duration": self.duration_secs().unwrap_or_default(),
+            })
+        } else if !delta.reasoning.is_empty() {
+            json!({"delta": delta.reasoning, "done": false})
+        } else {
+            return None;
+        };
+        Some(json!({
+            "type": "chat:completion",
+            "data": {"reasoning": reasoning}
+        }))
+    }
+
+    /// Add a `reasoning_content` delta
+    pub fn push_reasoning(&mut self, delta: &str) -> ReasoningDelta {
+        self.start_reasoning();
+        self.reasoning.push_str(delta);
+        ReasoningDelta {
+            reasoning: delta.to_string(),
+            ..ReasoningDelta::default()
+        }
+    }
+
+    /// Flush held back text and end open reasoning once the stream is over
+    pub fn finish(&mut self) -> ReasoningDelta {
+        let mut out = self.scan(true);
+        out.reasoning_done |= self.end_reasoning();
+        out
+    }
+
+    /// Seconds spent reasoning, once it ended
+    pub fn duration_secs(&self) -> Option<u64> {
+        self.duration.map(|d| d.as_secs())
+    }
+
+    /// Collapsible block with the reasoning for a message's content, once it ended
+    pub fn details(&self) -> Option<String> {
+        let duration = self.duration_secs()?;
+        if self.reasoning.trim().is_empty() {
+            return None;
+        }
+        Some(reasoning_details(&self.reasoning, duration))
+    }
+
+    fn start_reasoning(&mut self) {
+        self.reasoning_open = true;
+        self.started_at.get_or_insert_with(Instant::now);
+    }
+
+    /// Returns whether reasoning was open
+    fn end_reasoning(&mut self) -> bool {
+        if !self.reasoning_open {
+            return false;
+        }
+        self.reasoning_open = false;
+        self.duration = self.started_at.map(|s| s.elapsed());
+        true
+    }
+
+    fn scan(&mut self, at_end: bool) -> ReasoningDelta {
+        let mut out = ReasoningDelta::default();
+        let text = std::mem::take(&mut self.pending);
+        let mut rest = text.as_str();
+
+        while let Some(c) = rest.chars().next() {
+            if self.at_line_start && c == '`' {
+                if rest.starts_with(CODE_FENCE) {
+                    self.in_code_fence = !self.in_code_fence;
+                    self.at_line_start = false;
+                    self.emit(&mut out, CODE_FENCE);
+                    rest = &rest[CODE_FENCE.len()..];
+                    continue;
+                }
+                if !at_end && CODE_FENCE.starts_with(rest) {
+                    break;
+                }
+            }
+
+            if !self.in_code_fence && c == '<' {
+                let tag = if self.in_think { CLOSE_TAG } else { OPEN_TAG };
+                if rest.starts_with(tag) {
+                    self.in_think = !self.in_think;
+                    if self.in_think {
+                        self.start_reasoning();
+                    } else {
+                        out.reasoning_done |= self.end_reasoning();
+                        self.after_close_tag = true;
+                    }
+                    rest = &rest[tag.len()..];
+                    continue;
+                }
+                if !at_end && tag.starts_with(rest) {
+                    break;
+                }
+            }
+
+            if c == '\n' {
+                self.at_line_start = true;
+            } else if !c.is_whitespace() {
+                self.at_line_start = false;
+            }
+            if !(self.after_close_tag && c.is_whitespace()) {
+                self.after_close_tag = false;
+                let mut buf = [0; 4];
+                self.emit(&mut out, c.encode_utf8(&mut buf));
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+
+        self.pending = rest.to_string();
+        out
+    }
+
+    fn emit(&mut self, out: &mut ReasoningDelta, text: &str) {
+        if self.in_think {
+            self.reasoning.push_str(text);
+            out.reasoning.push_str(text);
+        } else {
+            self.after_close_tag = false;
+            out.content.push_str(text);
+        }
+    }
+}
+
+/// Reasoning as the web UI's collapsible `<details type="reasoning">` block
+pub fn reasoning_details(reasoning: &str, duration_secs: u64) -> String {
+    let quoted: Vec<String> = reasoning
+        .trim()
+        .lines()
+        .map(|line| format!("> {}", line))
+        .collect();
+    format!(
+        "<details type=\"reasoning\" done=\"true\" duration=\"{}\">\n\
+         <summary>Thought for {} seconds</summary>\n{}\n</details>\n",
+        duration_secs,
+        duration_secs,
+        quoted.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_all(parser: &mut ReasoningParser, deltas: &[&str]) -> (String, String) {
+        let mut content = String::new();
+        let mut reasoning = String::new();
+        for delta in deltas {
+            let out = parser.push_content(delta);
+            content.push_str(&out.content);
+            reasoning.push_str(&out.reasoning);
+        }
+        let out = parser.finish();
+        content.push_str(&out.content);
+        reasoning.push_str(&out.reasoning);
+        (content, reasoning)
+    }
+
+    #[test]
+    fn test_think_tags_split_across_deltas() {
+        let mut parser = ReasoningParser::new();
+        let (content, reasoning) = push_all(
+            &mut parser,
+            &[
+                "<th",
+                "ink>Let me ",
+                "add them.</thi",
+                "nk>\n\nThe answer",
+                " is 4 < 5.",
+            ],
+        );
+        assert_eq!(reasoning, "Let me add them.");
+        assert_eq!(content, "The answer is 4 < 5.");
+        assert_eq!(parser.reasoning, "Let me add them.");
+        assert!(parser.details().unwrap().contains("> Let me add them."));
+    }
+
+    #[test]
+    fn test_think_tags_in_code_fences_are_literal() {
+        let mut parser = ReasoningParser::new();
+        let (content, reasoning) = push_all(
+            &mut parser,
+            &[
+                "Use this:\n`",
+                "``html\n<think>not reasoning</think>\n``",
+                "`\nDone <thi",
+            ],
+        );
+        assert_eq!(reasoning, "");
+        assert_eq!(
+            content,
+            "Use this:\n```html\n<think>not reasoning</think>\n```\nDone <thi"
+        );
+        assert_eq!(parser.duration_secs(), None);
+
+        // Nor does a closing tag in a fence inside the reasoning end it
+        let mut parser = ReasoningParser::new();
+        let (content, reasoning) = push_all(
+            &mut parser,
+            &["<think>Try\n```\n</think>\n```\n</think>Answer"],
+        );
+        assert_eq!(reasoning, "Try\n```\n</think>\n```\n");
+        assert_eq!(content, "Answer");
+    }
+
+    #[test]
+    fn test_reasoning_content_ends_with_the_answer() {
+        let mut parser = ReasoningParser::new();
+        assert_eq!(parser.push_reasoning("Thinking").reasoning, "Thinking");
+        parser.push_reasoning(" hard");
+
+        let out = parser.push_content("Hi");
+        assert_eq!(out.content, "Hi");
+        assert!(out.reasoning_done);
+        assert_eq!(parser.reasoning, "Thinking hard");
+        assert!(!parser.finish().reasoning_done);
+    }
+
+    #[test]
+    fn test_reasoning_is_taken_out_of_chunks() {
+        let mut parser = ReasoningParser::new();
+
+        let mut chunk = json!({"choices": [{"index": 0, "delta": {"reasoning_content": "Hmm"}}]});
+        let out = parser.take_from_chunk(&mut chunk);
+        assert_eq!(chunk["choices"][0]["delta"], json!({}));
+        assert_eq!(
+            parser.event_for(&out).unwrap()["data"]["reasoning"],
+            json!({"delta": "Hmm", "done": false})
+        );
+
+        let mut chunk = json!({"choices": [{"index": 0, "delta": {"content": "Yes"}}]});
+        let out = parser.take_from_chunk(&mut chunk);
+        assert_eq!(chunk["choices"][0]["delta"]["content"], "Yes");
+        let event = parser.event_for(&out).unwrap();
+        assert_eq!(event["data"]["reasoning"]["content"], "Hmm");
+        assert_eq!(event["data"]["reasoning"]["done"], true);
+
+        // Chunks without reasoning have no event
+        let mut chunk = json!({"choices": [{"index": 0, "delta": {"content": "!"}}]});
+        let out = parser.take_from_chunk(&mut chunk);
+        assert!(parser.event_for(&out).is_none());
+    }
+
+    #[test]
+    fn test_reasoning_details() {
+        assert_eq!(
+            reasoning_details("\nStep one\nStep two\n", 3),
+            "<details type=\"reasoning\" done=\"true\" duration=\"3\">\n\
+             <summary>Thought for 3 seconds</summary>\n\
+             > Step one\n> Step two\n</details>\n"
+        );
+    }
+}
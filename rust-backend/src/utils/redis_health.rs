@@ -0,0 +1,190 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::utils::time::current_timestamp_seconds;
+
+/// Longest wait for a pooled connection before treating Redis as down
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether a Redis server is currently reachable
+///
+/// Redis-backed features check this before touching Redis and take their in-process
+/// path while it is down, so an outage costs neither latency nor errors. Changes are
+/// logged once per transition rather than on every failed call.
+pub struct RedisHealth {
+    name: &'static str,
+    available: AtomicBool,
+    last_error: Mutex<Option<String>>,
+    /// Unix seconds of the last transition
+    since: Mutex<i64>,
+}
+
+/// Snapshot of [`RedisHealth`] for health endpoints
+#[derive(Debug, Clone, Serialize)]
+pub struct RedisStatus {
+    pub status: &'static str,
+    pub last_error: Option<String>,
+    pub since: i64,
+}
+
+impl RedisHealth {
+    pub fn new(name: &'static str) -> Self {
+        RedisHealth {
+            name,
+            available: AtomicBool::new(true),
+            last_error: Mutex::new(None),
+            since: Mutex::new(current_timestamp_seconds()),
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.available.load(Ordering::Relaxed)
+    }
+
+    pub fn mark_failed(&self, error: impl std::fmt::Display) {
+        *self.last_error.lock().unwrap() = Some(error.to_string());
+        if self.available.swap(false, Ordering::Relaxed) {
+            *self.since.lock().unwrap() = current_timestamp_seconds();
+            tracing::warn!(
+                "{} unavailable, falling back to local-only behaviour: {}",
+                self.name,
+                error
+            );
+        }
+    }
+
+    pub fn mark_ok(&self) {
+        if !self.available.swap(true, Ordering::Relaxed) {
+            *self.since.lock().unwrap() = current_timestamp_seconds();
+            tracing::info!("{} reachable again", self.name);
+        }
+    }
+
+    pub fn status(&self) -> RedisStatus {
+        RedisStatus {
+            status: if self.is_available() {
+                "ok"
+            } else {
+                "unavailable"
+            },
+            last_error: self.last_error.lock().unwrap().clone(),
+            since: *self.since.lock().unwrap(),
+        }
+    }
+}
+
+/// Redis connection pool that stops handing out connections while Redis is down
+#[derive(Clone)]
+pub struct RedisPool {
+    pool: deadpool_redis::Pool,
+    health: Arc<RedisHealth>,
+}
+
+impl RedisPool {
+    pub fn new(pool: deadpool_redis::Pool) -> Self {
+        RedisPool {
+            pool,
+            health: Arc::new(RedisHealth::new("Redis")),
+        }
+    }
+
+    pub fn health(&self) -> &RedisHealth {
+        &self.health
+    }
+
+    /// A connection, or `None` when Redis is down and the caller should use its
+    /// local fallback
+    pub async fn get(&self) -> Option<deadpool_redis::Connection> {
+        if !self.health.is_available() {
+            return None;
+        }
+        match self.connect().await {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                self.health.mark_failed(e);
+                None
+            }
+        }
+    }
+
+    async fn connect(&self) -> Result<deadpool_redis::Connection, String> {
+        match tokio::time::timeout(CONNECT_TIMEOUT, self.pool.get()).await {
+            Ok(Ok(conn)) => Ok(conn),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err("timed out waiting for a connection".to_string()),
+        }
+    }
+
+    /// Ping Redis, bypassing the availability check, and record the outcome
+    pub async fn check(&self) -> bool {
+        let result = match self.connect().await {
+            Ok(mut conn) => redis::cmd("PING")
+                .query_async::<String>(&mut conn)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(_) => {
+                self.health.mark_ok();
+                true
+            }
+            Err(e) => {
+                self.health.mark_failed(e);
+                false
+            }
+        }
+    }
+
+    /// Ping Redis every `interval`, so outages are noticed and recoveries picked up
+    pub fn spawn_health_check(&self, interval: Duration) {
+        let pool = self.clone();
+        tokio::spawn(async move {
+            loop {
+                pool.check().await;
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unreachable_redis_degrades_quickly() {
+        // Nothing listens on port 1
+        let pool = deadpool_redis::Config::from_url("redis://127.0.0.1:1")
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+            .unwrap();
+        let pool = RedisPool::new(pool);
+        assert_eq!(pool.health().status().status, "ok");
+
+        assert!(pool.get().await.is_none());
+        let status = pool.health().status();
+        assert_eq!(status.status, "unavailable");
+        assert!(status.last_error.is_some());
+
+        // Later calls skip Redis entirely until a health check succeeds
+        let start = std::time::Instant::now();
+        assert!(pool.get().await.is_none());
+        assert!(start.elapsed() < Duration::from_millis(50));
+        assert!(!pool.check().await);
+    }
+
+    #[test]
+    fn test_transitions() {
+        let health = RedisHealth::new("Redis");
+        health.mark_failed("connection refused");
+        assert!(!health.is_available());
+        health.mark_ok();
+        assert!(health.is_available());
+        assert_eq!(
+            health.status().last_error.as_deref(),
+            Some("connection refused")
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use serde_json::{json, Value};
+
+/// Error surfaced when a model asked for JSON output answers with something else
+pub const INVALID_JSON_ERROR: &str =
+    "The model's response is not valid JSON. Try regenerating the response.";
+
+/// The request's `response_format` when it asks for JSON (`json_object` or `json_schema`)
+pub fn requested_json_format(payload: &Value) -> Option<&Value> {
+    payload.get("response_format").filter(|format| {
+        matches!(
+            format.get("type").and_then(|t| t.as_str()),
+            Some("json_object") | Some("json_schema")
+        )
+    })
+}
+
+/// Whether a connection accepts `response_format`; connections opt out with
+/// `supports_response_format: false` in their API config
+pub fn supports_response_format(api_config: &Value) -> bool {
+    api_config
+        .get("supports_response_format")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true)
+}
+
+/// System instruction asking for strict JSON, including the schema when one was given
+pub fn json_instruction(response_format: &Value) -> String {
+    let mut instruction = "Respond only with a single valid JSON value. Do not wrap it in \
+                           code fences or add any text before or after it."
+        .to_string();
+
+    let schema = response_format
+        .pointer("/json_schema/schema")
+        .filter(|s| !s.is_null());
+    if let Some(schema) = schema {
+        instruction.push_str(&format!(
+            " The JSON must conform to this JSON Schema:\n{}",
+            serde_json::to_string_pretty(schema).unwrap_or_default()
+        ));
+    }
+    instruction
+}
+
+/// Replace `response_format` with a prompted JSON instruction for upstreams that don't
+/// support it. Returns whether the payload asked for JSON (and was rewritten).
+pub fn apply_prompted_json(payload: &mut Value) -> bool {
+    let response_format = match requested_json_format(payload) {
+        Some(format) => format.clone(),
+        None => return false,
+    };
+
+    let instruction = json_instruction(&response_format);
+    if let Some(obj) = payload.as_object_mut() {
+        obj.remove("response_format");
+    }
+
+    if let Some(messages) = payload.get_mut("messages").and_then(|m| m.as_array_mut()) {
+        let existing_system = messages
+            .first_mut()
+            .filter(|m| m.get("role").and_then(|r| r.as_str()) == Some("system"))
+            .and_then(|m| m.get_mut("content"))
+            .filter(|c| c.is_string());
+
+        match existing_system {
+            Some(content) => {
+                let text = content.as_str().unwrap_or_default();
+                *content = json!(format!("{}\n\n{}", text, instruction));
+            }
+            None => messages.insert(0, json!({ "role": "system", "content": instruction })),
+        }
+    }
+    true
+}
+
+/// Whether `content` parses as JSON, tolerating a surrounding ```json fence
+pub fn is_valid_json_output(content: &str) -> bool {
+    let trimmed = content.trim();
+    let unfenced = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str::<Value>(unfenced.trim()).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requested_json_format() {
+        let payload = json!({"response_format": {"type": "json_object"}});
+        assert!(requested_json_format(&payload).is_some());
+        let text = json!({"response_format": {"type": "text"}});
+        assert!(requested_json_format(&text).is_none());
+        assert!(requested_json_format(&json!({})).is_none());
+    }
+
+    #[test]
+    fn test_apply_prompted_json_with_schema() {
+        let mut payload = json!({
+            "messages": [{"role": "user", "content": "List colors"}],
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": {"name": "colors", "schema": {"type": "array"}}
+            }
+        });
+
+        assert!(apply_prompted_json(&mut payload));
+        assert!(payload.get("response_format").is_none());
+        assert_eq!(payload["messages"][0]["role"], "system");
+        let instruction = payload["messages"][0]["content"].as_str().unwrap();
+        assert!(instruction.contains("\"type\": \"array\""));
+        assert_eq!(payload["messages"][1]["content"], "List colors");
+    }
+
+    #[test]
+    fn test_apply_prompted_json_extends_system_prompt() {
+        let mut payload = json!({
+            "messages": [
+                {"role": "system", "content": "You are terse."},
+                {"role": "user", "content": "Hi"}
+            ],
+            "response_format": {"type": "json_object"}
+        });
+
+        assert!(apply_prompted_json(&mut payload));
+        let messages = payload["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0]["content"]
+            .as_str()
+            .unwrap()
+            .starts_with("You are terse.\n\nRespond only"));
+
+        let mut plain = json!({"messages": []});
+        assert!(!apply_prompted_json(&mut plain));
+    }
+
+    #[test]
+    fn test_is_valid_json_output() {
+        assert!(is_valid_json_output(" {\"a\": 1} "));
+        assert!(is_valid_json_output("```json\n[1, 2]\n```"));
+        assert!(!is_valid_json_output("Sure! {\"a\": 1}"));
+        assert!(!is_valid_json_output(""));
+    }
+}
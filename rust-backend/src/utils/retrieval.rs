@@ -3,10 +3,11 @@
 // and inject them as context into chat messages (RAG - Retrieval Augmented Generation)
 
 use crate::{
+    config::Config,
     error::{AppError, AppResult},
     models::{chat::Chat, file::File, note::Note, user::User},
     services::{chat::ChatService, file::FileService, note::NoteService},
-    utils::misc::{get_message_list, has_access},
+    utils::misc::{get_message_list, has_access, message_content_text},
     AppState,
 };
 use serde::{Deserialize, Serialize};
@@ -44,6 +45,264 @@ Provide a clear and direct response to the user's query, including inline citati
 </user_query>
 "#;
 
+/// Asks for footnote citations, after a template that asks for inline ones
+const FOOTNOTE_CITATION_INSTRUCTION: &str = "Write citations as markdown footnotes ([^id], \
+e.g. [^1]) instead of [id], and list the cited sources as footnotes at the end of the response.";
+
+/// How the model is asked to cite sources, from RAG_CITATION_STYLE
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CitationStyle {
+    /// `[1]` markers next to the cited text
+    Inline,
+    /// `[^1]` markers, with the sources listed at the end
+    Footnotes,
+    /// No citations, and no sources returned with the completion
+    None,
+}
+
+impl CitationStyle {
+    /// Parse `inline`, `footnotes` or `none`, falling back to inline
+    pub fn parse(style: &str) -> Self {
+        match style.trim().to_lowercase().as_str() {
+            "footnotes" | "footnote" => CitationStyle::Footnotes,
+            "none" | "off" => CitationStyle::None,
+            _ => CitationStyle::Inline,
+        }
+    }
+}
+
+/// How sources are cited in the injected context and returned with the completion
+#[derive(Debug, Clone, Copy)]
+pub struct CitationOptions {
+    pub style: CitationStyle,
+    /// Name sources by their file name or URL
+    pub include_source: bool,
+}
+
+impl CitationOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            style: CitationStyle::parse(&config.rag_citation_style),
+            include_source: config.rag_citation_include_source,
+        }
+    }
+}
+
+impl Default for CitationOptions {
+    fn default() -> Self {
+        Self {
+            style: CitationStyle::Inline,
+            include_source: true,
+        }
+    }
+}
+
+/// Numbers sources in the order they're first cited
+#[derive(Default)]
+struct CitationNumbers {
+    numbers: HashMap<String, usize>,
+}
+
+impl CitationNumbers {
+    /// Number of a source's document, and whether it's the first time the source came up.
+    /// Documents are grouped by their `source` metadata, else by the source's id.
+    fn number(&mut self, source: &Source, metadata: &Value) -> (usize, bool) {
+        let key = metadata
+            .get("source")
+            .and_then(|s| s.as_str())
+            .or_else(|| source.source.get("id").and_then(|i| i.as_str()))
+            .unwrap_or("N/A");
+        if let Some(number) = self.numbers.get(key) {
+            return (*number, false);
+        }
+        let number = self.numbers.len() + 1;
+        self.numbers.insert(key.to_string(), number);
+        (number, true)
+    }
+}
+
+/// URL a source was found at, for web pages
+fn source_url(source: &Source, metadata: &Value) -> Option<String> {
+    source
+        .source
+        .get("url")
+        .and_then(|u| u.as_str())
+        .or_else(|| {
+            metadata
+                .get("source")
+                .and_then(|s| s.as_str())
+                .filter(|s| s.starts_with("http://") || s.starts_with("https://"))
+        })
+        .map(String::from)
+}
+
+/// Numbered list of the cited sources for the frontend's sources panel, in the order the
+/// context numbers them. Empty when citations are off.
+pub fn build_citations(sources: &[Source], options: &CitationOptions) -> Vec<Value> {
+    if options.style == CitationStyle::None {
+        return Vec::new();
+    }
+
+    let mut numbers = CitationNumbers::default();
+    let mut citations = Vec::new();
+    for source in sources {
+        for metadata in &source.metadata {
+            let (number, first) = numbers.number(source, metadata);
+            if !first {
+                continue;
+            }
+            let mut citation = json!({
+                "id": number,
+                "type": source.source.get("type").cloned().unwrap_or(Value::Null),
+            });
+            if options.include_source {
+                citation["name"] = source.source.get("name").cloned().unwrap_or(Value::Null);
+                if let Some(url) = source_url(source, metadata) {
+                    citation["url"] = json!(url);
+                }
+            }
+            citations.push(citation);
+        }
+    }
+    citations
+}
+
+/// Sources returned with the completion. Without source names they are numbered like
+/// the citations instead; with citations off there are none.
+pub fn sources_for_response(sources: &[Source], options: &CitationOptions) -> Vec<Value> {
+    if options.style == CitationStyle::None {
+        return Vec::new();
+    }
+    if options.include_source {
+        return sources
+            .iter()
+            .filter_map(|s| serde_json::to_value(s).ok())
+            .collect();
+    }
+
+    let mut numbers = CitationNumbers::default();
+    sources
+        .iter()
+        .map(|source| {
+            let metadata: Vec<Value> = source
+                .metadata
+                .iter()
+                .map(|metadata| {
+                    let (number, _) = numbers.number(source, metadata);
+                    json!({"source": number.to_string()})
+                })
+                .collect();
+            let id = metadata
+                .first()
+                .and_then(|m| m.get("source"))
+                .cloned()
+                .unwrap_or(Value::Null);
+            json!({
+                "source": {
+                    "type": source.source.get("type").cloned().unwrap_or(Value::Null),
+                    "id": id,
+                },
+                "document": source.document,
+                "metadata": metadata,
+            })
+        })
+        .collect()
+}
+
+/// Limits on the documents injected as context, applied once the sources are found
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RelevanceFilter {
+    /// Minimum score of a searched document; 0 keeps them all
+    pub threshold: f64,
+    /// Most documents to inject; 0 for no limit
+    pub max_sources: usize,
+}
+
+impl RelevanceFilter {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            threshold: config.relevance_threshold,
+            max_sources: config.rag_max_sources,
+        }
+    }
+}
+
+/// Relevance score search gave a document. Documents attached in full have none.
+fn document_score(metadata: &Value) -> Option<f64> {
+    metadata.get("score").and_then(|s| s.as_f64())
+}
+
+/// Leave out the documents scoring under the threshold, then keep the `max_sources` most
+/// relevant, in their order. Documents without a score were attached by the user, so
+/// they always clear the threshold and rank first. Sources left without documents are
+/// dropped, so nothing is injected when no document is relevant.
+pub fn filter_sources(sources: Vec<Source>, filter: &RelevanceFilter) -> Vec<Source> {
+    let clears_threshold = |metadata: &Value| match document_score(metadata) {
+        Some(score) if filter.threshold > 0.0 => score >= filter.threshold,
+        _ => true,
+    };
+
+    // (source index, document index, score) of the documents that clear the threshold
+    let mut documents: Vec<(usize, usize, Option<f64>)> = Vec::new();
+    let mut total = 0;
+    for (source_idx, source) in sources.iter().enumerate() {
+        for (document_idx, metadata) in source.metadata.iter().enumerate() {
+            total += 1;
+            if clears_threshold(metadata) {
+                documents.push((source_idx, document_idx, document_score(metadata)));
+            }
+        }
+    }
+    let below_threshold = total - documents.len();
+
+    if filter.max_sources > 0 && documents.len() > filter.max_sources {
+        // Stable, so documents with the same score keep their order
+        documents.sort_by(|a, b| {
+            let a = a.2.unwrap_or(f64::INFINITY);
+            let b = b.2.unwrap_or(f64::INFINITY);
+            b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        documents.truncate(filter.max_sources);
+    }
+    let over_limit = total - below_threshold - documents.len();
+
+    if below_threshold > 0 || over_limit > 0 {
+        tracing::info!(
+            "🧹 Filtered out {} source(s) below the relevance threshold {} and {} over the \
+             limit of {}, {} left",
+            below_threshold,
+            filter.threshold,
+            over_limit,
+            filter.max_sources,
+            documents.len()
+        );
+    }
+
+    let kept: HashSet<(usize, usize)> = documents.iter().map(|(s, d, _)| (*s, *d)).collect();
+    sources
+        .into_iter()
+        .enumerate()
+        .filter_map(|(source_idx, source)| {
+            let (document, metadata): (Vec<String>, Vec<Value>) = source
+                .document
+                .into_iter()
+                .zip(source.metadata)
+                .enumerate()
+                .filter(|(document_idx, _)| kept.contains(&(source_idx, *document_idx)))
+                .map(|(_, document)| document)
+                .unzip();
+            if document.is_empty() {
+                return None;
+            }
+            Some(Source {
+                source: source.source,
+                document,
+                metadata,
+            })
+        })
+        .collect()
+}
+
 /// Item representing a file, note, or chat attachment
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileItem {
@@ -65,43 +324,46 @@ pub struct Source {
     pub metadata: Vec<Value>,
 }
 
-/// Get last user message content from messages array
+/// Get last user message content from messages array; for multimodal messages the text
+/// parts are concatenated and images ignored
 pub fn get_last_user_message(messages: &[Value]) -> Option<String> {
-    for message in messages.iter().rev() {
-        if message.get("role").and_then(|r| r.as_str()) == Some("user") {
-            if let Some(content) = message.get("content") {
-                // Handle string content
-                if let Some(text) = content.as_str() {
-                    return Some(text.to_string());
-                }
-                // Handle array content (multi-modal messages)
-                if let Some(arr) = content.as_array() {
-                    for item in arr {
-                        if item.get("type").and_then(|t| t.as_str()) == Some("text") {
-                            if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
-                                return Some(text.to_string());
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-    None
+    messages
+        .iter()
+        .rev()
+        .find(|message| message.get("role").and_then(|r| r.as_str()) == Some("user"))
+        .and_then(|message| message.get("content"))
+        .map(message_content_text)
 }
 
 /// Add or update user message content
+///
+/// For multimodal messages the first text part is updated (or one is added) so image
+/// parts are kept.
 pub fn add_or_update_user_message(content: &str, messages: &mut Vec<Value>, append: bool) {
+    let combine = |existing: &str| {
+        if append {
+            format!("{}\n{}", existing, content)
+        } else {
+            format!("{}\n{}", content, existing)
+        }
+    };
+
     if let Some(last_msg) = messages.last_mut() {
         if last_msg.get("role").and_then(|r| r.as_str()) == Some("user") {
             // Update existing user message
             if let Some(existing_content) = last_msg.get("content").and_then(|c| c.as_str()) {
-                let new_content = if append {
-                    format!("{}\n{}", existing_content, content)
-                } else {
-                    format!("{}\n{}", content, existing_content)
-                };
-                last_msg["content"] = json!(new_content);
+                last_msg["content"] = json!(combine(existing_content));
+            } else if let Some(parts) = last_msg.get_mut("content").and_then(|c| c.as_array_mut()) {
+                let text_part = parts
+                    .iter_mut()
+                    .find(|part| part.get("type").and_then(|t| t.as_str()) == Some("text"));
+                match text_part {
+                    Some(part) => {
+                        let existing = part.get("text").and_then(|t| t.as_str()).unwrap_or("");
+                        part["text"] = json!(combine(existing));
+                    }
+                    None => parts.insert(0, json!({ "type": "text", "text": content })),
+                }
             } else {
                 last_msg["content"] = json!(content);
             }
@@ -272,7 +534,7 @@ pub async fn get_sources_from_items(
                                             .filter_map(|m| {
                                                 let role = m.get("role")?.as_str()?.to_string();
                                                 let content =
-                                                    m.get("content")?.as_str()?.to_string();
+                                                    message_content_text(m.get("content")?);
                                                 Some(format!(
                                                     "#### {}\n{}\n",
                                                     role.chars()
@@ -412,10 +674,15 @@ pub async fn get_sources_from_items(
 }
 
 /// Process sources and inject them into messages as RAG context
+///
+/// Each document is tagged `<source id="1" name="...">`. The id is left out when
+/// citations are off, which the default template takes as "don't cite", and the name
+/// when sources aren't named.
 pub fn inject_sources_into_messages(
     sources: Vec<Source>,
     messages: &mut Vec<Value>,
     rag_template_str: &str,
+    options: &CitationOptions,
 ) -> AppResult<Vec<Source>> {
     if sources.is_empty() {
         return Ok(sources);
@@ -423,38 +690,28 @@ pub fn inject_sources_into_messages(
 
     // Build context string with citations
     let mut context_string = String::new();
-    let mut citation_idx_map: HashMap<String, usize> = HashMap::new();
+    let mut numbers = CitationNumbers::default();
 
     for source in &sources {
-        if !source.document.is_empty() {
-            for (document_text, document_metadata) in
-                source.document.iter().zip(source.metadata.iter())
-            {
-                let source_name = source.source.get("name").and_then(|n| n.as_str());
-                let source_id = document_metadata
-                    .get("source")
-                    .and_then(|s| s.as_str())
-                    .or_else(|| source.source.get("id").and_then(|i| i.as_str()))
-                    .unwrap_or("N/A");
-
-                // Assign citation index
-                if !citation_idx_map.contains_key(source_id) {
-                    let idx = citation_idx_map.len() + 1;
-                    citation_idx_map.insert(source_id.to_string(), idx);
-                }
-
-                let citation_idx = citation_idx_map.get(source_id).unwrap();
-
-                // Format: <source id="1" name="...">content</source>
-                context_string.push_str(&format!(
-                    "<source id=\"{}\"{}>{}</source>\n",
-                    citation_idx,
-                    source_name
-                        .map(|n| format!(" name=\"{}\"", n))
-                        .unwrap_or_default(),
-                    document_text
-                ));
-            }
+        for (document_text, document_metadata) in source.document.iter().zip(source.metadata.iter())
+        {
+            let (citation_idx, _) = numbers.number(source, document_metadata);
+            let id_attr = match options.style {
+                CitationStyle::None => String::new(),
+                _ => format!(" id=\"{}\"", citation_idx),
+            };
+            let name_attr = source
+                .source
+                .get("name")
+                .and_then(|n| n.as_str())
+                .filter(|_| options.include_source)
+                .map(|n| format!(" name=\"{}\"", n))
+                .unwrap_or_default();
+
+            context_string.push_str(&format!(
+                "<source{}{}>{}</source>\n",
+                id_attr, name_attr, document_text
+            ));
         }
     }
 
@@ -470,7 +727,11 @@ pub fn inject_sources_into_messages(
     })?;
 
     // Apply RAG template (call the function, not the parameter)
-    let augmented_message = rag_template(rag_template_str, &context_string, &query);
+    let mut augmented_message = rag_template(rag_template_str, &context_string, &query);
+    if options.style == CitationStyle::Footnotes {
+        augmented_message.push_str("\n\n");
+        augmented_message.push_str(FOOTNOTE_CITATION_INSTRUCTION);
+    }
 
     // Update last user message
     add_or_update_user_message(&augmented_message, messages, false);
@@ -483,3 +744,225 @@ pub fn inject_sources_into_messages(
 
     Ok(sources)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(text: &str) -> Source {
+        Source {
+            source: json!({"id": "file-1", "name": "notes.txt"}),
+            document: vec![text.to_string()],
+            metadata: vec![json!({"source": "file-1"})],
+        }
+    }
+
+    #[test]
+    fn test_inject_sources_into_multimodal_message() {
+        let mut messages = vec![json!({
+            "role": "user",
+            "content": [
+                {"type": "text", "text": "What is in this picture?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}},
+                {"type": "text", "text": "Compare it with my notes."}
+            ]
+        })];
+
+        assert_eq!(
+            get_last_user_message(&messages).unwrap(),
+            "What is in this picture?\nCompare it with my notes."
+        );
+
+        inject_sources_into_messages(
+            vec![source("A red bicycle")],
+            &mut messages,
+            "",
+            &CitationOptions::default(),
+        )
+        .unwrap();
+
+        let parts = messages[0]["content"].as_array().unwrap();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[1]["type"], "image_url");
+        let text = parts[0]["text"].as_str().unwrap();
+        assert!(text.contains("<source id=\"1\" name=\"notes.txt\">A red bicycle</source>"));
+        assert!(text.contains("Compare it with my notes."));
+    }
+
+    fn web_source(url: &str, chunks: &[&str]) -> Source {
+        Source {
+            source: json!({"type": "web_search", "id": url, "name": "Bikes", "url": url}),
+            document: chunks.iter().map(|c| c.to_string()).collect(),
+            metadata: chunks.iter().map(|_| json!({"source": url})).collect(),
+        }
+    }
+
+    fn inject(options: CitationOptions) -> String {
+        let mut messages = vec![json!({"role": "user", "content": "Which bike?"})];
+        let sources = vec![
+            source("A red bicycle"),
+            web_source("https://bikes.example", &["Blue", "Green"]),
+        ];
+        inject_sources_into_messages(sources, &mut messages, "{{CONTEXT}}", &options).unwrap();
+        messages[0]["content"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_inline_citations() {
+        let options = CitationOptions::default();
+        let content = inject(options);
+        assert!(content.contains("<source id=\"1\" name=\"notes.txt\">A red bicycle</source>"));
+        assert!(content.contains("<source id=\"2\" name=\"Bikes\">Green</source>"));
+        assert!(!content.contains(FOOTNOTE_CITATION_INSTRUCTION));
+
+        let sources = vec![
+            source("A red bicycle"),
+            web_source("https://bikes.example", &["Blue", "Green"]),
+        ];
+        assert_eq!(
+            build_citations(&sources, &options),
+            vec![
+                json!({"id": 1, "type": null, "name": "notes.txt"}),
+                json!({
+                    "id": 2,
+                    "type": "web_search",
+                    "name": "Bikes",
+                    "url": "https://bikes.example",
+                }),
+            ]
+        );
+        assert_eq!(sources_for_response(&sources, &options).len(), 2);
+    }
+
+    #[test]
+    fn test_footnote_citations_without_source_names() {
+        let options = CitationOptions {
+            style: CitationStyle::parse("Footnotes"),
+            include_source: false,
+        };
+        let content = inject(options);
+        assert!(content.contains("<source id=\"1\">A red bicycle</source>"));
+        assert!(content.contains("<source id=\"2\">Blue</source>"));
+        assert!(!content.contains("bikes.example"));
+        assert!(content.contains(FOOTNOTE_CITATION_INSTRUCTION));
+
+        let sources = vec![web_source("https://bikes.example", &["Blue"])];
+        assert_eq!(
+            build_citations(&sources, &options),
+            vec![json!({"id": 1, "type": "web_search"})]
+        );
+        assert_eq!(
+            sources_for_response(&sources, &options),
+            vec![json!({
+                "source": {"type": "web_search", "id": "1"},
+                "document": ["Blue"],
+                "metadata": [{"source": "1"}],
+            })]
+        );
+    }
+
+    #[test]
+    fn test_no_citations() {
+        let options = CitationOptions {
+            style: CitationStyle::parse("none"),
+            include_source: true,
+        };
+        let content = inject(options);
+        assert!(content.contains("<source name=\"notes.txt\">A red bicycle</source>"));
+        assert!(!content.contains(" id="));
+
+        let sources = vec![source("A red bicycle")];
+        assert!(build_citations(&sources, &options).is_empty());
+        assert!(sources_for_response(&sources, &options).is_empty());
+    }
+
+    #[test]
+    fn test_add_text_part_to_image_only_message() {
+        let mut messages = vec![json!({
+            "role": "user",
+            "content": [{"type": "image_url", "image_url": {"url": "https://example.com/a.png"}}]
+        })];
+
+        add_or_update_user_message("Context", &mut messages, false);
+        assert_eq!(
+            messages[0]["content"][0],
+            json!({"type": "text", "text": "Context"})
+        );
+        assert_eq!(messages[0]["content"][1]["type"], "image_url");
+    }
+
+    /// Web search source with a document for each (text, score)
+    fn scored_source(url: &str, documents: &[(&str, f64)]) -> Source {
+        Source {
+            source: json!({"type": "web_search", "id": url, "name": url}),
+            document: documents.iter().map(|(text, _)| text.to_string()).collect(),
+            metadata: documents
+                .iter()
+                .map(|(_, score)| json!({"source": url, "score": score}))
+                .collect(),
+        }
+    }
+
+    fn documents(sources: &[Source]) -> Vec<&str> {
+        sources
+            .iter()
+            .flat_map(|s| s.document.iter().map(String::as_str))
+            .collect()
+    }
+
+    #[test]
+    fn test_relevance_threshold_boundary() {
+        let filter = RelevanceFilter {
+            threshold: 0.5,
+            max_sources: 0,
+        };
+        let sources = vec![
+            scored_source("https://a.example", &[("at", 0.5), ("just under", 0.4999)]),
+            scored_source("https://b.example", &[("under", 0.1)]),
+            source("attached"),
+        ];
+
+        let kept = filter_sources(sources.clone(), &filter);
+        // A score at the threshold clears it; attached documents have no score and stay
+        assert_eq!(documents(&kept), vec!["at", "attached"]);
+        assert_eq!(kept[0].metadata.len(), 1);
+
+        // A threshold of 0 keeps everything
+        let kept = filter_sources(sources, &RelevanceFilter::default());
+        assert_eq!(kept.len(), 3);
+    }
+
+    #[test]
+    fn test_nothing_relevant_skips_injection() {
+        let filter = RelevanceFilter {
+            threshold: 0.8,
+            max_sources: 0,
+        };
+        let kept = filter_sources(
+            vec![scored_source("https://a.example", &[("off topic", 0.79)])],
+            &filter,
+        );
+        assert!(kept.is_empty());
+
+        let mut messages = vec![json!({"role": "user", "content": "Question"})];
+        inject_sources_into_messages(kept, &mut messages, "", &CitationOptions::default()).unwrap();
+        assert_eq!(messages[0]["content"], "Question");
+    }
+
+    #[test]
+    fn test_max_sources_keeps_most_relevant_in_order() {
+        let filter = RelevanceFilter {
+            threshold: 0.0,
+            max_sources: 3,
+        };
+        let sources = vec![
+            scored_source("https://a.example", &[("a1", 0.2), ("a2", 0.9)]),
+            scored_source("https://b.example", &[("b1", 0.7), ("b2", 0.7)]),
+            source("attached"),
+        ];
+
+        let kept = filter_sources(sources, &filter);
+        // Attached first, then the best scores; ties keep their order
+        assert_eq!(documents(&kept), vec!["a2", "b1", "attached"]);
+    }
+}
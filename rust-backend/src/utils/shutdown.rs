@@ -0,0 +1,64 @@
+use actix_web::dev::ServerHandle;
+use actix_web::web;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::AppState;
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+            }
+            Err(e) => {
+                warn!("Failed to install SIGTERM handler: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Stop the server once a shutdown signal arrives, without losing streamed answers
+///
+/// New completions are refused while streams already running get `drain_timeout` to
+/// finish. Streams still going after that save their partial message (`done: false`)
+/// and tell the client the answer was interrupted. Socket.IO clients are then asked to
+/// reconnect, and the HTTP server stops.
+pub async fn graceful_shutdown(
+    state: web::Data<AppState>,
+    server: ServerHandle,
+    drain_timeout: Duration,
+) {
+    shutdown_signal().await;
+    info!(
+        "Shutdown requested, draining streaming completions for up to {:?}",
+        drain_timeout
+    );
+
+    let interrupted = state.tasks.drain(drain_timeout).await;
+    if interrupted > 0 {
+        warn!("Interrupted {} streaming completion(s)", interrupted);
+    }
+
+    if let Some(handler) = &state.socketio_handler {
+        handler.flush_ydoc_documents().await;
+        handler.flush_last_seen().await;
+        let notified = handler.notify_shutdown().await;
+        info!("Sent shutdown notice to {} Socket.IO session(s)", notified);
+    }
+
+    server.stop(true).await;
+}
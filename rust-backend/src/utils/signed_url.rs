@@ -0,0 +1,150 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The `expires` and `signature` query parameters of a signed URL
+#[derive(Debug, Default, Deserialize)]
+pub struct SignedQuery {
+    pub expires: Option<i64>,
+    pub signature: Option<String>,
+}
+
+impl SignedQuery {
+    /// Whether the request carries a signature at all
+    pub fn is_present(&self) -> bool {
+        self.expires.is_some() || self.signature.is_some()
+    }
+}
+
+fn url_mac(secret: &str, path: &str, expires: i64) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(path.as_bytes());
+    mac.update(b"\n");
+    mac.update(expires.to_string().as_bytes());
+    mac
+}
+
+/// Sign `path` so it can be fetched without a session until it expires
+///
+/// Signed with `webui_secret_key`, valid for `signed_url_expiry` seconds. The path is
+/// returned unchanged when signing is disabled.
+pub fn sign_path(config: &Config, path: &str) -> String {
+    if !config.enable_signed_urls {
+        return path.to_string();
+    }
+
+    let expires = chrono::Utc::now().timestamp() + config.signed_url_expiry as i64;
+    let signature = url_mac(&config.webui_secret_key, path, expires)
+        .finalize()
+        .into_bytes();
+    format!(
+        "{}?expires={}&signature={}",
+        path,
+        expires,
+        URL_SAFE_NO_PAD.encode(signature)
+    )
+}
+
+/// Check a request for `path` against its signature
+///
+/// Missing, expired and invalid signatures are all rejected with 403. Every request is
+/// accepted when signing is disabled.
+pub fn verify_path(config: &Config, path: &str, query: &SignedQuery) -> AppResult<()> {
+    if !config.enable_signed_urls {
+        return Ok(());
+    }
+
+    let (expires, signature) = match (query.expires, &query.signature) {
+        (Some(expires), Some(signature)) => (expires, signature),
+        _ => return Err(AppError::Forbidden("Signed URL required".to_string())),
+    };
+    if expires < chrono::Utc::now().timestamp() {
+        return Err(AppError::Forbidden("Signed URL has expired".to_string()));
+    }
+
+    let invalid = || AppError::Forbidden("Invalid URL signature".to_string());
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+    url_mac(&config.webui_secret_key, path, expires)
+        .verify_slice(&signature)
+        .map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_of(url: &str) -> SignedQuery {
+        let query = url.split_once('?').unwrap().1;
+        actix_web::web::Query::<SignedQuery>::from_query(query)
+            .unwrap()
+            .into_inner()
+    }
+
+    fn config() -> Config {
+        let mut config = Config::from_env().unwrap();
+        config.webui_secret_key = "test-secret".to_string();
+        config.enable_signed_urls = true;
+        config.signed_url_expiry = 3600;
+        config
+    }
+
+    #[test]
+    fn test_signed_path_round_trips() {
+        let config = config();
+        let url = sign_path(&config, "/cache/audio/speech/abc.mp3");
+        assert!(url.starts_with("/cache/audio/speech/abc.mp3?expires="));
+
+        let query = query_of(&url);
+        assert!(verify_path(&config, "/cache/audio/speech/abc.mp3", &query).is_ok());
+        // The signature only covers the path it was made for
+        assert!(verify_path(&config, "/cache/audio/speech/other.mp3", &query).is_err());
+    }
+
+    #[test]
+    fn test_unsigned_expired_and_forged_are_forbidden() {
+        let config = config();
+        let path = "/api/v1/files/123/content";
+
+        let err = verify_path(&config, path, &SignedQuery::default()).unwrap_err();
+        assert!(matches!(err, AppError::Forbidden(_)));
+
+        let expires = chrono::Utc::now().timestamp() - 10;
+        let signature = url_mac(&config.webui_secret_key, path, expires)
+            .finalize()
+            .into_bytes();
+        let query = SignedQuery {
+            expires: Some(expires),
+            signature: Some(URL_SAFE_NO_PAD.encode(signature)),
+        };
+        match verify_path(&config, path, &query) {
+            Err(AppError::Forbidden(message)) => assert!(message.contains("expired")),
+            other => panic!("expected 403, got {:?}", other),
+        }
+
+        let mut query = query_of(&sign_path(&config, path));
+        query.signature = Some(URL_SAFE_NO_PAD.encode(b"forged"));
+        assert!(verify_path(&config, path, &query).is_err());
+
+        let other_key = Config {
+            webui_secret_key: "other-secret".to_string(),
+            ..config.clone()
+        };
+        let query = query_of(&sign_path(&other_key, path));
+        assert!(verify_path(&config, path, &query).is_err());
+    }
+
+    #[test]
+    fn test_signing_disabled() {
+        let mut config = config();
+        config.enable_signed_urls = false;
+        assert_eq!(sign_path(&config, "/cache/x.png"), "/cache/x.png");
+        assert!(verify_path(&config, "/cache/x.png", &SignedQuery::default()).is_ok());
+    }
+}
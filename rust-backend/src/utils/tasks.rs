@@ -3,13 +3,34 @@ use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{watch, RwLock};
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
 use crate::error::{AppError, AppResult};
+use crate::middleware::request_id::propagate;
+use crate::utils::redis_health::RedisPool;
+
+/// Wait between attempts to re-establish the Redis command subscription
+const LISTENER_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How often a drain checks whether the remaining tasks have finished
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time cancelled tasks get to save their partial output before they are aborted
+const CANCEL_PERSIST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most chunks a stream buffer keeps for clients that reconnect mid-stream
+const STREAM_BUFFER_CAPACITY: usize = 4096;
+
+/// How long a finished stream can still be resumed
+const STREAM_RETENTION: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
@@ -28,24 +49,200 @@ pub enum TaskStatus {
     Cancelled,
 }
 
+/// How a buffered stream ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEnd {
+    Done,
+    Failed(String),
+}
+
+/// Chunks of a stream after a given sequence number
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamReplay {
+    pub chunks: Vec<(u64, String)>,
+    pub end: Option<StreamEnd>,
+}
+
+#[derive(Default)]
+struct StreamState {
+    chunks: VecDeque<(u64, String)>,
+    /// Sequence number of the last chunk pushed; the first chunk is 1
+    last_seq: u64,
+    /// Highest sequence number dropped to stay within the capacity
+    evicted_seq: u64,
+    end: Option<StreamEnd>,
+}
+
+/// Output of a streaming task, kept so a client that lost its connection can resume
+/// from the last chunk it received
+pub struct StreamBuffer {
+    pub id: String,
+    /// User the stream belongs to; only they may resume it
+    pub owner: String,
+    capacity: usize,
+    finished: AtomicBool,
+    state: Mutex<StreamState>,
+    updates: watch::Sender<u64>,
+}
+
+impl StreamBuffer {
+    pub fn new(id: String, owner: String, capacity: usize) -> Self {
+        Self {
+            id,
+            owner,
+            capacity: capacity.max(1),
+            finished: AtomicBool::new(false),
+            state: Mutex::new(StreamState::default()),
+            updates: watch::channel(0).0,
+        }
+    }
+
+    /// Append a chunk, returning its sequence number
+    pub fn push(&self, data: String) -> u64 {
+        let seq = {
+            let mut state = self.state.lock().unwrap();
+            state.last_seq += 1;
+            let seq = state.last_seq;
+            state.chunks.push_back((seq, data));
+            while state.chunks.len() > self.capacity {
+                if let Some((evicted, _)) = state.chunks.pop_front() {
+                    state.evicted_seq = evicted;
+                }
+            }
+            seq
+        };
+        self.updates.send_replace(seq);
+        seq
+    }
+
+    /// End the stream; later calls are ignored
+    pub fn finish(&self, end: StreamEnd) {
+        let seq = {
+            let mut state = self.state.lock().unwrap();
+            if state.end.is_some() {
+                return;
+            }
+            state.end = Some(end);
+            state.last_seq
+        };
+        self.finished.store(true, Ordering::SeqCst);
+        self.updates.send_replace(seq);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    /// Sequence number of the last chunk pushed
+    pub fn last_seq(&self) -> u64 {
+        self.state.lock().unwrap().last_seq
+    }
+
+    /// Chunks after `last_seq` and how the stream ended, if it has. `None` when some of
+    /// those chunks are no longer buffered.
+    pub fn replay_after(&self, last_seq: u64) -> Option<StreamReplay> {
+        let state = self.state.lock().unwrap();
+        if last_seq < state.evicted_seq {
+            return None;
+        }
+        Some(StreamReplay {
+            chunks: state
+                .chunks
+                .iter()
+                .filter(|(seq, _)| *seq > last_seq)
+                .cloned()
+                .collect(),
+            end: state.end.clone(),
+        })
+    }
+
+    /// Notified whenever a chunk is pushed or the stream ends
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.updates.subscribe()
+    }
+}
+
+/// Ends the buffer when the task finishes or is aborted, then drops it after the
+/// retention period
+struct StreamGuard {
+    streams: Arc<RwLock<HashMap<String, Arc<StreamBuffer>>>>,
+    buffer: Arc<StreamBuffer>,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.buffer
+            .finish(StreamEnd::Failed("Stream stopped".to_string()));
+
+        let streams = self.streams.clone();
+        let stream_id = self.buffer.id.clone();
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                tokio::time::sleep(STREAM_RETENTION).await;
+                streams.write().await.remove(&stream_id);
+            });
+        }
+    }
+}
+
 #[allow(dead_code)]
+#[derive(Clone)]
 pub struct TaskManager {
     // In-memory task storage
     tasks: Arc<RwLock<HashMap<String, JoinHandle<()>>>>,
     // Item ID -> Task IDs mapping
     item_tasks: Arc<RwLock<HashMap<String, Vec<String>>>>,
     // Redis pool (optional)
-    redis: Option<deadpool_redis::Pool>,
+    redis: Option<RedisPool>,
     // Redis URL for pub/sub
     redis_url: Option<String>,
     // Redis key prefix
     redis_key_prefix: String,
+    // Set once shutdown starts; no new tasks should be started
+    draining: Arc<AtomicBool>,
+    // Flipped to true when running tasks must stop and save what they have
+    cancel: Arc<watch::Sender<bool>>,
+    // Stream ID -> buffered output, kept for a while after the stream ends
+    streams: Arc<RwLock<HashMap<String, Arc<StreamBuffer>>>>,
+    // Task ID -> token cancelling it, for tasks that save their output when stopped
+    cancellations: Arc<RwLock<HashMap<String, CancellationToken>>>,
+    // Task ID -> user who started it, for tasks run on a user's behalf
+    owners: Arc<RwLock<HashMap<String, String>>>,
+}
+
+/// Stop a task running on this instance. Cancellable tasks are told to stop and get a few
+/// seconds to save their output; others are aborted right away.
+async fn stop_local_task(
+    tasks: &RwLock<HashMap<String, JoinHandle<()>>>,
+    cancellations: &RwLock<HashMap<String, CancellationToken>>,
+    task_id: &str,
+) -> bool {
+    let mut handle = match tasks.write().await.remove(task_id) {
+        Some(handle) => handle,
+        None => return false,
+    };
+    match cancellations.write().await.remove(task_id) {
+        Some(token) => {
+            token.cancel();
+            tokio::spawn(async move {
+                if tokio::time::timeout(CANCEL_PERSIST_TIMEOUT, &mut handle)
+                    .await
+                    .is_err()
+                {
+                    warn!("Aborting stopped task that ignored cancellation");
+                    handle.abort();
+                }
+            });
+        }
+        None => handle.abort(),
+    }
+    true
 }
 
 #[allow(dead_code)]
 impl TaskManager {
     pub fn new(
-        redis: Option<deadpool_redis::Pool>,
+        redis: Option<RedisPool>,
         redis_url: Option<String>,
         redis_key_prefix: String,
     ) -> Self {
@@ -55,24 +252,53 @@ impl TaskManager {
             redis,
             redis_url,
             redis_key_prefix,
+            draining: Arc::new(AtomicBool::new(false)),
+            cancel: Arc::new(watch::channel(false).0),
+            streams: Arc::new(RwLock::new(HashMap::new())),
+            cancellations: Arc::new(RwLock::new(HashMap::new())),
+            owners: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
     /// Create a new task
+    ///
+    /// The task is removed from the registry again once it finishes.
     pub async fn create_task<F>(&self, future: F, item_id: Option<String>) -> AppResult<String>
     where
         F: std::future::Future<Output = ()> + Send + 'static,
     {
-        let task_id = Uuid::new_v4().to_string();
-
-        // Create the tokio task
-        let task_handle = tokio::spawn(future);
+        self.spawn_task(future, item_id, None, None).await
+    }
 
-        // Store in memory
-        self.tasks
-            .write()
+    /// Create a task for `owner` that is stopped through the token passed to `task`
+    /// rather than aborted, so it can save its partial output first
+    pub async fn create_cancellable_task<F, Fut>(
+        &self,
+        owner: &str,
+        item_id: Option<String>,
+        task: F,
+    ) -> AppResult<String>
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let token = CancellationToken::new();
+        let future = task(token.clone());
+        self.spawn_task(future, item_id, Some(owner), Some(token))
             .await
-            .insert(task_id.clone(), task_handle);
+    }
+
+    async fn spawn_task<F>(
+        &self,
+        future: F,
+        item_id: Option<String>,
+        owner: Option<&str>,
+        cancellation: Option<CancellationToken>,
+    ) -> AppResult<String>
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let task_id = Uuid::new_v4().to_string();
 
         // Store item mapping
         if let Some(item_id) = &item_id {
@@ -85,7 +311,7 @@ impl TaskManager {
 
         // Store in Redis if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let tasks_key = format!("{}:tasks", self.redis_key_prefix);
                 let item_value = item_id.as_deref().unwrap_or("");
 
@@ -100,33 +326,189 @@ impl TaskManager {
                     let _: Result<(), redis::RedisError> =
                         conn.sadd(&item_tasks_key, &task_id).await;
                 }
+
+                if let Some(owner) = owner {
+                    let owners_key = format!("{}:tasks:owners", self.redis_key_prefix);
+                    let _: Result<(), redis::RedisError> =
+                        conn.hset(&owners_key, &task_id, owner).await;
+                }
             }
         }
 
+        if let Some(owner) = owner {
+            self.owners
+                .write()
+                .await
+                .insert(task_id.clone(), owner.to_string());
+        }
+
+        if let Some(token) = cancellation {
+            self.cancellations
+                .write()
+                .await
+                .insert(task_id.clone(), token);
+        }
+
+        // Create the tokio task while holding the lock, so its cleanup can't run
+        // before the handle is stored
+        let mut tasks = self.tasks.write().await;
+        let manager = self.clone();
+        let cleanup_id = task_id.clone();
+        let cleanup_item_id = item_id.clone();
+        let task_handle = tokio::spawn(propagate(async move {
+            future.await;
+            let _ = manager.cleanup_task(&cleanup_id, cleanup_item_id).await;
+        }));
+        tasks.insert(task_id.clone(), task_handle);
+        drop(tasks);
+
         info!("Created task {} for item {:?}", task_id, item_id);
         Ok(task_id)
     }
 
+    /// Create a task whose output goes to a [`StreamBuffer`] that clients can resume
+    /// from
+    ///
+    /// Fails when `owner` already has `max_per_user` streams running. The buffer is
+    /// ended when `task` returns, as a failure unless the task finished it itself.
+    pub async fn create_stream_task<F, Fut>(
+        &self,
+        owner: &str,
+        max_per_user: usize,
+        item_id: Option<String>,
+        task: F,
+    ) -> AppResult<Arc<StreamBuffer>>
+    where
+        F: FnOnce(Arc<StreamBuffer>) -> Fut,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        if self.is_draining() {
+            return Err(AppError::ServiceUnavailable(
+                "Server is shutting down".to_string(),
+            ));
+        }
+
+        let buffer = Arc::new(StreamBuffer::new(
+            Uuid::new_v4().to_string(),
+            owner.to_string(),
+            STREAM_BUFFER_CAPACITY,
+        ));
+        {
+            // Counted and registered under one lock so concurrent starts can't
+            // both slip under the limit
+            let mut streams = self.streams.write().await;
+            let running = streams
+                .values()
+                .filter(|s| s.owner == owner && !s.is_finished())
+                .count();
+            if running >= max_per_user {
+                return Err(AppError::TooManyRequests(format!(
+                    "At most {} streams may run at once",
+                    max_per_user
+                )));
+            }
+            streams.insert(buffer.id.clone(), buffer.clone());
+        }
+
+        let guard = StreamGuard {
+            streams: self.streams.clone(),
+            buffer: buffer.clone(),
+        };
+        let future = task(buffer.clone());
+        let created = self
+            .spawn_task(
+                async move {
+                    let _guard = guard;
+                    future.await;
+                },
+                item_id,
+                Some(owner),
+                None,
+            )
+            .await;
+        if let Err(e) = created {
+            self.streams.write().await.remove(&buffer.id);
+            return Err(e);
+        }
+
+        Ok(buffer)
+    }
+
+    /// Buffered output of a stream that is running or finished recently
+    pub async fn stream_buffer(&self, stream_id: &str) -> Option<Arc<StreamBuffer>> {
+        self.streams.read().await.get(stream_id).cloned()
+    }
+
+    /// Item (e.g. chat) a task on this instance runs for
+    pub async fn task_item_id(&self, task_id: &str) -> Option<String> {
+        let item_tasks = self.item_tasks.read().await;
+        item_tasks
+            .iter()
+            .find(|(_, tasks)| tasks.iter().any(|id| id == task_id))
+            .map(|(id, _)| id.clone())
+    }
+
+    /// User a task was started for, if it runs on someone's behalf
+    pub async fn task_owner(&self, task_id: &str) -> Option<String> {
+        if let Some(owner) = self.owners.read().await.get(task_id) {
+            return Some(owner.clone());
+        }
+
+        // The task may run on another instance
+        let redis = self.redis.as_ref()?;
+        let mut conn = redis.get().await?;
+        let owners_key = format!("{}:tasks:owners", self.redis_key_prefix);
+        conn.hget(&owners_key, task_id).await.ok().flatten()
+    }
+
+    /// Stop a task on behalf of `user_id`, who must have started it unless they are an
+    /// admin. Returns false, leaving the task running, when it isn't theirs.
+    pub async fn stop_task_as(
+        &self,
+        task_id: &str,
+        user_id: &str,
+        is_admin: bool,
+    ) -> AppResult<bool> {
+        if !is_admin && self.task_owner(task_id).await.as_deref() != Some(user_id) {
+            return Ok(false);
+        }
+        self.stop_task(task_id).await?;
+        Ok(true)
+    }
+
+    /// Stop the tasks of an item that `user_id` started, or all of them for an admin.
+    /// Returns false when the item has tasks running but none of them are the user's.
+    pub async fn stop_item_tasks_as(
+        &self,
+        item_id: &str,
+        user_id: &str,
+        is_admin: bool,
+    ) -> AppResult<bool> {
+        let task_ids = self.list_tasks_by_item(item_id).await?;
+        let mut allowed = task_ids.is_empty();
+
+        for task_id in task_ids {
+            match self.stop_task_as(&task_id, user_id, is_admin).await {
+                Ok(stopped) => allowed |= stopped,
+                Err(e) => warn!("Failed to stop task {}: {}", task_id, e),
+            }
+        }
+
+        Ok(allowed)
+    }
+
     /// Stop a task by ID
     pub async fn stop_task(&self, task_id: &str) -> AppResult<()> {
-        // Find the item_id for this task
-        let item_id = {
-            let item_tasks = self.item_tasks.read().await;
-            item_tasks
-                .iter()
-                .find(|(_, tasks)| tasks.contains(&task_id.to_string()))
-                .map(|(id, _)| id.clone())
-        };
+        let item_id = self.task_item_id(task_id).await;
 
         // Stop local task
-        if let Some(handle) = self.tasks.write().await.remove(task_id) {
-            handle.abort();
+        if stop_local_task(&self.tasks, &self.cancellations, task_id).await {
             info!("Stopped local task {}", task_id);
         }
 
         // Send stop command via Redis pub/sub if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let pubsub_channel = format!("{}:tasks:commands", self.redis_key_prefix);
                 let command = json!({
                     "action": "stop",
@@ -150,6 +532,8 @@ impl TaskManager {
     async fn cleanup_task(&self, task_id: &str, item_id: Option<String>) -> AppResult<()> {
         // Remove from memory
         self.tasks.write().await.remove(task_id);
+        self.cancellations.write().await.remove(task_id);
+        self.owners.write().await.remove(task_id);
 
         // Remove from item mapping
         if let Some(item_id) = &item_id {
@@ -164,7 +548,7 @@ impl TaskManager {
 
         // Clean up Redis if available
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let tasks_key = format!("{}:tasks", self.redis_key_prefix);
 
                 // Get item_id from Redis if not provided
@@ -175,6 +559,8 @@ impl TaskManager {
 
                 // Remove task from hash
                 let _: Result<(), redis::RedisError> = conn.hdel(&tasks_key, task_id).await;
+                let owners_key = format!("{}:tasks:owners", self.redis_key_prefix);
+                let _: Result<(), redis::RedisError> = conn.hdel(&owners_key, task_id).await;
 
                 // Remove from item set
                 if let Some(item_id) = item_id_to_clean {
@@ -198,7 +584,7 @@ impl TaskManager {
 
         // If Redis is available, also get remote tasks
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let tasks_key = format!("{}:tasks", self.redis_key_prefix);
                 let redis_tasks: Result<Vec<String>, redis::RedisError> =
                     conn.hkeys(&tasks_key).await;
@@ -232,7 +618,7 @@ impl TaskManager {
 
         // If Redis is available, also get remote tasks
         if let Some(redis) = &self.redis {
-            if let Ok(mut conn) = redis.get().await {
+            if let Some(mut conn) = redis.get().await {
                 let item_tasks_key = format!("{}:tasks:item:{}", self.redis_key_prefix, item_id);
                 let redis_tasks: Result<Vec<String>, redis::RedisError> =
                     conn.smembers(&item_tasks_key).await;
@@ -266,6 +652,66 @@ impl TaskManager {
         Ok(())
     }
 
+    /// Whether shutdown has started and new tasks should be refused
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once running tasks are told to stop for shutdown; they should save
+    /// their partial output and return
+    pub async fn cancelled(&self) {
+        let mut cancel = self.cancel.subscribe();
+        let _ = cancel.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// Number of local tasks still running
+    async fn running_count(&self) -> usize {
+        self.tasks
+            .read()
+            .await
+            .values()
+            .filter(|handle| !handle.is_finished())
+            .count()
+    }
+
+    /// Stop taking new tasks and wait up to `grace` for running ones to finish
+    ///
+    /// Tasks still running after that are cancelled through [`TaskManager::cancelled`]
+    /// and given a few seconds to save their output before they are aborted. Returns
+    /// how many tasks had to be cancelled.
+    pub async fn drain(&self, grace: Duration) -> usize {
+        self.draining.store(true, Ordering::SeqCst);
+
+        let deadline = tokio::time::Instant::now() + grace;
+        while self.running_count().await > 0 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+
+        let interrupted = self.running_count().await;
+        if interrupted == 0 {
+            return 0;
+        }
+
+        warn!(
+            "Cancelling {} task(s) still running after {:?}",
+            interrupted, grace
+        );
+        self.cancel.send_replace(true);
+
+        let deadline = tokio::time::Instant::now() + CANCEL_PERSIST_TIMEOUT;
+        while self.running_count().await > 0 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+        for (task_id, handle) in self.tasks.write().await.drain() {
+            if !handle.is_finished() {
+                warn!("Aborting task {} that ignored cancellation", task_id);
+                handle.abort();
+            }
+        }
+
+        interrupted
+    }
+
     /// Start listening to Redis pub/sub commands
     pub async fn start_redis_listener(&self) -> AppResult<()> {
         if let Some(redis) = &self.redis {
@@ -274,6 +720,7 @@ impl TaskManager {
             info!("Starting Redis task command listener on {}", pubsub_channel);
 
             let tasks = self.tasks.clone();
+            let cancellations = self.cancellations.clone();
             let _redis_clone = redis.clone();
             let _redis_key_prefix = self.redis_key_prefix.clone();
 
@@ -287,59 +734,67 @@ impl TaskManager {
                 redis::Client::open(redis_url).map_err(|e| AppError::Redis(e.to_string()))?;
 
             tokio::spawn(async move {
-                let mut pubsub = match client.get_async_pubsub().await {
-                    Ok(pubsub) => pubsub,
-                    Err(e) => {
-                        error!("Failed to get Redis pubsub connection: {}", e);
-                        return;
-                    }
-                };
-
-                if let Err(e) = pubsub.subscribe(&pubsub_channel).await {
-                    error!("Failed to subscribe to Redis channel: {}", e);
-                    return;
-                }
-
-                let mut message_stream = pubsub.on_message();
+                // Keep resubscribing so a Redis restart doesn't silently end cross-instance
+                // stop commands; failures are only logged when the connection state changes
+                let mut connected = true;
                 loop {
-                    match message_stream.next().await {
-                        Some(msg) => {
-                            let payload: String = match msg.get_payload() {
-                                Ok(p) => p,
-                                Err(e) => {
-                                    error!("Failed to get message payload: {}", e);
-                                    continue;
-                                }
-                            };
+                    let mut pubsub = match client.get_async_pubsub().await {
+                        Ok(pubsub) => pubsub,
+                        Err(e) => {
+                            if connected {
+                                error!("Failed to get Redis pubsub connection: {}", e);
+                                connected = false;
+                            }
+                            tokio::time::sleep(LISTENER_RETRY_DELAY).await;
+                            continue;
+                        }
+                    };
 
-                            match serde_json::from_str::<serde_json::Value>(&payload) {
-                                Ok(command) => {
-                                    if let Some("stop") =
-                                        command.get("action").and_then(|v| v.as_str())
+                    if let Err(e) = pubsub.subscribe(&pubsub_channel).await {
+                        if connected {
+                            error!("Failed to subscribe to Redis channel: {}", e);
+                            connected = false;
+                        }
+                        tokio::time::sleep(LISTENER_RETRY_DELAY).await;
+                        continue;
+                    }
+                    if !connected {
+                        info!("Resubscribed to Redis channel {}", pubsub_channel);
+                        connected = true;
+                    }
+
+                    let mut message_stream = pubsub.on_message();
+                    while let Some(msg) = message_stream.next().await {
+                        let payload: String = match msg.get_payload() {
+                            Ok(p) => p,
+                            Err(e) => {
+                                error!("Failed to get message payload: {}", e);
+                                continue;
+                            }
+                        };
+
+                        match serde_json::from_str::<serde_json::Value>(&payload) {
+                            Ok(command) => {
+                                if let Some("stop") = command.get("action").and_then(|v| v.as_str())
+                                {
+                                    if let Some(task_id) =
+                                        command.get("task_id").and_then(|v| v.as_str())
                                     {
-                                        if let Some(task_id) =
-                                            command.get("task_id").and_then(|v| v.as_str())
-                                        {
-                                            // Stop local task if it exists
-                                            if let Some(handle) =
-                                                tasks.write().await.remove(task_id)
-                                            {
-                                                handle.abort();
-                                                info!("Stopped task {} via Redis command", task_id);
-                                            }
+                                        // Stop local task if it exists
+                                        if stop_local_task(&tasks, &cancellations, task_id).await {
+                                            info!("Stopped task {} via Redis command", task_id);
                                         }
                                     }
                                 }
-                                Err(e) => {
-                                    error!("Failed to parse command JSON: {}", e);
-                                }
                             }
-                        }
-                        None => {
-                            warn!("Redis pub/sub connection closed");
-                            break;
+                            Err(e) => {
+                                error!("Failed to parse command JSON: {}", e);
+                            }
                         }
                     }
+
+                    warn!("Redis pub/sub connection closed, reconnecting");
+                    tokio::time::sleep(LISTENER_RETRY_DELAY).await;
                 }
             });
         }
@@ -393,6 +848,75 @@ mod tests {
         assert_eq!(tasks.len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_stopped_cancellable_task_saves_its_output() {
+        let manager = TaskManager::new(None, None, "test".to_string());
+        let (saved_tx, saved_rx) = tokio::sync::oneshot::channel();
+
+        let task_id = manager
+            .create_cancellable_task("user-1", Some("chat".to_string()), |token| async move {
+                token.cancelled().await;
+                // Still running after it was told to stop
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                let _ = saved_tx.send("partial content");
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            manager.task_item_id(&task_id).await.as_deref(),
+            Some("chat")
+        );
+
+        manager.stop_task(&task_id).await.unwrap();
+        assert!(manager.list_tasks().await.unwrap().is_empty());
+        assert_eq!(saved_rx.await.unwrap(), "partial content");
+        assert!(manager.cancellations.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_only_owner_or_admin_stops_task() {
+        let manager = TaskManager::new(None, None, "test".to_string());
+
+        let task_id = manager
+            .create_cancellable_task("user-a", Some("chat-a".to_string()), |token| async move {
+                token.cancelled().await;
+            })
+            .await
+            .unwrap();
+
+        // Another user can neither stop the task nor the chat's tasks
+        assert!(!manager
+            .stop_task_as(&task_id, "user-b", false)
+            .await
+            .unwrap());
+        assert!(!manager
+            .stop_item_tasks_as("chat-a", "user-b", false)
+            .await
+            .unwrap());
+        assert_eq!(manager.list_tasks().await.unwrap(), vec![task_id.clone()]);
+
+        // The owner can
+        assert!(manager
+            .stop_task_as(&task_id, "user-a", false)
+            .await
+            .unwrap());
+        assert!(manager.list_tasks().await.unwrap().is_empty());
+        assert_eq!(manager.task_owner(&task_id).await, None);
+
+        // And so can an admin
+        manager
+            .create_cancellable_task("user-a", Some("chat-a".to_string()), |token| async move {
+                token.cancelled().await;
+            })
+            .await
+            .unwrap();
+        assert!(manager
+            .stop_item_tasks_as("chat-a", "admin", true)
+            .await
+            .unwrap());
+        assert!(manager.list_tasks().await.unwrap().is_empty());
+    }
+
     #[tokio::test]
     async fn test_task_manager_item_tasks() {
         let manager = TaskManager::new(None, None, "test".to_string());
@@ -428,4 +952,147 @@ mod tests {
         let item_tasks = manager.list_tasks_by_item(&item_id).await.unwrap();
         assert_eq!(item_tasks.len(), 0);
     }
+
+    #[tokio::test]
+    async fn test_task_removed_when_finished() {
+        let manager = TaskManager::new(None, None, "test".to_string());
+
+        manager
+            .create_task(async {}, Some("test-item".to_string()))
+            .await
+            .unwrap();
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+
+        assert!(manager.list_tasks().await.unwrap().is_empty());
+        assert!(manager
+            .list_tasks_by_item("test-item")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_stream_buffer_replays_after_seq() {
+        let buffer = StreamBuffer::new("stream".to_string(), "user-1".to_string(), 3);
+
+        for chunk in ["a", "b", "c"] {
+            buffer.push(chunk.to_string());
+        }
+        let replay = buffer.replay_after(1).unwrap();
+        assert_eq!(
+            replay.chunks,
+            vec![(2, "b".to_string()), (3, "c".to_string())]
+        );
+        assert_eq!(replay.end, None);
+
+        // Chunks beyond the capacity push out the oldest ones
+        buffer.push("d".to_string());
+        assert!(buffer.replay_after(0).is_none());
+        assert_eq!(buffer.replay_after(1).unwrap().chunks.len(), 3);
+
+        buffer.finish(StreamEnd::Done);
+        buffer.finish(StreamEnd::Failed("late".to_string()));
+        assert!(buffer.is_finished());
+        let replay = buffer.replay_after(4).unwrap();
+        assert!(replay.chunks.is_empty());
+        assert_eq!(replay.end, Some(StreamEnd::Done));
+    }
+
+    #[tokio::test]
+    async fn test_stream_tasks_are_limited_per_user() {
+        let manager = TaskManager::new(None, None, "test".to_string());
+        let (release_tx, release_rx) = watch::channel(false);
+
+        let first = manager
+            .create_stream_task("user-1", 1, None, |buffer| {
+                let mut release = release_rx.clone();
+                async move {
+                    buffer.push("partial".to_string());
+                    let _ = release.wait_for(|released| *released).await;
+                    buffer.finish(StreamEnd::Done);
+                }
+            })
+            .await
+            .unwrap();
+
+        let limited = manager
+            .create_stream_task("user-1", 1, None, |_| async {})
+            .await;
+        assert!(matches!(limited, Err(AppError::TooManyRequests(_))));
+        // Other users have their own limit
+        manager
+            .create_stream_task("user-2", 1, None, |_| async {})
+            .await
+            .unwrap();
+
+        release_tx.send_replace(true);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The finished stream can still be resumed and no longer counts
+        let buffer = manager.stream_buffer(&first.id).await.unwrap();
+        let replay = buffer.replay_after(0).unwrap();
+        assert_eq!(replay.chunks, vec![(1, "partial".to_string())]);
+        assert_eq!(replay.end, Some(StreamEnd::Done));
+        manager
+            .create_stream_task("user-1", 1, None, |_| async {})
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stopped_stream_task_ends_buffer() {
+        let manager = TaskManager::new(None, None, "test".to_string());
+
+        let buffer = manager
+            .create_stream_task("user-1", 1, Some("chat".to_string()), |_| {
+                tokio::time::sleep(Duration::from_secs(10))
+            })
+            .await
+            .unwrap();
+        manager.stop_item_tasks("chat").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(buffer.is_finished());
+        assert_eq!(
+            buffer.replay_after(0).unwrap().end,
+            Some(StreamEnd::Failed("Stream stopped".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drain_cancels_tasks_past_grace_period() {
+        let manager = TaskManager::new(None, None, "test".to_string());
+
+        // Finishes within the grace period
+        manager
+            .create_task(
+                async {
+                    tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        // Only stops when cancelled, saving its output first
+        let (saved_tx, saved_rx) = tokio::sync::oneshot::channel();
+        let stream = manager.clone();
+        manager
+            .create_task(
+                async move {
+                    stream.cancelled().await;
+                    let _ = saved_tx.send("partial content");
+                },
+                Some("chat".to_string()),
+            )
+            .await
+            .unwrap();
+
+        let interrupted = manager.drain(Duration::from_millis(200)).await;
+
+        assert!(manager.is_draining());
+        assert_eq!(interrupted, 1);
+        assert_eq!(saved_rx.await.unwrap(), "partial content");
+        assert!(manager.list_tasks().await.unwrap().is_empty());
+    }
 }
@@ -0,0 +1,197 @@
+use once_cell::sync::OnceCell;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use std::collections::HashMap;
+use tracing::{Instrument, Span, Subscriber};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::middleware::request_id::WithRequestId;
+use crate::utils::metrics::endpoint_label;
+
+const SERVICE_NAME: &str = "open-webui-rust";
+
+static TRACER_PROVIDER: OnceCell<SdkTracerProvider> = OnceCell::new();
+
+/// Tracing layer exporting spans over OTLP, when an endpoint is configured
+///
+/// Configured with the standard `OTEL_EXPORTER_OTLP_ENDPOINT` (or
+/// `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`), `OTEL_EXPORTER_OTLP_PROTOCOL` (`grpc` or
+/// `http/protobuf`, the default), `OTEL_EXPORTER_OTLP_HEADERS` and `OTEL_SERVICE_NAME`
+/// variables. Returns `None`, leaving tracing to the log output alone, when no endpoint
+/// is set; W3C `traceparent` headers are only read and sent while exporting.
+pub fn otlp_layer<S>() -> anyhow::Result<Option<OpenTelemetryLayer<S, SdkTracer>>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let endpoint_set = [
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    ]
+    .iter()
+    .any(|name| std::env::var(name).map(|v| !v.is_empty()).unwrap_or(false));
+    if !endpoint_set {
+        return Ok(None);
+    }
+
+    let protocol = std::env::var("OTEL_EXPORTER_OTLP_TRACES_PROTOCOL")
+        .or_else(|_| std::env::var("OTEL_EXPORTER_OTLP_PROTOCOL"))
+        .unwrap_or_default();
+    let exporter = if protocol == "grpc" {
+        opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .build()?
+    } else {
+        opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .build()?
+    };
+
+    let mut resource = Resource::builder();
+    if std::env::var("OTEL_SERVICE_NAME").is_err() {
+        resource = resource.with_service_name(SERVICE_NAME);
+    }
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build();
+
+    let tracer = provider.tracer(SERVICE_NAME);
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    let _ = TRACER_PROVIDER.set(provider);
+
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Flush spans still buffered for export; called on shutdown
+pub fn shutdown() {
+    if let Some(provider) = TRACER_PROVIDER.get() {
+        if let Err(e) = provider.shutdown() {
+            eprintln!("Failed to flush OpenTelemetry spans: {}", e);
+        }
+    }
+}
+
+struct HeaderExtractor<'a>(&'a actix_web::http::header::HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HashMap<String, String>);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.insert(key.to_string(), value);
+    }
+}
+
+/// Continue the trace a caller started, from its `traceparent` header
+pub fn set_remote_parent(span: &Span, headers: &actix_web::http::header::HeaderMap) {
+    let context = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    });
+    span.set_parent(context);
+}
+
+/// `traceparent`/`tracestate` headers for the current span, empty while not exporting
+pub fn trace_headers() -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    let context = Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+/// Span around a call to an upstream service, tagged with the endpoint and model
+pub fn upstream_span(operation: &'static str, endpoint_url: &str, model: Option<&str>) -> Span {
+    tracing::info_span!(
+        "upstream",
+        otel.name = operation,
+        otel.kind = "client",
+        otel.status_code = tracing::field::Empty,
+        server.address = %endpoint_label(endpoint_url),
+        model = model.unwrap_or(""),
+        http.response.status_code = tracing::field::Empty,
+    )
+}
+
+/// Send an upstream request as a child span of the current request
+///
+/// The request carries the span's trace context and the request id, and the span
+/// records the response status.
+pub async fn send_traced(
+    request: reqwest::RequestBuilder,
+    span: Span,
+) -> reqwest::Result<reqwest::Response> {
+    let request = span.in_scope(|| request.with_request_id());
+    let result = request.send().instrument(span.clone()).await;
+    match &result {
+        Ok(response) => {
+            span.record("http.response.status_code", response.status().as_u16());
+            if response.status().is_server_error() {
+                span.record("otel.status_code", "ERROR");
+            }
+        }
+        Err(e) => {
+            span.record("otel.status_code", "ERROR");
+            span.in_scope(|| tracing::warn!("Upstream request failed: {}", e));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_trace_context_round_trips() {
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let mut incoming = actix_web::http::header::HeaderMap::new();
+            incoming.insert(
+                actix_web::http::header::HeaderName::from_static("traceparent"),
+                actix_web::http::header::HeaderValue::from_static(
+                    "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+                ),
+            );
+            let request = tracing::info_span!("request");
+            set_remote_parent(&request, &incoming);
+
+            let upstream = request.in_scope(|| {
+                upstream_span(
+                    "chat_completion",
+                    "https://api.openai.com/v1",
+                    Some("gpt-4o"),
+                )
+            });
+            let headers = upstream.in_scope(trace_headers);
+            let traceparent = headers.get("traceparent").unwrap();
+            // Same trace, new span id for the outgoing call
+            assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+            assert!(!traceparent.contains("00f067aa0ba902b7"));
+        });
+    }
+
+    #[test]
+    fn test_no_headers_without_a_propagator_context() {
+        assert!(!trace_headers().contains_key("traceparent"));
+    }
+}
@@ -0,0 +1,211 @@
+use rustls::crypto::ring::{default_provider, sign::any_supported_type};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+/// Server certificate read from PEM files, swappable while the server is running
+///
+/// Every TLS handshake asks the resolver for the current key, so a reload takes effect
+/// for new connections right away while open ones keep the certificate they started with.
+#[derive(Debug)]
+pub struct ReloadableCert {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadableCert {
+    pub fn load(
+        cert_file: impl Into<PathBuf>,
+        key_file: impl Into<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        let cert_file = cert_file.into();
+        let key_file = key_file.into();
+        let current = read_certified_key(&cert_file, &key_file)?;
+        Ok(Self {
+            cert_file,
+            key_file,
+            current: RwLock::new(Arc::new(current)),
+        })
+    }
+
+    /// Re-read the certificate and key files. On error the previous certificate stays.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let key = read_certified_key(&self.cert_file, &self.key_file)?;
+        *self.current.write().unwrap() = Arc::new(key);
+        Ok(())
+    }
+
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        self.current.read().unwrap().clone()
+    }
+}
+
+impl ResolvesServerCert for ReloadableCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+fn read_certified_key(
+    cert_file: &std::path::Path,
+    key_file: &std::path::Path,
+) -> anyhow::Result<CertifiedKey> {
+    let certs = CertificateDer::pem_file_iter(cert_file)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow::anyhow!("Failed to read SSL_CERT_FILE {:?}: {}", cert_file, e))?;
+    if certs.is_empty() {
+        anyhow::bail!("SSL_CERT_FILE {:?} contains no certificates", cert_file);
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .map_err(|e| anyhow::anyhow!("Failed to read SSL_KEY_FILE {:?}: {}", key_file, e))?;
+    let key = any_supported_type(&key)
+        .map_err(|e| anyhow::anyhow!("Unsupported key in SSL_KEY_FILE {:?}: {}", key_file, e))?;
+
+    Ok(CertifiedKey::new(certs, key))
+}
+
+/// rustls server configuration that always serves the current certificate of `cert`
+///
+/// actix-web adds the "h2" and "http/1.1" ALPN protocols when binding. WebSocket
+/// upgrades (Socket.IO) need HTTP/1.1; browsers open a separate HTTP/1.1 connection for
+/// them, so they work on the same listener.
+pub fn server_config(cert: Arc<ReloadableCert>) -> anyhow::Result<ServerConfig> {
+    let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(cert);
+    Ok(config)
+}
+
+/// Reload the certificate on SIGHUP, so a renewed certificate needs no restart
+pub fn reload_on_sighup(cert: Arc<ReloadableCert>) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sighup = match signal(SignalKind::hangup()) {
+            Ok(sighup) => sighup,
+            Err(e) => {
+                warn!(
+                    "Failed to install SIGHUP handler, certificate reload disabled: {}",
+                    e
+                );
+                return;
+            }
+        };
+        tokio::spawn(async move {
+            while sighup.recv().await.is_some() {
+                match cert.reload() {
+                    Ok(()) => info!("🔒 Reloaded TLS certificate from {:?}", cert.cert_file),
+                    Err(e) => warn!("TLS certificate reload failed, keeping the old one: {}", e),
+                }
+            }
+        });
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = cert;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn write_self_signed(dir: &std::path::Path) -> (PathBuf, PathBuf) {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_file = dir.join("cert.pem");
+        let key_file = dir.join("key.pem");
+        std::fs::write(&cert_file, generated.cert.pem()).unwrap();
+        std::fs::write(&key_file, generated.key_pair.serialize_pem()).unwrap();
+        (cert_file, key_file)
+    }
+
+    #[test]
+    fn test_reload_swaps_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_file, key_file) = write_self_signed(dir.path());
+        let cert = ReloadableCert::load(&cert_file, &key_file).unwrap();
+        let before = cert.current().cert[0].clone();
+
+        write_self_signed(dir.path());
+        cert.reload().unwrap();
+        assert_ne!(cert.current().cert[0], before);
+
+        // A broken renewal keeps serving the last good certificate
+        std::fs::write(&key_file, "not a key").unwrap();
+        let good = cert.current().cert[0].clone();
+        assert!(cert.reload().is_err());
+        assert_eq!(cert.current().cert[0], good);
+    }
+
+    async fn ws(req: HttpRequest, body: web::Payload) -> actix_web::Result<HttpResponse> {
+        let (response, _session, _stream) = actix_ws::handle(&req, body)?;
+        Ok(response)
+    }
+
+    #[actix_web::test]
+    async fn test_websocket_upgrade_over_tls() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_file, key_file) = write_self_signed(dir.path());
+        let cert = Arc::new(ReloadableCert::load(&cert_file, &key_file).unwrap());
+
+        let server = HttpServer::new(|| App::new().route("/ws", web::get().to(ws)))
+            .workers(1)
+            .bind_rustls_0_23(("127.0.0.1", 0), server_config(cert.clone()).unwrap())
+            .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(cert.current().cert[0].clone()).unwrap();
+        let mut client = rustls::ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut tls = tokio_rustls::TlsConnector::from(Arc::new(client))
+            .connect("localhost".try_into().unwrap(), tcp)
+            .await
+            .unwrap();
+
+        tls.write_all(
+            b"GET /ws HTTP/1.1\r\n\
+              Host: localhost\r\n\
+              Upgrade: websocket\r\n\
+              Connection: Upgrade\r\n\
+              Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+              Sec-WebSocket-Version: 13\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+        let mut response = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !response.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = tls.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed before the upgrade response");
+            response.extend_from_slice(&buf[..n]);
+        }
+        let response = String::from_utf8_lossy(&response).to_lowercase();
+        assert!(response.starts_with("http/1.1 101"), "{}", response);
+        assert!(response.contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="));
+
+        handle.stop(false).await;
+    }
+}
@@ -0,0 +1,470 @@
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Extensions of scripts and installers that run when opened
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "exe", "dll", "com", "scr", "msi", "msp", "bat", "cmd", "ps1", "psm1", "vbs", "vbe", "wsf",
+    "wsh", "hta", "cpl", "lnk", "sh", "bash", "zsh", "command", "app", "apk", "jar", "deb", "rpm",
+    "dmg", "pkg", "so", "dylib", "bin", "elf",
+];
+
+/// A file format recognised by its leading bytes
+struct Signature {
+    content_type: &'static str,
+    matches: fn(&[u8]) -> bool,
+    /// Declared types this format is legitimately uploaded as; `type/*` matches a family
+    declared_as: &'static [&'static str],
+    executable: bool,
+}
+
+const SIGNATURES: &[Signature] = &[
+    Signature {
+        content_type: "image/png",
+        matches: |b| b.starts_with(b"\x89PNG\r\n\x1a\n"),
+        declared_as: &["image/png", "image/apng"],
+        executable: false,
+    },
+    Signature {
+        content_type: "image/jpeg",
+        matches: |b| b.starts_with(&[0xff, 0xd8, 0xff]),
+        declared_as: &["image/jpeg", "image/jpg", "image/pjpeg"],
+        executable: false,
+    },
+    Signature {
+        content_type: "image/gif",
+        matches: |b| b.starts_with(b"GIF87a") || b.starts_with(b"GIF89a"),
+        declared_as: &["image/gif"],
+        executable: false,
+    },
+    Signature {
+        content_type: "image/webp",
+        matches: |b| b.starts_with(b"RIFF") && b.get(8..12) == Some(b"WEBP"),
+        declared_as: &["image/webp"],
+        executable: false,
+    },
+    Signature {
+        content_type: "audio/wav",
+        matches: |b| b.starts_with(b"RIFF") && b.get(8..12) == Some(b"WAVE"),
+        declared_as: &["audio/wav", "audio/x-wav", "audio/wave", "audio/vnd.wave"],
+        executable: false,
+    },
+    Signature {
+        content_type: "application/pdf",
+        matches: |b| b.starts_with(b"%PDF-"),
+        declared_as: &["application/pdf"],
+        executable: false,
+    },
+    Signature {
+        // Also the container of Office Open XML, OpenDocument and EPUB files
+        content_type: "application/zip",
+        matches: |b| b.starts_with(b"PK\x03\x04") || b.starts_with(b"PK\x05\x06"),
+        declared_as: &[
+            "application/zip",
+            "application/x-zip-compressed",
+            "application/epub+zip",
+            "application/vnd.openxmlformats-officedocument.*",
+            "application/vnd.oasis.opendocument.*",
+            "application/java-archive",
+            "application/vnd.android.package-archive",
+        ],
+        executable: false,
+    },
+    Signature {
+        // Legacy Office documents (.doc, .xls, .ppt) and .msi installers
+        content_type: "application/x-ole-storage",
+        matches: |b| b.starts_with(&[0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1]),
+        declared_as: &[
+            "application/msword",
+            "application/vnd.ms-*",
+            "application/x-msi",
+            "application/x-ole-storage",
+        ],
+        executable: false,
+    },
+    Signature {
+        content_type: "application/gzip",
+        matches: |b| b.starts_with(&[0x1f, 0x8b]),
+        declared_as: &[
+            "application/gzip",
+            "application/x-gzip",
+            "application/x-tar",
+        ],
+        executable: false,
+    },
+    Signature {
+        content_type: "audio/mpeg",
+        matches: |b| b.starts_with(b"ID3") || is_mpeg_frame(b),
+        declared_as: &["audio/mpeg", "audio/mp3", "audio/mpeg3", "audio/x-mpeg-3"],
+        executable: false,
+    },
+    Signature {
+        // ISO base media: MP4, M4A, QuickTime, HEIC, AVIF
+        content_type: "video/mp4",
+        matches: |b| b.get(4..8) == Some(b"ftyp"),
+        declared_as: &[
+            "video/*",
+            "audio/mp4",
+            "audio/x-m4a",
+            "audio/m4a",
+            "image/heic",
+            "image/heif",
+            "image/avif",
+        ],
+        executable: false,
+    },
+    Signature {
+        content_type: "audio/ogg",
+        matches: |b| b.starts_with(b"OggS"),
+        declared_as: &["audio/ogg", "audio/opus", "video/ogg", "application/ogg"],
+        executable: false,
+    },
+    Signature {
+        content_type: "audio/flac",
+        matches: |b| b.starts_with(b"fLaC"),
+        declared_as: &["audio/flac", "audio/x-flac"],
+        executable: false,
+    },
+    Signature {
+        content_type: "video/webm",
+        matches: |b| b.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]),
+        declared_as: &["video/webm", "audio/webm", "video/x-matroska"],
+        executable: false,
+    },
+    Signature {
+        content_type: "application/x-executable",
+        matches: |b| b.starts_with(b"\x7fELF"),
+        declared_as: &["application/x-executable", "application/x-elf"],
+        executable: true,
+    },
+    Signature {
+        content_type: "application/x-msdownload",
+        matches: is_pe,
+        declared_as: &["application/x-msdownload", "application/x-dosexec"],
+        executable: true,
+    },
+    Signature {
+        content_type: "application/x-mach-binary",
+        matches: |b| {
+            [
+                [0xfe, 0xed, 0xfa, 0xce],
+                [0xfe, 0xed, 0xfa, 0xcf],
+                [0xce, 0xfa, 0xed, 0xfe],
+                [0xcf, 0xfa, 0xed, 0xfe],
+                [0xca, 0xfe, 0xba, 0xbe],
+            ]
+            .iter()
+            .any(|magic| b.starts_with(magic))
+        },
+        declared_as: &["application/x-mach-binary", "application/mac-binary"],
+        executable: true,
+    },
+];
+
+/// An MPEG audio frame header: the 11-bit sync, a known version, Layer II or III, and a
+/// usable bitrate and sample rate. Layer I is left out, as its header starts with the
+/// UTF-16LE byte order mark.
+fn is_mpeg_frame(b: &[u8]) -> bool {
+    match b.get(..3) {
+        Some(&[sync, flags, rates]) => {
+            sync == 0xff
+                && flags & 0xe0 == 0xe0
+                && (flags >> 3) & 0x03 != 0x01
+                && matches!((flags >> 1) & 0x03, 0x01 | 0x02)
+                && !matches!(rates >> 4, 0x00 | 0x0f)
+                && (rates >> 2) & 0x03 != 0x03
+        }
+        _ => false,
+    }
+}
+
+/// A Windows PE image: a DOS header whose `e_lfanew` points at the `PE\0\0` signature
+fn is_pe(b: &[u8]) -> bool {
+    if !b.starts_with(b"MZ") {
+        return false;
+    }
+    match b.get(0x3c..0x40) {
+        Some(offset) => {
+            let offset = u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize;
+            offset
+                .checked_add(4)
+                .and_then(|end| b.get(offset..end))
+                .is_some_and(|sig| sig == b"PE\0\0")
+        }
+        None => false,
+    }
+}
+
+fn matches_pattern(content_type: &str, pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => content_type.starts_with(prefix),
+        None => content_type == pattern,
+    }
+}
+
+fn sniff(bytes: &[u8]) -> Option<&'static Signature> {
+    SIGNATURES.iter().find(|s| (s.matches)(bytes))
+}
+
+/// Whether the start of a file is UTF-8 text, allowing a character cut off at the end
+fn looks_like_text(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(8192)];
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && head.len() == 8192,
+    }
+}
+
+/// Whether a file of `size` bytes may be uploaded at all
+pub fn check_upload_size(config: &Config, size: u64) -> AppResult<()> {
+    if size > config.file_max_size {
+        return Err(AppError::PayloadTooLarge(format!(
+            "File exceeds the maximum upload size of {} MB",
+            config.file_max_size / 1024 / 1024
+        )));
+    }
+    Ok(())
+}
+
+/// Check an upload's content against its name and declared type
+///
+/// The declared type is the multipart part's `Content-Type`, or the one implied by the
+/// extension when the client sent none. Files whose leading bytes contradict that type
+/// are rejected, as are executables unless ENABLE_EXECUTABLE_UPLOADS is set, and types
+/// outside FILE_ALLOWED_CONTENT_TYPES. Returns the content type to record for the file.
+pub fn validate_upload(
+    config: &Config,
+    filename: &str,
+    declared: Option<&str>,
+    bytes: &[u8],
+) -> AppResult<String> {
+    check_upload_size(config, bytes.len() as u64)?;
+
+    let extension = std::path::Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    let signature = sniff(bytes);
+
+    let executable = EXECUTABLE_EXTENSIONS.contains(&extension.as_str())
+        || signature.map(|s| s.executable).unwrap_or(false);
+    if executable && !config.enable_executable_uploads {
+        return Err(AppError::UnsupportedMediaType(
+            "Executable files and scripts are not allowed".to_string(),
+        ));
+    }
+
+    let declared = declared
+        .map(|ct| ct.split(';').next().unwrap_or("").trim().to_lowercase())
+        .filter(|ct| !ct.is_empty() && ct != OCTET_STREAM)
+        .unwrap_or_else(|| {
+            mime_guess::from_path(filename)
+                .first_or_octet_stream()
+                .essence_str()
+                .to_string()
+        });
+
+    let content_type = match signature {
+        Some(signature) if declared == OCTET_STREAM => signature.content_type.to_string(),
+        Some(signature) => {
+            if !signature
+                .declared_as
+                .iter()
+                .any(|pattern| matches_pattern(&declared, pattern))
+            {
+                return Err(AppError::UnsupportedMediaType(format!(
+                    "File content ({}) does not match its declared type ({})",
+                    signature.content_type, declared
+                )));
+            }
+            declared
+        }
+        None => {
+            // A format with a known signature that doesn't start with it is mislabeled
+            let claimed = SIGNATURES.iter().find(|s| {
+                s.declared_as
+                    .iter()
+                    .any(|pattern| !pattern.ends_with('*') && *pattern == declared)
+            });
+            if let Some(claimed) = claimed {
+                return Err(AppError::UnsupportedMediaType(format!(
+                    "File content does not match its declared type ({})",
+                    claimed.content_type
+                )));
+            }
+            // Source files often have no registered type, or a misleading one
+            let allowed = config
+                .file_allowed_content_types
+                .iter()
+                .any(|pattern| matches_pattern(&declared, pattern));
+            if !allowed && looks_like_text(bytes) {
+                "text/plain".to_string()
+            } else {
+                declared
+            }
+        }
+    };
+
+    if !executable
+        && !config
+            .file_allowed_content_types
+            .iter()
+            .any(|pattern| matches_pattern(&content_type, pattern))
+    {
+        return Err(AppError::UnsupportedMediaType(format!(
+            "File type {} is not allowed",
+            content_type
+        )));
+    }
+
+    Ok(content_type)
+}
+
+/// Check that storing `size` more bytes keeps the user within their quota
+pub fn check_user_quota(config: &Config, used: u64, size: u64) -> AppResult<()> {
+    match config.file_user_quota {
+        Some(quota) if used + size > quota => Err(AppError::PayloadTooLarge(format!(
+            "Storage quota of {} MB exceeded ({} MB used)",
+            quota / 1024 / 1024,
+            used / 1024 / 1024
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The smallest PE layout: a DOS header pointing at the signature right after it
+    fn pe_stub() -> Vec<u8> {
+        let mut pe = vec![0u8; 0x48];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c] = 0x40;
+        pe[0x40..0x44].copy_from_slice(b"PE\0\0");
+        pe
+    }
+
+    fn config() -> Config {
+        let mut config = Config::from_env().unwrap();
+        config.file_max_size = 1024;
+        config.enable_executable_uploads = false;
+        config.file_allowed_content_types = vec![
+            "text/*".to_string(),
+            "image/*".to_string(),
+            "application/pdf".to_string(),
+            "application/vnd.openxmlformats-officedocument.*".to_string(),
+        ];
+        config
+    }
+
+    #[test]
+    fn test_matching_uploads_are_accepted() {
+        let config = config();
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR";
+        assert_eq!(
+            validate_upload(&config, "photo.png", Some("image/png"), png).unwrap(),
+            "image/png"
+        );
+        assert_eq!(
+            validate_upload(&config, "report.pdf", None, b"%PDF-1.7\n").unwrap(),
+            "application/pdf"
+        );
+        assert_eq!(
+            validate_upload(&config, "notes.docx", None, b"PK\x03\x04rest").unwrap(),
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+        );
+        assert_eq!(
+            validate_upload(&config, "Makefile", None, b"all:\n\tcargo build\n").unwrap(),
+            "text/plain"
+        );
+        assert_eq!(
+            validate_upload(&config, "data.csv", Some("text/csv"), b"a,b\n1,2\n").unwrap(),
+            "text/csv"
+        );
+    }
+
+    #[test]
+    fn test_mislabeled_uploads_are_rejected() {
+        let config = config();
+        // An executable renamed to look like an image
+        let err = validate_upload(&config, "cat.png", Some("image/png"), b"MZ\x90\0").unwrap_err();
+        assert!(matches!(err, AppError::UnsupportedMediaType(_)));
+        let err = validate_upload(&config, "cat.png", Some("image/png"), &pe_stub()).unwrap_err();
+        assert!(matches!(err, AppError::UnsupportedMediaType(_)));
+        // A PDF claiming to be a PNG, and text claiming to be a PDF
+        assert!(validate_upload(&config, "cat.png", None, b"%PDF-1.7\n").is_err());
+        assert!(validate_upload(&config, "doc.pdf", None, b"just text").is_err());
+        // A PNG renamed .txt
+        assert!(validate_upload(&config, "a.txt", None, b"\x89PNG\r\n\x1a\n").is_err());
+    }
+
+    #[test]
+    fn test_executables_need_opt_in() {
+        let mut config = config();
+        assert!(validate_upload(&config, "run.sh", None, b"#!/bin/sh\necho hi\n").is_err());
+        assert!(validate_upload(&config, "tool", None, b"\x7fELF\x02\x01").is_err());
+
+        config.enable_executable_uploads = true;
+        assert!(validate_upload(&config, "run.sh", None, b"#!/bin/sh\necho hi\n").is_ok());
+        assert_eq!(
+            validate_upload(&config, "tool", None, b"\x7fELF\x02\x01").unwrap(),
+            "application/x-executable"
+        );
+        assert_eq!(
+            validate_upload(&config, "setup", None, &pe_stub()).unwrap(),
+            "application/x-msdownload"
+        );
+    }
+
+    #[test]
+    fn test_text_is_not_sniffed_as_binary() {
+        let config = config();
+        // UTF-16LE text starts with FF FE, which looks like an MPEG frame sync
+        let utf16: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain("hi".encode_utf16().flat_map(|u| u.to_le_bytes()))
+            .collect();
+        assert!(sniff(&utf16).is_none());
+        assert_eq!(
+            validate_upload(&config, "notes.txt", None, &utf16).unwrap(),
+            "text/plain"
+        );
+        // Text starting with "MZ" is not a PE image
+        assert!(sniff(b"MZ notes without a PE header").is_none());
+        assert_eq!(
+            validate_upload(&config, "mz.txt", None, b"MZ notes").unwrap(),
+            "text/plain"
+        );
+
+        // Real MP3 frame headers (MPEG-1 Layer III, 128 kbps, 44.1 kHz) still match
+        assert_eq!(
+            sniff(&[0xff, 0xfb, 0x90, 0x64]).map(|s| s.content_type),
+            Some("audio/mpeg")
+        );
+        assert_eq!(
+            sniff(&[0xff, 0xf3, 0x90, 0x64]).map(|s| s.content_type),
+            Some("audio/mpeg")
+        );
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let mut config = config();
+        let err = validate_upload(&config, "big.txt", None, &[b'a'; 2048]).unwrap_err();
+        assert!(matches!(err, AppError::PayloadTooLarge(_)));
+
+        assert!(check_user_quota(&config, 10_000, 10_000).is_ok());
+        config.file_user_quota = Some(1024 * 1024);
+        assert!(check_user_quota(&config, 1024 * 1000, 1000).is_ok());
+        assert!(matches!(
+            check_user_quota(&config, 1024 * 1000, 50_000),
+            Err(AppError::PayloadTooLarge(_))
+        ));
+    }
+}
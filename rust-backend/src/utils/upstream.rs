@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use crate::error::{AppError, AppResult};
+use crate::socketio::circuit_breaker::CircuitBreakerStats;
+use crate::socketio::{CircuitBreaker, CircuitBreakerConfig};
+
+/// One circuit breaker per configured OpenAI endpoint, keyed by its `urlIdx`
+///
+/// After repeated failures (connection errors or 5xx responses) an endpoint's
+/// breaker opens and requests for it fail immediately with
+/// [`AppError::UpstreamUnavailable`] instead of waiting on a dead upstream. Once
+/// the breaker timeout has passed a few requests are let through again to test
+/// recovery.
+pub struct UpstreamBreakers {
+    config: CircuitBreakerConfig,
+    /// urlIdx -> (endpoint url, breaker); a breaker is replaced when the url at its
+    /// index changes, so reordering connections doesn't inherit stale state
+    breakers: RwLock<HashMap<usize, (String, Arc<CircuitBreaker>)>>,
+}
+
+impl UpstreamBreakers {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        UpstreamBreakers {
+            config,
+            breakers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Breaker guarding the endpoint at `idx`
+    pub fn breaker(&self, idx: usize, url: &str) -> Arc<CircuitBreaker> {
+        if let Some((current_url, breaker)) = self.breakers.read().unwrap().get(&idx) {
+            if current_url == url {
+                return breaker.clone();
+            }
+        }
+
+        let mut breakers = self.breakers.write().unwrap();
+        match breakers.get(&idx) {
+            Some((current_url, breaker)) if current_url == url => breaker.clone(),
+            _ => {
+                let breaker = Arc::new(CircuitBreaker::new(self.config.clone()));
+                breakers.insert(idx, (url.to_string(), breaker.clone()));
+                breaker
+            }
+        }
+    }
+
+    /// Breaker for the endpoint at `idx` if it currently lets requests through
+    pub async fn acquire(&self, idx: usize, url: &str) -> AppResult<Arc<CircuitBreaker>> {
+        let breaker = self.breaker(idx, url);
+        if breaker.allow_request().await {
+            Ok(breaker)
+        } else {
+            tracing::warn!("Circuit open for OpenAI endpoint {} ({})", idx, url);
+            Err(AppError::UpstreamUnavailable(format!(
+                "OpenAI endpoint {} is temporarily unavailable after repeated failures",
+                idx
+            )))
+        }
+    }
+
+    /// Breaker statistics by urlIdx
+    pub async fn stats(&self) -> BTreeMap<usize, CircuitBreakerStats> {
+        let breakers: Vec<(usize, Arc<CircuitBreaker>)> = self
+            .breakers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(idx, (_, breaker))| (*idx, breaker.clone()))
+            .collect();
+
+        let mut stats = BTreeMap::new();
+        for (idx, breaker) in breakers {
+            stats.insert(idx, breaker.get_stats().await);
+        }
+        stats
+    }
+}
+
+/// Record the outcome of an upstream call: transport errors and 5xx responses count
+/// as failures, anything else (including 4xx) shows the endpoint is alive
+pub async fn record_outcome<T>(breaker: &CircuitBreaker, result: &Result<reqwest::Response, T>) {
+    match result {
+        Ok(response) if !response.status().is_server_error() => breaker.record_success().await,
+        _ => breaker.record_failure().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socketio::circuit_breaker::CircuitState;
+    use std::time::Duration;
+
+    fn breakers() -> UpstreamBreakers {
+        UpstreamBreakers::new(CircuitBreakerConfig {
+            failure_threshold: 2,
+            success_threshold: 1,
+            timeout: Duration::from_millis(50),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_open_endpoint_short_circuits() {
+        let breakers = breakers();
+        let breaker = breakers.acquire(0, "http://a").await.unwrap();
+        breaker.record_failure().await;
+        breaker.record_failure().await;
+
+        assert!(matches!(
+            breakers.acquire(0, "http://a").await,
+            Err(AppError::UpstreamUnavailable(_))
+        ));
+        // Other endpoints are unaffected
+        assert!(breakers.acquire(1, "http://b").await.is_ok());
+        assert_eq!(breakers.stats().await[&0].state, CircuitState::Open);
+
+        // Half-opens after the timeout and closes on success
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let breaker = breakers.acquire(0, "http://a").await.unwrap();
+        breaker.record_success().await;
+        assert_eq!(breakers.stats().await[&0].state, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_breaker_resets_when_url_changes() {
+        let breakers = breakers();
+        let breaker = breakers.breaker(0, "http://a");
+        breaker.record_failure().await;
+        breaker.record_failure().await;
+        assert!(breakers.acquire(0, "http://a").await.is_err());
+
+        assert!(breakers.acquire(0, "http://replacement").await.is_ok());
+    }
+}
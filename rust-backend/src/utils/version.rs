@@ -1,8 +1,9 @@
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use tracing::{error, info};
 
-use crate::error::AppResult;
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+use crate::utils::http_client;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GitHubRelease {
@@ -28,8 +29,9 @@ pub struct VersionInfo {
 
 /// Check for updates from GitHub releases
 #[allow(dead_code)]
-pub async fn check_for_updates(current_version: &str) -> AppResult<VersionInfo> {
-    let client = Client::builder()
+pub async fn check_for_updates(config: &Config, current_version: &str) -> AppResult<VersionInfo> {
+    let client = http_client::builder(config)
+        .map_err(|e| AppError::Internal(e.to_string()))?
         .user_agent("open-webui-rust")
         .timeout(std::time::Duration::from_secs(10))
         .build()?;
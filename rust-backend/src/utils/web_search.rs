@@ -0,0 +1,868 @@
+// Web search for chat completions
+// Queries the configured search engine (SearXNG, Brave, Google PSE), fetches the result pages,
+// and ranks their chunks against the query so the most relevant passages can be injected as
+// RAG sources alongside file/note context
+
+use crate::{
+    config::Config,
+    error::{AppError, AppResult},
+    utils::retrieval::Source,
+    AppState,
+};
+use futures::StreamExt;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde_json::{json, Value};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Timeout for a single search engine request
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Timeout for fetching a single result page
+const PAGE_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pages larger than this are truncated before chunking
+const MAX_PAGE_CHARS: usize = 200_000;
+
+/// Bytes read from a result page; the rest of the body is never downloaded
+const MAX_PAGE_BYTES: usize = 1024 * 1024;
+
+/// Most redirects followed when fetching a result page
+const MAX_PAGE_REDIRECTS: usize = 5;
+
+/// Google PSE returns at most 10 results per request
+const GOOGLE_PSE_MAX_RESULTS: usize = 10;
+
+/// A single search engine hit
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebSearchResult {
+    pub link: String,
+    pub title: Option<String>,
+    pub snippet: Option<String>,
+}
+
+/// Search engine settings captured from config so no lock is held across requests
+#[derive(Debug, Clone)]
+struct WebSearchSettings {
+    engine: String,
+    result_count: usize,
+    searxng_query_url: String,
+    brave_search_api_key: String,
+    google_pse_api_key: String,
+    google_pse_engine_id: String,
+    chunk_size: usize,
+    chunk_overlap: usize,
+    top_k: usize,
+}
+
+impl WebSearchSettings {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            engine: config.web_search_engine.to_lowercase(),
+            result_count: config.web_search_result_count.max(1),
+            searxng_query_url: config.searxng_query_url.clone(),
+            brave_search_api_key: config.brave_search_api_key.clone(),
+            google_pse_api_key: config.google_pse_api_key.clone(),
+            google_pse_engine_id: config.google_pse_engine_id.clone(),
+            chunk_size: config.chunk_size,
+            chunk_overlap: config.chunk_overlap,
+            top_k: config.rag_top_k.max(1),
+        }
+    }
+}
+
+/// Run a web search for the query using the configured engine
+async fn search_with_settings(
+    client: &reqwest::Client,
+    settings: &WebSearchSettings,
+    query: &str,
+) -> AppResult<Vec<WebSearchResult>> {
+    let mut results = match settings.engine.as_str() {
+        "searxng" => search_searxng(client, settings, query).await?,
+        "brave" => search_brave(client, settings, query).await?,
+        "google_pse" => search_google_pse(client, settings, query).await?,
+        "" => {
+            return Err(AppError::BadRequest(
+                "Web search engine is not configured".to_string(),
+            ))
+        }
+        other => {
+            return Err(AppError::BadRequest(format!(
+                "Unsupported web search engine: {}",
+                other
+            )))
+        }
+    };
+
+    results.truncate(settings.result_count);
+    Ok(results)
+}
+
+async fn search_searxng(
+    client: &reqwest::Client,
+    settings: &WebSearchSettings,
+    query: &str,
+) -> AppResult<Vec<WebSearchResult>> {
+    if settings.searxng_query_url.is_empty() {
+        return Err(AppError::BadRequest(
+            "SEARXNG_QUERY_URL is not configured".to_string(),
+        ));
+    }
+
+    let response: Value = client
+        .get(&settings.searxng_query_url)
+        .query(&[("q", query), ("format", "json"), ("pageno", "1")])
+        .header("Accept", "application/json")
+        .timeout(SEARCH_TIMEOUT)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| AppError::ExternalServiceError(format!("SearXNG search failed: {}", e)))?
+        .json()
+        .await
+        .map_err(|e| AppError::ExternalServiceError(format!("Invalid SearXNG response: {}", e)))?;
+
+    Ok(parse_results(
+        &response["results"],
+        "url",
+        "title",
+        "content",
+    ))
+}
+
+async fn search_brave(
+    client: &reqwest::Client,
+    settings: &WebSearchSettings,
+    query: &str,
+) -> AppResult<Vec<WebSearchResult>> {
+    if settings.brave_search_api_key.is_empty() {
+        return Err(AppError::BadRequest(
+            "BRAVE_SEARCH_API_KEY is not configured".to_string(),
+        ));
+    }
+
+    let count = settings.result_count.to_string();
+    let response: Value = client
+        .get("https://api.search.brave.com/res/v1/web/search")
+        .query(&[("q", query), ("count", count.as_str())])
+        .header("Accept", "application/json")
+        .header("X-Subscription-Token", &settings.brave_search_api_key)
+        .timeout(SEARCH_TIMEOUT)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| AppError::ExternalServiceError(format!("Brave search failed: {}", e)))?
+        .json()
+        .await
+        .map_err(|e| AppError::ExternalServiceError(format!("Invalid Brave response: {}", e)))?;
+
+    Ok(parse_results(
+        &response["web"]["results"],
+        "url",
+        "title",
+        "description",
+    ))
+}
+
+async fn search_google_pse(
+    client: &reqwest::Client,
+    settings: &WebSearchSettings,
+    query: &str,
+) -> AppResult<Vec<WebSearchResult>> {
+    if settings.google_pse_api_key.is_empty() || settings.google_pse_engine_id.is_empty() {
+        return Err(AppError::BadRequest(
+            "GOOGLE_PSE_API_KEY and GOOGLE_PSE_ENGINE_ID must be configured".to_string(),
+        ));
+    }
+
+    let num = settings
+        .result_count
+        .min(GOOGLE_PSE_MAX_RESULTS)
+        .to_string();
+    let response: Value = client
+        .get("https://www.googleapis.com/customsearch/v1")
+        .query(&[
+            ("key", settings.google_pse_api_key.as_str()),
+            ("cx", settings.google_pse_engine_id.as_str()),
+            ("q", query),
+            ("num", num.as_str()),
+        ])
+        .timeout(SEARCH_TIMEOUT)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| AppError::ExternalServiceError(format!("Google PSE search failed: {}", e)))?
+        .json()
+        .await
+        .map_err(|e| {
+            AppError::ExternalServiceError(format!("Invalid Google PSE response: {}", e))
+        })?;
+
+    Ok(parse_results(
+        &response["items"],
+        "link",
+        "title",
+        "snippet",
+    ))
+}
+
+/// Map an engine's result array onto [`WebSearchResult`]s, skipping entries without a link
+fn parse_results(
+    items: &Value,
+    link_key: &str,
+    title_key: &str,
+    snippet_key: &str,
+) -> Vec<WebSearchResult> {
+    items
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    let link = item.get(link_key)?.as_str()?.to_string();
+                    Some(WebSearchResult {
+                        link,
+                        title: item
+                            .get(title_key)
+                            .and_then(|t| t.as_str())
+                            .map(String::from),
+                        snippet: item
+                            .get(snippet_key)
+                            .and_then(|s| s.as_str())
+                            .map(String::from),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// IPv4 blocks off the public internet, from the IANA special-purpose address registry
+const DENIED_IPV4: &[(Ipv4Addr, u8)] = &[
+    // "This network"
+    (Ipv4Addr::new(0, 0, 0, 0), 8),
+    (Ipv4Addr::new(10, 0, 0, 0), 8),
+    // Carrier-grade NAT
+    (Ipv4Addr::new(100, 64, 0, 0), 10),
+    (Ipv4Addr::new(127, 0, 0, 0), 8),
+    // Link-local, which holds cloud metadata endpoints
+    (Ipv4Addr::new(169, 254, 0, 0), 16),
+    (Ipv4Addr::new(172, 16, 0, 0), 12),
+    // IETF protocol assignments
+    (Ipv4Addr::new(192, 0, 0, 0), 24),
+    (Ipv4Addr::new(192, 0, 2, 0), 24),
+    // 6to4 relay anycast
+    (Ipv4Addr::new(192, 88, 99, 0), 24),
+    (Ipv4Addr::new(192, 168, 0, 0), 16),
+    // Benchmarking
+    (Ipv4Addr::new(198, 18, 0, 0), 15),
+    (Ipv4Addr::new(198, 51, 100, 0), 24),
+    (Ipv4Addr::new(203, 0, 113, 0), 24),
+    // Multicast
+    (Ipv4Addr::new(224, 0, 0, 0), 4),
+    // Reserved, including broadcast
+    (Ipv4Addr::new(240, 0, 0, 0), 4),
+];
+
+/// IPv6 blocks off the public internet; IPv4-mapped addresses are checked as IPv4
+const DENIED_IPV6: &[(Ipv6Addr, u8)] = &[
+    // Unspecified, loopback and the deprecated IPv4-compatible addresses
+    (Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 96),
+    // IPv4-translated
+    (Ipv6Addr::new(0, 0, 0, 0, 0xffff, 0, 0, 0), 96),
+    // NAT64, which reaches IPv4 addresses through the local translator
+    (Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0), 96),
+    (Ipv6Addr::new(0x64, 0xff9b, 1, 0, 0, 0, 0, 0), 48),
+    // Discard-only
+    (Ipv6Addr::new(0x100, 0, 0, 0, 0, 0, 0, 0), 64),
+    // IETF protocol assignments, including Teredo
+    (Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32),
+    // 6to4
+    (Ipv6Addr::new(0x2002, 0, 0, 0, 0, 0, 0, 0), 16),
+    // Unique local
+    (Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7),
+    // Link-local and the deprecated site-local
+    (Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10),
+    (Ipv6Addr::new(0xfec0, 0, 0, 0, 0, 0, 0, 0), 10),
+    // Multicast
+    (Ipv6Addr::new(0xff00, 0, 0, 0, 0, 0, 0, 0), 8),
+];
+
+/// Whether an address is on the public internet rather than in one of the private,
+/// loopback, link-local, multicast or otherwise reserved blocks
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let ip = u32::from(ip);
+            !DENIED_IPV4.iter().any(|(network, prefix)| {
+                let mask = u32::MAX << (32 - prefix);
+                ip & mask == u32::from(*network) & mask
+            })
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            None => {
+                let ip = u128::from(ip);
+                !DENIED_IPV6.iter().any(|(network, prefix)| {
+                    let mask = u128::MAX << (128 - prefix);
+                    ip & mask == u128::from(*network) & mask
+                })
+            }
+        },
+    }
+}
+
+/// Whether a result page URL may be fetched; host names are checked when they resolve
+fn is_fetchable_url(url: &reqwest::Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    match url.host() {
+        Some(url::Host::Ipv4(ip)) => is_public_ip(IpAddr::V4(ip)),
+        Some(url::Host::Ipv6(ip)) => is_public_ip(IpAddr::V6(ip)),
+        Some(url::Host::Domain(_)) => true,
+        None => false,
+    }
+}
+
+/// Whether a URL is fetchable and its host name, if any, only resolves to public
+/// addresses
+///
+/// Requests through a proxy never reach [`PublicResolver`], since the proxy resolves the
+/// host, so every request is checked here first.
+async fn resolves_to_public(url: &reqwest::Url) -> bool {
+    if !is_fetchable_url(url) {
+        return false;
+    }
+    let host = match url.host() {
+        Some(url::Host::Domain(host)) => host,
+        _ => return true,
+    };
+    let port = url.port_or_known_default().unwrap_or(80);
+    match tokio::net::lookup_host((host, port)).await {
+        Ok(addrs) => {
+            let addrs: Vec<SocketAddr> = addrs.collect();
+            !addrs.is_empty() && addrs.iter().all(|addr| is_public_ip(addr.ip()))
+        }
+        Err(_) => false,
+    }
+}
+
+/// Resolver refusing host names with a non-public address, checked at connect time so
+/// DNS rebinding can't reach internal services. The configured proxies are exempt,
+/// since they are usually internal themselves.
+struct PublicResolver {
+    allowed_hosts: Vec<String>,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        let allowed = self
+            .allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(&host));
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if !allowed && addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+                return Err(format!("{} resolves to a non-public address", host).into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Client for result pages, whose URLs come from the search engine and can point
+/// anywhere: it only connects to public addresses. Redirects are followed by
+/// [`fetch_page_text`], which checks each hop.
+fn page_client(config: &Config) -> anyhow::Result<reqwest::Client> {
+    let allowed_hosts = [&config.http_proxy, &config.https_proxy]
+        .into_iter()
+        .flatten()
+        .filter_map(|proxy| reqwest::Url::parse(proxy).ok())
+        .filter_map(|proxy| proxy.host_str().map(String::from))
+        .collect();
+
+    Ok(crate::utils::http_client::builder(config)?
+        .dns_resolver(Arc::new(PublicResolver { allowed_hosts }))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?)
+}
+
+/// Read at most `limit` bytes of a response body
+async fn read_capped(response: reqwest::Response, limit: usize) -> Option<String> {
+    let mut body = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.ok()?;
+        let room = limit - body.len();
+        body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        if body.len() >= limit {
+            break;
+        }
+    }
+    Some(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Fetch a result page and reduce it to plain text
+async fn fetch_page_text(client: &reqwest::Client, url: &str) -> Option<String> {
+    let mut url = reqwest::Url::parse(url).ok()?;
+    let mut redirects = 0;
+    let response = loop {
+        if !resolves_to_public(&url).await {
+            return None;
+        }
+        let response = client
+            .get(url.clone())
+            .timeout(PAGE_FETCH_TIMEOUT)
+            .send()
+            .await
+            .ok()?;
+        if !response.status().is_redirection() {
+            break response.error_for_status().ok()?;
+        }
+        if redirects >= MAX_PAGE_REDIRECTS {
+            return None;
+        }
+        redirects += 1;
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)?
+            .to_str()
+            .ok()?;
+        url = url.join(location).ok()?;
+    };
+
+    let is_html = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .map(|ct| ct.contains("html"))
+        .unwrap_or(true);
+    let body = read_capped(response, MAX_PAGE_BYTES).await?;
+    let body: String = body.chars().take(MAX_PAGE_CHARS).collect();
+
+    let text = if is_html { html_to_text(&body) } else { body };
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Strip markup, scripts and styles from an HTML document and collapse whitespace
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len() / 2);
+    let lower = html.to_ascii_lowercase();
+    let mut i = 0;
+
+    while i < html.len() {
+        let rest = &lower[i..];
+        if rest.starts_with("<script")
+            || rest.starts_with("<style")
+            || rest.starts_with("<noscript")
+        {
+            let tag = if rest.starts_with("<script") {
+                "</script>"
+            } else if rest.starts_with("<style") {
+                "</style>"
+            } else {
+                "</noscript>"
+            };
+            i = match rest.find(tag) {
+                Some(end) => i + end + tag.len(),
+                None => html.len(),
+            };
+            text.push(' ');
+        } else if rest.starts_with("<!--") {
+            i = match rest.find("-->") {
+                Some(end) => i + end + 3,
+                None => html.len(),
+            };
+        } else if rest.starts_with('<') {
+            i = match rest.find('>') {
+                Some(end) => i + end + 1,
+                None => html.len(),
+            };
+            text.push(' ');
+        } else {
+            let next = rest.find('<').map(|n| i + n).unwrap_or(html.len());
+            text.push_str(&html[i..next]);
+            i = next;
+        }
+    }
+
+    let decoded = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+
+    decoded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let magnitude_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let magnitude_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if magnitude_a == 0.0 || magnitude_b == 0.0 {
+        return 0.0;
+    }
+
+    dot_product / (magnitude_a * magnitude_b)
+}
+
+/// Fraction of query terms found in the chunk, used when no embedding provider is available
+fn keyword_score(query: &str, chunk: &str) -> f32 {
+    let chunk_lower = chunk.to_lowercase();
+    let terms: Vec<String> = query
+        .to_lowercase()
+        .split_whitespace()
+        .filter(|t| t.len() > 2)
+        .map(String::from)
+        .collect();
+
+    if terms.is_empty() {
+        return 0.0;
+    }
+
+    let matches = terms
+        .iter()
+        .filter(|t| chunk_lower.contains(t.as_str()))
+        .count();
+    matches as f32 / terms.len() as f32
+}
+
+/// Search the web for the query and return the most relevant passages as RAG sources,
+/// one source per result URL so citations point at the page
+pub async fn get_web_search_sources(state: &AppState, query: &str) -> AppResult<Vec<Source>> {
+    let (settings, page_client) = {
+        let config = state.config.snapshot();
+        (
+            WebSearchSettings::from_config(&config),
+            page_client(&config),
+        )
+    };
+
+    let results = search_with_settings(&state.http_client, &settings, query).await?;
+    tracing::info!("🔎 Web search returned {} result(s)", results.len());
+
+    // Fetch all pages concurrently; fall back to the snippet when a page can't be loaded
+    let pages = match page_client {
+        Ok(client) => {
+            futures::future::join_all(
+                results
+                    .iter()
+                    .map(|result| fetch_page_text(&client, &result.link)),
+            )
+            .await
+        }
+        Err(e) => {
+            tracing::warn!("Failed to build the web page client: {}", e);
+            vec![None; results.len()]
+        }
+    };
+
+    // (result index, chunk text)
+    let mut chunks: Vec<(usize, String)> = Vec::new();
+    for (idx, (result, page)) in results.iter().zip(pages).enumerate() {
+        let text = page.or_else(|| result.snippet.clone()).unwrap_or_default();
+        for chunk in
+            crate::retrieval::chunk_text(&text, settings.chunk_size, settings.chunk_overlap)
+        {
+            if !chunk.trim().is_empty() {
+                chunks.push((idx, chunk));
+            }
+        }
+    }
+
+    if chunks.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Embed query and chunks transiently; nothing is written to the vector DB
+    let scores: Vec<f32> = match &state.embedding_provider {
+        Some(provider) => {
+            let mut texts = vec![query.to_string()];
+            texts.extend(chunks.iter().map(|(_, chunk)| chunk.clone()));
+            match provider.embed(texts).await {
+                Ok(embeddings) if embeddings.len() == chunks.len() + 1 => embeddings[1..]
+                    .iter()
+                    .map(|embedding| cosine_similarity(&embeddings[0], embedding))
+                    .collect(),
+                _ => {
+                    tracing::warn!("Embedding web results failed, ranking by keyword overlap");
+                    chunks
+                        .iter()
+                        .map(|(_, chunk)| keyword_score(query, chunk))
+                        .collect()
+                }
+            }
+        }
+        None => chunks
+            .iter()
+            .map(|(_, chunk)| keyword_score(query, chunk))
+            .collect(),
+    };
+
+    let mut ranked: Vec<(f32, usize, String)> = scores
+        .into_iter()
+        .zip(chunks)
+        .map(|(score, (idx, chunk))| (score, idx, chunk))
+        .collect();
+    ranked.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    ranked.truncate(settings.top_k);
+
+    Ok(build_sources(&results, ranked))
+}
+
+/// Group ranked chunks back under the result they came from, keeping rank order
+fn build_sources(results: &[WebSearchResult], ranked: Vec<(f32, usize, String)>) -> Vec<Source> {
+    let mut sources: Vec<(usize, Source)> = Vec::new();
+
+    for (score, idx, chunk) in ranked {
+        let result = &results[idx];
+        let metadata = json!({
+            "source": result.link,
+            "title": result.title,
+            "score": score,
+        });
+
+        match sources.iter_mut().find(|(i, _)| *i == idx) {
+            Some((_, source)) => {
+                source.document.push(chunk);
+                source.metadata.push(metadata);
+            }
+            None => sources.push((
+                idx,
+                Source {
+                    source: json!({
+                        "type": "web_search",
+                        "id": result.link,
+                        "name": result.title.clone().unwrap_or_else(|| result.link.clone()),
+                        "url": result.link,
+                    }),
+                    document: vec![chunk],
+                    metadata: vec![metadata],
+                },
+            )),
+        }
+    }
+
+    sources.into_iter().map(|(_, source)| source).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text() {
+        let html = r#"<html><head><style>body { color: red; }</style>
+            <script>var x = "<p>";</script></head>
+            <body><!-- nav --><h1>Rust&nbsp;2024</h1><p>Fast &amp; safe.</p></body></html>"#;
+
+        assert_eq!(html_to_text(html), "Rust 2024 Fast & safe.");
+    }
+
+    #[test]
+    fn test_parse_results_per_engine() {
+        let searxng = json!({"results": [
+            {"url": "https://a.example", "title": "A", "content": "alpha"},
+            {"title": "missing link"}
+        ]});
+        let results = parse_results(&searxng["results"], "url", "title", "content");
+        assert_eq!(
+            results,
+            vec![WebSearchResult {
+                link: "https://a.example".to_string(),
+                title: Some("A".to_string()),
+                snippet: Some("alpha".to_string()),
+            }]
+        );
+
+        let google = json!({"items": [{"link": "https://b.example", "snippet": "beta"}]});
+        let results = parse_results(&google["items"], "link", "title", "snippet");
+        assert_eq!(results[0].link, "https://b.example");
+        assert_eq!(results[0].title, None);
+
+        assert!(parse_results(&json!(null), "url", "title", "content").is_empty());
+    }
+
+    #[test]
+    fn test_build_sources_groups_chunks_by_url() {
+        let results = vec![
+            WebSearchResult {
+                link: "https://a.example".to_string(),
+                title: Some("A".to_string()),
+                snippet: None,
+            },
+            WebSearchResult {
+                link: "https://b.example".to_string(),
+                title: None,
+                snippet: None,
+            },
+        ];
+        let ranked = vec![
+            (0.9, 1, "b1".to_string()),
+            (0.8, 0, "a1".to_string()),
+            (0.7, 1, "b2".to_string()),
+        ];
+
+        let sources = build_sources(&results, ranked);
+
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].source["id"], "https://b.example");
+        assert_eq!(sources[0].source["name"], "https://b.example");
+        assert_eq!(sources[0].document, vec!["b1", "b2"]);
+        assert_eq!(sources[0].metadata[1]["source"], "https://b.example");
+        assert_eq!(sources[1].source["name"], "A");
+    }
+
+    #[test]
+    fn test_only_public_addresses_are_fetchable() {
+        for ip in [
+            "0.1.2.3",
+            "10.1.2.3",
+            "100.64.0.1",
+            "100.127.255.254",
+            "127.0.0.1",
+            "169.254.169.254",
+            "172.16.0.1",
+            "172.31.255.255",
+            "192.0.0.8",
+            "192.0.2.1",
+            "192.88.99.1",
+            "192.168.1.1",
+            "198.18.0.1",
+            "198.19.255.254",
+            "198.51.100.7",
+            "203.0.113.9",
+            "224.0.0.251",
+            "239.255.255.250",
+            "240.0.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "::127.0.0.1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b:1::1",
+            "100::1",
+            "2001::1",
+            "2001:db8::1",
+            "2002:a9fe:a9fe::1",
+            "fd00:ec2::254",
+            "fe80::1",
+            "fec0::1",
+            "ff02::1",
+            "ff0e::1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "1.1.1.1",
+            "8.8.8.8",
+            "100.128.0.1",
+            "172.32.0.1",
+            "198.20.0.1",
+            "223.255.255.255",
+            "::ffff:1.1.1.1",
+            "2606:4700::1111",
+            "2a00:1450:4001::200e",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+
+        let url = |s: &str| reqwest::Url::parse(s).unwrap();
+        assert!(is_fetchable_url(&url("https://example.com/page")));
+        assert!(!is_fetchable_url(&url(
+            "http://169.254.169.254/latest/meta-data"
+        )));
+        assert!(!is_fetchable_url(&url("http://[::1]:8080/")));
+        assert!(!is_fetchable_url(&url("file:///etc/passwd")));
+    }
+
+    async fn start_page_server() -> (SocketAddr, actix_web::dev::ServerHandle) {
+        use actix_web::{web, App, HttpResponse, HttpServer};
+
+        let server = HttpServer::new(|| {
+            App::new()
+                .route(
+                    "/page",
+                    web::get().to(|| async { HttpResponse::Ok().body("internal") }),
+                )
+                .route(
+                    "/large",
+                    web::get()
+                        .to(|| async { HttpResponse::Ok().body("a".repeat(3 * MAX_PAGE_BYTES)) }),
+                )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        (addr, handle)
+    }
+
+    #[actix_web::test]
+    async fn test_internal_pages_are_not_fetched() {
+        let (addr, handle) = start_page_server().await;
+        let mut config = Config::from_env().unwrap();
+        config.http_proxy = None;
+        config.https_proxy = None;
+        let client = page_client(&config).unwrap();
+
+        let by_ip = format!("http://{}/page", addr);
+        let by_name = format!("http://localhost:{}/page", addr.port());
+        assert_eq!(fetch_page_text(&client, &by_ip).await, None);
+        assert_eq!(fetch_page_text(&client, &by_name).await, None);
+
+        // Large bodies are cut off instead of read whole
+        let response = reqwest::get(format!("http://{}/large", addr))
+            .await
+            .unwrap();
+        let body = read_capped(response, MAX_PAGE_BYTES).await.unwrap();
+        assert_eq!(body.len(), MAX_PAGE_BYTES);
+
+        handle.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_internal_pages_are_not_fetched_through_a_proxy() {
+        let (addr, handle) = start_page_server().await;
+        let (proxy, connections) = crate::test_utils::start_forward_proxy().await;
+        let mut config = Config::from_env().unwrap();
+        config.http_proxy = Some(format!("http://{}", proxy));
+        config.https_proxy = None;
+        config.no_proxy = Vec::new();
+        let client = page_client(&config).unwrap();
+
+        // The proxy would resolve the name itself, so the check happens before sending
+        let by_name = format!("http://localhost:{}/page", addr.port());
+        assert_eq!(fetch_page_text(&client, &by_name).await, None);
+        assert_eq!(connections.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        handle.stop(false).await;
+    }
+
+    #[test]
+    fn test_keyword_score() {
+        assert_eq!(
+            keyword_score("rust async runtime", "Tokio is an async Rust runtime"),
+            1.0
+        );
+        assert_eq!(keyword_score("rust", "nothing relevant"), 0.0);
+    }
+}
@@ -70,7 +70,11 @@ impl WebhookPayload {
 
 /// Post webhook to configured URL
 #[allow(dead_code)]
-pub async fn post_webhook(webhook_url: &str, payload: WebhookPayload) -> Result<(), AppError> {
+pub async fn post_webhook(
+    client: &Client,
+    webhook_url: &str,
+    payload: WebhookPayload,
+) -> Result<(), AppError> {
     if webhook_url.is_empty() {
         debug!("Webhook URL is empty, skipping webhook post");
         return Ok(());
@@ -81,12 +85,11 @@ pub async fn post_webhook(webhook_url: &str, payload: WebhookPayload) -> Result<
         webhook_url, payload
     );
 
-    let client = Client::builder()
+    let request = client
+        .post(webhook_url)
         .timeout(std::time::Duration::from_secs(10))
-        .build()
-        .map_err(|e| AppError::Internal(format!("Failed to create HTTP client: {}", e)))?;
-
-    match client.post(webhook_url).json(&payload).send().await {
+        .json(&payload);
+    match request.send().await {
         Ok(response) => {
             if response.status().is_success() {
                 debug!("Webhook posted successfully: {}", response.status());
@@ -111,6 +114,7 @@ pub async fn post_webhook(webhook_url: &str, payload: WebhookPayload) -> Result<
 /// Post user webhook (user-specific webhook URL)
 #[allow(dead_code)]
 pub async fn post_user_webhook(
+    client: &Client,
     webhook_url: &str,
     user_id: &str,
     payload: WebhookPayload,
@@ -126,7 +130,7 @@ pub async fn post_user_webhook(
         data_obj.insert("user_id".to_string(), json!(user_id));
     }
 
-    post_webhook(webhook_url, enriched_payload).await
+    post_webhook(client, webhook_url, enriched_payload).await
 }
 
 #[cfg(test)]
@@ -1,78 +1,392 @@
 use actix_web::{web, Error, HttpRequest, HttpResponse};
-use actix_ws::Message as WsMessage;
+use actix_ws::{CloseCode, CloseReason, Message as WsMessage, MessageStream, Session};
 use futures::stream::StreamExt;
-
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+use crate::error::{AppError, AppResult};
+use crate::middleware::auth::{authenticate_token, AuthUser};
+use crate::models::User;
+use crate::routes::openai::{check_model_request, get_openai_endpoint, request_stream_usage};
+use crate::utils::tasks::{StreamBuffer, StreamEnd};
 use crate::AppState;
 
+/// How often the server pings the client
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Connections silent for this long are closed
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Time a client connecting without a token has to send its `auth` message
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// WebSocket handler for real-time chat streaming
+///
+/// The token comes from the `Authorization` header, a `token` query parameter or a
+/// first `{"type": "auth", "token": ...}` message; cookies are not accepted so other
+/// sites can't open the socket as the user. Unauthenticated connections are closed
+/// with a policy violation.
+///
+/// `{"type": "chat", ...}` starts a completion and is answered with
+/// `{"type": "stream", "stream_id": ...}`, then `chunk` messages numbered by `seq` and
+/// a final `done` or `error`. The completion keeps running when the connection
+/// drops; `{"type": "resume", "stream_id": ..., "last_seq": ...}` on a new connection
+/// replays the chunks after `last_seq` and continues the stream.
 pub async fn websocket_chat_handler(
     req: HttpRequest,
     stream: web::Payload,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
+    let (response, session, msg_stream) = actix_ws::handle(&req, stream)?;
 
     tracing::info!("WebSocket connection established");
 
-    // Clone state for spawned task
-    let state_clone = state.clone();
+    let token = request_token(&req);
+    actix_web::rt::spawn(run_session(state, token, session, msg_stream));
 
-    // Spawn task to handle WebSocket messages
-    actix_web::rt::spawn(async move {
-        while let Some(Ok(msg)) = msg_stream.next().await {
-            match msg {
-                WsMessage::Text(text) => {
-                    tracing::debug!("Received WebSocket text: {}", text);
-
-                    // Parse the chat request
-                    match serde_json::from_str::<serde_json::Value>(&text) {
-                        Ok(payload) => {
-                            // Process chat completion in real-time
-                            if let Err(e) =
-                                process_chat_stream(&state_clone, payload, &mut session).await
-                            {
-                                tracing::error!("Error processing chat: {}", e);
-                                let error_msg = serde_json::json!({
-                                    "error": format!("Error: {}", e)
-                                })
-                                .to_string();
-                                let _ = session.text(error_msg).await;
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!("Failed to parse WebSocket message: {}", e);
-                            let error_msg = serde_json::json!({
-                                "error": format!("Invalid JSON: {}", e)
-                            })
-                            .to_string();
-                            let _ = session.text(error_msg).await;
+    Ok(response)
+}
+
+/// Token from the `Authorization` header or the `token` query parameter
+fn request_token(req: &HttpRequest) -> Option<String> {
+    let bearer = req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|t| t.to_string());
+
+    bearer.or_else(|| {
+        web::Query::<HashMap<String, String>>::from_query(req.query_string())
+            .ok()
+            .and_then(|q| q.get("token").cloned())
+    })
+}
+
+fn policy_violation(description: &str) -> CloseReason {
+    CloseReason {
+        code: CloseCode::Policy,
+        description: Some(description.to_string()),
+    }
+}
+
+/// Authenticate the connection, waiting for an `auth` message when the request
+/// carried no token
+async fn authenticate_session(
+    state: &AppState,
+    token: Option<String>,
+    msg_stream: &mut MessageStream,
+) -> Result<User, CloseReason> {
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let first = tokio::time::timeout(AUTH_TIMEOUT, msg_stream.next()).await;
+            let text = match first {
+                Ok(Some(Ok(WsMessage::Text(text)))) => text,
+                Err(_) => return Err(policy_violation("Authentication timed out")),
+                _ => return Err(policy_violation("Authentication required")),
+            };
+            let message = serde_json::from_str::<serde_json::Value>(&text).unwrap_or_default();
+            if message.get("type").and_then(|t| t.as_str()) != Some("auth") {
+                return Err(policy_violation("Authentication required"));
+            }
+            match message.get("token").and_then(|t| t.as_str()) {
+                Some(token) => token.to_string(),
+                None => return Err(policy_violation("Authentication required")),
+            }
+        }
+    };
+
+    authenticate_token(state, &token).await.map_err(|e| {
+        tracing::debug!("WebSocket chat authentication failed: {}", e);
+        policy_violation("Invalid or expired token")
+    })
+}
+
+async fn run_session(
+    state: web::Data<AppState>,
+    token: Option<String>,
+    mut session: Session,
+    mut msg_stream: MessageStream,
+) {
+    let user = match authenticate_session(&state, token, &mut msg_stream).await {
+        Ok(user) => user,
+        Err(reason) => {
+            let _ = session.close(Some(reason)).await;
+            return;
+        }
+    };
+    let auth_ok = json!({"type": "auth", "ok": true, "user_id": user.id});
+    if session.text(auth_ok.to_string()).await.is_err() {
+        return;
+    }
+
+    let mut forwarders: Vec<JoinHandle<()>> = Vec::new();
+    let mut last_activity = Instant::now();
+    let mut heartbeat = tokio::time::interval_at(
+        tokio::time::Instant::now() + HEARTBEAT_INTERVAL,
+        HEARTBEAT_INTERVAL,
+    );
+
+    let close_reason = loop {
+        tokio::select! {
+            msg = msg_stream.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) => msg,
+                    _ => break None,
+                };
+                last_activity = Instant::now();
+                match msg {
+                    WsMessage::Text(text) => {
+                        forwarders.retain(|f| !f.is_finished());
+                        if let Some(forwarder) =
+                            handle_text(&state, &user, &mut session, &text).await
+                        {
+                            forwarders.push(forwarder);
                         }
                     }
+                    WsMessage::Ping(bytes) if session.pong(&bytes).await.is_err() => {
+                        break None;
+                    }
+                    WsMessage::Close(reason) => {
+                        tracing::info!("WebSocket close: {:?}", reason);
+                        break reason;
+                    }
+                    _ => {}
                 }
-                WsMessage::Ping(bytes) => {
-                    let _ = session.pong(&bytes).await;
+            }
+            _ = heartbeat.tick() => {
+                if last_activity.elapsed() > CLIENT_TIMEOUT {
+                    tracing::debug!("Closing idle WebSocket chat connection of {}", user.id);
+                    break Some(CloseReason {
+                        code: CloseCode::Away,
+                        description: Some("Idle timeout".to_string()),
+                    });
                 }
-                WsMessage::Close(reason) => {
-                    tracing::info!("WebSocket close: {:?}", reason);
-                    let _ = session.close(reason).await;
-                    break;
+                if session.ping(b"").await.is_err() {
+                    break None;
                 }
-                _ => {}
             }
         }
+    };
 
-        tracing::info!("WebSocket connection closed");
-    });
+    // Completions keep running so the client can resume them
+    for forwarder in forwarders {
+        forwarder.abort();
+    }
+    let _ = session.close(close_reason).await;
 
-    Ok(response)
+    tracing::info!("WebSocket connection closed");
 }
 
-/// Process chat completion and stream results in real-time
-async fn process_chat_stream(
+fn error_message(stream_id: Option<&str>, error: impl std::fmt::Display) -> String {
+    json!({"type": "error", "stream_id": stream_id, "error": error.to_string()}).to_string()
+}
+
+/// Handle a client message, returning the forwarder of a stream it started or resumed
+async fn handle_text(
     state: &web::Data<AppState>,
+    user: &User,
+    session: &mut Session,
+    text: &str,
+) -> Option<JoinHandle<()>> {
+    tracing::debug!("Received WebSocket text: {}", text);
+
+    let mut message = match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(message) => message,
+        Err(e) => {
+            tracing::error!("Failed to parse WebSocket message: {}", e);
+            let _ = session
+                .text(error_message(None, format!("Invalid JSON: {}", e)))
+                .await;
+            return None;
+        }
+    };
+
+    let message_type = message
+        .get("type")
+        .and_then(|t| t.as_str())
+        .map(|t| t.to_string());
+    match message_type.as_deref() {
+        Some("ping") => {
+            let _ = session.text(json!({"type": "pong"}).to_string()).await;
+            None
+        }
+        // Already authenticated
+        Some("auth") => None,
+        Some("resume") => {
+            let stream_id = message
+                .get("stream_id")
+                .and_then(|s| s.as_str())
+                .unwrap_or_default();
+            let last_seq = message
+                .get("last_seq")
+                .and_then(|s| s.as_u64())
+                .unwrap_or(0);
+
+            match state.tasks.stream_buffer(stream_id).await {
+                Some(buffer) if buffer.owner == user.id => {
+                    Some(spawn_forwarder(buffer, session.clone(), last_seq))
+                }
+                _ => {
+                    let _ = session
+                        .text(error_message(Some(stream_id), "Stream not found"))
+                        .await;
+                    None
+                }
+            }
+        }
+        // Messages without a type are chat requests, as before
+        Some("chat") | None => {
+            if let Some(obj) = message.as_object_mut() {
+                obj.remove("type");
+            }
+            match start_stream(state, user, message).await {
+                Ok(buffer) => {
+                    let started = json!({"type": "stream", "stream_id": buffer.id});
+                    if session.text(started.to_string()).await.is_err() {
+                        return None;
+                    }
+                    Some(spawn_forwarder(buffer, session.clone(), 0))
+                }
+                Err(e) => {
+                    tracing::error!("Error processing chat: {}", e);
+                    let _ = session.text(error_message(None, e)).await;
+                    None
+                }
+            }
+        }
+        Some(other) => {
+            let _ = session
+                .text(error_message(
+                    None,
+                    format!("Unknown message type: {}", other),
+                ))
+                .await;
+            None
+        }
+    }
+}
+
+/// Send a stream's chunks after `last_seq` to the session, then follow it until it ends
+fn spawn_forwarder(
+    buffer: Arc<StreamBuffer>,
+    mut session: Session,
+    last_seq: u64,
+) -> JoinHandle<()> {
+    actix_web::rt::spawn(async move {
+        let mut updates = buffer.subscribe();
+        let mut last_seq = last_seq;
+        loop {
+            updates.borrow_and_update();
+            let replay = match buffer.replay_after(last_seq) {
+                Some(replay) => replay,
+                None => {
+                    let error = format!("Stream can no longer be resumed from seq {}", last_seq);
+                    let _ = session.text(error_message(Some(&buffer.id), error)).await;
+                    return;
+                }
+            };
+
+            for (seq, data) in replay.chunks {
+                let chunk = json!({
+                    "type": "chunk",
+                    "stream_id": buffer.id,
+                    "seq": seq,
+                    "data": data,
+                });
+                if session.text(chunk.to_string()).await.is_err() {
+                    return;
+                }
+                last_seq = seq;
+            }
+
+            if let Some(end) = replay.end {
+                let message = match end {
+                    StreamEnd::Done => {
+                        json!({"type": "done", "stream_id": buffer.id, "seq": last_seq})
+                    }
+                    StreamEnd::Failed(error) => json!({
+                        "type": "error",
+                        "stream_id": buffer.id,
+                        "seq": last_seq,
+                        "error": error,
+                    }),
+                };
+                let _ = session.text(message.to_string()).await;
+                return;
+            }
+
+            if updates.changed().await.is_err() {
+                return;
+            }
+        }
+    })
+}
+
+/// Start the completion as a task writing to a resumable stream buffer
+async fn start_stream(
+    state: &web::Data<AppState>,
+    user: &User,
     payload: serde_json::Value,
-    session: &mut actix_ws::Session,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> AppResult<Arc<StreamBuffer>> {
+    use crate::services::chat::ChatService;
+
+    let chat_id = payload
+        .get("metadata")
+        .and_then(|m| m.get("chat_id"))
+        .and_then(|v| v.as_str())
+        .map(String::from);
+
+    let model_id = payload
+        .get("model")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| AppError::BadRequest("Model ID required".to_string()))?;
+
+    // The same model access and token budget checks as HTTP chat completions
+    let auth_user = AuthUser { user: user.clone() };
+    let enforce_budget = check_model_request(state, &auth_user, model_id).await?;
+
+    // Only the chat's owner may stream into it
+    if let Some(chat_id) = &chat_id {
+        if let Some(chat) = ChatService::new(&state.db).get_chat_by_id(chat_id).await? {
+            if chat.user_id != user.id {
+                return Err(AppError::Forbidden("Access denied".to_string()));
+            }
+        }
+    }
+
+    let max_streams = state.config.snapshot().ws_chat_max_streams_per_user;
+    let tasks = state.tasks.clone();
+    let state = state.clone();
+    let user_id = user.id.clone();
+    tasks
+        .create_stream_task(&user.id, max_streams, chat_id, move |buffer| async move {
+            match process_chat_stream(&state, &user_id, payload, enforce_budget, &buffer).await {
+                Ok(()) => buffer.finish(StreamEnd::Done),
+                Err(e) => {
+                    tracing::error!("Error processing chat: {}", e);
+                    buffer.finish(StreamEnd::Failed(e.to_string()));
+                }
+            }
+        })
+        .await
+}
+
+/// Run the chat completion, writing the upstream output to `buffer` as it arrives
+///
+/// The model's own connection serves it, and the usage the upstream reports is counted
+/// against the user's token budget.
+async fn process_chat_stream(
+    state: &web::Data<AppState>,
+    user_id: &str,
+    mut payload: serde_json::Value,
+    enforce_budget: bool,
+    buffer: &StreamBuffer,
+) -> AppResult<()> {
     use crate::services::chat::ChatService;
 
     // Extract metadata
@@ -86,36 +400,50 @@ async fn process_chat_stream(
         .and_then(|m| m.get("message_id"))
         .and_then(|v| v.as_str())
         .map(String::from);
+    // Temporary chats are streamed but never saved
+    let temporary = crate::utils::chat_completion::is_temporary_chat(chat_id.as_deref(), &payload);
 
     // Extract model
     let model_id = payload
         .get("model")
         .and_then(|v| v.as_str())
-        .ok_or("Model ID required")?
+        .ok_or_else(|| AppError::BadRequest("Model ID required".to_string()))?
         .to_string();
 
     // Get OpenAI configuration
-    let (url, key) = {
-        let config = state.config.read().unwrap();
+    let client_builder = {
+        let config = state.config.snapshot();
         if !config.enable_openai_api || config.openai_api_base_urls.is_empty() {
-            return Err("OpenAI API not configured".into());
+            return Err(AppError::NotImplemented(
+                "OpenAI API not configured".to_string(),
+            ));
         }
-        let url = config.openai_api_base_urls[0].clone();
-        let key = config.openai_api_keys.get(0).cloned().unwrap_or_default();
-        (url, key)
+        crate::utils::http_client::builder(&config)
+            .map_err(|e| AppError::Internal(e.to_string()))?
     };
+    let (url, key, api_config) = get_openai_endpoint(state, &model_id)?;
+    if enforce_budget {
+        request_stream_usage(&mut payload);
+    }
 
     // Make request to OpenAI API with streaming - ZERO BUFFERING
-    let client = reqwest::Client::builder()
+    let client = client_builder
         .tcp_nodelay(true) // Disable Nagle's algorithm for real-time streaming
         .timeout(std::time::Duration::from_secs(300)) // 5 min timeout
         .http2_keep_alive_interval(Some(std::time::Duration::from_secs(5)))
         .http2_keep_alive_while_idle(true)
         .build()?;
 
-    let response = client
-        .post(format!("{}/chat/completions", url.trim_end_matches('/')))
-        .header("Authorization", format!("Bearer {}", key))
+    let mut request_builder =
+        client.post(format!("{}/chat/completions", url.trim_end_matches('/')));
+    let auth_type = api_config
+        .get("auth_type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("bearer");
+    if auth_type != "none" && !key.is_empty() {
+        request_builder = request_builder.header("Authorization", format!("Bearer {}", key));
+    }
+    let response = request_builder
         .header("Content-Type", "application/json")
         .header("Accept", "text/event-stream") // Explicitly request SSE
         .header("Cache-Control", "no-cache")
@@ -125,15 +453,7 @@ async fn process_chat_stream(
 
     if !response.status().is_success() {
         let error = response.text().await?;
-        session
-            .text(
-                serde_json::json!({
-                    "error": error
-                })
-                .to_string(),
-            )
-            .await?;
-        return Err(error.into());
+        return Err(AppError::ExternalServiceError(error));
     }
 
     // Check if streaming
@@ -143,81 +463,82 @@ async fn process_chat_stream(
         .unwrap_or(false);
 
     if is_stream {
-        // REAL-TIME STREAMING - ZERO BUFFERING APPROACH
-        // Use chunk_completion_stream to get smallest possible chunks
         let mut stream = response.bytes_stream();
+        let mut usage = crate::utils::chat_completion::SseUsageRecorder::new(
+            state.db.clone(),
+            user_id.to_string(),
+            model_id.clone(),
+        );
         let mut accumulated_content = String::new();
-        let mut buffer = String::new();
+        let mut buffer_text = String::new();
+        // Bytes of a character split across chunks
+        let mut pending = Vec::new();
 
         tracing::info!("🔴 LIVE STREAMING STARTED - forwarding chunks in real-time");
 
-        while let Some(chunk_result) = stream.next().await {
-            match chunk_result {
-                Ok(chunk) => {
-                    // Convert bytes to text
-                    if let Ok(text) = std::str::from_utf8(&chunk) {
-                        tracing::debug!("⚡ Received chunk: {} bytes", text.len());
-
-                        // CRITICAL: Send IMMEDIATELY to WebSocket without ANY delay
-                        // This ensures real-time streaming like live chat
-                        // actix-ws automatically flushes on each send, no manual flush needed
-                        if let Err(e) = session.text(text.to_string()).await {
-                            tracing::error!("❌ Failed to send WebSocket message: {}", e);
-                            return Err(e.into());
-                        }
-
-                        // Accumulate for parsing (for DB save only - doesn't affect streaming)
-                        buffer.push_str(text);
-
-                        // Parse accumulated buffer line by line for content extraction
-                        while let Some(newline_pos) = buffer.find('\n') {
-                            let line = buffer[..newline_pos].trim().to_string();
-                            buffer.drain(..=newline_pos);
-
-                            if line.is_empty() {
-                                continue;
-                            }
-
-                            // Parse SSE data for content accumulation (DB save)
-                            if line.starts_with("data: ") {
-                                let data = line.strip_prefix("data: ").unwrap_or("");
-
-                                if data == "[DONE]" {
-                                    tracing::info!("✅ Streaming completed - received [DONE]");
-                                    break;
-                                }
-
-                                if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
-                                    if let Some(choices) =
-                                        json.get("choices").and_then(|v| v.as_array())
-                                    {
-                                        if let Some(choice) = choices.first() {
-                                            if let Some(delta) = choice.get("delta") {
-                                                if let Some(content) =
-                                                    delta.get("content").and_then(|v| v.as_str())
-                                                {
-                                                    accumulated_content.push_str(content);
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+        loop {
+            let chunk_result = tokio::select! {
+                chunk = stream.next() => match chunk {
+                    Some(chunk) => chunk,
+                    None => break,
+                },
+                // Shutting down; save what was streamed so far
+                _ = state.tasks.cancelled() => {
+                    tracing::warn!("Chat stream cancelled for shutdown");
+                    break;
                 }
+            };
+
+            let chunk = match chunk_result {
+                Ok(chunk) => chunk,
                 Err(e) => {
                     tracing::error!("❌ Stream error: {}", e);
-                    session
-                        .text(
-                            serde_json::json!({
-                                "error": format!("Stream error: {}", e)
-                            })
-                            .to_string(),
-                        )
-                        .await?;
+                    return Err(AppError::ExternalServiceError(format!(
+                        "Stream error: {}",
+                        e
+                    )));
+                }
+            };
+
+            usage.feed(&chunk);
+            pending.extend_from_slice(&chunk);
+            let valid_up_to = match std::str::from_utf8(&pending) {
+                Ok(text) => text.len(),
+                Err(e) => e.valid_up_to(),
+            };
+            if valid_up_to == 0 {
+                continue;
+            }
+            let text = String::from_utf8_lossy(&pending[..valid_up_to]).into_owned();
+            pending.drain(..valid_up_to);
+
+            tracing::debug!("⚡ Received chunk: {} bytes", text.len());
+
+            // Accumulate for parsing (for DB save only - doesn't affect streaming)
+            buffer_text.push_str(&text);
+            buffer.push(text);
+
+            // Parse accumulated buffer line by line for content extraction
+            while let Some(newline_pos) = buffer_text.find('\n') {
+                let line = buffer_text[..newline_pos].trim().to_string();
+                buffer_text.drain(..=newline_pos);
+
+                let data = match line.strip_prefix("data: ") {
+                    Some(data) => data,
+                    None => continue,
+                };
+                if data == "[DONE]" {
+                    tracing::info!("✅ Streaming completed - received [DONE]");
                     break;
                 }
+                if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
+                    if let Some(content) = json
+                        .pointer("/choices/0/delta/content")
+                        .and_then(|v| v.as_str())
+                    {
+                        accumulated_content.push_str(content);
+                    }
+                }
             }
         }
 
@@ -228,11 +549,15 @@ async fn process_chat_stream(
 
         // Save to database after streaming completes - use Python's structure
         if let (Some(ch_id), Some(msg_id)) = (chat_id, message_id) {
-            if !accumulated_content.is_empty() {
+            if !accumulated_content.is_empty() && !temporary {
                 let chat_service = ChatService::new(&state.db);
 
                 // Get or create chat
                 if let Ok(Some(chat)) = chat_service.get_chat_by_id(&ch_id).await {
+                    if chat.user_id != user_id {
+                        tracing::warn!("Not saving stream into chat {} of another user", ch_id);
+                        return Ok(());
+                    }
                     let mut chat_json = chat.chat.clone();
 
                     // Use Python's structure: chat.chat.history.messages.{message_id}
@@ -317,8 +642,372 @@ async fn process_chat_stream(
     } else {
         // Non-streaming response
         let json_response = response.json::<serde_json::Value>().await?;
-        session.text(serde_json::to_string(&json_response)?).await?;
+        if let Some(usage) = json_response.get("usage") {
+            crate::utils::chat_completion::record_token_usage(&state.db, user_id, &model_id, usage)
+                .await;
+        }
+        buffer.push(json_response.to_string());
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_utils;
+    use actix_web::{App, HttpServer};
+    use futures::SinkExt;
+
+    const WORDS: [&str; 5] = ["Hello", " there", ", how", " are", " you?"];
+
+    /// OpenAI-compatible upstream streaming one word every 100ms
+    async fn upstream() -> HttpResponse {
+        let events = WORDS
+            .iter()
+            .map(|w| {
+                format!(
+                    "data: {}\n\n",
+                    json!({"choices": [{"delta": {"content": w}}]})
+                )
+            })
+            .chain(std::iter::once("data: [DONE]\n\n".to_string()));
+        let body = futures::stream::iter(events).then(|event| async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok::<_, std::io::Error>(web::Bytes::from(event))
+        });
+        HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .streaming(body)
+    }
+
+    /// Upstream model list, holding the one model the tests chat with
+    async fn upstream_models() -> HttpResponse {
+        HttpResponse::Ok().json(json!({
+            "object": "list",
+            "data": [{"id": "gpt", "object": "model", "created": 0, "owned_by": "openai"}]
+        }))
+    }
+
+    async fn test_state(
+        dir: &tempfile::TempDir,
+        upstream_url: String,
+        configure: impl FnOnce(&mut Config),
+    ) -> web::Data<AppState> {
+        let mut config = Config::from_env().unwrap();
+        config.enable_openai_api = true;
+        config.openai_api_base_urls = vec![upstream_url];
+        config.openai_api_keys = vec!["sk-test".to_string()];
+        config.enable_evaluation_arena_models = false;
+        configure(&mut config);
+        test_utils::test_state(dir, config).await
+    }
+
+    async fn start_server(dir: &tempfile::TempDir) -> (web::Data<AppState>, std::net::SocketAddr) {
+        start_server_with(dir, |_| {}).await
+    }
+
+    async fn start_server_with(
+        dir: &tempfile::TempDir,
+        configure: impl FnOnce(&mut Config),
+    ) -> (web::Data<AppState>, std::net::SocketAddr) {
+        let upstream = HttpServer::new(|| {
+            App::new()
+                .route("/chat/completions", web::post().to(upstream))
+                .route("/models", web::get().to(upstream_models))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let upstream_addr = upstream.addrs()[0];
+        actix_web::rt::spawn(upstream.run());
+
+        let state = test_state(dir, format!("http://{}", upstream_addr), configure).await;
+        let app_state = state.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_state.clone())
+                .service(web::resource("/api/ws/chat").route(web::get().to(websocket_chat_handler)))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        (state, addr)
+    }
+
+    async fn user_token(state: &AppState, id: &str) -> String {
+        test_utils::create_user(&state.db, id, "user").await;
+        test_utils::user_token(state, id)
+    }
+
+    type Connection = actix_codec::Framed<awc::BoxedSocket, awc::ws::Codec>;
+
+    async fn connect(addr: std::net::SocketAddr, query: &str) -> Connection {
+        let url = format!("http://{}/api/ws/chat{}", addr, query);
+        let (_, connection) = awc::Client::new().ws(url).connect().await.unwrap();
+        connection
+    }
+
+    async fn send(connection: &mut Connection, message: serde_json::Value) {
+        connection
+            .send(awc::ws::Message::Text(message.to_string().into()))
+            .await
+            .unwrap();
+    }
+
+    /// Next text message, skipping pings
+    async fn recv(connection: &mut Connection) -> serde_json::Value {
+        loop {
+            let frame = tokio::time::timeout(Duration::from_secs(5), connection.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            match frame {
+                awc::ws::Frame::Text(text) => return serde_json::from_slice(&text).unwrap(),
+                awc::ws::Frame::Ping(_) | awc::ws::Frame::Pong(_) => {}
+                other => panic!("unexpected frame {:?}", other),
+            }
+        }
+    }
+
+    async fn recv_close(connection: &mut Connection) -> Option<CloseReason> {
+        let frame = tokio::time::timeout(Duration::from_secs(5), connection.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        match frame {
+            awc::ws::Frame::Close(reason) => reason,
+            other => panic!("expected close, got {:?}", other),
+        }
+    }
+
+    /// Chunk data until the stream's `done` message
+    async fn recv_until_done(connection: &mut Connection, mut next_seq: u64) -> String {
+        let mut data = String::new();
+        loop {
+            let message = recv(connection).await;
+            match message["type"].as_str() {
+                Some("chunk") => {
+                    assert_eq!(message["seq"], next_seq);
+                    next_seq += 1;
+                    data.push_str(message["data"].as_str().unwrap());
+                }
+                Some("done") => return data,
+                _ => panic!("unexpected message {}", message),
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_unauthenticated_connections_are_closed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_state, addr) = start_server(&dir).await;
+
+        let mut connection = connect(addr, "?token=not-a-token").await;
+        let reason = recv_close(&mut connection).await.unwrap();
+        assert_eq!(reason.code, CloseCode::Policy);
+
+        // Without a token, the first message has to authenticate
+        let mut connection = connect(addr, "").await;
+        send(&mut connection, json!({"type": "chat", "model": "gpt"})).await;
+        let reason = recv_close(&mut connection).await.unwrap();
+        assert_eq!(reason.code, CloseCode::Policy);
+    }
+
+    #[actix_web::test]
+    async fn test_chats_check_model_access_and_budget() {
+        use crate::services::model::ModelService;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (state, addr) = start_server_with(&dir, |config| {
+            config.model_token_limits = json!({"gpt": 10});
+        })
+        .await;
+        let token = user_token(&state, "alice").await;
+        user_token(&state, "bob").await;
+
+        // A workspace model bob kept private
+        ModelService::new(&state.db)
+            .create_model(
+                "bobs-model",
+                "bob",
+                Some("gpt"),
+                "Bob's",
+                json!({}),
+                json!({}),
+            )
+            .await
+            .unwrap();
+        sqlx::query("UPDATE model SET access_control = '{}' WHERE id = 'bobs-model'")
+            .execute(&state.db.pool)
+            .await
+            .unwrap();
+
+        let mut connection = connect(addr, &format!("?token={}", token)).await;
+        recv(&mut connection).await;
+        let chat = |model: &str| {
+            json!({
+                "type": "chat",
+                "model": model,
+                "stream": true,
+                "messages": [{"role": "user", "content": "Hi"}],
+            })
+        };
+
+        for model in ["bobs-model", "not-listed"] {
+            send(&mut connection, chat(model)).await;
+            let message = recv(&mut connection).await;
+            assert_eq!(message["type"], "error", "{}", model);
+            assert!(message["error"].as_str().unwrap().contains("not found"));
+        }
+
+        // Within budget the chat streams; once it is used up it is refused
+        send(&mut connection, chat("gpt")).await;
+        assert_eq!(recv(&mut connection).await["type"], "stream");
+        recv_until_done(&mut connection, 1).await;
+
+        crate::utils::chat_completion::record_token_usage(
+            &state.db,
+            "alice",
+            "gpt",
+            &json!({"prompt_tokens": 20, "completion_tokens": 5}),
+        )
+        .await;
+        send(&mut connection, chat("gpt")).await;
+        let message = recv(&mut connection).await;
+        assert_eq!(message["type"], "error");
+        assert!(message["error"]
+            .as_str()
+            .unwrap()
+            .contains("Token budget for model gpt exceeded"));
+    }
+
+    #[actix_web::test]
+    async fn test_resume_replays_missed_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (state, addr) = start_server(&dir).await;
+        let token = user_token(&state, "alice").await;
+
+        // Authenticated by its first message
+        let mut connection = connect(addr, "").await;
+        send(&mut connection, json!({"type": "auth", "token": token})).await;
+        assert_eq!(recv(&mut connection).await["ok"], true);
+
+        let request = json!({
+            "type": "chat",
+            "model": "gpt",
+            "stream": true,
+            "messages": [{"role": "user", "content": "Hi"}],
+        });
+        send(&mut connection, request).await;
+        let started = recv(&mut connection).await;
+        assert_eq!(started["type"], "stream");
+        let stream_id = started["stream_id"].as_str().unwrap().to_string();
+
+        let first = recv(&mut connection).await;
+        assert_eq!(first["seq"], 1);
+        let mut data = first["data"].as_str().unwrap().to_string();
+        drop(connection);
+
+        // Another user can't pick the stream up
+        let other_token = user_token(&state, "bob").await;
+        let mut connection = connect(addr, &format!("?token={}", other_token)).await;
+        recv(&mut connection).await;
+        send(
+            &mut connection,
+            json!({"type": "resume", "stream_id": stream_id, "last_seq": 1}),
+        )
+        .await;
+        assert_eq!(recv(&mut connection).await["error"], "Stream not found");
+
+        let mut connection = connect(addr, &format!("?token={}", token)).await;
+        recv(&mut connection).await;
+        send(
+            &mut connection,
+            json!({"type": "resume", "stream_id": stream_id, "last_seq": 1}),
+        )
+        .await;
+        data.push_str(&recv_until_done(&mut connection, 2).await);
+
+        let content: String = data
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter_map(|data| serde_json::from_str::<serde_json::Value>(data).ok())
+            .filter_map(|event| {
+                event["choices"][0]["delta"]["content"]
+                    .as_str()
+                    .map(String::from)
+            })
+            .collect();
+        assert_eq!(content, WORDS.concat());
+    }
+
+    #[actix_web::test]
+    async fn test_temporary_chats_are_not_saved() {
+        use crate::models::chat::CreateChatRequest;
+        use crate::services::chat::ChatService;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (state, addr) = start_server(&dir).await;
+        let token = user_token(&state, "alice").await;
+        let chat_service = ChatService::new(&state.db);
+        chat_service
+            .create_chat(
+                "alice",
+                CreateChatRequest {
+                    id: "chat-1".to_string(),
+                    title: None,
+                    chat: json!({"history": {"messages": {}}}),
+                    folder_id: None,
+                    archived: None,
+                    pinned: None,
+                    share_id: None,
+                    meta: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let mut connection = connect(addr, &format!("?token={}", token)).await;
+        recv(&mut connection).await;
+        for (message_id, metadata) in [
+            ("msg-1", json!({"chat_id": "local:session-1"})),
+            ("msg-2", json!({"chat_id": "chat-1", "temporary": true})),
+            ("msg-3", json!({"chat_id": "chat-1"})),
+        ] {
+            let mut metadata = metadata;
+            metadata["message_id"] = json!(message_id);
+            let request = json!({
+                "type": "chat",
+                "model": "gpt",
+                "stream": true,
+                "messages": [{"role": "user", "content": "Hi"}],
+                "metadata": metadata,
+            });
+            send(&mut connection, request).await;
+            assert_eq!(recv(&mut connection).await["type"], "stream");
+            recv_until_done(&mut connection, 1).await;
+        }
+
+        let (chats,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM chat")
+            .fetch_one(&state.db.pool)
+            .await
+            .unwrap();
+        assert_eq!(chats, 1);
+
+        // Only the message sent outside a temporary chat was saved
+        let chat = chat_service
+            .get_chat_by_id("chat-1")
+            .await
+            .unwrap()
+            .unwrap();
+        let messages = &chat.chat["history"]["messages"];
+        assert!(messages.get("msg-2").is_none());
+        assert_eq!(messages["msg-3"]["content"], WORDS.concat());
+    }
+}
@@ -1,9 +1,17 @@
 use actix_web::{web, HttpResponse, ResponseError, Result};
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use tokio::sync::{mpsc, oneshot};
 use tracing::{error, info};
 use validator::Validate;
 
+use crate::error::{SandboxError, SandboxResult};
+use crate::executor::sessions::{Session, SessionLease};
 use crate::executor::ExecutionEngine;
-use crate::models::{ConfigResponse, ExecuteRequest, ExecuteResponse, HealthResponse};
+use crate::models::{
+    ConfigResponse, CreateSessionRequest, ErrorResponse, ExecuteRequest, ExecuteResponse,
+    ExecutionEvent, HealthResponse, Language, SessionExecuteRequest,
+};
 use crate::state::AppState;
 
 pub async fn health_check(state: web::Data<AppState>) -> Result<HttpResponse> {
@@ -27,20 +35,12 @@ pub async fn health_check(state: web::Data<AppState>) -> Result<HttpResponse> {
 }
 
 pub async fn get_config(state: web::Data<AppState>) -> Result<HttpResponse> {
-    let mut supported_languages = Vec::new();
-
-    if state.config.enable_python {
-        supported_languages.push("python".to_string());
-    }
-    if state.config.enable_javascript {
-        supported_languages.push("javascript".to_string());
-    }
-    if state.config.enable_shell {
-        supported_languages.push("shell".to_string());
-    }
-    if state.config.enable_rust {
-        supported_languages.push("rust".to_string());
-    }
+    let supported_languages = state
+        .config
+        .enabled_languages()
+        .iter()
+        .map(|language| language.name().to_string())
+        .collect();
 
     let response = ConfigResponse {
         max_execution_time: state.config.max_execution_time,
@@ -53,14 +53,12 @@ pub async fn get_config(state: web::Data<AppState>) -> Result<HttpResponse> {
     Ok(HttpResponse::Ok().json(response))
 }
 
-pub async fn execute_code(
-    state: web::Data<AppState>,
-    request: web::Json<ExecuteRequest>,
-) -> Result<HttpResponse> {
+/// Response for a request that is invalid or over the concurrent execution limit
+async fn reject_request(state: &AppState, request: &ExecuteRequest) -> Option<HttpResponse> {
     // Validate request
     if let Err(e) = request.validate() {
         error!("Invalid request: {}", e);
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+        return Some(HttpResponse::BadRequest().json(serde_json::json!({
             "error": "ValidationError",
             "message": format!("Invalid request: {}", e),
         })));
@@ -74,7 +72,7 @@ pub async fn execute_code(
     // Check concurrent execution limit
     let stats = state.get_stats().await;
     if stats.active_executions >= state.config.max_concurrent_executions {
-        return Ok(HttpResponse::TooManyRequests().json(serde_json::json!({
+        return Some(HttpResponse::TooManyRequests().json(serde_json::json!({
             "error": "TooManyRequests",
             "message": format!(
                 "Maximum concurrent executions ({}) reached",
@@ -83,28 +81,28 @@ pub async fn execute_code(
         })));
     }
 
-    // Increment active executions
-    state.increment_executions().await;
+    None
+}
 
-    // Create execution engine with container pool for fast execution
-    let engine = ExecutionEngine::new(
+/// Create execution engine with container pool for fast execution
+fn execution_engine(state: &AppState) -> ExecutionEngine {
+    ExecutionEngine::new(
         state.container_manager.clone(),
         Some(state.container_pool.clone()),
         state.config.clone(),
-    );
-
-    // Execute code
-    let result = engine.execute(request.into_inner()).await;
+    )
+}
 
+/// Count a finished execution in the stats
+async fn record_outcome(state: &AppState, result: &SandboxResult<ExecuteResponse>) {
     // Decrement active executions
     state.decrement_active_executions().await;
 
+    let mut stats = state.stats.write().await;
     match result {
         Ok(response) => {
             info!("Execution completed: {}", response.execution_id);
 
-            // Update stats
-            let mut stats = state.stats.write().await;
             match response.status {
                 crate::models::ExecutionStatus::Success => {
                     stats.successful_executions += 1;
@@ -118,19 +116,277 @@ pub async fn execute_code(
                 }
                 _ => {}
             }
-
-            Ok(HttpResponse::Ok().json(response))
         }
         Err(e) => {
             error!("Execution error: {}", e);
-
-            // Update failed stats
-            let mut stats = state.stats.write().await;
             stats.failed_executions += 1;
+        }
+    }
+}
+
+pub async fn execute_code(
+    state: web::Data<AppState>,
+    request: web::Json<ExecuteRequest>,
+) -> Result<HttpResponse> {
+    if let Some(rejection) = reject_request(&state, &request).await {
+        return Ok(rejection);
+    }
 
-            Ok(e.error_response())
+    // Increment active executions
+    state.increment_executions().await;
+
+    // Execute code
+    let result = execution_engine(&state).execute(request.into_inner()).await;
+    record_outcome(&state, &result).await;
+
+    match result {
+        Ok(response) => Ok(HttpResponse::Ok().json(response)),
+        Err(e) => Ok(e.error_response()),
+    }
+}
+
+/// Execute code, streaming its stdout as NDJSON `stdout` events while it runs and ending
+/// with a `result` event, or an `error` event when it couldn't run
+pub async fn execute_code_stream(
+    state: web::Data<AppState>,
+    request: web::Json<ExecuteRequest>,
+) -> Result<HttpResponse> {
+    if let Some(rejection) = reject_request(&state, &request).await {
+        return Ok(rejection);
+    }
+
+    state.increment_executions().await;
+
+    // The execution keeps running if the client disconnects, like a regular one
+    let (output_tx, output_rx) = mpsc::unbounded_channel();
+    let (result_tx, result_rx) = oneshot::channel();
+    actix_web::rt::spawn(async move {
+        let result = execution_engine(&state)
+            .execute_streaming(request.into_inner(), output_tx)
+            .await;
+        record_outcome(&state, &result).await;
+        let _ = result_tx.send(result);
+    });
+
+    Ok(stream_events(output_rx, result_rx))
+}
+
+/// NDJSON response of a streamed execution: its stdout, then its outcome
+fn stream_events(
+    output_rx: mpsc::UnboundedReceiver<String>,
+    result_rx: oneshot::Receiver<SandboxResult<ExecuteResponse>>,
+) -> HttpResponse {
+    // The stdout events end once the execution dropped its sender
+    let stdout = stream::unfold(output_rx, |mut output_rx| async move {
+        let chunk = output_rx.recv().await?;
+        Some((ExecutionEvent::Stdout(chunk), output_rx))
+    });
+    let outcome = stream::once(async move {
+        match result_rx.await {
+            Ok(Ok(response)) => ExecutionEvent::Result(response),
+            Ok(Err(e)) => ExecutionEvent::Error(ErrorResponse {
+                error: format!("{:?}", e),
+                message: e.to_string(),
+                execution_id: None,
+            }),
+            Err(_) => ExecutionEvent::Error(ErrorResponse {
+                error: "InternalError".to_string(),
+                message: "Execution ended without a result".to_string(),
+                execution_id: None,
+            }),
         }
+    });
+    let body = stdout.chain(outcome).map(|event| {
+        let mut line = serde_json::to_vec(&event)?;
+        line.push(b'\n');
+        Ok::<_, actix_web::Error>(Bytes::from(line))
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(body)
+}
+
+/// Open a session, or return the caller's open session with the same key
+pub async fn create_session(
+    state: web::Data<AppState>,
+    request: web::Json<CreateSessionRequest>,
+) -> Result<HttpResponse> {
+    if !state.config.enable_sessions {
+        return Ok(
+            SandboxError::InvalidInput("Sessions are disabled".to_string()).error_response(),
+        );
+    }
+
+    let request = request.into_inner();
+    let language = match Language::from_str(&request.language) {
+        Ok(language) => language,
+        Err(e) => return Ok(SandboxError::InvalidInput(e).error_response()),
+    };
+    if !state.config.is_language_enabled(&language) {
+        return Ok(
+            SandboxError::LanguageNotSupported(format!("{:?} is not enabled", language))
+                .error_response(),
+        );
     }
+
+    if let Some(ref key) = request.session_key {
+        if let Some(session) = state
+            .sessions
+            .find_by_key(key, request.user_id.as_deref(), &language)
+            .await
+        {
+            return Ok(HttpResponse::Ok().json(session));
+        }
+    }
+
+    let container_id = match state.container_pool.reserve_container(&language).await {
+        Ok(container_id) => container_id,
+        Err(e) => {
+            error!("Failed to open session: {}", e);
+            return Ok(e.error_response());
+        }
+    };
+
+    let session = Session::new(language, request.session_key, request.user_id, container_id);
+    info!("Opened {} session {}", session.language.name(), session.id);
+
+    Ok(HttpResponse::Created().json(state.sessions.insert(session).await))
+}
+
+pub async fn get_session(
+    state: web::Data<AppState>,
+    session_id: web::Path<String>,
+) -> Result<HttpResponse> {
+    match state.sessions.get(&session_id).await {
+        Ok(session) => Ok(HttpResponse::Ok().json(session)),
+        Err(e) => Ok(e.error_response()),
+    }
+}
+
+pub async fn delete_session(
+    state: web::Data<AppState>,
+    session_id: web::Path<String>,
+) -> Result<HttpResponse> {
+    if close_session(&state, &session_id).await {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(SandboxError::SessionNotFound(session_id.into_inner()).error_response())
+    }
+}
+
+/// Close a session and release its container. False if there is no such session.
+async fn close_session(state: &AppState, session_id: &str) -> bool {
+    match state.sessions.remove(session_id).await {
+        Some(session) => {
+            info!("Closed session {}", session.id);
+            state
+                .container_pool
+                .release_container(&session.container_id)
+                .await;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Take a session for an execution, or the response when the execution can't run in it
+async fn begin_session_execution(
+    state: &AppState,
+    session_id: &str,
+    request: SessionExecuteRequest,
+) -> std::result::Result<(SessionLease, ExecuteRequest), HttpResponse> {
+    let lease = match state.sessions.begin(session_id).await {
+        Ok(lease) => lease,
+        Err(e) => return Err(e.error_response()),
+    };
+
+    let request = ExecuteRequest {
+        code: request.code,
+        language: lease.language.name().to_string(),
+        timeout: request.timeout,
+        env_vars: request.env_vars,
+        files: request.files,
+        packages: request.packages,
+        user_id: lease.user_id.clone(),
+        request_id: request.request_id,
+    };
+    if let Some(rejection) = reject_request(state, &request).await {
+        state.sessions.cancel(&lease).await;
+        return Err(rejection);
+    }
+
+    Ok((lease, request))
+}
+
+/// Keep a session for its next execution, or close it when the execution timed out or
+/// failed in the container, as the container may still be busy or broken
+async fn end_session_execution(
+    state: &AppState,
+    lease: &SessionLease,
+    result: &SandboxResult<ExecuteResponse>,
+) {
+    match result {
+        Ok(response) if response.error.is_none() => state.sessions.finish(lease).await,
+        Ok(_) => {
+            close_session(state, &lease.session_id).await;
+        }
+        // Rejected before it ran
+        Err(_) => state.sessions.cancel(lease).await,
+    }
+}
+
+pub async fn execute_in_session(
+    state: web::Data<AppState>,
+    session_id: web::Path<String>,
+    request: web::Json<SessionExecuteRequest>,
+) -> Result<HttpResponse> {
+    let (lease, request) =
+        match begin_session_execution(&state, &session_id, request.into_inner()).await {
+            Ok(execution) => execution,
+            Err(rejection) => return Ok(rejection),
+        };
+
+    state.increment_executions().await;
+
+    let result = execution_engine(&state)
+        .execute_in_session(&lease, request, None)
+        .await;
+    record_outcome(&state, &result).await;
+    end_session_execution(&state, &lease, &result).await;
+
+    match result {
+        Ok(response) => Ok(HttpResponse::Ok().json(response)),
+        Err(e) => Ok(e.error_response()),
+    }
+}
+
+/// Execute in a session, streaming NDJSON like `execute_code_stream`
+pub async fn execute_in_session_stream(
+    state: web::Data<AppState>,
+    session_id: web::Path<String>,
+    request: web::Json<SessionExecuteRequest>,
+) -> Result<HttpResponse> {
+    let (lease, request) =
+        match begin_session_execution(&state, &session_id, request.into_inner()).await {
+            Ok(execution) => execution,
+            Err(rejection) => return Ok(rejection),
+        };
+
+    state.increment_executions().await;
+
+    let (output_tx, output_rx) = mpsc::unbounded_channel();
+    let (result_tx, result_rx) = oneshot::channel();
+    actix_web::rt::spawn(async move {
+        let result = execution_engine(&state)
+            .execute_in_session(&lease, request, Some(output_tx))
+            .await;
+        record_outcome(&state, &result).await;
+        end_session_execution(&state, &lease, &result).await;
+        let _ = result_tx.send(result);
+    });
+
+    Ok(stream_events(output_rx, result_rx))
 }
 
 pub async fn get_stats(state: web::Data<AppState>) -> Result<HttpResponse> {
@@ -142,6 +398,8 @@ pub async fn get_stats(state: web::Data<AppState>) -> Result<HttpResponse> {
         "successful_executions": stats.successful_executions,
         "failed_executions": stats.failed_executions,
         "timeout_executions": stats.timeout_executions,
+        "active_sessions": state.sessions.count().await,
+        "reserved_containers": state.container_pool.reserved_count().await,
         "uptime_seconds": state.uptime_seconds(),
         "success_rate": if stats.total_executions > 0 {
             (stats.successful_executions as f64 / stats.total_executions as f64) * 100.0
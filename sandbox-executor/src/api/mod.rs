@@ -9,6 +9,27 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
             .route("/health", web::get().to(handlers::health_check))
             .route("/config", web::get().to(handlers::get_config))
             .route("/execute", web::post().to(handlers::execute_code))
+            .route(
+                "/execute/stream",
+                web::post().to(handlers::execute_code_stream),
+            )
+            .route("/sessions", web::post().to(handlers::create_session))
+            .route(
+                "/sessions/{session_id}",
+                web::get().to(handlers::get_session),
+            )
+            .route(
+                "/sessions/{session_id}",
+                web::delete().to(handlers::delete_session),
+            )
+            .route(
+                "/sessions/{session_id}/execute",
+                web::post().to(handlers::execute_in_session),
+            )
+            .route(
+                "/sessions/{session_id}/execute/stream",
+                web::post().to(handlers::execute_in_session_stream),
+            )
             .route("/stats", web::get().to(handlers::get_stats)),
     );
 }
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::env;
 
+use crate::models::Language;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub host: String,
@@ -20,6 +22,11 @@ pub struct Config {
 
     // Container settings
     pub container_image: String,
+    /// Images for each language, instead of `container_image`
+    pub python_image: Option<String>,
+    pub javascript_image: Option<String>,
+    pub bash_image: Option<String>,
+    pub rust_image: Option<String>,
     pub network_mode: NetworkMode,
     pub read_only_root: bool,
     pub drop_all_capabilities: bool,
@@ -28,16 +35,41 @@ pub struct Config {
     pub enable_streaming: bool,
     pub keep_containers: bool, // For debugging
 
+    // Artifacts: files the code writes to /workspace, returned with the result
+    pub max_artifacts: usize,
+    pub max_artifact_size_mb: u64,
+    pub max_artifacts_total_mb: u64,
+
+    // Sessions: a container kept for a caller, so state carries over between executions
+    pub enable_sessions: bool,
+    pub max_sessions: usize,
+    pub session_idle_ttl_seconds: u64,
+    pub session_max_lifetime_seconds: u64,
+
+    // Packages: Python packages code may ask for, installed before it runs
+    /// `name` or `name==version` entries; empty allows none
+    pub package_allowlist: Vec<String>,
+    /// Host directory of wheels to install from, mounted read-only into containers
+    pub package_wheel_dir: Option<String>,
+    /// Package index to install from when there's no wheel directory, e.g. a proxy
+    pub package_index_url: Option<String>,
+    pub package_install_timeout: u64, // seconds
+
     // Container Pool settings
     pub enable_container_pool: bool,
     pub pool_size_per_language: usize,
+    /// Warm containers for each language, instead of `pool_size_per_language`
+    pub python_pool_size: Option<usize>,
+    pub javascript_pool_size: Option<usize>,
+    pub bash_pool_size: Option<usize>,
+    pub rust_pool_size: Option<usize>,
     pub pool_max_container_reuse: u32,
     pub pool_max_container_age_seconds: u64,
 
     // Language support
     pub enable_python: bool,
     pub enable_javascript: bool,
-    pub enable_shell: bool,
+    pub enable_bash: bool,
     pub enable_rust: bool,
 
     // Audit & logging
@@ -73,6 +105,10 @@ impl Default for Config {
 
             // Container defaults
             container_image: "sandbox-runtime:latest".to_string(),
+            python_image: None,
+            javascript_image: None,
+            bash_image: None,
+            rust_image: None,
             network_mode: NetworkMode::None,
             read_only_root: false, // TODO: Enable with proper tmpfs configuration
             drop_all_capabilities: true,
@@ -81,16 +117,37 @@ impl Default for Config {
             enable_streaming: true,
             keep_containers: false,
 
+            // Artifact defaults - a few plots or CSVs
+            max_artifacts: 10,
+            max_artifact_size_mb: 5,
+            max_artifacts_total_mb: 20,
+
+            // Session defaults
+            enable_sessions: true,
+            max_sessions: 10,
+            session_idle_ttl_seconds: 600,      // 10 minutes
+            session_max_lifetime_seconds: 3600, // 1 hour
+
+            // Package defaults - nothing is installed unless allowlisted
+            package_allowlist: Vec::new(),
+            package_wheel_dir: None,
+            package_index_url: None,
+            package_install_timeout: 120,
+
             // Container Pool defaults
             enable_container_pool: true,
             pool_size_per_language: 3,
+            python_pool_size: None,
+            javascript_pool_size: None,
+            bash_pool_size: None,
+            rust_pool_size: None,
             pool_max_container_reuse: 50,
             pool_max_container_age_seconds: 600, // 10 minutes
 
             // Language support - all enabled by default
             enable_python: true,
             enable_javascript: true,
-            enable_shell: true,
+            enable_bash: true,
             enable_rust: true,
 
             // Audit
@@ -165,6 +222,11 @@ impl Config {
             config.container_image = image;
         }
 
+        config.python_image = env::var("PYTHON_IMAGE").ok();
+        config.javascript_image = env::var("JAVASCRIPT_IMAGE").ok();
+        config.bash_image = env::var("BASH_IMAGE").ok();
+        config.rust_image = env::var("RUST_IMAGE").ok();
+
         if let Ok(network) = env::var("NETWORK_MODE") {
             config.network_mode = match network.to_lowercase().as_str() {
                 "none" => NetworkMode::None,
@@ -198,6 +260,68 @@ impl Config {
                 .map_err(|e| format!("Invalid keep_containers: {}", e))?;
         }
 
+        // Artifacts
+        if let Ok(max_artifacts) = env::var("MAX_ARTIFACTS") {
+            config.max_artifacts = max_artifacts
+                .parse()
+                .map_err(|e| format!("Invalid max_artifacts: {}", e))?;
+        }
+
+        if let Ok(max_size) = env::var("MAX_ARTIFACT_SIZE_MB") {
+            config.max_artifact_size_mb = max_size
+                .parse()
+                .map_err(|e| format!("Invalid max_artifact_size_mb: {}", e))?;
+        }
+
+        if let Ok(max_total) = env::var("MAX_ARTIFACTS_TOTAL_MB") {
+            config.max_artifacts_total_mb = max_total
+                .parse()
+                .map_err(|e| format!("Invalid max_artifacts_total_mb: {}", e))?;
+        }
+
+        // Sessions
+        if let Ok(enable_sessions) = env::var("ENABLE_SESSIONS") {
+            config.enable_sessions = enable_sessions
+                .parse()
+                .map_err(|e| format!("Invalid enable_sessions: {}", e))?;
+        }
+
+        if let Ok(max_sessions) = env::var("MAX_SESSIONS") {
+            config.max_sessions = max_sessions
+                .parse()
+                .map_err(|e| format!("Invalid max_sessions: {}", e))?;
+        }
+
+        if let Ok(idle_ttl) = env::var("SESSION_IDLE_TTL_SECONDS") {
+            config.session_idle_ttl_seconds = idle_ttl
+                .parse()
+                .map_err(|e| format!("Invalid session_idle_ttl_seconds: {}", e))?;
+        }
+
+        if let Ok(max_lifetime) = env::var("SESSION_MAX_LIFETIME_SECONDS") {
+            config.session_max_lifetime_seconds = max_lifetime
+                .parse()
+                .map_err(|e| format!("Invalid session_max_lifetime_seconds: {}", e))?;
+        }
+
+        // Packages
+        if let Ok(allowlist) = env::var("PACKAGE_ALLOWLIST") {
+            config.package_allowlist = allowlist
+                .split(',')
+                .map(|entry| entry.trim().to_string())
+                .filter(|entry| !entry.is_empty())
+                .collect();
+        }
+
+        config.package_wheel_dir = env::var("PACKAGE_WHEEL_DIR").ok();
+        config.package_index_url = env::var("PACKAGE_INDEX_URL").ok();
+
+        if let Ok(install_timeout) = env::var("PACKAGE_INSTALL_TIMEOUT") {
+            config.package_install_timeout = install_timeout
+                .parse()
+                .map_err(|e| format!("Invalid package_install_timeout: {}", e))?;
+        }
+
         // Container Pool settings
         if let Ok(enable_pool) = env::var("ENABLE_CONTAINER_POOL") {
             config.enable_container_pool = enable_pool
@@ -211,6 +335,20 @@ impl Config {
                 .map_err(|e| format!("Invalid pool_size_per_language: {}", e))?;
         }
 
+        for (name, pool_size) in [
+            ("POOL_SIZE_PYTHON", &mut config.python_pool_size),
+            ("POOL_SIZE_JAVASCRIPT", &mut config.javascript_pool_size),
+            ("POOL_SIZE_BASH", &mut config.bash_pool_size),
+            ("POOL_SIZE_RUST", &mut config.rust_pool_size),
+        ] {
+            if let Ok(size) = env::var(name) {
+                *pool_size = Some(
+                    size.parse()
+                        .map_err(|e| format!("Invalid {}: {}", name.to_lowercase(), e))?,
+                );
+            }
+        }
+
         if let Ok(max_reuse) = env::var("POOL_MAX_CONTAINER_REUSE") {
             config.pool_max_container_reuse = max_reuse
                 .parse()
@@ -236,10 +374,11 @@ impl Config {
                 .map_err(|e| format!("Invalid enable_javascript: {}", e))?;
         }
 
-        if let Ok(shell) = env::var("ENABLE_SHELL") {
-            config.enable_shell = shell
+        // ENABLE_SHELL is the older name
+        if let Ok(bash) = env::var("ENABLE_BASH").or_else(|_| env::var("ENABLE_SHELL")) {
+            config.enable_bash = bash
                 .parse()
-                .map_err(|e| format!("Invalid enable_shell: {}", e))?;
+                .map_err(|e| format!("Invalid enable_bash: {}", e))?;
         }
 
         if let Ok(rust) = env::var("ENABLE_RUST") {
@@ -261,4 +400,69 @@ impl Config {
 
         Ok(config)
     }
+    /// Whether code in `language` may run
+    pub fn is_language_enabled(&self, language: &Language) -> bool {
+        match language {
+            Language::Python => self.enable_python,
+            Language::Javascript => self.enable_javascript,
+            Language::Bash => self.enable_bash,
+            Language::Rust => self.enable_rust,
+        }
+    }
+
+    /// Languages code may run in
+    pub fn enabled_languages(&self) -> Vec<Language> {
+        Language::ALL
+            .into_iter()
+            .filter(|language| self.is_language_enabled(language))
+            .collect()
+    }
+
+    /// Image of the containers running `language`
+    pub fn image_for(&self, language: &Language) -> &str {
+        let image = match language {
+            Language::Python => &self.python_image,
+            Language::Javascript => &self.javascript_image,
+            Language::Bash => &self.bash_image,
+            Language::Rust => &self.rust_image,
+        };
+        image.as_deref().unwrap_or(&self.container_image)
+    }
+
+    /// Number of warm containers the pool keeps for `language`
+    pub fn pool_size_for(&self, language: &Language) -> usize {
+        let size = match language {
+            Language::Python => self.python_pool_size,
+            Language::Javascript => self.javascript_pool_size,
+            Language::Bash => self.bash_pool_size,
+            Language::Rust => self.rust_pool_size,
+        };
+        size.unwrap_or(self.pool_size_per_language)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_settings_fall_back_to_the_defaults() {
+        let config = Config {
+            javascript_image: Some("node:20-slim".to_string()),
+            python_pool_size: Some(5),
+            rust_pool_size: Some(0),
+            enable_rust: false,
+            ..Config::default()
+        };
+
+        assert_eq!(config.image_for(&Language::Javascript), "node:20-slim");
+        assert_eq!(config.image_for(&Language::Bash), "sandbox-runtime:latest");
+        assert_eq!(config.pool_size_for(&Language::Python), 5);
+        assert_eq!(config.pool_size_for(&Language::Bash), 3);
+        assert_eq!(config.pool_size_for(&Language::Rust), 0);
+
+        let enabled = config.enabled_languages();
+        let names: Vec<&str> = enabled.iter().map(|language| language.name()).collect();
+        assert_eq!(names, vec!["python", "javascript", "bash"]);
+    }
 }
@@ -0,0 +1,189 @@
+use std::io::Read;
+
+use crate::config::Config;
+
+/// Find command listing the files an execution wrote to /workspace, as `size<TAB>path`
+/// lines. Files older than the script were there before it ran.
+pub fn list_command(script: &str) -> Vec<String> {
+    vec![
+        "find".to_string(),
+        "/workspace".to_string(),
+        "-type".to_string(),
+        "f".to_string(),
+        "-newer".to_string(),
+        format!("/workspace/{}", script),
+        "-printf".to_string(),
+        "%s\\t%P\\n".to_string(),
+    ]
+}
+
+/// A file the code wrote, relative to /workspace
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkspaceFile {
+    pub path: String,
+    pub size: u64,
+}
+
+/// Files in the output of [`list_command`], leaving out hidden files and caches
+pub fn parse_listing(listing: &str) -> Vec<WorkspaceFile> {
+    let mut files: Vec<WorkspaceFile> = listing
+        .lines()
+        .filter_map(|line| {
+            let (size, path) = line.split_once('\t')?;
+            Some(WorkspaceFile {
+                path: path.to_string(),
+                size: size.trim().parse().ok()?,
+            })
+        })
+        .filter(|file| {
+            !file.path.is_empty()
+                && file
+                    .path
+                    .split('/')
+                    .all(|part| !part.starts_with('.') && part != "__pycache__")
+        })
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files
+}
+
+/// Files to return, within the per-file size and per-execution count and size limits.
+/// Returns the files to keep and the paths of those left out.
+pub fn select_artifacts(
+    files: Vec<WorkspaceFile>,
+    config: &Config,
+) -> (Vec<WorkspaceFile>, Vec<String>) {
+    let max_file_size = config.max_artifact_size_mb * 1024 * 1024;
+    let max_total_size = config.max_artifacts_total_mb * 1024 * 1024;
+
+    let mut selected = Vec::new();
+    let mut skipped = Vec::new();
+    let mut total_size = 0;
+    for file in files {
+        if selected.len() >= config.max_artifacts
+            || file.size > max_file_size
+            || total_size + file.size > max_total_size
+        {
+            skipped.push(file.path);
+            continue;
+        }
+        total_size += file.size;
+        selected.push(file);
+    }
+    (selected, skipped)
+}
+
+/// Contents of the first file in a tar archive from the Docker archive API
+pub fn read_tar_file(archive: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut archive = tar::Archive::new(archive);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_file() {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            return Ok(content);
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "archive has no file",
+    ))
+}
+
+/// MIME type of a file from its extension
+pub fn mime_type_for(path: &str) -> &'static str {
+    let extension = path
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "csv" => "text/csv",
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64) -> WorkspaceFile {
+        WorkspaceFile {
+            path: path.to_string(),
+            size,
+        }
+    }
+
+    #[test]
+    fn test_listing_leaves_out_hidden_files_and_caches() {
+        let listing = "2048\tplot.png\n\
+                       12\t.config/matplotlib/fontlist.json\n\
+                       300\tout/data.csv\n\
+                       90\t__pycache__/helper.cpython-312.pyc\n\
+                       not a line\n";
+        assert_eq!(
+            parse_listing(listing),
+            vec![file("out/data.csv", 300), file("plot.png", 2048)]
+        );
+    }
+
+    #[test]
+    fn test_artifacts_are_limited_per_execution() {
+        let config = Config {
+            max_artifacts: 2,
+            max_artifact_size_mb: 1,
+            max_artifacts_total_mb: 2,
+            ..Config::default()
+        };
+        let mb = 1024 * 1024;
+
+        let (selected, skipped) = select_artifacts(
+            vec![
+                file("a.csv", mb),
+                file("big.bin", 2 * mb),
+                file("c.png", mb),
+            ],
+            &config,
+        );
+        assert_eq!(selected, vec![file("a.csv", mb), file("c.png", mb)]);
+        assert_eq!(skipped, vec!["big.bin".to_string()]);
+
+        let (selected, skipped) = select_artifacts(
+            vec![file("a.txt", 1), file("b.txt", 1), file("c.txt", 1)],
+            &config,
+        );
+        assert_eq!(selected.len(), 2);
+        assert_eq!(skipped, vec!["c.txt".to_string()]);
+    }
+
+    #[test]
+    fn test_tar_file_and_mime_type() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "data.csv", &b"a,b\n1"[..])
+            .unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        assert_eq!(read_tar_file(&archive).unwrap(), b"a,b\n1");
+        assert_eq!(mime_type_for("out/Plot.PNG"), "image/png");
+        assert_eq!(mime_type_for("data.csv"), "text/csv");
+        assert_eq!(mime_type_for("README"), "application/octet-stream");
+    }
+}
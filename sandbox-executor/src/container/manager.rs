@@ -1,16 +1,20 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
 use bollard::exec::{CreateExecOptions, StartExecOptions, StartExecResults};
 use bollard::models::HostConfig;
 use bollard::service::ContainerCreateBody;
 use bollard::Docker;
 use bytes::Bytes;
 use futures::StreamExt;
+use std::collections::HashSet;
 use std::time::Duration;
 use tokio::time::timeout;
 use tracing::{debug, error, info, warn};
 
 use crate::config::Config as AppConfig;
+use crate::container::packages::{self, PackageSpec};
+use crate::container::{artifacts, repl};
 use crate::error::{SandboxError, SandboxResult};
-use crate::models::{ExecutionContext, ExecutionResult};
+use crate::models::{Artifact, ExecutionContext, ExecutionResult, Language};
 use crate::security::SecurityConfig;
 
 pub struct ContainerManager {
@@ -49,12 +53,29 @@ impl ContainerManager {
     pub async fn create_execution_container(
         &self,
         ctx: &ExecutionContext,
+    ) -> SandboxResult<String> {
+        // Long enough to install packages before running the code
+        let lifetime = ctx.timeout + self.config.package_install_timeout + 10;
+        self.create_container(ctx, lifetime).await
+    }
+
+    /// Create a container that keeps running for `lifetime` seconds, for executions to be
+    /// run in it
+    pub async fn create_container(
+        &self,
+        ctx: &ExecutionContext,
+        lifetime: u64,
     ) -> SandboxResult<String> {
         let container_name = format!("sandbox-exec-{}", ctx.id);
 
         // Build security configuration
         let security_config = SecurityConfig::from_config(&self.config);
-        let host_config = security_config.to_host_config();
+        let mut host_config = security_config.to_host_config();
+
+        // The wheel cache packages are installed from
+        if let Some(ref wheel_dir) = self.config.package_wheel_dir {
+            host_config.binds = Some(vec![format!("{}:{}:ro", wheel_dir, packages::WHEEL_DIR)]);
+        }
 
         debug!(
             "Container readonly_rootfs setting: {:?}",
@@ -71,10 +92,13 @@ impl ContainerManager {
         // Add language-specific env vars
         env_vars.push("DEBIAN_FRONTEND=noninteractive".to_string());
         env_vars.push(format!("EXECUTION_TIMEOUT={}", ctx.timeout));
+        if let Language::Python = ctx.language {
+            env_vars.push(format!("PYTHONPATH={}", packages::PACKAGE_DIR));
+        }
 
         // Build container config
         let mut container_config = ContainerCreateBody::default();
-        container_config.image = Some(self.config.container_image.clone());
+        container_config.image = Some(self.config.image_for(&ctx.language).to_string());
         container_config.hostname = Some(format!("sandbox-{}", &ctx.id.to_string()[..8]));
         container_config.user = Some("sandbox".to_string()); // Non-root user
         container_config.working_dir = Some("/workspace".to_string());
@@ -88,7 +112,7 @@ impl ContainerManager {
 
         // Override the default CMD to keep the container running
         // This allows us to exec into it to write files and run code
-        container_config.cmd = Some(vec!["sleep".to_string(), format!("{}", ctx.timeout + 10)]);
+        container_config.cmd = Some(vec!["sleep".to_string(), lifetime.to_string()]);
 
         debug!("Creating container: {}", container_name);
 
@@ -139,7 +163,8 @@ impl ContainerManager {
         let script_content = self.prepare_execution_script(ctx)?;
 
         // Copy code to container
-        self.copy_to_container(container_id, &script_content, ctx)
+        let filepath = format!("/workspace/script.{}", ctx.language.file_extension());
+        self.copy_to_container(container_id, &script_content, &filepath)
             .await?;
 
         // Execute the code
@@ -179,7 +204,12 @@ impl ContainerManager {
                 while let Some(msg) = output.next().await {
                     match msg {
                         Ok(bollard::container::LogOutput::StdOut { message }) => {
-                            stdout.push_str(&String::from_utf8_lossy(&message));
+                            let text = String::from_utf8_lossy(&message);
+                            if let Some(ref output) = ctx.output {
+                                // The receiver is gone when the client disconnected
+                                let _ = output.send(text.to_string());
+                            }
+                            stdout.push_str(&text);
                         }
                         Ok(bollard::container::LogOutput::StdErr { message }) => {
                             stderr.push_str(&String::from_utf8_lossy(&message));
@@ -219,6 +249,8 @@ impl ContainerManager {
         // Get memory usage stats
         let memory_used_mb = self.get_memory_usage(container_id).await;
 
+        let artifacts = self.collect_artifacts(container_id, ctx).await;
+
         Ok(ExecutionResult {
             stdout: stdout.trim().to_string(),
             stderr: stderr.trim().to_string(),
@@ -226,9 +258,72 @@ impl ContainerManager {
             exit_code,
             execution_time_ms,
             memory_used_mb,
+            artifacts,
         })
     }
 
+    /// Files the code wrote to /workspace, within the artifact limits. Files that can't
+    /// be read are left out rather than failing the execution.
+    async fn collect_artifacts(&self, container_id: &str, ctx: &ExecutionContext) -> Vec<Artifact> {
+        if self.config.max_artifacts == 0 {
+            return Vec::new();
+        }
+
+        let script = format!("script.{}", ctx.language.file_extension());
+        let listing = match self
+            .exec_command_output(container_id, artifacts::list_command(&script))
+            .await
+        {
+            Ok(listing) => listing,
+            Err(e) => {
+                warn!("Failed to list artifacts of execution {}: {}", ctx.id, e);
+                return Vec::new();
+            }
+        };
+
+        let (files, skipped) =
+            artifacts::select_artifacts(artifacts::parse_listing(&listing), &self.config);
+        if !skipped.is_empty() {
+            warn!(
+                "Execution {} wrote files over the artifact limits: {}",
+                ctx.id,
+                skipped.join(", ")
+            );
+        }
+
+        let mut collected = Vec::new();
+        for file in files {
+            match self.download_file(container_id, &file.path).await {
+                Ok(content) => collected.push(Artifact {
+                    mime_type: artifacts::mime_type_for(&file.path).to_string(),
+                    size: content.len() as u64,
+                    content: STANDARD.encode(content),
+                    name: file.path,
+                }),
+                Err(e) => warn!("Failed to read artifact {}: {}", file.path, e),
+            }
+        }
+        collected
+    }
+
+    /// Read a file out of /workspace through the Docker archive API
+    async fn download_file(&self, container_id: &str, path: &str) -> SandboxResult<Vec<u8>> {
+        let mut stream = self.docker.download_from_container(
+            container_id,
+            Some(bollard::container::DownloadFromContainerOptions {
+                path: format!("/workspace/{}", path),
+            }),
+        );
+
+        let mut archive = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| SandboxError::InternalError(e.to_string()))?;
+            archive.extend_from_slice(&chunk);
+        }
+
+        artifacts::read_tar_file(&archive).map_err(|e| SandboxError::InternalError(e.to_string()))
+    }
+
     fn prepare_execution_script(&self, ctx: &ExecutionContext) -> SandboxResult<String> {
         Ok(ctx.code.clone())
     }
@@ -237,11 +332,8 @@ impl ContainerManager {
         &self,
         container_id: &str,
         content: &str,
-        ctx: &ExecutionContext,
+        filepath: &str,
     ) -> SandboxResult<()> {
-        let filename = format!("script.{}", ctx.language.file_extension());
-        let filepath = format!("/workspace/{}", filename);
-
         debug!("Copying file {} to container {}", filepath, container_id);
 
         // Use exec to write the file content instead of upload_to_container
@@ -250,7 +342,7 @@ impl ContainerManager {
         let exec_config = bollard::exec::CreateExecOptions {
             attach_stdout: Some(true),
             attach_stderr: Some(true),
-            cmd: Some(vec!["tee".to_string(), filepath]),
+            cmd: Some(vec!["tee".to_string(), filepath.to_string()]),
             user: Some("sandbox".to_string()),
             working_dir: Some("/workspace".to_string()),
             attach_stdin: Some(true),
@@ -355,26 +447,13 @@ impl ContainerManager {
     }
 
     fn build_execution_command(&self, ctx: &ExecutionContext) -> Vec<String> {
-        let filename = format!("script.{}", ctx.language.file_extension());
-
-        match ctx.language {
-            crate::models::Language::Python => {
-                vec!["python3".to_string(), filename]
-            }
-            crate::models::Language::Javascript => {
-                vec!["node".to_string(), filename]
-            }
-            crate::models::Language::Shell => {
-                vec!["/bin/sh".to_string(), filename]
-            }
-            crate::models::Language::Rust => {
-                vec![
-                    "sh".to_string(),
-                    "-c".to_string(),
-                    format!("rustc {} -o /tmp/program && /tmp/program", filename),
-                ]
-            }
+        // In a Python session the script runs in the session's interpreter
+        if let Some(step) = ctx.session_step {
+            return repl::step_command(step);
         }
+
+        let filename = format!("script.{}", ctx.language.file_extension());
+        ctx.language.command(&filename)
     }
 
     async fn get_memory_usage(&self, container_id: &str) -> Option<f64> {
@@ -483,20 +562,143 @@ impl ContainerManager {
         }
     }
 
-    /// Execute a simple command without capturing all output (for cleanup operations)
-    pub async fn exec_simple_command(
+    /// Install the packages in `requested` the container doesn't have yet, adding them to
+    /// `installed`
+    pub async fn ensure_packages(
         &self,
         container_id: &str,
-        command: Vec<String>,
+        requested: &[PackageSpec],
+        installed: &mut HashSet<String>,
     ) -> SandboxResult<()> {
-        debug!(
-            "Executing simple command in container {}: {:?}",
-            container_id, command
+        let missing: Vec<PackageSpec> = requested
+            .iter()
+            .filter(|spec| !installed.contains(&spec.to_string()))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        self.install_packages(container_id, &missing).await?;
+        installed.extend(missing.iter().map(|spec| spec.to_string()));
+        Ok(())
+    }
+
+    /// Install packages as root, so the code can't change them, within the package
+    /// install timeout rather than the execution's
+    async fn install_packages(
+        &self,
+        container_id: &str,
+        specs: &[PackageSpec],
+    ) -> SandboxResult<()> {
+        let command = match packages::install_command(specs, &self.config) {
+            Some(command) => command,
+            None => {
+                return Err(SandboxError::ConfigurationError(
+                    "Set PACKAGE_WHEEL_DIR or PACKAGE_INDEX_URL to install packages".to_string(),
+                ))
+            }
+        };
+
+        let names: Vec<String> = specs.iter().map(|spec| spec.to_string()).collect();
+        info!(
+            "Installing packages in container {}: {}",
+            container_id,
+            names.join(", ")
         );
 
+        let install = async {
+            let exec_config = CreateExecOptions {
+                attach_stdout: Some(true),
+                attach_stderr: Some(true),
+                cmd: Some(command),
+                user: Some("root".to_string()),
+                working_dir: Some("/workspace".to_string()),
+                ..Default::default()
+            };
+
+            let exec_result = self
+                .docker
+                .create_exec(container_id, exec_config)
+                .await
+                .map_err(|e| SandboxError::PackageInstallFailed(e.to_string()))?;
+
+            let mut log = String::new();
+            if let StartExecResults::Attached { mut output, .. } = self
+                .docker
+                .start_exec(&exec_result.id, None::<StartExecOptions>)
+                .await
+                .map_err(|e| SandboxError::PackageInstallFailed(e.to_string()))?
+            {
+                while let Some(msg) = output.next().await {
+                    match msg {
+                        Ok(bollard::container::LogOutput::StdOut { message })
+                        | Ok(bollard::container::LogOutput::StdErr { message }) => {
+                            log.push_str(&String::from_utf8_lossy(&message));
+                        }
+                        Ok(_) => {}
+                        Err(e) => return Err(SandboxError::PackageInstallFailed(e.to_string())),
+                    }
+                }
+            }
+
+            let inspect = self.docker.inspect_exec(&exec_result.id).await.ok();
+            let exit_code = inspect.and_then(|i| i.exit_code).unwrap_or(0);
+            Ok::<(i64, String), SandboxError>((exit_code, log))
+        };
+
+        match timeout(
+            Duration::from_secs(self.config.package_install_timeout),
+            install,
+        )
+        .await
+        {
+            Ok(Ok((0, _))) => {
+                debug!("Installed packages in container {}", container_id);
+                Ok(())
+            }
+            Ok(Ok((_, log))) => Err(SandboxError::PackageInstallFailed(log.trim().to_string())),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(SandboxError::PackageInstallFailed(format!(
+                "timed out after {}s",
+                self.config.package_install_timeout
+            ))),
+        }
+    }
+
+    /// Start the persistent interpreter of a Python session in a started container
+    pub async fn start_python_repl(&self, container_id: &str) -> SandboxResult<()> {
+        self.exec_command_output(
+            container_id,
+            vec![
+                "mkdir".to_string(),
+                "-p".to_string(),
+                repl::REPL_DIR.to_string(),
+            ],
+        )
+        .await?;
+        self.copy_to_container(container_id, repl::PYTHON_REPL, &repl::repl_path("repl.py"))
+            .await?;
+        self.copy_to_container(
+            container_id,
+            repl::PYTHON_REPL_CLIENT,
+            &repl::repl_path("client.py"),
+        )
+        .await?;
+        self.exec_detached(container_id, repl::start_command())
+            .await?;
+
+        debug!("Started Python interpreter in container {}", container_id);
+        Ok(())
+    }
+
+    /// Execute a command in the background, without waiting for it
+    pub async fn exec_detached(
+        &self,
+        container_id: &str,
+        command: Vec<String>,
+    ) -> SandboxResult<()> {
         let exec_config = CreateExecOptions {
-            attach_stdout: Some(false),
-            attach_stderr: Some(false),
             cmd: Some(command),
             user: Some("sandbox".to_string()),
             working_dir: Some("/workspace".to_string()),
@@ -510,10 +712,58 @@ impl ContainerManager {
             .map_err(|e| SandboxError::ExecutionFailed(e.to_string()))?;
 
         self.docker
-            .start_exec(&exec_result.id, None::<StartExecOptions>)
+            .start_exec(
+                &exec_result.id,
+                Some(StartExecOptions {
+                    detach: true,
+                    ..Default::default()
+                }),
+            )
             .await
             .map_err(|e| SandboxError::ExecutionFailed(e.to_string()))?;
 
         Ok(())
     }
+
+    /// Execute a command and wait for it to finish, returning its stdout
+    pub async fn exec_command_output(
+        &self,
+        container_id: &str,
+        command: Vec<String>,
+    ) -> SandboxResult<String> {
+        let exec_config = CreateExecOptions {
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            cmd: Some(command),
+            user: Some("sandbox".to_string()),
+            working_dir: Some("/workspace".to_string()),
+            ..Default::default()
+        };
+
+        let exec_result = self
+            .docker
+            .create_exec(container_id, exec_config)
+            .await
+            .map_err(|e| SandboxError::ExecutionFailed(e.to_string()))?;
+
+        let mut stdout = String::new();
+        if let StartExecResults::Attached { mut output, .. } = self
+            .docker
+            .start_exec(&exec_result.id, None::<StartExecOptions>)
+            .await
+            .map_err(|e| SandboxError::ExecutionFailed(e.to_string()))?
+        {
+            while let Some(msg) = output.next().await {
+                match msg {
+                    Ok(bollard::container::LogOutput::StdOut { message }) => {
+                        stdout.push_str(&String::from_utf8_lossy(&message));
+                    }
+                    Ok(_) => {}
+                    Err(e) => return Err(SandboxError::ExecutionFailed(e.to_string())),
+                }
+            }
+        }
+
+        Ok(stdout)
+    }
 }
@@ -1,5 +1,8 @@
+pub mod artifacts;
 pub mod manager;
+pub mod packages;
 pub mod pool;
+pub mod repl;
 pub mod runtime;
 
 pub use manager::ContainerManager;
@@ -0,0 +1,221 @@
+use std::fmt;
+
+use crate::config::Config;
+
+/// Directory Python packages are installed to, owned by root so code can't change the
+/// packages later executions in the container import
+pub const PACKAGE_DIR: &str = "/opt/sandbox-packages";
+
+/// Where the wheel cache volume is mounted in containers
+pub const WHEEL_DIR: &str = "/wheels";
+
+/// A package to install: a name, optionally pinned to a version
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageSpec {
+    /// Normalized name, e.g. `scikit-learn` for `Scikit_Learn`
+    pub name: String,
+    pub version: Option<String>,
+}
+
+impl PackageSpec {
+    /// Parse `name` or `name==version`. Other version specifiers, extras and URLs are
+    /// not accepted.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (name, version) = match spec.trim().split_once("==") {
+            Some((name, version)) => (name.trim(), Some(version.trim())),
+            None => (spec.trim(), None),
+        };
+
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+        let valid_version = version.is_none_or(|version| {
+            !version.is_empty()
+                && version
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '!' | '-'))
+        });
+        if !valid_name || !valid_version {
+            return None;
+        }
+
+        Some(Self {
+            name: normalize_name(name),
+            version: version.map(String::from),
+        })
+    }
+}
+
+impl fmt::Display for PackageSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            Some(ref version) => write!(f, "{}=={}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Name as package indexes compare them: lowercase, with runs of `-`, `_` and `.` as `-`
+fn normalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, '-' | '_' | '.') {
+            if !normalized.ends_with('-') {
+                normalized.push('-');
+            }
+        } else {
+            normalized.push(c.to_ascii_lowercase());
+        }
+    }
+    normalized
+}
+
+/// Packages to install for the requested ones, or the requested ones the allowlist
+/// doesn't allow. An allowlist entry without a version allows any version; one with a
+/// version allows that version, which is installed when none is requested.
+pub fn resolve(
+    requested: &[String],
+    allowlist: &[String],
+) -> Result<Vec<PackageSpec>, Vec<String>> {
+    let allowed: Vec<PackageSpec> = allowlist
+        .iter()
+        .filter_map(|entry| PackageSpec::parse(entry))
+        .collect();
+
+    let mut specs: Vec<PackageSpec> = Vec::new();
+    let mut rejected = Vec::new();
+    for request in requested {
+        let spec = PackageSpec::parse(request).and_then(|spec| {
+            let entry = allowed.iter().find(|entry| entry.name == spec.name)?;
+            match (&entry.version, &spec.version) {
+                (None, _) => Some(spec),
+                (Some(_), None) => Some(entry.clone()),
+                (Some(pinned), Some(version)) if pinned == version => Some(spec),
+                _ => None,
+            }
+        });
+        match spec {
+            Some(spec) if !specs.iter().any(|s| s.name == spec.name) => specs.push(spec),
+            Some(_) => {}
+            None => rejected.push(request.trim().to_string()),
+        }
+    }
+
+    if rejected.is_empty() {
+        Ok(specs)
+    } else {
+        Err(rejected)
+    }
+}
+
+/// pip command installing `specs` from the wheel cache, or else the package index.
+/// None when neither is configured.
+pub fn install_command(specs: &[PackageSpec], config: &Config) -> Option<Vec<String>> {
+    let mut command: Vec<String> = [
+        "python3",
+        "-m",
+        "pip",
+        "install",
+        "--quiet",
+        "--disable-pip-version-check",
+        "--no-warn-script-location",
+        // Wheels only, so no package's build script runs
+        "--only-binary=:all:",
+        "--target",
+        PACKAGE_DIR,
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+
+    if config.package_wheel_dir.is_some() {
+        command.push("--no-index".to_string());
+        command.push("--find-links".to_string());
+        command.push(WHEEL_DIR.to_string());
+    } else if let Some(ref index_url) = config.package_index_url {
+        command.push("--index-url".to_string());
+        command.push(index_url.clone());
+    } else {
+        return None;
+    }
+
+    command.extend(specs.iter().map(|spec| spec.to_string()));
+    Some(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_package_specs() {
+        assert_eq!(
+            PackageSpec::parse(" Scikit_Learn==1.5.0 "),
+            Some(PackageSpec {
+                name: "scikit-learn".to_string(),
+                version: Some("1.5.0".to_string()),
+            })
+        );
+        assert_eq!(PackageSpec::parse("numpy").unwrap().to_string(), "numpy");
+        for invalid in [
+            "numpy>=1.0",
+            "pandas[excel]",
+            "--index-url",
+            "",
+            "numpy==",
+            "a b",
+        ] {
+            assert_eq!(PackageSpec::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_only_allowlisted_packages_resolve() {
+        let allowlist = strings(&["pandas==2.2.2", "numpy", "matplotlib"]);
+
+        let specs = resolve(&strings(&["Pandas", "numpy==1.26.4", "pandas"]), &allowlist).unwrap();
+        let specs: Vec<String> = specs.iter().map(ToString::to_string).collect();
+        assert_eq!(specs, vec!["pandas==2.2.2", "numpy==1.26.4"]);
+
+        assert_eq!(
+            resolve(
+                &strings(&["numpy", "pandas==2.1.0", "requests", "numpy>=2"]),
+                &allowlist
+            ),
+            Err(strings(&["pandas==2.1.0", "requests", "numpy>=2"]))
+        );
+        assert_eq!(resolve(&strings(&["numpy"]), &[]), Err(strings(&["numpy"])));
+    }
+
+    #[test]
+    fn test_install_command_uses_the_configured_source() {
+        let specs = vec![PackageSpec::parse("numpy==1.26.4").unwrap()];
+
+        let config = Config {
+            package_wheel_dir: Some("/srv/wheels".to_string()),
+            package_index_url: Some("http://pypi-proxy:3141/simple".to_string()),
+            ..Config::default()
+        };
+        let command = install_command(&specs, &config).unwrap();
+        assert!(command.ends_with(&strings(&[
+            "--no-index",
+            "--find-links",
+            WHEEL_DIR,
+            "numpy==1.26.4"
+        ])));
+
+        let config = Config {
+            package_index_url: Some("http://pypi-proxy:3141/simple".to_string()),
+            ..Config::default()
+        };
+        let command = install_command(&specs, &config).unwrap();
+        assert!(command.contains(&"http://pypi-proxy:3141/simple".to_string()));
+
+        assert_eq!(install_command(&specs, &Config::default()), None);
+    }
+}
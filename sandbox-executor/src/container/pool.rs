@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, Semaphore};
@@ -18,6 +18,8 @@ pub struct PooledContainer {
     pub created_at: Instant,
     pub last_used: Instant,
     pub execution_count: u32,
+    /// Packages installed in the container, so they're only installed once
+    pub packages: HashSet<String>,
 }
 
 /// Container pool for reusing containers across executions
@@ -25,6 +27,9 @@ pub struct ContainerPool {
     manager: Arc<ContainerManager>,
     config: Arc<Config>,
     pools: Arc<Mutex<PoolsByLanguage>>,
+    /// Containers reserved for sessions, which don't go back to the pool, with the
+    /// packages installed in them
+    reserved: Arc<Mutex<HashMap<String, HashSet<String>>>>,
     semaphore: Arc<Semaphore>,
 }
 
@@ -32,7 +37,7 @@ pub struct ContainerPool {
 struct PoolsByLanguage {
     python: VecDeque<PooledContainer>,
     javascript: VecDeque<PooledContainer>,
-    shell: VecDeque<PooledContainer>,
+    bash: VecDeque<PooledContainer>,
     rust: VecDeque<PooledContainer>,
 }
 
@@ -41,7 +46,7 @@ impl PoolsByLanguage {
         Self {
             python: VecDeque::new(),
             javascript: VecDeque::new(),
-            shell: VecDeque::new(),
+            bash: VecDeque::new(),
             rust: VecDeque::new(),
         }
     }
@@ -50,13 +55,13 @@ impl PoolsByLanguage {
         match language {
             Language::Python => &mut self.python,
             Language::Javascript => &mut self.javascript,
-            Language::Shell => &mut self.shell,
+            Language::Bash => &mut self.bash,
             Language::Rust => &mut self.rust,
         }
     }
 
     fn total_size(&self) -> usize {
-        self.python.len() + self.javascript.len() + self.shell.len() + self.rust.len()
+        self.python.len() + self.javascript.len() + self.bash.len() + self.rust.len()
     }
 }
 
@@ -68,6 +73,7 @@ impl ContainerPool {
             manager,
             config,
             pools: Arc::new(Mutex::new(PoolsByLanguage::new())),
+            reserved: Arc::new(Mutex::new(HashMap::new())),
             semaphore,
         }
     }
@@ -79,22 +85,19 @@ impl ContainerPool {
             return Ok(());
         }
 
-        info!(
-            "Initializing container pool (size: {} per language)...",
-            self.config.pool_size_per_language
-        );
+        info!("Initializing container pool...");
 
-        let languages = vec![
-            (Language::Python, self.config.enable_python),
-            (Language::Javascript, self.config.enable_javascript),
-            (Language::Shell, self.config.enable_shell),
-            (Language::Rust, self.config.enable_rust),
-        ];
-
-        for (language, enabled) in languages {
-            if enabled {
-                for _ in 0..self.config.pool_size_per_language {
-                    if let Err(e) = self.create_pooled_container(&language).await {
+        for language in self.config.enabled_languages() {
+            let size = self.config.pool_size_for(&language);
+            info!("Pre-warming {} {} containers", size, language.name());
+
+            for _ in 0..size {
+                match self.create_pooled_container(&language).await {
+                    Ok(container) => {
+                        let mut pools = self.pools.lock().await;
+                        pools.get_pool_mut(&language).push_back(container);
+                    }
+                    Err(e) => {
                         warn!("Failed to pre-create container for {:?}: {}", language, e);
                     }
                 }
@@ -122,7 +125,7 @@ impl ContainerPool {
             })?;
 
         // Try to get a container from the pool
-        let container = self.acquire_container(&ctx.language).await?;
+        let mut container = self.acquire_container(&ctx.language).await?;
 
         debug!(
             "Using pooled container {} (age: {}s, uses: {})",
@@ -131,8 +134,15 @@ impl ContainerPool {
             container.execution_count
         );
 
-        // Execute the code
-        let result = self.manager.execute_in_container(&container.id, ctx).await;
+        // Install the packages it doesn't have yet, then execute the code
+        let result = match self
+            .manager
+            .ensure_packages(&container.id, &ctx.packages, &mut container.packages)
+            .await
+        {
+            Ok(()) => self.manager.execute_in_container(&container.id, ctx).await,
+            Err(e) => Err(e),
+        };
 
         // Handle result and return container to pool
         match result {
@@ -158,6 +168,93 @@ impl ContainerPool {
         }
     }
 
+    /// Reserve a container for a session, until it's released. Warm containers only live
+    /// for one execution, so a session gets a new one that lives for the whole session.
+    pub async fn reserve_container(&self, language: &Language) -> SandboxResult<String> {
+        // Hold the lock while creating, so sessions opened together stay within the limit
+        let mut reserved = self.reserved.lock().await;
+        if reserved.len() >= self.config.max_sessions {
+            return Err(SandboxError::ResourceLimitExceeded(format!(
+                "Maximum sessions ({}) reached",
+                self.config.max_sessions
+            )));
+        }
+
+        let ctx = self.container_context(language);
+        let container_id = self
+            .manager
+            .create_container(&ctx, self.config.session_max_lifetime_seconds)
+            .await?;
+
+        let started = async {
+            self.manager.start_container(&container_id).await?;
+            if let Language::Python = language {
+                self.manager.start_python_repl(&container_id).await?;
+            }
+            Ok::<(), SandboxError>(())
+        }
+        .await;
+        if let Err(e) = started {
+            self.manager.cleanup_container(&container_id).await;
+            return Err(e);
+        }
+
+        reserved.insert(container_id.clone(), HashSet::new());
+        debug!(
+            "Reserved container {} for a {:?} session ({} reserved)",
+            container_id,
+            language,
+            reserved.len()
+        );
+        Ok(container_id)
+    }
+
+    /// Execute code in a reserved container, keeping its state for the next execution
+    pub async fn execute_reserved(
+        &self,
+        container_id: &str,
+        ctx: &ExecutionContext,
+    ) -> SandboxResult<ExecutionResult> {
+        // Sessions count towards the concurrent executions like everything else
+        let _permit =
+            self.semaphore.acquire().await.map_err(|e| {
+                SandboxError::InternalError(format!("Failed to acquire permit: {}", e))
+            })?;
+
+        let installed = self.reserved.lock().await.get(container_id).cloned();
+        let mut installed = match installed {
+            Some(installed) => installed,
+            None => {
+                return Err(SandboxError::InternalError(format!(
+                    "Container {} is not reserved",
+                    container_id
+                )))
+            }
+        };
+
+        // One execution runs in a session at a time, so nothing else changes the set
+        self.manager
+            .ensure_packages(container_id, &ctx.packages, &mut installed)
+            .await?;
+        if let Some(packages) = self.reserved.lock().await.get_mut(container_id) {
+            *packages = installed;
+        }
+
+        self.manager.execute_in_container(container_id, ctx).await
+    }
+
+    /// Remove a reserved container once its session is closed
+    pub async fn release_container(&self, container_id: &str) {
+        self.reserved.lock().await.remove(container_id);
+        debug!("Releasing reserved container {}", container_id);
+        self.manager.cleanup_container(container_id).await;
+    }
+
+    /// Number of containers reserved for sessions
+    pub async fn reserved_count(&self) -> usize {
+        self.reserved.lock().await.len()
+    }
+
     /// Acquire a container from the pool or create a new one
     async fn acquire_container(&self, language: &Language) -> SandboxResult<PooledContainer> {
         let mut pools = self.pools.lock().await;
@@ -183,22 +280,29 @@ impl ContainerPool {
         self.create_pooled_container(language).await
     }
 
-    /// Create a new container for the pool
-    async fn create_pooled_container(&self, language: &Language) -> SandboxResult<PooledContainer> {
+    /// A dummy execution context just for container creation
+    fn container_context(&self, language: &Language) -> ExecutionContext {
         use chrono::Utc;
 
-        // Create a dummy execution context just for container creation
-        let ctx = ExecutionContext {
+        ExecutionContext {
             id: Uuid::new_v4(),
             code: "# Pool initialization".to_string(),
             language: language.clone(),
             timeout: self.config.max_execution_time,
             env_vars: vec![],
             files: vec![],
+            packages: vec![],
             user_id: None,
             request_id: None,
             created_at: Utc::now(),
-        };
+            output: None,
+            session_step: None,
+        }
+    }
+
+    /// Create a new container for the pool
+    async fn create_pooled_container(&self, language: &Language) -> SandboxResult<PooledContainer> {
+        let ctx = self.container_context(language);
 
         let container_id = self.manager.create_execution_container(&ctx).await?;
         self.manager.start_container(&container_id).await?;
@@ -210,6 +314,7 @@ impl ContainerPool {
             created_at: now,
             last_used: now,
             execution_count: 0,
+            packages: HashSet::new(),
         };
 
         debug!(
@@ -260,11 +365,13 @@ impl ContainerPool {
 
     /// Clean container state after execution
     async fn clean_container(&self, container_id: &str) -> SandboxResult<()> {
-        // Remove all files from /workspace
+        // Remove all files from /workspace, so the next execution starts empty and only
+        // returns its own artifacts
         let cleanup_script = "find /workspace -mindepth 1 -delete 2>/dev/null || true";
 
+        // Wait for the cleanup to finish before the container goes back to the pool
         self.manager
-            .exec_simple_command(
+            .exec_command_output(
                 container_id,
                 vec![
                     "sh".to_string(),
@@ -280,7 +387,8 @@ impl ContainerPool {
 
     /// Return container to the pool
     async fn return_container(&self, container: PooledContainer) {
-        let max_pool_size = self.config.pool_size_per_language * 2; // Allow 2x normal size
+        // Allow 2x normal size
+        let max_pool_size = self.config.pool_size_for(&container.language) * 2;
         let language = container.language.clone();
         let container_id = container.id.clone();
 
@@ -311,12 +419,7 @@ impl ContainerPool {
         let mut pools = self.pools.lock().await;
 
         // Remove expired containers and replenish
-        for language in &[
-            Language::Python,
-            Language::Javascript,
-            Language::Shell,
-            Language::Rust,
-        ] {
+        for language in &Language::ALL {
             let pool = pools.get_pool_mut(language);
             let mut to_remove = Vec::new();
 
@@ -346,12 +449,7 @@ impl ContainerPool {
 
         let mut pools = self.pools.lock().await;
 
-        for language in &[
-            Language::Python,
-            Language::Javascript,
-            Language::Shell,
-            Language::Rust,
-        ] {
+        for language in &Language::ALL {
             let pool = pools.get_pool_mut(language);
             while let Some(container) = pool.pop_front() {
                 debug!("Cleaning up pooled container {}", container.id);
@@ -359,6 +457,12 @@ impl ContainerPool {
             }
         }
 
+        let mut reserved = self.reserved.lock().await;
+        for (container_id, _) in reserved.drain() {
+            debug!("Cleaning up reserved container {}", container_id);
+            self.manager.cleanup_container(&container_id).await;
+        }
+
         info!("Container pool shutdown complete");
     }
 }
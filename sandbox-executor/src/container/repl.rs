@@ -0,0 +1,96 @@
+/// Directory of the persistent Python interpreter of a session. Hidden, so its files
+/// aren't returned as artifacts.
+pub const REPL_DIR: &str = "/workspace/.session";
+
+/// Interpreter kept running in a Python session. For each step it waits for
+/// `<step>.ready`, runs /workspace/script.py in the same globals as the steps before,
+/// and writes the step's stdout, stderr and exit code next to the marker.
+pub const PYTHON_REPL: &str = r#"import contextlib
+import importlib
+import os
+import sys
+import time
+import traceback
+
+SESSION_DIR = "/workspace/.session"
+namespace = {"__name__": "__main__"}
+step = 0
+
+while True:
+    step += 1
+    prefix = os.path.join(SESSION_DIR, str(step))
+    while not os.path.exists(prefix + ".ready"):
+        time.sleep(0.02)
+    with open("/workspace/script.py") as f:
+        code = f.read()
+    # Packages may have been installed since the last step
+    importlib.invalidate_caches()
+
+    exit_code = 0
+    with open(prefix + ".out", "w", buffering=1) as out, open(prefix + ".err", "w") as err:
+        with contextlib.redirect_stdout(out), contextlib.redirect_stderr(err):
+            try:
+                exec(compile(code, "script.py", "exec"), namespace)
+            except SystemExit as e:
+                if e.code is None or isinstance(e.code, int):
+                    exit_code = e.code or 0
+                else:
+                    print(e.code, file=err)
+                    exit_code = 1
+            except BaseException:
+                # Leave this loop's frame out of the traceback
+                error_type, error, tb = sys.exc_info()
+                traceback.print_exception(error_type, error, tb.tb_next)
+                exit_code = 1
+
+    with open(prefix + ".exit.tmp", "w") as f:
+        f.write(str(exit_code))
+    os.replace(prefix + ".exit.tmp", prefix + ".exit")
+"#;
+
+/// Runs one step in the session's interpreter, passing its stdout through as it's
+/// written and exiting with the step's exit code
+pub const PYTHON_REPL_CLIENT: &str = r#"import os
+import sys
+import time
+
+prefix = sys.argv[1]
+open(prefix + ".ready", "w").close()
+while not os.path.exists(prefix + ".out"):
+    time.sleep(0.02)
+
+with open(prefix + ".out", "rb") as out:
+    while True:
+        done = os.path.exists(prefix + ".exit")
+        chunk = out.read()
+        if chunk:
+            sys.stdout.buffer.write(chunk)
+            sys.stdout.flush()
+        if done:
+            break
+        time.sleep(0.05)
+
+with open(prefix + ".err", "rb") as err:
+    sys.stderr.buffer.write(err.read())
+with open(prefix + ".exit") as f:
+    sys.exit(int(f.read() or 1))
+"#;
+
+/// Path of a file in the interpreter's directory
+pub fn repl_path(name: &str) -> String {
+    format!("{}/{}", REPL_DIR, name)
+}
+
+/// Command running step `step` of a Python session
+pub fn step_command(step: u64) -> Vec<String> {
+    vec![
+        "python3".to_string(),
+        repl_path("client.py"),
+        repl_path(&step.to_string()),
+    ]
+}
+
+/// Command starting the interpreter of a Python session
+pub fn start_command() -> Vec<String> {
+    vec!["python3".to_string(), repl_path("repl.py")]
+}
@@ -20,6 +20,14 @@ pub enum SandboxError {
     ResourceLimitExceeded(String),
     InvalidInput(String),
 
+    // Package errors
+    PackagesNotAllowed(Vec<String>),
+    PackageInstallFailed(String),
+
+    // Session errors
+    SessionNotFound(String),
+    SessionBusy(String),
+
     // System errors
     InternalError(String),
     ConfigurationError(String),
@@ -56,6 +64,18 @@ impl fmt::Display for SandboxError {
             }
             SandboxError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
 
+            SandboxError::PackagesNotAllowed(names) => {
+                write!(f, "Packages not in the allowlist: {}", names.join(", "))
+            }
+            SandboxError::PackageInstallFailed(msg) => {
+                write!(f, "Failed to install packages: {}", msg)
+            }
+
+            SandboxError::SessionNotFound(id) => write!(f, "Session not found: {}", id),
+            SandboxError::SessionBusy(id) => {
+                write!(f, "Session {} is already running code", id)
+            }
+
             SandboxError::InternalError(msg) => write!(f, "Internal error: {}", msg),
             SandboxError::ConfigurationError(msg) => write!(f, "Configuration error: {}", msg),
         }
@@ -81,6 +101,12 @@ impl ResponseError for SandboxError {
             SandboxError::ResourceLimitExceeded(_) => StatusCode::BAD_REQUEST,
             SandboxError::InvalidInput(_) => StatusCode::BAD_REQUEST,
 
+            SandboxError::PackagesNotAllowed(_) => StatusCode::BAD_REQUEST,
+            SandboxError::PackageInstallFailed(_) => StatusCode::BAD_REQUEST,
+
+            SandboxError::SessionNotFound(_) => StatusCode::NOT_FOUND,
+            SandboxError::SessionBusy(_) => StatusCode::CONFLICT,
+
             SandboxError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             SandboxError::ConfigurationError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
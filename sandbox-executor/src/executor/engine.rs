@@ -1,11 +1,15 @@
 use chrono::Utc;
+use std::collections::HashSet;
 use std::sync::Arc;
+use tokio::sync::mpsc;
 use tracing::{error, info, warn};
 
 use crate::config::Config;
+use crate::container::packages::{self, PackageSpec};
 use crate::container::{ContainerManager, ContainerPool};
 use crate::error::{SandboxError, SandboxResult};
 use crate::executor::audit::AuditLogger;
+use crate::executor::sessions::SessionLease;
 use crate::models::{ExecuteRequest, ExecuteResponse, ExecutionContext, ExecutionStatus, Language};
 use crate::security::{limits::ResourceLimits, validate_code};
 
@@ -43,14 +47,51 @@ impl ExecutionEngine {
     }
 
     pub async fn execute(&self, request: ExecuteRequest) -> SandboxResult<ExecuteResponse> {
+        self.execute_with_output(request, None, None).await
+    }
+
+    /// Execute, sending stdout to `output` as the code writes it
+    pub async fn execute_streaming(
+        &self,
+        request: ExecuteRequest,
+        output: mpsc::UnboundedSender<String>,
+    ) -> SandboxResult<ExecuteResponse> {
+        self.execute_with_output(request, Some(output), None).await
+    }
+
+    /// Execute in the container of a session, sending stdout to `output` if given
+    pub async fn execute_in_session(
+        &self,
+        lease: &SessionLease,
+        request: ExecuteRequest,
+        output: Option<mpsc::UnboundedSender<String>>,
+    ) -> SandboxResult<ExecuteResponse> {
+        self.execute_with_output(request, output, Some(lease)).await
+    }
+
+    async fn execute_with_output(
+        &self,
+        request: ExecuteRequest,
+        output: Option<mpsc::UnboundedSender<String>>,
+        session: Option<&SessionLease>,
+    ) -> SandboxResult<ExecuteResponse> {
         let created_at = Utc::now();
 
         // Validate request
         self.validate_request(&request)?;
+        let packages = self.resolve_packages(&request)?;
 
         // Create execution context
-        let ctx =
+        let mut ctx =
             ExecutionContext::new(request.clone()).map_err(|e| SandboxError::InvalidInput(e))?;
+        ctx.output = output;
+        ctx.packages = packages;
+        if let Some(lease) = session {
+            // Python sessions run the code in their persistent interpreter
+            if let Language::Python = ctx.language {
+                ctx.session_step = Some(lease.step);
+            }
+        }
 
         info!(
             "Starting execution {} for language {:?}",
@@ -65,7 +106,10 @@ impl ExecutionEngine {
         let execution_id = ctx.id.to_string();
 
         // Execute with proper error handling
-        let result = self.execute_internal(&ctx).await;
+        let result = match session {
+            Some(lease) => self.execute_reserved(&lease.container_id, &ctx).await,
+            None => self.execute_internal(&ctx).await,
+        };
 
         // Build response
         let response = match result {
@@ -95,6 +139,7 @@ impl ExecutionEngine {
                     memory_used_mb: exec_result.memory_used_mb,
                     exit_code: Some(exec_result.exit_code),
                     error: None,
+                    artifacts: exec_result.artifacts,
                     created_at,
                     completed_at: Some(Utc::now()),
                 }
@@ -121,6 +166,7 @@ impl ExecutionEngine {
                     memory_used_mb: None,
                     exit_code: None,
                     error: Some(e.to_string()),
+                    artifacts: Vec::new(),
                     created_at,
                     completed_at: Some(Utc::now()),
                 }
@@ -155,6 +201,10 @@ impl ExecutionEngine {
             // Start container
             self.container_manager.start_container(&id).await?;
 
+            self.container_manager
+                .ensure_packages(&id, &ctx.packages, &mut HashSet::new())
+                .await?;
+
             // Execute code in container
             let exec_result = self
                 .container_manager
@@ -177,20 +227,47 @@ impl ExecutionEngine {
         result
     }
 
+    async fn execute_reserved(
+        &self,
+        container_id: &str,
+        ctx: &ExecutionContext,
+    ) -> SandboxResult<crate::models::ExecutionResult> {
+        match self.container_pool {
+            Some(ref pool) => pool.execute_reserved(container_id, ctx).await,
+            None => Err(SandboxError::InternalError(
+                "Sessions need the container pool".to_string(),
+            )),
+        }
+    }
+
+    /// Packages to install for the request. Only Python code can ask for packages, and
+    /// only for allowlisted ones.
+    fn resolve_packages(&self, request: &ExecuteRequest) -> SandboxResult<Vec<PackageSpec>> {
+        let requested = match request.packages {
+            Some(ref requested) if !requested.is_empty() => requested,
+            _ => return Ok(Vec::new()),
+        };
+
+        match Language::from_str(&request.language) {
+            Ok(Language::Python) => {}
+            _ => {
+                return Err(SandboxError::InvalidInput(
+                    "Packages can only be installed for Python".to_string(),
+                ))
+            }
+        }
+
+        packages::resolve(requested, &self.config.package_allowlist)
+            .map_err(SandboxError::PackagesNotAllowed)
+    }
+
     fn validate_request(&self, request: &ExecuteRequest) -> SandboxResult<()> {
         // Validate language support
         let language =
             Language::from_str(&request.language).map_err(|e| SandboxError::InvalidInput(e))?;
 
         // Check if language is enabled
-        let enabled = match language {
-            Language::Python => self.config.enable_python,
-            Language::Javascript => self.config.enable_javascript,
-            Language::Shell => self.config.enable_shell,
-            Language::Rust => self.config.enable_rust,
-        };
-
-        if !enabled {
+        if !self.config.is_language_enabled(&language) {
             return Err(SandboxError::LanguageNotSupported(format!(
                 "{:?} is not enabled",
                 language
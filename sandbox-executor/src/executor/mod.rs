@@ -1,5 +1,7 @@
 pub mod audit;
 pub mod engine;
+pub mod sessions;
 
 pub use audit::AuditLogger;
 pub use engine::ExecutionEngine;
+pub use sessions::SessionRegistry;
@@ -0,0 +1,275 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::error::{SandboxError, SandboxResult};
+use crate::models::{Language, SessionResponse};
+
+/// An execution session: a container kept for one caller, so files (and, for Python,
+/// interpreter state) carry over from one execution to the next
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub language: Language,
+    /// Caller's key for the session, e.g. a chat id
+    pub key: Option<String>,
+    pub user_id: Option<String>,
+    pub container_id: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
+    pub execution_count: u64,
+    /// Code is running in the session
+    pub busy: bool,
+}
+
+impl Session {
+    pub fn new(
+        language: Language,
+        key: Option<String>,
+        user_id: Option<String>,
+        container_id: String,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            language,
+            key,
+            user_id,
+            container_id,
+            created_at: now,
+            last_used: now,
+            execution_count: 0,
+            busy: false,
+        }
+    }
+}
+
+/// What an execution in a session needs, taken while the session is marked busy
+#[derive(Debug, Clone)]
+pub struct SessionLease {
+    pub session_id: String,
+    pub container_id: String,
+    pub language: Language,
+    pub user_id: Option<String>,
+    /// Number of this execution in the session, counting from 1
+    pub step: u64,
+}
+
+/// Open sessions, expiring those left idle for longer than the TTL or open for longer
+/// than the maximum lifetime
+pub struct SessionRegistry {
+    sessions: Mutex<HashMap<String, Session>>,
+    idle_ttl: Duration,
+    max_lifetime: Duration,
+}
+
+impl SessionRegistry {
+    pub fn new(idle_ttl_seconds: u64, max_lifetime_seconds: u64) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            idle_ttl: Duration::seconds(idle_ttl_seconds as i64),
+            max_lifetime: Duration::seconds(max_lifetime_seconds as i64),
+        }
+    }
+
+    fn response(&self, session: &Session) -> SessionResponse {
+        SessionResponse {
+            session_id: session.id.clone(),
+            language: session.language.name().to_string(),
+            session_key: session.key.clone(),
+            created_at: session.created_at,
+            expires_at: (session.last_used + self.idle_ttl)
+                .min(session.created_at + self.max_lifetime),
+            execution_count: session.execution_count,
+        }
+    }
+
+    pub async fn insert(&self, session: Session) -> SessionResponse {
+        let response = self.response(&session);
+        self.sessions
+            .lock()
+            .await
+            .insert(session.id.clone(), session);
+        response
+    }
+
+    /// The caller's open session with `key` in `language`, if there is one
+    pub async fn find_by_key(
+        &self,
+        key: &str,
+        user_id: Option<&str>,
+        language: &Language,
+    ) -> Option<SessionResponse> {
+        let sessions = self.sessions.lock().await;
+        sessions
+            .values()
+            .find(|session| {
+                session.key.as_deref() == Some(key)
+                    && session.user_id.as_deref() == user_id
+                    && session.language.name() == language.name()
+            })
+            .map(|session| self.response(session))
+    }
+
+    pub async fn get(&self, id: &str) -> SandboxResult<SessionResponse> {
+        let sessions = self.sessions.lock().await;
+        match sessions.get(id) {
+            Some(session) => Ok(self.response(session)),
+            None => Err(SandboxError::SessionNotFound(id.to_string())),
+        }
+    }
+
+    /// Mark a session busy for an execution. One execution runs in a session at a time.
+    pub async fn begin(&self, id: &str) -> SandboxResult<SessionLease> {
+        let mut sessions = self.sessions.lock().await;
+        let session = match sessions.get_mut(id) {
+            Some(session) => session,
+            None => return Err(SandboxError::SessionNotFound(id.to_string())),
+        };
+        if session.busy {
+            return Err(SandboxError::SessionBusy(id.to_string()));
+        }
+
+        session.busy = true;
+        Ok(SessionLease {
+            session_id: session.id.clone(),
+            container_id: session.container_id.clone(),
+            language: session.language.clone(),
+            user_id: session.user_id.clone(),
+            step: session.execution_count + 1,
+        })
+    }
+
+    /// Mark the execution of a lease finished, restarting the idle TTL
+    pub async fn finish(&self, lease: &SessionLease) {
+        let mut sessions = self.sessions.lock().await;
+        if let Some(session) = sessions.get_mut(&lease.session_id) {
+            session.busy = false;
+            session.last_used = Utc::now();
+            session.execution_count = lease.step;
+        }
+    }
+
+    /// Give a session back without counting an execution, when the code didn't run
+    pub async fn cancel(&self, lease: &SessionLease) {
+        let mut sessions = self.sessions.lock().await;
+        if let Some(session) = sessions.get_mut(&lease.session_id) {
+            session.busy = false;
+        }
+    }
+
+    /// Close a session, returning it so its container can be released
+    pub async fn remove(&self, id: &str) -> Option<Session> {
+        self.sessions.lock().await.remove(id)
+    }
+
+    /// Close the sessions that expired at `now`
+    pub async fn take_expired(&self, now: DateTime<Utc>) -> Vec<Session> {
+        let mut sessions = self.sessions.lock().await;
+        let expired: Vec<String> = sessions
+            .values()
+            .filter(|session| {
+                !session.busy
+                    && (now - session.last_used > self.idle_ttl
+                        || now - session.created_at > self.max_lifetime)
+            })
+            .map(|session| session.id.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|id| sessions.remove(id))
+            .collect()
+    }
+
+    pub async fn count(&self) -> usize {
+        self.sessions.lock().await.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(key: &str) -> Session {
+        Session::new(
+            Language::Python,
+            Some(key.to_string()),
+            Some("user-1".to_string()),
+            format!("container-{}", key),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_found_by_key() {
+        let registry = SessionRegistry::new(600, 3600);
+        let created = registry.insert(session("chat-1")).await;
+
+        let found = registry
+            .find_by_key("chat-1", Some("user-1"), &Language::Python)
+            .await
+            .unwrap();
+        assert_eq!(found.session_id, created.session_id);
+
+        // Another user, or another language, gets a session of their own
+        assert!(registry
+            .find_by_key("chat-1", Some("user-2"), &Language::Python)
+            .await
+            .is_none());
+        assert!(registry
+            .find_by_key("chat-1", Some("user-1"), &Language::Bash)
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_one_execution_at_a_time() {
+        let registry = SessionRegistry::new(600, 3600);
+        let id = registry.insert(session("chat-1")).await.session_id;
+
+        let lease = registry.begin(&id).await.unwrap();
+        assert_eq!(lease.step, 1);
+        assert_eq!(lease.container_id, "container-chat-1");
+        assert!(matches!(
+            registry.begin(&id).await,
+            Err(SandboxError::SessionBusy(_))
+        ));
+
+        registry.finish(&lease).await;
+        let lease = registry.begin(&id).await.unwrap();
+        assert_eq!(lease.step, 2);
+
+        // Code that didn't run doesn't take up a step
+        registry.cancel(&lease).await;
+        assert_eq!(registry.begin(&id).await.unwrap().step, 2);
+        assert!(matches!(
+            registry.begin("missing").await,
+            Err(SandboxError::SessionNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_idle_sessions_expire() {
+        let registry = SessionRegistry::new(60, 3600);
+        let idle = registry.insert(session("idle")).await.session_id;
+        let running = registry.insert(session("running")).await.session_id;
+        registry.begin(&running).await.unwrap();
+
+        let later = Utc::now() + Duration::seconds(61);
+        assert!(registry.take_expired(Utc::now()).await.is_empty());
+        let expired = registry.take_expired(later).await;
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, idle);
+
+        // A session running code isn't expired under it
+        assert_eq!(registry.count().await, 1);
+        assert!(registry.remove(&running).await.is_some());
+        assert_eq!(registry.count().await, 0);
+
+        // Sessions used again and again still close at the end of their lifetime
+        let registry = SessionRegistry::new(600, 60);
+        let old = registry.insert(session("old")).await;
+        assert_eq!(old.expires_at, old.created_at + Duration::seconds(60));
+        assert_eq!(registry.take_expired(later).await.len(), 1);
+    }
+}
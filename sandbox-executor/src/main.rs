@@ -63,6 +63,24 @@ async fn main() -> std::io::Result<()> {
     // Create application state
     let app_state = web::Data::new(AppState::new(config.clone(), dummy_manager, container_pool));
 
+    // Close expired sessions and release their containers
+    if config.enable_sessions {
+        let state = app_state.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                for session in state.sessions.take_expired(chrono::Utc::now()).await {
+                    info!("Session {} expired", session.id);
+                    state
+                        .container_pool
+                        .release_container(&session.container_id)
+                        .await;
+                }
+            }
+        });
+    }
+
     let bind_addr = format!("{}:{}", config.host, config.port);
     info!("🌐 Starting HTTP server on {}", bind_addr);
 
@@ -1,33 +1,54 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::container::packages::PackageSpec;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Language {
     Python,
     Javascript,
-    Shell,
+    #[serde(alias = "shell")]
+    Bash,
     Rust,
 }
 
 impl Language {
+    pub const ALL: [Language; 4] = [
+        Language::Python,
+        Language::Javascript,
+        Language::Bash,
+        Language::Rust,
+    ];
+
     pub fn from_str(s: &str) -> Result<Self, String> {
         match s.to_lowercase().as_str() {
             "python" | "py" => Ok(Language::Python),
             "javascript" | "js" | "node" => Ok(Language::Javascript),
-            "shell" | "sh" | "bash" => Ok(Language::Shell),
+            "bash" | "shell" | "sh" => Ok(Language::Bash),
             "rust" | "rs" => Ok(Language::Rust),
             _ => Err(format!("Unsupported language: {}", s)),
         }
     }
 
+    /// Name the language is advertised and requested by
+    pub fn name(&self) -> &str {
+        match self {
+            Language::Python => "python",
+            Language::Javascript => "javascript",
+            Language::Bash => "bash",
+            Language::Rust => "rust",
+        }
+    }
+
     pub fn executor(&self) -> &str {
         match self {
             Language::Python => "python3",
             Language::Javascript => "node",
-            Language::Shell => "/bin/sh",
+            Language::Bash => "bash",
             Language::Rust => "rustc",
         }
     }
@@ -36,10 +57,30 @@ impl Language {
         match self {
             Language::Python => "py",
             Language::Javascript => "js",
-            Language::Shell => "sh",
+            Language::Bash => "sh",
             Language::Rust => "rs",
         }
     }
+
+    /// Command running the script file `filename`
+    pub fn command(&self, filename: &str) -> Vec<String> {
+        match self {
+            Language::Python => vec!["python3".to_string(), filename.to_string()],
+            Language::Javascript => vec!["node".to_string(), filename.to_string()],
+            // Without the profile and rc files, so only the script runs
+            Language::Bash => vec![
+                "bash".to_string(),
+                "--noprofile".to_string(),
+                "--norc".to_string(),
+                filename.to_string(),
+            ],
+            Language::Rust => vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                format!("rustc {} -o /tmp/program && /tmp/program", filename),
+            ],
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
@@ -56,11 +97,52 @@ pub struct ExecuteRequest {
 
     pub files: Option<Vec<FileInput>>,
 
+    /// Python packages to install before running, as `name` or `name==version`
+    pub packages: Option<Vec<String>>,
+
     // User/request identification
     pub user_id: Option<String>,
     pub request_id: Option<String>,
 }
 
+/// Request to open an execution session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateSessionRequest {
+    pub language: String,
+    /// Caller's key for the session, e.g. a chat id. Opening a session with the key of
+    /// one that's still open returns that session.
+    pub session_key: Option<String>,
+    pub user_id: Option<String>,
+}
+
+/// Code to run in an open session, in the session's language. Validated as the
+/// [`ExecuteRequest`] it becomes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionExecuteRequest {
+    pub code: String,
+
+    pub timeout: Option<u64>,
+
+    pub env_vars: Option<Vec<EnvVar>>,
+
+    pub files: Option<Vec<FileInput>>,
+
+    pub packages: Option<Vec<String>>,
+
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionResponse {
+    pub session_id: String,
+    pub language: String,
+    pub session_key: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// When the session closes if no more code runs in it
+    pub expires_at: DateTime<Utc>,
+    pub execution_count: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnvVar {
     pub key: String,
@@ -85,10 +167,24 @@ pub struct ExecuteResponse {
     pub memory_used_mb: Option<f64>,
     pub exit_code: Option<i32>,
     pub error: Option<String>,
+    /// Files the code wrote to /workspace
+    #[serde(default)]
+    pub artifacts: Vec<Artifact>,
     pub created_at: DateTime<Utc>,
     pub completed_at: Option<DateTime<Utc>>,
 }
 
+/// A file an execution wrote, such as a plot or a CSV
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact {
+    /// Path relative to /workspace
+    pub name: String,
+    pub mime_type: String,
+    pub size: u64,
+    /// Base64 encoded content
+    pub content: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum ExecutionStatus {
@@ -108,9 +204,15 @@ pub struct ExecutionContext {
     pub timeout: u64,
     pub env_vars: Vec<EnvVar>,
     pub files: Vec<FileInput>,
+    /// Allowlisted packages to install before running
+    pub packages: Vec<PackageSpec>,
     pub user_id: Option<String>,
     pub request_id: Option<String>,
     pub created_at: DateTime<Utc>,
+    /// Receives stdout as the code writes it, for streamed executions
+    pub output: Option<mpsc::UnboundedSender<String>>,
+    /// Step to run in the persistent interpreter of a Python session
+    pub session_step: Option<u64>,
 }
 
 impl ExecutionContext {
@@ -124,13 +226,28 @@ impl ExecutionContext {
             timeout: req.timeout.unwrap_or(60),
             env_vars: req.env_vars.unwrap_or_default(),
             files: req.files.unwrap_or_default(),
+            packages: Vec::new(),
             user_id: req.user_id,
             request_id: req.request_id,
             created_at: Utc::now(),
+            output: None,
+            session_step: None,
         })
     }
 }
 
+/// Line of a streamed execution's NDJSON response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+pub enum ExecutionEvent {
+    /// Output the code wrote to stdout since the last event
+    Stdout(String),
+    /// Final event of an execution that ran
+    Result(ExecuteResponse),
+    /// Final event of an execution that was rejected
+    Error(ErrorResponse),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionResult {
     pub stdout: String,
@@ -139,6 +256,7 @@ pub struct ExecutionResult {
     pub exit_code: i32,
     pub execution_time_ms: u64,
     pub memory_used_mb: Option<f64>,
+    pub artifacts: Vec<Artifact>,
 }
 
 impl Default for ExecutionResult {
@@ -150,6 +268,7 @@ impl Default for ExecutionResult {
             exit_code: 0,
             execution_time_ms: 0,
             memory_used_mb: None,
+            artifacts: Vec::new(),
         }
     }
 }
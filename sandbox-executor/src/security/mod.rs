@@ -59,6 +59,11 @@ impl SecurityConfig {
                 "/workspace".to_string(),
                 "rw,nosuid,size=100m,uid=1000,gid=1000,mode=1777".to_string(),
             );
+            // Owned by root, so only package installs write to it
+            tmpfs.insert(
+                crate::container::packages::PACKAGE_DIR.to_string(),
+                "rw,nosuid,size=500m,mode=755".to_string(),
+            );
             host_config.tmpfs = Some(tmpfs);
         }
 
@@ -87,8 +92,23 @@ impl SecurityConfig {
     }
 }
 
+/// Whether a shell script defines a function that pipes into itself in the background,
+/// like `:(){ :|:& };:`
+fn has_fork_bomb(code: &str) -> bool {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    code.match_indices("(){").any(|(start, _)| {
+        let name_start = code[..start]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || *c == '_' || *c == ':' || *c == '.'))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let name = &code[name_start..start];
+        !name.is_empty() && code[start + 3..].starts_with(&format!("{}|{}&", name, name))
+    })
+}
+
 /// Validate code for potential security issues
-pub fn validate_code(code: &str, _language: &crate::models::Language) -> Result<(), String> {
+pub fn validate_code(code: &str, language: &crate::models::Language) -> Result<(), String> {
     // Check code size
     if code.len() > 100_000 {
         return Err("Code size exceeds 100KB limit".to_string());
@@ -99,8 +119,25 @@ pub fn validate_code(code: &str, _language: &crate::models::Language) -> Result<
         return Err("Code contains null bytes".to_string());
     }
 
-    // Additional security checks can be added here
-    // For example: detecting suspicious patterns, import restrictions, etc.
+    match language {
+        // The pids limit would stop it, but only after starving other executions
+        crate::models::Language::Bash if has_fork_bomb(code) => {
+            return Err("Code contains a fork bomb".to_string());
+        }
+        crate::models::Language::Javascript => {
+            // Node internals that load native code outside the module system
+            for pattern in [
+                "process.binding(",
+                "process._linkedBinding(",
+                "process.dlopen(",
+            ] {
+                if code.contains(pattern) {
+                    return Err(format!("Code uses {}) which is not allowed", pattern));
+                }
+            }
+        }
+        _ => {}
+    }
 
     Ok(())
 }
@@ -119,6 +156,18 @@ mod tests {
         assert!(validate_code(normal_code, &Language::Python).is_ok());
     }
 
+    #[test]
+    fn test_language_specific_rules() {
+        assert!(validate_code(":(){ :|:& };:", &Language::Bash).is_err());
+        assert!(validate_code("bomb() {\n  bomb | bomb &\n}\nbomb", &Language::Bash).is_err());
+        assert!(validate_code("f() { echo hi | cat; }\nf &", &Language::Bash).is_ok());
+        // Only shell scripts are checked for fork bombs
+        assert!(validate_code("s = ':(){ :|:& };:'", &Language::Python).is_ok());
+
+        assert!(validate_code("process.binding('fs')", &Language::Javascript).is_err());
+        assert!(validate_code("console.log(process.version)", &Language::Javascript).is_ok());
+    }
+
     #[test]
     fn test_validate_null_bytes() {
         let code_with_null = "print('hello\0world')";
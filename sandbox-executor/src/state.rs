@@ -1,5 +1,6 @@
 use crate::config::Config;
 use crate::container::{ContainerManager, ContainerPool};
+use crate::executor::SessionRegistry;
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::RwLock;
@@ -9,6 +10,7 @@ pub struct AppState {
     pub config: Config,
     pub container_manager: Arc<ContainerManager>,
     pub container_pool: Arc<ContainerPool>,
+    pub sessions: Arc<SessionRegistry>,
     pub stats: Arc<RwLock<ServiceStats>>,
     pub start_time: Instant,
 }
@@ -19,10 +21,16 @@ impl AppState {
         container_manager: ContainerManager,
         container_pool: ContainerPool,
     ) -> Self {
+        let sessions = SessionRegistry::new(
+            config.session_idle_ttl_seconds,
+            config.session_max_lifetime_seconds,
+        );
+
         Self {
             config,
             container_manager: Arc::new(container_manager),
             container_pool: Arc::new(container_pool),
+            sessions: Arc::new(sessions),
             stats: Arc::new(RwLock::new(ServiceStats::default())),
             start_time: Instant::now(),
         }